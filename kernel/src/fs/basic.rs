@@ -1,4 +1,4 @@
-use alloc::{sync::Arc, vec};
+use alloc::{string::String, sync::Arc, vec};
 use core::cmp::min;
 
 use constants::{
@@ -11,19 +11,30 @@ use constants::{
 use gmanager::ManagerError;
 use log::{info, warn};
 use syscall_table::syscall_func;
-use vfs::{eventfd::eventfd, kfile::KernelFile, system_root_fs};
+use vfs::{
+    eventfd::eventfd,
+    fuse::{self, FuseDevFile},
+    kfile::{File, KernelFile},
+};
 use vfscore::{
+    dentry::VfsDentry,
     path::VfsPath,
     utils::{VfsFileStat, VfsFsStat, VfsNodeType, VfsRenameFlag},
 };
 
 use super::im2vim;
 use crate::{
-    fs::{syscontext_for_vfs, user_path_at},
-    task::current_task,
+    fs::{cgroup_parent_at, check_writable_at, syscontext_for_vfs, user_path_at},
+    task::{current_task, tasks},
 };
 
 /// 用于将一个设备(通常是存储设备)挂载到一个已经存在的目录上，可以挂载文件系统。
+///
+/// `fs_type` 可以是任何已注册的文件系统，为 `auto` 时根据块设备上的超级块识别文件系统。
+/// 支持 `MS_RDONLY`、`MS_REMOUNT` 与 `MS_BIND`，`data` 为传给文件系统的选项字符串，可以为空。
+/// 详细的挂载规则见 [`vfs::mount::do_mount`]。
+///
+/// `fs_type` 为 `fuse` 或 `fuse.<子类型>` 时挂载用户态文件系统，`data` 中的 `fd=` 是调用者打开的 `/dev/fuse`。
 #[syscall_func(40)]
 pub fn sys_mount(
     source: *const u8,
@@ -35,30 +46,33 @@ pub fn sys_mount(
     let task = current_task().unwrap();
     let source = task.transfer_str(source);
     let dir = task.transfer_str(dir);
-    let fs_type = task.transfer_str(fs_type);
-    assert!(data.is_null());
-    let flags = MountFlags::from_bits(flags as u32).unwrap();
+    let fs_type = if fs_type.is_null() {
+        String::new()
+    } else {
+        task.transfer_str(fs_type)
+    };
+    let data = if data.is_null() {
+        String::new()
+    } else {
+        task.transfer_str(data)
+    };
+    let flags = MountFlags::from_bits_truncate(flags as u32);
     info!(
         "mount special:{:?},dir:{:?},fs_type:{:?},flags:{:?},data:{:?}",
         source, dir, fs_type, flags, data
     );
-    let find = vfs::system_support_fs(&fs_type).ok_or(LinuxErrno::EINVAL)?;
-    let path = VfsPath::new(vfs::system_root_fs(), system_root_fs());
-    let fs_root = match find.fs_name().as_str() {
-        name @ ("tmpfs" | "ramfs" | "fat32") => {
-            let fs = vfs::system_support_fs(name).unwrap();
-            let dev = if name.eq("fat32") {
-                let dev = path.join(source)?.open(None)?;
-                Some(dev.inode()?)
-            } else {
-                None
-            };
-            let new_fs = fs.i_mount(0, &dir, dev, &[])?;
-            new_fs
-        }
-        _ => return Err(LinuxErrno::EINVAL),
-    };
-    path.join(dir)?.mount(fs_root, flags.bits())?;
+    let root = task.access_inner().fs_info.root.clone();
+    let path = VfsPath::new(root.clone(), root);
+    if fuse::is_fuse_type(&fs_type)
+        && !flags.intersects(MountFlags::MS_REMOUNT | MountFlags::MS_BIND)
+    {
+        let dev = task
+            .get_file(fuse::device_fd(&data)?)
+            .ok_or(LinuxErrno::EBADF)?;
+        fuse::do_mount(&path, &source, &dir, &fs_type, flags, &data, dev)?;
+        return Ok(0);
+    }
+    vfs::mount::do_mount(&path, &source, &dir, &fs_type, flags, &data)?;
     Ok(0)
 }
 
@@ -68,8 +82,9 @@ pub fn sys_umount(dir: *const u8) -> AlienResult<isize> {
     let process = current_task().unwrap();
     let dir = process.transfer_str(dir);
     info!("umount dir:{:?}", dir);
-    let path = VfsPath::new(vfs::system_root_fs(), system_root_fs());
-    path.join(dir)?.umount()?;
+    let root = process.access_inner().fs_info.root.clone();
+    let path = VfsPath::new(root.clone(), root);
+    vfs::mount::do_umount(&path, &dir)?;
     Ok(0)
 }
 
@@ -87,6 +102,11 @@ pub fn sys_openat(dirfd: isize, path: *const u8, flag: usize, mode: u32) -> Alie
     .map(|x| im2vim(x));
     let process = current_task().unwrap();
     let path_str = process.transfer_str(path);
+    if flag.intersects(
+        OpenFlags::O_WRONLY | OpenFlags::O_RDWR | OpenFlags::O_CREAT | OpenFlags::O_TRUNC,
+    ) {
+        check_writable_at(dirfd, &path_str)?;
+    }
     let path = user_path_at(dirfd, &path_str)?;
     warn!(
         "open file: dirfd:[{}], {:?},flag:{:?}, mode:{:?}",
@@ -94,9 +114,14 @@ pub fn sys_openat(dirfd: isize, path: *const u8, flag: usize, mode: u32) -> Alie
     );
 
     let dentry = path.open(file_mode)?;
-    let file = KernelFile::new(dentry, flag);
+    // 每次打开 /dev/fuse 都建立一个新的连接
+    let file: Arc<dyn File> = if fuse::is_fuse_device(&dentry) {
+        Arc::new(FuseDevFile::new(dentry, flag))
+    } else {
+        Arc::new(KernelFile::new(dentry, flag))
+    };
 
-    let fd = process.add_file(Arc::new(file));
+    let fd = process.add_file(file);
     warn!("openat fd: {:?}", fd);
     if fd.is_err() {
         let error = ManagerError::from((fd.unwrap_err()) as usize);
@@ -170,6 +195,7 @@ pub fn sys_getdents(fd: usize, buf: *mut u8, len: usize) -> AlienResult<isize> {
 pub fn sys_truncate(path: usize, len: usize) -> AlienResult<isize> {
     let process = current_task().unwrap();
     let path = process.transfer_str(path as *const u8);
+    check_writable_at(AT_FDCWD, &path)?;
     let path = user_path_at(AT_FDCWD, &path)?;
     path.truncate(len as u64)?;
     Ok(0)
@@ -253,7 +279,8 @@ pub fn sys_getcwd(buf: *mut u8, len: usize) -> isize {
 
     let mut buf = task.transfer_buffer(buf, len);
     let mut count = 0;
-    let path = cwd.cwd.path();
+    // 工作目录相对于进程的根目录
+    let path = cwd.relative_path(&cwd.cwd);
     let mut cwd = path.as_bytes();
     buf.iter_mut().for_each(|buf| {
         // fill buf
@@ -304,9 +331,141 @@ pub fn sys_fchdir(fd: usize) -> AlienResult<isize> {
     Ok(0)
 }
 
+/// 一个系统调用，用于切换当前进程的根目录。`path` 指出新的根目录，当前工作目录保持不变。
+///
+/// 之后该进程以 `/` 开头的路径都将基于新的根目录解析。切换成功后返回 0；`path` 不是目录时返回 `ENOTDIR`。
+///
+/// Reference: [chroot](https://man7.org/linux/man-pages/man2/chroot.2.html)
+#[syscall_func(51)]
+pub fn sys_chroot(path: *const u8) -> AlienResult<isize> {
+    let process = current_task().unwrap();
+    let path = process.transfer_str(path);
+    let dt = user_path_at(AT_FDCWD, &path)?.open(None)?;
+    if dt.inode()?.inode_type() != VfsNodeType::Dir {
+        return Err(LinuxErrno::ENOTDIR);
+    }
+    info!("chroot: {:?}", dt.path());
+    process.access_inner().fs_info.root = dt;
+    Ok(0)
+}
+
+/// 一个系统调用，用于切换当前挂载命名空间的根目录。
+///
+/// `new_root` 必须是一个挂载点，且不能是当前的根目录；`put_old` 必须位于 `new_root` 之下。
+/// 与 Linux 相同，`new_root` 先从它原来的挂载点上摘下，原来的根目录再被挂载到 `put_old` 上，
+/// 因此原根目录树中不再包含 `new_root`，两棵目录树之间不会形成环。
+/// 挂载命名空间中所有根目录或工作目录为原根目录的任务都将切换到 `new_root`。
+/// 当 `put_old` 与 `new_root` 相同时，原根目录不会被挂载到任何位置，对应 `pivot_root(".", ".")` 的用法。
+///
+/// Reference: [pivot_root](https://man7.org/linux/man-pages/man2/pivot_root.2.html)
+#[syscall_func(41)]
+pub fn sys_pivot_root(new_root: *const u8, put_old: *const u8) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let new_root = task.transfer_str(new_root);
+    let put_old = task.transfer_str(put_old);
+    let new_dt = user_path_at(AT_FDCWD, &new_root)?.open(None)?;
+    let put_old_dt = user_path_at(AT_FDCWD, &put_old)?.open(None)?;
+    if new_dt.inode()?.inode_type() != VfsNodeType::Dir
+        || put_old_dt.inode()?.inode_type() != VfsNodeType::Dir
+    {
+        return Err(LinuxErrno::ENOTDIR);
+    }
+    let (old_root, mnt) = {
+        let inner = task.access_inner();
+        (inner.fs_info.root.clone(), inner.ns.mnt.clone())
+    };
+    if Arc::ptr_eq(&new_dt, &old_root) {
+        return Err(LinuxErrno::EBUSY);
+    }
+    if !is_mount_point(&new_dt)? {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let new_path = new_dt.path();
+    let put_old_path = put_old_dt.path();
+    let put_old_rel = match put_old_path.strip_prefix(new_path.as_str()) {
+        Some(rel) if rel.is_empty() || rel.starts_with('/') || new_path.ends_with('/') => rel,
+        _ => return Err(LinuxErrno::EINVAL),
+    };
+    info!(
+        "pivot_root: new_root: {:?}, put_old: {:?}",
+        new_path, put_old_path
+    );
+    let detached = find_mount_point(&new_dt).and_then(|point| {
+        let mnt = point.mount_point()?;
+        point.clear_mount_point();
+        Some((point, mnt.mnt_flags))
+    });
+    if !put_old_rel.is_empty() {
+        let res = VfsPath::new(new_dt.clone(), new_dt.clone())
+            .join(put_old_rel)
+            .and_then(|path| path.mount(old_root.clone(), 0));
+        if let Err(e) = res {
+            // 恢复 new_root 原来的挂载
+            if let Some((point, flags)) = detached {
+                let _ = point.to_mount_point(new_dt.clone(), flags);
+            }
+            return Err(e);
+        }
+    }
+    if Arc::ptr_eq(&mnt.root(), &old_root) {
+        mnt.set_root(new_dt.clone());
+    }
+    // 获取其它任务的锁之前不能持有当前任务的锁
+    for t in tasks() {
+        let mut inner = t.access_inner();
+        if !Arc::ptr_eq(&inner.ns.mnt, &mnt) {
+            continue;
+        }
+        if Arc::ptr_eq(&inner.fs_info.root, &old_root) {
+            inner.fs_info.root = new_dt.clone();
+        }
+        if Arc::ptr_eq(&inner.fs_info.cwd, &old_root) {
+            inner.fs_info.cwd = new_dt.clone();
+        }
+    }
+    Ok(0)
+}
+
+/// 查找文件系统的根目录 `root` 被挂载到的目录项，`root` 没有被挂载到其它目录下时返回 None
+fn find_mount_point(root: &Arc<dyn VfsDentry>) -> Option<Arc<dyn VfsDentry>> {
+    let is_point = |dt: &Arc<dyn VfsDentry>| {
+        dt.mount_point()
+            .is_some_and(|mnt| Arc::ptr_eq(&mnt.root, root))
+    };
+    let parent = root.parent()?;
+    if is_point(&parent) {
+        return Some(parent);
+    }
+    // 挂载点是父目录中已经被查找过的某个子目录项
+    let inode = parent.inode().ok()?;
+    let mut index = 0;
+    while let Ok(Some(entry)) = inode.readdir(index) {
+        index += 1;
+        if let Some(child) = parent.find(&entry.name) {
+            if is_point(&child) {
+                return Some(child);
+            }
+        }
+    }
+    None
+}
+
+/// 目录项 `dt` 是否是一个挂载点，即它是文件系统的根目录，或与父目录位于不同的文件系统中
+fn is_mount_point(dt: &Arc<dyn VfsDentry>) -> AlienResult<bool> {
+    match dt.parent() {
+        None => Ok(true),
+        Some(parent) => {
+            let sb = dt.inode()?.get_super_block()?;
+            let parent_sb = parent.inode()?.get_super_block()?;
+            Ok(!Arc::ptr_eq(&sb, &parent_sb))
+        }
+    }
+}
+
 /// 一个系统调用，用于在 相对于一个目录某位置处 路径下创建一个空的目录。功能与 [`sys_mkdir`] 相似。
 ///
 /// 有关对 `dirfd` 和 `mode` 的解析规则以及 flag 的相关设置可见 [`sys_openat`]。成功创建目录则返回 0；否则返回错误码。
+/// 在 cgroup2 文件系统中创建目录即创建一个子 cgroup。
 ///
 /// Reference: [mkdirat](https://man7.org/linux/man-pages/man2/mkdirat.2.html)
 #[syscall_func(34)]
@@ -315,6 +474,11 @@ pub fn sys_mkdirat(dirfd: isize, path: *const u8, mode: u32) -> AlienResult<isiz
     let path = process.transfer_str(path);
     let mut mode = InodeMode::from_bits_truncate(mode);
     warn!("mkdirat path: {}, mode: {:?}", path, mode);
+    if let Some((cgroup, parent, name)) = cgroup_parent_at(dirfd, &path)? {
+        cgroup.mkdir(&parent, &name)?;
+        return Ok(0);
+    }
+    check_writable_at(dirfd, &path)?;
     let path = user_path_at(dirfd, &path)?;
     mode |= InodeMode::DIR;
     assert_eq!(mode & InodeMode::TYPE_MASK, InodeMode::DIR);
@@ -563,6 +727,8 @@ pub fn sys_renameat(
         "renameat2: {:?} {:?} {:?} {:?}",
         old_dirfd, old_path, new_dirfd, new_path
     );
+    check_writable_at(old_dirfd, &old_path)?;
+    check_writable_at(new_dirfd, &new_path)?;
     let old_path = user_path_at(old_dirfd, &old_path)?;
     let new_path = user_path_at(new_dirfd, &new_path)?;
     old_path.rename_to(
@@ -594,6 +760,8 @@ pub fn sys_renameat2(
         "renameat2: {:?} {:?} {:?} {:?}, flag: {:?}",
         old_dirfd, old_path, new_dirfd, new_path, flag
     );
+    check_writable_at(old_dirfd, &old_path)?;
+    check_writable_at(new_dirfd, &new_path)?;
     let old_path = user_path_at(old_dirfd, &old_path)?;
     let new_path = user_path_at(new_dirfd, &new_path)?;
 
@@ -659,16 +827,38 @@ pub fn send_file(
 /// 一个系统调用函数，用于包把含更新文件的所有内核缓冲区(包含数据块、指针块、元数据等)都flush到磁盘上。
 #[syscall_func(81)]
 pub fn sync() -> isize {
+    if let Err(e) = vfs::sync_filesystems() {
+        warn!("sync: writeback failed: {:?}", e);
+    }
     0
 }
 
 /// 用于把打开的文件描述符fd相关的所有缓冲元数据和数据都刷新到磁盘上。
+///
+/// 先同步文件所在的文件系统，再将块设备缓存中的脏页写回并发出写屏障，写回失败时返回 `EIO`。
 #[syscall_func(82)]
 pub fn fsync(fd: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
     let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
     let fs = file.inode().get_super_block()?;
-    fs.sync_fs(false)?;
+    fs.sync_fs(true)?;
+    devices::flush_block_devices()?;
+    Ok(0)
+}
+
+/// 与 [`fsync`] 相同。文件数据与元数据一起写回
+#[syscall_func(83)]
+pub fn fdatasync(fd: usize) -> AlienResult<isize> {
+    fsync(fd)
+}
+
+/// 同步文件描述符 `fd` 所在的整个文件系统。
+#[syscall_func(267)]
+pub fn syncfs(fd: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    file.inode().get_super_block()?.sync_fs(true)?;
+    devices::flush_block_devices()?;
     Ok(0)
 }
 
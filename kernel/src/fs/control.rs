@@ -1,5 +1,7 @@
 use constants::{
+    block,
     io::{FaccessatFlags, FaccessatMode, Fcntl64Cmd, OpenFlags, TeletypeCommand},
+    loop_dev::{self, LoopConfig, LOOP_CONFIGURE, LOOP_SET_FD},
     time::TimeSpec,
     AlienResult, LinuxErrno, AT_FDCWD,
 };
@@ -81,8 +83,10 @@ pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> AlienResult<isize> {
 /// 在一些需要细分的情境下，如果需要扩展新的功能，通常以增设 ioctl() 命令的方式实现。
 ///
 /// `fd` 指明要操作的设备的文件描述符；`cmd` 指明控制操作的类型，
-/// 目前 Alien 支持的 ioctl 操作可见 [`TeletypeCommand`] 和 `rvfs` 中有关 `ioctl` 的支持；
-/// `arg` 指明操作的参数。
+/// 目前 Alien 支持的 ioctl 操作可见 [`TeletypeCommand`]、块设备命令 [`block`]、loop 设备命令 [`loop_dev`]
+/// 和 `rvfs` 中有关 `ioctl` 的支持；`arg` 指明操作的参数。
+///
+/// `LOOP_SET_FD` 与 `LOOP_CONFIGURE` 需要把另一个文件描述符绑定到 loop 设备上，在这里直接处理。
 ///
 /// 根据不同的 ioctl 命令，将有不同的返回值。
 ///
@@ -91,9 +95,38 @@ pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> AlienResult<isize> {
 pub fn ioctl(fd: usize, cmd: usize, arg: usize) -> AlienResult<isize> {
     let process = current_task().unwrap();
     let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
-    let cmd = TeletypeCommand::try_from(cmd as u32).map_err(|_| LinuxErrno::EINVAL)?;
-    info!("ioctl: {:?} {:?} {:?}", fd, cmd, arg);
-    let res = file.ioctl(cmd as u32, arg)?;
+    let cmd = cmd as u32;
+    match TeletypeCommand::try_from(cmd) {
+        Ok(tty_cmd) => info!("ioctl: {:?} {:?} {:?}", fd, tty_cmd, arg),
+        Err(_) if block::is_block_ioctl(cmd) => {
+            info!("ioctl: {:?} {:#x} {:?}", fd, cmd, arg);
+            // 其它设备只认识终端命令，不能把块设备命令交给它们
+            if file.inode().inode_type() != VfsNodeType::BlockDevice {
+                return Err(LinuxErrno::ENOTTY);
+            }
+        }
+        Err(_) if loop_dev::is_loop_ioctl(cmd) => {
+            info!("ioctl: {:?} {:#x} {:?}", fd, cmd, arg);
+            if matches!(cmd, LOOP_SET_FD | LOOP_CONFIGURE) {
+                let dev =
+                    vfs::dev::loopdev::find_loop_device(&file.inode()).ok_or(LinuxErrno::ENOTTY)?;
+                let (backing_fd, info) = if cmd == LOOP_CONFIGURE {
+                    let mut config = LoopConfig::default();
+                    process
+                        .access_inner()
+                        .copy_from_user(arg as *const LoopConfig, &mut config);
+                    (config.fd as usize, Some(config.info))
+                } else {
+                    (arg, None)
+                };
+                let backing = process.get_file(backing_fd).ok_or(LinuxErrno::EBADF)?;
+                dev.attach(backing, info)?;
+                return Ok(0);
+            }
+        }
+        Err(_) => return Err(LinuxErrno::EINVAL),
+    }
+    let res = file.ioctl(cmd, arg)?;
     Ok(res as isize)
 }
 
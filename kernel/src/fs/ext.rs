@@ -2,7 +2,6 @@ use core::cmp::min;
 
 use constants::{AlienResult, LinuxErrno, AT_FDCWD};
 use syscall_table::syscall_func;
-use vfs::system_root_fs;
 use vfscore::path::VfsPath;
 
 use crate::{fs::user_path_at, task::current_task};
@@ -74,7 +73,8 @@ pub fn sys_fsetxattr(
     let name = process.transfer_str(name);
     let value = process.transfer_buffer(value, size);
     let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
-    let path = VfsPath::new(system_root_fs(), file.dentry());
+    let root = process.access_inner().fs_info.root.clone();
+    let path = VfsPath::new(root, file.dentry());
     path.set_xattr(&name, value[0])?;
     Ok(0)
 }
@@ -141,7 +141,8 @@ pub fn sys_fgetxattr(
     let name = process.transfer_str(name);
     let mut value = process.transfer_buffer(value, size);
     let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
-    let path = VfsPath::new(system_root_fs(), file.dentry());
+    let root = process.access_inner().fs_info.root.clone();
+    let path = VfsPath::new(root, file.dentry());
     let res = path.get_xattr(&name)?;
     let mut copy = 0;
     value.iter_mut().for_each(|x| {
@@ -7,7 +7,10 @@ use constants::{
 use log::{info, warn};
 use syscall_table::syscall_func;
 
-use crate::{fs::user_path_at, task::current_task};
+use crate::{
+    fs::{cgroup_parent_at, check_writable_at, user_path_at},
+    task::current_task,
+};
 /// 一个系统调用，用于创建相对于一个目录某位置处的一个文件的(硬)链接。
 ///
 /// 当传入的 `old_name` 是一个相对地址时，那么 `old_name` 会被解析成基于文件描述符 `old_fd`
@@ -41,6 +44,7 @@ pub fn sys_linkat(
     let old_name = process.transfer_str(old_name);
     let old_path = user_path_at(old_fd, &old_name)?;
     let new_name = process.transfer_str(new_name);
+    check_writable_at(new_fd, &new_name)?;
     let new_path = user_path_at(new_fd, &new_name)?;
 
     warn!(
@@ -72,7 +76,8 @@ pub fn sys_linkat(
 ///
 ///
 /// `flag`处可以传入的值及其含义包括：
-/// + AT_REMOVEDIR: 0x200，`sys_linkat`将执行`rmdir`操作。(`rmdir`要求要删除的目录必须为空)
+/// + AT_REMOVEDIR: 0x200，`sys_linkat`将执行`rmdir`操作。(`rmdir`要求要删除的目录必须为空，
+///   删除 cgroup2 文件系统中的目录时则要求对应的 cgroup 中没有任务和子 cgroup)
 ///
 /// `flag`可以置为AT_REMOVEDIR或者为0。
 ///
@@ -88,6 +93,13 @@ pub fn sys_unlinkat(fd: isize, path: *const u8, flag: usize) -> AlienResult<isiz
     let path = task.transfer_str(path);
     let flag = UnlinkatFlags::from_bits_truncate(flag as u32);
     info!("unlinkat path: {:?}, flag: {:?}", path, flag);
+    if flag.contains(UnlinkatFlags::AT_REMOVEDIR) {
+        if let Some((cgroup, parent, name)) = cgroup_parent_at(fd, &path)? {
+            cgroup.rmdir(&parent, &name)?;
+            return Ok(0);
+        }
+    }
+    check_writable_at(fd, &path)?;
     let path = user_path_at(fd, &path)?;
     if flag.contains(UnlinkatFlags::AT_REMOVEDIR) {
         path.rmdir()?;
@@ -111,6 +123,7 @@ pub fn sys_symlinkat(
     let process = current_task().unwrap();
     let old_name = process.transfer_str(old_name);
     let new_name = process.transfer_str(new_name);
+    check_writable_at(new_fd, &new_name)?;
     let new_path = user_path_at(new_fd, &new_name)?;
     new_path.symlink(&old_name)?;
     Ok(0)
@@ -5,18 +5,29 @@ pub mod link;
 pub mod poll;
 pub mod select;
 pub mod stdio;
+pub mod writeback;
 
-use alloc::vec::Vec;
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 
 use constants::{io::InodeMode, AlienResult, LinuxErrno, AT_FDCWD};
 use log::info;
 use vfs::system_root_fs;
 use vfscore::{
+    dentry::VfsDentry,
+    inode::VfsInode,
     path::{SysContext, VfsPath},
     utils::{VfsInodeMode, VfsNodeType},
 };
 
-use crate::task::{current_task, FsContext};
+use crate::task::{
+    cgroup::{find_cgroup, Cgroup},
+    current_task, FsContext,
+};
 
 /// 地址解析函数，通过 `fd` 所指向的一个目录文件 和 相对于该目录文件的路径或绝对路径 `path` 解析出某目标文件的绝对路径。
 ///
@@ -29,21 +40,78 @@ use crate::task::{current_task, FsContext};
 fn user_path_at(fd: isize, path: &str) -> AlienResult<VfsPath> {
     info!("user_path_at fd: {},path:{}", fd, path);
     let process = current_task().unwrap();
+    // 路径基于进程的根目录解析，不同挂载命名空间中的进程拥有不同的根目录
+    let fs_context = process.access_inner().fs_info.clone();
+    let root = fs_context.root.clone();
     let res = if !path.starts_with("/") {
         if fd == AT_FDCWD {
-            let fs_context = process.access_inner().fs_info.clone();
-            VfsPath::new(system_root_fs(), fs_context.cwd).join(path)
+            VfsPath::new(root, fs_context.cwd).join(path)
         } else {
             let fd = fd as usize;
             let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
-            VfsPath::new(system_root_fs(), file.dentry()).join(path)
+            VfsPath::new(root, file.dentry()).join(path)
         }
     } else {
-        VfsPath::new(system_root_fs(), system_root_fs()).join(path)
+        VfsPath::new(root.clone(), root).join(path)
     };
     res.map_err(|e| e.into())
 }
 
+/// 将 `path` 拆分为父目录与最后一级
+fn split_parent(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches('/');
+    match path.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((parent, name)) => (parent, name),
+        None => (".", path),
+    }
+}
+
+/// 修改 `path` 前检查访问它时经过的挂载或者它所在的文件系统是否是只读的，是则返回 `EROFS`。
+/// `path` 不存在时检查它的父目录，用于创建文件；父目录也不存在时交给通常的路径解析报告错误
+fn check_writable_at(fd: isize, path: &str) -> AlienResult<()> {
+    let open = |path: &str| user_path_at(fd, path).and_then(|p| p.open(None).map_err(Into::into));
+    let dentry = match open(path) {
+        Ok(dentry) => dentry,
+        Err(_) => match open(split_parent(path).0) {
+            Ok(dentry) => dentry,
+            Err(_) => return Ok(()),
+        },
+    };
+    // 挂载表中的挂载点是相对于根目录的路径，相对路径需要加上起始目录的路径
+    let full_path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        let task = current_task().unwrap();
+        let base = if fd == AT_FDCWD {
+            task.access_inner().cwd().cwd
+        } else {
+            task.get_file(fd as usize)
+                .ok_or(LinuxErrno::EBADF)?
+                .dentry()
+        };
+        format!("{}/{}", base.path(), path)
+    };
+    vfs::mount::check_writable(&dentry, &full_path)
+}
+
+/// 如果 `path` 的父目录是 cgroup2 文件系统中的目录，返回父目录对应的 cgroup、父目录以及路径的最后一级，
+/// 用于通过 `mkdir`/`rmdir` 创建和删除 cgroup。父目录不存在时返回 `None`，交给通常的路径解析报告错误。
+fn cgroup_parent_at(
+    fd: isize,
+    path: &str,
+) -> AlienResult<Option<(Arc<Cgroup>, Arc<dyn VfsDentry>, String)>> {
+    let (parent, name) = split_parent(path);
+    if name.is_empty() {
+        return Ok(None);
+    }
+    let Ok(parent_dt) = user_path_at(fd, parent).and_then(|p| p.open(None).map_err(Into::into))
+    else {
+        return Ok(None);
+    };
+    Ok(find_cgroup(&parent_dt)?.map(|cgroup| (cgroup, parent_dt, name.to_string())))
+}
+
 pub fn read_all(file_name: &str, buf: &mut Vec<u8>) -> bool {
     let task = current_task();
     let path = if task.is_none() {
@@ -75,6 +143,20 @@ pub fn read_all(file_name: &str, buf: &mut Vec<u8>) -> bool {
     assert_eq!(offset, size);
     true
 }
+/// 以 `mode` 创建 (或打开已存在的) 普通文件 `file_name` 并将其截断为空，相对路径基于当前进程的工作目录解析。
+///
+/// 用于内核主动写出文件的场景，如生成 core 文件。
+pub fn create_file(file_name: &str, mode: u32) -> AlienResult<Arc<dyn VfsInode>> {
+    let path = user_path_at(AT_FDCWD, file_name)?;
+    let dentry = path.open(Some(VfsInodeMode::from_bits_truncate(mode)))?;
+    let inode = dentry.inode()?;
+    if inode.inode_type() != VfsNodeType::File {
+        return Err(LinuxErrno::EISDIR);
+    }
+    let root = current_task().unwrap().access_inner().fs_info.root.clone();
+    VfsPath::new(root, dentry).truncate(0)?;
+    Ok(inode)
+}
 
 /// [InodeMode](InodeMode)转换为[VfsInodeMode](VfsInodeMode)
 fn im2vim(mode: InodeMode) -> VfsInodeMode {
@@ -7,11 +7,15 @@ use constants::{
     AlienResult, LinuxErrno,
 };
 use log::{info, warn};
+use platform::config::CLOCK_FREQ;
 use syscall_table::syscall_func;
-use timer::{get_time_ms, TimeNow, ToClock};
+use timer::{get_time_ms, read_timer, TimeNow, ToClock};
 use vfs::epoll::EpollFile;
 
-use crate::task::{current_task, do_suspend};
+use crate::{
+    task::{current_task, do_suspend},
+    time::sleep_until,
+};
 
 /// 一个系统调用，用于在一些文件描述符上等待事件。作用与 [`pselect6`] 相似。
 ///
@@ -55,6 +59,11 @@ pub fn ppoll(fds_ptr: usize, nfds: usize, time: usize, _mask: usize) -> AlienRes
     } else {
         None
     }; // wait forever
+    if nfds == 0 {
+        // 没有需要等待的文件描述符，直接睡眠到超时或被信号打断
+        sleep_until(wait_time)?;
+        return Ok(0);
+    }
     let mut res = 0;
     loop {
         let task = current_task().unwrap();
@@ -157,6 +166,13 @@ pub fn epoll_pwait(
         let epoll_file = epoll_file
             .downcast_arc::<EpollFile>()
             .map_err(|_| LinuxErrno::EINVAL)?;
+        if epoll_file.interest().is_empty() {
+            // 没有需要等待的文件描述符，直接睡眠到超时或被信号打断
+            let deadline =
+                (timeout_ms as isize >= 0).then(|| read_timer() + timeout_ms * CLOCK_FREQ / 1000);
+            sleep_until(deadline)?;
+            break Vec::new();
+        }
 
         let interset = epoll_file.interest();
         let mut res = Vec::with_capacity(interset.len());
@@ -13,7 +13,10 @@ use log::{info, trace};
 use syscall_table::syscall_func;
 use timer::{TimeNow, ToClock};
 
-use crate::task::{current_task, do_suspend};
+use crate::{
+    task::{current_task, do_suspend},
+    time::sleep_until,
+};
 
 /// 一个系统调用，实现 IO 端口的复用。一般用于用户程序的一段循环体中，
 /// 用于周期性检测一组关注的文件描述符集里是否有需要进行处理的IO事件发生。
@@ -103,6 +106,12 @@ pub fn pselect6(
         0
     };
 
+    if nfds == 0 || ori_readfds | ori_writefds | ori_exceptfds == 0 {
+        // 没有需要等待的文件描述符，直接睡眠到超时或被信号打断
+        sleep_until(wait_time.filter(|time| *time != usize::MAX))?;
+        return Ok(0);
+    }
+
     // at iperf test, if readfds hav one fd is ok, but writefds is empty,
     // it still return 1 and cause recursion error
     do_suspend();
@@ -1,27 +1,34 @@
-use alloc::sync::Arc;
+use alloc::{format, sync::Arc};
 
 use constants::io::OpenFlags;
 use spin::Lazy;
 use vfs::{kfile::KernelFile, system_root_fs};
-use vfscore::path::VfsPath;
+use vfscore::{dentry::VfsDentry, path::VfsPath};
 
 type Stdin = KernelFile;
 type Stdout = KernelFile;
 
+/// 打开控制台设备。命令行中的 `console=` 指定了 `/dev` 下的设备名，设备不存在时使用 `/dev/tty`
+fn open_console() -> Arc<dyn VfsDentry> {
+    let path = VfsPath::new(system_root_fs(), system_root_fs());
+    if let Some(console) = platform::cmdline::cmdline().console.as_ref() {
+        match path
+            .join(format!("dev/{}", console))
+            .and_then(|p| p.open(None))
+        {
+            Ok(dentry) => return dentry,
+            Err(_) => println!("console {} not found, use tty", console),
+        }
+    }
+    path.join("dev/tty").unwrap().open(None).unwrap()
+}
+
 pub static STDIN: Lazy<Arc<Stdin>> = Lazy::new(|| {
-    let path = VfsPath::new(system_root_fs(), system_root_fs())
-        .join("dev/tty")
-        .unwrap();
-    let dentry = path.open(None).unwrap();
-    let file = KernelFile::new(dentry, OpenFlags::O_RDONLY);
+    let file = KernelFile::new(open_console(), OpenFlags::O_RDONLY);
     Arc::new(file)
 });
 
 pub static STDOUT: Lazy<Arc<Stdout>> = Lazy::new(|| {
-    let path = VfsPath::new(system_root_fs(), system_root_fs())
-        .join("dev/tty")
-        .unwrap();
-    let dentry = path.open(None).unwrap();
-    let file = KernelFile::new(dentry, OpenFlags::O_WRONLY);
+    let file = KernelFile::new(open_console(), OpenFlags::O_WRONLY);
     Arc::new(file)
 });
@@ -0,0 +1,24 @@
+//! 块设备缓存的周期性写回
+//!
+//! `flush` 内核线程每隔 [`DIRTY_WRITEBACK_INTERVAL_MS`] 毫秒同步一次磁盘文件系统，
+//! 并把块设备缓存中的脏页写回磁盘，使得系统被意外终止时最多丢失一个周期内写入的数据。
+use log::warn;
+use platform::config::CLOCK_FREQ;
+use timer::read_timer;
+
+use crate::time::sleep_until;
+
+/// 两次写回之间的间隔(ms)
+pub const DIRTY_WRITEBACK_INTERVAL_MS: usize = 5000;
+
+/// `flush` 内核线程的入口
+pub fn writeback_thread() {
+    loop {
+        let deadline = read_timer() + DIRTY_WRITEBACK_INTERVAL_MS * CLOCK_FREQ / 1000;
+        // 内核线程不会收到信号，被打断时直接进行下一次写回
+        let _ = sleep_until(Some(deadline));
+        if let Err(e) = vfs::sync_filesystems() {
+            warn!("writeback failed: {:?}", e);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 //! GUI 相关的系统调用
+use constants::LinuxErrno;
 use devices::{GPU_DEVICE, KEYBOARD_INPUT_DEVICE, MOUSE_INPUT_DEVICE};
 use page_table::addr::{align_up_4k, PhysAddr, VirtAddr};
 
@@ -17,11 +18,14 @@ pub fn sys_framebuffer() -> isize {
     let virt_addr = VirtAddr::from(FB_VADDR);
     let current_process = current_task().unwrap();
     let inner = current_process.access_inner();
-    inner
+    if inner
         .address_space
         .lock()
         .map_region(virt_addr, phy_addr, align_up_4k(len), "RWUVAD".into(), true)
-        .unwrap();
+        .is_err()
+    {
+        return LinuxErrno::ENOMEM as isize;
+    }
     FB_VADDR as isize
 }
 
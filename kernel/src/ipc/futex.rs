@@ -12,24 +12,120 @@
 //!
 //! Reference: https://cloud.tencent.com/developer/article/1176832
 //!
-use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
-use core::cmp::min;
+//! Alien 中的 futex 以 futex 字所在的物理地址作为等待队列的键值，因此不同进程通过共享内存使用同一个 futex 时也能正确地同步。
+//! 除了基本的等待与唤醒外，这里还实现了带位掩码的等待与唤醒、`FUTEX_WAKE_OP`、重新排队、
+//! 同时等待多个 futex 的 `futex_waitv` 以及线程退出时对 robust 锁列表的清理。
+//!
+//! Alien 的调度器中任务没有优先级，无法实现优先级继承，因此与没有开启 `CONFIG_FUTEX_PI` 的 Linux 一样，
+//! PI 锁相关的操作返回 `ENOSYS`，用户态的线程库据此认为系统不支持 `PTHREAD_PRIO_INHERIT`。
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{
+    cmp::min,
+    mem::size_of,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
-use constants::{AlienError, AlienResult};
-use ksync::Mutex;
+use constants::{AlienResult, LinuxErrno};
+use ksync::{Mutex, MutexGuard};
 use smpscheduler::FifoTask;
-use timer::read_timer;
+use timer::{
+    hrtimer::{hrtimer_cancel, hrtimer_start},
+    read_timer,
+};
+
+use crate::{
+    ipc::FUTEX_WAITER,
+    task::{current_task, schedule::schedule, Task, TaskState, GLOBAL_TASK_MANAGER},
+};
+
+pub const FUTEX_WAIT: u32 = 0;
+pub const FUTEX_WAKE: u32 = 1;
+pub const FUTEX_FD: u32 = 2;
+pub const FUTEX_REQUEUE: u32 = 3;
+pub const FUTEX_CMP_REQUEUE: u32 = 4;
+pub const FUTEX_WAKE_OP: u32 = 5;
+pub const FUTEX_LOCK_PI: u32 = 6;
+pub const FUTEX_UNLOCK_PI: u32 = 7;
+pub const FUTEX_TRYLOCK_PI: u32 = 8;
+pub const FUTEX_WAIT_BITSET: u32 = 9;
+pub const FUTEX_WAKE_BITSET: u32 = 10;
+pub const FUTEX_WAIT_REQUEUE_PI: u32 = 11;
+pub const FUTEX_CMP_REQUEUE_PI: u32 = 12;
+pub const FUTEX_LOCK_PI2: u32 = 13;
+
+/// 表示 futex 只在进程内部使用。由于等待队列以物理地址为键值，该标志不影响 Alien 中 futex 的行为
+pub const FUTEX_PRIVATE_FLAG: u32 = 128;
+/// 表示超时时间基于 `CLOCK_REALTIME` 而不是 `CLOCK_MONOTONIC`
+pub const FUTEX_CLOCK_REALTIME: u32 = 256;
+/// 去掉 futex_op 中的标志位后得到操作的类型
+pub const FUTEX_CMD_MASK: u32 = !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+/// 匹配所有等待者的位掩码
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// PI 锁与 robust 锁中，表示有任务正在等待该锁
+pub const FUTEX_WAITERS: u32 = 0x8000_0000;
+/// PI 锁与 robust 锁中，表示锁的持有者已经退出
+pub const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+/// PI 锁与 robust 锁中，锁的持有者的 tid 所占的位
+pub const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+
+/// `FUTEX_WAKE_OP` 中表示操作数为 `1 << oparg`
+const FUTEX_OP_OPARG_SHIFT: u32 = 8;
+const FUTEX_OP_SET: u32 = 0;
+const FUTEX_OP_ADD: u32 = 1;
+const FUTEX_OP_OR: u32 = 2;
+const FUTEX_OP_ANDN: u32 = 3;
+const FUTEX_OP_XOR: u32 = 4;
+const FUTEX_OP_CMP_EQ: u32 = 0;
+const FUTEX_OP_CMP_NE: u32 = 1;
+const FUTEX_OP_CMP_LT: u32 = 2;
+const FUTEX_OP_CMP_LE: u32 = 3;
+const FUTEX_OP_CMP_GT: u32 = 4;
+const FUTEX_OP_CMP_GE: u32 = 5;
+
+/// `futex_waitv` 一次最多等待的 futex 数
+pub const FUTEX_WAITV_MAX: usize = 128;
+const FUTEX2_SIZE_U32: u32 = 0x02;
+const FUTEX2_SIZE_MASK: u32 = 0x03;
+const FUTEX2_PRIVATE: u32 = FUTEX_PRIVATE_FLAG;
 
-use crate::task::{Task, GLOBAL_TASK_MANAGER};
+/// 退出时最多处理的 robust 锁的个数，防止用户传入的链表成环
+const ROBUST_LIST_LIMIT: usize = 2048;
+
+/// `futex_waitv` 中描述一个 futex 的结构，与 Linux 中的 `struct futex_waitv` 保持一致
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FutexWaitv {
+    /// 期望的 futex 字的值
+    pub val: u64,
+    /// futex 字的地址
+    pub uaddr: u64,
+    /// `FUTEX2_*` 标志
+    pub flags: u32,
+    __reserved: u32,
+}
+
+/// 等待 futex 的任务被唤醒的原因
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FutexWakeReason {
+    /// 被其它任务唤醒，附带被唤醒的 futex 在 `futex_waitv` 中的下标
+    Wake(usize),
+    /// 等待超时
+    Timeout,
+    /// 收到信号
+    Signal,
+}
 
 /// 用于记录一个进程等待一个 futex 的相关信息
 pub struct FutexWaiter {
     /// 进程的控制块
     task: Option<Arc<Task>>,
-    /// 进程等待 futex 的等待时间
-    wait_time: Option<usize>,
-    /// 超时事件的标志位，标识该进程对于 futex 等待是否超时
-    timeout_flag: Arc<Mutex<bool>>,
+    /// 位掩码，只有与唤醒时的位掩码有交集才会被唤醒
+    bitset: u32,
+    /// 该 futex 在 `futex_waitv` 中的下标，普通的等待中为 0
+    index: usize,
+    /// 唤醒的原因。`futex_waitv` 中同一个任务的多个等待者共享该状态，任务只会被其中一个唤醒
+    state: Arc<Mutex<Option<FutexWakeReason>>>,
 }
 
 /// 用于管理 futex 等待队列的数据结构
@@ -41,17 +137,45 @@ pub struct FutexWaitManager {
 
 impl FutexWaiter {
     /// 创建一个新的 `FutexWaiter` 保存等待在某 futex 上的一个进程 有关等待的相关信息
-    pub fn new(task: Arc<Task>, wait_time: Option<usize>, timeout_flag: Arc<Mutex<bool>>) -> Self {
+    pub fn new(
+        task: Arc<Task>,
+        bitset: u32,
+        index: usize,
+        state: Arc<Mutex<Option<FutexWakeReason>>>,
+    ) -> Self {
         Self {
             task: Some(task),
-            wait_time,
-            timeout_flag,
+            bitset,
+            index,
+            state,
         }
     }
 
-    /// 唤醒该进程，返回该进程的控制块
-    pub fn wake(&mut self) -> Arc<Task> {
-        self.task.take().unwrap()
+    /// 该等待者对应的任务是否已经被唤醒(可能是通过 `futex_waitv` 中的另一个 futex)
+    fn is_woken(&self) -> bool {
+        self.task.is_none() || self.state.lock().is_some()
+    }
+
+    /// 等待者对应的任务的 tid
+    fn tid(&self) -> u32 {
+        self.task.as_ref().map_or(0, |task| task.get_tid() as u32)
+    }
+
+    /// 以 `reason` 为原因唤醒该进程。如果进程已经被唤醒，则返回 false
+    fn wake(&mut self, reason: FutexWakeReason) -> bool {
+        let mut state = self.state.lock();
+        if state.is_some() {
+            return false;
+        }
+        *state = Some(reason);
+        drop(state);
+        match self.task.take() {
+            Some(task) => {
+                GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
+                true
+            }
+            None => false,
+        }
     }
 }
 
@@ -68,108 +192,354 @@ impl FutexWaitManager {
         self.map.entry(futex).or_insert(Vec::new()).push(waiter);
     }
 
-    ///由于信号引发的唤醒操作
-    pub fn wake_for_signal(&mut self) {
+    /// 由于信号引发的唤醒操作，唤醒任务 `tid` 的所有等待
+    pub fn wake_for_signal(&mut self, tid: usize) {
         for (_, waiters) in self.map.iter_mut() {
-            let mut record = vec![];
-            for (index, waiter) in waiters.iter_mut().enumerate() {
-                let task = waiter.task.as_ref().unwrap();
-                let task_inner = task.access_inner();
-                let receiver = task_inner.signal_receivers.lock();
-                if receiver.have_signal() {
-                    drop(receiver);
-                    drop(task_inner);
-                    let task = waiter.wake();
-                    GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
-                    record.push(index);
+            waiters.retain_mut(|waiter| {
+                if waiter.is_woken() {
+                    return false;
                 }
-            }
-            record.iter().for_each(|index| {
-                waiters.remove(*index);
-            })
+                if waiter.tid() as usize != tid {
+                    return true;
+                }
+                waiter.wake(FutexWakeReason::Signal);
+                false
+            });
         }
         self.delete_empty_waiters();
     }
 
-    /// 由于超时引发的唤醒操作
-    pub fn wake_for_timeout(&mut self) {
-        let now = read_timer();
-        for (_, waiters) in self.map.iter_mut() {
-            let mut record = vec![];
-            for (index, waiter) in waiters.iter_mut().enumerate() {
-                if let Some(wait_time) = waiter.wait_time {
-                    if wait_time <= now {
-                        *waiter.timeout_flag.lock() = true;
-                        let task = waiter.wake();
-                        GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
-                        record.push(index);
-                    }
-                }
-            }
-            record.iter().for_each(|index| {
-                waiters.remove(*index);
-            })
+    /// 由于超时引发的唤醒操作，由等待时设置的 hrtimer 在截止时间到达时调用
+    pub fn wake_for_timeout(&mut self, state: &Arc<Mutex<Option<FutexWakeReason>>>) {
+        let waiter = self
+            .map
+            .values_mut()
+            .flat_map(|waiters| waiters.iter_mut())
+            .find(|waiter| Arc::ptr_eq(&waiter.state, state));
+        if let Some(waiter) = waiter {
+            waiter.wake(FutexWakeReason::Timeout);
         }
-        // delete empty waiters
-        self.delete_empty_waiters();
+        self.remove_state(state);
     }
 
     /// 清空所有空的等待队列
     fn delete_empty_waiters(&mut self) {
-        let mut record = vec![];
-        for (futex, waiters) in self.map.iter() {
-            if waiters.is_empty() {
-                record.push(*futex);
-            }
-        }
-        record.iter().for_each(|futex| {
-            self.map.remove(futex);
-        })
+        self.map.retain(|_, waiters| !waiters.is_empty());
     }
 
-    /// 唤醒 futex 上的至多 num 个等待的进程
-    pub fn wake(&mut self, futex: usize, num: usize) -> AlienResult<usize> {
+    /// 唤醒 futex 上的至多 num 个位掩码与 `bitset` 有交集的等待进程，返回唤醒的进程数
+    pub fn wake(&mut self, futex: usize, num: usize, bitset: u32) -> usize {
+        let mut count = 0;
         if let Some(waiters) = self.map.get_mut(&futex) {
-            error!("there are {} waiters, wake {}", waiters.len(), num);
-            let min_index = min(num, waiters.len());
-            for i in 0..min_index {
-                let task = waiters[i].wake();
-                GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
-            }
-            // delete waiters
-            waiters.drain(0..min_index);
-            warn!("wake {} tasks", min_index);
-            Ok(min_index)
-        } else {
-            error!("futex {} not found", futex);
-            Err(AlienError::EINVAL)
+            waiters.retain_mut(|waiter| {
+                if waiter.is_woken() {
+                    return false;
+                }
+                if count >= num || waiter.bitset & bitset == 0 {
+                    return true;
+                }
+                if waiter.wake(FutexWakeReason::Wake(waiter.index)) {
+                    count += 1;
+                }
+                false
+            });
         }
+        self.delete_empty_waiters();
+        warn!("wake {} tasks on futex {:#x}", count, futex);
+        count
     }
 
     /// 将原来等待在 old_futex 上至多 num 个进程转移到 requeue_futex 上等待，返回转移的进程数
-    pub fn requeue(
-        &mut self,
-        requeue_futex: usize,
-        num: usize,
-        old_futex: usize,
-    ) -> AlienResult<usize> {
-        if num == 0 {
-            return Ok(0);
-        }
-        // move waiters
-        let mut waiters = self.map.remove(&old_futex).unwrap();
-        // create new waiters
-        let new_waiters = self.map.entry(requeue_futex).or_insert(Vec::new());
-        let min_index = min(num, waiters.len());
-        error!("requeue {} waiters", min_index);
-        for _ in 0..min_index {
-            let waiter = waiters.pop().unwrap();
-            new_waiters.push(waiter);
-        }
-        // insert old waiters
+    pub fn requeue(&mut self, requeue_futex: usize, num: usize, old_futex: usize) -> usize {
+        let mut waiters = match self.map.remove(&old_futex) {
+            Some(waiters) => waiters,
+            None => return 0,
+        };
+        waiters.retain(|waiter| !waiter.is_woken());
+        let num = min(num, waiters.len());
+        let moved = waiters.drain(..num).collect::<Vec<_>>();
+        self.map.entry(requeue_futex).or_default().extend(moved);
         if !waiters.is_empty() {
             self.map.insert(old_futex, waiters);
         }
-        Ok(min_index)
+        num
+    }
+
+    /// 删除所有与 `state` 关联的等待者，在任务被唤醒后调用
+    fn remove_state(&mut self, state: &Arc<Mutex<Option<FutexWakeReason>>>) {
+        for (_, waiters) in self.map.iter_mut() {
+            waiters.retain(|waiter| !Arc::ptr_eq(&waiter.state, state));
+        }
+        self.delete_empty_waiters();
+    }
+}
+
+/// 获取用户地址 `uaddr` 处的 futex 字，同时返回其物理地址作为等待队列的键值
+fn futex_word(task: &Arc<Task>, uaddr: usize) -> AlienResult<(&'static AtomicU32, usize)> {
+    if uaddr % size_of::<u32>() != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let phy = task.access_inner().try_transfer_raw(uaddr)?;
+    Ok((unsafe { &*(phy as *const AtomicU32) }, phy))
+}
+
+/// 截止时间是否已经到达
+fn is_expired(deadline: Option<usize>) -> bool {
+    deadline.map_or(false, |deadline| deadline <= read_timer())
+}
+
+/// 将当前任务加入 `keys` 中各个 futex 的等待队列后让出 CPU，被唤醒后返回唤醒的原因。
+///
+/// 调用者需要持有 futex 管理器的锁，并在持有锁的情况下检查 futex 字的值，以免错过唤醒。
+fn futex_sleep(
+    mut manager: MutexGuard<FutexWaitManager>,
+    keys: &[usize],
+    deadline: Option<usize>,
+    bitset: u32,
+) -> FutexWakeReason {
+    let task = current_task().unwrap();
+    let state = Arc::new(Mutex::new(None));
+    for (index, key) in keys.iter().enumerate() {
+        let waiter = FutexWaiter::new(task.clone(), bitset, index, state.clone());
+        manager.add_waiter(*key, waiter);
+    }
+    let timer = deadline.map(|deadline| {
+        let state = state.clone();
+        hrtimer_start(
+            deadline,
+            Box::new(move || FUTEX_WAITER.lock().wake_for_timeout(&state)),
+        )
+    });
+    task.update_state(TaskState::Waiting);
+    drop(manager);
+    warn!("Because of futex, we switch to other task");
+    schedule();
+    if let Some(timer) = timer {
+        hrtimer_cancel(timer);
+    }
+    FUTEX_WAITER.lock().remove_state(&state);
+    let reason = state.lock().take();
+    reason.unwrap_or(FutexWakeReason::Wake(0))
+}
+
+/// `FUTEX_WAIT` 与 `FUTEX_WAIT_BITSET`：如果 `uaddr` 处的值等于 `val`，则等待直到被位掩码与 `bitset` 有交集的唤醒操作唤醒。
+///
+/// 值不相等时返回 `EAGAIN`，超时返回 `ETIMEDOUT`，收到信号返回 `EINTR`。
+pub fn futex_wait(
+    uaddr: usize,
+    val: u32,
+    deadline: Option<usize>,
+    bitset: u32,
+) -> AlienResult<isize> {
+    if bitset == 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let (word, key) = futex_word(task, uaddr)?;
+    let manager = FUTEX_WAITER.lock();
+    if word.load(Ordering::SeqCst) != val {
+        return Err(LinuxErrno::EAGAIN);
+    }
+    if is_expired(deadline) {
+        return Err(LinuxErrno::ETIMEDOUT);
+    }
+    match futex_sleep(manager, &[key], deadline, bitset) {
+        FutexWakeReason::Wake(_) => Ok(0),
+        FutexWakeReason::Timeout => Err(LinuxErrno::ETIMEDOUT),
+        FutexWakeReason::Signal => Err(LinuxErrno::EINTR),
+    }
+}
+
+/// `FUTEX_WAKE` 与 `FUTEX_WAKE_BITSET`：唤醒 `uaddr` 上至多 `num` 个位掩码与 `bitset` 有交集的等待进程，返回唤醒的进程数
+pub fn futex_wake(uaddr: usize, num: usize, bitset: u32) -> AlienResult<isize> {
+    if bitset == 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let (_, key) = futex_word(task, uaddr)?;
+    let count = FUTEX_WAITER.lock().wake(key, num, bitset);
+    Ok(count as isize)
+}
+
+/// `FUTEX_REQUEUE` 与 `FUTEX_CMP_REQUEUE`：唤醒 `uaddr` 上至多 `num` 个等待进程，再将至多 `nr_requeue` 个等待进程转移到 `uaddr2` 上。
+///
+/// `cmpval` 不为 None 时，先检查 `uaddr` 处的值是否等于 `cmpval`，不相等时返回 `EAGAIN`。返回唤醒与转移的进程数之和。
+pub fn futex_requeue(
+    uaddr: usize,
+    num: usize,
+    uaddr2: usize,
+    nr_requeue: usize,
+    cmpval: Option<u32>,
+) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let (word, key) = futex_word(task, uaddr)?;
+    let (_, key2) = futex_word(task, uaddr2)?;
+    let mut manager = FUTEX_WAITER.lock();
+    if let Some(cmpval) = cmpval {
+        if word.load(Ordering::SeqCst) != cmpval {
+            return Err(LinuxErrno::EAGAIN);
+        }
+    }
+    let woken = manager.wake(key, num, FUTEX_BITSET_MATCH_ANY);
+    let requeued = manager.requeue(key2, nr_requeue, key);
+    Ok((woken + requeued) as isize)
+}
+
+/// 将 12 位有符号数扩展为 i32
+#[inline]
+fn sign_extend_12(val: u32) -> i32 {
+    ((val << 20) as i32) >> 20
+}
+
+/// `FUTEX_WAKE_OP`：原子地对 `uaddr2` 处的值进行 `encoded_op` 中指定的操作，然后唤醒 `uaddr` 上至多 `num` 个等待进程；
+/// 如果 `uaddr2` 处原来的值满足 `encoded_op` 中指定的比较条件，再唤醒 `uaddr2` 上至多 `num2` 个等待进程。
+///
+/// 返回唤醒的进程总数，操作或比较的类型不合法时返回 `ENOSYS`。
+pub fn futex_wake_op(
+    uaddr: usize,
+    num: usize,
+    uaddr2: usize,
+    num2: usize,
+    encoded_op: u32,
+) -> AlienResult<isize> {
+    let op = (encoded_op >> 28) & 0x7;
+    let cmp = (encoded_op >> 24) & 0xf;
+    let mut oparg = sign_extend_12(encoded_op >> 12);
+    let cmparg = sign_extend_12(encoded_op);
+    if encoded_op & (FUTEX_OP_OPARG_SHIFT << 28) != 0 {
+        oparg = 1 << (oparg & 31);
+    }
+    if op > FUTEX_OP_XOR || cmp > FUTEX_OP_CMP_GE {
+        return Err(LinuxErrno::ENOSYS);
+    }
+    let task = current_task().unwrap();
+    let (_, key) = futex_word(task, uaddr)?;
+    let (word2, key2) = futex_word(task, uaddr2)?;
+    let mut manager = FUTEX_WAITER.lock();
+    let old = word2
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
+            let old = old as i32;
+            let new = match op {
+                FUTEX_OP_SET => oparg,
+                FUTEX_OP_ADD => old.wrapping_add(oparg),
+                FUTEX_OP_OR => old | oparg,
+                FUTEX_OP_ANDN => old & !oparg,
+                _ => old ^ oparg,
+            };
+            Some(new as u32)
+        })
+        .unwrap() as i32;
+    let cond = match cmp {
+        FUTEX_OP_CMP_EQ => old == cmparg,
+        FUTEX_OP_CMP_NE => old != cmparg,
+        FUTEX_OP_CMP_LT => old < cmparg,
+        FUTEX_OP_CMP_LE => old <= cmparg,
+        FUTEX_OP_CMP_GT => old > cmparg,
+        _ => old >= cmparg,
+    };
+    let mut count = manager.wake(key, num, FUTEX_BITSET_MATCH_ANY);
+    if cond {
+        count += manager.wake(key2, num2, FUTEX_BITSET_MATCH_ANY);
+    }
+    Ok(count as isize)
+}
+
+/// `futex_waitv`：同时等待 `waiters` 中的多个 futex，任意一个被唤醒即返回其下标。
+///
+/// 任意一个 futex 字的值与期望值不相等时返回 `EAGAIN`，超时返回 `ETIMEDOUT`，收到信号返回 `EINTR`。
+pub fn futex_wait_multiple(waiters: &[FutexWaitv], deadline: Option<usize>) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let mut words = Vec::with_capacity(waiters.len());
+    for waitv in waiters {
+        if waitv.__reserved != 0
+            || waitv.flags & !(FUTEX2_SIZE_MASK | FUTEX2_PRIVATE) != 0
+            || waitv.flags & FUTEX2_SIZE_MASK != FUTEX2_SIZE_U32
+            || waitv.val > u32::MAX as u64
+        {
+            return Err(LinuxErrno::EINVAL);
+        }
+        words.push(futex_word(task, waitv.uaddr as usize)?);
+    }
+    let manager = FUTEX_WAITER.lock();
+    for ((word, _), waitv) in words.iter().zip(waiters) {
+        if word.load(Ordering::SeqCst) as u64 != waitv.val {
+            return Err(LinuxErrno::EAGAIN);
+        }
+    }
+    if is_expired(deadline) {
+        return Err(LinuxErrno::ETIMEDOUT);
+    }
+    let keys = words.iter().map(|(_, key)| *key).collect::<Vec<_>>();
+    match futex_sleep(manager, &keys, deadline, FUTEX_BITSET_MATCH_ANY) {
+        FutexWakeReason::Wake(index) => Ok(index as isize),
+        FutexWakeReason::Timeout => Err(LinuxErrno::ETIMEDOUT),
+        FutexWakeReason::Signal => Err(LinuxErrno::EINTR),
+    }
+}
+
+/// 读取用户地址空间中 `addr` 处的一个 usize，地址不合法时返回 None
+fn read_user_usize(task: &Arc<Task>, addr: usize) -> Option<usize> {
+    if addr % size_of::<usize>() != 0 {
+        return None;
+    }
+    let phy = task.access_inner().try_transfer_raw(addr).ok()?;
+    Some(unsafe { *(phy as *const usize) })
+}
+
+/// 处理一个持有者已经退出的 robust 锁：如果锁仍被 `tid` 持有，则设置 `FUTEX_OWNER_DIED` 并唤醒一个等待者。
+fn handle_futex_death(task: &Arc<Task>, uaddr: usize, tid: u32) {
+    let (word, key) = match futex_word(task, uaddr) {
+        Ok(word) => word,
+        Err(_) => return,
+    };
+    let mut manager = FUTEX_WAITER.lock();
+    let res = word.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |val| {
+        if val & FUTEX_TID_MASK != tid {
+            None
+        } else {
+            Some((val & FUTEX_WAITERS) | FUTEX_OWNER_DIED)
+        }
+    });
+    if let Ok(old) = res {
+        if old & FUTEX_WAITERS != 0 {
+            manager.wake(key, 1, FUTEX_BITSET_MATCH_ANY);
+        }
+    }
+}
+
+/// 线程退出时遍历其通过 `set_robust_list` 注册的 robust 锁列表，释放其仍然持有的锁。
+///
+/// 列表头的结构与 Linux 中的 `struct robust_list_head` 一致：依次为指向第一个节点的指针、
+/// 节点到 futex 字的偏移以及正在加锁或解锁的节点。节点指针的最低位表示该锁是否为 PI 锁。
+pub fn exit_robust_list(task: &Arc<Task>) {
+    let head = core::mem::take(&mut task.access_inner().robust.head);
+    if head == 0 {
+        return;
+    }
+    let tid = task.get_tid() as u32;
+    let (first, futex_offset, pending) = match (
+        read_user_usize(task, head),
+        read_user_usize(task, head + size_of::<usize>()),
+        read_user_usize(task, head + 2 * size_of::<usize>()),
+    ) {
+        (Some(first), Some(offset), Some(pending)) => (first, offset as isize, pending & !1),
+        _ => return,
+    };
+    let mut entry = first & !1;
+    let mut limit = ROBUST_LIST_LIMIT;
+    while entry != head && entry != 0 && limit > 0 {
+        // 先读取下一个节点，因为处理当前节点后其所在的内存可能被其它线程释放
+        let next = read_user_usize(task, entry).map(|next| next & !1);
+        if entry != pending {
+            handle_futex_death(task, entry.wrapping_add_signed(futex_offset), tid);
+        }
+        entry = match next {
+            Some(next) => next,
+            None => return,
+        };
+        limit -= 1;
+    }
+    if pending != 0 {
+        handle_futex_death(task, pending.wrapping_add_signed(futex_offset), tid);
     }
 }
@@ -1,35 +1,41 @@
-//! IPC 进程间通信，目前 Alien 支持管道、共享内存、信号以及futex'等进程间的通信机制。
+//! IPC 进程间通信，目前 Alien 支持管道、共享内存、System V 与 POSIX 消息队列、信号量、信号以及futex'等进程间的通信机制。
 //!
 //! [`futex`] 子模块指明了 Alien 中的 futex (快速用户空间互斥体)结构。
 //! [`pipe`] 子模块指明了 Alien 中管道结构。
 //! [`shm`] 子模块指明了 Alien 中的共享内存结构。
+//! [`msg`] 子模块指明了 Alien 中的 System V 消息队列。
+//! [`mqueue`] 子模块指明了 Alien 中的 POSIX 消息队列。
+//! [`sem`] 子模块指明了 Alien 中的 System V 信号量集。
 //! [`signal`] 子模块指明了 Alien 中使用的信号机制。
 
-use alloc::sync::Arc;
-use core::sync::atomic::{AtomicI32, Ordering};
+use alloc::vec;
 
-use constants::{
-    ipc::{FutexOp, RobustList},
-    time::TimeSpec,
-    AlienResult, LinuxErrno,
-};
+use constants::{ipc::RobustList, time::TimeSpec, AlienResult, LinuxErrno};
 use ksync::Mutex;
 pub use pipe::*;
 pub use shm::*;
 pub use signal::*;
 use spin::Lazy;
-use timer::{TimeNow, ToClock};
+use timer::{read_timer, ToClock};
 
 use crate::{
     fs::basic::sys_close,
-    ipc::futex::{FutexWaitManager, FutexWaiter},
-    task::{current_task, schedule::schedule, TaskState},
+    ipc::futex::*,
+    task::current_task,
+    time::{
+        posix_timer::{CLOCK_MONOTONIC, CLOCK_REALTIME},
+        realtime_to_clock, wake_sleeper,
+    },
 };
 
 pub mod futex;
+pub mod mqueue;
+pub mod msg;
 mod pipe;
+pub mod sem;
 pub mod shm;
 pub mod signal;
+mod sysv;
 
 /// 一个全局变量，用于记录和管理 futex 的等待队列
 pub static FUTEX_WAITER: Lazy<Mutex<FutexWaitManager>> =
@@ -113,20 +119,20 @@ static FCOUNT: Mutex<usize> = Mutex::new(0);
 ///
 /// 参数：
 /// + `uaddr`: 用户态下共享内存的地址，里面存放的是一个对齐的整型计数器，指向一个 futex。
-/// + `futex_op`: 指明操作的类型。具体操作类型可见 [`FutexOp`]。目前 Alien 识别的 futex_op 包括：
-///     + FutexOp::FutexWaitPrivate | FutexOp::FutexWait: 先比较 uaddr 上计数器的值和 val 是否相等，如果不相等则将直接返回 `EAGAIN`；否则
-/// 该进程将等待在 uaddr 上，并根据 val2 的值确定等待的逻辑。若 val2 值为0，则表示进程一直等待；若 val2 是一个正数，则表示进程将在等待 val2 时间后因超时被唤醒
-///     + FutexOp::FutexCmpRequeuePiPrivate: 先比较 uaddr 上计数器的值和 val3 是否相等，如果不相等则将直接返回 `EAGAIN`；否则
-/// 唤醒至多 val 个在 uaddr 上等待的进程后，将原来等待在 uaddr 上至多 val2 个进程转移到 uaddr2 上等待，最后返回 唤醒的进程数 + 转移的进程数
-///     + FutexOp::FutexRequeuePrivate: 唤醒至多 val 个在 uaddr 上等待的进程后，将原来等待在 uaddr 上至多 val2 个进程转移到 uaddr2 上等待
-/// 最后返回 唤醒的进程数 + 转移的进程数
-///     + FutexOp::FutexWakePrivate | FutexOp::FutexWake: 唤醒至多 val 个在 uaddr 上等待的进程。最后返回 唤醒的进程数。
+/// + `futex_op`: 指明操作的类型，可以附加 `FUTEX_PRIVATE_FLAG` 与 `FUTEX_CLOCK_REALTIME` 标志。目前 Alien 支持的操作包括：
+///     + `FUTEX_WAIT`/`FUTEX_WAIT_BITSET`: 如果 uaddr 上计数器的值和 val 相等，则在 uaddr 上等待。`val2` 指向超时时间，
+/// `FUTEX_WAIT` 中为相对时间，`FUTEX_WAIT_BITSET` 中为绝对时间，后者只能被位掩码与 `val3` 有交集的唤醒操作唤醒
+///     + `FUTEX_WAKE`/`FUTEX_WAKE_BITSET`: 唤醒至多 val 个在 uaddr 上等待的进程，后者只唤醒位掩码与 `val3` 有交集的进程
+///     + `FUTEX_REQUEUE`/`FUTEX_CMP_REQUEUE`: 唤醒至多 val 个在 uaddr 上等待的进程后，将至多 val2 个进程转移到 uaddr2 上等待，
+/// 后者会先检查 uaddr 上计数器的值是否和 val3 相等
+///     + `FUTEX_WAKE_OP`: 对 uaddr2 进行 val3 编码的原子操作后唤醒 uaddr 上至多 val 个进程，满足条件时再唤醒 uaddr2 上至多 val2 个进程
 /// + `val`: 传入的参数1，将根据 futex_op 发挥不同的作用。
 /// + `val2`: 传入的参数2，将根据 futex_op 发挥不同的作用。
 /// + `uaddr2`: 传入的地址2，将根据 futex_op 发挥不同的作用。
 /// + `val3`: 传入的参数3，将根据 futex_op 发挥不同的作用。
 ///
-/// 在此过程中，如果出现异常，会返回异常类型。
+/// `FUTEX_WAIT_BITSET` 的绝对超时默认基于 `CLOCK_MONOTONIC`，设置 `FUTEX_CLOCK_REALTIME` 时基于 `CLOCK_REALTIME`。
+/// 在此过程中，如果出现异常，会返回异常类型；不支持的操作(包括所有 PI 锁的操作)返回 `ENOSYS`。
 ///
 /// Reference: [futex](https://man7.org/linux/man-pages/man2/futex.2.html)
 #[syscall_func(98)]
@@ -137,92 +143,111 @@ pub fn futex(
     val2: usize,
     uaddr2: usize,
     val3: u32,
-) -> isize {
+) -> AlienResult<isize> {
     *FCOUNT.lock() += 1;
-    let futex_op = FutexOp::try_from(futex_op).unwrap();
-    let task = current_task().unwrap();
-    let task_inner = task.access_inner();
     warn!(
-        "futex: {:?} {:?} {:?} {:?} {:?} {:?}",
+        "futex: {:#x} {:#x} {:?} {:#x} {:#x} {:?}",
         uaddr, futex_op, val, val2, uaddr2, val3
     );
-    match futex_op {
-        FutexOp::FutexWaitPrivate | FutexOp::FutexWait => {
-            let uaddr_ref = task_inner.transfer_raw_ptr_mut(uaddr as *mut i32);
-            let uaddr_atomic = AtomicI32::from_mut(uaddr_ref);
-
-            if uaddr_atomic.load(Ordering::SeqCst) != val as i32 {
-                error!("FutexWait: uaddr_ref != val");
-                return LinuxErrno::EAGAIN as isize;
-            }
-            // we checkout the timeout
-            let wait_time = if val2 != 0 {
-                let time_spec = task_inner.transfer_raw_ptr(val2 as *const TimeSpec);
-                Some(time_spec.to_clock() + TimeSpec::now().to_clock())
-            } else {
-                // wait forever
-                None
-            };
-            // add to wait queue
-            drop(task_inner);
-            warn!("Futex wait time: {:?}", wait_time);
-            let timeout_flag = Arc::new(Mutex::new(false));
-            let waiter = FutexWaiter::new(task.clone(), wait_time, timeout_flag.clone());
-            FUTEX_WAITER.lock().add_waiter(uaddr, waiter);
-            // switch to other task
-            task.update_state(TaskState::Waiting);
-            warn!("Because of futex, we switch to other task");
-            schedule();
-            // checkout the timeout flag
-            let timeout_flag = timeout_flag.lock();
-            if *timeout_flag {
-                return 0;
-            }
-        }
-        FutexOp::FutexCmpRequeuePiPrivate => {
-            let uaddr_ref = task_inner.transfer_raw_ptr(uaddr as *const u32);
-            if *uaddr_ref != val3 {
-                error!("FutexRequeuePrivate: uaddr_ref != val");
-                return LinuxErrno::EAGAIN as isize;
-            }
-            // wake val tasks
-            let res = FUTEX_WAITER.lock().wake(uaddr, val as usize);
-            if res.is_err() {
-                return LinuxErrno::EINVAL as isize;
-            }
-            // requeue val2 tasks to uaddr2
-            let res2 = FUTEX_WAITER.lock().requeue(uaddr2, val2, uaddr);
-            if res2.is_err() {
-                return LinuxErrno::EINVAL as isize;
-            }
-            return res2.unwrap() as isize + res.unwrap() as isize;
-        }
-        FutexOp::FutexRequeuePrivate => {
-            // wake val tasks
-            let res = FUTEX_WAITER.lock().wake(uaddr, val as usize);
-            if res.is_err() {
-                return LinuxErrno::EINVAL as isize;
-            }
-            // requeue val2 tasks to uaddr2
-            let res2 = FUTEX_WAITER.lock().requeue(uaddr2, val2, uaddr);
-            if res2.is_err() {
-                return LinuxErrno::EINVAL as isize;
-            }
-            return res2.unwrap() as isize + res.unwrap() as isize;
-        }
-        FutexOp::FutexWakePrivate | FutexOp::FutexWake => {
-            let res = FUTEX_WAITER.lock().wake(uaddr, val as usize);
-            if res.is_err() {
-                return LinuxErrno::EINVAL as isize;
-            }
-            return res.unwrap() as isize;
+    let cmd = futex_op & FUTEX_CMD_MASK;
+    if futex_op & FUTEX_CLOCK_REALTIME != 0 && !matches!(cmd, FUTEX_WAIT | FUTEX_WAIT_BITSET) {
+        return Err(LinuxErrno::ENOSYS);
+    }
+    // 绝对超时所基于的时钟
+    let clock = if futex_op & FUTEX_CLOCK_REALTIME != 0 {
+        CLOCK_REALTIME
+    } else {
+        CLOCK_MONOTONIC
+    };
+    // 重新排队的操作中，val2 表示转移的进程数
+    let nr_requeue = || {
+        let nr = val2 as i32;
+        if nr < 0 {
+            Err(LinuxErrno::EINVAL)
+        } else {
+            Ok(nr as usize)
         }
+    };
+    match cmd {
+        FUTEX_WAIT => futex_wait(
+            uaddr,
+            val,
+            read_futex_timeout(val2, None)?,
+            FUTEX_BITSET_MATCH_ANY,
+        ),
+        FUTEX_WAIT_BITSET => futex_wait(uaddr, val, read_futex_timeout(val2, Some(clock))?, val3),
+        FUTEX_WAKE => futex_wake(uaddr, val as usize, FUTEX_BITSET_MATCH_ANY),
+        FUTEX_WAKE_BITSET => futex_wake(uaddr, val as usize, val3),
+        FUTEX_REQUEUE => futex_requeue(uaddr, val as usize, uaddr2, nr_requeue()?, None),
+        FUTEX_CMP_REQUEUE => futex_requeue(uaddr, val as usize, uaddr2, nr_requeue()?, Some(val3)),
+        FUTEX_WAKE_OP => futex_wake_op(uaddr, val as usize, uaddr2, val2 as u32 as usize, val3),
         _ => {
-            panic!("futex: unimplemented futex_op: {:?}", futex_op);
-            // return LinuxErrno::EINVAL as isize;
+            warn!("futex: unsupported futex_op: {:#x}", futex_op);
+            Err(LinuxErrno::ENOSYS)
         }
     }
-    0
+}
+
+/// 读取 futex 相关系统调用中的超时时间，并将其转换为截止时的计时器的值。
+///
+/// `clock` 为 None 时 `timeout` 是相对时间，否则是时钟 `clock`(`CLOCK_REALTIME` 或 `CLOCK_MONOTONIC`)上的绝对时间。
+fn read_futex_timeout(timeout: usize, clock: Option<usize>) -> AlienResult<Option<usize>> {
+    if timeout == 0 {
+        return Ok(None);
+    }
+    let task = current_task().unwrap();
+    let mut time_spec = TimeSpec::default();
+    task.access_inner()
+        .copy_from_user(timeout as *const TimeSpec, &mut time_spec);
+    if time_spec.tv_nsec >= 1000_000_000 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    match clock {
+        None => Ok(Some(time_spec.to_clock() + read_timer())),
+        Some(CLOCK_REALTIME) => Ok(Some(realtime_to_clock(&time_spec))),
+        Some(_) => Ok(Some(time_spec.to_clock())),
+    }
+}
+
+/// 一个系统调用，用于同时等待多个 futex，任意一个 futex 被唤醒时返回其在 `waiters` 中的下标。
+///
+/// 参数：
+/// + `waiters`: 指向一个 [`FutexWaitv`] 数组，每一项描述一个 futex 的地址、期望值以及标志。
+/// + `nr_futexes`: 数组的长度，需在 1 到 `FUTEX_WAITV_MAX` 之间。
+/// + `flags`: 目前必须为 0。
+/// + `timeout`: 指向一个 [`TimeSpec`] 结构，表示等待的绝对截止时间。为 0 时表示一直等待。
+/// + `clockid`: 截止时间所基于的时钟，只能为 `CLOCK_REALTIME` 或 `CLOCK_MONOTONIC`。
+///
+/// 任意一个 futex 的值与期望值不相等时返回 `EAGAIN`，超时返回 `ETIMEDOUT`，收到信号返回 `EINTR`。
+///
+/// Reference: [futex_waitv](https://docs.kernel.org/userspace-api/futex2.html)
+#[syscall_func(449)]
+pub fn futex_waitv(
+    waiters: usize,
+    nr_futexes: usize,
+    flags: u32,
+    timeout: usize,
+    clockid: usize,
+) -> AlienResult<isize> {
+    warn!(
+        "futex_waitv: waiters:{:#x}, nr_futexes:{}, flags:{:#x}, timeout:{:#x}, clockid:{}",
+        waiters, nr_futexes, flags, timeout, clockid
+    );
+    if flags != 0 || waiters == 0 || nr_futexes == 0 || nr_futexes > FUTEX_WAITV_MAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    if timeout != 0 && clockid != CLOCK_REALTIME && clockid != CLOCK_MONOTONIC {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let mut futexes = vec![FutexWaitv::default(); nr_futexes];
+    task.access_inner().copy_from_user_buffer(
+        waiters as *const FutexWaitv,
+        futexes.as_mut_ptr(),
+        nr_futexes,
+    );
+    let deadline = read_futex_timeout(timeout, Some(clockid))?;
+    futex_wait_multiple(&futexes, deadline)
 }
 
 /// 一个系统调用，用于设置当前进程的 robust 锁的列表头。robust 锁主要是解决当一个持有互斥锁的线程退出之后这个锁成为不可用状态的问题。
@@ -243,11 +268,13 @@ pub fn set_robust_list(head: usize, len: usize) -> isize {
 ///
 /// `pid` 指明了要获取相关信息的进程号；`head_ptr` 指明了获取信息后保存的位置；`len_ptr` 指明了获取列表长度信息后保存的位置。
 ///
-/// 当 `pid` 的值为 0 时，将会导致函数 panic；当函数正确执行时，返回 0。
+/// 目前只支持获取当前线程的信息，`pid` 既不为 0 也不是当前线程的 tid 时返回 `ESRCH`；当函数正确执行时，返回 0。
 #[syscall_func(100)]
 pub fn get_robust_list(pid: usize, head_ptr: usize, len_ptr: usize) -> isize {
-    assert_eq!(pid, 0);
     let task = current_task().unwrap();
+    if pid != 0 && pid != task.get_tid() as usize {
+        return LinuxErrno::ESRCH as isize;
+    }
     let task_inner = task.access_inner();
     let head = task_inner.robust.head;
     let len = RobustList::HEAD_SIZE;
@@ -258,9 +285,10 @@ pub fn get_robust_list(pid: usize, head_ptr: usize, len_ptr: usize) -> isize {
     0
 }
 
-/// 唤醒所有当前正在等待 futex 但因为超时或者信号而需要被唤醒的进程
-pub fn solve_futex_wait() {
-    let mut futex_waiter = FUTEX_WAITER.lock();
-    futex_waiter.wake_for_timeout();
-    futex_waiter.wake_for_signal();
+/// 唤醒因为等待 futex 或睡眠而阻塞、并且可以被信号打断的任务 `tid`，在任务收到信号后调用。
+///
+/// futex 与睡眠的超时由 hrtimer 负责唤醒，不需要在时钟中断中检查。
+pub fn wake_for_signal(tid: usize) {
+    FUTEX_WAITER.lock().wake_for_signal(tid);
+    wake_sleeper(tid);
 }
@@ -0,0 +1,678 @@
+//! POSIX 消息队列。
+//!
+//! 与 System V 消息队列 [`super::msg`] 不同，POSIX 消息队列通过名字进行标识，`mq_open` 返回的是一个文件描述符。
+//! 每个消息队列都对应 mqueue 文件系统(挂载于 `/dev/mqueue`)根目录下的一个文件，读取该文件可以获得消息队列的状态信息。
+//! 消息按照优先级从高到低出队，相同优先级的消息按照先进先出的顺序出队。消息队列文件支持 `poll`，
+//! 因此可以与 `ppoll`/`pselect6` 一起使用。
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::String,
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::fmt::{Debug, Formatter};
+
+use constants::{
+    io::{OpenFlags, PollEvents, SeekFrom},
+    signal::SignalNumber,
+    time::TimeSpec,
+    AlienResult, LinuxErrno,
+};
+use ksync::Mutex;
+use syscall_table::syscall_func;
+use timer::read_timer;
+use vfs::{
+    kfile::File,
+    mqueue::{MqueueFsDirInodeImpl, MQUEUE_FS_ROOT},
+};
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType, VfsPollEvents},
+    VfsResult,
+};
+
+use crate::{
+    ipc::signal::{send_signal_info, SigEvent, SigInfo, SIGEV_NONE, SIGEV_SIGNAL, SI_MESGQ},
+    task::{current_task, do_suspend},
+    time::realtime_to_clock,
+};
+
+/// 消息优先级的上限(不含)
+const MQ_PRIO_MAX: u32 = 32768;
+/// 创建消息队列时未指定属性时使用的最大消息数
+const DFLT_MSGMAX: usize = 10;
+/// 创建消息队列时未指定属性时使用的最大消息长度
+const DFLT_MSGSIZEMAX: usize = 8192;
+/// 消息队列中最大消息数的上限
+const HARD_MSGMAX: usize = 65536;
+/// 消息队列中最大消息长度的上限
+const HARD_MSGSIZEMAX: usize = 16 * 1024 * 1024;
+/// 消息队列名字的最大长度
+const NAME_MAX: usize = 255;
+/// 打开方式所占的位
+const O_ACCMODE: usize = 0o3;
+
+/// 所有已经创建且尚未被 `mq_unlink` 删除的消息队列，以及它们在 mqueue 文件系统中对应的目录项
+static MQUEUES: Mutex<BTreeMap<String, (Arc<MqInode>, Arc<dyn VfsDentry>)>> =
+    Mutex::new(BTreeMap::new());
+
+/// 消息队列的属性，与 Linux 中的 `struct mq_attr` 保持一致
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MqAttr {
+    /// 消息队列描述符的标志，只有 `O_NONBLOCK` 有效
+    pub mq_flags: isize,
+    /// 消息队列中最多能存放的消息数
+    pub mq_maxmsg: isize,
+    /// 每条消息的最大长度
+    pub mq_msgsize: isize,
+    /// 消息队列中当前的消息数
+    pub mq_curmsgs: isize,
+    __reserved: [isize; 4],
+}
+
+/// 通过 `mq_notify` 注册的通知
+#[derive(Debug, Copy, Clone)]
+struct MqNotify {
+    /// 注册通知的进程
+    pid: usize,
+    event: SigEvent,
+}
+
+/// 消息队列，作为 mqueue 文件系统中的一个文件存在
+pub struct MqInode {
+    inner: Mutex<MqInner>,
+}
+
+struct MqInner {
+    maxmsg: usize,
+    msgsize: usize,
+    mode: u32,
+    /// 按优先级组织的消息，每个优先级下的消息按照先进先出的顺序排列
+    messages: BTreeMap<u32, VecDeque<Vec<u8>>>,
+    curmsgs: usize,
+    /// 所有消息的总长度
+    qsize: usize,
+    notify: Option<MqNotify>,
+    /// 正在等待接收消息的任务数
+    receivers: usize,
+}
+
+impl MqInode {
+    fn new(maxmsg: usize, msgsize: usize, mode: u32) -> Self {
+        Self {
+            inner: Mutex::new(MqInner {
+                maxmsg,
+                msgsize,
+                mode,
+                messages: BTreeMap::new(),
+                curmsgs: 0,
+                qsize: 0,
+                notify: None,
+                receivers: 0,
+            }),
+        }
+    }
+
+    /// 尝试向消息队列中放入一条消息，队列已满时返回 `EAGAIN`
+    fn try_send(&self, msg: &[u8], prio: u32) -> AlienResult<()> {
+        let mut inner = self.inner.lock();
+        if inner.curmsgs >= inner.maxmsg {
+            return Err(LinuxErrno::EAGAIN);
+        }
+        let was_empty = inner.curmsgs == 0;
+        inner.qsize += msg.len();
+        inner.curmsgs += 1;
+        inner
+            .messages
+            .entry(prio)
+            .or_default()
+            .push_back(msg.to_vec());
+        // 只有当消息到达一个空队列且没有任务正在等待接收时才发送通知，通知发送后即被注销
+        if was_empty && inner.receivers == 0 {
+            if let Some(notify) = inner.notify.take() {
+                if notify.event.sigev_notify == SIGEV_SIGNAL {
+                    let sender = current_task().unwrap().get_pid() as usize;
+                    let info = SigInfo::new(notify.event.sigev_signo as usize, SI_MESGQ)
+                        .with_sender(sender, 0)
+                        .with_value(notify.event.sigev_value);
+                    let _ = send_signal_info(notify.pid, info);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 尝试从消息队列中取出优先级最高的消息，队列为空时返回 `EAGAIN`
+    fn try_receive(&self) -> AlienResult<(Vec<u8>, u32)> {
+        let mut inner = self.inner.lock();
+        let mut entry = inner.messages.last_entry().ok_or(LinuxErrno::EAGAIN)?;
+        let prio = *entry.key();
+        let msg = entry.get_mut().pop_front().unwrap();
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        inner.qsize -= msg.len();
+        inner.curmsgs -= 1;
+        Ok((msg, prio))
+    }
+
+    /// 读取消息队列文件时得到的状态信息
+    fn status(&self) -> String {
+        let inner = self.inner.lock();
+        let (notify, signo, pid) = match inner.notify {
+            Some(notify) => (
+                notify.event.sigev_notify,
+                notify.event.sigev_signo,
+                notify.pid,
+            ),
+            None => (0, 0, 0),
+        };
+        format!(
+            "QSIZE:{:<10} NOTIFY:{:<5} SIGNO:{:<5} NOTIFY_PID:{:<6}\n",
+            inner.qsize, notify, signo, pid
+        )
+    }
+}
+
+impl VfsFile for MqInode {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let status = self.status();
+        let status = status.as_bytes();
+        if offset as usize >= status.len() {
+            return Ok(0);
+        }
+        let len = core::cmp::min(buf.len(), status.len() - offset as usize);
+        buf[..len].copy_from_slice(&status[offset as usize..offset as usize + len]);
+        Ok(len)
+    }
+    fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        let inner = self.inner.lock();
+        let mut res = VfsPollEvents::empty();
+        if event.contains(VfsPollEvents::IN) && inner.curmsgs > 0 {
+            res |= VfsPollEvents::IN;
+        }
+        if event.contains(VfsPollEvents::OUT) && inner.curmsgs < inner.maxmsg {
+            res |= VfsPollEvents::OUT;
+        }
+        Ok(res)
+    }
+}
+
+impl VfsInode for MqInode {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        VfsNodePerm::from_bits_truncate(self.inner.lock().mode as u16)
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_size: self.status().len() as u64,
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::File
+    }
+}
+
+/// 通过 `mq_open` 得到的消息队列描述符
+pub struct MqFile {
+    pos: Mutex<u64>,
+    open_flag: Mutex<OpenFlags>,
+    dentry: Arc<dyn VfsDentry>,
+    queue: Arc<MqInode>,
+}
+
+impl Debug for MqFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MqFile")
+            .field("open_flag", &self.open_flag)
+            .field("name", &self.dentry.name())
+            .finish()
+    }
+}
+
+impl MqFile {
+    fn new(dentry: Arc<dyn VfsDentry>, open_flag: OpenFlags, queue: Arc<MqInode>) -> Self {
+        Self {
+            pos: Mutex::new(0),
+            open_flag: Mutex::new(open_flag),
+            dentry,
+            queue,
+        }
+    }
+
+    fn is_nonblock(&self) -> bool {
+        self.open_flag.lock().contains(OpenFlags::O_NONBLOCK)
+    }
+}
+
+impl File for MqFile {
+    fn read(&self, buf: &mut [u8]) -> AlienResult<usize> {
+        let mut pos = self.pos.lock();
+        let read = self.queue.read_at(*pos, buf)?;
+        *pos += read as u64;
+        Ok(read)
+    }
+    fn write(&self, _buf: &[u8]) -> AlienResult<usize> {
+        Err(LinuxErrno::EINVAL)
+    }
+    fn seek(&self, pos: SeekFrom) -> AlienResult<u64> {
+        let mut spos = self.pos.lock();
+        *spos = match pos {
+            SeekFrom::Start(pos) => pos,
+            _ => return Err(LinuxErrno::EINVAL),
+        };
+        Ok(*spos)
+    }
+    fn get_attr(&self) -> AlienResult<VfsFileStat> {
+        self.queue.get_attr().map_err(Into::into)
+    }
+    fn set_open_flag(&self, flag: OpenFlags) {
+        *self.open_flag.lock() = flag;
+    }
+    fn get_open_flag(&self) -> OpenFlags {
+        *self.open_flag.lock()
+    }
+    fn dentry(&self) -> Arc<dyn VfsDentry> {
+        self.dentry.clone()
+    }
+    fn inode(&self) -> Arc<dyn VfsInode> {
+        self.queue.clone()
+    }
+    fn is_readable(&self) -> bool {
+        let mode = self.open_flag.lock().bits() as usize & O_ACCMODE;
+        mode == OpenFlags::O_RDONLY.bits() as usize || mode == OpenFlags::O_RDWR.bits() as usize
+    }
+    fn is_writable(&self) -> bool {
+        let mode = self.open_flag.lock().bits() as usize & O_ACCMODE;
+        mode == OpenFlags::O_WRONLY.bits() as usize || mode == OpenFlags::O_RDWR.bits() as usize
+    }
+    fn is_append(&self) -> bool {
+        false
+    }
+    fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        self.queue
+            .poll(VfsPollEvents::from_bits_truncate(event.bits() as u16))
+            .map(|e| PollEvents::from_bits_truncate(e.bits() as u32))
+            .map_err(Into::into)
+    }
+}
+
+/// 根据文件描述符获取对应的消息队列描述符，`mqdes` 不是一个消息队列描述符时返回 `EBADF`
+fn get_mq_file(mqdes: usize) -> AlienResult<Arc<MqFile>> {
+    let task = current_task().unwrap();
+    let file = task.get_file(mqdes).ok_or(LinuxErrno::EBADF)?;
+    file.downcast_arc::<MqFile>().map_err(|_| LinuxErrno::EBADF)
+}
+
+/// 读取用户传入的绝对超时时间(`CLOCK_REALTIME`)，并将其转换为单调时钟上计时器的值
+fn read_abs_timeout(abs_timeout: usize) -> AlienResult<Option<usize>> {
+    if abs_timeout == 0 {
+        return Ok(None);
+    }
+    let task = current_task().unwrap();
+    let mut time_spec = TimeSpec::default();
+    task.access_inner()
+        .copy_from_user(abs_timeout as *const TimeSpec, &mut time_spec);
+    if time_spec.tv_nsec >= 1000_000_000 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    Ok(Some(realtime_to_clock(&time_spec)))
+}
+
+/// 等待消息队列的状态发生变化。超时返回 `ETIMEDOUT`，收到信号返回 `EINTR`。
+fn wait_for_queue(deadline: Option<usize>) -> AlienResult<()> {
+    if let Some(deadline) = deadline {
+        if deadline <= read_timer() {
+            return Err(LinuxErrno::ETIMEDOUT);
+        }
+    }
+    do_suspend();
+    let task = current_task().unwrap();
+    let task_inner = task.access_inner();
+    let receiver = task_inner.signal_receivers.lock();
+    if receiver.have_signal() {
+        return Err(LinuxErrno::EINTR);
+    }
+    Ok(())
+}
+
+/// 检查消息队列的名字。由 libc 负责去掉名字开头的 `/`，因此内核收到的名字中不应再包含 `/`。
+fn check_mq_name(name: &str) -> AlienResult<()> {
+    if name.is_empty() {
+        return Err(LinuxErrno::ENOENT);
+    }
+    if name.len() > NAME_MAX {
+        return Err(LinuxErrno::ENAMETOOLONG);
+    }
+    if name.contains('/') {
+        return Err(LinuxErrno::EACCES);
+    }
+    Ok(())
+}
+
+/// 在 mqueue 文件系统的根目录下为消息队列创建一个文件
+fn mq_insert(name: &str, queue: Arc<MqInode>, mode: u32) -> AlienResult<Arc<dyn VfsDentry>> {
+    let root = MQUEUE_FS_ROOT.get().unwrap();
+    let root_inode = root
+        .inode()?
+        .downcast_arc::<MqueueFsDirInodeImpl>()
+        .map_err(|_| LinuxErrno::EINVAL)?;
+    let perm = VfsNodePerm::from_bits_truncate((mode & 0o777) as u16);
+    let inode = root_inode.add_file_manually(name, queue, perm)?;
+    let dentry = root.i_insert(name, inode)?;
+    Ok(dentry)
+}
+
+/// 从 mqueue 文件系统的根目录下删除消息队列对应的文件
+fn mq_remove(name: &str) -> AlienResult<()> {
+    let root = MQUEUE_FS_ROOT.get().unwrap();
+    let root_inode = root
+        .inode()?
+        .downcast_arc::<MqueueFsDirInodeImpl>()
+        .map_err(|_| LinuxErrno::EINVAL)?;
+    root.remove(name)?;
+    root_inode.remove_manually(name)?;
+    Ok(())
+}
+
+/// 一个系统调用，用于创建或打开一个 POSIX 消息队列。
+///
+/// 参数：
+/// + `name`: 消息队列的名字(不含开头的 `/`)。
+/// + `oflag`: 打开方式，支持 `O_RDONLY`、`O_WRONLY`、`O_RDWR`、`O_CREAT`、`O_EXCL`、`O_NONBLOCK` 与 `O_CLOEXEC`。
+/// + `mode`: 创建消息队列时使用的访问权限。
+/// + `attr`: 指向一个 [`MqAttr`] 结构，创建消息队列时用于指定 `mq_maxmsg` 与 `mq_msgsize`。为 0 时使用默认值。
+///
+/// 成功时返回消息队列描述符；消息队列已存在且指定了 `O_CREAT | O_EXCL` 时返回 `EEXIST`；
+/// 消息队列不存在且未指定 `O_CREAT` 时返回 `ENOENT`；`attr` 中的属性不合法时返回 `EINVAL`。
+///
+/// Reference: [mq_open](https://man7.org/linux/man-pages/man3/mq_open.3.html)
+#[syscall_func(180)]
+pub fn mq_open(name: *const u8, oflag: usize, mode: u32, attr: usize) -> AlienResult<isize> {
+    if name.is_null() {
+        return Err(LinuxErrno::EFAULT);
+    }
+    let task = current_task().unwrap();
+    let name = task.transfer_str(name);
+    let flag = OpenFlags::from_bits_truncate(oflag);
+    info!(
+        "mq_open name:{}, flag:{:?}, mode:{:#o}, attr:{:#x}",
+        name, flag, mode, attr
+    );
+    check_mq_name(&name)?;
+    if oflag & O_ACCMODE == O_ACCMODE {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let mut queues = MQUEUES.lock();
+    let (queue, dentry) = match queues.get(&name) {
+        Some((queue, dentry)) => {
+            if flag.contains(OpenFlags::O_CREAT) && flag.contains(OpenFlags::O_EXCL) {
+                return Err(LinuxErrno::EEXIST);
+            }
+            (queue.clone(), dentry.clone())
+        }
+        None => {
+            if !flag.contains(OpenFlags::O_CREAT) {
+                return Err(LinuxErrno::ENOENT);
+            }
+            let (maxmsg, msgsize) = if attr != 0 {
+                let mut mq_attr = MqAttr::default();
+                task.access_inner()
+                    .copy_from_user(attr as *const MqAttr, &mut mq_attr);
+                if mq_attr.mq_maxmsg <= 0
+                    || mq_attr.mq_maxmsg as usize > HARD_MSGMAX
+                    || mq_attr.mq_msgsize <= 0
+                    || mq_attr.mq_msgsize as usize > HARD_MSGSIZEMAX
+                {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                (mq_attr.mq_maxmsg as usize, mq_attr.mq_msgsize as usize)
+            } else {
+                (DFLT_MSGMAX, DFLT_MSGSIZEMAX)
+            };
+            let queue = Arc::new(MqInode::new(maxmsg, msgsize, mode & 0o777));
+            let dentry = mq_insert(&name, queue.clone(), mode)?;
+            queues.insert(name, (queue.clone(), dentry.clone()));
+            (queue, dentry)
+        }
+    };
+    drop(queues);
+    let file = MqFile::new(
+        dentry,
+        flag & !(OpenFlags::O_CREAT | OpenFlags::O_EXCL),
+        queue,
+    );
+    let fd = task
+        .add_file(Arc::new(file))
+        .map_err(|_| LinuxErrno::EMFILE)?;
+    Ok(fd as isize)
+}
+
+/// 一个系统调用，用于删除一个 POSIX 消息队列。
+///
+/// 消息队列的名字会被立即删除，而消息队列本身会在所有引用它的描述符都被关闭后才被释放。
+/// 消息队列不存在时返回 `ENOENT`。
+///
+/// Reference: [mq_unlink](https://man7.org/linux/man-pages/man3/mq_unlink.3.html)
+#[syscall_func(181)]
+pub fn mq_unlink(name: *const u8) -> AlienResult<isize> {
+    if name.is_null() {
+        return Err(LinuxErrno::EFAULT);
+    }
+    let task = current_task().unwrap();
+    let name = task.transfer_str(name);
+    info!("mq_unlink name:{}", name);
+    check_mq_name(&name)?;
+    let mut queues = MQUEUES.lock();
+    queues.remove(&name).ok_or(LinuxErrno::ENOENT)?;
+    mq_remove(&name)?;
+    Ok(0)
+}
+
+/// 一个系统调用，用于向 POSIX 消息队列中发送一条消息。
+///
+/// 参数：
+/// + `mqdes`: 消息队列描述符。
+/// + `msg_ptr`: 消息的起始地址。
+/// + `msg_len`: 消息的长度，不能超过消息队列的 `mq_msgsize`，否则返回 `EMSGSIZE`。
+/// + `msg_prio`: 消息的优先级，需小于 `MQ_PRIO_MAX`，数值越大优先级越高。
+/// + `abs_timeout`: 指向一个 [`TimeSpec`] 结构，表示等待的绝对截止时间(`CLOCK_REALTIME`)。为 0 时表示一直等待。
+///
+/// 消息队列已满时，如果描述符设置了 `O_NONBLOCK` 则返回 `EAGAIN`，否则阻塞直到队列中有空闲位置，
+/// 超时返回 `ETIMEDOUT`，收到信号返回 `EINTR`。
+///
+/// Reference: [mq_timedsend](https://man7.org/linux/man-pages/man3/mq_send.3.html)
+#[syscall_func(182)]
+pub fn mq_timedsend(
+    mqdes: usize,
+    msg_ptr: usize,
+    msg_len: usize,
+    msg_prio: u32,
+    abs_timeout: usize,
+) -> AlienResult<isize> {
+    info!(
+        "mq_timedsend mqdes:{}, msg_ptr:{:#x}, msg_len:{}, msg_prio:{}",
+        mqdes, msg_ptr, msg_len, msg_prio
+    );
+    let file = get_mq_file(mqdes)?;
+    if !file.is_writable() {
+        return Err(LinuxErrno::EBADF);
+    }
+    if msg_prio >= MQ_PRIO_MAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    if msg_len > file.queue.inner.lock().msgsize {
+        return Err(LinuxErrno::EMSGSIZE);
+    }
+    let task = current_task().unwrap();
+    let mut msg = vec![0u8; msg_len];
+    if msg_len > 0 {
+        task.access_inner()
+            .copy_from_user_buffer(msg_ptr as *const u8, msg.as_mut_ptr(), msg_len);
+    }
+    let deadline = if file.is_nonblock() {
+        None
+    } else {
+        read_abs_timeout(abs_timeout)?
+    };
+    loop {
+        match file.queue.try_send(&msg, msg_prio) {
+            Err(LinuxErrno::EAGAIN) if !file.is_nonblock() => wait_for_queue(deadline)?,
+            res => return res.map(|_| 0),
+        }
+    }
+}
+
+/// 一个系统调用，用于从 POSIX 消息队列中接收一条消息。
+///
+/// 参数：
+/// + `mqdes`: 消息队列描述符。
+/// + `msg_ptr`: 用于保存消息的缓冲区。
+/// + `msg_len`: 缓冲区的长度，不能小于消息队列的 `mq_msgsize`，否则返回 `EMSGSIZE`。
+/// + `msg_prio`: 不为 0 时，消息的优先级将被写入该地址。
+/// + `abs_timeout`: 指向一个 [`TimeSpec`] 结构，表示等待的绝对截止时间(`CLOCK_REALTIME`)。为 0 时表示一直等待。
+///
+/// 总是取出队列中优先级最高且最早到达的消息，成功时返回消息的长度。消息队列为空时，
+/// 如果描述符设置了 `O_NONBLOCK` 则返回 `EAGAIN`，否则阻塞直到有消息到达，超时返回 `ETIMEDOUT`，收到信号返回 `EINTR`。
+///
+/// Reference: [mq_timedreceive](https://man7.org/linux/man-pages/man3/mq_receive.3.html)
+#[syscall_func(183)]
+pub fn mq_timedreceive(
+    mqdes: usize,
+    msg_ptr: usize,
+    msg_len: usize,
+    msg_prio: usize,
+    abs_timeout: usize,
+) -> AlienResult<isize> {
+    info!(
+        "mq_timedreceive mqdes:{}, msg_ptr:{:#x}, msg_len:{}",
+        mqdes, msg_ptr, msg_len
+    );
+    let file = get_mq_file(mqdes)?;
+    if !file.is_readable() {
+        return Err(LinuxErrno::EBADF);
+    }
+    if msg_len < file.queue.inner.lock().msgsize {
+        return Err(LinuxErrno::EMSGSIZE);
+    }
+    let deadline = if file.is_nonblock() {
+        None
+    } else {
+        read_abs_timeout(abs_timeout)?
+    };
+    let (msg, prio) = loop {
+        match file.queue.try_receive() {
+            Err(LinuxErrno::EAGAIN) if !file.is_nonblock() => {
+                file.queue.inner.lock().receivers += 1;
+                let res = wait_for_queue(deadline);
+                file.queue.inner.lock().receivers -= 1;
+                res?;
+            }
+            res => break res?,
+        }
+    };
+    let task = current_task().unwrap();
+    let mut task_inner = task.access_inner();
+    if !msg.is_empty() {
+        task_inner.copy_to_user_buffer(msg.as_ptr(), msg_ptr as *mut u8, msg.len());
+    }
+    if msg_prio != 0 {
+        task_inner.copy_to_user(&prio, msg_prio as *mut u32);
+    }
+    Ok(msg.len() as isize)
+}
+
+/// 一个系统调用，用于注册或注销消息到达空消息队列时的异步通知。
+///
+/// `sevp` 为 0 时，如果当前进程注册了通知则将其注销；否则 `sevp` 指向一个 [`SigEvent`] 结构，
+/// 目前支持 `SIGEV_NONE` 与 `SIGEV_SIGNAL` 两种通知方式。同一时刻只能有一个进程注册通知，
+/// 已经有进程注册时返回 `EBUSY`。通知在发送一次后即被注销。
+///
+/// Reference: [mq_notify](https://man7.org/linux/man-pages/man3/mq_notify.3.html)
+#[syscall_func(184)]
+pub fn mq_notify(mqdes: usize, sevp: usize) -> AlienResult<isize> {
+    info!("mq_notify mqdes:{}, sevp:{:#x}", mqdes, sevp);
+    let file = get_mq_file(mqdes)?;
+    let task = current_task().unwrap();
+    let pid = task.get_pid() as usize;
+    if sevp == 0 {
+        let mut inner = file.queue.inner.lock();
+        if inner.notify.map_or(false, |notify| notify.pid == pid) {
+            inner.notify = None;
+        }
+        return Ok(0);
+    }
+    let mut event = SigEvent::default();
+    task.access_inner()
+        .copy_from_user(sevp as *const SigEvent, &mut event);
+    match event.sigev_notify {
+        SIGEV_NONE => {}
+        SIGEV_SIGNAL => {
+            if event.sigev_signo <= 0 || SignalNumber::try_from(event.sigev_signo as u8).is_err() {
+                return Err(LinuxErrno::EINVAL);
+            }
+        }
+        _ => return Err(LinuxErrno::EINVAL),
+    }
+    let mut inner = file.queue.inner.lock();
+    if inner.notify.is_some() {
+        return Err(LinuxErrno::EBUSY);
+    }
+    inner.notify = Some(MqNotify { pid, event });
+    Ok(0)
+}
+
+/// 一个系统调用，用于获取或设置 POSIX 消息队列的属性。
+///
+/// `newattr` 不为 0 时，使用其中的 `mq_flags` 设置描述符的 `O_NONBLOCK` 标志，其余字段被忽略；
+/// `oldattr` 不为 0 时，修改前的属性将被写入该地址。
+///
+/// Reference: [mq_getsetattr](https://man7.org/linux/man-pages/man2/mq_getsetattr.2.html)
+#[syscall_func(185)]
+pub fn mq_getsetattr(mqdes: usize, newattr: usize, oldattr: usize) -> AlienResult<isize> {
+    info!(
+        "mq_getsetattr mqdes:{}, newattr:{:#x}, oldattr:{:#x}",
+        mqdes, newattr, oldattr
+    );
+    let file = get_mq_file(mqdes)?;
+    let task = current_task().unwrap();
+    let old = {
+        let inner = file.queue.inner.lock();
+        MqAttr {
+            mq_flags: (file.get_open_flag() & OpenFlags::O_NONBLOCK).bits() as isize,
+            mq_maxmsg: inner.maxmsg as isize,
+            mq_msgsize: inner.msgsize as isize,
+            mq_curmsgs: inner.curmsgs as isize,
+            ..Default::default()
+        }
+    };
+    if newattr != 0 {
+        let mut new = MqAttr::default();
+        task.access_inner()
+            .copy_from_user(newattr as *const MqAttr, &mut new);
+        let nonblock = OpenFlags::O_NONBLOCK.bits() as isize;
+        if new.mq_flags & !nonblock != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let mut open_flag = file.open_flag.lock();
+        open_flag.set(OpenFlags::O_NONBLOCK, new.mq_flags & nonblock != 0);
+    }
+    if oldattr != 0 {
+        task.access_inner()
+            .copy_to_user(&old, oldattr as *mut MqAttr);
+    }
+    Ok(0)
+}
@@ -0,0 +1,460 @@
+//! System V 消息队列。
+//!
+//! 消息队列是保存在内核中的消息链表，每条消息由一个正整数类型 `mtype` 和一段数据组成。
+//! 进程可以通过 [`msgsnd`] 向队列中追加消息，也可以通过 [`msgrcv`] 按照消息类型从队列中取出消息。
+//! 当队列已满或者没有满足条件的消息时，调用者将被阻塞，直到条件满足、队列被删除或者收到信号。
+//!
+//! 消息队列记录在进程所在的 IPC 命名空间中，不同命名空间中的消息队列互不可见。
+use alloc::{collections::VecDeque, sync::Arc, vec, vec::Vec};
+
+use constants::{ipc::IPC_PRIVATE, AlienResult, LinuxErrno};
+use ksync::{Mutex, MutexGuard};
+use syscall_table::syscall_func;
+
+use crate::{
+    ipc::sysv::{
+        alloc_ipc_id, ipc_cmd, ipc_now, IpcPerm, IPC_CREAT, IPC_EXCL, IPC_INFO, IPC_MNI,
+        IPC_NOWAIT, IPC_RMID, IPC_SET, IPC_STAT,
+    },
+    task::{current_task, do_suspend, namespace::current_ipc_ns},
+};
+
+/// 消息过长时截断消息而不是返回 `E2BIG`
+const MSG_NOERROR: u32 = 0o10000;
+/// 接收第一条类型不等于 `msgtyp` 的消息
+const MSG_EXCEPT: u32 = 0o20000;
+/// 将 `msgtyp` 作为消息在队列中的下标，复制而不是取出该消息
+const MSG_COPY: u32 = 0o40000;
+
+/// 与 `IPC_STAT` 相同，但参数是内部索引而不是 id
+const MSG_STAT: usize = 11;
+/// 获取消息队列的使用信息
+const MSG_INFO: usize = 12;
+
+/// 单条消息的最大字节数
+const MSGMAX: usize = 8192;
+/// 消息队列默认的最大字节数
+const MSGMNB: usize = 16384;
+
+/// 队列中的一条消息
+#[derive(Debug)]
+struct Message {
+    /// 消息类型，总是大于 0
+    mtype: isize,
+    /// 消息数据
+    data: Vec<u8>,
+}
+
+/// 消息队列被 Mutex 封装后的结构
+#[derive(Debug)]
+pub struct MsgQueue {
+    inner: Mutex<MsgQueueInner>,
+}
+
+/// 未加入同步机制前的消息队列
+#[derive(Debug)]
+pub struct MsgQueueInner {
+    /// 权限信息
+    perm: IpcPerm,
+    /// 队列中的消息
+    messages: VecDeque<Message>,
+    /// 队列中所有消息的字节数之和
+    cbytes: usize,
+    /// 队列允许的最大字节数
+    qbytes: usize,
+    /// 最后一次发送消息的时间
+    stime: usize,
+    /// 最后一次接收消息的时间
+    rtime: usize,
+    /// 最后一次修改的时间
+    ctime: usize,
+    /// 最后一次发送消息的进程的 pid
+    lspid: usize,
+    /// 最后一次接收消息的进程的 pid
+    lrpid: usize,
+    /// 队列是否已经被删除，被阻塞的进程需要据此返回 `EIDRM`
+    deleted: bool,
+}
+
+/// 与 Linux 中的 `struct msqid64_ds` 保持一致，用于 `IPC_STAT` 和 `IPC_SET`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MsqIdDs {
+    pub msg_perm: IpcPerm,
+    pub msg_stime: usize,
+    pub msg_rtime: usize,
+    pub msg_ctime: usize,
+    pub msg_cbytes: usize,
+    pub msg_qnum: usize,
+    pub msg_qbytes: usize,
+    pub msg_lspid: i32,
+    pub msg_lrpid: i32,
+    __unused4: usize,
+    __unused5: usize,
+}
+
+/// 与 Linux 中的 `struct msginfo` 保持一致，用于 `IPC_INFO` 和 `MSG_INFO`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+struct MsgLimitInfo {
+    msgpool: i32,
+    msgmap: i32,
+    msgmax: i32,
+    msgmnb: i32,
+    msgmni: i32,
+    msgssz: i32,
+    msgtql: i32,
+    msgseg: u16,
+}
+
+impl MsgQueue {
+    /// 创建一个新的消息队列
+    pub fn new(perm: IpcPerm) -> Self {
+        Self {
+            inner: Mutex::new(MsgQueueInner {
+                perm,
+                messages: VecDeque::new(),
+                cbytes: 0,
+                qbytes: MSGMNB,
+                stime: 0,
+                rtime: 0,
+                ctime: ipc_now(),
+                lspid: 0,
+                lrpid: 0,
+                deleted: false,
+            }),
+        }
+    }
+
+    /// 同步获取内部的消息队列信息
+    pub fn access_inner(&self) -> MutexGuard<MsgQueueInner> {
+        self.inner.lock()
+    }
+
+    /// 返回当前消息队列的状态信息
+    pub fn stat(&self) -> MsqIdDs {
+        let inner = self.access_inner();
+        MsqIdDs {
+            msg_perm: inner.perm,
+            msg_stime: inner.stime,
+            msg_rtime: inner.rtime,
+            msg_ctime: inner.ctime,
+            msg_cbytes: inner.cbytes,
+            msg_qnum: inner.messages.len(),
+            msg_qbytes: inner.qbytes,
+            msg_lspid: inner.lspid as i32,
+            msg_lrpid: inner.lrpid as i32,
+            ..Default::default()
+        }
+    }
+}
+
+impl MsgQueueInner {
+    /// 按照 `msgtyp` 与 `msgflg` 的规则，找到第一条满足条件的消息在队列中的下标
+    fn find(&self, msgtyp: isize, msgflg: u32) -> Option<usize> {
+        if msgflg & MSG_COPY != 0 {
+            return if (msgtyp as usize) < self.messages.len() {
+                Some(msgtyp as usize)
+            } else {
+                None
+            };
+        }
+        if msgtyp == 0 {
+            return if self.messages.is_empty() {
+                None
+            } else {
+                Some(0)
+            };
+        }
+        if msgtyp > 0 {
+            let except = msgflg & MSG_EXCEPT != 0;
+            return self
+                .messages
+                .iter()
+                .position(|msg| (msg.mtype == msgtyp) != except);
+        }
+        // msgtyp < 0: the first message with the lowest type less than or equal to |msgtyp|
+        let limit = -msgtyp;
+        self.messages
+            .iter()
+            .enumerate()
+            .filter(|(_, msg)| msg.mtype <= limit)
+            .min_by_key(|(index, msg)| (msg.mtype, *index))
+            .map(|(index, _)| index)
+    }
+}
+
+/// 根据 id 获取一个消息队列
+fn get_msg_queue(msqid: usize) -> AlienResult<Arc<MsgQueue>> {
+    current_ipc_ns()
+        .msg
+        .lock()
+        .get(&msqid)
+        .cloned()
+        .ok_or(LinuxErrno::EINVAL)
+}
+
+/// 让出 CPU 等待消息队列状态的改变，如果期间收到信号则返回 `EINTR`
+fn wait_for_queue() -> AlienResult<()> {
+    do_suspend();
+    let task = current_task().unwrap();
+    let task_inner = task.access_inner();
+    let receiver = task_inner.signal_receivers.lock();
+    if receiver.have_signal() {
+        return Err(LinuxErrno::EINTR);
+    }
+    Ok(())
+}
+
+/// 一个系统调用，用于创建或获取一个消息队列。
+///
+/// 参数：
+/// + `key`: 消息队列的键值。当其值为 `IPC_PRIVATE` 时，总是创建一个新的消息队列。
+/// + `msgflg`: 支持 `IPC_CREAT` 与 `IPC_EXCL`，低 9 位为消息队列的访问权限。
+///
+/// 成功时返回消息队列的 id；`key` 不存在且未指定 `IPC_CREAT` 时返回 `ENOENT`；
+/// 指定了 `IPC_CREAT | IPC_EXCL` 但 `key` 已经存在时返回 `EEXIST`；消息队列数量达到上限时返回 `ENOSPC`。
+///
+/// Reference: [msgget](https://man7.org/linux/man-pages/man2/msgget.2.html)
+#[syscall_func(186)]
+pub fn msgget(key: usize, msgflg: u32) -> AlienResult<isize> {
+    info!("msgget key:{}, msgflg:{:#o}", key, msgflg);
+    let ipc_ns = current_ipc_ns();
+    let mut queues = ipc_ns.msg.lock();
+    if key != IPC_PRIVATE {
+        let exist = queues
+            .iter()
+            .find(|(_, queue)| queue.access_inner().perm.key == key as i32);
+        if let Some((id, _)) = exist {
+            if msgflg & IPC_CREAT != 0 && msgflg & IPC_EXCL != 0 {
+                return Err(LinuxErrno::EEXIST);
+            }
+            return Ok(*id as isize);
+        }
+        if msgflg & IPC_CREAT == 0 {
+            return Err(LinuxErrno::ENOENT);
+        }
+    }
+    let msqid = alloc_ipc_id(queues.keys()).ok_or(LinuxErrno::ENOSPC)?;
+    queues.insert(msqid, Arc::new(MsgQueue::new(IpcPerm::new(key, msgflg))));
+    Ok(msqid as isize)
+}
+
+/// 一个系统调用，用于向消息队列发送一条消息。
+///
+/// 参数：
+/// + `msqid`: 消息队列的 id。
+/// + `msgp`: 指向用户态的 `struct msgbuf { long mtype; char mtext[]; }`，其中 `mtype` 必须大于 0。
+/// + `msgsz`: `mtext` 的长度，不能超过 `MSGMAX`。
+/// + `msgflg`: 当队列已满时，如果包含 `IPC_NOWAIT` 则直接返回 `EAGAIN`，否则阻塞等待。
+///
+/// 成功时返回 0；等待期间队列被删除则返回 `EIDRM`，收到信号则返回 `EINTR`。
+///
+/// Reference: [msgsnd](https://man7.org/linux/man-pages/man2/msgsnd.2.html)
+#[syscall_func(189)]
+pub fn msgsnd(msqid: usize, msgp: usize, msgsz: usize, msgflg: u32) -> AlienResult<isize> {
+    info!(
+        "msgsnd msqid:{}, msgp:{:#x}, msgsz:{}, msgflg:{:#o}",
+        msqid, msgp, msgsz, msgflg
+    );
+    if msgsz > MSGMAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let mut mtype = 0isize;
+    task.access_inner()
+        .copy_from_user(msgp as *const isize, &mut mtype);
+    if mtype <= 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let mut data = vec![0u8; msgsz];
+    if msgsz > 0 {
+        task.access_inner().copy_from_user_buffer(
+            (msgp + core::mem::size_of::<isize>()) as *const u8,
+            data.as_mut_ptr(),
+            msgsz,
+        );
+    }
+    let queue = get_msg_queue(msqid)?;
+    loop {
+        let mut inner = queue.access_inner();
+        if inner.deleted {
+            return Err(LinuxErrno::EIDRM);
+        }
+        if msgsz > inner.qbytes {
+            return Err(LinuxErrno::EINVAL);
+        }
+        if inner.cbytes + msgsz <= inner.qbytes && inner.messages.len() < inner.qbytes {
+            inner.cbytes += msgsz;
+            inner.messages.push_back(Message { mtype, data });
+            inner.stime = ipc_now();
+            inner.lspid = task.get_pid() as usize;
+            return Ok(0);
+        }
+        if msgflg & IPC_NOWAIT != 0 {
+            return Err(LinuxErrno::EAGAIN);
+        }
+        drop(inner);
+        wait_for_queue()?;
+    }
+}
+
+/// 一个系统调用，用于从消息队列接收一条消息。
+///
+/// 参数：
+/// + `msqid`: 消息队列的 id。
+/// + `msgp`: 指向用户态的 `struct msgbuf`，用于保存接收到的消息。
+/// + `msgsz`: `mtext` 缓冲区的长度。当消息长度大于 `msgsz` 时，如果包含 `MSG_NOERROR` 则截断消息，否则返回 `E2BIG`。
+/// + `msgtyp`: 为 0 时接收队列中的第一条消息；大于 0 时接收第一条类型为 `msgtyp` 的消息(指定 `MSG_EXCEPT` 时为第一条类型不为 `msgtyp` 的消息)；
+/// 小于 0 时接收类型小于等于 `msgtyp` 绝对值的消息中，类型最小的第一条消息。
+/// + `msgflg`: 支持 `IPC_NOWAIT`、`MSG_NOERROR`、`MSG_EXCEPT` 与 `MSG_COPY`。
+///
+/// 成功时返回复制到 `mtext` 中的字节数；没有满足条件的消息且包含 `IPC_NOWAIT` 时返回 `ENOMSG`；
+/// 等待期间队列被删除则返回 `EIDRM`，收到信号则返回 `EINTR`。
+///
+/// Reference: [msgrcv](https://man7.org/linux/man-pages/man2/msgrcv.2.html)
+#[syscall_func(188)]
+pub fn msgrcv(
+    msqid: usize,
+    msgp: usize,
+    msgsz: usize,
+    msgtyp: isize,
+    msgflg: u32,
+) -> AlienResult<isize> {
+    info!(
+        "msgrcv msqid:{}, msgp:{:#x}, msgsz:{}, msgtyp:{}, msgflg:{:#o}",
+        msqid, msgp, msgsz, msgtyp, msgflg
+    );
+    if msgflg & MSG_COPY != 0 && (msgflg & IPC_NOWAIT == 0 || msgflg & MSG_EXCEPT != 0) {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let queue = get_msg_queue(msqid)?;
+    let (mtype, data) = loop {
+        let mut inner = queue.access_inner();
+        if inner.deleted {
+            return Err(LinuxErrno::EIDRM);
+        }
+        if let Some(index) = inner.find(msgtyp, msgflg) {
+            let msg = &inner.messages[index];
+            if msg.data.len() > msgsz && msgflg & MSG_NOERROR == 0 {
+                return Err(LinuxErrno::E2BIG);
+            }
+            let mtype = msg.mtype;
+            let len = core::cmp::min(msg.data.len(), msgsz);
+            if msgflg & MSG_COPY != 0 {
+                break (mtype, msg.data[..len].to_vec());
+            }
+            let msg = inner.messages.remove(index).unwrap();
+            inner.cbytes -= msg.data.len();
+            inner.rtime = ipc_now();
+            inner.lrpid = task.get_pid() as usize;
+            let mut data = msg.data;
+            data.truncate(len);
+            break (mtype, data);
+        }
+        if msgflg & IPC_NOWAIT != 0 {
+            return Err(LinuxErrno::ENOMSG);
+        }
+        drop(inner);
+        wait_for_queue()?;
+    };
+    let mut task_inner = task.access_inner();
+    task_inner.copy_to_user(&mtype, msgp as *mut isize);
+    if !data.is_empty() {
+        task_inner.copy_to_user_buffer(
+            data.as_ptr(),
+            (msgp + core::mem::size_of::<isize>()) as *mut u8,
+            data.len(),
+        );
+    }
+    Ok(data.len() as isize)
+}
+
+/// 一个系统调用，用于控制消息队列。
+///
+/// 参数：
+/// + `msqid`: 消息队列的 id。
+/// + `cmd`: 指明要采取的操作。目前 Alien 支持以下操作：
+///     + `IPC_STAT`/`MSG_STAT`: 将消息队列的状态信息 [`MsqIdDs`] 写入 `buf` 中
+///     + `IPC_SET`: 使用 `buf` 中的 uid、gid、mode 以及 `msg_qbytes` 更新消息队列
+///     + `IPC_RMID`: 立即删除消息队列，并唤醒所有等待在该队列上的进程
+///     + `IPC_INFO`/`MSG_INFO`: 获取系统中消息队列的限制信息/使用信息
+/// + `buf`: 指向一个 [`MsqIdDs`] 结构，根据 `cmd` 的不同作为输入或输出。
+///
+/// 成功执行后，`IPC_INFO`/`MSG_INFO` 返回当前最大的消息队列 id，`MSG_STAT` 返回 `msqid`，其余命令返回 0。
+///
+/// Reference: [msgctl](https://man7.org/linux/man-pages/man2/msgctl.2.html)
+#[syscall_func(187)]
+pub fn msgctl(msqid: usize, cmd: usize, buf: usize) -> AlienResult<isize> {
+    let cmd = ipc_cmd(cmd);
+    info!("msgctl msqid:{}, cmd:{}, buf:{:#x}", msqid, cmd, buf);
+    let task = current_task().unwrap();
+    match cmd {
+        IPC_INFO | MSG_INFO => {
+            let ipc_ns = current_ipc_ns();
+            let queues = ipc_ns.msg.lock();
+            let mut info = MsgLimitInfo {
+                msgpool: (MSGMNB * IPC_MNI / 1024) as i32,
+                msgmap: MSGMNB as i32,
+                msgmax: MSGMAX as i32,
+                msgmnb: MSGMNB as i32,
+                msgmni: IPC_MNI as i32,
+                msgssz: 16,
+                msgtql: MSGMNB as i32,
+                msgseg: u16::MAX,
+            };
+            if cmd == MSG_INFO {
+                info.msgpool = queues.len() as i32;
+                info.msgmap = queues
+                    .values()
+                    .map(|queue| queue.access_inner().messages.len())
+                    .sum::<usize>() as i32;
+                info.msgtql = queues
+                    .values()
+                    .map(|queue| queue.access_inner().cbytes)
+                    .sum::<usize>() as i32;
+            }
+            task.access_inner()
+                .copy_to_user(&info, buf as *mut MsgLimitInfo);
+            return Ok(*queues.keys().max().unwrap_or(&0) as isize);
+        }
+        IPC_RMID => {
+            let queue = current_ipc_ns()
+                .msg
+                .lock()
+                .remove(&msqid)
+                .ok_or(LinuxErrno::EINVAL)?;
+            let mut inner = queue.access_inner();
+            inner.deleted = true;
+            inner.messages.clear();
+            inner.cbytes = 0;
+            return Ok(0);
+        }
+        _ => {}
+    }
+    let queue = get_msg_queue(msqid)?;
+    match cmd {
+        IPC_STAT | MSG_STAT => {
+            let stat = queue.stat();
+            task.access_inner().copy_to_user(&stat, buf as *mut MsqIdDs);
+            if cmd == MSG_STAT {
+                return Ok(msqid as isize);
+            }
+        }
+        IPC_SET => {
+            let mut stat = MsqIdDs::default();
+            task.access_inner()
+                .copy_from_user(buf as *const MsqIdDs, &mut stat);
+            if stat.msg_qbytes == 0 {
+                return Err(LinuxErrno::EINVAL);
+            }
+            let mut inner = queue.access_inner();
+            inner.perm.update(&stat.msg_perm);
+            inner.qbytes = stat.msg_qbytes;
+            inner.ctime = ipc_now();
+        }
+        _ => return Err(LinuxErrno::EINVAL),
+    }
+    Ok(0)
+}
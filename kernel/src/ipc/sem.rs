@@ -0,0 +1,584 @@
+//! System V 信号量集。
+//!
+//! 一个信号量集中包含若干个信号量，进程通过 [`semop`]/[`semtimedop`] 原子地对集合中的多个信号量进行操作：
+//! 要么所有操作都能立即完成，要么一个都不执行并等待。
+//!
+//! 如果操作中带有 `SEM_UNDO` 标志，内核会在任务的 [`SemUndoList`] 中记录该操作的相反值，
+//! 当任务退出时，这些调整值将被重新作用到信号量上，防止任务异常退出后信号量无法被释放。
+//!
+//! 信号量集记录在任务所在的 IPC 命名空间中，任务切换 IPC 命名空间前会先执行所有的调整值。
+use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use constants::{ipc::IPC_PRIVATE, time::TimeSpec, AlienResult, LinuxErrno};
+use ksync::{Mutex, MutexGuard};
+use syscall_table::syscall_func;
+use timer::{read_timer, ToClock};
+
+use crate::{
+    ipc::sysv::{
+        alloc_ipc_id, ipc_cmd, ipc_now, IpcPerm, IPC_CREAT, IPC_EXCL, IPC_INFO, IPC_MNI,
+        IPC_NOWAIT, IPC_RMID, IPC_SET, IPC_STAT,
+    },
+    task::{
+        current_task, do_suspend,
+        namespace::{current_ipc_ns, IpcNamespace},
+        tasks,
+    },
+};
+
+/// 任务退出时撤销该操作
+const SEM_UNDO: i16 = 0x1000;
+
+/// 获取最后一次操作信号量的进程的 pid
+const GETPID: usize = 11;
+/// 获取信号量的值
+const GETVAL: usize = 12;
+/// 获取信号量集中所有信号量的值
+const GETALL: usize = 13;
+/// 获取等待信号量增加的进程数
+const GETNCNT: usize = 14;
+/// 获取等待信号量变为 0 的进程数
+const GETZCNT: usize = 15;
+/// 设置信号量的值
+const SETVAL: usize = 16;
+/// 设置信号量集中所有信号量的值
+const SETALL: usize = 17;
+/// 与 `IPC_STAT` 相同，但参数是内部索引而不是 id
+const SEM_STAT: usize = 18;
+/// 获取信号量集的使用信息
+const SEM_INFO: usize = 19;
+
+/// 信号量的最大值
+const SEMVMX: i32 = 32767;
+/// 一个信号量集中信号量的最大数量
+const SEMMSL: usize = 32000;
+/// 一次 semop 中操作的最大数量
+const SEMOPM: usize = 500;
+
+/// 信号量集的序列号，用于区分先后使用同一个 id 的不同信号量集
+static SEM_SEQ: AtomicU16 = AtomicU16::new(0);
+
+/// 与 Linux 中的 `struct sembuf` 保持一致，描述对一个信号量的操作
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SemBuf {
+    /// 信号量在集合中的下标
+    pub sem_num: u16,
+    /// 操作值
+    pub sem_op: i16,
+    /// 操作标志，支持 `IPC_NOWAIT` 与 `SEM_UNDO`
+    pub sem_flg: i16,
+}
+
+/// 与 Linux 中的 `struct semid64_ds` 保持一致，用于 `IPC_STAT` 和 `IPC_SET`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SemIdDs {
+    pub sem_perm: IpcPerm,
+    pub sem_otime: usize,
+    pub sem_ctime: usize,
+    pub sem_nsems: usize,
+    __unused3: usize,
+    __unused4: usize,
+}
+
+/// 与 Linux 中的 `struct seminfo` 保持一致，用于 `IPC_INFO` 和 `SEM_INFO`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+struct SemLimitInfo {
+    semmap: i32,
+    semmni: i32,
+    semmns: i32,
+    semmnu: i32,
+    semmsl: i32,
+    semopm: i32,
+    semume: i32,
+    semusz: i32,
+    semvmx: i32,
+    semaem: i32,
+}
+
+/// 信号量集中的一个信号量
+#[derive(Debug, Copy, Clone, Default)]
+struct Semaphore {
+    /// 信号量的值
+    semval: i32,
+    /// 最后一次操作该信号量的进程的 pid
+    sempid: usize,
+    /// 等待信号量增加的进程数
+    semncnt: usize,
+    /// 等待信号量变为 0 的进程数
+    semzcnt: usize,
+}
+
+/// 信号量集被 Mutex 封装后的结构
+#[derive(Debug)]
+pub struct SemSet {
+    inner: Mutex<SemSetInner>,
+}
+
+/// 未加入同步机制前的信号量集
+#[derive(Debug)]
+pub struct SemSetInner {
+    /// 权限信息
+    perm: IpcPerm,
+    /// 集合中的信号量
+    sems: Vec<Semaphore>,
+    /// 最后一次 semop 的时间
+    otime: usize,
+    /// 最后一次修改的时间
+    ctime: usize,
+    /// 信号量集是否已经被删除，被阻塞的进程需要据此返回 `EIDRM`
+    deleted: bool,
+}
+
+/// 一次 semop 尝试的结果
+enum SemOpResult {
+    /// 所有操作均已完成
+    Done,
+    /// 需要等待下标为 `usize` 的信号量，`bool` 表示是否在等待其变为 0
+    Block(usize, bool),
+}
+
+impl SemSet {
+    /// 创建一个包含 `nsems` 个信号量的信号量集
+    pub fn new(mut perm: IpcPerm, nsems: usize) -> Self {
+        perm.seq = SEM_SEQ.fetch_add(1, Ordering::Relaxed);
+        Self {
+            inner: Mutex::new(SemSetInner {
+                perm,
+                sems: vec![Semaphore::default(); nsems],
+                otime: 0,
+                ctime: ipc_now(),
+                deleted: false,
+            }),
+        }
+    }
+
+    /// 同步获取内部的信号量集信息
+    pub fn access_inner(&self) -> MutexGuard<SemSetInner> {
+        self.inner.lock()
+    }
+
+    /// 返回当前信号量集的状态信息
+    pub fn stat(&self) -> SemIdDs {
+        let inner = self.access_inner();
+        SemIdDs {
+            sem_perm: inner.perm,
+            sem_otime: inner.otime,
+            sem_ctime: inner.ctime,
+            sem_nsems: inner.sems.len(),
+            ..Default::default()
+        }
+    }
+}
+
+impl SemSetInner {
+    /// 尝试原子地执行一组操作。只有所有操作都可以立即完成时才会修改信号量的值
+    fn try_apply(&mut self, sops: &[SemBuf], pid: usize) -> AlienResult<SemOpResult> {
+        let mut values = self.sems.iter().map(|sem| sem.semval).collect::<Vec<i32>>();
+        for sop in sops {
+            let index = sop.sem_num as usize;
+            let value = values[index] + sop.sem_op as i32;
+            if sop.sem_op == 0 {
+                if values[index] != 0 {
+                    return Ok(SemOpResult::Block(index, true));
+                }
+            } else if value < 0 {
+                return Ok(SemOpResult::Block(index, false));
+            } else if value > SEMVMX {
+                return Err(LinuxErrno::ERANGE);
+            } else {
+                values[index] = value;
+            }
+        }
+        for sop in sops {
+            self.sems[sop.sem_num as usize].sempid = pid;
+        }
+        self.sems
+            .iter_mut()
+            .zip(values)
+            .for_each(|(sem, value)| sem.semval = value);
+        self.otime = ipc_now();
+        Ok(SemOpResult::Done)
+    }
+}
+
+/// 任务中记录 `SEM_UNDO` 调整值的结构。
+///
+/// 使用 `CLONE_SYSVSEM` 创建的任务之间共享同一个 `SemUndoList`，当最后一个共享它的任务退出时，调整值才会被作用到信号量上。
+#[derive(Debug, Default)]
+pub struct SemUndoList {
+    /// 信号量集 id -> (信号量集的序列号, 每个信号量的调整值)
+    undos: BTreeMap<usize, (u16, BTreeMap<u16, i32>)>,
+}
+
+impl SemUndoList {
+    /// 创建一个空的 `SemUndoList`
+    pub fn new() -> Self {
+        Self {
+            undos: BTreeMap::new(),
+        }
+    }
+
+    /// 记录一次带 `SEM_UNDO` 的操作
+    fn record(&mut self, semid: usize, seq: u16, sop: &SemBuf) {
+        let entry = self
+            .undos
+            .entry(semid)
+            .or_insert_with(|| (seq, BTreeMap::new()));
+        if entry.0 != seq {
+            // the old semaphore set has been removed and the id is reused
+            *entry = (seq, BTreeMap::new());
+        }
+        *entry.1.entry(sop.sem_num).or_insert(0) -= sop.sem_op as i32;
+    }
+
+    /// 清除信号量集中某个信号量的调整值，用于 `SETVAL` 与 `SETALL`
+    fn clear(&mut self, semid: usize, sem_num: Option<u16>) {
+        if let Some((_, adj)) = self.undos.get_mut(&semid) {
+            match sem_num {
+                Some(num) => {
+                    adj.remove(&num);
+                }
+                None => adj.clear(),
+            }
+        }
+    }
+
+    /// 将所有调整值作用到 IPC 命名空间 `ns` 中对应的信号量上，调整后的值被限制在 [0, SEMVMX] 之间
+    pub fn apply(&mut self, ns: &IpcNamespace, pid: usize) {
+        let sem_sets = ns.sem.lock();
+        for (semid, (seq, adj)) in core::mem::take(&mut self.undos) {
+            if let Some(set) = sem_sets.get(&semid) {
+                let mut inner = set.access_inner();
+                if inner.perm.seq != seq {
+                    continue;
+                }
+                for (num, value) in adj {
+                    if let Some(sem) = inner.sems.get_mut(num as usize) {
+                        sem.semval = (sem.semval + value).clamp(0, SEMVMX);
+                        sem.sempid = pid;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 清除 IPC 命名空间 `ns` 中所有任务对信号量集 `semid` 中信号量的调整值，`sem_num` 为 `None` 时清除整个信号量集的调整值
+fn clear_undos(ns: &Arc<IpcNamespace>, semid: usize, sem_num: Option<u16>) {
+    // 共享同一个 SemUndoList 的任务会重复清除，这不会产生影响
+    tasks()
+        .into_iter()
+        .filter_map(|task| {
+            let inner = task.access_inner();
+            Arc::ptr_eq(&inner.ns.ipc, ns).then(|| inner.sem_undo.clone())
+        })
+        .for_each(|undo| undo.lock().clear(semid, sem_num));
+}
+
+/// 根据 id 获取一个信号量集
+fn get_sem_set(semid: usize) -> AlienResult<Arc<SemSet>> {
+    current_ipc_ns()
+        .sem
+        .lock()
+        .get(&semid)
+        .cloned()
+        .ok_or(LinuxErrno::EINVAL)
+}
+
+/// 一个系统调用，用于创建或获取一个信号量集。
+///
+/// 参数：
+/// + `key`: 信号量集的键值。当其值为 `IPC_PRIVATE` 时，总是创建一个新的信号量集。
+/// + `nsems`: 信号量集中信号量的数量。获取已有的信号量集时，该值不能大于已有信号量集的大小。
+/// + `semflg`: 支持 `IPC_CREAT` 与 `IPC_EXCL`，低 9 位为信号量集的访问权限。
+///
+/// 成功时返回信号量集的 id；`key` 不存在且未指定 `IPC_CREAT` 时返回 `ENOENT`；
+/// 指定了 `IPC_CREAT | IPC_EXCL` 但 `key` 已经存在时返回 `EEXIST`；`nsems` 不合法时返回 `EINVAL`。
+///
+/// Reference: [semget](https://man7.org/linux/man-pages/man2/semget.2.html)
+#[syscall_func(190)]
+pub fn semget(key: usize, nsems: usize, semflg: u32) -> AlienResult<isize> {
+    info!("semget key:{}, nsems:{}, semflg:{:#o}", key, nsems, semflg);
+    if nsems > SEMMSL {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let ipc_ns = current_ipc_ns();
+    let mut sem_sets = ipc_ns.sem.lock();
+    if key != IPC_PRIVATE {
+        let exist = sem_sets
+            .iter()
+            .find(|(_, set)| set.access_inner().perm.key == key as i32);
+        if let Some((id, set)) = exist {
+            if semflg & IPC_CREAT != 0 && semflg & IPC_EXCL != 0 {
+                return Err(LinuxErrno::EEXIST);
+            }
+            if nsems > set.access_inner().sems.len() {
+                return Err(LinuxErrno::EINVAL);
+            }
+            return Ok(*id as isize);
+        }
+        if semflg & IPC_CREAT == 0 {
+            return Err(LinuxErrno::ENOENT);
+        }
+    }
+    if nsems == 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let semid = alloc_ipc_id(sem_sets.keys()).ok_or(LinuxErrno::ENOSPC)?;
+    sem_sets.insert(
+        semid,
+        Arc::new(SemSet::new(IpcPerm::new(key, semflg), nsems)),
+    );
+    Ok(semid as isize)
+}
+
+/// 一个系统调用，用于原子地对信号量集中的信号量进行一组操作。等价于 `timeout` 为 NULL 的 [`semtimedop`]。
+///
+/// Reference: [semop](https://man7.org/linux/man-pages/man2/semop.2.html)
+#[syscall_func(193)]
+pub fn semop(semid: usize, sops: usize, nsops: usize) -> AlienResult<isize> {
+    semtimedop(semid, sops, nsops, 0)
+}
+
+/// 一个系统调用，用于原子地对信号量集中的信号量进行一组操作，并可以指定等待的超时时间。
+///
+/// 参数：
+/// + `semid`: 信号量集的 id。
+/// + `sops`: 指向用户态的 [`SemBuf`] 数组，每个元素描述对一个信号量的操作：
+///     + `sem_op > 0`: 将信号量的值加上 `sem_op`
+///     + `sem_op < 0`: 如果信号量的值不小于 `sem_op` 的绝对值，则将其减去，否则等待
+///     + `sem_op == 0`: 等待信号量的值变为 0
+/// + `nsops`: 操作的数量。
+/// + `timeout`: 指向一个 [`TimeSpec`] 结构，表示等待的最长(相对)时间。为 0 时表示一直等待。
+///
+/// 只有所有操作都可以立即完成时才会执行它们；否则如果需要等待的操作包含 `IPC_NOWAIT` 则返回 `EAGAIN`，
+/// 超时返回 `EAGAIN`，等待期间信号量集被删除返回 `EIDRM`，收到信号返回 `EINTR`。
+/// 带有 `SEM_UNDO` 的操作将在任务退出时被撤销。
+///
+/// Reference: [semtimedop](https://man7.org/linux/man-pages/man2/semop.2.html)
+#[syscall_func(192)]
+pub fn semtimedop(semid: usize, sops: usize, nsops: usize, timeout: usize) -> AlienResult<isize> {
+    info!(
+        "semtimedop semid:{}, sops:{:#x}, nsops:{}, timeout:{:#x}",
+        semid, sops, nsops, timeout
+    );
+    if nsops == 0 || nsops > SEMOPM {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let mut ops = vec![SemBuf::default(); nsops];
+    task.access_inner()
+        .copy_from_user_buffer(sops as *const SemBuf, ops.as_mut_ptr(), nsops);
+    let wait_time = if timeout != 0 {
+        let mut time_spec = TimeSpec::default();
+        task.access_inner()
+            .copy_from_user(timeout as *const TimeSpec, &mut time_spec);
+        Some(time_spec.to_clock() + read_timer())
+    } else {
+        None
+    };
+    let set = get_sem_set(semid)?;
+    let pid = task.get_pid() as usize;
+    loop {
+        let mut inner = set.access_inner();
+        if inner.deleted {
+            return Err(LinuxErrno::EIDRM);
+        }
+        if ops
+            .iter()
+            .any(|sop| sop.sem_num as usize >= inner.sems.len())
+        {
+            return Err(LinuxErrno::EFBIG);
+        }
+        let (index, zero) = match inner.try_apply(&ops, pid)? {
+            SemOpResult::Done => {
+                let seq = inner.perm.seq;
+                drop(inner);
+                let undo = task.access_inner().sem_undo.clone();
+                let mut undo = undo.lock();
+                ops.iter()
+                    .filter(|sop| sop.sem_flg & SEM_UNDO != 0 && sop.sem_op != 0)
+                    .for_each(|sop| undo.record(semid, seq, sop));
+                return Ok(0);
+            }
+            SemOpResult::Block(index, zero) => (index, zero),
+        };
+        let nowait = ops
+            .iter()
+            .any(|sop| sop.sem_num as usize == index && sop.sem_flg as u32 & IPC_NOWAIT != 0);
+        if nowait {
+            return Err(LinuxErrno::EAGAIN);
+        }
+        if let Some(wait_time) = wait_time {
+            if wait_time <= read_timer() {
+                return Err(LinuxErrno::EAGAIN);
+            }
+        }
+        if zero {
+            inner.sems[index].semzcnt += 1;
+        } else {
+            inner.sems[index].semncnt += 1;
+        }
+        drop(inner);
+        do_suspend();
+        let mut inner = set.access_inner();
+        if let Some(sem) = inner.sems.get_mut(index) {
+            if zero {
+                sem.semzcnt -= 1;
+            } else {
+                sem.semncnt -= 1;
+            }
+        }
+        drop(inner);
+        let task_inner = task.access_inner();
+        let receiver = task_inner.signal_receivers.lock();
+        if receiver.have_signal() {
+            return Err(LinuxErrno::EINTR);
+        }
+    }
+}
+
+/// 一个系统调用，用于控制信号量集。
+///
+/// 参数：
+/// + `semid`: 信号量集的 id。
+/// + `semnum`: 信号量在集合中的下标，仅对 `GETVAL`、`SETVAL`、`GETPID`、`GETNCNT`、`GETZCNT` 有效。
+/// + `cmd`: 指明要采取的操作。目前 Alien 支持以下操作：
+///     + `IPC_STAT`/`SEM_STAT`: 将信号量集的状态信息 [`SemIdDs`] 写入 `arg` 指向的位置
+///     + `IPC_SET`: 使用 `arg` 指向的 [`SemIdDs`] 中的 uid、gid 以及 mode 更新信号量集
+///     + `IPC_RMID`: 立即删除信号量集，并唤醒所有等待在该信号量集上的进程
+///     + `IPC_INFO`/`SEM_INFO`: 获取系统中信号量集的限制信息/使用信息
+///     + `GETVAL`/`GETPID`/`GETNCNT`/`GETZCNT`: 返回信号量的对应信息
+///     + `GETALL`/`SETALL`: 获取/设置信号量集中所有信号量的值，`arg` 指向一个 `u16` 数组
+///     + `SETVAL`: 将信号量的值设置为 `arg`
+/// + `arg`: 即 `union semun`，根据 `cmd` 的不同作为值或者指针。
+///
+/// `SETVAL` 与 `SETALL` 会清除所有任务中对应信号量的 `SEM_UNDO` 调整值。
+///
+/// Reference: [semctl](https://man7.org/linux/man-pages/man2/semctl.2.html)
+#[syscall_func(191)]
+pub fn semctl(semid: usize, semnum: usize, cmd: usize, arg: usize) -> AlienResult<isize> {
+    let cmd = ipc_cmd(cmd);
+    info!(
+        "semctl semid:{}, semnum:{}, cmd:{}, arg:{:#x}",
+        semid, semnum, cmd, arg
+    );
+    let task = current_task().unwrap();
+    match cmd {
+        IPC_INFO | SEM_INFO => {
+            let ipc_ns = current_ipc_ns();
+            let sem_sets = ipc_ns.sem.lock();
+            let mut info = SemLimitInfo {
+                semmap: SEMMSL as i32,
+                semmni: IPC_MNI as i32,
+                semmns: (SEMMSL * IPC_MNI).min(i32::MAX as usize) as i32,
+                semmnu: SEMMSL as i32,
+                semmsl: SEMMSL as i32,
+                semopm: SEMOPM as i32,
+                semume: SEMOPM as i32,
+                semusz: core::mem::size_of::<SemUndoList>() as i32,
+                semvmx: SEMVMX,
+                semaem: SEMVMX,
+            };
+            if cmd == SEM_INFO {
+                info.semusz = sem_sets.len() as i32;
+                info.semaem = sem_sets
+                    .values()
+                    .map(|set| set.access_inner().sems.len())
+                    .sum::<usize>() as i32;
+            }
+            task.access_inner()
+                .copy_to_user(&info, arg as *mut SemLimitInfo);
+            return Ok(*sem_sets.keys().max().unwrap_or(&0) as isize);
+        }
+        IPC_RMID => {
+            let set = current_ipc_ns()
+                .sem
+                .lock()
+                .remove(&semid)
+                .ok_or(LinuxErrno::EINVAL)?;
+            set.access_inner().deleted = true;
+            return Ok(0);
+        }
+        _ => {}
+    }
+    let set = get_sem_set(semid)?;
+    let pid = task.get_pid() as usize;
+    match cmd {
+        IPC_STAT | SEM_STAT => {
+            let stat = set.stat();
+            task.access_inner().copy_to_user(&stat, arg as *mut SemIdDs);
+            if cmd == SEM_STAT {
+                return Ok(semid as isize);
+            }
+        }
+        IPC_SET => {
+            let mut stat = SemIdDs::default();
+            task.access_inner()
+                .copy_from_user(arg as *const SemIdDs, &mut stat);
+            let mut inner = set.access_inner();
+            inner.perm.update(&stat.sem_perm);
+            inner.ctime = ipc_now();
+        }
+        GETVAL | GETPID | GETNCNT | GETZCNT => {
+            let inner = set.access_inner();
+            let sem = inner.sems.get(semnum).ok_or(LinuxErrno::EINVAL)?;
+            let res = match cmd {
+                GETVAL => sem.semval as isize,
+                GETPID => sem.sempid as isize,
+                GETNCNT => sem.semncnt as isize,
+                _ => sem.semzcnt as isize,
+            };
+            return Ok(res);
+        }
+        GETALL => {
+            let values = set
+                .access_inner()
+                .sems
+                .iter()
+                .map(|sem| sem.semval as u16)
+                .collect::<Vec<u16>>();
+            task.access_inner()
+                .copy_to_user_buffer(values.as_ptr(), arg as *mut u16, values.len());
+        }
+        SETVAL => {
+            let value = arg as i32;
+            if value < 0 || value > SEMVMX {
+                return Err(LinuxErrno::ERANGE);
+            }
+            let mut inner = set.access_inner();
+            let sem = inner.sems.get_mut(semnum).ok_or(LinuxErrno::EINVAL)?;
+            sem.semval = value;
+            sem.sempid = pid;
+            inner.ctime = ipc_now();
+            drop(inner);
+            clear_undos(&current_ipc_ns(), semid, Some(semnum as u16));
+        }
+        SETALL => {
+            let nsems = set.access_inner().sems.len();
+            let mut values = vec![0u16; nsems];
+            task.access_inner().copy_from_user_buffer(
+                arg as *const u16,
+                values.as_mut_ptr(),
+                nsems,
+            );
+            if values.iter().any(|value| *value as i32 > SEMVMX) {
+                return Err(LinuxErrno::ERANGE);
+            }
+            let mut inner = set.access_inner();
+            inner.sems.iter_mut().zip(values).for_each(|(sem, value)| {
+                sem.semval = value as i32;
+                sem.sempid = pid;
+            });
+            inner.ctime = ipc_now();
+            drop(inner);
+            clear_undos(&current_ipc_ns(), semid, None);
+        }
+        _ => return Err(LinuxErrno::EINVAL),
+    }
+    Ok(0)
+}
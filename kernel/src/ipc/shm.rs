@@ -5,19 +5,57 @@
 //! Alien 中的共享内存同时提供了同步机制，也就是说，所有的 [`ShmMemoryInner`] 结构被包裹在一个 Mutex 中，
 //! 最后封装成 [`ShmMemory`] 结构。
 //!
-use alloc::collections::btree_map::BTreeMap;
+//! 当共享内存被标记删除(`IPC_RMID`)且没有进程再附加它时，其占用的物理页将被立即回收。
+//!
+//! 共享内存记录在进程所在的 IPC 命名空间 [`IpcNamespace`] 中，进程切换 IPC 命名空间后，已经附加的共享内存仍然有效。
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
 
 use config::FRAME_SIZE;
 use constants::{
-    ipc::{ShmAtFlags, ShmCtlCmd, ShmGetFlags, IPC_PRIVATE},
+    ipc::{ShmGetFlags, IPC_PRIVATE},
     AlienResult, LinuxErrno,
 };
 use ksync::{Mutex, MutexGuard};
-use mem::{alloc_frame_trackers, FrameTracker};
-use page_table::addr::{align_down_4k, PhysAddr, VirtAddr};
+use mem::{try_alloc_frame_trackers, FrameTracker};
+use page_table::{
+    addr::{align_up_4k, PhysAddr, VirtAddr},
+    pte::MappingFlags,
+};
 use syscall_table::syscall_func;
 
-use crate::task::current_task;
+use crate::{
+    ipc::sysv::{
+        alloc_ipc_id, ipc_cmd, ipc_now, IpcPerm, IPC_CREAT, IPC_EXCL, IPC_INFO, IPC_MNI, IPC_RMID,
+        IPC_SET, IPC_STAT,
+    },
+    task::{
+        current_task,
+        namespace::{current_ipc_ns, IpcNamespace},
+    },
+};
+
+/// 以只读方式附加共享内存
+const SHM_RDONLY: u32 = 0o10000;
+/// 将 `shmaddr` 向下对齐到 `SHMLBA`
+const SHM_RND: u32 = 0o20000;
+/// 允许替换 `shmaddr` 处已有的映射
+const SHM_REMAP: u32 = 0o40000;
+/// 允许执行共享内存中的代码
+const SHM_EXEC: u32 = 0o100000;
+
+/// 共享内存附加地址的对齐要求，riscv 上等于页大小
+const SHMLBA: usize = FRAME_SIZE;
+/// 单个共享内存的最大字节数
+const SHMMAX: usize = 0x1000_0000;
+
+/// 锁定共享内存(Alien 不会换出内存，因此直接忽略)
+const SHM_LOCK: usize = 11;
+/// 解锁共享内存
+const SHM_UNLOCK: usize = 12;
+/// 与 `IPC_STAT` 相同，但参数是内部索引而不是 id
+const SHM_STAT: usize = 13;
+/// 获取共享内存的使用信息
+const SHM_INFO: usize = 14;
 
 /// 共享内存被 Mutex 封装后的结构
 #[derive(Debug)]
@@ -35,32 +73,111 @@ pub struct ShmMemoryInner {
     pub frames: FrameTracker,
     /// 共享内存的状态
     state: ShmMemoryState,
+    /// 权限信息
+    perm: IpcPerm,
+    /// 创建时用户申请的大小(字节数)
+    size: usize,
+    /// 最后一次附加的时间
+    atime: usize,
+    /// 最后一次分离的时间
+    dtime: usize,
+    /// 最后一次修改的时间
+    ctime: usize,
+    /// 创建者的 pid
+    cpid: usize,
+    /// 最后一次附加/分离的进程的 pid
+    lpid: usize,
 }
 
 /// 共享内存的信息，在创建一块共享内存时，需要将对应的信息加入到进程控制块中的 `shm` 字段下
 #[derive(Debug, Clone)]
 pub struct ShmInfo {
+    /// 共享内存的 id
+    pub shmid: usize,
     /// 共享内存的虚拟地址首地址
     pub start_va: usize,
     /// 共享内存的虚拟地址尾地址
     pub end_va: usize,
+    /// 共享内存所在的 IPC 命名空间
+    pub ns: Arc<IpcNamespace>,
 }
 
 impl ShmInfo {
     /// 创建新的共享内存信息
-    pub fn new(start_va: usize, end_va: usize) -> Self {
-        Self { start_va, end_va }
+    pub fn new(shmid: usize, start_va: usize, end_va: usize, ns: Arc<IpcNamespace>) -> Self {
+        Self {
+            shmid,
+            start_va,
+            end_va,
+            ns,
+        }
+    }
+
+    /// 进程 `pid` 分离该共享内存，如果共享内存已被删除且没有进程附加，则回收其物理页
+    fn detach(&self, pid: usize) {
+        let mut shm_memory = self.ns.shm.lock();
+        if let Some(mem) = shm_memory.get(&self.shmid) {
+            mem.detach(pid);
+        }
+        try_reclaim(&mut shm_memory, self.shmid);
     }
 }
 
+/// 与 Linux 中的 `struct shmid64_ds` 保持一致，用于 `IPC_STAT` 和 `IPC_SET`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ShmIdDs {
+    pub shm_perm: IpcPerm,
+    pub shm_segsz: usize,
+    pub shm_atime: usize,
+    pub shm_dtime: usize,
+    pub shm_ctime: usize,
+    pub shm_cpid: i32,
+    pub shm_lpid: i32,
+    pub shm_nattch: usize,
+    __unused4: usize,
+    __unused5: usize,
+}
+
+/// 与 Linux 中的 `struct shminfo64` 保持一致，用于 `IPC_INFO`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+struct ShmLimitInfo {
+    shmmax: usize,
+    shmmin: usize,
+    shmmni: usize,
+    shmseg: usize,
+    shmall: usize,
+    __unused: [usize; 4],
+}
+
+/// 与 Linux 中的 `struct shm_info` 保持一致，用于 `SHM_INFO`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+struct ShmUsageInfo {
+    used_ids: i32,
+    shm_tot: usize,
+    shm_rss: usize,
+    shm_swp: usize,
+    swap_attempts: usize,
+    swap_successes: usize,
+}
+
 impl ShmMemory {
     /// 创建新的共享内存
-    pub fn new(frames: FrameTracker) -> Self {
+    pub fn new(perm: IpcPerm, size: usize, frames: FrameTracker, cpid: usize) -> Self {
         Self {
             inner: Mutex::new(ShmMemoryInner {
                 ref_count: 0,
                 frames,
                 state: ShmMemoryState::Init,
+                perm,
+                size,
+                atime: 0,
+                dtime: 0,
+                ctime: ipc_now(),
+                cpid,
+                lpid: 0,
             }),
         }
     }
@@ -85,15 +202,59 @@ impl ShmMemory {
         self.access_inner().ref_count
     }
 
-    /// 删除当前的共享内存
+    /// 删除当前的共享内存。被删除后，该共享内存的 key 将不再可用
     pub fn delete(&self) {
-        self.access_inner().state = ShmMemoryState::Deleted;
+        let mut inner = self.access_inner();
+        inner.state = ShmMemoryState::Deleted;
+        inner.perm.key = IPC_PRIVATE as i32;
     }
 
     /// 查询当前的共享内存是否被删除
     pub fn is_deleted(&self) -> bool {
         self.access_inner().state == ShmMemoryState::Deleted
     }
+
+    /// 当前共享内存是否可以被回收
+    pub fn can_reclaim(&self) -> bool {
+        let inner = self.access_inner();
+        inner.ref_count == 0 && inner.state == ShmMemoryState::Deleted
+    }
+
+    /// 返回当前共享内存的状态信息
+    pub fn stat(&self) -> ShmIdDs {
+        let inner = self.access_inner();
+        ShmIdDs {
+            shm_perm: inner.perm,
+            shm_segsz: inner.size,
+            shm_atime: inner.atime,
+            shm_dtime: inner.dtime,
+            shm_ctime: inner.ctime,
+            shm_cpid: inner.cpid as i32,
+            shm_lpid: inner.lpid as i32,
+            shm_nattch: inner.ref_count,
+            ..Default::default()
+        }
+    }
+
+    /// 记录一次附加操作
+    fn attach(&self, pid: usize) {
+        let mut inner = self.access_inner();
+        inner.state = match inner.state {
+            ShmMemoryState::Deleted => ShmMemoryState::Deleted,
+            _ => ShmMemoryState::Used,
+        };
+        inner.ref_count += 1;
+        inner.atime = ipc_now();
+        inner.lpid = pid;
+    }
+
+    /// 记录一次分离操作
+    fn detach(&self, pid: usize) {
+        let mut inner = self.access_inner();
+        inner.ref_count = inner.ref_count.saturating_sub(1);
+        inner.dtime = ipc_now();
+        inner.lpid = pid;
+    }
 }
 
 /// 记录共享内存当前状态的结构
@@ -104,166 +265,322 @@ pub enum ShmMemoryState {
     Deleted,
 }
 
-/// 用于记录共享内存分配情况的全局变量，可使用其获取已经被创建的一块共享内存
-pub static SHM_MEMORY: Mutex<BTreeMap<usize, ShmMemory>> = Mutex::new(BTreeMap::new());
+/// 如果 `shmid` 对应的共享内存已被删除且没有进程附加，则回收其物理页
+fn try_reclaim(shm_memory: &mut BTreeMap<usize, ShmMemory>, shmid: usize) {
+    let reclaim = shm_memory
+        .get(&shmid)
+        .map(|shm| shm.can_reclaim())
+        .unwrap_or(false);
+    if reclaim {
+        info!("reclaim share memory {}", shmid);
+        shm_memory.remove(&shmid);
+    }
+}
+
+/// 进程 fork 时，子进程会继承父进程已经附加的所有共享内存，需要增加它们的引用计数
+pub fn shm_inherit(shm: &BTreeMap<usize, ShmInfo>) {
+    shm.values().for_each(|info| {
+        if let Some(mem) = info.ns.shm.lock().get(&info.shmid) {
+            mem.add_ref();
+        }
+    });
+}
+
+/// 进程退出或执行 exec 时，分离所有已附加的共享内存。
+///
+/// 此时进程原有的地址空间将被整体回收，因此这里不再逐一解除映射，只减少引用计数并回收可以回收的共享内存。
+pub fn shm_detach_all(shm: &mut BTreeMap<usize, ShmInfo>, pid: usize) {
+    let detached = core::mem::take(shm);
+    detached.values().for_each(|info| info.detach(pid));
+}
 
 /// 一个系统调用，用于创建一块共享内存，方便进程间通信。
 ///
 /// 参数：
-/// + `key`: 指明共享内存的键值，多个进程可以通过它来访问同一个共享内存。当其值为 `IPC_PRIVATE` 时，用于创建当前进程的私有共享内存，多用于父子进程间。
-/// + `size`: 用于指明创建共享内存区大小。在函数执行过程中，内核将自动将该值与帧大小(4K)对齐。
-/// + `shmflg`: 用于指明操作的类型。当包含 `IPC_CREAT` 时，将创建一块共享内存，目前 Alien 中仅对 `IPC_CREAT` 有所识别。其它 flag 具体可见 [`ShmGetFlags`]。
+/// + `key`: 指明共享内存的键值，多个进程可以通过它来访问同一个共享内存。当其值为 `IPC_PRIVATE` 时，总是创建一块新的共享内存，多用于父子进程间。
+/// + `size`: 用于指明创建共享内存区大小。在函数执行过程中，内核将自动将该值向上与帧大小(4K)对齐。
+/// + `shmflg`: 用于指明操作的类型以及共享内存的访问权限(低 9 位)。支持 `IPC_CREAT` 与 `IPC_EXCL`。
 ///
-/// 如果已经有共享内存使用了键值 `key`，那么将直接返回 `key` 的值，不会进行创建共享内存操作。
+/// 如果已经有共享内存使用了键值 `key`，那么将直接返回该共享内存的 id；若同时指定了 `IPC_CREAT | IPC_EXCL`，则返回 `EEXIST`。
 ///
-/// 返回值：如果创建共享内存成功或已经有共享内存使用了键值 `key`，则返回 `key` 值；否则返回 `ENOENT`。
+/// 返回值：成功时返回共享内存的 id；`key` 不存在且未指定 `IPC_CREAT` 时返回 `ENOENT`；
+/// `size` 不合法时返回 `EINVAL`；共享内存数量达到上限时返回 `ENOSPC`。
 ///
 /// Reference: [shmget](https://man7.org/linux/man-pages/man2/shmget.2.html)
 #[syscall_func(194)]
-pub fn shmget(key: usize, size: usize, shmflg: u32) -> isize {
+pub fn shmget(key: usize, size: usize, shmflg: u32) -> AlienResult<isize> {
     info!(
         "shmget key:{},size:{},shmflg:{:?}",
         key,
         size,
         ShmGetFlags::from_bits_truncate(shmflg as i32)
     );
-    let key = if key == IPC_PRIVATE {
-        // we must create a key
-        *SHM_MEMORY.lock().keys().max().unwrap_or(&0) + 1
-    } else {
-        key
-    };
-    let mut shm_memory = SHM_MEMORY.lock();
-    let shm = shm_memory.get(&key);
-    // now we ignore flag
-    if shm.is_some() {
-        return key as isize;
+    let ipc_ns = current_ipc_ns();
+    let mut shm_memory = ipc_ns.shm.lock();
+    if key != IPC_PRIVATE {
+        let exist = shm_memory
+            .iter()
+            .find(|(_, shm)| !shm.is_deleted() && shm.access_inner().perm.key == key as i32);
+        if let Some((id, shm)) = exist {
+            if shmflg & IPC_CREAT != 0 && shmflg & IPC_EXCL != 0 {
+                return Err(LinuxErrno::EEXIST);
+            }
+            if size > shm.access_inner().size {
+                return Err(LinuxErrno::EINVAL);
+            }
+            return Ok(*id as isize);
+        }
+        if shmflg & IPC_CREAT == 0 {
+            return Err(LinuxErrno::ENOENT);
+        }
     }
-    let flag = ShmGetFlags::from_bits_truncate(shmflg as i32);
-    if flag.contains(ShmGetFlags::IPC_CREAT) {
-        info!("create new share memory {}", key);
-        // alloc frames
-        let frames = alloc_frame_trackers(align_down_4k(size) / FRAME_SIZE);
-        // if frames.is_none() {
-        //     return LinuxErrno::ENOMEM as isize;
-        // }
-        // let frames = frames.unwrap();
-        let share_mem = ShmMemory::new(frames);
-        shm_memory.insert(key, share_mem);
-        return key as isize;
+    if size == 0 || size > SHMMAX {
+        return Err(LinuxErrno::EINVAL);
     }
-    LinuxErrno::ENOENT as isize
+    let shmid = alloc_ipc_id(shm_memory.keys()).ok_or(LinuxErrno::ENOSPC)?;
+    info!("create new share memory {}, key: {}", shmid, key);
+    // alloc frames
+    let mut frames =
+        try_alloc_frame_trackers(align_up_4k(size) / FRAME_SIZE).ok_or(LinuxErrno::ENOMEM)?;
+    frames.fill(0);
+    let pid = current_task().unwrap().get_pid() as usize;
+    let share_mem = ShmMemory::new(IpcPerm::new(key, shmflg), size, frames, pid);
+    shm_memory.insert(shmid, share_mem);
+    Ok(shmid as isize)
 }
 
 /// 一个系统调用，用于将一块共享内存映射到进程的虚拟空间中。通常与 [`shmget`] 一起使用。
 ///
 /// 参数：
-/// + `shmid`: 用于指明要映射的共享内存的键值 `key`, 一般为 [`shmget`] 的返回值。
-/// + `shmaddr`: 用于指明共享内存要映射到的虚存地址。一般有以下几种情况(目前Alien只能处理情况1，其余情况会导致 panic 退出)
+/// + `shmid`: 用于指明要映射的共享内存的 id, 一般为 [`shmget`] 的返回值。
+/// + `shmaddr`: 用于指明共享内存要映射到的虚存地址。一般有以下几种情况
 ///     1. 如果 `shmaddr` 是NULL，系统将自动选择一个合适的地址
-///     2. 如果 `shmaddr` 不是NULL 并且没有指定 SHM_RND，则此段连接到addr所指定的地址上
-///     3. 如果 `shmaddr` 不是NULL 并且指定了 SHM_RND，则此段连接到 shmaddr -(shmaddr mod SHMLAB)所表示的地址上
-/// + `shmflg`: 一组标志位，通常为0。详细可见 [`ShmAtFlags`]。
+///     2. 如果 `shmaddr` 不是NULL 并且没有指定 SHM_RND，则此段连接到addr所指定的地址上，此时 `shmaddr` 需要按页对齐
+///     3. 如果 `shmaddr` 不是NULL 并且指定了 SHM_RND，则此段连接到 shmaddr -(shmaddr mod SHMLBA)所表示的地址上
+/// + `shmflg`: 一组标志位，通常为0。支持 `SHM_RDONLY`、`SHM_RND`、`SHM_REMAP` 与 `SHM_EXEC`。
 ///
-/// 函数正常执行且映射成功时，则会返回虚拟空间中映射的首地址；当 `shmid` 不合法时，会返回 `EINVAL`。
+/// 函数正常执行且映射成功时，则会返回虚拟空间中映射的首地址；当 `shmid` 或 `shmaddr` 不合法时，会返回 `EINVAL`。
 ///
 /// Reference: [shmat](https://www.man7.org/linux/man-pages/man3/shmat.3p.html)
 #[syscall_func(196)]
 pub fn shmat(shmid: usize, shmaddr: usize, shmflg: u32) -> AlienResult<isize> {
-    warn!(
-        "shmat shmid:{},shmaddr:{:#x},shmflg:{:?}",
-        shmid,
-        shmaddr,
-        ShmAtFlags::from_bits_truncate(shmflg as i32)
+    info!(
+        "shmat shmid:{},shmaddr:{:#x},shmflg:{:#o}",
+        shmid, shmaddr, shmflg
     );
-    let shm_memory = SHM_MEMORY.lock();
+    let ipc_ns = current_ipc_ns();
+    let shm_memory = ipc_ns.shm.lock();
     let shm = shm_memory.get(&shmid).ok_or(LinuxErrno::EINVAL)?;
-
-    let flag = ShmAtFlags::from_bits_truncate(shmflg as i32);
-    assert!(flag.is_empty());
-    if flag.contains(ShmAtFlags::SHM_RDONLY) {
-        info!("read only");
+    if shm.is_deleted() && shm.get_ref() == 0 {
+        return Err(LinuxErrno::EIDRM);
+    }
+    let shmaddr = if shmaddr != 0 && shmflg & SHM_RND != 0 {
+        shmaddr & !(SHMLBA - 1)
+    } else {
+        shmaddr
+    };
+    if shmaddr % SHMLBA != 0 || (shmaddr == 0 && shmflg & SHM_REMAP != 0) {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let mut map_flags: MappingFlags = "UVRAD".into();
+    if shmflg & SHM_RDONLY == 0 {
+        map_flags |= MappingFlags::W;
+    }
+    if shmflg & SHM_EXEC != 0 {
+        map_flags |= MappingFlags::X;
     }
-    assert_eq!(shmaddr, 0);
-    // we must find a place to map
-    let task = current_task().unwrap();
-    let free_map = task.access_inner().mmap.alloc(shm.len());
-    // map to va
-    error!("shm map range:{:#x?}", free_map);
-    shm.access_inner().state = ShmMemoryState::Used;
-    let mut task_inner = task.access_inner();
-    let mut address_space = task_inner.address_space.lock();
     let size = shm.len();
     let start_phy = shm.access_inner().frames.start();
-    address_space
+
+    let task = current_task().unwrap();
+    let pid = task.get_pid() as usize;
+    let mut task_inner = task.access_inner();
+    let mut replaced = Vec::new();
+    let mut split = Vec::new();
+    let map_range = if shmaddr == 0 {
+        // we must find a place to map
+        task_inner.mmap.alloc(size)
+    } else {
+        let range = shmaddr..shmaddr + size;
+        let mut address_space = task_inner.address_space.lock();
+        let mut va = range.start;
+        while va < range.end {
+            if address_space.query(VirtAddr::from(va)).is_ok() {
+                if shmflg & SHM_REMAP == 0 {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                address_space
+                    .unmap_region(VirtAddr::from(va), FRAME_SIZE)
+                    .map_err(|_| LinuxErrno::EINVAL)?;
+            }
+            va += FRAME_SIZE;
+        }
+        drop(address_space);
+        // 被替换的 mmap 区域与共享内存只保留 range 之外的部分
+        task_inner.mmap.remove_range(range.clone());
+        let replaced_starts = task_inner
+            .shm
+            .values()
+            .filter(|info| info.start_va < range.end && range.start < info.end_va)
+            .map(|info| info.start_va)
+            .collect::<Vec<usize>>();
+        for start in replaced_starts {
+            let info = task_inner.shm.remove(&start).unwrap();
+            let mut pieces = Vec::new();
+            if info.start_va < range.start {
+                pieces.push(info.start_va..range.start);
+            }
+            if range.end < info.end_va {
+                pieces.push(range.end..info.end_va);
+            }
+            if pieces.is_empty() {
+                replaced.push(info);
+                continue;
+            }
+            // 分割出的每一部分都是一次附加
+            if pieces.len() > 1 {
+                split.push((info.ns.clone(), info.shmid));
+            }
+            for piece in pieces {
+                task_inner.shm.insert(
+                    piece.start,
+                    ShmInfo::new(info.shmid, piece.start, piece.end, info.ns.clone()),
+                );
+            }
+        }
+        range
+    };
+    // map to va
+    task_inner
+        .address_space
+        .lock()
         .map_region(
-            VirtAddr::from(free_map.start),
+            VirtAddr::from(map_range.start),
             PhysAddr::from(start_phy),
             size,
-            "UVRWAD".into(),
+            map_flags,
             false,
         )
-        .unwrap();
-
-    info!("shm map range:{:#x?}", free_map);
+        .map_err(|_| LinuxErrno::ENOMEM)?;
+    info!("shm map range:{:#x?}", map_range);
+    task_inner.shm.insert(
+        map_range.start,
+        ShmInfo::new(shmid, map_range.start, map_range.end, ipc_ns.clone()),
+    );
+    drop(task_inner);
+    shm_memory.get(&shmid).unwrap().attach(pid);
+    drop(shm_memory);
+    // 被替换的共享内存可能属于其它 IPC 命名空间
+    split.iter().for_each(|(ns, shmid)| {
+        if let Some(mem) = ns.shm.lock().get(shmid) {
+            mem.add_ref();
+        }
+    });
+    replaced.iter().for_each(|info| info.detach(pid));
+    Ok(map_range.start as isize)
+}
 
-    drop(address_space);
+/// 一个系统调用，用于将一块共享内存从进程的虚拟空间中分离。
+///
+/// `shmaddr` 需要是之前 [`shmat`] 返回的地址。分离后，若该共享内存已被标记删除且没有进程再附加它，其物理页将被回收。
+///
+/// 函数正常执行后返回 0；当 `shmaddr` 处没有附加的共享内存时，返回 `EINVAL`。
+///
+/// Reference: [shmdt](https://man7.org/linux/man-pages/man2/shmdt.2.html)
+#[syscall_func(197)]
+pub fn shmdt(shmaddr: usize) -> AlienResult<isize> {
+    info!("shmdt shmaddr:{:#x}", shmaddr);
+    let task = current_task().unwrap();
+    let mut task_inner = task.access_inner();
+    let info = task_inner.shm.remove(&shmaddr).ok_or(LinuxErrno::EINVAL)?;
     task_inner
-        .shm
-        .insert(shmid, ShmInfo::new(free_map.start, free_map.end));
-    shm.add_ref();
-    Ok(free_map.start as isize)
+        .address_space
+        .lock()
+        .unmap_region(VirtAddr::from(info.start_va), info.end_va - info.start_va)
+        .map_err(|_| LinuxErrno::EINVAL)?;
+    drop(task_inner);
+    info.detach(task.get_pid() as usize);
+    Ok(0)
 }
 
 /// 一个系统调用，用于控制共享内存。
 ///
 /// 参数：
-/// + `shmid`: 用于指明要操作的共享内存的键值 `key`, 一般为 [`shmget`] 的返回值。
-/// + `cmd`: 指明要采取的操作。具体可见 [`ShmCtlCmd`]，目前Alien仅支持 `IpcRmid`，即删除共享内存操作。
-/// + `_buf`: 指向一个存储共享内存模式和访问权限的结构，目前未用到。
+/// + `shmid`: 用于指明要操作的共享内存的 id, 一般为 [`shmget`] 的返回值。
+/// + `cmd`: 指明要采取的操作。目前 Alien 支持以下操作：
+///     + `IPC_STAT`/`SHM_STAT`: 将共享内存的状态信息 [`ShmIdDs`] 写入 `buf` 中
+///     + `IPC_SET`: 使用 `buf` 中的 uid、gid 以及 mode 更新共享内存的权限信息
+///     + `IPC_RMID`: 标记删除共享内存，当没有进程附加它时，其物理页将被回收
+///     + `IPC_INFO`/`SHM_INFO`: 获取系统中共享内存的限制信息/使用信息
+///     + `SHM_LOCK`/`SHM_UNLOCK`: Alien 不会换出内存，因此直接返回 0
+/// + `buf`: 指向一个 [`ShmIdDs`] 结构，根据 `cmd` 的不同作为输入或输出。
 ///
-/// 当接受的 `cmd` 为 `IpcRmid` 且 成功执行后，将返回 0；否则会因为还未支持相关操作类型而 panic。
+/// 成功执行后，`IPC_INFO`/`SHM_INFO` 返回当前最大的共享内存 id，`SHM_STAT` 返回 `shmid`，其余命令返回 0。
+/// 当 `shmid` 或 `cmd` 不合法时返回 `EINVAL`。
 ///
 /// Reference: [shmctl](https://man7.org/linux/man-pages/man2/shmctl.2.html)
 #[syscall_func(195)]
-pub fn shmctl(shmid: usize, cmd: usize, _buf: usize) -> AlienResult<isize> {
-    let cmd = ShmCtlCmd::try_from(cmd as u32).map_err(|_| LinuxErrno::EINVAL)?;
+pub fn shmctl(shmid: usize, cmd: usize, buf: usize) -> AlienResult<isize> {
+    let cmd = ipc_cmd(cmd);
+    info!("shmctl shmid:{}, cmd:{}, buf:{:#x}", shmid, cmd, buf);
+    let task = current_task().unwrap();
+    let ipc_ns = current_ipc_ns();
+    let mut shm_memory = ipc_ns.shm.lock();
     match cmd {
-        ShmCtlCmd::IpcRmid => {
-            //delete
-            let shm_memory = SHM_MEMORY.lock();
-            let shm = shm_memory.get(&shmid).ok_or(LinuxErrno::EINVAL)?;
-            shm.delete();
-            let task = current_task().unwrap();
-            let task_inner = task.access_inner();
-            let have_detach = task_inner.shm.get(&shmid).clone();
-            if have_detach.is_some() {
-                // task_inner.shm.remove(&shmid);
-                // unmap
-                // let mut address_space = task_inner.address_space.lock();
-                // let mut virt_start = shm.access_inner().start_va;
-                // let virt_end = shm.access_inner().end_va;
-                // error!("shm unmap rang: {:#x}-{:#x}",virt_start,virt_end);
-                // while virt_start < virt_end {
-                //     let (phy, flag, _) = address_space.query(
-                //         VirtAddr::from(virt_start)
-                //     ).unwrap();
-                //     error!("query {:#x} to {:#x} {:?}",virt_start,phy,flag);
-                //     address_space.unmap(VirtAddr::from(virt_start)).unwrap();
-                //     virt_start += FRAME_SIZE;
-                // }
-            }
-            let mut flag = false;
-            if shm.get_ref() == 0 && shm.is_deleted() {
-                flag = true;
-            }
-            if flag {
-                // shm_memory.remove(&shmid);
+        IPC_INFO => {
+            let info = ShmLimitInfo {
+                shmmax: SHMMAX,
+                shmmin: 1,
+                shmmni: IPC_MNI,
+                shmseg: IPC_MNI,
+                shmall: SHMMAX / FRAME_SIZE,
+                ..Default::default()
+            };
+            task.access_inner()
+                .copy_to_user(&info, buf as *mut ShmLimitInfo);
+            return Ok(*shm_memory.keys().max().unwrap_or(&0) as isize);
+        }
+        SHM_INFO => {
+            let pages = shm_memory
+                .values()
+                .map(|shm| shm.len() / FRAME_SIZE)
+                .sum::<usize>();
+            let info = ShmUsageInfo {
+                used_ids: shm_memory.len() as i32,
+                shm_tot: pages,
+                shm_rss: pages,
+                ..Default::default()
+            };
+            task.access_inner()
+                .copy_to_user(&info, buf as *mut ShmUsageInfo);
+            return Ok(*shm_memory.keys().max().unwrap_or(&0) as isize);
+        }
+        _ => {}
+    }
+    let shm = shm_memory.get(&shmid).ok_or(LinuxErrno::EINVAL)?;
+    match cmd {
+        IPC_STAT | SHM_STAT => {
+            let stat = shm.stat();
+            task.access_inner().copy_to_user(&stat, buf as *mut ShmIdDs);
+            if cmd == SHM_STAT {
+                return Ok(shmid as isize);
             }
         }
-        _ => {
-            panic!("not support")
+        IPC_SET => {
+            let mut stat = ShmIdDs::default();
+            task.access_inner()
+                .copy_from_user(buf as *const ShmIdDs, &mut stat);
+            let mut inner = shm.access_inner();
+            inner.perm.update(&stat.shm_perm);
+            inner.ctime = ipc_now();
+        }
+        IPC_RMID => {
+            shm.delete();
+            shm.access_inner().ctime = ipc_now();
+            try_reclaim(&mut shm_memory, shmid);
         }
+        SHM_LOCK | SHM_UNLOCK => {}
+        _ => return Err(LinuxErrno::EINVAL),
     }
     Ok(0)
 }
@@ -2,23 +2,379 @@
 //! 内核也可以因为内部事件而给进程发送信号，通知进程发生了某个事件。
 //!
 //! 有关 Alien 中信号的具体处理流程可见 [`signal_handler`]。
-use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
 use core::mem::size_of;
 
 use constants::{signal::*, time::TimeSpec, AlienResult, LinuxErrno};
 use ksync::Mutex;
+use smpscheduler::FifoTask;
 use syscall_table::syscall_func;
 use timer::{read_timer, ToClock};
 
-use crate::task::{current_task, do_exit, do_suspend};
+use crate::{
+    ipc::wake_for_signal,
+    task::{
+        current_task, do_coredump, do_exit_by_signal, find_task, is_orphaned_pgrp,
+        namespace::find_vpid, process_group, processes, schedule::schedule, thread_group, Task,
+        TaskState, GLOBAL_TASK_MANAGER,
+    },
+    time::sleep_until,
+    trap::TrapFrame,
+};
+
+/// 第一个实时信号，实时信号在处理前的每一次发送都会排队
+pub const SIGRTMIN: usize = 32;
+/// 最后一个实时信号
+pub const SIGRTMAX: usize = 64;
+/// 一个线程中排队等待处理的实时信号的最大数量，超过后发送实时信号会返回 `EAGAIN`
+pub const SIGQUEUE_MAX: usize = 1024;
+
+/// 默认的信号处理方式
+pub const SIG_DFL: usize = 0;
+/// 忽略信号
+pub const SIG_IGN: usize = 1;
+
+/// 由 `kill` 发送的信号
+pub const SI_USER: i32 = 0;
+/// 由内核发送的信号
+pub const SI_KERNEL: i32 = 0x80;
+/// 由 `sigqueue` 发送的信号
+pub const SI_QUEUE: i32 = -1;
+/// POSIX 计时器到期时发送的信号
+pub const SI_TIMER: i32 = -2;
+/// POSIX 消息队列发送的通知信号
+pub const SI_MESGQ: i32 = -3;
+/// 由 `tkill` 或 `tgkill` 发送的信号
+pub const SI_TKILL: i32 = -6;
+/// SIGILL: 非法的操作码
+pub const ILL_ILLOPC: i32 = 1;
+/// SIGSEGV: 地址没有被映射
+pub const SEGV_MAPERR: i32 = 1;
+/// SIGSEGV: 没有访问该地址的权限
+pub const SEGV_ACCERR: i32 = 2;
+/// SIGCHLD: 子进程正常退出
+pub const CLD_EXITED: i32 = 1;
+/// SIGCHLD: 子进程被信号终止
+pub const CLD_KILLED: i32 = 2;
+/// SIGCHLD: 子进程被信号终止并产生了 core dump
+pub const CLD_DUMPED: i32 = 3;
+/// SIGCHLD: 子进程被停止信号暂停
+pub const CLD_STOPPED: i32 = 5;
+/// SIGCHLD: 被暂停的子进程继续执行
+pub const CLD_CONTINUED: i32 = 6;
+
+/// 当前正在备用信号栈上执行
+const SS_ONSTACK: u32 = 1;
+/// 备用信号栈被禁用
+const SS_DISABLE: u32 = 2;
+/// 进入信号处理函数时自动禁用备用信号栈，Alien 中忽略该标志
+const SS_AUTODISARM: u32 = 1 << 31;
+/// 备用信号栈的最小大小
+const MINSIGSTKSZ: usize = 2048;
+
+/// 被信号打断后，即使信号处理函数设置了 `SA_RESTART` 也不会重新执行的系统调用
+const NO_RESTART_SYSCALLS: [usize; 7] = [22, 72, 73, 101, 115, 133, 137];
+/// `rt_sigreturn` 的系统调用号
+const SYSCALL_SIGRETURN: usize = 139;
+
+/// 信号 `signum` 在信号集中对应的位，与用户态的 `sigset_t` 保持一致
+#[inline]
+fn sig_bit(signum: usize) -> usize {
+    1 << (signum - 1)
+}
+
+/// 不能被阻塞的信号
+fn unblockable() -> usize {
+    sig_bit(SignalNumber::SIGKILL as usize) | sig_bit(SignalNumber::SIGSTOP as usize)
+}
+
+/// 信号附带的信息，与 Linux 中的 `siginfo_t` 保持一致
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SigInfo {
+    /// 信号编号
+    pub si_signo: i32,
+    /// 错误码，总是为 0
+    pub si_errno: i32,
+    /// 信号的来源，取值为 `SI_*` 或与信号相关的 `*_*` 常量
+    pub si_code: i32,
+    __pad: i32,
+    /// `siginfo_t` 中与 `si_code` 相关的联合体，按 64 位字访问
+    fields: [usize; 14],
+}
+
+impl SigInfo {
+    /// 创建一个只包含信号编号与来源的信息
+    pub fn new(signum: usize, code: i32) -> Self {
+        Self {
+            si_signo: signum as i32,
+            si_errno: 0,
+            si_code: code,
+            __pad: 0,
+            fields: [0; 14],
+        }
+    }
+
+    /// 设置发送者的 `si_pid` 与 `si_uid`
+    pub fn with_sender(mut self, pid: usize, uid: u32) -> Self {
+        self.fields[0] = pid as u32 as usize | (uid as usize) << 32;
+        self
+    }
+
+    /// 设置随信号传递的数据 `si_value`
+    pub fn with_value(mut self, value: usize) -> Self {
+        self.fields[1] = value;
+        self
+    }
+
+    /// 设置 POSIX 计时器信号的 `si_timerid` 与 `si_overrun`
+    pub fn with_timer(mut self, timer_id: usize, overrun: usize) -> Self {
+        self.fields[0] = timer_id as u32 as usize | (overrun as u32 as usize) << 32;
+        self
+    }
+
+    /// 设置硬件异常信号中产生异常的地址 `si_addr`
+    pub fn with_addr(mut self, addr: usize) -> Self {
+        self.fields[0] = addr;
+        self
+    }
+
+    /// 设置 SIGCHLD 信号中子进程的状态 `si_status`
+    pub fn with_status(mut self, status: i32) -> Self {
+        self.fields[1] = status as u32 as usize;
+        self
+    }
+
+    /// 根据 `wait4` 返回的状态 `wait_status` 构造子进程 `pid` 退出、暂停或继续执行时发送给父进程的 SIGCHLD 信息
+    pub fn child_status(pid: usize, wait_status: i32) -> Self {
+        let sig = wait_status & 0x7f;
+        let (code, status) = if wait_status == 0xffff {
+            (CLD_CONTINUED, SignalNumber::SIGCONT as i32)
+        } else if sig == 0x7f {
+            (CLD_STOPPED, (wait_status >> 8) & 0xff)
+        } else if sig == 0 {
+            (CLD_EXITED, (wait_status >> 8) & 0xff)
+        } else if wait_status & 0x80 != 0 {
+            (CLD_DUMPED, sig)
+        } else {
+            (CLD_KILLED, sig)
+        };
+        SigInfo::new(SignalNumber::SIGCHLD as usize, code)
+            .with_sender(pid, 0)
+            .with_status(status)
+    }
+
+    /// 信号编号
+    pub fn signum(&self) -> usize {
+        self.si_signo as usize
+    }
+}
+
+/// 信号的默认处理方式
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SigActionDefault {
+    /// 终止进程
+    Terminate,
+    /// 终止进程并产生 core dump
+    CoreDump,
+    /// 忽略信号
+    Ignore,
+    /// 暂停进程，直到收到 SIGCONT
+    Stop,
+    /// 继续执行被暂停的进程
+    Continue,
+}
+
+impl SigActionDefault {
+    /// 获取信号 `signum` 的默认处理方式，实时信号与未知信号的默认处理方式均为终止进程
+    pub fn of_signal(signum: usize) -> Self {
+        let Ok(sig) = SignalNumber::try_from(signum as u8) else {
+            return Self::Terminate;
+        };
+        match sig {
+            SignalNumber::SIGCHLD | SignalNumber::SIGURG | SignalNumber::SIGWINCH => Self::Ignore,
+            SignalNumber::SIGSTOP
+            | SignalNumber::SIGTSTP
+            | SignalNumber::SIGTTIN
+            | SignalNumber::SIGTTOU => Self::Stop,
+            SignalNumber::SIGCONT => Self::Continue,
+            SignalNumber::SIGQUIT
+            | SignalNumber::SIGILL
+            | SignalNumber::SIGTRAP
+            | SignalNumber::SIGABRT
+            | SignalNumber::SIGBUS
+            | SignalNumber::SIGFPE
+            | SignalNumber::SIGSEGV
+            | SignalNumber::SIGXCPU
+            | SignalNumber::SIGXFSZ
+            | SignalNumber::SIGSYS => Self::CoreDump,
+            _ => Self::Terminate,
+        }
+    }
+}
+
+/// 信号处理函数执行时保存的寄存器，与 Linux 中 riscv64 的 `struct sigcontext` 保持一致。
+///
+/// `gregs[0]` 保存的是 pc，其余为 x1-x31。`fpregs` 按 `struct __riscv_d_ext_state` 的布局保存
+/// f0-f31，之后是 fcsr
+#[repr(C, align(16))]
+#[derive(Copy, Clone)]
+pub struct MContext {
+    pub gregs: [usize; 32],
+    pub fpregs: [u64; 66],
+}
+
+/// fcsr 在 [`MContext::fpregs`] 中的位置，只使用低 32 位
+const FCSR_INDEX: usize = 32;
+
+/// 信号处理函数执行时保存的上下文，与 Linux 中 riscv64 的 `struct ucontext` 保持一致。
+///
+/// 用户可以在信号处理函数中修改其中的内容(如 musl-libc 的 `pthread_cancel` 会修改 pc)，
+/// [`signal_return`] 会按照修改后的内容恢复上下文。
+#[repr(C, align(16))]
+#[derive(Copy, Clone)]
+pub struct UContext {
+    pub uc_flags: usize,
+    pub uc_link: usize,
+    /// 进入信号处理函数前的备用信号栈
+    pub uc_stack: SignalStack,
+    /// 进入信号处理函数前的信号屏蔽字，信号处理函数返回时恢复
+    pub uc_sigmask: usize,
+    __unused: [u8; 120],
+    pub uc_mcontext: MContext,
+}
+
+impl UContext {
+    fn empty() -> Self {
+        Self {
+            uc_flags: 0,
+            uc_link: 0,
+            uc_stack: SignalStack::default(),
+            uc_sigmask: 0,
+            __unused: [0; 120],
+            uc_mcontext: MContext {
+                gregs: [0; 32],
+                fpregs: [0; 66],
+            },
+        }
+    }
+}
+
+/// 执行信号处理函数时放在用户栈上的信号帧，信号处理函数的 a1 与 a2 分别指向其中的 `info` 与 `uc`。
+///
+/// 每一次进入信号处理函数都会在栈上放置一个新的信号帧，因此信号处理函数可以嵌套执行。
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct SigFrame {
+    info: SigInfo,
+    uc: UContext,
+}
+
+/// 线程中待处理信号附带的 [`SigInfo`]。
+///
+/// 标准信号在被处理前重复发送时只保留第一次的信息，实时信号则按发送的顺序排队，每一次发送都会被处理一次。
+#[derive(Default)]
+struct SigQueue {
+    infos: BTreeMap<usize, VecDeque<SigInfo>>,
+    /// 正在排队的实时信号数量
+    queued: usize,
+}
+
+impl SigQueue {
+    /// 加入一个信号的信息，实时信号排队的数量达到上限时返回 false
+    fn push(&mut self, info: SigInfo) -> bool {
+        let signum = info.signum();
+        if signum < SIGRTMIN {
+            let infos = self.infos.entry(signum).or_default();
+            if infos.is_empty() {
+                infos.push_back(info);
+            }
+            return true;
+        }
+        if self.queued >= SIGQUEUE_MAX {
+            return false;
+        }
+        self.infos.entry(signum).or_default().push_back(info);
+        self.queued += 1;
+        true
+    }
+
+    /// 取出信号 `signum` 最早的信息，同时返回该信号是否还有其它实例在排队
+    fn pop(&mut self, signum: usize) -> (SigInfo, bool) {
+        let Some(infos) = self.infos.get_mut(&signum) else {
+            return (SigInfo::new(signum, SI_KERNEL), false);
+        };
+        let info = infos.pop_front();
+        if info.is_some() && signum >= SIGRTMIN {
+            self.queued -= 1;
+        }
+        let more = !infos.is_empty();
+        if !more {
+            self.infos.remove(&signum);
+        }
+        (
+            info.unwrap_or_else(|| SigInfo::new(signum, SI_KERNEL)),
+            more,
+        )
+    }
+
+    /// 信号 `signum` 是否正在等待处理
+    fn is_pending(&self, signum: usize) -> bool {
+        self.infos.contains_key(&signum)
+    }
+
+    /// 丢弃信号 `signum` 所有的信息
+    fn discard(&mut self, signum: usize) {
+        if let Some(infos) = self.infos.remove(&signum) {
+            if signum >= SIGRTMIN {
+                self.queued -= infos.len();
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.infos.clear();
+        self.queued = 0;
+    }
+}
+
+/// 一个线程接收信号所需的结构
+#[derive(Clone)]
+struct SignalTarget {
+    /// 待处理的信号与信号屏蔽字
+    receivers: Arc<Mutex<SignalReceivers>>,
+    /// 待处理信号附带的信息。与 `receivers` 同时使用时，需要先获取 `receivers` 的锁
+    queue: Arc<Mutex<SigQueue>>,
+}
 
 /// 记录每个线程的信号量，从 tid 获取信号相关信息
-static TID2SIGNALS: Mutex<BTreeMap<usize, Arc<Mutex<SignalReceivers>>>> =
-    Mutex::new(BTreeMap::new());
+static TID2SIGNALS: Mutex<BTreeMap<usize, SignalTarget>> = Mutex::new(BTreeMap::new());
+
+/// 被停止信号暂停的任务，以 tid 为键，收到 SIGCONT 或 SIGKILL 时重新加入调度队列
+static STOPPED_TASKS: Mutex<BTreeMap<usize, Arc<Task>>> = Mutex::new(BTreeMap::new());
+
+/// 正在进行的组停止，以 pid 为键。只在持有 [`STOPPED_TASKS`] 的锁时访问
+static GROUP_STOPS: Mutex<BTreeMap<usize, GroupStop>> = Mutex::new(BTreeMap::new());
+
+/// 停止信号使整个线程组停止，线程组中的每个线程在返回用户态之前都会被暂停
+struct GroupStop {
+    /// 使线程组停止的信号
+    signum: usize,
+    /// 是否已经通知过父进程，所有线程都被暂停后只通知一次
+    notified: bool,
+}
 
 /// 所有线程初始化时均需要加入表
 pub fn global_register_signals(tid: usize, signals: Arc<Mutex<SignalReceivers>>) {
-    TID2SIGNALS.lock().insert(tid, signals).take();
+    let target = SignalTarget {
+        receivers: signals,
+        queue: Arc::new(Mutex::new(SigQueue::default())),
+    };
+    TID2SIGNALS.lock().insert(tid, target).take();
 }
 
 /// 所有线程退出时均需要从表中删除
@@ -28,20 +384,301 @@ pub fn global_logoff_signals(tid: usize) {
 
 /// 获取信号量。这个函数会复制一个 Arc，不会影响表中的信号本身
 pub fn get_signals_from_tid(tid: usize) -> Option<Arc<Mutex<SignalReceivers>>> {
-    TID2SIGNALS.lock().get(&tid).map(|s| s.clone())
+    TID2SIGNALS.lock().get(&tid).map(|s| s.receivers.clone())
+}
+
+fn get_target_from_tid(tid: usize) -> Option<SignalTarget> {
+    TID2SIGNALS.lock().get(&tid).cloned()
+}
+
+/// 丢弃线程 tid 所有待处理信号附带的信息，在 `exec` 清空待处理信号时调用
+pub fn flush_signal_queue(tid: usize) {
+    if let Some(target) = get_target_from_tid(tid) {
+        target.queue.lock().clear();
+    }
+}
+
+/// 是否为停止信号
+fn is_stop_signal(signum: usize) -> bool {
+    SigActionDefault::of_signal(signum) == SigActionDefault::Stop
+}
+
+/// 发送一个信号给线程 tid，信号附带的信息由 `info` 给出
+///
+/// 如果目标线程正在可以被信号打断的等待中，会将其唤醒；如果目标线程被停止信号暂停，SIGCONT 与 SIGKILL 会使其继续执行。
+/// 目标线程不存在时返回 `ESRCH`，实时信号排队的数量达到上限时返回 `EAGAIN`。
+pub fn send_signal_info(tid: usize, info: SigInfo) -> AlienResult<()> {
+    let target = get_target_from_tid(tid).ok_or(LinuxErrno::ESRCH)?;
+    let signum = info.signum();
+    warn!(
+        "send signal {:?} to {}, code: {}",
+        SignalNumber::try_from(signum as u8),
+        tid,
+        info.si_code
+    );
+    let continued = signum == SignalNumber::SIGCONT as usize;
+    {
+        let mut queue = target.queue.lock();
+        // SIGCONT 与停止信号会互相取消对方
+        if continued {
+            (1..SIGRTMIN)
+                .filter(|&sig| is_stop_signal(sig))
+                .for_each(|sig| queue.discard(sig));
+        } else if is_stop_signal(signum) {
+            queue.discard(SignalNumber::SIGCONT as usize);
+        }
+        if !queue.push(info) {
+            return Err(LinuxErrno::EAGAIN);
+        }
+    }
+    let pending = {
+        let mut signals = target.receivers.lock();
+        if continued {
+            (1..SIGRTMIN)
+                .filter(|&sig| is_stop_signal(sig))
+                .for_each(|sig| {
+                    signals.check_signal(sig);
+                });
+        } else if is_stop_signal(signum) {
+            signals.check_signal(SignalNumber::SIGCONT as usize);
+        }
+        signals.try_add_bit(signum);
+        signals.have_signal()
+    };
+    if continued || signum == SignalNumber::SIGKILL as usize {
+        continue_task(tid, continued);
+    }
+    if pending {
+        wake_for_signal(tid);
+    }
+    Ok(())
 }
 
-/// 发送一个信号给进程 tid
+/// 发送一个由内核产生的信号给线程 tid
+///
+/// 如果目标线程正在可以被信号打断的等待中，会将其唤醒
 pub fn send_signal(tid: usize, signum: usize) {
-    if let Some(signals) = get_signals_from_tid(tid) {
-        // 获取目标线程(可以是自己)的 signals 数组
-        warn!(
-            "send signal {:?} to {}",
-            SignalNumber::try_from(signum as u8),
-            tid
-        );
-        signals.lock().try_add_bit(signum);
+    let _ = send_signal_info(tid, SigInfo::new(signum, SI_KERNEL));
+}
+
+/// 向当前线程发送一个由硬件异常产生的信号。
+///
+/// 这类信号不能被阻塞或忽略：如果信号被阻塞或被设置为忽略，会恢复为默认的处理方式，
+/// 避免进程反复触发同一个异常。
+pub fn force_signal(info: SigInfo) {
+    let task = current_task().unwrap();
+    let signum = info.signum();
+    {
+        let task_inner = task.access_inner();
+        let mut handlers = task_inner.signal_handlers.lock();
+        let mut receivers = task_inner.signal_receivers.lock();
+        let mut action = SigAction::empty();
+        handlers.get_action(signum, &mut action);
+        if receivers.mask.bits() & sig_bit(signum) != 0 || action.is_ignore() {
+            handlers.set_action(signum, &SigAction::empty());
+            receivers.mask -= SimpleBitSet::from(sig_bit(signum));
+        }
+    }
+    let _ = send_signal_info(task.get_tid() as usize, info);
+}
+
+/// 从线程的待处理信号中取出一个没有被屏蔽的信号，返回其附带的信息
+fn dequeue_signal(target: &SignalTarget) -> Option<SigInfo> {
+    let mut receivers = target.receivers.lock();
+    let signum = receivers.get_one_signal()?;
+    let (info, more) = target.queue.lock().pop(signum);
+    if more {
+        receivers.try_add_bit(signum);
+    }
+    Some(info)
+}
+
+/// 从线程的待处理信号中取出一个属于信号集 `set` 的信号，返回其附带的信息
+fn dequeue_signal_in(target: &SignalTarget, set: usize) -> Option<SigInfo> {
+    let mut receivers = target.receivers.lock();
+    let signum =
+        (1..=SIGRTMAX).find(|&sig| set & sig_bit(sig) != 0 && receivers.check_signal(sig))?;
+    let (info, more) = target.queue.lock().pop(signum);
+    if more {
+        receivers.try_add_bit(signum);
+    }
+    Some(info)
+}
+
+/// 任务 tid 是否被停止信号暂停
+pub fn is_task_stopped(tid: usize) -> bool {
+    STOPPED_TASKS.lock().contains_key(&tid)
+}
+
+/// 进程 `task` 被暂停或继续执行时，记录可以被 `wait4` 获取的状态 `status`，并向父进程发送 SIGCHLD。
+///
+/// 父进程为 SIGCHLD 设置了 `SA_NOCLDSTOP` 时不发送信号
+fn notify_parent_job(task: &Arc<Task>, status: i32) {
+    if task.get_pid() != task.get_tid() {
+        return;
+    }
+    task.access_inner().job_status = Some(status);
+    let Some(parent) = task.parent() else {
+        return;
+    };
+    let mut action = SigAction::empty();
+    parent
+        .access_inner()
+        .signal_handlers
+        .lock()
+        .get_action(SignalNumber::SIGCHLD as usize, &mut action);
+    if action.flags.contains(SigActionFlags::SA_NOCLDSTOP) {
+        return;
+    }
+    let info = SigInfo::child_status(task.get_pid() as usize, status);
+    let _ = send_signal_info(parent.get_pid() as usize, info);
+}
+
+/// 使任务 tid 所在线程组中被停止信号暂停的线程全部继续执行，并结束正在进行的组停止。
+///
+/// `continued` 表示是否因为收到 SIGCONT，此时线程组被停止过的话会通知父进程一次。
+fn continue_task(tid: usize, continued: bool) {
+    let pid = find_task(tid).map_or(tid, |task| task.get_pid() as usize);
+    let (tasks, group_stopped) = {
+        let mut stopped = STOPPED_TASKS.lock();
+        let group_stopped = GROUP_STOPS.lock().remove(&pid).is_some();
+        let tids = stopped
+            .iter()
+            .filter(|(_, task)| task.get_pid() as usize == pid)
+            .map(|(tid, _)| *tid)
+            .collect::<Vec<_>>();
+        let tasks = tids
+            .into_iter()
+            .filter_map(|tid| stopped.remove(&tid))
+            .collect::<Vec<_>>();
+        (tasks, group_stopped)
+    };
+    if tasks.is_empty() && !group_stopped {
+        return;
+    }
+    warn!("thread group {} continued", pid);
+    if continued {
+        if let Some(leader) = find_task(pid) {
+            notify_parent_job(&leader, 0xffff);
+        }
     }
+    tasks
+        .into_iter()
+        .for_each(|task| GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task))));
+}
+
+/// 因为停止信号 `signum` 暂停当前任务所在的整个线程组，直到收到 SIGCONT 或 SIGKILL。
+///
+/// 线程组中的其它线程会被唤醒，在返回用户态之前由 [`join_group_stop`] 暂停
+fn do_signal_stop(task: &Arc<Task>, target: &SignalTarget, signum: usize) {
+    let pid = task.get_pid() as usize;
+    {
+        // 在持有锁的情况下检查，发送 SIGCONT 与 SIGKILL 的一方在加入信号后才会尝试使任务继续执行，因此不会错过
+        let _stopped = STOPPED_TASKS.lock();
+        let queue = target.queue.lock();
+        if queue.is_pending(SignalNumber::SIGCONT as usize)
+            || queue.is_pending(SignalNumber::SIGKILL as usize)
+        {
+            return;
+        }
+        GROUP_STOPS.lock().entry(pid).or_insert(GroupStop {
+            signum,
+            notified: false,
+        });
+    }
+    warn!("thread group {} stopped by signal {}", pid, signum);
+    thread_group(pid)
+        .iter()
+        .filter(|thread| thread.get_tid() != task.get_tid())
+        .for_each(|thread| wake_for_signal(thread.get_tid() as usize));
+    join_group_stop(task, target);
+}
+
+/// 当前任务所在的线程组正在停止时暂停当前任务，返回是否被暂停过。
+///
+/// 线程组中最后一个被暂停的线程负责通知父进程
+fn join_group_stop(task: &Arc<Task>, target: &SignalTarget) -> bool {
+    let tid = task.get_tid() as usize;
+    let pid = task.get_pid() as usize;
+    let mut stopped = STOPPED_TASKS.lock();
+    let mut groups = GROUP_STOPS.lock();
+    let Some(group) = groups.get_mut(&pid) else {
+        return false;
+    };
+    if target
+        .queue
+        .lock()
+        .is_pending(SignalNumber::SIGKILL as usize)
+    {
+        return false;
+    }
+    stopped.insert(tid, task.clone());
+    let all_stopped = thread_group(pid)
+        .iter()
+        .all(|thread| stopped.contains_key(&(thread.get_tid() as usize)));
+    let notify = if all_stopped && !group.notified {
+        group.notified = true;
+        Some(group.signum)
+    } else {
+        None
+    };
+    drop(groups);
+    drop(stopped);
+    warn!("task {} stopped", tid);
+    task.update_state(TaskState::Waiting);
+    if let Some(signum) = notify {
+        if let Some(leader) = find_task(pid) {
+            notify_parent_job(&leader, (signum as i32) << 8 | 0x7f);
+        }
+    }
+    schedule();
+    true
+}
+
+/// 重新执行被信号打断的系统调用，`a0` 为系统调用原来的第一个参数
+fn restart_syscall(trap_frame: &mut TrapFrame, a0: usize) {
+    trap_frame.regs()[10] = a0;
+    trap_frame.set_sepc(trap_frame.sepc() - 4);
+}
+
+/// 栈指针 `sp` 是否位于备用信号栈 `ss` 上
+fn on_sigaltstack(ss: &SignalStack, sp: usize) -> bool {
+    ss.ss_flags as u32 & SS_DISABLE == 0 && sp > ss.ss_sp && sp - ss.ss_sp <= ss.ss_size
+}
+
+/// 记录当前线程的系统调用被信号打断，`a0` 为系统调用原来的第一个参数。
+///
+/// 系统调用返回 `EINTR` 时调用，之后由 [`signal_handler`] 根据信号的处理方式决定是否重新执行该系统调用
+pub fn syscall_interrupted(syscall_id: usize, a0: usize) {
+    if syscall_id == SYSCALL_SIGRETURN {
+        return;
+    }
+    let task = current_task().unwrap();
+    task.access_inner().syscall_restart = Some((syscall_id, a0));
+}
+
+/// 事件发生时通过发送信号进行通知
+pub const SIGEV_SIGNAL: i32 = 0;
+/// 事件发生时不进行任何通知
+pub const SIGEV_NONE: i32 = 1;
+/// 事件发生时创建一个新的线程进行通知，需要 libc 的配合
+pub const SIGEV_THREAD: i32 = 2;
+/// 事件发生时向 `sigev_notify_thread_id` 指定的线程发送信号
+pub const SIGEV_THREAD_ID: i32 = 4;
+
+/// 异步事件的通知方式，与 Linux 中的 `struct sigevent` 保持一致
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SigEvent {
+    /// 随通知一起传递的数据
+    pub sigev_value: usize,
+    /// 用于通知的信号
+    pub sigev_signo: i32,
+    /// 通知方式，取值为 `SIGEV_*`
+    pub sigev_notify: i32,
+    /// `SIGEV_THREAD_ID` 方式下接收信号的线程
+    pub sigev_notify_thread_id: i32,
+    __pad: [i32; 11],
 }
 
 /// 一个系统调用，用于获取或修改与指定信号相关联的处理动作。
@@ -54,16 +691,17 @@ pub fn send_signal(tid: usize, signum: usize) {
 /// + `action`: 指定新的信号处理方式的指针。详情可见 [`SigAction`]。当该值为空指针时，`sigaction` 将不会修改信号的处理动作。
 /// + `old_action`: 指出原信号处理方式要保存到的位置。详情可见 [`SigAction`]。当该值为空指针时，`sigaction` 将不会保存信号的原处理动作。
 ///
-/// 函数执行成功后返回 0；若输入的 `sig` 是 `SIGSTOP`, `SIGKILL`, `ERR`中的一个时，将导致函数返回 `EINVAL`。
+/// 函数执行成功后返回 0；若输入的 `sig` 不是合法的信号，或者试图修改 `SIGSTOP`, `SIGKILL` 的处理动作时，将导致函数返回 `EINVAL`。
 #[syscall_func(134)]
 pub fn sigaction(sig: usize, action: usize, old_action: usize) -> isize {
     let action = action as *const SigAction;
     let old_action = old_action as *mut SigAction;
     // check whether sig is valid
-    let signum = SignalNumber::try_from(sig as u8).unwrap();
-    if signum == SignalNumber::SIGSTOP
-        || signum == SignalNumber::SIGKILL
-        || signum == SignalNumber::ERR
+    if sig == 0 || sig > SIGRTMAX {
+        return LinuxErrno::EINVAL as isize;
+    }
+    if !action.is_null()
+        && (sig == SignalNumber::SIGSTOP as usize || sig == SignalNumber::SIGKILL as usize)
     {
         return LinuxErrno::EINVAL as isize;
     }
@@ -74,13 +712,13 @@ pub fn sigaction(sig: usize, action: usize, old_action: usize) -> isize {
     if !old_action.is_null() {
         let mut tmp = SigAction::empty();
         signal_handler.get_action(sig, &mut tmp);
-        warn!("get sig {:?} old action is {:?}", signum, tmp);
+        warn!("get sig {} old action is {:?}", sig, tmp);
         task_inner.copy_to_user(&tmp, old_action);
     }
     if !action.is_null() {
         let mut tmp_action = SigAction::empty();
         task_inner.copy_from_user(action, &mut tmp_action);
-        warn!("set sig {:?} action is {:?}", signum, tmp_action);
+        warn!("set sig {} action is {:?}", sig, tmp_action);
         signal_handler.set_action(sig, &tmp_action);
     }
     0
@@ -89,76 +727,65 @@ pub fn sigaction(sig: usize, action: usize, old_action: usize) -> isize {
 /// 一个系统调用，用于使得一个进程在一段时间限制内等待一个信号，并保存信号的相关信息。
 ///
 /// 参数：
-/// + `set`: 用于指明等待的信号集，当进程接收到 `set` 中的任一一种信号时，都会返回。
+/// + `set`: 指向等待的信号集，当进程接收到 `set` 中的任一一种信号时，都会返回。等待期间 `set` 中的信号会被临时解除屏蔽。
 /// + `info`: 用于指明保存信号相关信息的位置。 当该值为空时，将不执行保存信号信息的操作。具体可见 [`SigInfo`] 结构。
-/// + `time`: 指明等待的时间。具体可见 [`TimeSpec`] 结构。
+/// + `time`: 指明等待的时间。具体可见 [`TimeSpec`] 结构。当该值为空时，将一直等待。
 ///
-/// 当函数在规定的时间内成功接收到 `set` 中包含的某个信号时，将会返回该信号的序号；
+/// 当函数在规定的时间内成功接收到 `set` 中包含的某个信号时，将会返回该信号的序号，该信号不会再被信号处理函数处理；
 /// 当函数在规定的时间内未接收到 `set` 中包含的某个信号时，将返回 `EAGAIN` 表示超时；
-/// 如果 `time` 所指明的时间为 0，那么函数将直接返回-1。
+/// 等待被 `set` 之外的信号打断时返回 `EINTR`。
 ///
 /// Reference: [sigtimedwait](https://linux.die.net/man/2/sigtimedwait)
 #[syscall_func(137)]
-pub fn sigtimewait(set: usize, info: usize, time: usize) -> isize {
+pub fn sigtimewait(set: usize, info: usize, time: usize) -> AlienResult<isize> {
     warn!(
         "sigtimewait: set: {:x}, info: {:x}, time: {:x}",
         set, info, time
     );
-
-    let mut flag = false;
-    let mut target_time = 0;
-
     let task = current_task().unwrap().clone();
-    let mut time_spec = TimeSpec::new(0, 0);
+    let mut wait_set = 0usize;
     task.access_inner()
-        .copy_from_user(time as *const TimeSpec, &mut time_spec);
-    loop {
-        let mut task_inner = task.access_inner();
-        let mut signal_receivers = task_inner.signal_receivers.lock();
-        for i in 1..64 {
-            if set & (1 << i) != 0 {
-                if signal_receivers.check_signal(i) {
-                    if info != 0 {
-                        let mut tmp_info = SigInfo::default();
-                        tmp_info.si_signo = i as i32;
-                        tmp_info.si_code = 0;
-                        drop(signal_receivers);
-                        task_inner.copy_to_user(&tmp_info, info as *mut SigInfo);
-                    }
-                    return i as isize;
-                }
-            }
-        }
-
-        // wait time
-        if time_spec.tv_sec == 0 && time_spec.tv_nsec == 0 {
-            return -1;
-        }
-        drop(signal_receivers);
-        drop(task_inner);
-        if !flag {
-            warn!("sigtimewait: sleep for {:?}", time_spec);
-            let t_time = read_timer() + time_spec.to_clock();
-            target_time = t_time;
-            flag = true;
+        .copy_from_user(set as *const usize, &mut wait_set);
+    wait_set &= !unblockable();
+    let deadline = if time == 0 {
+        None
+    } else {
+        let mut time_spec = TimeSpec::new(0, 0);
+        task.access_inner()
+            .copy_from_user(time as *const TimeSpec, &mut time_spec);
+        Some(read_timer() + time_spec.to_clock())
+    };
+    let target = get_target_from_tid(task.get_tid() as usize).ok_or(LinuxErrno::ESRCH)?;
+    // 等待期间临时解除对 set 中信号的屏蔽，使得这些信号能够打断睡眠
+    let old_mask = {
+        let mut receivers = target.receivers.lock();
+        let old_mask = receivers.mask.bits();
+        receivers.mask = SimpleBitSet::from(old_mask & !wait_set);
+        old_mask
+    };
+    let mut interrupted = false;
+    let res = loop {
+        if let Some(sig_info) = dequeue_signal_in(&target, wait_set) {
+            break Ok(sig_info);
         }
-        let now = read_timer();
-        if now >= target_time {
-            warn!("sigtimewait: timeout");
-            break;
+        if interrupted {
+            break Err(LinuxErrno::EINTR);
         }
-        do_suspend();
-        let task = current_task().unwrap();
-
-        // interrupt by signal
-        let task_inner = task.access_inner();
-        let receiver = task_inner.signal_receivers.lock();
-        if receiver.have_signal() {
-            let sig = receiver.have_signal_with_number().unwrap();
-            return sig as isize;
+        match sleep_until(deadline) {
+            Ok(()) => {
+                warn!("sigtimewait: timeout");
+                break Err(LinuxErrno::EAGAIN);
+            }
+            Err(_) => interrupted = true,
         }
+    };
+    target.receivers.lock().mask = SimpleBitSet::from(old_mask);
+    let sig_info = res?;
+    if info != 0 {
+        task.access_inner()
+            .copy_to_user(&sig_info, info as *mut SigInfo);
     }
-    LinuxErrno::EAGAIN.into()
+    Ok(sig_info.si_signo as isize)
 }
 
 /// 一个系统调用，用于获取和设置信号的屏蔽位。通过 `sigprocmask`，进程可以方便的屏蔽某些信号。
@@ -169,7 +796,7 @@ pub fn sigtimewait(set: usize, info: usize, time: usize) -> isize {
 /// + `oldset`: 用于获取当前对信号的屏蔽位。具体可见 [`SimpleBitSet`]。当该值为 null 时，将不保存信号的旧屏蔽位。
 /// + `_sig_set_size`: 用于指示 `set` 和 `oldset` 所指向的信号屏蔽位的长度，目前在 Alien 中未使用。
 ///
-/// 函数正常执行后，返回 0。
+/// `SIGKILL` 与 `SIGSTOP` 不能被屏蔽，`set` 中的这两个信号会被忽略。函数正常执行后，返回 0。
 ///
 /// Reference: [sigprocmask](https://www.man7.org/linux/man-pages/man2/sigprocmask.2.html)
 #[syscall_func(135)]
@@ -181,19 +808,21 @@ pub fn sigprocmask(how: usize, set: usize, oldset: usize, _sig_set_size: usize)
         let set_mut = task_inner.transfer_raw_ptr_mut(oldset as *mut usize);
         *set_mut = signal_receivers.mask.bits();
     }
-    let how = SigProcMaskHow::try_from(how).unwrap();
+    let Ok(how) = SigProcMaskHow::try_from(how) else {
+        return LinuxErrno::EINVAL as isize;
+    };
     warn!("sigprocmask: how: {:?}, set: {:x}", how, set);
     if set != 0 {
-        let set = task_inner.transfer_raw_ptr(set as *const usize);
+        let set = *task_inner.transfer_raw_ptr(set as *const usize) & !unblockable();
         match how {
             SigProcMaskHow::SigBlock => {
-                signal_receivers.mask += SimpleBitSet::from(*set);
+                signal_receivers.mask += SimpleBitSet::from(set);
             }
             SigProcMaskHow::SigUnblock => {
-                signal_receivers.mask -= SimpleBitSet::from(*set);
+                signal_receivers.mask -= SimpleBitSet::from(set);
             }
             SigProcMaskHow::SigSetMask => {
-                signal_receivers.mask = SimpleBitSet::from(*set);
+                signal_receivers.mask = SimpleBitSet::from(set);
             }
         }
     }
@@ -207,34 +836,66 @@ pub fn sigprocmask(how: usize, set: usize, oldset: usize, _sig_set_size: usize)
 ///
 /// pid 有如下情况
 /// 1. pid > 0，则发送给指定进程
-/// 2. pid = 0，则发送给所有同组进程
-/// 3. pid = -1，则发送给除了初始进程(pid=1)外的所有当前进程有权限的进程
-/// 4. pid < -2，则发送给组内 pid 为参数相反数的进程
+/// 2. pid = 0，则发送给与当前进程在同一进程组中的所有进程
+/// 3. pid = -1，则发送给除了初始进程(pid=1)与当前进程外的所有进程
+/// 4. pid < -1，则发送给进程组 `-pid` 中的所有进程，libc 中的 `killpg` 通过这种方式实现
+///
+/// 其中的 pid 均为当前进程所在的 PID 命名空间中的 id，其它命名空间中的进程不可见。
 ///
-/// 目前 2/3/4 未实现。对于 1，仿照 zCore 的设置，认为**当前进程自己或其直接子进程** 是"有权限"或者"同组"的进程。
+/// 在实现多用户权限前，认为当前进程有权限向所有进程发送信号。
+/// `sig` 为 0 时不发送信号，只检查进程是否存在。信号附带的 `si_pid` 为发送者的 pid。
 ///  
-/// 目前如果函数成功执行后会返回0；否则返回错误类型。
+/// 函数成功执行后会返回0；没有找到任何目标进程时返回 `ESRCH`。
 ///
 /// Reference: [kill](https://man7.org/linux/man-pages/man2/kill.2.html)
 #[syscall_func(129)]
-pub fn kill(pid: usize, sig: usize) -> isize {
-    warn!(
-        "kill pid {}, signal id {:?}",
-        pid,
-        SignalNumber::try_from(sig as u8).unwrap()
-    );
+pub fn kill(pid: isize, sig: usize) -> AlienResult<isize> {
+    warn!("kill pid {}, signal id {}", pid, sig);
+    if sig > SIGRTMAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let sender = task.get_pid() as usize;
     if pid > 0 {
-        //println!("kill pid {}, signal id {}", pid, signal_id);
-        if sig > 0 {
-            send_signal(pid, sig);
+        let pid = find_vpid(pid as usize).ok_or(LinuxErrno::ESRCH)?;
+        if sig == 0 {
+            return get_target_from_tid(pid).map(|_| 0).ok_or(LinuxErrno::ESRCH);
         }
-        0
-    } else if pid == 0 {
-        LinuxErrno::ESRCH as isize
-    } else {
-        // 如果 signal_id == 0，则仅为了检查是否存在对应进程，此时应该返回参数错误。是的，用户库是会刻意触发这个错误的
-        LinuxErrno::EINVAL as isize
+        send_signal_info(pid, SigInfo::new(sig, SI_USER).with_sender(sender, 0))?;
+        return Ok(0);
+    }
+    let targets = match pid {
+        0 => process_group(task.pgid()),
+        -1 => {
+            // 只能向当前 PID 命名空间中可见的进程发送信号，并且不包括命名空间中的 1 号进程
+            let pid_ns = task.access_inner().ns.pid.clone();
+            processes()
+                .into_iter()
+                .filter(|target| {
+                    let id = pid_ns.local_id(target.get_pid() as usize);
+                    id.is_some() && id != Some(1) && target.get_pid() as usize != sender
+                })
+                .collect()
+        }
+        _ => process_group(find_vpid((-pid) as usize).ok_or(LinuxErrno::ESRCH)?),
+    };
+    if targets.is_empty() {
+        return Err(LinuxErrno::ESRCH);
+    }
+    if sig != 0 {
+        targets.iter().for_each(|target| {
+            let info = SigInfo::new(sig, SI_USER).with_sender(sender, 0);
+            let _ = send_signal_info(target.get_pid() as usize, info);
+        });
     }
+    Ok(0)
+}
+
+/// 向进程组 `pgid` 中的所有进程发送一个由内核产生的信号，用于终端向前台进程组发送 SIGINT、SIGTSTP 等信号
+pub fn kill_pgrp(pgid: usize, signum: usize) {
+    process_group(pgid)
+        .iter()
+        .for_each(|target| send_signal(target.get_pid() as usize, signum));
 }
 
 /// 一个系统调用函数，向 `tid` 指定的线程发送信号。在`Alien`中`tid`是task的唯一标识，故 `tid` 只会指向一个线程。
@@ -243,187 +904,323 @@ pub fn kill(pid: usize, sig: usize) -> isize {
 ///
 /// Reference: [tkill](https://man7.org/linux/man-pages/man2/tkill.2.html)
 #[syscall_func(130)]
-pub fn tkill(tid: usize, sig: usize) -> isize {
-    warn!(
-        "tkill tid {}, signal id {:?}",
-        tid,
-        SignalNumber::try_from(sig as u8).unwrap()
-    );
-    if tid > 0 && sig > 0 {
-        //println!("kill pid {}, signal id {}", pid, signal_id);
-        send_signal(tid, sig);
-        0
-    } else {
-        // 如果 signal_id == 0，则仅为了检查是否存在对应进程，此时应该返回参数错误。是的，用户库是会刻意触发这个错误的
-        LinuxErrno::EINVAL as isize
+pub fn tkill(tid: usize, sig: usize) -> AlienResult<isize> {
+    warn!("tkill tid {}, signal id {}", tid, sig);
+    if tid == 0 || sig > SIGRTMAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let tid = find_vpid(tid).ok_or(LinuxErrno::ESRCH)?;
+    if sig == 0 {
+        return get_target_from_tid(tid).map(|_| 0).ok_or(LinuxErrno::ESRCH);
+    }
+    let sender = current_task().unwrap().get_pid() as usize;
+    send_signal_info(tid, SigInfo::new(sig, SI_TKILL).with_sender(sender, 0))?;
+    Ok(0)
+}
+
+/// 一个系统调用函数，向 `pid` 指定的进程发送信号 `sig`，信号附带的信息由 `uinfo` 指向的 [`SigInfo`] 给出。
+/// libc 中的 `sigqueue` 通过该系统调用实现，实时信号的每一次发送都会排队，并由信号处理函数依次处理。
+///
+/// 为了防止伪造由内核发送的信号，向其它进程发送 `si_code` 不小于 0 或为 `SI_TKILL` 的信号时返回 `EPERM`；
+/// 实时信号排队的数量达到上限时返回 `EAGAIN`。
+///
+/// Reference: [rt_sigqueueinfo](https://man7.org/linux/man-pages/man2/rt_sigqueueinfo.2.html)
+#[syscall_func(138)]
+pub fn rt_sigqueueinfo(pid: usize, sig: usize, uinfo: usize) -> AlienResult<isize> {
+    let pid = find_vpid(pid).ok_or(LinuxErrno::ESRCH)?;
+    queue_signal_info(pid, sig, uinfo)
+}
+
+/// 一个系统调用函数，与 [`rt_sigqueueinfo`] 相同，但信号发送给线程组 `tgid` 中的线程 `tid`。
+#[syscall_func(240)]
+pub fn rt_tgsigqueueinfo(_tgid: usize, tid: usize, sig: usize, uinfo: usize) -> AlienResult<isize> {
+    let tid = find_vpid(tid).ok_or(LinuxErrno::ESRCH)?;
+    queue_signal_info(tid, sig, uinfo)
+}
+
+fn queue_signal_info(tid: usize, sig: usize, uinfo: usize) -> AlienResult<isize> {
+    if sig == 0 || sig > SIGRTMAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let mut info = SigInfo::new(sig, SI_QUEUE);
+    task.access_inner()
+        .copy_from_user(uinfo as *const SigInfo, &mut info);
+    if (info.si_code >= 0 || info.si_code == SI_TKILL) && tid != task.get_pid() as usize {
+        return Err(LinuxErrno::EPERM);
     }
+    info.si_signo = sig as i32;
+    send_signal_info(tid, info)?;
+    Ok(0)
 }
 
-/// 一个系统调用函数，用于在用户态执行完信号处理函数后重新装回原 trap 上下文，一般不会被用户态程序调用。函数返回原 trap 上下文的 a0。
+/// 一个系统调用函数，用于在用户态执行完信号处理函数后恢复进入信号处理函数前的上下文，一般不会被用户态程序调用。
+///
+/// 上下文保存在用户栈上的信号帧中，具体可见 [`UContext`]。信号处理函数返回时栈指针指向信号帧，
+/// 因此嵌套执行的信号处理函数会依次恢复各自的上下文。函数返回原上下文的 a0。
 #[syscall_func(139)]
 pub fn signal_return() -> isize {
     let task = current_task().unwrap();
     let mut task_inner = task.access_inner();
-    let a0 = task_inner.load_trap_frame();
-    a0
+    let trap_frame = task_inner.trap_frame();
+    let frame = trap_frame.regs()[2];
+    let mut uc = UContext::empty();
+    task_inner.copy_from_user((frame + size_of::<SigInfo>()) as *const UContext, &mut uc);
+    trap_frame.regs()[1..].copy_from_slice(&uc.uc_mcontext.gregs[1..]);
+    trap_frame.set_sepc(uc.uc_mcontext.gregs[0]);
+    let fpregs = &uc.uc_mcontext.fpregs;
+    for (f, value) in trap_frame.fregs().iter_mut().zip(fpregs) {
+        *f = *value as usize;
+    }
+    trap_frame.set_fcsr(fpregs[FCSR_INDEX] as u32 as usize);
+    task_inner.signal_receivers.lock().mask = SimpleBitSet::from(uc.uc_sigmask & !unblockable());
+    // 恢复进入信号处理函数前的备用信号栈，正在备用信号栈上执行时不能修改
+    let sp = trap_frame.regs()[2];
+    if !on_sigaltstack(&task_inner.ss_stack, sp) {
+        let mut ss_stack = uc.uc_stack;
+        ss_stack.ss_flags = (uc.uc_stack.ss_flags as u32 & SS_DISABLE) as _;
+        task_inner.ss_stack = ss_stack;
+    }
+    warn!(
+        "sig return to pc = {:#x}, sp = {:#x}",
+        trap_frame.sepc(),
+        sp
+    );
+    trap_frame.regs()[10] as isize // old arg0
 }
 
 /// 信号处理函数。该函数在进程即将从内核态回到用户态时被调用，用于处理当前进程所接收到的信号。
 ///
 /// 进行信号处理的前提:
 /// 1. 有要处理的信号；
-/// 2. 该信号目前没有被该进程屏蔽；
-/// 3. 该信号没有被当前正在处理的信号屏蔽。
+/// 2. 该信号目前没有被该进程屏蔽。
 ///
-/// 当进入 `signal_handler` 后，对于该进程 `signal_receivers` 下所有信号种类开始遍历：
-/// 先检查此种信号是否满足上面所有的前提，如果有一项以上不满足，直接continue;
-/// 否则需要根据该信号是否已经设置非默认的处理函数进行接下来的操作。
+/// 函数会依次取出没有被屏蔽的信号，根据该信号是否已经设置非默认的处理函数进行接下来的操作。
 ///
-/// + 对于一些固定采用采用默认信号处理方式的信号，或由于未设置其它信号处理函数的信号，仍然使用默认信号处理方式，Alien 中采用 [`SigActionDefault`] 对该信号进行判定：
-///     + 如果属于 `Terminate` 类型，将导致进程终止。
-///     + 如果属于 `Ignore` 类型，进程将直接忽略该信号。
-/// + 如果进程已经设置过信号处理函数，由于信号处理函数的位置位于用户虚拟内存空间，需要回到用户态下进行信号处理函数的执行，
-/// 但由于原来在用户态下我们还保存有一个 trap 上下文，因此我们需要记录这个 trap 上下文，同时将设计好的新的执行信号处理函数的上下文转移至原trap上下文的位置，
-/// 以便其执行用户态下的信号处理函数。
+/// + 对于 `SIGKILL`、`SIGSTOP` 以及未设置其它信号处理函数的信号，使用默认信号处理方式，Alien 中采用 [`SigActionDefault`] 对该信号进行判定：
+///     + 如果属于 `Terminate` 或 `CoreDump` 类型，将导致进程终止，`CoreDump` 类型还会通过 [`do_coredump`] 生成 core 文件。
+///     + 如果属于 `Stop` 类型，进程将被暂停，直到收到 `SIGCONT` 或 `SIGKILL`。
+///     + 如果属于 `Ignore` 或 `Continue` 类型，进程将直接忽略该信号。
+/// + 如果进程已经设置过信号处理函数，由于信号处理函数的位置位于用户虚拟内存空间，需要回到用户态下进行信号处理函数的执行。
+/// 此时会在用户栈(设置了 `SA_ONSTACK` 时为备用信号栈)上放置一个信号帧，其中包含信号的 [`SigInfo`] 与原上下文 [`UContext`]，
+/// 然后修改 trap 上下文使其跳转至信号处理函数。信号处理函数执行期间会屏蔽该信号(设置了 `SA_NODEFER` 时除外)与 `sa_mask` 中的信号，
+/// 其它信号仍然可以打断信号处理函数，在其之上嵌套执行。
 ///
-/// 待用户态下的信号处理函数执行完毕后进程将重新陷入内核态，调用 [`signal_return`] 重新装载回原 trap 上下文。
+/// 待用户态下的信号处理函数执行完毕后进程将重新陷入内核态，调用 [`signal_return`] 从信号帧中恢复原上下文。
 /// 至此，一个信号被处理完毕。
+///
+/// 被信号打断的系统调用，在信号被忽略或者信号处理函数设置了 `SA_RESTART` 时会重新执行，否则返回 `EINTR`。
 pub fn signal_handler() {
     let task = current_task().unwrap();
-    let mut task_inner = task.access_inner();
-    let receiver = task_inner.signal_receivers.clone();
-    let mut receiver = receiver.lock();
-    let handler = task_inner.signal_handlers.clone();
-    let handler = handler.lock();
-    if let Some(signum) = receiver.get_one_signal() {
-        let sig = SignalNumber::try_from(signum as u8).unwrap();
-        error!("task {:?} receive signal {:?}", task.tid, sig);
-        match sig {
-            SignalNumber::SIGSEGV | SignalNumber::SIGBUS => {
-                // we need exit the process
-                drop(task_inner);
-                drop(handler);
-                drop(receiver);
-                warn!("task {:?} exit by signal {:?}", task.tid, sig);
-                do_exit(-1, 0);
-            }
-            _ => {
-                if let Some(action) = handler.get_action_ref(signum) {
-                    // we find the handler
-                    if action.is_ignore() {
-                        return;
-                    }
-                    warn!("find handler for signal {:?}", sig);
-                    if !task_inner.save_trap_frame() {
-                        // we are in signal handler,don't nest
-                        return;
-                    }
-                    // save the trap context
-                    let trap_contex = task_inner.trap_frame();
-                    // modify trap context
-                    // set ra to save user's stack
-                    trap_contex.regs()[1] = action.get_restorer();
-                    //
-                    let old_pc = trap_contex.sepc();
-                    trap_contex.set_sepc(action.handler);
-                    // a0 ==signum
-                    trap_contex.regs()[10] = signum;
-                    assert_eq!(trap_contex.regs()[10], signum);
-
-                    warn!(
-                        "task {:?} handle signal {:?} at {:#x}, old pc: {:#x}, old_sp: {:#x}",
-                        task.tid,
-                        sig,
-                        trap_contex.sepc(),
-                        old_pc,
-                        trap_contex.regs()[2]
-                    );
-                    let mut sp = trap_contex.regs()[2] - 0x200; // 128
-                    if action.flags.contains(SigActionFlags::SA_SIGINFO) {
-                        task_inner.signal_set_siginfo = true;
-                        // 如果带 SIGINFO，则需要在用户栈上放额外的信息
-                        sp = (sp - size_of::<SigInfo>()) & !0xf;
-                        info!("add siginfo at {:x}", sp);
-                        let mut info = SigInfo::default();
-                        info.si_signo = signum as i32;
-                        unsafe {
-                            let phy_sp = task_inner.transfer_raw(sp);
-                            *(phy_sp as *mut SigInfo) = info;
-                        }
-                        // a1 = &siginfo
-                        trap_contex.regs()[11] = sp;
-                        sp = (sp - size_of::<SignalUserContext>()) & !0xf;
-                        info!("add ucontext at {:x}", sp);
-                        unsafe {
-                            let phy_sp = task_inner.transfer_raw(sp);
-                            *(phy_sp as *mut SignalUserContext) =
-                                SignalUserContext::init(receiver.mask.bits() as u64, old_pc);
-                        }
-                        // a2 = &ucontext
-                        trap_contex.regs()[12] = sp;
-                    }
-                    // set sp
-                    trap_contex.regs()[2] = sp;
+    let Some(target) = get_target_from_tid(task.get_tid() as usize) else {
+        return;
+    };
+    let restart = task.access_inner().syscall_restart.take();
+    // 线程组中的其它线程收到了停止信号，被打断的系统调用在继续执行后重新执行
+    let mut handled = join_group_stop(task, &target);
+    while let Some(info) = dequeue_signal(&target) {
+        handled = true;
+        let signum = info.signum();
+        error!("task {:?} receive signal {}", task.tid, signum);
+        let mut action = SigAction::empty();
+        task.access_inner()
+            .signal_handlers
+            .lock()
+            .get_action(signum, &mut action);
+        let uncatchable =
+            signum == SignalNumber::SIGKILL as usize || signum == SignalNumber::SIGSTOP as usize;
+        if uncatchable || action.handler == SIG_DFL {
+            // 查找默认处理方式
+            match SigActionDefault::of_signal(signum) {
+                SigActionDefault::Terminate => {
+                    warn!("task {:?} exit by signal {}", task.tid, signum);
+                    do_exit_by_signal(signum, false);
+                }
+                SigActionDefault::CoreDump => {
                     warn!(
-                        "task {:?} handle signal {:?}, pc:{:#x}, sp:{:#x}",
-                        task.tid,
-                        sig,
-                        trap_contex.sepc(),
-                        trap_contex.regs()[2]
+                        "task {:?} exit by signal {} and dump core",
+                        task.tid, signum
                     );
-                } else {
-                    // find the default handler
-                    // 否则，查找默认处理方式
-                    match SigActionDefault::of_signal(sig) {
-                        SigActionDefault::Terminate => {
-                            // 这里不需要 drop(task)，因为当前函数没有用到 task_inner，在 task.save_trap... 内部用过后已经 drop 了
-                            drop(task_inner);
-                            drop(handler);
-                            drop(receiver);
-                            do_exit(-1, 0);
-                        }
-                        SigActionDefault::Ignore => {
-                            // 忽略信号时，要将已保存的上下文删除
-                            warn!("ignore signal {:?}", sig);
-                        }
+                    let core_dumped = do_coredump(task, &info);
+                    do_exit_by_signal(signum, core_dumped);
+                }
+                SigActionDefault::Stop => {
+                    // 孤儿进程组中的进程不会被终端产生的停止信号暂停
+                    if signum != SignalNumber::SIGSTOP as usize && is_orphaned_pgrp(task.pgid()) {
+                        warn!("discard signal {} in orphaned process group", signum);
+                    } else {
+                        do_signal_stop(task, &target, signum)
                     }
                 }
+                SigActionDefault::Ignore | SigActionDefault::Continue => {
+                    warn!("ignore signal {}", signum);
+                }
             }
+        } else if action.is_ignore() {
+            warn!("ignore signal {}", signum);
+        } else {
+            warn!("find handler for signal {}", signum);
+            setup_signal_frame(task, &info, &action, restart);
+            return;
         }
     }
+    // 没有进入用户的信号处理函数，sigsuspend 等系统调用临时设置的信号屏蔽字需要恢复
+    let mut task_inner = task.access_inner();
+    if let Some(mask) = task_inner.saved_sigmask.take() {
+        task_inner.signal_receivers.lock().mask = SimpleBitSet::from(mask);
+    }
+    // 打断系统调用的信号都被忽略了，重新执行该系统调用
+    if let (true, Some((_, a0))) = (handled, restart) {
+        restart_syscall(task_inner.trap_frame(), a0);
+    }
 }
 
-/// 一个系统调用函数，用于阻塞当前进程，等待其他进程传入信号打断阻塞。当进程接收到某种信号时，终止阻塞，函数返回 `EINTR`。
-#[syscall_func(133)]
-pub fn sigsuspend() -> isize {
-    loop {
-        do_suspend();
-        let task = current_task().unwrap();
-        // interrupt by signal
-        let task_inner = task.access_inner();
-        let receiver = task_inner.signal_receivers.lock();
-        if receiver.have_signal() {
-            return LinuxErrno::EINTR.into();
+/// 在用户栈上放置信号帧，并修改 trap 上下文使得回到用户态时执行信号 `info` 的处理函数 `action`
+fn setup_signal_frame(
+    task: &Arc<Task>,
+    info: &SigInfo,
+    action: &SigAction,
+    restart: Option<(usize, usize)>,
+) {
+    let signum = info.signum();
+    let mut task_inner = task.access_inner();
+    let trap_frame = task_inner.trap_frame();
+    if let Some((syscall_id, a0)) = restart {
+        if action.flags.contains(SigActionFlags::SA_RESTART)
+            && !NO_RESTART_SYSCALLS.contains(&syscall_id)
+        {
+            restart_syscall(trap_frame, a0);
         }
     }
+    let receivers = task_inner.signal_receivers.clone();
+    let mut receivers = receivers.lock();
+    // sigsuspend 等系统调用临时修改了信号屏蔽字时，信号处理函数返回后应该恢复原来的屏蔽字
+    let old_mask = task_inner
+        .saved_sigmask
+        .take()
+        .unwrap_or(receivers.mask.bits());
+    let sp = trap_frame.regs()[2];
+    let ss_stack = task_inner.ss_stack;
+    let on_stack = on_sigaltstack(&ss_stack, sp);
+    let stack_top = if action.flags.contains(SigActionFlags::SA_ONSTACK)
+        && ss_stack.ss_flags as u32 & SS_DISABLE == 0
+        && !on_stack
+    {
+        ss_stack.ss_sp + ss_stack.ss_size
+    } else {
+        sp
+    };
+    let frame_addr = (stack_top - size_of::<SigFrame>()) & !0xf;
+    let mut frame = SigFrame {
+        info: *info,
+        uc: UContext::empty(),
+    };
+    frame.uc.uc_stack = ss_stack;
+    if on_stack {
+        frame.uc.uc_stack.ss_flags = SS_ONSTACK as _;
+    }
+    frame.uc.uc_sigmask = old_mask;
+    frame
+        .uc
+        .uc_mcontext
+        .gregs
+        .copy_from_slice(trap_frame.regs());
+    frame.uc.uc_mcontext.gregs[0] = trap_frame.sepc();
+    let fpregs = &mut frame.uc.uc_mcontext.fpregs;
+    for (value, f) in fpregs.iter_mut().zip(trap_frame.fregs().iter()) {
+        *value = *f as u64;
+    }
+    fpregs[FCSR_INDEX] = trap_frame.fcsr() as u32 as u64;
+    task_inner.copy_to_user(&frame, frame_addr as *mut SigFrame);
+    // 信号处理函数执行期间屏蔽 sa_mask 中的信号，没有设置 SA_NODEFER 时还需要屏蔽该信号本身
+    let mut mask = receivers.mask.bits() | action.mask;
+    if !action.flags.contains(SigActionFlags::SA_NODEFER) {
+        mask |= sig_bit(signum);
+    }
+    receivers.mask = SimpleBitSet::from(mask & !unblockable());
+    drop(receivers);
+    if action.flags.contains(SigActionFlags::SA_RESETHAND) {
+        task_inner
+            .signal_handlers
+            .lock()
+            .set_action(signum, &SigAction::empty());
+    }
+    // ra 指向 sigreturn 的入口，信号处理函数返回时回到内核恢复上下文
+    trap_frame.regs()[1] = action.get_restorer();
+    trap_frame.regs()[2] = frame_addr;
+    // a0 = signum, a1 = &siginfo, a2 = &ucontext
+    trap_frame.regs()[10] = signum;
+    trap_frame.regs()[11] = frame_addr;
+    trap_frame.regs()[12] = frame_addr + size_of::<SigInfo>();
+    trap_frame.set_sepc(action.handler);
+    warn!(
+        "task {:?} handle signal {}, pc:{:#x}, sp:{:#x}, old pc: {:#x}",
+        task.tid,
+        signum,
+        trap_frame.sepc(),
+        frame_addr,
+        frame.uc.uc_mcontext.gregs[0]
+    );
+}
+
+/// 一个系统调用函数，用于将信号屏蔽字临时替换为 `mask` 指向的信号集，并阻塞当前进程，等待信号打断阻塞。
+///
+/// 当进程接收到某种信号时，终止阻塞，函数返回 `EINTR`。原来的信号屏蔽字会在信号处理函数返回后恢复。
+#[syscall_func(133)]
+pub fn sigsuspend(mask: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    {
+        let mut task_inner = task.access_inner();
+        let mut new_mask = 0usize;
+        task_inner.copy_from_user(mask as *const usize, &mut new_mask);
+        let receivers = task_inner.signal_receivers.clone();
+        let mut receivers = receivers.lock();
+        task_inner.saved_sigmask = Some(receivers.mask.bits());
+        receivers.mask = SimpleBitSet::from(new_mask & !unblockable());
+    }
+    // 没有超时，只会被信号打断
+    while sleep_until(None).is_ok() {}
+    Err(LinuxErrno::EINTR)
 }
 
 #[syscall_func(132)]
+/// 一个系统调用函数，用于设置与获取备用信号栈。设置了 `SA_ONSTACK` 的信号处理函数会在备用信号栈上执行。
+///
+/// 正在备用信号栈上执行时不能修改备用信号栈，此时返回 `EPERM`；备用信号栈小于 `MINSIGSTKSZ` 时返回 `ENOMEM`。
+///
 /// See https://man7.org/linux/man-pages/man2/sigaltstack.2.html
 pub fn sigaltstack(uss: usize, uoss: usize) -> AlienResult<isize> {
-    //println_color!(32, "sigaltstack: uss: {:x}, uoss: {:x}", uss, uoss);
     let task = current_task().unwrap();
+    let mut task_inner = task.access_inner();
+    let sp = task_inner.trap_frame().regs()[2];
+    let on_stack = on_sigaltstack(&task_inner.ss_stack, sp);
     if uoss != 0 {
-        let old_ss_stack = task.access_inner().ss_stack;
-        // println_color!(32, "get old sigaltstack: {:x?}", old_ss_stack);
-        task.access_inner()
-            .copy_to_user(&old_ss_stack, uoss as *mut SignalStack);
+        let mut old_ss_stack = task_inner.ss_stack;
+        if on_stack {
+            old_ss_stack.ss_flags = SS_ONSTACK as _;
+        }
+        task_inner.copy_to_user(&old_ss_stack, uoss as *mut SignalStack);
     }
     if uss != 0 {
+        if on_stack {
+            return Err(LinuxErrno::EPERM);
+        }
         let mut ss_stack = SignalStack::default();
-        task.access_inner().copy_from_user(uss as _, &mut ss_stack);
-        // println_color!(32, "set sigaltstack: {:x?}", ss_stack);
-        task.access_inner().ss_stack = ss_stack;
+        task_inner.copy_from_user(uss as _, &mut ss_stack);
+        let flags = ss_stack.ss_flags as u32 & !SS_AUTODISARM;
+        if flags == SS_DISABLE {
+            ss_stack.ss_sp = 0;
+            ss_stack.ss_size = 0;
+            ss_stack.ss_flags = SS_DISABLE as _;
+        } else if flags == 0 || flags == SS_ONSTACK {
+            if ss_stack.ss_size < MINSIGSTKSZ {
+                return Err(LinuxErrno::ENOMEM);
+            }
+            ss_stack.ss_flags = 0;
+        } else {
+            return Err(LinuxErrno::EINVAL);
+        }
+        task_inner.ss_stack = ss_stack;
     }
     Ok(0)
 }
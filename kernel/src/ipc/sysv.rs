@@ -0,0 +1,97 @@
+//! System V IPC 的公共定义。
+//!
+//! 共享内存 [`super::shm`]、消息队列 [`super::msg`] 与信号量集 [`super::sem`] 三种 System V IPC 对象
+//! 共用同一套权限结构 [`IpcPerm`]、控制命令以及 `*get` 系统调用中的标志位，这里统一给出它们的定义。
+use constants::time::TimeSpec;
+use timer::TimeNow;
+
+/// 如果 key 对应的 IPC 对象不存在则创建
+pub const IPC_CREAT: u32 = 0o1000;
+/// 与 `IPC_CREAT` 一起使用，如果 key 对应的 IPC 对象已经存在则返回 `EEXIST`
+pub const IPC_EXCL: u32 = 0o2000;
+/// 操作无法立即完成时不阻塞，直接返回错误
+pub const IPC_NOWAIT: u32 = 0o4000;
+
+/// 删除 IPC 对象
+pub const IPC_RMID: usize = 0;
+/// 设置 IPC 对象的权限信息
+pub const IPC_SET: usize = 1;
+/// 获取 IPC 对象的状态信息
+pub const IPC_STAT: usize = 2;
+/// 获取系统中 IPC 对象的限制信息
+pub const IPC_INFO: usize = 3;
+
+/// 部分 libc 会在 `*ctl` 的命令中附加该位，表示使用 64 位版本的数据结构
+const IPC_64: usize = 0x100;
+
+/// 系统中每类 IPC 对象的最大数量
+pub const IPC_MNI: usize = 32000;
+
+/// 去掉 `*ctl` 命令中的 `IPC_64` 标志位
+#[inline]
+pub fn ipc_cmd(cmd: usize) -> usize {
+    cmd & !IPC_64
+}
+
+/// 获取当前时间(秒)，用于更新 IPC 对象的各个时间戳
+#[inline]
+pub fn ipc_now() -> usize {
+    TimeSpec::now().tv_sec
+}
+
+/// IPC 对象的权限信息，与 Linux 中的 `struct ipc64_perm` 保持一致
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct IpcPerm {
+    /// 创建该对象时使用的键值
+    pub key: i32,
+    /// 所有者的用户 id
+    pub uid: u32,
+    /// 所有者的用户组 id
+    pub gid: u32,
+    /// 创建者的用户 id
+    pub cuid: u32,
+    /// 创建者的用户组 id
+    pub cgid: u32,
+    /// 访问权限，仅低 9 位有效
+    pub mode: u32,
+    /// 序列号
+    pub seq: u16,
+    __pad2: u16,
+    __unused1: usize,
+    __unused2: usize,
+}
+
+impl IpcPerm {
+    /// 根据键值和 `*get` 系统调用中的 flag 创建一个新的权限信息
+    pub fn new(key: usize, flag: u32) -> Self {
+        Self {
+            key: key as i32,
+            mode: flag & 0o777,
+            ..Default::default()
+        }
+    }
+
+    /// 使用 `IPC_SET` 传入的信息更新权限，只有 uid、gid 以及 mode 的低 9 位可以被修改
+    pub fn update(&mut self, other: &IpcPerm) {
+        self.uid = other.uid;
+        self.gid = other.gid;
+        self.mode = (self.mode & !0o777) | (other.mode & 0o777);
+    }
+}
+
+/// 在有序的 `ids` 中找到一个未被使用的最小 id(从 1 开始)。当 id 耗尽时返回 None。
+pub fn alloc_ipc_id<'a, I: Iterator<Item = &'a usize>>(ids: I) -> Option<usize> {
+    let mut next = 1;
+    for id in ids {
+        if *id != next {
+            break;
+        }
+        next += 1;
+    }
+    if next > IPC_MNI {
+        None
+    } else {
+        Some(next)
+    }
+}
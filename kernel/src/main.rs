@@ -51,6 +51,8 @@ fn main(hart_id: usize) {
         vfs::init_filesystem().expect("init filesystem failed");
         trap::init_trap_subsystem();
         arch::allow_access_user_memory();
+        arch::allow_user_read_time();
+        time::init_realtime();
         task::init_task();
         // register all syscall
         syscall_table::init_init_array!();
@@ -61,6 +63,7 @@ fn main(hart_id: usize) {
         }
         mem::init_memory_system(0, false);
         arch::allow_access_user_memory();
+        arch::allow_user_read_time();
         trap::init_trap_subsystem();
         println!("hart {} start", arch::hart_id());
     }
@@ -1,5 +1,8 @@
 use alloc::{string::String, vec, vec::Vec};
-use core::fmt::{Debug, Formatter};
+use core::{
+    fmt::{Debug, Formatter},
+    ops::Range,
+};
 
 use mem::VmmPageAllocator;
 use page_table::table::Sv39PageTable;
@@ -16,6 +19,8 @@ pub enum ELFError {
     NoEntrySegment,
     RelocationError,
     DynsymNotFind,
+    InterpreterNotFound,
+    NoMemory,
 }
 
 impl Debug for ELFInfo {
@@ -31,15 +36,25 @@ impl Debug for ELFInfo {
 
 pub struct ELFInfo {
     pub address_space: Sv39PageTable<VmmPageAllocator>,
+    /// 任务开始执行的地址，对于动态链接的程序为动态链接器的入口
     pub entry: usize,
+    /// 程序自身的入口 (`AT_ENTRY`)
+    pub program_entry: usize,
     pub stack_top: usize,
     pub heap_bottom: usize,
+    /// vDSO 的 ELF 头所在的地址 (`AT_SYSINFO_EHDR`)
+    pub vdso_ehdr: usize,
+    /// mmap 区域的起始位置
+    pub mmap_base: usize,
     pub ph_num: usize,
     pub ph_entry_size: usize,
     pub ph_drift: usize,
     pub tls: usize,
+    /// 动态链接器的加载基址 (`AT_BASE`)，没有动态链接器时为 0
     pub bias: usize,
     pub name: String,
+    /// 程序与动态链接器的各个 `LOAD` 段按页对齐后的地址范围
+    pub segments: Vec<Range<usize>>,
 }
 
 pub trait ELFReader {
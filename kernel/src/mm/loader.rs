@@ -4,7 +4,7 @@ use alloc::{
     vec,
     vec::Vec,
 };
-use core::{cmp::min, fmt::Debug};
+use core::{cmp::min, fmt::Debug, ops::Range, sync::atomic::Ordering};
 
 use config::*;
 use mem::{VmmPageAllocator, FRAME_REF_MANAGER};
@@ -13,19 +13,37 @@ use page_table::{
     pte::MappingFlags,
     table::Sv39PageTable,
 };
-use spin::Lazy;
-use xmas_elf::program::{SegmentData, Type};
+use vfs::{dev::random::random_usize, proc::sysctl::RANDOMIZE_VA_SPACE};
+use xmas_elf::{
+    program::{SegmentData, Type},
+    ElfFile,
+};
 
 use crate::{
     fs,
     ipc::ShmInfo,
-    mm::elf::{ELFError, ELFInfo, ELFReader},
+    mm::{
+        elf::{ELFError, ELFInfo, ELFReader},
+        vdso::map_vdso,
+    },
+    task::ADDR_NO_RANDOMIZE,
     trap::TrapFrame,
 };
 
 extern "C" {
     fn strampoline();
 }
+
+/// `AT_HWCAP` 中提供给用户程序的硬件特性，每一位对应一个单字母的 ISA 扩展 (`'A'` 对应第 0 位)，这里为 `rv64imafdc`
+pub const RISCV_HWCAP: usize = {
+    const fn ext(c: u8) -> usize {
+        1 << (c - b'A')
+    }
+    ext(b'I') | ext(b'M') | ext(b'A') | ext(b'F') | ext(b'D') | ext(b'C')
+};
+
+/// `AT_CLKTCK`，`times` 等系统调用返回的时间的单位 (每秒的时钟节拍数)
+pub const USER_CLOCK_TICKS: usize = 100;
 #[derive(Debug)]
 pub struct UserStack {
     pub virt_stack_top: usize,
@@ -94,7 +112,7 @@ impl UserStack {
 pub fn build_thread_address_space(
     table: &mut Sv39PageTable<VmmPageAllocator>,
     thread_num_within: usize,
-) -> &'static mut TrapFrame {
+) -> Option<&'static mut TrapFrame> {
     let address = TRAP_CONTEXT_BASE - FRAME_SIZE * thread_num_within;
     let (_virt_dst, phy_dst, _) = table
         .map_region_no_target(
@@ -104,9 +122,8 @@ pub fn build_thread_address_space(
             true,
             false,
         )
-        .unwrap()
-        .next()
-        .unwrap();
+        .ok()?
+        .next()?;
     // copy data
     let (phy, _flag, page_size) = table.query(VirtAddr::from(TRAP_CONTEXT_BASE)).unwrap();
     assert_eq!(usize::from(page_size), FRAME_SIZE);
@@ -116,14 +133,14 @@ pub fn build_thread_address_space(
     unsafe {
         core::ptr::copy(src_ptr, dst_ptr, usize::from(page_size));
     }
-    TrapFrame::from_raw_ptr(dst_ptr as *mut TrapFrame)
+    Some(TrapFrame::from_raw_ptr(dst_ptr as *mut TrapFrame))
 }
 
 pub fn build_cow_address_space(
     p_table: &mut Sv39PageTable<VmmPageAllocator>,
     shm: BTreeMap<usize, ShmInfo>,
-) -> Sv39PageTable<VmmPageAllocator> {
-    let mut address_space = Sv39PageTable::<VmmPageAllocator>::try_new().unwrap();
+) -> Option<Sv39PageTable<VmmPageAllocator>> {
+    let mut address_space = Sv39PageTable::<VmmPageAllocator>::try_new().ok()?;
     for (v_addr, target) in p_table.get_record().into_iter() {
         trace!("v_addr: {:?}, target: {}", v_addr, target);
         let (phy, flag, page_size) = p_table.query(v_addr).unwrap();
@@ -143,7 +160,7 @@ pub fn build_cow_address_space(
             assert_eq!(usize::from(page_size), TRAMPOLINE - TRAP_CONTEXT_BASE);
             let dst = address_space
                 .map_no_target(v_addr, page_size, flag, false)
-                .unwrap();
+                .ok()?;
             // copy data
             let src_ptr = phy.as_usize() as *const u8;
             let dst_ptr = dst.as_usize() as *mut u8;
@@ -152,14 +169,14 @@ pub fn build_cow_address_space(
             }
         } else if is_in_segs(v_addr.as_usize()) {
             // for shm, we now skip it
-            address_space.map(v_addr, phy, page_size, flag).unwrap();
+            address_space.map(v_addr, phy, page_size, flag).ok()?;
         } else {
             // cow
             // checkout whether pte flags has `W` flag
             let mut flags = flag.clone();
             if !flag.contains(MappingFlags::V) {
                 // if flags is not valid, we just map it
-                address_space.map(v_addr, phy, page_size, flags).unwrap();
+                address_space.map(v_addr, phy, page_size, flags).ok()?;
                 if target {
                     address_space.get_record_mut().insert(v_addr, true);
                 }
@@ -171,7 +188,7 @@ pub fn build_cow_address_space(
                                             // update parent's flag and clear dirty
                 p_table.modify_pte_flags(v_addr, flags, false).unwrap();
             }
-            address_space.map(v_addr, phy, page_size, flags).unwrap();
+            address_space.map(v_addr, phy, page_size, flags).ok()?;
             // add ref for alloc page
             if target {
                 for i in 0..usize::from(page_size) / FRAME_SIZE {
@@ -183,85 +200,40 @@ pub fn build_cow_address_space(
             }
         }
     }
-    address_space
+    Some(address_space)
 }
 
-static LD_MUSL_RV64_CACHE: Lazy<Vec<u8>> = Lazy::new(|| {
-    let mut data = vec![];
-    fs::read_all("/tests/libc.so", &mut data);
-    data
-});
-
-pub fn build_elf_address_space(
-    elf: &[u8],
-    args: &mut Vec<String>,
-    name: &str,
-) -> Result<ELFInfo, ELFError> {
-    let mut address_space = Sv39PageTable::<VmmPageAllocator>::try_new().unwrap();
-    const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
-    if elf[0..4] != ELF_MAGIC {
-        return Err(ELFError::NotELF);
-    }
-    let elf = xmas_elf::ElfFile::new(elf).map_err(|_| ELFError::NotELF)?;
-    // check whether it's a dynamic linked elf
-    if let Some(inter) = elf
-        .program_iter()
-        .find(|ph| ph.get_type().unwrap() == Type::Interp)
-    {
-        let data = match inter.get_data(&elf).unwrap() {
-            SegmentData::Undefined(data) => data,
-            _ => return Err(ELFError::NoEntrySegment),
-        };
-        let path = core::str::from_utf8(data).unwrap();
-        assert!(
-            path.starts_with("/lib/ld-musl-riscv64"),
-            "interpreter: {:?}",
-            path
-        );
-        let mut new_args = vec!["/tests/libc.so\0".to_string()];
-        // args.remove(0); // remove the first arg
-        new_args.extend(args.clone());
-        *args = new_args;
-        // load interpreter
-        warn!("load interpreter: {}, new_args:{:?}", path, args);
-        return build_elf_address_space(&LD_MUSL_RV64_CACHE, args, "/tests/libc.so");
+/// 根据进程的 `personality` 与 `/proc/sys/kernel/randomize_va_space` 得到生效的地址空间随机化等级
+pub fn randomize_level(personality: u32) -> usize {
+    if personality & ADDR_NO_RANDOMIZE != 0 {
+        0
+    } else {
+        RANDOMIZE_VA_SPACE.load(Ordering::Relaxed)
     }
+}
 
-    // calculate bias for dynamic linked elf
-    // if elf is static linked, bias is 0
-    let bias = match elf.header.pt2.type_().as_type() {
-        // static
-        xmas_elf::header::Type::Executable => 0,
-        xmas_elf::header::Type::SharedObject => {
-            match elf
-                .program_iter()
-                .filter(|ph| ph.get_type().unwrap() == Type::Interp)
-                .count()
-            {
-                // It's a loader!
-                0 => ELF_BASE_RELOCATE,
-                // It's a dynamically linked ELF.
-                1 => 0,
-                // Emmm, It has multiple interpreters.
-                _ => return Err(ELFError::NotSupported),
-            }
-        }
-        _ => return Err(ELFError::NotSupported),
-    };
-    trace!("bias: {:#x}", bias);
-
-    let tls = elf
-        .program_iter()
-        .find(|x| x.get_type().unwrap() == Type::Tls)
-        .map(|ph| ph.virtual_addr())
-        .unwrap_or(0);
-
-    warn!("ELF tls: {:#x}", tls);
+/// 随机化等级不低于 `level` 时，返回一个不超过 `max_pages` 页的随机偏移，否则返回 0
+fn random_offset(randomize: usize, level: usize, max_pages: usize) -> usize {
+    if randomize >= level {
+        (random_usize() % max_pages) * FRAME_SIZE
+    } else {
+        0
+    }
+}
 
+/// 将 elf 文件中所有 `LOAD` 类型的段加载到地址空间中偏移 `bias` 的位置，返回加载的最大地址。
+///
+/// 每个段按页对齐后的地址范围会被记录到 `segments` 中
+fn load_segments(
+    address_space: &mut Sv39PageTable<VmmPageAllocator>,
+    elf: &ElfFile,
+    bias: usize,
+    segments: &mut Vec<Range<usize>>,
+) -> Result<usize, ELFError> {
     let mut break_addr = 0usize;
     elf.program_iter()
         .filter(|ph| ph.get_type() == Ok(Type::Load))
-        .for_each(|ph| {
+        .try_for_each(|ph| {
             let start_addr = ph.virtual_addr() as usize + bias;
             let end_addr = start_addr + ph.mem_size() as usize;
             let mut permission: MappingFlags = "UVAD".into();
@@ -278,8 +250,9 @@ pub fn build_elf_address_space(
             let vaddr = VirtAddr::from(start_addr).align_down_4k();
             let end_vaddr = VirtAddr::from(end_addr).align_up_4k();
             // 记录程序地址空间的最大地址
-            break_addr = end_addr;
+            break_addr = break_addr.max(end_addr);
             let len = end_vaddr.as_usize() - vaddr.as_usize();
+            segments.push(vaddr.as_usize()..end_vaddr.as_usize());
             warn!(
                 "load segment: {:#x} - {:#x} -> {:#x}-{:#x}, permission: {:?}",
                 start_addr,
@@ -292,7 +265,7 @@ pub fn build_elf_address_space(
                 &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize];
             let map_info = address_space
                 .map_region_no_target(vaddr, len, permission, false, false)
-                .unwrap();
+                .map_err(|_| ELFError::NoMemory)?;
             // copy data
             let mut page_offset = start_addr & (FRAME_SIZE - 1);
             let mut count = 0;
@@ -308,10 +281,100 @@ pub fn build_elf_address_space(
                     page_offset = 0;
                 });
             assert_eq!(count, ph.file_size() as usize);
-        });
+            Ok(())
+        })?;
+    Ok(break_addr)
+}
+
+/// 获取 elf 文件的程序头表被加载到的虚拟地址 (未加上偏移)
+fn program_header_addr(elf: &ElfFile) -> usize {
+    if let Some(phdr) = elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(Type::Phdr))
+    {
+        // if phdr exists in program header, use it
+        phdr.virtual_addr() as usize
+    } else if let Some(elf_addr) = elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(Type::Load) && ph.offset() == 0)
+    {
+        // otherwise, check if elf is loaded from the beginning, then phdr can be inferred.
+        (elf_addr.virtual_addr() + elf.header.pt2.ph_offset()) as usize
+    } else {
+        warn!("elf: no phdr found, tls might not work");
+        0
+    }
+}
 
-    // 地址向上取整对齐4
-    let ceil_addr = align_up_4k(break_addr + FRAME_SIZE);
+/// 获取 elf 文件 `PT_INTERP` 段指定的解释器 (动态链接器) 的路径
+fn interpreter_path(elf: &ElfFile) -> Result<Option<String>, ELFError> {
+    let mut interps = elf
+        .program_iter()
+        .filter(|ph| ph.get_type() == Ok(Type::Interp));
+    let inter = match interps.next() {
+        Some(inter) => inter,
+        None => return Ok(None),
+    };
+    // Emmm, It has multiple interpreters.
+    if interps.next().is_some() {
+        return Err(ELFError::NotSupported);
+    }
+    let data = match inter.get_data(elf).map_err(|_| ELFError::FileBreak)? {
+        SegmentData::Undefined(data) => data,
+        _ => return Err(ELFError::NoEntrySegment),
+    };
+    let path = core::str::from_utf8(data).map_err(|_| ELFError::FileBreak)?;
+    Ok(Some(path.trim_end_matches('\0').to_string()))
+}
+
+/// 为 elf 文件构建地址空间。
+///
+/// 每个地址空间中都会映射 vDSO，见 [`map_vdso`]。
+/// 开启地址空间随机化时 (见 [`randomize_level`])，PIE 程序、动态链接器、vDSO、用户栈与 mmap 区域的位置都会加上随机的偏移，
+/// 随机化等级为 2 时堆的起始位置也会被随机化。
+pub fn build_elf_address_space(
+    elf: &[u8],
+    name: &str,
+    personality: u32,
+) -> Result<ELFInfo, ELFError> {
+    let randomize = randomize_level(personality);
+    let mut address_space =
+        Sv39PageTable::<VmmPageAllocator>::try_new().map_err(|_| ELFError::NoMemory)?;
+    const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+    if elf.len() < 4 || elf[0..4] != ELF_MAGIC {
+        return Err(ELFError::NotELF);
+    }
+    let elf = ElfFile::new(elf).map_err(|_| ELFError::NotELF)?;
+    // check whether it's a dynamic linked elf
+    let interp = interpreter_path(&elf)?;
+
+    // calculate bias for dynamic linked elf
+    // if elf is static linked, bias is 0
+    let bias = match elf.header.pt2.type_().as_type() {
+        // static
+        xmas_elf::header::Type::Executable => 0,
+        // It's a loader or a position independent executable.
+        xmas_elf::header::Type::SharedObject => {
+            ELF_BASE_RELOCATE + random_offset(randomize, 1, ELF_RANDOM_PAGES)
+        }
+        _ => return Err(ELFError::NotSupported),
+    };
+    trace!("bias: {:#x}", bias);
+
+    let tls = elf
+        .program_iter()
+        .find(|x| x.get_type().unwrap() == Type::Tls)
+        .map(|ph| ph.virtual_addr())
+        .unwrap_or(0);
+
+    warn!("ELF tls: {:#x}", tls);
+
+    let mut segments = vec![];
+    let break_addr = load_segments(&mut address_space, &elf, bias, &mut segments)?;
+
+    // 地址向上取整对齐4，并在程序与用户栈之间留出随机的间隔
+    let ceil_addr =
+        align_up_4k(break_addr + FRAME_SIZE) + random_offset(randomize, 1, STACK_RANDOM_PAGES);
     // 留出一个用户栈的位置+隔离页
     let top = ceil_addr + USER_STACK_SIZE + FRAME_SIZE;
     warn!(
@@ -328,12 +391,12 @@ pub fn build_elf_address_space(
             false,
             true,
         )
-        .unwrap();
+        .map_err(|_| ELFError::NoMemory)?;
     // 初始化一个有效页
     address_space
         .validate(VirtAddr::from(top - FRAME_SIZE * 2), "RWUVAD".into())
-        .unwrap();
-    let heap_bottom = top;
+        .map_err(|_| ELFError::NoMemory)?;
+    let heap_bottom = top + random_offset(randomize, 2, HEAP_RANDOM_PAGES);
     // align to 4k
     warn!("trap context: {:#x} - {:#x}", TRAP_CONTEXT_BASE, TRAMPOLINE);
     address_space
@@ -344,7 +407,7 @@ pub fn build_elf_address_space(
             true,
             false,
         )
-        .unwrap();
+        .map_err(|_| ELFError::NoMemory)?;
     warn!(
         "TRAMPOLINE: {:#x} - {:#x}",
         TRAMPOLINE,
@@ -358,60 +421,77 @@ pub fn build_elf_address_space(
             "RXVAD".into(),
             true,
         )
-        .unwrap();
+        .map_err(|_| ELFError::NoMemory)?;
 
-    let res = if let Some(phdr) = elf
-        .program_iter()
-        .find(|ph| ph.get_type() == Ok(Type::Phdr))
-    {
-        // if phdr exists in program header, use it
-        Ok(phdr.virtual_addr())
-    } else if let Some(elf_addr) = elf
-        .program_iter()
-        .find(|ph| ph.get_type() == Ok(Type::Load) && ph.offset() == 0)
-    {
-        // otherwise, check if elf is loaded from the beginning, then phdr can be inferred.
-        // Ok(elf_addr.virtual_addr())
-        Ok(elf_addr.virtual_addr() + elf.header.pt2.ph_offset())
-    } else {
-        warn!("elf: no phdr found, tls might not work");
-        Err(ELFError::NoEntrySegment)
-    }
-    .unwrap_or(0);
-    warn!(
-        "entry: {:#x}, phdr:{:#x}",
-        elf.header.pt2.entry_point() + bias as u64,
-        res + bias as u64
-    );
-    // relocate if elf is dynamically linked
-    if bias != 0 {
-        if let Ok(kvs) = elf.relocate_plt(bias) {
-            kvs.into_iter().for_each(|kv| {
-                trace!("relocate: {:#x} -> {:#x}", kv.0, kv.1);
-                let (addr, ..) = address_space.query(VirtAddr::from(kv.0)).unwrap();
-                unsafe { (addr.as_usize() as *mut usize).write(kv.1) }
-            });
-            info!("relocate plt done")
+    let vdso_ehdr = map_vdso(
+        &mut address_space,
+        VDSO_BASE + random_offset(randomize, 1, VDSO_RANDOM_PAGES),
+    )
+    .ok_or(ELFError::NoMemory)?;
+
+    let phdr = program_header_addr(&elf);
+    let program_entry = elf.header.pt2.entry_point() as usize + bias;
+    warn!("entry: {:#x}, phdr:{:#x}", program_entry, phdr + bias);
+
+    // load the interpreter named by PT_INTERP at a randomised base,
+    // it will relocate both itself and the program
+    let (entry, base) = if let Some(path) = interp {
+        let mut data = vec![];
+        if !fs::read_all(&path, &mut data) {
+            warn!("interpreter {} not found", path);
+            return Err(ELFError::InterpreterNotFound);
         }
-        if let Ok(kvs) = elf.relocate_dyn(bias) {
-            kvs.into_iter().for_each(|kv| {
-                trace!("relocate: {:#x} -> {:#x}", kv.0, kv.1);
-                let (addr, ..) = address_space.query(VirtAddr::from(kv.0)).unwrap();
-                unsafe { (addr.as_usize() as *mut usize).write(kv.1) }
-            });
-            info!("relocate dyn done")
+        if data.len() < 4 || data[0..4] != ELF_MAGIC {
+            return Err(ELFError::NotELF);
         }
-    }
+        let interp_elf = ElfFile::new(&data).map_err(|_| ELFError::NotELF)?;
+        if interp_elf.header.pt2.type_().as_type() != xmas_elf::header::Type::SharedObject {
+            return Err(ELFError::NotSupported);
+        }
+        let interp_base = INTERP_BASE + random_offset(randomize, 1, INTERP_RANDOM_PAGES);
+        warn!("load interpreter: {} at {:#x}", path, interp_base);
+        load_segments(&mut address_space, &interp_elf, interp_base, &mut segments)?;
+        (
+            interp_elf.header.pt2.entry_point() as usize + interp_base,
+            interp_base,
+        )
+    } else {
+        // relocate if elf is a loader
+        if bias != 0 {
+            if let Ok(kvs) = elf.relocate_plt(bias) {
+                kvs.into_iter().for_each(|kv| {
+                    trace!("relocate: {:#x} -> {:#x}", kv.0, kv.1);
+                    let (addr, ..) = address_space.query(VirtAddr::from(kv.0)).unwrap();
+                    unsafe { (addr.as_usize() as *mut usize).write(kv.1) }
+                });
+                info!("relocate plt done")
+            }
+            if let Ok(kvs) = elf.relocate_dyn(bias) {
+                kvs.into_iter().for_each(|kv| {
+                    trace!("relocate: {:#x} -> {:#x}", kv.0, kv.1);
+                    let (addr, ..) = address_space.query(VirtAddr::from(kv.0)).unwrap();
+                    unsafe { (addr.as_usize() as *mut usize).write(kv.1) }
+                });
+                info!("relocate dyn done")
+            }
+        }
+        // 没有动态链接器时 AT_BASE 为 0
+        (program_entry, 0)
+    };
     Ok(ELFInfo {
         address_space,
-        entry: elf.header.pt2.entry_point() as usize + bias,
+        entry,
+        program_entry,
         stack_top: top - FRAME_SIZE,
         heap_bottom,
+        vdso_ehdr,
+        mmap_base: PROCESS_HEAP_MAX + random_offset(randomize, 1, MMAP_RANDOM_PAGES),
         ph_num: elf.header.pt2.ph_count() as usize,
         ph_entry_size: elf.header.pt2.ph_entry_size() as usize,
-        ph_drift: res as usize + bias,
+        ph_drift: phdr + bias,
         tls: tls as usize + bias,
-        bias,
+        bias: base,
         name: name.to_string(),
+        segments,
     })
 }
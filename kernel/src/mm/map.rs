@@ -73,6 +73,14 @@ impl MMapInfo {
         }
     }
 
+    /// 创建一个 mmap 区域从 `map_start` 开始的 [`MMapInfo`]，用于对 mmap 区域的起始位置进行随机化
+    pub fn with_base(map_start: usize) -> Self {
+        Self {
+            map_start,
+            regions: Vec::new(),
+        }
+    }
+
     pub fn alloc(&mut self, len: usize) -> Range<usize> {
         let addr = self.map_start;
         self.map_start += len;
@@ -81,6 +89,11 @@ impl MMapInfo {
         addr..self.map_start
     }
 
+    /// 返回所有的映射区域
+    pub fn regions(&self) -> &[MMapRegion] {
+        &self.regions
+    }
+
     pub fn add_region(&mut self, region: MMapRegion) {
         self.regions.push(region);
     }
@@ -113,6 +126,22 @@ impl MMapInfo {
         }
         self.regions.remove(index);
     }
+
+    /// 移除与 `range` 重叠的映射区域，部分重叠的区域会被分割，只保留 `range` 之外的部分。`range` 需要按页对齐
+    pub fn remove_range(&mut self, range: Range<usize>) {
+        for region in core::mem::take(&mut self.regions) {
+            if region.start + region.map_len <= range.start || range.end <= region.start {
+                self.regions.push(region);
+                continue;
+            }
+            if region.start < range.start {
+                self.regions.push(region.split(range.start).0);
+            }
+            if range.end < region.start + region.len {
+                self.regions.push(region.split(range.end).1);
+            }
+        }
+    }
 }
 
 impl MMapRegion {
@@ -3,6 +3,7 @@ use arch::hart_id;
 pub mod elf;
 pub mod loader;
 pub mod map;
+pub mod vdso;
 
 /// This function will be call in slab allocator
 #[no_mangle]
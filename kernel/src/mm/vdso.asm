@@ -0,0 +1,182 @@
+    # vDSO image, mapped into every process right after the vDSO data page.
+    # All addresses inside the image are offsets from its start, the layout is fixed:
+    #   0x000 ELF header    0x040 program headers
+    #   0x100 .hash         0x140 .dynsym    0x1a0 .dynstr    0x300 .dynamic
+    #   0x400 __vdso_clock_gettime
+    #   0x500 __vdso_gettimeofday
+    #   0x600 __vdso_getcpu
+    # The data page is one page below the image, so a function at offset X
+    # reaches it with `auipc t0, 0` followed by subtracting 0x1000 + X.
+    # Data page layout: 0 clock_freq, 8 realtime_sec, 16 realtime_nsec, 24 nr_cpus
+    .option push
+    .option norelax
+    .section .rodata.vdso, "a"
+    .p2align 12
+    .globl vdso_start
+    .globl vdso_end
+vdso_start:
+    # ELF header
+    .byte 0x7f, 0x45, 0x4c, 0x46, 2, 1, 1, 0
+    .zero 8
+    .half 3                 # e_type: ET_DYN
+    .half 243               # e_machine: EM_RISCV
+    .word 1                 # e_version
+    .dword 0                # e_entry
+    .dword 0x40             # e_phoff
+    .dword 0                # e_shoff
+    .word 0x5               # e_flags: RVC | double-float ABI
+    .half 64                # e_ehsize
+    .half 56                # e_phentsize
+    .half 2                 # e_phnum
+    .half 64                # e_shentsize
+    .half 0                 # e_shnum
+    .half 0                 # e_shstrndx
+
+    # PT_LOAD
+    .word 1, 5
+    .dword 0, 0, 0, 0x1000, 0x1000, 0x1000
+    # PT_DYNAMIC
+    .word 2, 4
+    .dword 0x300, 0x300, 0x300, 0x70, 0x70, 8
+
+    # .hash: a single bucket chaining all the symbols
+    .org vdso_start + 0x100
+    .word 1, 4              # nbucket, nchain
+    .word 3                 # bucket[0]
+    .word 0, 0, 1, 2        # chain
+
+    # .dynsym
+    .org vdso_start + 0x140
+    .word 0
+    .byte 0, 0
+    .half 0
+    .dword 0, 0
+    .word 1                 # __vdso_clock_gettime
+    .byte 0x12, 0           # STB_GLOBAL, STT_FUNC
+    .half 1
+    .dword 0x400, 0x100
+    .word 22                # __vdso_gettimeofday
+    .byte 0x12, 0
+    .half 1
+    .dword 0x500, 0x100
+    .word 42                # __vdso_getcpu
+    .byte 0x12, 0
+    .half 1
+    .dword 0x600, 0x100
+
+    # .dynstr
+    .org vdso_start + 0x1a0
+    .asciz ""
+    .asciz "__vdso_clock_gettime"
+    .asciz "__vdso_gettimeofday"
+    .asciz "__vdso_getcpu"
+    .asciz "linux-vdso.so.1"
+
+    # .dynamic
+    .org vdso_start + 0x300
+    .dword 4, 0x100         # DT_HASH
+    .dword 5, 0x1a0         # DT_STRTAB
+    .dword 6, 0x140         # DT_SYMTAB
+    .dword 10, 72           # DT_STRSZ
+    .dword 11, 24           # DT_SYMENT
+    .dword 14, 56           # DT_SONAME
+    .dword 0, 0             # DT_NULL
+
+    # int __vdso_clock_gettime(clockid_t clock, struct timespec *ts)
+    .org vdso_start + 0x400
+    auipc t0, 0
+    li t1, 0x1400
+    sub t0, t0, t1
+    li t3, 8
+    bgeu a0, t3, vdso_clock_gettime_syscall
+    # monotonic clocks: MONOTONIC, MONOTONIC_RAW, MONOTONIC_COARSE, BOOTTIME
+    li t1, 0xd2
+    srl t1, t1, a0
+    andi t1, t1, 1
+    # realtime clocks: REALTIME, REALTIME_COARSE
+    li t2, 0x21
+    srl t2, t2, a0
+    andi t2, t2, 1
+    or t3, t1, t2
+    beqz t3, vdso_clock_gettime_syscall
+    rdtime t3
+    ld t4, 0(t0)
+    divu t5, t3, t4
+    remu t6, t3, t4
+    li t1, 1000000000
+    mul t6, t6, t1
+    divu t6, t6, t4
+    beqz t2, vdso_clock_gettime_store
+    ld t3, 8(t0)
+    add t5, t5, t3
+    ld t3, 16(t0)
+    add t6, t6, t3
+    bltu t6, t1, vdso_clock_gettime_store
+    sub t6, t6, t1
+    addi t5, t5, 1
+vdso_clock_gettime_store:
+    sd t5, 0(a1)
+    sd t6, 8(a1)
+    li a0, 0
+    ret
+vdso_clock_gettime_syscall:
+    li a7, 113
+    ecall
+    ret
+
+    # int __vdso_gettimeofday(struct timeval *tv, struct timezone *tz)
+    .org vdso_start + 0x500
+    auipc t0, 0
+    li t1, 0x1500
+    sub t0, t0, t1
+    beqz a0, vdso_gettimeofday_tz
+    rdtime t3
+    ld t4, 0(t0)
+    divu t5, t3, t4
+    remu t6, t3, t4
+    li t1, 1000000000
+    mul t6, t6, t1
+    divu t6, t6, t4
+    ld t3, 8(t0)
+    add t5, t5, t3
+    ld t3, 16(t0)
+    add t6, t6, t3
+    bltu t6, t1, vdso_gettimeofday_store
+    sub t6, t6, t1
+    addi t5, t5, 1
+vdso_gettimeofday_store:
+    li t1, 1000
+    divu t6, t6, t1
+    sd t5, 0(a0)
+    sd t6, 8(a0)
+vdso_gettimeofday_tz:
+    beqz a1, vdso_gettimeofday_ret
+    sd zero, 0(a1)
+vdso_gettimeofday_ret:
+    li a0, 0
+    ret
+
+    # int __vdso_getcpu(unsigned *cpu, unsigned *node, void *cache)
+    .org vdso_start + 0x600
+    auipc t0, 0
+    li t1, 0x1600
+    sub t0, t0, t1
+    ld t1, 24(t0)
+    li t2, 1
+    bne t1, t2, vdso_getcpu_syscall
+    beqz a0, vdso_getcpu_node
+    sw zero, 0(a0)
+vdso_getcpu_node:
+    beqz a1, vdso_getcpu_ret
+    sw zero, 0(a1)
+vdso_getcpu_ret:
+    li a0, 0
+    ret
+vdso_getcpu_syscall:
+    li a7, 168
+    ecall
+    ret
+
+    .p2align 12
+vdso_end:
+    .option pop
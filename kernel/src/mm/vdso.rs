@@ -0,0 +1,92 @@
+//! vDSO (virtual dynamic shared object)。
+//!
+//! vDSO 是一个由内核提供、映射到每个进程地址空间中的共享库，其中的 `__vdso_clock_gettime`、`__vdso_gettimeofday`
+//! 与 `__vdso_getcpu` 可以在用户态直接读取 `time` 寄存器与内核维护的数据页 [`VdsoData`] 完成计算，而不必陷入内核。
+//! libc 通过辅助向量中的 `AT_SYSINFO_EHDR` 找到 vDSO 的 ELF 头。
+//!
+//! vDSO 的镜像在 `vdso.asm` 中手工构造，在用户地址空间中数据页位于镜像之前一页的位置，
+//! 镜像中的代码通过 pc 相对寻址访问数据页。
+use core::{
+    arch::global_asm,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use config::{CPU_NUM, FRAME_SIZE};
+use mem::VmmPageAllocator;
+use page_table::{
+    addr::{PhysAddr, VirtAddr},
+    table::Sv39PageTable,
+};
+use platform::config::CLOCK_FREQ;
+
+global_asm!(include_str!("vdso.asm"));
+
+extern "C" {
+    fn vdso_start();
+    fn vdso_end();
+}
+
+/// vDSO 数据页，所有进程共享同一个物理页，用户态只读。字段的偏移与 `vdso.asm` 中的代码保持一致
+#[repr(C, align(4096))]
+pub struct VdsoData {
+    /// `time` 寄存器的频率
+    clock_freq: AtomicUsize,
+    /// `CLOCK_REALTIME` 相对于 `CLOCK_MONOTONIC` 的偏移中的秒数
+    realtime_sec: AtomicUsize,
+    /// `CLOCK_REALTIME` 相对于 `CLOCK_MONOTONIC` 的偏移中的纳秒数
+    realtime_nsec: AtomicUsize,
+    /// cpu 的数量，只有一个 cpu 时 `__vdso_getcpu` 不需要陷入内核
+    nr_cpus: AtomicUsize,
+}
+
+static VDSO_DATA: VdsoData = VdsoData {
+    clock_freq: AtomicUsize::new(CLOCK_FREQ),
+    realtime_sec: AtomicUsize::new(0),
+    realtime_nsec: AtomicUsize::new(0),
+    nr_cpus: AtomicUsize::new(CPU_NUM),
+};
+
+/// 设置 `CLOCK_REALTIME` 相对于 `CLOCK_MONOTONIC` 的偏移
+pub fn set_realtime_offset(sec: usize, nsec: usize) {
+    VDSO_DATA.realtime_sec.store(sec, Ordering::Relaxed);
+    VDSO_DATA.realtime_nsec.store(nsec, Ordering::Relaxed);
+}
+
+/// 获取 `CLOCK_REALTIME` 相对于 `CLOCK_MONOTONIC` 的偏移，返回 (秒, 纳秒)
+pub fn realtime_offset() -> (usize, usize) {
+    (
+        VDSO_DATA.realtime_sec.load(Ordering::Relaxed),
+        VDSO_DATA.realtime_nsec.load(Ordering::Relaxed),
+    )
+}
+
+/// vDSO 镜像 (不含数据页) 的大小
+fn vdso_size() -> usize {
+    vdso_end as usize - vdso_start as usize
+}
+
+/// 将 vDSO 数据页与镜像映射到地址空间中从 `base` 开始的位置，返回 vDSO 的 ELF 头所在的地址 (`AT_SYSINFO_EHDR`)，
+/// 无法分配页表时返回 `None`
+pub fn map_vdso(address_space: &mut Sv39PageTable<VmmPageAllocator>, base: usize) -> Option<usize> {
+    address_space
+        .map_region(
+            VirtAddr::from(base),
+            PhysAddr::from(&VDSO_DATA as *const VdsoData as usize),
+            FRAME_SIZE,
+            "RUVAD".into(),
+            true,
+        )
+        .ok()?;
+    let ehdr = base + FRAME_SIZE;
+    address_space
+        .map_region(
+            VirtAddr::from(ehdr),
+            PhysAddr::from(vdso_start as usize),
+            vdso_size(),
+            "RXUVAD".into(),
+            true,
+        )
+        .ok()?;
+    warn!("vdso: {:#x} - {:#x}", base, ehdr + vdso_size());
+    Some(ehdr)
+}
@@ -41,6 +41,8 @@ pub fn socket_addr_resolution(family_user_addr: usize, len: usize) -> AlienResul
                 len,
             );
             let path = String::from_utf8_lossy(&buf[2..len - 2]).to_string();
+            // 套接字路径基于进程的根目录与工作目录解析
+            let path = task.access_inner().fs_info.global_path(&path);
             Ok(SocketAddrExt::LocalPath(path))
         }
     }
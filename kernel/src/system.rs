@@ -1,6 +1,6 @@
 //! uname系统调用实现
 
-use alloc::vec;
+use alloc::{string::String, vec, vec::Vec};
 use core::cmp::min;
 
 use constants::{
@@ -10,8 +10,12 @@ use constants::{
 };
 use syscall_table::syscall_func;
 use timer::{get_time_ms, TimeFromFreq};
+use vfs::dev::random::random_fill;
 
-use crate::task::current_task;
+use crate::task::{
+    current_task,
+    namespace::{current_uts_ns, HOST_NAME_MAX},
+};
 
 /// 记录系统信息的结构，包括操作系统名、在网络中的用户名、操作系统release和version版本、硬件类型、域名等信息。
 #[repr(C)]
@@ -31,14 +35,15 @@ pub struct Utsname {
     domainname: [u8; 65],
 }
 
-/// 返回系统信息，信息保存在[`Utsname`]结构中。
+/// 返回系统信息，信息保存在[`Utsname`]结构中。主机名与域名来自当前任务所在的 UTS 命名空间。
 fn system_info() -> Utsname {
     const SYSNAME: &str = "Linux";
-    const NODENAME: &str = "Alien";
     const RELEASE: &str = "5.1";
     const VERSION: &str = "5.1";
     const MACHINE: &str = "riscv64";
-    const DOMAINNAME: &str = "RustOS";
+    let uts = current_uts_ns();
+    let nodename = uts.hostname();
+    let domainname = uts.domainname();
     let mut name = Utsname {
         sysname: [0; 65],
         nodename: [0; 65],
@@ -48,11 +53,11 @@ fn system_info() -> Utsname {
         domainname: [0; 65],
     };
     name.sysname[..SYSNAME.len()].copy_from_slice(SYSNAME.as_bytes());
-    name.nodename[..NODENAME.len()].copy_from_slice(NODENAME.as_bytes());
+    name.nodename[..nodename.len()].copy_from_slice(nodename.as_bytes());
     name.release[..RELEASE.len()].copy_from_slice(RELEASE.as_bytes());
     name.version[..VERSION.len()].copy_from_slice(VERSION.as_bytes());
     name.machine[..MACHINE.len()].copy_from_slice(MACHINE.as_bytes());
-    name.domainname[..DOMAINNAME.len()].copy_from_slice(DOMAINNAME.as_bytes());
+    name.domainname[..domainname.len()].copy_from_slice(domainname.as_bytes());
     name
 }
 
@@ -67,6 +72,46 @@ pub fn uname(utsname: *const u8) -> isize {
     0
 }
 
+/// 从用户空间读取长度为 `len` 的名字，名字不以 `\0` 结尾
+fn read_uts_name(name: *const u8, len: usize) -> AlienResult<String> {
+    if len > HOST_NAME_MAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let name = task
+        .transfer_buffer(name, len)
+        .into_iter()
+        .flat_map(|buf| buf.iter().cloned())
+        .collect::<Vec<u8>>();
+    String::from_utf8(name).map_err(|_| LinuxErrno::EINVAL)
+}
+
+/// 一个系统调用，设置当前任务所在的 UTS 命名空间中的主机名，`len` 为名字的长度。
+///
+/// 名字的长度超过 64 时返回 `EINVAL`。
+///
+/// Reference: [sethostname](https://man7.org/linux/man-pages/man2/sethostname.2.html)
+#[syscall_func(161)]
+pub fn sethostname(name: *const u8, len: usize) -> AlienResult<isize> {
+    let name = read_uts_name(name, len)?;
+    info!("sethostname: {}", name);
+    current_uts_ns().set_hostname(&name)?;
+    Ok(0)
+}
+
+/// 一个系统调用，设置当前任务所在的 UTS 命名空间中的域名，`len` 为名字的长度。
+///
+/// 名字的长度超过 64 时返回 `EINVAL`。
+///
+/// Reference: [setdomainname](https://man7.org/linux/man-pages/man2/setdomainname.2.html)
+#[syscall_func(162)]
+pub fn setdomainname(name: *const u8, len: usize) -> AlienResult<isize> {
+    let name = read_uts_name(name, len)?;
+    info!("setdomainname: {}", name);
+    current_uts_ns().set_domainname(&name)?;
+    Ok(0)
+}
+
 const LOG_BUF_LEN: usize = 4096;
 const LOG: &str = r"
 [    0.000000] Linux version 5.10.0-7-riscv64 (debian-kernel@lists.debian.org) (gcc-10 (Debian 10.2.1-6) 10.2.1 20210110, GNU ld (GNU Binutils for Debian) 2.35.2) #1 SMP Debian 5.10.40-1 (2021-05-28)
@@ -228,18 +273,20 @@ pub fn get_random(buf: *mut u8, len: usize, flags: u32) -> AlienResult<isize> {
     );
     let task = current_task().unwrap();
     let mut rand_buf = vec![0; len];
-    for v in rand_buf.iter_mut() {
-        let time_ms = get_time_ms();
-        *v = (time_ms & 0xff) as u8;
-    }
+    random_fill(&mut rand_buf);
     task.access_inner()
         .copy_to_user_buffer(rand_buf.as_ptr(), buf, len);
     Ok(len as isize)
 }
 
 /// 一个系统调用，通过调用 SBI_SHUTDOWN 来关闭操作系统（直接退出 QEMU）
+///
+/// 关机前会同步所有磁盘文件系统并写回块设备缓存。
 #[syscall_func(2003)]
 pub fn system_shutdown() -> AlienResult<isize> {
     println!("shutdown...");
+    if let Err(e) = vfs::sync_filesystems() {
+        println!("shutdown: writeback failed: {:?}", e);
+    }
     platform::system_shutdown()
 }
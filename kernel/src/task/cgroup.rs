@@ -0,0 +1,832 @@
+//! cgroup v2。
+//!
+//! 所有 cgroup 组成一棵树，以 cgroup2 文件系统(挂载于 `/sys/fs/cgroup`)中的目录的形式出现，
+//! 在其中创建或删除目录即创建或删除 cgroup。每个任务属于一个 cgroup，子任务继承父任务所在的 cgroup，
+//! 向 `cgroup.procs` 写入 pid 会将该进程的所有线程移动到对应的 cgroup 中。
+//! 所有的控制器总是处于开启状态，`cgroup.subtree_control` 只记录写入的内容。资源的使用量按层级统计，
+//! 任务受到其所在的 cgroup 以及所有祖先 cgroup 的限制。
+//!
+//! + 内存控制器：分配的物理页被记到分配时当前任务所在的 cgroup 上，直到物理页被释放。
+//!   不会进行回收，使用量将要超过 `memory.max` 时拒绝记账，物理页不会被分配：用户地址空间的映射返回 `ENOMEM`，
+//!   缺页异常中分配失败时向当前进程发送 SIGKILL。内核中不能失败的分配 (`alloc_frame_trackers`) 总是被记账。
+//! + CPU 控制器：`cpu.weight` 决定任务每次被调度后能够连续运行的时钟节拍数，默认权重对应一个时钟节拍。
+//!   `cpu.max` 限制每个周期内 cgroup 中的任务能够使用的 CPU 时间，超出后任务在下一个周期开始前不会再被调度，
+//!   超出的部分会从之后的周期中扣除。
+//! + 任务数控制器：cgroup 中的任务(包括线程)数达到 `pids.max` 时 `clone` 返回 `EAGAIN`，移动任务不受限制。
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    cmp::min,
+    fmt::{Debug, Formatter},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use config::FRAME_SIZE;
+use constants::{AlienResult, LinuxErrno};
+use ksync::Mutex;
+use mem::{FrameCharger, FrameOwner, OverLimit};
+use platform::config::CLOCK_FREQ;
+use smpscheduler::FifoTask;
+use spin::Lazy;
+use timer::{
+    hrtimer::{hrtimer_cancel, hrtimer_start, HrTimerId},
+    read_timer,
+};
+use vfs::cgroup::{CgroupFsDirInodeImpl, CGROUP_FS_ROOT};
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    path::VfsPath,
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType},
+    VfsResult,
+};
+
+use crate::task::{
+    current_cpu, current_task,
+    namespace::{find_vpid, pid_vnr},
+    tasks, Task, GLOBAL_TASK_MANAGER,
+};
+
+/// 所有 cgroup 都支持的控制器
+const CGROUP_CONTROLLERS: &str = "cpu memory pids";
+/// `cpu.weight` 的默认值，对应一个时钟节拍的时间片
+const CPU_WEIGHT_DEFAULT: usize = 100;
+/// `cpu.weight` 的上限
+const CPU_WEIGHT_MAX: usize = 10000;
+/// `cpu.max` 中周期的默认值，单位为微秒
+const CPU_PERIOD_DEFAULT: usize = 100_000;
+/// `cpu.max` 中周期的下限，单位为微秒
+const CPU_PERIOD_MIN: usize = 1000;
+/// `cpu.max` 中周期的上限，单位为微秒
+const CPU_PERIOD_MAX: usize = 1_000_000;
+/// `cpu.max` 中配额的下限，单位为微秒
+const CPU_QUOTA_MIN: usize = 1000;
+
+/// cgroup 目录中的控制文件
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum CgroupFileKind {
+    Procs,
+    Controllers,
+    SubtreeControl,
+    CpuWeight,
+    CpuMax,
+    CpuStat,
+    MemoryCurrent,
+    MemoryMax,
+    MemoryEvents,
+    PidsCurrent,
+    PidsMax,
+    PidsEvents,
+}
+
+/// 控制文件的文件名、类型、权限以及根 cgroup 中是否存在该文件
+const CGROUP_FILES: [(&str, CgroupFileKind, &str, bool); 12] = [
+    ("cgroup.procs", CgroupFileKind::Procs, "rw-r--r--", true),
+    (
+        "cgroup.controllers",
+        CgroupFileKind::Controllers,
+        "r--r--r--",
+        true,
+    ),
+    (
+        "cgroup.subtree_control",
+        CgroupFileKind::SubtreeControl,
+        "rw-r--r--",
+        true,
+    ),
+    ("cpu.weight", CgroupFileKind::CpuWeight, "rw-r--r--", false),
+    ("cpu.max", CgroupFileKind::CpuMax, "rw-r--r--", false),
+    ("cpu.stat", CgroupFileKind::CpuStat, "r--r--r--", true),
+    (
+        "memory.current",
+        CgroupFileKind::MemoryCurrent,
+        "r--r--r--",
+        true,
+    ),
+    ("memory.max", CgroupFileKind::MemoryMax, "rw-r--r--", false),
+    (
+        "memory.events",
+        CgroupFileKind::MemoryEvents,
+        "r--r--r--",
+        false,
+    ),
+    (
+        "pids.current",
+        CgroupFileKind::PidsCurrent,
+        "r--r--r--",
+        true,
+    ),
+    ("pids.max", CgroupFileKind::PidsMax, "rw-r--r--", false),
+    (
+        "pids.events",
+        CgroupFileKind::PidsEvents,
+        "r--r--r--",
+        false,
+    ),
+];
+
+/// CPU 控制器的状态，时间的单位均为微秒
+struct CpuState {
+    weight: usize,
+    /// 每个周期内能够使用的 CPU 时间，为 `None` 时不限制
+    quota: Option<usize>,
+    period: usize,
+    /// 当前周期的起始时刻
+    period_start: usize,
+    /// 当前周期内已经使用的 CPU 时间，包括之前的周期中超出配额的部分
+    runtime: usize,
+    /// 总共使用的 CPU 时间
+    usage: usize,
+    nr_periods: usize,
+    nr_throttled: usize,
+    throttled_usec: usize,
+    /// 被限流的起始时刻，为 `None` 时没有被限流
+    throttled_since: Option<usize>,
+    /// 在下一个周期开始时解除限流的计时器
+    timer: Option<HrTimerId>,
+    /// 因为限流而等待下一个周期的任务
+    waiting: Vec<Arc<Task>>,
+}
+
+impl CpuState {
+    fn new() -> Self {
+        Self {
+            weight: CPU_WEIGHT_DEFAULT,
+            quota: None,
+            period: CPU_PERIOD_DEFAULT,
+            period_start: now_us(),
+            runtime: 0,
+            usage: 0,
+            nr_periods: 0,
+            nr_throttled: 0,
+            throttled_usec: 0,
+            throttled_since: None,
+            timer: None,
+            waiting: Vec::new(),
+        }
+    }
+
+    /// 进入 `now` 所在的周期，每经过一个周期扣除一个周期的配额
+    fn refresh(&mut self, now: usize) {
+        let Some(quota) = self.quota else {
+            return;
+        };
+        if now < self.period_start + self.period {
+            return;
+        }
+        let n = (now - self.period_start) / self.period;
+        self.runtime = self.runtime.saturating_sub(quota.saturating_mul(n));
+        self.period_start += n * self.period;
+        self.nr_periods += n;
+    }
+
+    fn over_quota(&self) -> bool {
+        self.quota.map_or(false, |quota| self.runtime >= quota)
+    }
+
+    /// 解除限流，返回等待的任务
+    fn unthrottle(&mut self, now: usize) -> Vec<Arc<Task>> {
+        if let Some(since) = self.throttled_since.take() {
+            self.throttled_usec += now.saturating_sub(since);
+        }
+        if let Some(timer) = self.timer.take() {
+            hrtimer_cancel(timer);
+        }
+        core::mem::take(&mut self.waiting)
+    }
+}
+
+/// 一个 cgroup
+pub struct Cgroup {
+    name: String,
+    parent: Option<Arc<Cgroup>>,
+    /// cgroup 对应的目录
+    dir: Arc<dyn VfsInode>,
+    children: Mutex<BTreeMap<String, Arc<Cgroup>>>,
+    subtree_control: Mutex<String>,
+    /// 已经记账的物理页数
+    memory_current: AtomicUsize,
+    /// 物理页数的上限，`usize::MAX` 表示不限制
+    memory_max: AtomicUsize,
+    memory_events_max: AtomicUsize,
+    memory_oom_kill: AtomicUsize,
+    pids_current: AtomicUsize,
+    /// 任务数的上限，`usize::MAX` 表示不限制
+    pids_max: AtomicUsize,
+    pids_events_max: AtomicUsize,
+    cpu: Mutex<CpuState>,
+}
+
+impl Debug for Cgroup {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Cgroup")
+            .field("path", &self.path())
+            .finish()
+    }
+}
+
+/// 根 cgroup，对应 cgroup2 文件系统的根目录
+static ROOT_CGROUP: Lazy<Arc<Cgroup>> = Lazy::new(|| {
+    let root = CGROUP_FS_ROOT.get().unwrap();
+    let cgroup = Arc::new(Cgroup::new(String::new(), None, root.inode().unwrap()));
+    cgroup.populate(root).unwrap();
+    cgroup
+});
+
+/// 获取根 cgroup
+pub fn root_cgroup() -> Arc<Cgroup> {
+    ROOT_CGROUP.clone()
+}
+
+/// 创建根 cgroup 并注册物理页的记账接口
+pub fn init_cgroup() {
+    Lazy::force(&ROOT_CGROUP);
+    mem::register_frame_charger(Box::new(CgroupFrameCharger));
+}
+
+impl Cgroup {
+    fn new(name: String, parent: Option<Arc<Cgroup>>, dir: Arc<dyn VfsInode>) -> Self {
+        Self {
+            name,
+            parent,
+            dir,
+            children: Mutex::new(BTreeMap::new()),
+            subtree_control: Mutex::new(String::new()),
+            memory_current: AtomicUsize::new(0),
+            memory_max: AtomicUsize::new(usize::MAX),
+            memory_events_max: AtomicUsize::new(0),
+            memory_oom_kill: AtomicUsize::new(0),
+            pids_current: AtomicUsize::new(0),
+            pids_max: AtomicUsize::new(usize::MAX),
+            pids_events_max: AtomicUsize::new(0),
+            cpu: Mutex::new(CpuState::new()),
+        }
+    }
+
+    fn is_root(&self) -> bool {
+        self.parent.is_none()
+    }
+
+    /// cgroup 相对于层级结构根目录的路径
+    pub fn path(&self) -> String {
+        match &self.parent {
+            None => "/".to_string(),
+            Some(parent) if parent.is_root() => format!("/{}", self.name),
+            Some(parent) => format!("{}/{}", parent.path(), self.name),
+        }
+    }
+
+    /// 从自身到根 cgroup 的所有 cgroup
+    fn ancestors(self: &Arc<Self>) -> Vec<Arc<Cgroup>> {
+        let mut res = Vec::new();
+        let mut cgroup = Some(self.clone());
+        while let Some(c) = cgroup {
+            cgroup = c.parent.clone();
+            res.push(c);
+        }
+        res
+    }
+
+    /// 在 cgroup 的目录 `dentry` 中创建控制文件
+    fn populate(self: &Arc<Self>, dentry: &Arc<dyn VfsDentry>) -> AlienResult<()> {
+        let dir = dentry
+            .inode()?
+            .downcast_arc::<CgroupFsDirInodeImpl>()
+            .map_err(|_| LinuxErrno::EINVAL)?;
+        for (name, kind, perm, in_root) in CGROUP_FILES {
+            if self.is_root() && !in_root {
+                continue;
+            }
+            let file = CgroupFile {
+                cgroup: Arc::downgrade(self),
+                kind,
+                perm: perm.into(),
+            };
+            dir.add_file_manually(name, Arc::new(file), perm.into())?;
+        }
+        Ok(())
+    }
+
+    /// 在目录 `dentry` 对应的 cgroup 下创建一个名为 `name` 的子 cgroup
+    pub fn mkdir(self: &Arc<Self>, dentry: &Arc<dyn VfsDentry>, name: &str) -> AlienResult<()> {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let mut children = self.children.lock();
+        if children.contains_key(name) || CGROUP_FILES.iter().any(|(file, ..)| *file == name) {
+            return Err(LinuxErrno::EEXIST);
+        }
+        let dir = dentry
+            .inode()?
+            .downcast_arc::<CgroupFsDirInodeImpl>()
+            .map_err(|_| LinuxErrno::EINVAL)?;
+        dir.add_dir_manually(name, "rwxr-xr-x".into())?;
+        let child_dentry = VfsPath::new(dentry.clone(), dentry.clone())
+            .join(name)?
+            .open(None)?;
+        let child = Arc::new(Cgroup::new(
+            name.to_string(),
+            Some(self.clone()),
+            child_dentry.inode()?,
+        ));
+        child.populate(&child_dentry)?;
+        children.insert(name.to_string(), child);
+        Ok(())
+    }
+
+    /// 删除目录 `dentry` 对应的 cgroup 下名为 `name` 的子 cgroup，其中不能有任务或者子 cgroup
+    pub fn rmdir(&self, dentry: &Arc<dyn VfsDentry>, name: &str) -> AlienResult<()> {
+        let mut children = self.children.lock();
+        let child = children.get(name).ok_or(LinuxErrno::ENOENT)?;
+        if !child.children.lock().is_empty() || child.pids_current.load(Ordering::Relaxed) != 0 {
+            return Err(LinuxErrno::EBUSY);
+        }
+        let child_dentry = VfsPath::new(dentry.clone(), dentry.clone())
+            .join(name)?
+            .open(None)?;
+        let child_dir = child_dentry
+            .inode()?
+            .downcast_arc::<CgroupFsDirInodeImpl>()
+            .map_err(|_| LinuxErrno::EINVAL)?;
+        for (file, ..) in CGROUP_FILES {
+            let _ = child_dentry.remove(file);
+            child_dir.remove_manually(file)?;
+        }
+        let dir = dentry
+            .inode()?
+            .downcast_arc::<CgroupFsDirInodeImpl>()
+            .map_err(|_| LinuxErrno::EINVAL)?;
+        dentry.remove(name)?;
+        dir.remove_manually(name)?;
+        // 仍然被记账的物理页持有该 cgroup 的引用，直到它们被释放
+        children.remove(name);
+        Ok(())
+    }
+
+    /// 将一个任务计入 cgroup 的任务数，不检查 `pids.max`
+    pub fn charge_pid(self: &Arc<Self>) {
+        for cgroup in self.ancestors() {
+            cgroup.pids_current.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 为新创建的任务计入 cgroup 的任务数，任意一级 cgroup 的任务数达到 `pids.max` 时返回 `EAGAIN`
+    pub fn try_charge_pid(self: &Arc<Self>) -> AlienResult<()> {
+        let ancestors = self.ancestors();
+        if let Some(limited) = ancestors.iter().find(|cgroup| {
+            cgroup.pids_current.load(Ordering::Relaxed) >= cgroup.pids_max.load(Ordering::Relaxed)
+        }) {
+            limited.pids_events_max.fetch_add(1, Ordering::Relaxed);
+            return Err(LinuxErrno::EAGAIN);
+        }
+        for cgroup in ancestors {
+            cgroup.pids_current.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// 任务被回收或者移出 cgroup 时减少 cgroup 的任务数
+    pub fn uncharge_pid(self: &Arc<Self>) {
+        for cgroup in self.ancestors() {
+            cgroup.pids_current.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 记账 `count` 个物理页，任意一级 cgroup 的使用量将要超过 `memory.max` 时不记账并返回该 cgroup，
+    /// `force` 为真时总是进行记账
+    fn try_charge_memory(self: &Arc<Self>, count: usize, force: bool) -> Result<(), Arc<Cgroup>> {
+        let ancestors = self.ancestors();
+        if !force {
+            if let Some(limited) = ancestors.iter().find(|cgroup| {
+                cgroup.memory_current.load(Ordering::Relaxed) + count
+                    > cgroup.memory_max.load(Ordering::Relaxed)
+            }) {
+                limited.memory_events_max.fetch_add(1, Ordering::Relaxed);
+                return Err(limited.clone());
+            }
+        }
+        for cgroup in ancestors {
+            cgroup.memory_current.fetch_add(count, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// 缺页异常中物理页的记账被拒绝，当前进程将被杀死
+    pub fn record_oom_kill(self: &Arc<Self>) {
+        if let Some(limited) = self.ancestors().into_iter().find(|cgroup| {
+            cgroup.memory_current.load(Ordering::Relaxed)
+                >= cgroup.memory_max.load(Ordering::Relaxed)
+        }) {
+            limited.memory_oom_kill.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 任务每次被调度后能够连续运行的时钟节拍数
+    pub fn time_slice(&self) -> usize {
+        (self.cpu.lock().weight / CPU_WEIGHT_DEFAULT).max(1)
+    }
+
+    /// 记账 `delta` 微秒的 CPU 时间，返回被限流的 cgroup
+    fn charge_cpu(self: &Arc<Self>, delta: usize, now: usize) -> Option<Arc<Cgroup>> {
+        let mut throttled = None;
+        for cgroup in self.ancestors() {
+            let mut cpu = cgroup.cpu.lock();
+            cpu.usage += delta;
+            if cpu.quota.is_none() {
+                continue;
+            }
+            cpu.refresh(now);
+            cpu.runtime += delta;
+            if cpu.throttled_since.is_none() && cpu.over_quota() {
+                cpu.throttled_since = Some(now);
+                cpu.nr_throttled += 1;
+                let deadline = us_to_clock(cpu.period_start + cpu.period);
+                let target = cgroup.clone();
+                cpu.timer = Some(hrtimer_start(
+                    deadline,
+                    Box::new(move || target.period_timer()),
+                ));
+            }
+            if throttled.is_none() && cpu.throttled_since.is_some() {
+                throttled = Some(cgroup.clone());
+            }
+        }
+        throttled
+    }
+
+    /// 周期结束时由计时器调用，配额仍然不足时等待下一个周期
+    fn period_timer(self: Arc<Self>) {
+        let now = now_us();
+        let waiting = {
+            let mut cpu = self.cpu.lock();
+            cpu.timer = None;
+            cpu.refresh(now);
+            if cpu.throttled_since.is_none() {
+                return;
+            }
+            if cpu.over_quota() {
+                let deadline = us_to_clock(cpu.period_start + cpu.period);
+                let target = self.clone();
+                cpu.timer = Some(hrtimer_start(
+                    deadline,
+                    Box::new(move || target.period_timer()),
+                ));
+                return;
+            }
+            cpu.unthrottle(now)
+        };
+        wake_tasks(waiting);
+    }
+
+    /// 将因为限流而让出 CPU 的任务放入等待队列，cgroup 已经解除限流时直接加入调度队列
+    pub fn park(&self, task: Arc<Task>) {
+        let mut cpu = self.cpu.lock();
+        if cpu.throttled_since.is_some() {
+            cpu.waiting.push(task);
+        } else {
+            drop(cpu);
+            GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
+        }
+    }
+
+    /// 读取控制文件的内容
+    fn show(self: &Arc<Self>, kind: CgroupFileKind) -> String {
+        match kind {
+            CgroupFileKind::Procs => {
+                let mut pids = tasks()
+                    .into_iter()
+                    .filter(|task| {
+                        task.get_pid() == task.get_tid() && Arc::ptr_eq(&task.cgroup(), self)
+                    })
+                    .map(|task| pid_vnr(task.get_pid() as usize))
+                    .filter(|&pid| pid != 0)
+                    .collect::<Vec<_>>();
+                pids.sort();
+                pids.iter().map(|pid| format!("{}\n", pid)).collect()
+            }
+            CgroupFileKind::Controllers => format!("{}\n", CGROUP_CONTROLLERS),
+            CgroupFileKind::SubtreeControl => format!("{}\n", self.subtree_control.lock()),
+            CgroupFileKind::CpuWeight => format!("{}\n", self.cpu.lock().weight),
+            CgroupFileKind::CpuMax => {
+                let cpu = self.cpu.lock();
+                match cpu.quota {
+                    Some(quota) => format!("{} {}\n", quota, cpu.period),
+                    None => format!("max {}\n", cpu.period),
+                }
+            }
+            CgroupFileKind::CpuStat => {
+                let cpu = self.cpu.lock();
+                format!(
+                    "usage_usec {}\nnr_periods {}\nnr_throttled {}\nthrottled_usec {}\n",
+                    cpu.usage, cpu.nr_periods, cpu.nr_throttled, cpu.throttled_usec
+                )
+            }
+            CgroupFileKind::MemoryCurrent => format!(
+                "{}\n",
+                self.memory_current.load(Ordering::Relaxed) * FRAME_SIZE
+            ),
+            CgroupFileKind::MemoryMax => match self.memory_max.load(Ordering::Relaxed) {
+                usize::MAX => "max\n".to_string(),
+                max => format!("{}\n", max * FRAME_SIZE),
+            },
+            CgroupFileKind::MemoryEvents => format!(
+                "max {}\noom_kill {}\n",
+                self.memory_events_max.load(Ordering::Relaxed),
+                self.memory_oom_kill.load(Ordering::Relaxed)
+            ),
+            CgroupFileKind::PidsCurrent => {
+                format!("{}\n", self.pids_current.load(Ordering::Relaxed))
+            }
+            CgroupFileKind::PidsMax => match self.pids_max.load(Ordering::Relaxed) {
+                usize::MAX => "max\n".to_string(),
+                max => format!("{}\n", max),
+            },
+            CgroupFileKind::PidsEvents => {
+                format!("max {}\n", self.pids_events_max.load(Ordering::Relaxed))
+            }
+        }
+    }
+
+    /// 写入控制文件
+    fn store(self: &Arc<Self>, kind: CgroupFileKind, value: &str) -> AlienResult<()> {
+        match kind {
+            CgroupFileKind::Procs => {
+                let pid = value.parse::<usize>().map_err(|_| LinuxErrno::EINVAL)?;
+                self.attach(pid)
+            }
+            CgroupFileKind::SubtreeControl => {
+                for token in value.split_whitespace() {
+                    let controller = token
+                        .strip_prefix(|c| c == '+' || c == '-')
+                        .ok_or(LinuxErrno::EINVAL)?;
+                    if !CGROUP_CONTROLLERS.split(' ').any(|c| c == controller) {
+                        return Err(LinuxErrno::ENOENT);
+                    }
+                }
+                let mut enabled = self
+                    .subtree_control
+                    .lock()
+                    .split_whitespace()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>();
+                for token in value.split_whitespace() {
+                    let controller = &token[1..];
+                    enabled.retain(|c| c != controller);
+                    if token.starts_with('+') {
+                        enabled.push(controller.to_string());
+                    }
+                }
+                // 按照 cgroup.controllers 中的顺序显示
+                let enabled = CGROUP_CONTROLLERS
+                    .split(' ')
+                    .filter(|c| enabled.iter().any(|e| e == c))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                *self.subtree_control.lock() = enabled;
+                Ok(())
+            }
+            CgroupFileKind::CpuWeight => {
+                let weight = value.parse::<usize>().map_err(|_| LinuxErrno::EINVAL)?;
+                if weight == 0 || weight > CPU_WEIGHT_MAX {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                self.cpu.lock().weight = weight;
+                Ok(())
+            }
+            CgroupFileKind::CpuMax => {
+                let mut fields = value.split_whitespace();
+                let quota = match fields.next().ok_or(LinuxErrno::EINVAL)? {
+                    "max" => None,
+                    quota => Some(quota.parse::<usize>().map_err(|_| LinuxErrno::EINVAL)?),
+                };
+                let period = fields
+                    .next()
+                    .map(|period| period.parse::<usize>().map_err(|_| LinuxErrno::EINVAL))
+                    .transpose()?;
+                if fields.next().is_some()
+                    || quota.map_or(false, |quota| quota < CPU_QUOTA_MIN)
+                    || period.map_or(false, |period| {
+                        !(CPU_PERIOD_MIN..=CPU_PERIOD_MAX).contains(&period)
+                    })
+                {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                let now = now_us();
+                let waiting = {
+                    let mut cpu = self.cpu.lock();
+                    cpu.quota = quota;
+                    if let Some(period) = period {
+                        cpu.period = period;
+                    }
+                    cpu.period_start = now;
+                    cpu.runtime = 0;
+                    cpu.unthrottle(now)
+                };
+                wake_tasks(waiting);
+                Ok(())
+            }
+            CgroupFileKind::MemoryMax => {
+                let max = match value {
+                    "max" => usize::MAX,
+                    value => parse_size(value)? / FRAME_SIZE,
+                };
+                self.memory_max.store(max, Ordering::Relaxed);
+                Ok(())
+            }
+            CgroupFileKind::PidsMax => {
+                let max = match value {
+                    "max" => usize::MAX,
+                    value => value.parse::<usize>().map_err(|_| LinuxErrno::EINVAL)?,
+                };
+                self.pids_max.store(max, Ordering::Relaxed);
+                Ok(())
+            }
+            _ => Err(LinuxErrno::EACCES),
+        }
+    }
+
+    /// 将进程 `pid` (调用者所在的 PID 命名空间中的 id，为 0 时表示调用者自身) 的所有线程移动到该 cgroup 中
+    fn attach(self: &Arc<Self>, pid: usize) -> AlienResult<()> {
+        let pid = if pid == 0 {
+            current_task().unwrap().get_pid() as usize
+        } else {
+            find_vpid(pid).ok_or(LinuxErrno::ESRCH)?
+        };
+        let threads = tasks()
+            .into_iter()
+            .filter(|task| task.get_pid() as usize == pid)
+            .collect::<Vec<_>>();
+        if threads.is_empty() {
+            return Err(LinuxErrno::ESRCH);
+        }
+        for task in threads {
+            let old = core::mem::replace(&mut *task.cgroup.lock(), self.clone());
+            old.uncharge_pid();
+            self.charge_pid();
+        }
+        Ok(())
+    }
+}
+
+/// 查找目录 `dentry` 对应的 cgroup，`dentry` 不在 cgroup2 文件系统中时返回 `None`
+pub fn find_cgroup(dentry: &Arc<dyn VfsDentry>) -> AlienResult<Option<Arc<Cgroup>>> {
+    let inode = dentry.inode()?;
+    if inode.inode_type() != VfsNodeType::Dir {
+        return Ok(None);
+    }
+    let mut stack = alloc::vec![root_cgroup()];
+    while let Some(cgroup) = stack.pop() {
+        if Arc::ptr_eq(&cgroup.dir, &inode) {
+            return Ok(Some(cgroup));
+        }
+        stack.extend(cgroup.children.lock().values().cloned());
+    }
+    Ok(None)
+}
+
+/// 将当前 CPU 上的任务 `task` 自上次记账以来使用的 CPU 时间记到其所在的 cgroup 上，返回被限流的 cgroup
+pub fn charge_cpu_time(task: &Arc<Task>) -> Option<Arc<Cgroup>> {
+    let cpu = current_cpu();
+    let now = read_timer();
+    let delta = clock_to_us(now.saturating_sub(cpu.switch_time));
+    cpu.switch_time = now;
+    task.cgroup().charge_cpu(delta, clock_to_us(now))
+}
+
+/// 用户态的时钟中断时调用，返回当前任务是否应该让出 CPU
+pub fn cpu_tick() -> bool {
+    let cpu = current_cpu();
+    cpu.ticks += 1;
+    let ticks = cpu.ticks;
+    let Some(task) = current_task() else {
+        return true;
+    };
+    charge_cpu_time(task).is_some() || ticks >= task.cgroup().time_slice()
+}
+
+fn wake_tasks(tasks: Vec<Arc<Task>>) {
+    for task in tasks {
+        GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
+    }
+}
+
+fn now_us() -> usize {
+    clock_to_us(read_timer())
+}
+
+fn clock_to_us(clock: usize) -> usize {
+    (clock as u128 * 1_000_000 / CLOCK_FREQ as u128) as usize
+}
+
+fn us_to_clock(us: usize) -> usize {
+    (us as u128 * CLOCK_FREQ as u128 / 1_000_000) as usize
+}
+
+/// 解析带有可选的 `K`/`M`/`G` 后缀的字节数
+fn parse_size(value: &str) -> AlienResult<usize> {
+    let (digits, shift) = match value.as_bytes().last() {
+        Some(b'k' | b'K') => (&value[..value.len() - 1], 10),
+        Some(b'm' | b'M') => (&value[..value.len() - 1], 20),
+        Some(b'g' | b'G') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    let size = digits.parse::<usize>().map_err(|_| LinuxErrno::EINVAL)?;
+    size.checked_shl(shift)
+        .filter(|res| res >> shift == size)
+        .ok_or(LinuxErrno::EINVAL)
+}
+
+impl FrameOwner for Cgroup {
+    fn uncharge(&self, count: usize) {
+        let mut cgroup = Some(self);
+        while let Some(c) = cgroup {
+            c.memory_current.fetch_sub(count, Ordering::Relaxed);
+            cgroup = c.parent.as_deref();
+        }
+    }
+}
+
+/// 物理页的记账接口，物理页被记到分配时当前任务所在的 cgroup 上
+struct CgroupFrameCharger;
+
+impl FrameCharger for CgroupFrameCharger {
+    fn charge(&self, count: usize, force: bool) -> Result<Option<Arc<dyn FrameOwner>>, OverLimit> {
+        let Some(task) = current_task() else {
+            return Ok(None);
+        };
+        let cgroup = task.cgroup();
+        cgroup.try_charge_memory(count, force).map_err(|limited| {
+            warn!(
+                "cgroup {} reach memory.max, refuse to charge {} pages",
+                limited.path(),
+                count
+            );
+            OverLimit
+        })?;
+        Ok(Some(cgroup))
+    }
+}
+
+/// cgroup 目录中的控制文件
+struct CgroupFile {
+    cgroup: Weak<Cgroup>,
+    kind: CgroupFileKind,
+    perm: VfsNodePerm,
+}
+
+impl CgroupFile {
+    fn cgroup(&self) -> VfsResult<Arc<Cgroup>> {
+        self.cgroup.upgrade().ok_or(VfsError::Invalid)
+    }
+}
+
+impl VfsFile for CgroupFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let info = self.cgroup()?.show(self.kind);
+        let info = info.as_bytes();
+        if offset as usize >= info.len() {
+            return Ok(0);
+        }
+        let len = min(buf.len(), info.len() - offset as usize);
+        buf[..len].copy_from_slice(&info[offset as usize..offset as usize + len]);
+        Ok(len)
+    }
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let value = core::str::from_utf8(buf)
+            .map_err(|_| VfsError::Invalid)?
+            .trim_end_matches(|c| c == '\n' || c == '\0')
+            .trim();
+        self.cgroup()?.store(self.kind, value)?;
+        Ok(buf.len())
+    }
+}
+
+impl VfsInode for CgroupFile {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        self.perm
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_size: self.cgroup()?.show(self.kind).len() as u64,
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::File
+    }
+}
@@ -0,0 +1,553 @@
+//! 进程因信号终止时生成 ELF 格式的 core 文件。
+//!
+//! core 文件由 ELF 头、程序头表、一个 `PT_NOTE` 段以及若干 `PT_LOAD` 段组成：
+//! + `PT_NOTE` 段中依次包含产生 core 的线程的 `NT_PRSTATUS`(寄存器与信号状态)、`NT_PRPSINFO`(进程信息)、`NT_SIGINFO`、
+//! `NT_AUXV`(辅助向量) 与 `NT_FILE`(文件映射)，之后是线程组中其它线程各自的 `NT_PRSTATUS`，
+//! 其它线程的寄存器为其最近一次陷入内核时保存的值；
+//! + 每个 `PT_LOAD` 段对应用户地址空间中一段连续的、已经分配物理页且权限相同的区域，
+//! 区域来自程序的 `LOAD` 段、堆、用户栈以及 mmap 映射。尚未分配物理页的部分不会被写出。
+//!
+//! core 文件的路径由 `/proc/sys/kernel/core_pattern` 决定，文件大小受 `RLIMIT_CORE` 的限制。
+use alloc::{
+    collections::BTreeSet,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{mem::size_of, ops::Range};
+
+use config::FRAME_SIZE;
+use ksync::Mutex;
+use mem::VmmPageAllocator;
+use page_table::{addr::VirtAddr, pte::MappingFlags, table::Sv39PageTable};
+use platform::config::CLOCK_FREQ;
+use vfs::{kfile::File, proc::sysctl::core_pattern};
+use vfscore::{dentry::VfsDentry, file::VfsFile, inode::VfsInode};
+
+use crate::{
+    fs::create_file,
+    ipc::SigInfo,
+    task::{task::TaskInner, thread_group, Task},
+    time::realtime_now,
+};
+
+const ET_CORE: u16 = 4;
+const EM_RISCV: u16 = 243;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_SIGINFO: u32 = 0x53494749;
+const NT_FILE: u32 = 0x46494c45;
+
+/// `PF_X`、`PF_W`、`PF_R`
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct Elf64Ehdr {
+    e_ident: [u8; 16],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct Elf64Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+
+/// `NT_PRSTATUS` 的内容，与 Linux 中 riscv64 的 `struct elf_prstatus` 保持一致
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct ElfPrStatus {
+    si_signo: i32,
+    si_code: i32,
+    si_errno: i32,
+    pr_cursig: i16,
+    pr_sigpend: u64,
+    pr_sighold: u64,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    /// 用户态、内核态以及已回收子进程的用户态、内核态运行时间，每项为 `struct timeval`
+    pr_times: [[i64; 2]; 4],
+    /// pc 与 x1 - x31
+    pr_reg: [usize; 32],
+    pr_fpvalid: i32,
+}
+
+/// `NT_PRPSINFO` 的内容，与 Linux 中的 `struct elf_prpsinfo` 保持一致
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct ElfPrPsInfo {
+    pr_state: i8,
+    pr_sname: u8,
+    pr_zomb: i8,
+    pr_nice: i8,
+    pr_flag: u64,
+    pr_uid: u32,
+    pr_gid: u32,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_fname: [u8; 16],
+    pr_psargs: [u8; 80],
+}
+
+/// 一段需要写入 core 文件的用户内存，由若干个连续且权限相同的页组成
+struct CoreSegment {
+    start: usize,
+    /// 每一页对应的物理地址
+    pages: Vec<usize>,
+    flags: u32,
+}
+
+/// 线程组中一个线程的状态，对应一个 `NT_PRSTATUS`
+struct ThreadStatus {
+    tid: usize,
+    regs: [usize; 32],
+    sighold: usize,
+    times: [usize; 4],
+}
+
+impl ThreadStatus {
+    fn new(tid: usize, inner: &TaskInner) -> Self {
+        let trap_frame = inner.trap_frame();
+        let mut regs = [0; 32];
+        regs.copy_from_slice(trap_frame.regs());
+        regs[0] = trap_frame.sepc();
+        let data = inner.statistical_data();
+        let times = [
+            data.tms_utime,
+            data.tms_stime,
+            data.tms_cutime,
+            data.tms_cstime,
+        ];
+        let sighold = inner.signal_receivers.lock().mask.bits();
+        Self {
+            tid,
+            regs,
+            sighold,
+            times,
+        }
+    }
+}
+
+/// 生成 core 文件所需要的进程信息，在持有任务锁时收集
+struct CoreInfo {
+    pid: usize,
+    tid: usize,
+    ppid: usize,
+    pgrp: usize,
+    sid: usize,
+    name: String,
+    /// 线程组中所有线程的状态，第一个为产生 core 的线程
+    threads: Vec<ThreadStatus>,
+    auxv: Vec<(usize, usize)>,
+    /// 文件映射，(起始地址, 结束地址, 以页为单位的文件偏移, 文件路径)
+    files: Vec<(usize, usize, usize, String)>,
+    segments: Vec<CoreSegment>,
+    limit: u64,
+    /// 保证写出的过程中物理页不会被释放
+    _address_space: Arc<Mutex<Sv39PageTable<VmmPageAllocator>>>,
+}
+
+/// 将任意 `repr(C)` 的结构体视为字节序列
+fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+/// 向 `buf` 中追加一个名字为 `CORE` 的 note，名字与内容均按 4 字节对齐
+fn push_note(buf: &mut Vec<u8>, n_type: u32, desc: &[u8]) {
+    let name = b"CORE\0\0\0\0";
+    buf.extend_from_slice(&5u32.to_le_bytes());
+    buf.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&n_type.to_le_bytes());
+    buf.extend_from_slice(name);
+    buf.extend_from_slice(desc);
+    buf.resize((buf.len() + 3) & !3, 0);
+}
+
+/// 将以时钟周期为单位的时间转换为 `struct timeval`
+fn ticks_to_timeval(ticks: usize) -> [i64; 2] {
+    [
+        (ticks / CLOCK_FREQ) as i64,
+        ((ticks % CLOCK_FREQ) * 1_000_000 / CLOCK_FREQ) as i64,
+    ]
+}
+
+/// 将映射权限转换为程序头中的 `p_flags`
+fn segment_flags(flags: MappingFlags) -> u32 {
+    let mut p_flags = 0;
+    if flags.contains(MappingFlags::R) {
+        p_flags |= PF_R;
+    }
+    if flags.contains(MappingFlags::W) {
+        p_flags |= PF_W;
+    }
+    if flags.contains(MappingFlags::X) {
+        p_flags |= PF_X;
+    }
+    p_flags
+}
+
+/// 遍历 `ranges` 中的所有页，将已经分配物理页的部分按地址与权限的连续性合并为 [`CoreSegment`]
+fn collect_segments(
+    address_space: &Sv39PageTable<VmmPageAllocator>,
+    ranges: &[Range<usize>],
+) -> Vec<CoreSegment> {
+    let mut pages = BTreeSet::new();
+    ranges.iter().for_each(|range| {
+        let start = range.start & !(FRAME_SIZE - 1);
+        let end = (range.end + FRAME_SIZE - 1) & !(FRAME_SIZE - 1);
+        (start..end).step_by(FRAME_SIZE).for_each(|page| {
+            pages.insert(page);
+        });
+    });
+    let mut segments: Vec<CoreSegment> = Vec::new();
+    for page in pages {
+        let Ok((phy, flags, _)) = address_space.query(VirtAddr::from(page)) else {
+            continue;
+        };
+        if !flags.contains(MappingFlags::V) {
+            continue;
+        }
+        let flags = segment_flags(flags);
+        match segments.last_mut() {
+            Some(last)
+                if last.start + last.pages.len() * FRAME_SIZE == page && last.flags == flags =>
+            {
+                last.pages.push(phy.as_usize());
+            }
+            _ => segments.push(CoreSegment {
+                start: page,
+                pages: vec![phy.as_usize()],
+                flags,
+            }),
+        }
+    }
+    segments
+}
+
+/// 在持有任务锁的情况下收集生成 core 文件所需的信息，`RLIMIT_CORE` 不足一页时不生成 core 文件
+fn collect_info(task: &Arc<Task>) -> Option<CoreInfo> {
+    let inner = task.access_inner();
+    let limit = inner.core_limit.rlim_cur;
+    if limit < FRAME_SIZE as u64 {
+        return None;
+    }
+    let tid = task.get_tid() as usize;
+    let status = ThreadStatus::new(tid, &inner);
+    let ppid = inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map(|parent| parent.get_pid() as usize)
+        .unwrap_or(0);
+
+    let mut ranges = inner.elf_segments.clone();
+    {
+        let heap = inner.heap.lock();
+        ranges.push(heap.start..heap.current);
+    }
+    ranges.push(inner.stack.clone());
+    let mut files = Vec::new();
+    inner.mmap.regions().iter().for_each(|region| {
+        ranges.push(region.start..region.start + region.len);
+        if let Some(file) = &region.fd {
+            let end = (region.start + region.len + FRAME_SIZE - 1) & !(FRAME_SIZE - 1);
+            files.push((
+                region.start,
+                end,
+                region.offset / FRAME_SIZE,
+                file.dentry().path(),
+            ));
+        }
+    });
+    let address_space = inner.address_space.clone();
+    let segments = collect_segments(&address_space.lock(), &ranges);
+    let pgrp = inner.pgid;
+    let sid = inner.sid;
+    let name = inner.name.clone();
+    let auxv = inner.auxv.clone();
+    drop(inner);
+    // 每次只持有一个任务的锁
+    let pid = task.get_pid() as usize;
+    let mut threads = vec![status];
+    thread_group(pid)
+        .iter()
+        .filter(|thread| thread.get_tid() as usize != tid)
+        .for_each(|thread| {
+            let inner = thread.access_inner();
+            threads.push(ThreadStatus::new(thread.get_tid() as usize, &inner));
+        });
+    Some(CoreInfo {
+        pid,
+        tid,
+        ppid,
+        pgrp,
+        sid,
+        name,
+        threads,
+        auxv,
+        files,
+        segments,
+        limit,
+        _address_space: address_space,
+    })
+}
+
+/// 展开 core 文件的命名模板
+///
+/// 支持 `%p`(pid)、`%P`、`%i`(tid)、`%I`、`%e`(程序名)、`%s`(信号)、`%t`(时间戳)、`%h`(主机名)、
+/// `%u`、`%g` 与 `%%`，不支持的占位符会被忽略
+fn expand_pattern(pattern: &str, info: &CoreInfo, signum: usize) -> String {
+    let comm = info.name.rsplit('/').next().unwrap_or("");
+    let comm = &comm[..comm.len().min(15)];
+    let mut path = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            path.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('p') | Some('P') => path += &info.pid.to_string(),
+            Some('i') | Some('I') => path += &info.tid.to_string(),
+            Some('e') => path += comm,
+            Some('s') => path += &signum.to_string(),
+            Some('t') => path += &realtime_now().tv_sec.to_string(),
+            Some('h') => path += "Alien",
+            Some('u') | Some('g') => path.push('0'),
+            Some('%') => path.push('%'),
+            _ => {}
+        }
+    }
+    path
+}
+
+/// 构造 `PT_NOTE` 段的内容
+fn build_notes(info: &CoreInfo, siginfo: &SigInfo) -> Vec<u8> {
+    let prstatus = |thread: &ThreadStatus| ElfPrStatus {
+        si_signo: siginfo.si_signo,
+        si_code: siginfo.si_code,
+        si_errno: siginfo.si_errno,
+        pr_cursig: siginfo.si_signo as i16,
+        pr_sigpend: 0,
+        pr_sighold: thread.sighold as u64,
+        pr_pid: thread.tid as i32,
+        pr_ppid: info.ppid as i32,
+        pr_pgrp: info.pgrp as i32,
+        pr_sid: info.sid as i32,
+        pr_times: thread.times.map(ticks_to_timeval),
+        pr_reg: thread.regs,
+        pr_fpvalid: 0,
+    };
+    let mut notes = Vec::new();
+    push_note(
+        &mut notes,
+        NT_PRSTATUS,
+        as_bytes(&prstatus(&info.threads[0])),
+    );
+
+    let mut prpsinfo = ElfPrPsInfo {
+        pr_state: 0,
+        pr_sname: b'R',
+        pr_zomb: 0,
+        pr_nice: 0,
+        pr_flag: 0,
+        pr_uid: 0,
+        pr_gid: 0,
+        pr_pid: info.pid as i32,
+        pr_ppid: info.ppid as i32,
+        pr_pgrp: info.pgrp as i32,
+        pr_sid: info.sid as i32,
+        pr_fname: [0; 16],
+        pr_psargs: [0; 80],
+    };
+    let comm = info.name.rsplit('/').next().unwrap_or("").as_bytes();
+    let len = comm.len().min(15);
+    prpsinfo.pr_fname[..len].copy_from_slice(&comm[..len]);
+    let args = info.name.as_bytes();
+    let len = args.len().min(79);
+    prpsinfo.pr_psargs[..len].copy_from_slice(&args[..len]);
+    push_note(&mut notes, NT_PRPSINFO, as_bytes(&prpsinfo));
+
+    push_note(&mut notes, NT_SIGINFO, as_bytes(siginfo));
+
+    let auxv = info
+        .auxv
+        .iter()
+        .flat_map(|(key, value)| [*key, *value])
+        .flat_map(|x| x.to_le_bytes())
+        .collect::<Vec<u8>>();
+    push_note(&mut notes, NT_AUXV, &auxv);
+
+    let mut file_note = Vec::new();
+    file_note.extend_from_slice(&(info.files.len() as u64).to_le_bytes());
+    file_note.extend_from_slice(&(FRAME_SIZE as u64).to_le_bytes());
+    info.files.iter().for_each(|(start, end, offset, _)| {
+        file_note.extend_from_slice(&(*start as u64).to_le_bytes());
+        file_note.extend_from_slice(&(*end as u64).to_le_bytes());
+        file_note.extend_from_slice(&(*offset as u64).to_le_bytes());
+    });
+    info.files.iter().for_each(|(.., path)| {
+        file_note.extend_from_slice(path.as_bytes());
+        file_note.push(0);
+    });
+    push_note(&mut notes, NT_FILE, &file_note);
+    info.threads[1..].iter().for_each(|thread| {
+        push_note(&mut notes, NT_PRSTATUS, as_bytes(&prstatus(thread)));
+    });
+    notes
+}
+
+/// 写 core 文件，超出 `RLIMIT_CORE` 的部分会被丢弃
+struct CoreWriter {
+    inode: Arc<dyn VfsInode>,
+    offset: u64,
+    limit: u64,
+}
+
+impl CoreWriter {
+    /// 在当前位置写入 `data`，超出大小限制或写入失败时返回 false
+    fn write(&mut self, data: &[u8]) -> bool {
+        let remain = self.limit.saturating_sub(self.offset) as usize;
+        let len = data.len().min(remain);
+        let mut written = 0;
+        while written < len {
+            match self
+                .inode
+                .write_at(self.offset + written as u64, &data[written..len])
+            {
+                Ok(0) | Err(_) => return false,
+                Ok(n) => written += n,
+            }
+        }
+        self.offset += written as u64;
+        len == data.len()
+    }
+
+    /// 用 0 填充到 `offset` 处
+    fn pad_to(&mut self, offset: u64) -> bool {
+        let zero = [0u8; 512];
+        while self.offset < offset {
+            let len = (offset - self.offset).min(zero.len() as u64) as usize;
+            if !self.write(&zero[..len]) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// 为因信号 `siginfo` 终止的任务 `task` 生成 core 文件，返回是否完整地生成了 core 文件。
+///
+/// 与 Linux 相同，core 文件因 `RLIMIT_CORE` 被截断时也视为没有生成。调用时不能持有任务的锁。
+pub fn do_coredump(task: &Arc<Task>, siginfo: &SigInfo) -> bool {
+    let Some(info) = collect_info(task) else {
+        return false;
+    };
+    let pattern = core_pattern();
+    if pattern.starts_with('|') {
+        warn!("core_pattern pipe {} is not supported", pattern);
+        return false;
+    }
+    let path = expand_pattern(&pattern, &info, siginfo.signum());
+    let inode = match create_file(&path, 0o600) {
+        Ok(inode) => inode,
+        Err(e) => {
+            warn!("create core file {} failed: {:?}", path, e);
+            return false;
+        }
+    };
+    warn!("dump core of task {} to {}", info.tid, path);
+
+    let notes = build_notes(&info, siginfo);
+    let phnum = 1 + info.segments.len();
+    let notes_offset = size_of::<Elf64Ehdr>() + phnum * size_of::<Elf64Phdr>();
+    let data_offset = (notes_offset + notes.len() + FRAME_SIZE - 1) & !(FRAME_SIZE - 1);
+
+    let mut e_ident = [0u8; 16];
+    e_ident[..7].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+    let ehdr = Elf64Ehdr {
+        e_ident,
+        e_type: ET_CORE,
+        e_machine: EM_RISCV,
+        e_version: 1,
+        e_phoff: size_of::<Elf64Ehdr>() as u64,
+        e_ehsize: size_of::<Elf64Ehdr>() as u16,
+        e_phentsize: size_of::<Elf64Phdr>() as u16,
+        e_phnum: phnum as u16,
+        ..Default::default()
+    };
+    let mut headers = Vec::from(as_bytes(&ehdr));
+    let note_phdr = Elf64Phdr {
+        p_type: PT_NOTE,
+        p_offset: notes_offset as u64,
+        p_filesz: notes.len() as u64,
+        ..Default::default()
+    };
+    headers.extend_from_slice(as_bytes(&note_phdr));
+    let mut offset = data_offset;
+    info.segments.iter().for_each(|segment| {
+        let size = (segment.pages.len() * FRAME_SIZE) as u64;
+        let phdr = Elf64Phdr {
+            p_type: PT_LOAD,
+            p_flags: segment.flags,
+            p_offset: offset as u64,
+            p_vaddr: segment.start as u64,
+            p_paddr: 0,
+            p_filesz: size,
+            p_memsz: size,
+            p_align: FRAME_SIZE as u64,
+        };
+        headers.extend_from_slice(as_bytes(&phdr));
+        offset += size as usize;
+    });
+
+    let mut writer = CoreWriter {
+        inode,
+        offset: 0,
+        limit: info.limit,
+    };
+    if !writer.write(&headers) || !writer.write(&notes) || !writer.pad_to(data_offset as u64) {
+        return false;
+    }
+    for segment in info.segments.iter() {
+        for phy in segment.pages.iter() {
+            // 内核中物理地址与虚拟地址相同
+            let page = unsafe { core::slice::from_raw_parts(*phy as *const u8, FRAME_SIZE) };
+            if !writer.write(page) {
+                return false;
+            }
+        }
+    }
+    true
+}
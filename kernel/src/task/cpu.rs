@@ -1,17 +1,18 @@
 //! Alien 中有关进程的系统调用 和 多核的相关支持。
 use alloc::{
+    format,
     string::{String, ToString},
     sync::Arc,
+    vec,
     vec::Vec,
 };
-use core::cell::UnsafeCell;
+use core::{cell::UnsafeCell, cmp::min};
 
 use config::CPU_NUM;
 use constants::{
-    ipc::FutexOp,
     signal::SignalNumber,
     task::{CloneFlags, WaitOptions},
-    AlienError, AlienResult, PrLimitResType, RLimit64,
+    AlienError, AlienResult, LinuxErrno, PrLimitResType, RLimit64,
 };
 use ksync::Mutex;
 use log::{info, warn};
@@ -19,13 +20,21 @@ use platform::system_shutdown;
 use smpscheduler::{FifoSmpScheduler, FifoTask, ScheduleHart};
 use spin::Lazy;
 use syscall_table::syscall_func;
+use vfs::proc::binfmt::binfmt_match;
 
 use crate::{
     fs,
-    ipc::{futex, global_logoff_signals},
+    ipc::{
+        futex::{exit_robust_list, futex_wake, FUTEX_BITSET_MATCH_ANY},
+        global_logoff_signals, send_signal,
+    },
     task::{
         context::Context,
+        namespace::{current_ns, find_vpid, pid_vnr},
         schedule::schedule,
+        session::{
+            check_orphaned_pgrp, find_task, process_group, set_process_group, unregister_task,
+        },
         task::{Task, TaskState},
         INIT_PROCESS,
     },
@@ -39,6 +48,10 @@ pub struct CPU {
     pub task: Option<Arc<Task>>,
     /// 当前线程的上下文
     pub context: Context,
+    /// 上一次为当前线程记账 CPU 时间的时刻，以 cpu 时钟周期数表示
+    pub switch_time: usize,
+    /// 当前线程被调度后经过的时钟节拍数
+    pub ticks: usize,
 }
 
 impl CPU {
@@ -47,6 +60,8 @@ impl CPU {
         Self {
             task: None,
             context: Context::empty(),
+            switch_time: 0,
+            ticks: 0,
         }
     }
 
@@ -120,27 +135,70 @@ pub fn current_trap_frame() -> &'static mut TrapFrame {
 /// 等待父进程得知其终止退出后，将回收该进程的其余资源。
 /// `exit_code`中的值，将会在其父进程调用[`wait4`]时，作为信息传递给父进程。
 /// 当一个具有子进程的进程终止时，其所有子进程将转交至init进程，由init进程完成其子进程相关资源的回收。
+/// 退出前会释放线程通过 `set_robust_list` 注册的仍然持有的 robust 锁。
 /// 当`clear_child_tid`不为0时，会将`clear_child_tid`该处的值置为0，同时内核唤醒当前正在等待的futex。
 ///
 /// 当调用该函数的进程为`pid==0`的init进程时，将直接调用`system_shutdown`使得内核终止。
 #[syscall_func(93)]
 pub fn do_exit(exit_code: i32, exit_group: u8) -> isize {
+    exit_with_status((exit_code & 0xff) << 8, exit_group)
+}
+
+/// 因为收到信号 `signum` 而终止当前进程，`core_dumped` 表示是否产生了 core dump。
+///
+/// 父进程通过 [`wait4`] 得到的状态中，低 7 位为终止进程的信号，第 8 位为 core dump 标志。
+pub fn do_exit_by_signal(signum: usize, core_dumped: bool) -> isize {
+    let mut status = signum as i32 & 0x7f;
+    if core_dumped {
+        status |= 0x80;
+    }
+    exit_with_status(status, 1)
+}
+
+/// 以 `wait4` 中的状态 `exit_code` 终止当前进程
+fn exit_with_status(exit_code: i32, exit_group: u8) -> isize {
     let task = current_task().unwrap();
-    let exit_code = (exit_code & 0xff) << 8;
     if task.get_pid() == 1 {
         println!("Init process exit with code {}", exit_code);
         system_shutdown();
     }
+    // 退出的进程不再属于任何进程组
+    unregister_task(task.get_tid() as usize);
+    let tid = task.get_tid() as usize;
+    let pid_ns = task.access_inner().ns.pid.clone();
+    // PID 命名空间中的 1 号进程退出时，命名空间中的其它任务都会被终止，否则子进程由命名空间中的 1 号进程收养
+    let is_reaper = !pid_ns.is_root() && pid_ns.local_id(tid) == Some(1);
+    if is_reaper {
+        pid_ns
+            .members()
+            .into_iter()
+            .filter(|member| *member != tid)
+            .for_each(|member| send_signal(member, SignalNumber::SIGKILL as usize));
+    }
+    let reaper = pid_ns
+        .child_reaper()
+        .filter(|reaper| !is_reaper && reaper.get_pid() != task.get_pid());
+    let mut pgids = Vec::new();
     {
-        let init = INIT_PROCESS.clone();
+        let init = reaper.unwrap_or(INIT_PROCESS.clone());
         task.take_children().into_iter().for_each(|child| {
             if exit_group != 0 {
                 child.set_exit_group();
             }
+            pgids.push(child.pgid());
             child.update_parent(init.clone());
             init.insert_child(child);
         });
     }
+    // 进程退出可能使自己所在的进程组与子进程所在的进程组成为孤儿进程组
+    if task.get_pid() == task.get_tid() {
+        pgids.push(task.pgid());
+        pgids.sort();
+        pgids.dedup();
+        pgids.into_iter().for_each(check_orphaned_pgrp);
+    }
+    // 释放线程仍然持有的 robust 锁，需要在地址空间被回收之前进行
+    exit_robust_list(task);
     task.update_state(TaskState::Zombie);
     task.update_exit_code(exit_code);
     global_logoff_signals(task.get_tid() as usize);
@@ -153,7 +211,7 @@ pub fn do_exit(exit_code: i32, exit_group: u8) -> isize {
         let phy_addr = task.transfer_raw_ptr(clear_child_tid as *mut usize);
         *phy_addr = 0;
         info!("exit wake futex on {:#x}", clear_child_tid);
-        futex(clear_child_tid, FutexOp::FutexWake as u32, 1, 0, 0, 0);
+        let _ = futex_wake(clear_child_tid, 1, FUTEX_BITSET_MATCH_ANY);
     } else {
         info!("exit clear_child_tid is 0");
     }
@@ -187,29 +245,95 @@ pub fn do_suspend() -> isize {
     0
 }
 
-/// (待实现)设置进程组的id。目前直接返回0。
+/// 一个系统调用，将进程 `pid` 加入进程组 `pgid`。
+///
+/// `pid` 为 0 时表示当前进程，`pgid` 为 0 时表示使用 `pid` 作为进程组的 id，即创建一个新的进程组。
+/// 只能修改当前进程或其子进程，否则返回 `ESRCH`；目标进程是会话的首进程、与当前进程不在同一会话中，
+/// 或者 `pgid` 指向的进程组不在当前会话中时返回 `EPERM`。
+///
+/// Reference: [setpgid](https://man7.org/linux/man-pages/man2/setpgid.2.html)
 #[syscall_func(154)]
-pub fn set_pgid() -> isize {
-    0
+pub fn set_pgid(pid: usize, pgid: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let pid = if pid == 0 {
+        task.get_pid() as usize
+    } else {
+        find_vpid(pid).ok_or(LinuxErrno::ESRCH)?
+    };
+    let pgid = if pgid == 0 {
+        pid
+    } else {
+        find_vpid(pgid).ok_or(LinuxErrno::EPERM)?
+    };
+    let target = find_task(pid)
+        .filter(|target| target.get_pid() == target.get_tid())
+        .ok_or(LinuxErrno::ESRCH)?;
+    let is_child = target
+        .parent()
+        .map(|parent| parent.get_pid() == task.get_pid())
+        .unwrap_or(false);
+    if pid != task.get_pid() as usize && !is_child {
+        return Err(LinuxErrno::ESRCH);
+    }
+    let sid = task.sid();
+    if target.sid() != sid || target.sid() == pid {
+        return Err(LinuxErrno::EPERM);
+    }
+    if pgid != pid && !process_group(pgid).iter().any(|member| member.sid() == sid) {
+        return Err(LinuxErrno::EPERM);
+    }
+    warn!("set pgid of {} to {}", pid, pgid);
+    set_process_group(pid, pgid, sid);
+    Ok(0)
 }
 
-/// (待实现)获取进程组的id。目前直接返回0。
+/// 一个系统调用，获取进程 `pid` 所在进程组的 id，`pid` 为 0 时表示当前进程。进程不存在时返回 `ESRCH`。
 #[syscall_func(155)]
-pub fn get_pgid() -> isize {
-    0
+pub fn get_pgid(pid: usize) -> AlienResult<isize> {
+    if pid == 0 {
+        return Ok(pid_vnr(current_task().unwrap().pgid()) as isize);
+    }
+    let task = find_vpid(pid)
+        .and_then(find_task)
+        .ok_or(LinuxErrno::ESRCH)?;
+    Ok(pid_vnr(task.pgid()) as isize)
+}
+
+/// 一个系统调用，获取进程 `pid` 所在会话的 id，`pid` 为 0 时表示当前进程。进程不存在时返回 `ESRCH`。
+#[syscall_func(156)]
+pub fn get_sid(pid: usize) -> AlienResult<isize> {
+    if pid == 0 {
+        return Ok(pid_vnr(current_task().unwrap().sid()) as isize);
+    }
+    let task = find_vpid(pid)
+        .and_then(find_task)
+        .ok_or(LinuxErrno::ESRCH)?;
+    Ok(pid_vnr(task.sid()) as isize)
 }
 
-/// 创建一个新的session，并使得使用系统调用的当前task成为新session的leader，同时也是新进程组的leader(待实现)
+/// 创建一个新的会话，并使得使用系统调用的当前进程成为新会话的首进程，同时也是新进程组的首进程。
+///
+/// 当前进程已经是某个进程组的首进程时返回 `EPERM`，成功时返回新会话的 id。
+///
+/// Reference: [setsid](https://man7.org/linux/man-pages/man2/setsid.2.html)
 #[syscall_func(157)]
-pub fn set_sid() -> isize {
-    0
+pub fn set_sid() -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let pid = task.get_pid() as usize;
+    if !process_group(pid).is_empty() {
+        return Err(LinuxErrno::EPERM);
+    }
+    warn!("create session {}", pid);
+    set_process_group(pid, pid, pid);
+    Ok(pid_vnr(pid) as isize)
 }
 
 /// 获取当前正在运行task的pid号。在Alien中pid作为线程组的标识符，位于同一线程组中的线程的pid相同。
+/// 返回的是当前task所在的 PID 命名空间中的 id。
 #[syscall_func(172)]
 pub fn get_pid() -> isize {
     let process = current_task().unwrap();
-    process.get_pid()
+    pid_vnr(process.get_pid() as usize) as isize
 }
 
 /// 获取当前正在运行task的ppid号，即父task的pid号。
@@ -220,7 +344,8 @@ pub fn get_ppid() -> isize {
     if parent.is_none() {
         return 0;
     } else {
-        parent.unwrap().upgrade().unwrap().get_pid()
+        // 父进程在当前 PID 命名空间中不可见时返回 0
+        pid_vnr(parent.unwrap().upgrade().unwrap().get_pid() as usize) as isize
     }
 }
 
@@ -248,11 +373,11 @@ pub fn getegid() -> isize {
     0
 }
 
-/// 获取当前正在运行task的tid号。在Alien中tid作为task的唯一标识符。
+/// 获取当前正在运行task的tid号。在Alien中tid作为task的唯一标识符，返回的是当前task所在的 PID 命名空间中的 id。
 #[syscall_func(178)]
 pub fn get_tid() -> isize {
     let process = current_task().unwrap();
-    process.get_tid()
+    pid_vnr(process.get_tid() as usize) as isize
 }
 
 /// 一个系统调用，用于创建一个子进程。
@@ -268,11 +393,22 @@ pub fn get_tid() -> isize {
 /// `tls`用于为子进程创建新的TLS(thread-local storage)值，在flag包含`CLONE_SETTLS`时才会实际产生效果。
 /// `ctid`用于给子进程中的[`set_child_tid`]和[`clear_child_tid`]赋值(分别在flag中包含`CLONE_CHILD_SETTID`和`CLONE_CHILD_CLEARTID`时产生效果)。
 ///
-/// 成功创建子进程后父进程会返回子进程的tid号，子进程的返回值将被设置为0；否则返回-1。
+/// `flag`中的`CLONE_NEWNS`、`CLONE_NEWUTS`、`CLONE_NEWIPC`与`CLONE_NEWPID`用于为子进程创建新的命名空间，
+/// 具体可见[`namespace`](super::namespace)。
+///
+/// 成功创建子进程后父进程会返回子进程在父进程所在的 PID 命名空间中的tid号，子进程的返回值将被设置为0；
+/// 创建线程时不能进入新的 PID 命名空间，否则返回`EINVAL`；所在的 cgroup 中的任务数达到 `pids.max` 时返回`EAGAIN`；
+/// 创建失败时返回`ENOMEM`。
 ///
 /// Reference: [clone](https://www.man7.org/linux/man-pages/man2/clone.2.html)
 #[syscall_func(220)]
-pub fn clone(flag: usize, stack: usize, ptid: usize, tls: usize, ctid: usize) -> isize {
+pub fn clone(
+    flag: usize,
+    stack: usize,
+    ptid: usize,
+    tls: usize,
+    ctid: usize,
+) -> AlienResult<isize> {
     let clone_flag = CloneFlags::from_bits_truncate(flag as u32);
     // check whether flag include signal
     let sig = flag & 0xff;
@@ -284,17 +420,55 @@ pub fn clone(flag: usize, stack: usize, ptid: usize, tls: usize, ctid: usize) ->
         do_suspend();
         task = current_task().unwrap();
     }
-    let new_task = task.t_clone(clone_flag, stack, sig, ptid, tls, ctid);
-    if new_task.is_none() {
-        return -1;
-    }
-    let new_task = new_task.unwrap();
+    let ns = current_ns().copy(flag)?;
+    let cgroup = task.cgroup();
+    cgroup.try_charge_pid()?;
+    let Some(new_task) = task.t_clone(clone_flag, stack, sig, ptid, tls, ctid, ns) else {
+        cgroup.uncharge_pid();
+        return Err(LinuxErrno::ENOMEM);
+    };
     // update return value
     let trap_frame = new_task.trap_frame();
     trap_frame.update_res(0);
-    let tid = new_task.get_tid();
+    let tid = pid_vnr(new_task.get_tid() as usize);
     GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(new_task)));
-    tid
+    Ok(tid as isize)
+}
+
+/// 解释器嵌套的最大层数，即一个脚本的解释器本身又是脚本的情况最多允许出现的次数
+const BINPRM_MAX_RECURSION: usize = 4;
+/// 解析 `#!` 行时最多读取的字节数
+const BINPRM_BUF_SIZE: usize = 256;
+
+/// 解析脚本开头的 `#!interpreter [arg]` 行，返回解释器的路径与可选的一个参数。
+///
+/// 与 Linux 相同，解释器之后的所有内容 (去掉首尾空白后) 作为一个参数传递给解释器。
+/// 该行以换行符、NUL 或者数据的结尾结束，因此没有换行符的 `#!` 行同样可以被识别。
+fn parse_shebang(data: &[u8]) -> AlienResult<Option<(String, Option<String>)>> {
+    if !data.starts_with(b"#!") {
+        return Ok(None);
+    }
+    let data = &data[2..min(data.len(), BINPRM_BUF_SIZE)];
+    // 读取的文件内容可能在末尾用 0 填充，第一个 NUL 与文件结尾一样结束解释器所在的行
+    let line = match data.iter().position(|&b| b == b'\n' || b == 0) {
+        Some(end) => &data[..end],
+        // the interpreter must not be truncated
+        None if data.len() + 2 >= BINPRM_BUF_SIZE => return Err(AlienError::ENOEXEC),
+        None => data,
+    };
+    let line = core::str::from_utf8(line).map_err(|_| AlienError::ENOEXEC)?;
+    let line = line.trim_matches(|c| c == ' ' || c == '\t' || c == '\r');
+    if line.is_empty() {
+        return Err(AlienError::ENOEXEC);
+    }
+    let (interp, arg) = match line.find(|c| c == ' ' || c == '\t') {
+        Some(pos) => {
+            let arg = line[pos..].trim_matches(|c| c == ' ' || c == '\t');
+            (&line[..pos], (!arg.is_empty()).then(|| arg.to_string()))
+        }
+        None => (line, None),
+    };
+    Ok(Some((interp.to_string(), arg)))
 }
 
 /// 一个系统调用，用于执行一个文件。
@@ -303,6 +477,9 @@ pub fn clone(flag: usize, stack: usize, ptid: usize, tls: usize, ctid: usize) ->
 /// `args_ptr`用于指明保存启动可执行文件时要传入的参数的地址。
 /// `env`用于指明保存相关环境变量的地址。
 ///
+/// 如果文件以 `#!` 开头，或者匹配 binfmt_misc 中注册的某条规则，则会转而执行对应的解释器，
+/// 并将文件的路径作为参数传递给解释器。解释器本身也可以是脚本，但嵌套的层数不能超过 [`BINPRM_MAX_RECURSION`]。
+///
 /// 成功执行文件后会返回0；否则会返回-1或错误类型。
 #[syscall_func(221)]
 pub fn do_exec(path: *const u8, args_ptr: usize, env: usize) -> AlienResult<isize> {
@@ -312,55 +489,118 @@ pub fn do_exec(path: *const u8, args_ptr: usize, env: usize) -> AlienResult<isiz
     let (mut args, envs) = parse_user_arg_env(args_ptr, env);
     warn!("exec path: {}", path_str);
     warn!("exec args: {:?} ,env: {:?}", args, envs);
-    if path_str.ends_with(".sh") {
-        if args.is_empty() {
-            let mut new_path = path_str.clone();
-            new_path.push('\0');
-            args.insert(0, new_path);
-        }
-        path_str = "./busybox".to_string();
-        args.insert(0, "sh\0".to_string());
-    }
-    let mut data = Vec::new();
     if path_str.contains("libc-bench") {
         path_str = "libc-bench2".to_string();
     }
-    if fs::read_all(&path_str, &mut data) {
-        let res = task.exec(&path_str, data.as_slice(), args, envs);
-        if res.is_err() {
-            return Err(AlienError::ENOEXEC);
+    let name = path_str.clone();
+    let mut data = Vec::new();
+    let mut depth = 0;
+    loop {
+        data.clear();
+        if !fs::read_all(&path_str, &mut data) {
+            info!("exec {} failed", path_str);
+            return Err(AlienError::ENOENT);
         }
-        Ok(0)
-    } else {
-        info!("exec {} failed", path_str);
-        Err(AlienError::ENOENT)
+        let mut file_arg = path_str.clone();
+        file_arg.push('\0');
+        let (interp, new_args) = if let Some((interp, arg)) = parse_shebang(&data)? {
+            // argv: interpreter [arg] path argv[1..]
+            let mut new_args = vec![format!("{}\0", interp)];
+            if let Some(arg) = arg {
+                new_args.push(format!("{}\0", arg));
+            }
+            new_args.push(file_arg);
+            (interp, new_args)
+        } else if let Some(handler) = binfmt_match(&path_str, &data) {
+            // argv: interpreter (argv[0] or path) argv[1..]
+            let argv0 = match args.first() {
+                Some(argv0) if handler.preserve_argv0 => argv0.clone(),
+                _ => file_arg,
+            };
+            let interp = handler.interpreter;
+            (interp.clone(), vec![format!("{}\0", interp), argv0])
+        } else {
+            break;
+        };
+        depth += 1;
+        if depth > BINPRM_MAX_RECURSION {
+            return Err(AlienError::ELOOP);
+        }
+        warn!("exec {} with interpreter {}", path_str, interp);
+        let mut new_args = new_args;
+        new_args.extend(args.into_iter().skip(1));
+        args = new_args;
+        path_str = interp;
     }
+    let res = task.exec(&name, data.as_slice(), args, envs);
+    if res.is_err() {
+        return Err(AlienError::ENOEXEC);
+    }
+    Ok(0)
 }
 
-/// 一个系统调用，用于父进程等待某子进程退出。
+/// `wait4` 的选项，同时等待被暂停的子进程
+const WUNTRACED: u32 = 2;
+/// `wait4` 的选项，同时等待收到 SIGCONT 继续执行的子进程
+const WCONTINUED: u32 = 8;
+/// `wait4` 的选项，只获取子进程的状态而不回收子进程或清除其状态
+const WNOWAIT: u32 = 0x1000000;
+
+/// 子进程 `child` 是否为 `wait4` 中 `pid` 指定的等待对象，`pgid` 为调用者所在的进程组
+fn wait_target_match(child: &Arc<Task>, pid: isize, pgid: usize) -> bool {
+    match pid {
+        -1 => true,
+        0 => child.pgid() == pgid,
+        pid if pid < 0 => child.pgid() == (-pid) as usize,
+        pid => child.get_pid() == pid,
+    }
+}
+
+/// 一个系统调用，用于父进程等待某子进程的状态改变。
 ///
-/// `pid`用于指明等待的子进程pid号。`pid == -1`表示父进程等待任意子进程返回。
-/// 当`exit_code`非空时，将会把退出的子程序的退出值赋给`exit_code`所指向的位置。
-/// `options`主要用于控制`wait4`的执行逻辑，例如当`wait_options`包含`WNOHANG`时，即使未发现子程序返回，函数也将直接返回0。
+/// `pid`用于指明等待的子进程：`pid > 0` 表示等待进程号为 pid 的子进程，`pid == -1`表示等待任意子进程，
+/// `pid == 0` 表示等待与调用者在同一进程组中的子进程，`pid < -1` 表示等待进程组 `-pid` 中的子进程。
+/// 当`exit_code`非空时，将会把子进程的状态赋给`exit_code`所指向的位置。
+/// `options`主要用于控制`wait4`的执行逻辑：
+/// + 包含`WNOHANG`时，即使未发现子程序状态改变，函数也将直接返回0；
+/// + 包含`WUNTRACED`时，被停止信号暂停的子进程也会被返回，状态为 `(signum << 8) | 0x7f`；
+/// + 包含`WCONTINUED`时，收到 SIGCONT 继续执行的子进程也会被返回，状态为 `0xffff`；
+/// + 包含`WNOWAIT`时，子进程的状态不会被清除。
 ///
-/// 一般`wait4`会使得父进程阻塞，直到子进程退出，返回退出的子进程pid。但当`wait_options`包含`WNOHANG`时，即使未发现子程序返回，函数也将直接返回0。
-/// 当父进程的所有子进程中不包含进程号为pid的子进程，将返回-1。
+/// `pid`与返回的子进程pid均为当前进程所在的 PID 命名空间中的 id。
+/// 一般`wait4`会使得父进程阻塞，直到子进程退出，返回状态改变的子进程pid。
+/// 当父进程的所有子进程中不包含满足 `pid` 的子进程，将返回-1。
 ///
 /// Reference:[wait](https://man7.org/linux/man-pages/man2/wait.2.html)
 #[syscall_func(260)]
 pub fn wait4(pid: isize, exit_code: *mut i32, options: u32, _rusage: *const u8) -> isize {
+    // 将当前 PID 命名空间中的 id 转换为全局 id
+    let pid = match pid {
+        -1 | 0 => pid,
+        pid => match find_vpid(pid.unsigned_abs()) {
+            Some(global) => global as isize * pid.signum(),
+            None => return -1,
+        },
+    };
     loop {
         let task = current_task().unwrap();
-        if task
+        let pgid = task.pgid();
+        if !task
             .children()
             .iter()
-            .find(|child| child.get_pid() == pid || pid == -1)
-            .is_none()
+            .any(|child| wait_target_match(child, pid, pgid))
         {
             return -1;
         }
-        let res = task.check_child(pid);
+        let res = task.check_child(|child| wait_target_match(child, pid, pgid));
         if let Some(index) = res {
+            if options & WNOWAIT != 0 {
+                let child = task.children()[index].clone();
+                if !exit_code.is_null() {
+                    *task.transfer_raw_ptr(exit_code) = child.exit_code();
+                }
+                return pid_vnr(child.get_tid() as usize) as isize;
+            }
             let child = task.remove_child(index);
             assert_eq!(
                 Arc::strong_count(&child),
@@ -375,14 +615,40 @@ pub fn wait4(pid: isize, exit_code: *mut i32, options: u32, _rusage: *const u8)
                 let exit_code_ref = task.transfer_raw_ptr(exit_code);
                 *exit_code_ref = child.exit_code();
             }
-            return child.get_tid();
-        } else {
-            let wait_options = WaitOptions::from_bits(options).unwrap();
-            if wait_options.contains(WaitOptions::WNOHANG) {
-                return 0;
-            } else {
-                do_suspend();
+            return pid_vnr(child.get_tid() as usize) as isize;
+        }
+        // 被暂停或继续执行的子进程
+        let job = task
+            .children()
+            .iter()
+            .filter(|child| wait_target_match(child, pid, pgid))
+            .find_map(|child| {
+                let mut inner = child.access_inner();
+                let status = inner.job_status?;
+                let wanted = if status == 0xffff {
+                    options & WCONTINUED != 0
+                } else {
+                    options & WUNTRACED != 0
+                };
+                if !wanted {
+                    return None;
+                }
+                if options & WNOWAIT == 0 {
+                    inner.job_status = None;
+                }
+                Some((child.get_tid(), status))
+            });
+        if let Some((tid, status)) = job {
+            if !exit_code.is_null() {
+                *task.transfer_raw_ptr(exit_code) = status;
             }
+            return pid_vnr(tid as usize) as isize;
+        }
+        let wait_options = WaitOptions::from_bits_truncate(options);
+        if wait_options.contains(WaitOptions::WNOHANG) {
+            return 0;
+        } else {
+            do_suspend();
         }
     }
 }
@@ -417,9 +683,51 @@ pub fn do_brk(addr: usize) -> isize {
 pub fn set_tid_address(tidptr: usize) -> isize {
     let task = current_task().unwrap();
     task.set_tid_address(tidptr);
-    task.get_tid()
+    pid_vnr(task.get_tid() as usize) as isize
+}
+
+/// `personality` 中关闭地址空间随机化的标志
+pub const ADDR_NO_RANDOMIZE: u32 = 0x0040000;
+/// 调用 `personality` 时传入该值表示只查询当前的执行域而不修改
+const PERSONALITY_QUERY: u32 = 0xffffffff;
+
+/// 一个系统调用，用于设置进程的执行域，返回原来的执行域。
+///
+/// 目前只有 [`ADDR_NO_RANDOMIZE`] 标志会产生效果，设置后该进程之后 `exec` 的程序将不会进行地址空间随机化。
+/// `persona` 为 `0xffffffff` 时只返回当前的执行域。
+///
+/// Reference: [personality](https://man7.org/linux/man-pages/man2/personality.2.html)
+#[syscall_func(92)]
+pub fn personality(persona: u32) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    let old = inner.personality;
+    if persona != PERSONALITY_QUERY {
+        inner.personality = persona;
+    }
+    old as isize
+}
+
+/// 一个系统调用，获取当前任务所在的 cpu 编号与 NUMA 节点编号，节点编号总是 0。
+///
+/// vDSO 中的 `__vdso_getcpu` 在只有一个 cpu 时直接返回，否则会通过该系统调用获取。
+///
+/// Reference: [getcpu](https://man7.org/linux/man-pages/man2/getcpu.2.html)
+#[syscall_func(168)]
+pub fn getcpu(cpu: *mut u32, node: *mut u32) -> isize {
+    let task = current_task().unwrap();
+    if !cpu.is_null() {
+        *task.transfer_raw_ptr(cpu) = arch::hart_id() as u32;
+    }
+    if !node.is_null() {
+        *task.transfer_raw_ptr(node) = 0;
+    }
+    0
 }
 
+/// 资源类型 `RLIMIT_CORE`
+const RLIMIT_CORE: usize = 4;
+
 /// 一个系统调用，用于修改进程的资源限制。
 ///
 /// 进程对其拥有的资源，包括用户栈大小、可以打开的文件描述符数、用户地址空间大小等都有所上限。
@@ -430,12 +738,32 @@ pub fn set_tid_address(tidptr: usize) -> isize {
 /// `new_limit`用于指明新限制的指针，如果为空指针则不进行新限制的赋值。
 /// `old_limit`用于指明存放旧限制的指针，如果为空则不进行旧限制的保存。
 ///
+/// 除此之外还支持 `RLIMIT_CORE`，用于限制进程因信号终止时生成的 core 文件的大小，软上限为 0 时不生成 core 文件。
+///
 /// 正确执行后会返回0；如果输入的pid为0或者为当前正在运行的进程号，则会直接终止。
 #[syscall_func(261)]
 pub fn prlimit64(pid: usize, resource: usize, new_limit: *const u8, old_limit: *mut u8) -> isize {
     assert!(pid == 0 || pid == current_task().unwrap().get_pid() as usize);
     let task = current_task().unwrap();
     let mut inner = task.access_inner();
+    if resource == RLIMIT_CORE {
+        let mut limit = RLimit64::new(0, 0);
+        if !new_limit.is_null() {
+            inner.copy_from_user(new_limit as *const RLimit64, &mut limit);
+            if limit.rlim_cur > limit.rlim_max {
+                return LinuxErrno::EINVAL as isize;
+            }
+        }
+        if !old_limit.is_null() {
+            let old = inner.core_limit;
+            inner.copy_to_user(&old, old_limit as *mut RLimit64);
+        }
+        if !new_limit.is_null() {
+            warn!("set rlimit core to {:?}", limit);
+            inner.core_limit = limit;
+        }
+        return 0;
+    }
     if let Ok(resource) = PrLimitResType::try_from(resource) {
         if !old_limit.is_null() {
             let limit = inner.get_prlimit(resource);
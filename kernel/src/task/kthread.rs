@@ -15,14 +15,18 @@ use vfs::kfile::File;
 
 use crate::{
     fs::stdio::{STDIN, STDOUT},
+    ipc::sem::SemUndoList,
     mm::map::MMapInfo,
     task::{
+        cgroup::root_cgroup,
         context::Context,
+        namespace::NsProxy,
         resource::{HeapInfo, TidHandle},
         stack::Stack,
         task::{TaskInner, TaskTimer},
         FsContext, StatisticalData, Task, TaskState, GLOBAL_TASK_MANAGER,
     },
+    time::posix_timer::PosixTimers,
 };
 
 type FdManager = MinimalManager<Arc<dyn File>>;
@@ -35,10 +39,13 @@ pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
     let cwd = vfs::system_root_fs();
     let k_stack_top = k_stack.top();
     let func_ptr = func as usize;
+    let cgroup = root_cgroup();
+    cgroup.charge_pid();
     let task = Task {
         tid,
         kernel_stack: k_stack,
         pid,
+        cgroup: Mutex::new(cgroup),
         inner: Mutex::new(TaskInner {
             name: name.to_string(),
             threads: MinimalManager::new(MAX_THREAD_NUM),
@@ -56,6 +63,7 @@ pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
             },
             context: Context::new(func_ptr, k_stack_top),
             fs_info: FsContext::new(cwd.clone(), cwd),
+            ns: NsProxy::init(),
             statistical_data: StatisticalData::new(),
             timer: TaskTimer::default(),
             exit_code: 0,
@@ -65,10 +73,19 @@ pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
             signal_receivers: Arc::new(Mutex::new(SignalReceivers::new())),
             set_child_tid: 0,
             clear_child_tid: 0,
-            trap_cx_before_signal: None,
-            signal_set_siginfo: false,
+            saved_sigmask: None,
+            syscall_restart: None,
             robust: RobustList::default(),
             shm: BTreeMap::new(),
+            sem_undo: Arc::new(Mutex::new(SemUndoList::new())),
+            posix_timers: Arc::new(Mutex::new(PosixTimers::new())),
+            personality: 0,
+            elf_segments: Vec::new(),
+            auxv: Vec::new(),
+            core_limit: RLimit64::new(0, 0),
+            pgid: 0,
+            sid: 0,
+            job_status: None,
             cpu_affinity: {
                 let mut affinity = 0;
                 affinity.set_bits(0..CPU_NUM, 1 << CPU_NUM - 1);
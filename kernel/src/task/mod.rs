@@ -1,14 +1,20 @@
 //! Alien 中有关进程管理的相关数据结构
 //!
+//! [`cgroup`] 子模块实现了 cgroup v2 的层级结构以及 CPU、内存与任务数控制器。
 //! [`context`] 子模块定义了 Alien 中线程上下文的相关结构.
+//! [`coredump`] 子模块用于在进程因信号终止时生成 core 文件。
 //! [`cpu`] 子模块中指明了 Alien 中有关进程的系统调用 和 多核的相关支持。
 //! [`heap`] 子模块定义了 Alien 记录进程堆空间的相关信息的结构。
+//! [`namespace`] 子模块定义了 Alien 中的挂载、PID、UTS 与 IPC 命名空间。
+//! [`session`] 子模块记录了 Alien 中的进程组与会话。
 //! [`schedule`] 子模块指明了 Alien 中有关 CPU 调度的相关机制
 //! [`stack`] 子模块定义了 Alien 中有关内核栈的相关结构。
 //! [`task`] 子模块定义了 Alien 中有关进程控制块的定义。
 use alloc::{sync::Arc, vec::Vec};
 
+pub use coredump::do_coredump;
 pub use cpu::*;
+pub use session::{find_task, is_orphaned_pgrp, process_group, processes, tasks, thread_group};
 use shim::{KTask, KTaskShim};
 use smpscheduler::FifoTask;
 use spin::Lazy;
@@ -16,30 +22,57 @@ pub use task::{StatisticalData, Task, TaskState};
 use timer::get_time_ms;
 
 pub use crate::task::task::FsContext;
-use crate::{fs::read_all, task::schedule::schedule_now};
+use crate::{fs::read_all, ipc::kill_pgrp, task::schedule::schedule_now};
 
+pub mod cgroup;
 mod context;
 mod control;
+mod coredump;
 mod cpu;
 mod kthread;
+pub mod namespace;
 mod resource;
 pub mod schedule;
+mod session;
 mod stack;
 mod task;
 
+/// 命令行没有通过 `init=` 指定或者指定的程序无法运行时，依次尝试的初始进程
+const DEFAULT_INIT: [&str; 5] = [
+    "/tests/init",
+    "/sbin/init",
+    "/etc/init",
+    "/bin/init",
+    "/bin/sh",
+];
+
 /// 初始进程（0号进程）
 pub static INIT_PROCESS: Lazy<Arc<Task>> = Lazy::new(|| {
-    let mut data = Vec::new();
-    read_all("/tests/init", &mut data);
-    assert!(data.len() > 0);
-    let task = Task::from_elf("/tests/init", data.as_slice()).unwrap();
-    Arc::new(task)
+    let requested = platform::cmdline::cmdline().init.as_deref();
+    for path in requested.into_iter().chain(DEFAULT_INIT) {
+        let mut data = Vec::new();
+        match read_all(path, &mut data)
+            .then(|| Task::from_elf(path, data.as_slice()))
+            .flatten()
+        {
+            Some(task) => {
+                println!("Run {} as init process", path);
+                return Arc::new(task);
+            }
+            None if Some(path) == requested => println!("Requested init {} failed", path),
+            None => {}
+        }
+    }
+    panic!("No working init found, try passing init= option to kernel");
 });
 
 /// 将初始进程加入进程池中进行调度
 pub fn init_task() {
+    cgroup::init_cgroup();
     kthread::ktread_create(kthread_init, "kthread_test").unwrap();
+    kthread::ktread_create(crate::fs::writeback::writeback_thread, "flush").unwrap();
     let task = INIT_PROCESS.clone();
+    session::register_task(&task);
     GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
     println!("Init task success");
 }
@@ -97,6 +130,9 @@ impl KTaskShim for DriverTaskImpl {
         let task = current_task().unwrap();
         task.transfer_buffer(src as *const u8, size)
     }
+    fn kill_pgrp(&self, pgid: usize, signum: usize) {
+        kill_pgrp(pgid, signum);
+    }
 }
 
 // online test has no sort.src
@@ -0,0 +1,656 @@
+//! 命名空间。
+//!
+//! 每个任务持有一个 [`NsProxy`]，记录其所在的挂载、PID、UTS 与 System V IPC 命名空间。
+//! `clone` 时通过 `CLONE_NEW*` 标志为子任务创建新的命名空间，`unshare` 为调用者自身创建新的命名空间，
+//! `setns` 则让调用者加入 pidfd 所指向的进程所在的命名空间。
+//!
+//! + 挂载命名空间：rvfs 中的挂载点是目录项的属性，无法复制整棵挂载树。因此新的挂载命名空间拥有一个新的 ramfs 根目录，
+//!   并把原根目录下的每个目录挂载到新根目录下的同名目录上。在新根目录下创建的目录及其上的挂载只在新的命名空间中可见，
+//!   而更深层目录(以及被挂载的顶层目录本身)上的挂载仍然会在两个命名空间之间传播。
+//! + PID 命名空间：任务的 tid 在全局唯一，每个非初始的 PID 命名空间为其中的任务分配从 1 开始的局部 id，
+//!   任务在其所在的命名空间以及所有祖先命名空间中都拥有一个 id。初始命名空间中的局部 id 与全局 id 相同。
+//! + UTS 命名空间：记录 `uname` 返回的主机名与域名。
+//! + IPC 命名空间：记录 System V 共享内存、信号量集与消息队列。
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    fmt::{Debug, Formatter},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use constants::{
+    io::{OpenFlags, SeekFrom},
+    AlienResult, LinuxErrno,
+};
+use ksync::Mutex;
+use spin::Lazy;
+use syscall_table::syscall_func;
+use vfs::{
+    kfile::File,
+    pipefs::{PipeFsDirInodeImpl, PIPE_FS_ROOT},
+};
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    path::VfsPath,
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType},
+    VfsResult,
+};
+
+use crate::{
+    ipc::{msg::MsgQueue, sem::SemSet, ShmMemory},
+    task::{current_task, find_task, FsContext, Task},
+};
+
+/// 创建新的挂载命名空间
+pub const CLONE_NEWNS: usize = 0x0002_0000;
+/// 创建新的 UTS 命名空间
+pub const CLONE_NEWUTS: usize = 0x0400_0000;
+/// 创建新的 IPC 命名空间
+pub const CLONE_NEWIPC: usize = 0x0800_0000;
+/// 创建新的 PID 命名空间
+pub const CLONE_NEWPID: usize = 0x2000_0000;
+/// Alien 支持的所有命名空间标志
+const CLONE_NS_MASK: usize = CLONE_NEWNS | CLONE_NEWUTS | CLONE_NEWIPC | CLONE_NEWPID;
+/// `clone` 与 `unshare` 中的 `CLONE_THREAD` 标志
+const CLONE_THREAD: usize = 0x0001_0000;
+/// `clone` 与 `unshare` 中的 `CLONE_SYSVSEM` 标志
+const CLONE_SYSVSEM: usize = 0x0004_0000;
+
+/// PID 命名空间的最大嵌套层数，与 Linux 保持一致
+const MAX_PID_NS_LEVEL: usize = 32;
+/// 主机名与域名的最大长度
+pub const HOST_NAME_MAX: usize = 64;
+
+/// 挂载命名空间
+pub struct MntNamespace {
+    /// 命名空间的根目录，可以被 `pivot_root` 修改
+    root: Mutex<Arc<dyn VfsDentry>>,
+}
+
+impl Debug for MntNamespace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MntNamespace")
+            .field("root", &self.root().path())
+            .finish()
+    }
+}
+
+impl MntNamespace {
+    /// 命名空间的根目录
+    pub fn root(&self) -> Arc<dyn VfsDentry> {
+        self.root.lock().clone()
+    }
+
+    /// 将命名空间的根目录切换为 `root`，返回原来的根目录
+    pub fn set_root(&self, root: Arc<dyn VfsDentry>) -> Arc<dyn VfsDentry> {
+        core::mem::replace(&mut *self.root.lock(), root)
+    }
+
+    /// 复制一个新的挂载命名空间，原根目录下的每个目录被挂载到新根目录下的同名目录上
+    fn copy(&self) -> AlienResult<Arc<Self>> {
+        let ramfs = vfs::system_support_fs("ramfs").ok_or(LinuxErrno::EINVAL)?;
+        let root = ramfs.i_mount(0, "/", None, &[])?;
+        let root_inode = root.inode()?;
+        let old_root = self.root();
+        let old_inode = old_root.inode()?;
+        let old_path = VfsPath::new(old_root.clone(), old_root);
+        let new_path = VfsPath::new(root.clone(), root.clone());
+        let mut index = 0;
+        while let Some(entry) = old_inode.readdir(index)? {
+            index += 1;
+            // 顶层的普通文件无法跨文件系统挂载，在新的命名空间中不可见
+            if entry.name == "." || entry.name == ".." || entry.ty != VfsNodeType::Dir {
+                continue;
+            }
+            let target = old_path.join(&entry.name)?.open(None)?;
+            root_inode.create(&entry.name, VfsNodeType::Dir, "rwxr-xr-x".into(), None)?;
+            new_path.join(&entry.name)?.mount(target, 0)?;
+        }
+        Ok(Arc::new(Self {
+            root: Mutex::new(root),
+        }))
+    }
+
+    /// 进入该命名空间后任务的根目录与工作目录，工作目录按照原来的路径重新解析，解析失败时为根目录
+    fn fs_context(&self, old: &FsContext) -> FsContext {
+        let root = self.root();
+        let cwd = VfsPath::new(root.clone(), root.clone())
+            .join(&old.relative_path(&old.cwd))
+            .and_then(|path| path.open(None))
+            .unwrap_or(root.clone());
+        FsContext::new(root, cwd)
+    }
+}
+
+/// UTS 命名空间
+#[derive(Debug)]
+pub struct UtsNamespace {
+    /// 主机名
+    hostname: Mutex<String>,
+    /// 域名
+    domainname: Mutex<String>,
+}
+
+impl UtsNamespace {
+    fn new(hostname: &str, domainname: &str) -> Self {
+        Self {
+            hostname: Mutex::new(hostname.to_string()),
+            domainname: Mutex::new(domainname.to_string()),
+        }
+    }
+
+    /// 主机名
+    pub fn hostname(&self) -> String {
+        self.hostname.lock().clone()
+    }
+
+    /// 域名
+    pub fn domainname(&self) -> String {
+        self.domainname.lock().clone()
+    }
+
+    /// 设置主机名，长度超过 `HOST_NAME_MAX` 时返回 `EINVAL`
+    pub fn set_hostname(&self, name: &str) -> AlienResult<()> {
+        if name.len() > HOST_NAME_MAX {
+            return Err(LinuxErrno::EINVAL);
+        }
+        *self.hostname.lock() = name.to_string();
+        Ok(())
+    }
+
+    /// 设置域名，长度超过 `HOST_NAME_MAX` 时返回 `EINVAL`
+    pub fn set_domainname(&self, name: &str) -> AlienResult<()> {
+        if name.len() > HOST_NAME_MAX {
+            return Err(LinuxErrno::EINVAL);
+        }
+        *self.domainname.lock() = name.to_string();
+        Ok(())
+    }
+
+    /// 复制一个新的 UTS 命名空间，初始的主机名与域名与原命名空间相同
+    fn copy(&self) -> Arc<Self> {
+        Arc::new(Self::new(&self.hostname(), &self.domainname()))
+    }
+}
+
+/// System V IPC 命名空间
+pub struct IpcNamespace {
+    /// 共享内存
+    pub shm: Mutex<BTreeMap<usize, ShmMemory>>,
+    /// 信号量集
+    pub sem: Mutex<BTreeMap<usize, Arc<SemSet>>>,
+    /// 消息队列
+    pub msg: Mutex<BTreeMap<usize, Arc<MsgQueue>>>,
+}
+
+impl Debug for IpcNamespace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("IpcNamespace")
+            .field("shm", &self.shm.lock().keys().collect::<Vec<_>>())
+            .field("sem", &self.sem.lock().keys().collect::<Vec<_>>())
+            .field("msg", &self.msg.lock().keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl IpcNamespace {
+    /// 创建一个空的 IPC 命名空间
+    fn new() -> Self {
+        Self {
+            shm: Mutex::new(BTreeMap::new()),
+            sem: Mutex::new(BTreeMap::new()),
+            msg: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+/// PID 命名空间
+#[derive(Debug)]
+pub struct PidNamespace {
+    /// 父命名空间，初始命名空间没有父命名空间
+    parent: Option<Arc<PidNamespace>>,
+    /// 嵌套的层数，初始命名空间为 0
+    level: usize,
+    inner: Mutex<PidNamespaceInner>,
+}
+
+#[derive(Debug)]
+struct PidNamespaceInner {
+    /// 下一个分配的局部 id
+    next: usize,
+    /// 全局 id 到局部 id 的映射
+    ids: BTreeMap<usize, usize>,
+}
+
+impl PidNamespace {
+    fn new(parent: Option<Arc<PidNamespace>>) -> Self {
+        let level = parent.as_ref().map(|parent| parent.level + 1).unwrap_or(0);
+        Self {
+            parent,
+            level,
+            inner: Mutex::new(PidNamespaceInner {
+                next: 1,
+                ids: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// 创建一个子命名空间，嵌套层数超过上限时返回 `ENOSPC`
+    fn new_child(self: &Arc<Self>) -> AlienResult<Arc<Self>> {
+        if self.level + 1 >= MAX_PID_NS_LEVEL {
+            return Err(LinuxErrno::ENOSPC);
+        }
+        Ok(Arc::new(Self::new(Some(self.clone()))))
+    }
+
+    /// 是否为初始命名空间
+    pub fn is_root(&self) -> bool {
+        self.parent.is_none()
+    }
+
+    /// 在本命名空间及所有祖先命名空间中为全局 id 为 `tid` 的任务分配局部 id
+    pub fn attach(&self, tid: usize) {
+        if self.is_root() {
+            return;
+        }
+        let mut inner = self.inner.lock();
+        let id = inner.next;
+        inner.next += 1;
+        inner.ids.insert(tid, id);
+        drop(inner);
+        self.parent.as_ref().unwrap().attach(tid);
+    }
+
+    /// 任务退出时释放其在本命名空间及所有祖先命名空间中的局部 id
+    pub fn detach(&self, tid: usize) {
+        if self.is_root() {
+            return;
+        }
+        self.inner.lock().ids.remove(&tid);
+        self.parent.as_ref().unwrap().detach(tid);
+    }
+
+    /// 全局 id 为 `tid` 的任务在本命名空间中的 id，任务在本命名空间中不可见时返回 `None`
+    pub fn local_id(&self, tid: usize) -> Option<usize> {
+        if self.is_root() {
+            return Some(tid);
+        }
+        self.inner.lock().ids.get(&tid).cloned()
+    }
+
+    /// 本命名空间中的 id `id` 所对应的全局 id
+    pub fn global_id(&self, id: usize) -> Option<usize> {
+        if self.is_root() {
+            return Some(id);
+        }
+        self.inner
+            .lock()
+            .ids
+            .iter()
+            .find(|(_, local)| **local == id)
+            .map(|(tid, _)| *tid)
+    }
+
+    /// 本命名空间中所有任务的全局 id，初始命名空间返回空
+    pub fn members(&self) -> Vec<usize> {
+        self.inner.lock().ids.keys().cloned().collect()
+    }
+
+    /// 本命名空间中的 1 号进程，负责收养命名空间中的孤儿进程
+    pub fn child_reaper(&self) -> Option<Arc<Task>> {
+        self.global_id(1).and_then(find_task)
+    }
+}
+
+/// 任务所在的所有命名空间
+#[derive(Debug, Clone)]
+pub struct NsProxy {
+    /// 挂载命名空间
+    pub mnt: Arc<MntNamespace>,
+    /// UTS 命名空间
+    pub uts: Arc<UtsNamespace>,
+    /// IPC 命名空间
+    pub ipc: Arc<IpcNamespace>,
+    /// 任务所在的 PID 命名空间，任务创建后不会改变
+    pub pid: Arc<PidNamespace>,
+    /// 子进程所在的 PID 命名空间，`unshare` 与 `setns` 只修改该字段
+    pub pid_for_children: Arc<PidNamespace>,
+}
+
+/// 初始挂载命名空间
+static INIT_MNT_NS: Lazy<Arc<MntNamespace>> = Lazy::new(|| {
+    Arc::new(MntNamespace {
+        root: Mutex::new(vfs::system_root_fs()),
+    })
+});
+
+/// 初始 UTS 命名空间
+static INIT_UTS_NS: Lazy<Arc<UtsNamespace>> =
+    Lazy::new(|| Arc::new(UtsNamespace::new("Alien", "RustOS")));
+
+/// 初始 IPC 命名空间
+static INIT_IPC_NS: Lazy<Arc<IpcNamespace>> = Lazy::new(|| Arc::new(IpcNamespace::new()));
+
+/// 初始 PID 命名空间
+static INIT_PID_NS: Lazy<Arc<PidNamespace>> = Lazy::new(|| Arc::new(PidNamespace::new(None)));
+
+impl NsProxy {
+    /// 初始命名空间，用于 init 进程与内核线程
+    pub fn init() -> Self {
+        Self {
+            mnt: INIT_MNT_NS.clone(),
+            uts: INIT_UTS_NS.clone(),
+            ipc: INIT_IPC_NS.clone(),
+            pid: INIT_PID_NS.clone(),
+            pid_for_children: INIT_PID_NS.clone(),
+        }
+    }
+
+    /// 根据 `clone` 的标志 `flags` 生成子任务的命名空间。
+    ///
+    /// 线程必须与进程位于同一个 PID 命名空间中，因此创建线程时指定了 `CLONE_NEWPID`，
+    /// 或者之前通过 `unshare`/`setns` 修改了子进程所在的 PID 命名空间时返回 `EINVAL`。
+    pub fn copy(&self, flags: usize) -> AlienResult<Self> {
+        if flags & CLONE_THREAD != 0
+            && (flags & CLONE_NEWPID != 0 || !Arc::ptr_eq(&self.pid, &self.pid_for_children))
+        {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let mut ns = self.unshare(flags)?;
+        ns.pid = ns.pid_for_children.clone();
+        Ok(ns)
+    }
+
+    /// 根据标志 `flags` 为需要分离的命名空间创建新的命名空间，任务自身所在的 PID 命名空间保持不变
+    fn unshare(&self, flags: usize) -> AlienResult<Self> {
+        let mnt = if flags & CLONE_NEWNS != 0 {
+            self.mnt.copy()?
+        } else {
+            self.mnt.clone()
+        };
+        let uts = if flags & CLONE_NEWUTS != 0 {
+            self.uts.copy()
+        } else {
+            self.uts.clone()
+        };
+        let ipc = if flags & CLONE_NEWIPC != 0 {
+            Arc::new(IpcNamespace::new())
+        } else {
+            self.ipc.clone()
+        };
+        let pid_for_children = if flags & CLONE_NEWPID != 0 {
+            self.pid_for_children.new_child()?
+        } else {
+            self.pid_for_children.clone()
+        };
+        Ok(Self {
+            mnt,
+            uts,
+            ipc,
+            pid: self.pid.clone(),
+            pid_for_children,
+        })
+    }
+
+    /// 挂载命名空间发生变化时，子任务的根目录与工作目录
+    pub fn fs_context(&self, old: &NsProxy, fs_info: &FsContext) -> FsContext {
+        if Arc::ptr_eq(&self.mnt, &old.mnt) {
+            fs_info.clone()
+        } else {
+            self.mnt.fs_context(fs_info)
+        }
+    }
+}
+
+/// 当前任务的命名空间
+pub fn current_ns() -> NsProxy {
+    current_task().unwrap().access_inner().ns.clone()
+}
+
+/// 当前任务所在的 IPC 命名空间
+pub fn current_ipc_ns() -> Arc<IpcNamespace> {
+    current_task().unwrap().access_inner().ns.ipc.clone()
+}
+
+/// 当前任务所在的 UTS 命名空间
+pub fn current_uts_ns() -> Arc<UtsNamespace> {
+    current_task().unwrap().access_inner().ns.uts.clone()
+}
+
+/// 全局 id 为 `tid` 的任务在当前任务所在的 PID 命名空间中的 id，不可见时返回 0
+pub fn pid_vnr(tid: usize) -> usize {
+    current_task()
+        .unwrap()
+        .access_inner()
+        .ns
+        .pid
+        .local_id(tid)
+        .unwrap_or(0)
+}
+
+/// 当前任务所在的 PID 命名空间中的 id `id` 所对应的全局 id
+pub fn find_vpid(id: usize) -> Option<usize> {
+    current_task().unwrap().access_inner().ns.pid.global_id(id)
+}
+
+static PIDFD: AtomicUsize = AtomicUsize::new(0);
+
+/// pidfd 的匿名 inode，与管道一样挂在 pipefs 的根目录下，使 pidfd 可以像普通文件一样提供目录项与 inode
+struct PidFdInode;
+
+impl VfsFile for PidFdInode {}
+
+impl VfsInode for PidFdInode {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        VfsNodePerm::from_bits_truncate(0o600)
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat::default())
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::File
+    }
+}
+
+/// 指向一个进程的文件描述符，可以作为 `setns` 的参数加入该进程所在的命名空间
+pub struct PidFd {
+    /// 不持有进程的强引用，避免影响父进程对其的回收
+    task: Weak<Task>,
+    dentry: Arc<dyn VfsDentry>,
+}
+
+impl Debug for PidFd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PidFd")
+            .field("name", &self.dentry.name())
+            .finish()
+    }
+}
+
+impl PidFd {
+    fn new(task: &Arc<Task>) -> AlienResult<Self> {
+        let root = PIPE_FS_ROOT.get().unwrap();
+        let root_inode = root
+            .inode()?
+            .downcast_arc::<PipeFsDirInodeImpl>()
+            .map_err(|_| LinuxErrno::EINVAL)?;
+        let name = format!("pidfd-{}", PIDFD.fetch_add(1, Ordering::AcqRel));
+        let inode =
+            root_inode.add_file_manually(&name, Arc::new(PidFdInode), "rw-------".into())?;
+        let dentry = root.i_insert(&name, inode)?;
+        Ok(Self {
+            task: Arc::downgrade(task),
+            dentry,
+        })
+    }
+}
+
+impl Drop for PidFd {
+    fn drop(&mut self) {
+        let name = self.dentry.name();
+        let root = PIPE_FS_ROOT.get().unwrap();
+        let _ = root.remove(&name);
+        if let Ok(root_inode) = root.inode().unwrap().downcast_arc::<PipeFsDirInodeImpl>() {
+            let _ = root_inode.remove_manually(&name);
+        }
+    }
+}
+
+impl File for PidFd {
+    fn read(&self, _buf: &mut [u8]) -> AlienResult<usize> {
+        Err(LinuxErrno::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> AlienResult<usize> {
+        Err(LinuxErrno::EINVAL)
+    }
+
+    fn seek(&self, _pos: SeekFrom) -> AlienResult<u64> {
+        Err(LinuxErrno::ESPIPE)
+    }
+
+    fn get_attr(&self) -> AlienResult<VfsFileStat> {
+        Err(LinuxErrno::ENOSYS)
+    }
+
+    fn dentry(&self) -> Arc<dyn VfsDentry> {
+        self.dentry.clone()
+    }
+
+    fn inode(&self) -> Arc<dyn VfsInode> {
+        self.dentry.inode().unwrap()
+    }
+
+    fn is_readable(&self) -> bool {
+        false
+    }
+
+    fn is_writable(&self) -> bool {
+        false
+    }
+
+    fn is_append(&self) -> bool {
+        false
+    }
+}
+
+/// 一个系统调用，获取一个指向进程 `pid` 的文件描述符，该文件描述符可以用于 [`setns`]。
+///
+/// `flags` 只支持 `O_NONBLOCK`；进程不存在或 `pid` 不是一个进程的主线程时返回 `ESRCH`。
+///
+/// Reference: [pidfd_open](https://man7.org/linux/man-pages/man2/pidfd_open.2.html)
+#[syscall_func(434)]
+pub fn pidfd_open(pid: usize, flags: usize) -> AlienResult<isize> {
+    let flags = OpenFlags::from_bits(flags).ok_or(LinuxErrno::EINVAL)?;
+    if !(flags - OpenFlags::O_NONBLOCK).is_empty() {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let target = find_vpid(pid)
+        .and_then(find_task)
+        .filter(|target| target.get_pid() == target.get_tid())
+        .ok_or(LinuxErrno::ESRCH)?;
+    let file: Arc<dyn File> = Arc::new(PidFd::new(&target)?);
+    let task = current_task().unwrap();
+    let fd = task.add_file(file).map_err(|_| LinuxErrno::EMFILE)?;
+    Ok(fd as isize)
+}
+
+/// 一个系统调用，将当前任务的部分执行上下文与其它任务分离，`flags` 为 `CLONE_NEW*` 时为当前任务创建对应的新的命名空间。
+///
+/// `CLONE_NEWPID` 不改变当前任务所在的 PID 命名空间，只有之后创建的子进程进入新的命名空间，
+/// 其中第一个子进程的 pid 为 1。进入新的 IPC 命名空间前会先执行信号量的调整值。
+/// 由于 Alien 中的文件描述符表等资源在创建任务时已经按照 `clone` 的标志决定是否共享，这里不支持分离它们，
+/// 包含其它标志时返回 `EINVAL`。
+///
+/// Reference: [unshare](https://man7.org/linux/man-pages/man2/unshare.2.html)
+#[syscall_func(97)]
+pub fn unshare(flags: usize) -> AlienResult<isize> {
+    warn!("unshare flags: {:#x}", flags);
+    if flags & !(CLONE_NS_MASK | CLONE_SYSVSEM) != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let old = current_ns();
+    let ns = old.unshare(flags)?;
+    let mut inner = task.access_inner();
+    if flags & (CLONE_NEWIPC | CLONE_SYSVSEM) != 0 {
+        inner.exit_sem(task.get_pid() as usize);
+    }
+    inner.fs_info = ns.fs_context(&old, &inner.fs_info);
+    inner.ns = ns;
+    Ok(0)
+}
+
+/// 一个系统调用，让当前任务加入 `fd` 所指向的进程所在的命名空间。
+///
+/// `fd` 必须为 [`pidfd_open`] 返回的文件描述符，`nstype` 为 `CLONE_NEW*` 的组合，指明需要加入哪些命名空间。
+/// 加入 PID 命名空间时只有之后创建的子进程进入该命名空间，并且只能加入当前 PID 命名空间或其后代命名空间。
+/// 加入挂载命名空间后，当前任务的根目录与工作目录变为该命名空间的根目录。
+///
+/// `fd` 不是 pidfd 时返回 `EINVAL`，其指向的进程已经被回收时返回 `ESRCH`。
+///
+/// Reference: [setns](https://man7.org/linux/man-pages/man2/setns.2.html)
+#[syscall_func(268)]
+pub fn setns(fd: usize, nstype: usize) -> AlienResult<isize> {
+    warn!("setns fd: {}, nstype: {:#x}", fd, nstype);
+    if nstype == 0 || nstype & !CLONE_NS_MASK != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    let pidfd = file
+        .downcast_arc::<PidFd>()
+        .map_err(|_| LinuxErrno::EINVAL)?;
+    let target = pidfd.task.upgrade().ok_or(LinuxErrno::ESRCH)?;
+    let target_ns = target.access_inner().ns.clone();
+    let old = current_ns();
+    let mut ns = old.clone();
+    if nstype & CLONE_NEWPID != 0 {
+        // 只能进入当前 PID 命名空间或其后代命名空间
+        let mut pid_ns = Some(target_ns.pid.clone());
+        while let Some(current) = pid_ns {
+            if Arc::ptr_eq(&current, &old.pid) {
+                break;
+            }
+            pid_ns = current.parent.clone();
+        }
+        if pid_ns.is_none() {
+            return Err(LinuxErrno::EINVAL);
+        }
+        ns.pid_for_children = target_ns.pid.clone();
+    }
+    if nstype & CLONE_NEWNS != 0 {
+        ns.mnt = target_ns.mnt.clone();
+    }
+    if nstype & CLONE_NEWUTS != 0 {
+        ns.uts = target_ns.uts.clone();
+    }
+    if nstype & CLONE_NEWIPC != 0 {
+        ns.ipc = target_ns.ipc.clone();
+    }
+    let mut inner = task.access_inner();
+    if !Arc::ptr_eq(&ns.ipc, &old.ipc) {
+        inner.exit_sem(task.get_pid() as usize);
+    }
+    if !Arc::ptr_eq(&ns.mnt, &old.mnt) {
+        let root = ns.mnt.root();
+        inner.fs_info = FsContext::new(root.clone(), root);
+    }
+    inner.ns = ns;
+    Ok(0)
+}
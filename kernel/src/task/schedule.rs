@@ -1,16 +1,17 @@
 //! CPU 调度
 use alloc::sync::Arc;
-use core::hint::spin_loop;
 
-use constants::signal::SignalNumber;
+use arch::{interrupt_disable, interrupt_enable, wait_for_interrupt};
 use smpscheduler::FifoTask;
+use timer::{hrtimer::tick_enabled, read_timer};
 
 use crate::{
-    ipc::send_signal,
+    ipc::{send_signal_info, SigInfo},
     task::{
-        context::switch, cpu::current_cpu, take_current_task, task::TaskState, Task,
-        GLOBAL_TASK_MANAGER,
+        cgroup::charge_cpu_time, context::switch, cpu::current_cpu, take_current_task,
+        task::TaskState, Task, GLOBAL_TASK_MANAGER,
     },
+    time::{set_next_trigger, stop_tick},
 };
 
 /// 在 CPU 启动并初始化完毕后初次进入用户态时，或者在一个任务将要让渡 CPU 时 将会执行该函数。
@@ -21,11 +22,26 @@ use crate::{
 /// - 如果该任务处于其他状态，我们将其放入线程池中等待下一次分配。
 ///
 /// 之后如果在线程池中有任务需要调度，那么就把该任务的上下文切换到 CPU 上来运行；
-/// 否则该 CPU 将进入等待状态，等待其它核的中断信号。
+/// 否则该 CPU 将关闭时钟节拍并进入等待状态，直到 hrtimer 到期或外部中断到来。
 pub fn run_task() -> ! {
     loop {
         let cpu = current_cpu();
-        if let Some(task) = GLOBAL_TASK_MANAGER.pick_next_task() {
+        // 关闭中断后再检查线程池，以免在检查后、等待前到来的中断唤醒的任务被错过
+        interrupt_disable();
+        let next = GLOBAL_TASK_MANAGER.pick_next_task();
+        if next.is_none() {
+            stop_tick();
+            // 即使全局中断关闭，wfi 也会在有中断待处理时返回，开启中断后再进行处理
+            wait_for_interrupt();
+        }
+        interrupt_enable();
+        if let Some(task) = next {
+            // the tick may be stopped while the cpu was idle
+            if !tick_enabled() {
+                set_next_trigger();
+            }
+            cpu.switch_time = read_timer();
+            cpu.ticks = 0;
             // update state to running
             task.inner().update_state(TaskState::Running);
             // get the process context
@@ -36,8 +52,6 @@ pub fn run_task() -> ! {
             // println!("hart {} switch to task {}", hart_id(),task.get_tid());
             drop(task);
             switch(cpu_context, context);
-        } else {
-            spin_loop();
         }
     }
 }
@@ -51,6 +65,7 @@ pub fn schedule() {
 // todo!(fix bugs)
 pub fn schedule_now(task: Arc<Task>) {
     let context = task.get_context_mut_raw_ptr();
+    let throttled = charge_cpu_time(&task);
     match task.state() {
         TaskState::Waiting => {
             drop(task);
@@ -65,12 +80,17 @@ pub fn schedule_now(task: Arc<Task>) {
                     .unwrap()
                     .upgrade()
                     .unwrap();
-                send_signal(parent.pid, SignalNumber::SIGCHLD as usize);
+                let info = SigInfo::child_status(task.pid, task.exit_code());
+                let _ = send_signal_info(parent.pid, info);
             }
             task.terminate(); // release some resources
         }
         _ => {
-            GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
+            // 所在的 cgroup 超出了 CPU 时间的配额时，任务在下一个周期开始后才会被重新调度
+            match throttled {
+                Some(cgroup) => cgroup.park(task),
+                None => GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task))),
+            }
         }
     }
     let cpu = current_cpu();
@@ -0,0 +1,120 @@
+//! 进程组与会话。
+//!
+//! 每个进程属于一个进程组，每个进程组属于一个会话，进程组与会话的 id 记录在 [`TaskInner`](super::task::TaskInner) 中，
+//! 同一进程中的所有线程保持一致。为了能够向一个进程组中的所有进程发送信号，这里记录了所有存活的任务。
+//!
+//! 当一个进程组中所有进程的父进程要么属于同一进程组，要么属于其它会话时，该进程组成为孤儿进程组。
+//! 孤儿进程组中没有进程可以进行作业控制，因此如果其中有被暂停的进程，会向组内所有进程发送 SIGHUP 与 SIGCONT。
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use constants::signal::SignalNumber;
+use ksync::Mutex;
+
+use crate::{
+    ipc::{is_task_stopped, send_signal},
+    task::Task,
+};
+
+/// 所有存活的任务，以 tid 为键
+static TASKS: Mutex<BTreeMap<usize, Weak<Task>>> = Mutex::new(BTreeMap::new());
+
+/// 记录一个新创建的任务
+pub fn register_task(task: &Arc<Task>) {
+    TASKS
+        .lock()
+        .insert(task.get_tid() as usize, Arc::downgrade(task));
+}
+
+/// 任务退出时将其移除，之后它不再属于任何进程组
+pub fn unregister_task(tid: usize) {
+    TASKS.lock().remove(&tid);
+}
+
+/// 根据 tid 查找一个存活的任务
+pub fn find_task(tid: usize) -> Option<Arc<Task>> {
+    TASKS.lock().get(&tid).and_then(|task| task.upgrade())
+}
+
+/// 所有存活的任务，包括线程
+pub fn tasks() -> Vec<Arc<Task>> {
+    TASKS
+        .lock()
+        .values()
+        .filter_map(|task| task.upgrade())
+        .collect()
+}
+
+/// 所有存活的进程，即线程组中的主线程
+pub fn processes() -> Vec<Arc<Task>> {
+    // 线程的 tid 与 pid 不同
+    tasks()
+        .into_iter()
+        .filter(|task| task.get_pid() == task.get_tid())
+        .collect()
+}
+
+/// 进程 `pid` 中所有存活的线程
+pub fn thread_group(pid: usize) -> Vec<Arc<Task>> {
+    tasks()
+        .into_iter()
+        .filter(|task| task.get_pid() as usize == pid)
+        .collect()
+}
+
+/// 进程组 `pgid` 中的所有进程
+pub fn process_group(pgid: usize) -> Vec<Arc<Task>> {
+    processes()
+        .into_iter()
+        .filter(|task| task.pgid() == pgid)
+        .collect()
+}
+
+/// 将进程 `pid` 中所有线程的进程组与会话设置为 `pgid` 与 `sid`
+pub fn set_process_group(pid: usize, pgid: usize, sid: usize) {
+    // 获取任务的锁之前释放全局表的锁
+    let tasks = TASKS
+        .lock()
+        .values()
+        .filter_map(|task| task.upgrade())
+        .filter(|task| task.get_pid() as usize == pid)
+        .collect::<Vec<_>>();
+    tasks.into_iter().for_each(|task| {
+        let mut inner = task.access_inner();
+        inner.pgid = pgid;
+        inner.sid = sid;
+    });
+}
+
+/// 进程组 `pgid` 是否为孤儿进程组
+pub fn is_orphaned_pgrp(pgid: usize) -> bool {
+    process_group(pgid).iter().all(|task| {
+        let sid = task.sid();
+        match task.parent() {
+            Some(parent) => parent.pgid() == pgid || parent.sid() != sid,
+            None => true,
+        }
+    })
+}
+
+/// 进程退出后检查进程组 `pgid` 是否成为了孤儿进程组，如果其中有被暂停的进程，向组内所有进程发送 SIGHUP 与 SIGCONT
+pub fn check_orphaned_pgrp(pgid: usize) {
+    let members = process_group(pgid);
+    if members.is_empty() || !is_orphaned_pgrp(pgid) {
+        return;
+    }
+    if !members
+        .iter()
+        .any(|task| is_task_stopped(task.get_tid() as usize))
+    {
+        return;
+    }
+    warn!("process group {} is orphaned", pgid);
+    members.iter().for_each(|task| {
+        send_signal(task.get_pid() as usize, SignalNumber::SIGHUP as usize);
+        send_signal(task.get_pid() as usize, SignalNumber::SIGCONT as usize);
+    });
+}
@@ -28,23 +28,31 @@ use page_table::{
     table::Sv39PageTable,
 };
 use timer::{read_timer, TimeNow, ToClock};
-use vfs::kfile::File;
+use vfs::{dev::random::random_fill, kfile::File};
 use vfscore::dentry::VfsDentry;
 
 use crate::{
     fs::stdio::{STDIN, STDOUT},
-    ipc::{global_register_signals, ShmInfo},
+    ipc::{
+        flush_signal_queue, global_register_signals, sem::SemUndoList, shm_detach_all, shm_inherit,
+        ShmInfo,
+    },
     mm::{
         loader::{
-            build_cow_address_space, build_elf_address_space, build_thread_address_space, UserStack,
+            build_cow_address_space, build_elf_address_space, build_thread_address_space,
+            UserStack, RISCV_HWCAP, USER_CLOCK_TICKS,
         },
         map::{MMapInfo, MMapRegion, ProtFlags},
     },
     task::{
+        cgroup::{root_cgroup, Cgroup},
         context::Context,
+        namespace::NsProxy,
         resource::{HeapInfo, TidHandle},
+        session::register_task,
         stack::Stack,
     },
+    time::{clear_real_itimer, posix_timer::PosixTimers},
     trap::{trap_common_read_file, trap_return, user_trap_vector, TrapFrame},
 };
 
@@ -62,6 +70,8 @@ pub struct Task {
     pub send_sigchld_when_exit: bool,
     /// 内核栈
     pub kernel_stack: Stack,
+    /// 任务所在的 cgroup，分配物理页时需要在持有 `inner` 的锁的情况下访问，因此单独加锁
+    pub cgroup: Mutex<Arc<Cgroup>>,
     /// 更详细的信息
     pub inner: Mutex<TaskInner>,
 }
@@ -88,6 +98,8 @@ pub struct TaskInner {
     pub context: Context,
     /// 文件系统的信息
     pub fs_info: FsContext,
+    /// 任务所在的命名空间
+    pub ns: NsProxy,
     /// 有关任务执行情况的统计信息
     pub statistical_data: StatisticalData,
     /// 任务计时器
@@ -109,17 +121,35 @@ pub struct TaskInner {
     /// 子线程初始化时，将这个地址清空；子线程退出时，触发这里的 futex。
     /// 在创建时包含 CLONE_CHILD_SETTID 时才非0，但可以被 sys_set_tid_address 修改
     pub clear_child_tid: usize,
-    /// 处理信号时，保存的之前的用户线程的上下文信息
-    pub trap_cx_before_signal: Option<TrapFrame>,
-    /// 保存信息时，处理函数是否设置了 SIGINFO 选项
-    /// 如果设置了，说明信号触发前的上下文信息通过 ucontext 传递给了用户，
-    /// 此时用户可能修改其中的 pc 信息(如musl-libc 的 pthread_cancel 函数)。
-    /// 在这种情况下，需要手动在 sigreturn 时更新已保存的上下文信息
-    pub signal_set_siginfo: bool,
+    /// sigsuspend 等系统调用临时替换信号屏蔽字时，保存的原信号屏蔽字。
+    /// 原屏蔽字会在信号处理函数返回后恢复，具体可见 [`crate::ipc::signal_handler`]
+    pub saved_sigmask: Option<usize>,
+    /// 被信号打断(返回 `EINTR`)的系统调用号与其原来的第一个参数，用于重新执行该系统调用
+    pub syscall_restart: Option<(usize, usize)>,
     /// robust 锁的列表
     pub robust: RobustList,
-    /// 共享内存
+    /// 共享内存，以附加的首地址为键
     pub shm: BTreeMap<usize, ShmInfo>,
+    /// System V 信号量的 `SEM_UNDO` 调整值
+    pub sem_undo: Arc<Mutex<SemUndoList>>,
+    /// POSIX 计时器表，同一线程组中的线程共享
+    pub posix_timers: Arc<Mutex<PosixTimers>>,
+    /// 执行域 (`personality`)，会被子进程继承，并在 `exec` 后保留
+    pub personality: u32,
+    /// 程序与动态链接器的 `LOAD` 段的地址范围，用于生成 core 文件
+    pub elf_segments: Vec<Range<usize>>,
+    /// `exec` 时传递给程序的辅助向量，以 `AT_NULL` 结尾，用于生成 core 文件
+    pub auxv: Vec<(usize, usize)>,
+    /// core 文件大小的限制 (`RLIMIT_CORE`)，会被子进程继承
+    pub core_limit: RLimit64,
+    /// 所属进程组的 id，同一进程中的线程保持一致
+    pub pgid: usize,
+    /// 所属会话的 id，同一进程中的线程保持一致
+    pub sid: usize,
+    /// 尚未被父进程通过 `wait4` 获取的暂停或继续执行的状态
+    ///
+    /// 暂停时为 `(signum << 8) | 0x7f`，继续执行时为 `0xffff`
+    pub job_status: Option<i32>,
     /// cpu 亲和力，用于 cpu 调度时 倾向于将该任务调度给 哪个 CPU
     pub cpu_affinity: usize,
     /// 进程创建文件时，文件权限的默认掩码
@@ -244,6 +274,38 @@ impl FsContext {
     pub fn new(root: Arc<dyn VfsDentry>, cwd: Arc<dyn VfsDentry>) -> Self {
         FsContext { cwd, root }
     }
+
+    /// 目录项 `dentry` 相对于根目录的路径，位于根目录之外的目录项返回其完整路径
+    pub fn relative_path(&self, dentry: &Arc<dyn VfsDentry>) -> String {
+        let path = dentry.path();
+        let root = self.root.path();
+        if root == "/" {
+            return path;
+        }
+        match path.strip_prefix(root.as_str()) {
+            Some("") => "/".to_string(),
+            Some(rest) if rest.starts_with('/') => rest.to_string(),
+            _ => path,
+        }
+    }
+
+    /// 将相对于根目录与当前工作目录的路径 `path` 转换为系统根目录下的完整路径，
+    /// 用于 Unix 套接字这类不经过路径解析的地址
+    pub fn global_path(&self, path: &str) -> String {
+        let base = if path.starts_with('/') {
+            self.root.path()
+        } else {
+            self.cwd.path()
+        };
+        let path = path.trim_start_matches('/');
+        if path.is_empty() {
+            base
+        } else if base.ends_with('/') {
+            format!("{}{}", base, path)
+        } else {
+            format!("{}/{}", base, path)
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialOrd, PartialEq)]
@@ -262,7 +324,21 @@ pub enum TaskState {
     Terminated,
 }
 
+impl Drop for Task {
+    /// 任务被回收时释放其在各级 PID 命名空间中的 id，之后全局 tid 才会被释放。同时减少所在 cgroup 的任务数
+    fn drop(&mut self) {
+        let tid = self.get_tid() as usize;
+        self.inner.lock().ns.pid.detach(tid);
+        self.cgroup.lock().uncharge_pid();
+    }
+}
+
 impl Task {
+    /// 获取任务所在的 cgroup
+    pub fn cgroup(&self) -> Arc<Cgroup> {
+        self.cgroup.lock().clone()
+    }
+
     pub fn set_exit_group(&self) {
         self.access_inner().exit_group = true;
     }
@@ -297,6 +373,25 @@ impl Task {
         self.tid.0 as isize
     }
 
+    /// 获取进程所属进程组的 id
+    pub fn pgid(&self) -> usize {
+        self.inner.lock().pgid
+    }
+
+    /// 获取进程所属会话的 id
+    pub fn sid(&self) -> usize {
+        self.inner.lock().sid
+    }
+
+    /// 获取父进程的控制块，父进程已经退出时返回 None
+    pub fn parent(&self) -> Option<Arc<Task>> {
+        self.inner
+            .lock()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+    }
+
     /// 设置 `clear_child_tid` 字段的 值
     pub fn set_tid_address(&self, tidptr: usize) {
         let mut inner = self.inner.lock();
@@ -367,17 +462,18 @@ impl Task {
         inner.children.clone()
     }
 
-    /// 查找进程号为pid的子进程是否已经退出。当pid为-1时，任意子进程退出都将返回子进程的pid号。
-    pub fn check_child(&self, pid: isize) -> Option<usize> {
+    /// 查找满足 `target` 的已经退出的子进程，返回其在子进程列表中的位置
+    pub fn check_child<F>(&self, target: F) -> Option<usize>
+    where
+        F: Fn(&Arc<Task>) -> bool,
+    {
         let res = self
             .inner
             .lock()
             .children
             .iter()
             .enumerate()
-            .find(|(_, child)| {
-                child.state() == TaskState::Terminated && (child.get_pid() == pid || pid == -1)
-            })
+            .find(|(_, child)| child.state() == TaskState::Terminated && target(child))
             .map(|(index, _)| index);
         res
     }
@@ -538,6 +634,15 @@ impl Task {
 }
 
 impl TaskInner {
+    /// 不再使用当前的信号量调整值列表，如果没有其它任务共享该列表，则将调整值作用到对应的信号量上
+    pub fn exit_sem(&mut self, pid: usize) {
+        let sem_undo =
+            core::mem::replace(&mut self.sem_undo, Arc::new(Mutex::new(SemUndoList::new())));
+        if Arc::strong_count(&sem_undo) == 1 {
+            sem_undo.lock().apply(&self.ns.ipc, pid);
+        }
+    }
+
     /// 获取进程的文件系统信息
     pub fn cwd(&self) -> FsContext {
         self.fs_info.clone()
@@ -595,40 +700,6 @@ impl TaskInner {
         TrapFrame::from_raw_ptr(physical.as_usize() as *mut TrapFrame)
     }
 
-    /// 在信号处理需要执行用户态信号处理函数时，保存原 trap 上下文。
-    pub fn save_trap_frame(&mut self) -> bool {
-        let trap_frame = self.trap_frame();
-        if self.trap_cx_before_signal.is_some() {
-            return false;
-        }
-        self.trap_cx_before_signal = Some(*trap_frame);
-        self.signal_set_siginfo = false;
-        true
-    }
-
-    /// 待用户态信号处理函数执行完毕后，需要重新加载原 trap 上下文。
-    pub fn load_trap_frame(&mut self) -> isize {
-        if let Some(old_trap_frame) = self.trap_cx_before_signal.take() {
-            let trap_frame = self.trap_frame();
-            // 这里假定是 sigreturn 触发的，即用户的信号处理函数 return 了(cancel_handler)
-            // 也就是说信号触发时的 sp 就是现在的 sp
-            let sp = trap_frame.regs()[2];
-            // 获取可能被修改的 pc
-            let phy_sp = self.transfer_raw(sp);
-
-            let pc = unsafe { (*(phy_sp as *const SignalUserContext)).get_pc() };
-            *trap_frame = old_trap_frame;
-            if self.signal_set_siginfo {
-                // 更新用户修改的 pc
-                trap_frame.set_sepc(pc);
-                warn!("sig return sp = {:x} pc = {:x}", sp, pc);
-            }
-            trap_frame.regs()[10] as isize // old arg0
-        } else {
-            -1
-        }
-    }
-
     /// 获取一个虚拟地址 `ptr` 的实际物理地址
     pub fn transfer_raw(&mut self, ptr: usize) -> usize {
         let (phy, flag, _) = self
@@ -650,6 +721,30 @@ impl TaskInner {
         phy.as_usize()
     }
 
+    /// 获取一个虚拟地址 `ptr` 的实际物理地址。与 [`TaskInner::transfer_raw`] 不同，
+    /// 当 `ptr` 不属于任务的地址空间时返回 `EFAULT` 而不会 panic，适用于访问不可信的用户指针。
+    pub fn try_transfer_raw(&mut self, ptr: usize) -> AlienResult<usize> {
+        let (phy, flag, _) = self
+            .address_space
+            .lock()
+            .query(VirtAddr::from(ptr))
+            .map_err(|_| LinuxErrno::EFAULT)?;
+        if flag.contains(MappingFlags::V) {
+            return Ok(phy.as_usize());
+        }
+        self.invalid_page_solver(ptr)
+            .map_err(|_| LinuxErrno::EFAULT)?;
+        let (phy, flag, _) = self
+            .address_space
+            .lock()
+            .query(VirtAddr::from(ptr))
+            .map_err(|_| LinuxErrno::EFAULT)?;
+        if !flag.contains(MappingFlags::V) {
+            return Err(LinuxErrno::EFAULT);
+        }
+        Ok(phy.as_usize())
+    }
+
     /// 获取 虚拟地址空间中的以 `ptr` 为起始地址，以 '\0' 结尾的字符串
     pub fn transfer_str(&self, ptr: *const u8) -> String {
         let mut res = String::new();
@@ -885,6 +980,7 @@ impl TaskInner {
         let time = now - self.statistical_data.last_stime;
         self.update_timer();
         self.statistical_data.tms_stime += time;
+        self.posix_timers.lock().account_cputime(time);
         self.statistical_data.last_utime = now;
     }
 
@@ -894,6 +990,7 @@ impl TaskInner {
         let time = now - self.statistical_data.last_utime;
         self.update_timer();
         self.statistical_data.tms_utime += time;
+        self.posix_timers.lock().account_cputime(time);
         self.statistical_data.last_stime = now;
     }
 
@@ -960,8 +1057,8 @@ impl TaskInner {
     /// 拓展堆空间
     pub fn extend_heap(&mut self, addr: usize) -> Result<usize, AlienError> {
         let mut heap = self.heap.lock();
-        heap.current = addr;
         if addr < heap.end {
+            heap.current = addr;
             return Ok(heap.current);
         }
         let addition = addr - heap.end;
@@ -979,9 +1076,10 @@ impl TaskInner {
                 false,
                 true,
             )
-            .unwrap();
+            .map_err(|_| AlienError::ENOMEM)?;
         let new_end = end + addition;
         heap.end = new_end;
+        heap.current = addr;
         Ok(heap.current)
     }
 
@@ -1102,8 +1200,6 @@ impl TaskInner {
             fd,
             offset,
         );
-        // warn!("add mmap region:{:#x?}",region);
-        self.mmap.add_region(region);
         let start = v_range.start;
         let mut map_flags: MappingFlags = prot.into(); // no V  flag
         map_flags |= "AD".into();
@@ -1116,7 +1212,9 @@ impl TaskInner {
                 false,
                 true,
             )
-            .unwrap();
+            .map_err(|_| AlienError::ENOMEM)?;
+        // warn!("add mmap region:{:#x?}",region);
+        self.mmap.add_region(region);
         Ok(start)
     }
 
@@ -1173,7 +1271,7 @@ impl TaskInner {
             .address_space
             .lock()
             .query(VirtAddr::from(addr))
-            .expect(format!("addr:{:#x}", addr).as_str());
+            .map_err(|_| AlienError::EFAULT)?;
         trace!(
             "do load page fault:{:#x}, flags:{:?}, page_size:{:?}",
             addr,
@@ -1183,28 +1281,9 @@ impl TaskInner {
         if !flags.contains(MappingFlags::V) {
             return self.invalid_page_solver(addr);
         }
-        assert!(!flags.contains(MappingFlags::RSD));
-
-        let region = self.mmap.get_region(addr).ok_or(AlienError::EINVAL)?;
-        // now we need make sure the start is equal to the start of the region, and the len is equal to the len of the region
-        // update page table
-        let mut map_flags: MappingFlags = region.prot.into();
-        map_flags |= "V".into();
-
-        let mut address_space = self.address_space.lock();
-
-        let (_, flags, _) = address_space.query(VirtAddr::from(addr)).unwrap();
-        assert!(!flags.contains(MappingFlags::V));
-        address_space
-            .validate(VirtAddr::from(addr), map_flags)
-            .unwrap();
-        let (phy, _, size) = address_space.query(VirtAddr::from(addr)).unwrap();
-        let buf =
-            unsafe { core::slice::from_raw_parts_mut(phy.as_usize() as *mut u8, size.into()) };
-        let file = &region.fd;
-
-        let read_offset = region.offset + (addr - region.start);
-        Ok(Some((file.clone(), buf, read_offset as u64)))
+        // 页已经映射，读取仍然出错说明该页不可读
+        warn!("load from unreadable page {:#x}, flags:{:?}", addr, flags);
+        Err(AlienError::EACCES)
     }
 
     /// 用于处理无效页错误
@@ -1228,7 +1307,7 @@ impl TaskInner {
             self.address_space
                 .lock()
                 .validate(VirtAddr::from(addr), map_flags)
-                .unwrap();
+                .map_err(|_| AlienError::ENOMEM)?;
         } else if is_mmap.is_some() {
             let region = is_mmap.unwrap();
             // assert_eq!(addr % FRAME_SIZE, 0);
@@ -1242,7 +1321,7 @@ impl TaskInner {
             self.address_space
                 .lock()
                 .validate(VirtAddr::from(addr).align_down_4k(), map_flags)
-                .unwrap();
+                .map_err(|_| AlienError::ENOMEM)?;
             let (phy, flag, size) = self
                 .address_space
                 .lock()
@@ -1260,7 +1339,7 @@ impl TaskInner {
             self.address_space
                 .lock()
                 .validate(VirtAddr::from(addr), map_flags)
-                .unwrap();
+                .map_err(|_| AlienError::ENOMEM)?;
         }
         Ok(None)
     }
@@ -1304,8 +1383,8 @@ impl TaskInner {
                     self.need_wait += 1;
                     AlienError::EAGAIN
                 } else {
-                    error!("do_store_page_fault panic :{:#x}", o_addr);
-                    AlienError::EPERM
+                    error!("do_store_page_fault: no mapping at {:#x}", o_addr);
+                    AlienError::EFAULT
                 }
             })?;
         // .expect(format!("addr:{:#x}", addr).as_str());
@@ -1318,15 +1397,11 @@ impl TaskInner {
         if !flags.contains(MappingFlags::V) {
             return self.invalid_page_solver(addr);
         }
-        // if !flags.contains(MappingFlags::RSD) {
-        //     return Ok(None);
-        // }
-        assert!(
-            flags.contains(MappingFlags::RSD),
-            "addr:{:#x} flags:{:?}",
-            o_addr,
-            flags
-        );
+        // 页已经映射但不是写时复制的页，说明该页只读
+        if !flags.contains(MappingFlags::RSD) {
+            warn!("store to read-only page {:#x}, flags:{:?}", o_addr, flags);
+            return Err(AlienError::EACCES);
+        }
         // decrease the reference count
         let mut flags = flags | "W".into();
         flags -= MappingFlags::RSD;
@@ -1334,7 +1409,7 @@ impl TaskInner {
             .address_space
             .lock()
             .modify_pte_flags(VirtAddr::from(addr), flags, true)
-            .unwrap();
+            .map_err(|_| AlienError::ENOMEM)?;
         assert!(new_phy.is_some());
         // copy data
         let src_ptr = phy.as_usize() as *const u8;
@@ -1364,9 +1439,14 @@ impl Task {
         let mut inner = self.inner.lock();
         // delete child process
         inner.children.clear();
+        inner.exit_sem(self.pid);
+        // release the POSIX timers, they are deleted when the last thread of the group exits
+        inner.posix_timers = Arc::new(Mutex::new(PosixTimers::new()));
         let thread_number = inner.thread_number;
         if thread_number == 0 {
             let _ = inner.fd_table.lock().clear();
+            shm_detach_all(&mut inner.shm, self.pid);
+            clear_real_itimer(self.pid);
             drop(inner);
         }
     }
@@ -1381,8 +1461,7 @@ impl Task {
         let tid = TidHandle::new()?;
         let pid = tid.0;
         // 创建进程地址空间
-        let mut args = vec![];
-        let elf_info = build_elf_address_space(elf, &mut args, "/bin/init");
+        let elf_info = build_elf_address_space(elf, "/bin/init", 0);
         if elf_info.is_err() {
             return None;
         }
@@ -1392,11 +1471,14 @@ impl Task {
         let k_stack_top = k_stack.top();
         let stack_info = elf_info.stack_top - USER_STACK_SIZE..elf_info.stack_top;
         let cwd = vfs::system_root_fs();
+        let cgroup = root_cgroup();
+        cgroup.charge_pid();
 
         let process = Task {
             tid,
             kernel_stack: k_stack,
             pid,
+            cgroup: Mutex::new(cgroup),
             inner: Mutex::new(TaskInner {
                 name: name.to_string(),
                 threads: MinimalManager::new(MAX_THREAD_NUM),
@@ -1414,6 +1496,7 @@ impl Task {
                 },
                 context: Context::new(trap_return as usize, k_stack_top),
                 fs_info: FsContext::new(cwd.clone(), cwd),
+                ns: NsProxy::init(),
                 statistical_data: StatisticalData::new(),
                 timer: TaskTimer::default(),
                 exit_code: 0,
@@ -1421,15 +1504,24 @@ impl Task {
                     elf_info.heap_bottom,
                     elf_info.heap_bottom,
                 ))),
-                mmap: MMapInfo::new(),
+                mmap: MMapInfo::with_base(elf_info.mmap_base),
                 signal_handlers: Arc::new(Mutex::new(SignalHandlers::new())),
                 signal_receivers: Arc::new(Mutex::new(SignalReceivers::new())),
                 set_child_tid: 0,
                 clear_child_tid: 0,
-                trap_cx_before_signal: None,
-                signal_set_siginfo: false,
+                saved_sigmask: None,
+                syscall_restart: None,
                 robust: RobustList::default(),
                 shm: BTreeMap::new(),
+                sem_undo: Arc::new(Mutex::new(SemUndoList::new())),
+                posix_timers: Arc::new(Mutex::new(PosixTimers::new())),
+                personality: 0,
+                elf_segments: Vec::new(),
+                auxv: Vec::new(),
+                core_limit: RLimit64::new(0, u64::MAX),
+                pgid: pid,
+                sid: pid,
+                job_status: None,
                 cpu_affinity: {
                     let mut affinity = 0;
                     affinity.set_bits(0..CPU_NUM, 1 << CPU_NUM - 1);
@@ -1485,6 +1577,7 @@ impl Task {
         ptid: usize,
         tls: usize,
         ctid: usize,
+        ns: NsProxy,
     ) -> Option<Arc<Task>> {
         warn!(
             "clone: flag:{:?}, sig:{:?}, stack:{:#x}, ptid:{:#x}, tls:{:#x}, ctid:{:#x}",
@@ -1498,7 +1591,7 @@ impl Task {
         } else {
             // to create process
             let address_space =
-                build_cow_address_space(&mut inner.address_space.lock(), inner.shm.clone());
+                build_cow_address_space(&mut inner.address_space.lock(), inner.shm.clone())?;
             Arc::new(Mutex::new(address_space))
         };
 
@@ -1522,20 +1615,17 @@ impl Task {
 
         let k_stack = Stack::new(USER_KERNEL_STACK_SIZE / FRAME_SIZE)?;
         let k_stack_top = k_stack.top();
-        let pid = if flag.contains(CloneFlags::CLONE_THREAD) {
-            self.pid
-        } else {
-            tid.0
-        };
-        let signal_receivers = Arc::new(Mutex::new(SignalReceivers::new()));
-        // 注册线程-信号对应关系
-        global_register_signals(tid.0, signal_receivers.clone());
         // map the thread trap_context if clone_vm
         let (trap_context, thread_num) = if flag.contains(CloneFlags::CLONE_VM) {
             let thread_num = inner.threads.insert(()).unwrap() + 1;
             warn!("thread_num: {}", thread_num);
             // calculate the address for thread context
-            let trap_context = build_thread_address_space(&mut address_space.lock(), thread_num);
+            let Some(trap_context) =
+                build_thread_address_space(&mut address_space.lock(), thread_num)
+            else {
+                inner.threads.remove(thread_num - 1).unwrap();
+                return None;
+            };
             (trap_context, thread_num)
         } else {
             let (physical, _, _) = address_space
@@ -1546,12 +1636,37 @@ impl Task {
             (trap_frame, 0)
         };
 
+        // 在子任务所在的 PID 命名空间及其祖先命名空间中分配 id
+        ns.pid.attach(tid.0);
+        let pid = if flag.contains(CloneFlags::CLONE_THREAD) {
+            self.pid
+        } else {
+            tid.0
+        };
+        let signal_receivers = Arc::new(Mutex::new(SignalReceivers::new()));
+        // 注册线程-信号对应关系
+        global_register_signals(tid.0, signal_receivers.clone());
+
         let heap = if flag.contains(CloneFlags::CLONE_VM) {
             inner.heap.clone()
         } else {
+            // the child process attaches all shm of the parent
+            shm_inherit(&inner.shm);
             Arc::new(Mutex::new(inner.heap.lock().clone()))
         };
 
+        let sem_undo = if flag.contains(CloneFlags::CLONE_SYSVSEM) {
+            inner.sem_undo.clone()
+        } else {
+            Arc::new(Mutex::new(SemUndoList::new()))
+        };
+
+        let posix_timers = if flag.contains(CloneFlags::CLONE_THREAD) {
+            inner.posix_timers.clone()
+        } else {
+            Arc::new(Mutex::new(PosixTimers::new()))
+        };
+
         // 设置内核栈地址
         trap_context.update_kernel_sp(k_stack_top);
 
@@ -1566,8 +1681,10 @@ impl Task {
             let res = inner.address_space.lock().query(VirtAddr::from(ptid));
             if res.is_ok() {
                 let (physical, _, _) = res.unwrap();
+                // 写入的是子任务在父任务所在的 PID 命名空间中的 id
+                let ptid_value = inner.ns.pid.local_id(tid.0).unwrap();
                 unsafe {
-                    *(physical.as_usize() as *mut i32) = tid.0 as i32;
+                    *(physical.as_usize() as *mut i32) = ptid_value as i32;
                 }
             } else {
                 panic!("clone: ptid is not mapped")
@@ -1577,7 +1694,7 @@ impl Task {
         let ctid_value = if flag.contains(CloneFlags::CLONE_CHILD_SETTID)
             || flag.contains(CloneFlags::CLONE_CHILD_CLEARTID)
         {
-            tid.0
+            ns.pid.local_id(tid.0).unwrap()
         } else {
             0
         };
@@ -1602,6 +1719,8 @@ impl Task {
             tid,
             kernel_stack: k_stack,
             pid,
+            // 任务数已经在 clone 中记账
+            cgroup: Mutex::new(self.cgroup()),
             inner: Mutex::new(TaskInner {
                 name: inner.name.clone(),
                 threads: MinimalManager::new(MAX_THREAD_NUM),
@@ -1612,7 +1731,8 @@ impl Task {
                 children: Vec::new(),
                 fd_table,
                 context: Context::new(trap_return as usize, k_stack_top),
-                fs_info: inner.fs_info.clone(),
+                fs_info: ns.fs_context(&inner.ns, &inner.fs_info),
+                ns,
                 statistical_data: StatisticalData::new(),
                 timer: TaskTimer::default(),
                 exit_code: 0,
@@ -1630,10 +1750,19 @@ impl Task {
                 } else {
                     0
                 },
-                trap_cx_before_signal: None,
-                signal_set_siginfo: false,
+                saved_sigmask: None,
+                syscall_restart: None,
                 robust: RobustList::default(),
                 shm: inner.shm.clone(),
+                sem_undo,
+                posix_timers,
+                personality: inner.personality,
+                elf_segments: inner.elf_segments.clone(),
+                auxv: inner.auxv.clone(),
+                core_limit: inner.core_limit,
+                pgid: inner.pgid,
+                sid: inner.sid,
+                job_status: None,
                 cpu_affinity: {
                     let mut affinity = 0;
                     affinity.set_bits(0..CPU_NUM, 1 << CPU_NUM - 1);
@@ -1652,6 +1781,7 @@ impl Task {
             send_sigchld_when_exit: sig == SignalNumber::SIGCHLD,
         };
         let task = Arc::new(task);
+        register_task(&task);
         if !flag.contains(CloneFlags::CLONE_PARENT) {
             inner.children.push(task.clone());
         }
@@ -1674,8 +1804,8 @@ impl Task {
         args: Vec<String>,
         env: Vec<String>,
     ) -> Result<(), isize> {
-        let mut args = args;
-        let elf_info = build_elf_address_space(elf_data, &mut args, name);
+        let personality = self.access_inner().personality;
+        let elf_info = build_elf_address_space(elf_data, name, personality);
         if elf_info.is_err() {
             return Err(-1);
         }
@@ -1684,6 +1814,8 @@ impl Task {
         assert_eq!(inner.thread_number, 0);
         let name = elf_info.name;
         let address_space = elf_info.address_space;
+        // detach all shm, they are not inherited by the new program
+        shm_detach_all(&mut inner.shm, self.pid);
         // reset the address space
         inner.address_space = Arc::new(Mutex::new(address_space));
         // reset the heap
@@ -1692,7 +1824,7 @@ impl Task {
             elf_info.heap_bottom,
         )));
         // reset the mmap
-        inner.mmap = MMapInfo::new();
+        inner.mmap = MMapInfo::with_base(elf_info.mmap_base);
         // set the name of the process
         inner.name = name.to_string();
         // reset time record
@@ -1703,7 +1835,16 @@ impl Task {
         // reset signal handler
         inner.signal_handlers.lock().clear();
         inner.signal_receivers.lock().clear();
+        flush_signal_queue(self.get_tid() as usize);
+        inner.saved_sigmask = None;
+        inner.ss_stack = SignalStack {
+            ss_sp: 0,
+            ss_flags: 0x2,
+            ss_size: 0,
+        };
         inner.timer.clear();
+        // POSIX timers are deleted by exec
+        inner.posix_timers.lock().clear();
         inner.stack = elf_info.stack_top - USER_STACK_SIZE..elf_info.stack_top;
         let env = if env.is_empty() {
             let envp = vec![
@@ -1738,43 +1879,44 @@ impl Task {
             .collect::<Vec<usize>>();
         // push padding to the top of stack of the process
         user_stack.align_to(8).unwrap();
-        let random_ptr = user_stack.push_bytes(&[0u8; 16]).unwrap();
+        let mut random = [0u8; 16];
+        random_fill(&mut random);
+        let random_ptr = user_stack.push_bytes(&random).unwrap();
         // padding
         user_stack.push_bytes(&[0u8; 8]).unwrap();
         // push aux
         let platform = user_stack.push_str("riscv").unwrap();
 
         let ex_path = user_stack.push_str(&name).unwrap();
+        let auxv = vec![
+            (AT_RANDOM, random_ptr),
+            (AT_SECURE, 0),
+            (AT_EUID, 0),
+            (AT_UID, 0),
+            (AT_EGID, 0),
+            (AT_GID, 0),
+            (AT_PHDR, elf_info.ph_drift),
+            (AT_PHENT, elf_info.ph_entry_size),
+            (AT_ENTRY, elf_info.program_entry),
+            (AT_BASE, elf_info.bias),
+            (AT_SYSINFO_EHDR, elf_info.vdso_ehdr),
+            (AT_CLKTCK, USER_CLOCK_TICKS),
+            (AT_HWCAP, RISCV_HWCAP),
+            (AT_PAGESZ, FRAME_SIZE),
+            (AT_PHNUM, elf_info.ph_num),
+            (AT_EXECFN, ex_path),
+            (AT_PLATFORM, platform),
+        ];
+        // AT_NULL
         user_stack.push(0).unwrap();
-        user_stack.push(platform).unwrap();
-        user_stack.push(AT_PLATFORM).unwrap();
-        user_stack.push(ex_path).unwrap();
-        user_stack.push(AT_EXECFN).unwrap();
-        user_stack.push(elf_info.ph_num).unwrap();
-        user_stack.push(AT_PHNUM).unwrap();
-        user_stack.push(FRAME_SIZE).unwrap();
-        user_stack.push(AT_PAGESZ).unwrap();
-
-        user_stack.push(elf_info.bias).unwrap();
-        user_stack.push(AT_BASE).unwrap();
-        user_stack.push(elf_info.entry).unwrap();
-        user_stack.push(AT_ENTRY).unwrap();
-        user_stack.push(elf_info.ph_entry_size).unwrap();
-        user_stack.push(AT_PHENT).unwrap();
-        user_stack.push(elf_info.ph_drift).unwrap();
-        user_stack.push(AT_PHDR).unwrap();
-        user_stack.push(0).unwrap();
-        user_stack.push(AT_GID).unwrap();
-        user_stack.push(0).unwrap();
-        user_stack.push(AT_EGID).unwrap();
-        user_stack.push(0).unwrap();
-        user_stack.push(AT_UID).unwrap();
-        user_stack.push(0).unwrap();
-        user_stack.push(AT_EUID).unwrap();
-        user_stack.push(0).unwrap();
-        user_stack.push(AT_SECURE).unwrap();
-        user_stack.push(random_ptr).unwrap();
-        user_stack.push(AT_RANDOM).unwrap();
+        auxv.iter().rev().for_each(|(key, value)| {
+            user_stack.push(*value).unwrap();
+            user_stack.push(*key).unwrap();
+        });
+        // keep a copy of the auxiliary vector for the core dump
+        inner.auxv = auxv;
+        inner.auxv.push((0, 0));
+        inner.elf_segments = elf_info.segments;
 
         user_stack.push(0).unwrap();
         // push the env addr to the top of stack of the process
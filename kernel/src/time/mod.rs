@@ -0,0 +1,588 @@
+//! Alien 中的有关时钟、计时器的结构 以及 一些计时器的系统调用。
+//!
+//! 在对系统时间的记录上，Alien 中使用 [`TimeVal`] 记录 (秒，微秒) 的时间，使用 [`TimeSpec`] 记录 更精细的 (秒，纳秒) 的时间；
+//! 在对进程的运行时间的记录上，使用 [`Times`] 结构记录进程运行的时间，记录的信息包括程序在用户态、内核态下分别运行的时间，
+//! 其子进程运行的总时间等，在任务控制块中记录相应数据的结构为 [`StatisticalData`]。
+//! `CLOCK_REALTIME` 由单调时钟加上启动时根据 RTC 得到的偏移计算 (见 [`init_realtime`])，该偏移保存在 vDSO 数据页中，
+//! 因此用户程序可以通过 vDSO 直接获取时间而不必陷入内核。基于 `CLOCK_REALTIME` 的绝对截止时间 (`clock_nanosleep`、POSIX 计时器、
+//! futex、`futex_waitv` 与 POSIX 消息队列的超时) 都需要通过 [`realtime_to_clock`] 转换为单调时钟上的时刻后再与计时器比较。
+//!
+//! 计时器方面，内核中所有需要在某一时刻被唤醒的操作 (睡眠、futex 超时、timerfd、`ITIMER_REAL` 计时器、POSIX 计时器以及 poll 的超时等)
+//! 均通过 `timer` 模块中的高精度计时器 (hrtimer) 实现。SBI 计时器总是被设置为下一个时钟节拍与最早到期的 hrtimer 中较早的一个，
+//! 当发生时钟中断时，会执行所有已经到期的 hrtimer 的回调函数，具体可见 [`check_timer_queue`]。
+//! 睡眠的任务记录在 [`SLEEPERS`] 中，计时器到期或收到信号时会被唤醒，具体可见 [`sleep_until`]。
+//! [`ITimerVal`] 结构为系统调用 [`getitimer`] / [`setitimer`] 指定的类型，用户执行系统调用时获取和输入时需要为该种类型的计时器,
+//! `ITIMER_REAL` 计时器记录在 [`REAL_ITIMERS`] 中，其余计时器在任务控制块中记录相应数据的字段为 `timer`(结构为 `TaskTimer` )。
+//!
+//! [`posix_timer`] 子模块实现了 POSIX 间隔计时器，每个进程可以创建多个基于不同时钟的计时器。
+//!
+//! 对于时间片 (每次引发时钟中断的时间间隔) 大小的设计：目前 Alien 中用户态和内核态下采用相同的时间片间隔，1s 内触发 10 次时钟中断。
+//! 当 CPU 空闲时会关闭时钟节拍，只在 hrtimer 到期或外部中断到来时被唤醒。
+
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
+
+use constants::{
+    io::OpenFlags,
+    signal::SignalNumber,
+    time::{ClockId, ITimeSpec, ITimerVal, TimeSpec, TimeVal, TimerFdFlags, TimerType},
+    AlienResult, FromUsize, LinuxErrno,
+};
+use ksync::Mutex;
+use log::{info, warn};
+use platform::config::CLOCK_FREQ;
+use smpscheduler::FifoTask;
+use syscall_table::syscall_func;
+use timer::{
+    get_time_ms,
+    hrtimer::{hrtimer_cancel, hrtimer_run, hrtimer_start, program_next_event, HrTimerId},
+    read_timer, TimeFromFreq, TimeNow, Times, ToClock,
+};
+use vfs::timerfd::TimerFile;
+
+use crate::{
+    ipc::send_signal,
+    mm::vdso::{realtime_offset, set_realtime_offset},
+    task::{
+        current_task, schedule::schedule, StatisticalData, Task, TaskState, GLOBAL_TASK_MANAGER,
+    },
+    time::posix_timer::{
+        thread_cputime, CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME,
+        CLOCK_THREAD_CPUTIME_ID,
+    },
+};
+
+pub mod posix_timer;
+
+#[inline]
+#[allow(unused)]
+pub fn read_time_ms() -> u64 {
+    get_time_ms() as u64
+}
+
+#[inline]
+#[allow(unused)]
+pub fn read_time_ns() -> u64 {
+    let time = read_timer();
+    time as u64 * 1_000_000_000 / CLOCK_FREQ as u64
+}
+
+#[inline]
+#[allow(unused)]
+pub fn read_time_us() -> u64 {
+    let time = read_timer();
+    time as u64 * 1_000_000 / CLOCK_FREQ as u64
+}
+/// 每秒包含的 时间片 数，每隔一个时间片，就会产生一个时钟中断
+const TICKS_PER_SEC: usize = 10;
+// const TICKS_PER_SEC_IN_KERNEL: usize = 1000;
+
+/// 设置下一次时钟的中断
+///
+/// 实际设置的时钟中断为下一个时钟节拍与最早到期的 hrtimer 中较早的一个。
+#[inline]
+pub fn set_next_trigger() {
+    let next = read_timer() + CLOCK_FREQ / TICKS_PER_SEC;
+    assert!(next > read_timer());
+    program_next_event(Some(next));
+}
+
+/// 设置内核态中下一次时钟的中断
+///
+/// 原设计为内核态下的时间片设置的更短一些，以免一个进程在进入内核态前后占用过多的时间片。但目前修改为 内核态和用户态下的时间片大小相同。
+#[inline]
+pub fn set_next_trigger_in_kernel() {
+    let next = read_timer() + CLOCK_FREQ / TICKS_PER_SEC;
+    assert!(next > read_timer());
+    program_next_event(Some(next));
+}
+
+/// 关闭当前 CPU 的时钟节拍，之后只有 hrtimer 到期或外部中断才会引发中断。在 CPU 空闲时使用
+#[inline]
+pub fn stop_tick() {
+    program_next_event(None);
+}
+
+/// 正在睡眠的任务，以 tid 为键，同时记录唤醒该任务的 hrtimer
+static SLEEPERS: Mutex<BTreeMap<usize, (Arc<Task>, Option<HrTimerId>)>> =
+    Mutex::new(BTreeMap::new());
+
+/// 使当前任务睡眠直到时刻 `deadline`(以 cpu 时钟周期数表示)，`deadline` 为 `None` 时一直睡眠直到收到信号。
+///
+/// 睡眠期间任务不会被调度，到期时由 hrtimer 唤醒。睡眠可以被信号打断，此时返回 `EINTR`。
+pub fn sleep_until(deadline: Option<usize>) -> AlienResult<()> {
+    let task = current_task().unwrap().clone();
+    let tid = task.get_tid() as usize;
+    let receivers = task.access_inner().signal_receivers.clone();
+    loop {
+        if deadline.map_or(false, |deadline| read_timer() >= deadline) {
+            return Ok(());
+        }
+        let mut sleepers = SLEEPERS.lock();
+        // 在持有锁的情况下检查信号，发送信号的一方在设置信号后才会尝试唤醒，因此不会错过唤醒
+        if receivers.lock().have_signal() {
+            return Err(LinuxErrno::EINTR);
+        }
+        let timer =
+            deadline.map(|deadline| hrtimer_start(deadline, Box::new(move || wake_sleeper(tid))));
+        sleepers.insert(tid, (task.clone(), timer));
+        drop(sleepers);
+        task.update_state(TaskState::Waiting);
+        schedule();
+    }
+}
+
+/// 唤醒正在睡眠的任务 `tid`。任务没有在睡眠时不做任何操作
+pub fn wake_sleeper(tid: usize) {
+    let sleeper = SLEEPERS.lock().remove(&tid);
+    if let Some((task, timer)) = sleeper {
+        if let Some(timer) = timer {
+            hrtimer_cancel(timer);
+        }
+        GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
+    }
+}
+
+/// 根据 RTC 设置 `CLOCK_REALTIME` 相对于 `CLOCK_MONOTONIC` 的偏移，该偏移保存在 vDSO 数据页中。没有 RTC 时偏移为 0
+pub fn init_realtime() {
+    let Some(time) = devices::RTC_DEVICE.get().map(|rtc| rtc.read_time()) else {
+        return;
+    };
+    let sec = days_from_civil(time.year as usize, time.mon as usize, time.mday as usize) * 86400
+        + time.hour as usize * 3600
+        + time.min as usize * 60
+        + time.sec as usize;
+    let now = TimeSpec::now();
+    let (sec, nsec) = if now.tv_nsec == 0 {
+        (sec.saturating_sub(now.tv_sec), 0)
+    } else {
+        (
+            sec.saturating_sub(now.tv_sec + 1),
+            1000_000_000 - now.tv_nsec,
+        )
+    };
+    set_realtime_offset(sec, nsec);
+    info!("realtime offset: {}s {}ns", sec, nsec);
+}
+
+/// 计算公历日期 `year-month-day` 距离 1970-01-01 的天数
+fn days_from_civil(year: usize, month: usize, day: usize) -> usize {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// 将 `CLOCK_REALTIME` 时钟上的绝对时刻转换为 cpu 时钟周期数表示的时刻
+pub fn realtime_to_clock(time: &TimeSpec) -> usize {
+    let (sec, nsec) = realtime_offset();
+    time.to_clock()
+        .saturating_sub(TimeSpec::new(sec, nsec).to_clock())
+}
+
+/// 获取 `CLOCK_REALTIME` 时钟的当前时间
+pub fn realtime_now() -> TimeSpec {
+    let now = TimeSpec::now();
+    let (sec, nsec) = realtime_offset();
+    let nsec = now.tv_nsec + nsec;
+    TimeSpec {
+        tv_sec: now.tv_sec + sec + nsec / 1000_000_000,
+        tv_nsec: nsec % 1000_000_000,
+    }
+}
+
+/// 一个系统调用函数，获取当前的时间，获取的时间将存储在`tv`所指向的[`TimeVal`]结构处。
+/// 执行成功则返回0。
+///
+/// Reference: [get_time_of_day](https://man7.org/linux/man-pages/man2/gettimeofday.2.html)
+#[syscall_func(169)]
+pub fn get_time_of_day(tv: *mut u8) -> isize {
+    let now = realtime_now();
+    let time = TimeVal {
+        tv_sec: now.tv_sec,
+        tv_usec: now.tv_nsec / 1000,
+    };
+    let process = current_task().unwrap();
+    let tv = process.transfer_raw_ptr(tv as *mut TimeVal);
+    *tv = time;
+    0
+}
+
+/// 一个系统调用函数，获取当前进程在用户态/内核态下运行的时间、最后一次运行在用户态/内核态下的时间等，
+/// 获取的信息将保存在`tms`所指向的[`Times`]结构处。执行成功返回0。
+///
+/// Reference: [times](https://man7.org/linux/man-pages/man2/times.2.html)
+#[syscall_func(153)]
+pub fn times(tms: *mut u8) -> isize {
+    let mut task = current_task().unwrap().access_inner();
+    let statistic_data = task.statistical_data();
+    let time = times_from_process_data(statistic_data);
+    task.copy_to_user(&time, tms as *mut Times);
+    0
+}
+
+/// 从一个 [`StatisticalData`] 结构 (一般为 task 的 statistical_data 字段) 得到一个 `Times` 变量
+pub fn times_from_process_data(data: &StatisticalData) -> Times {
+    Times {
+        tms_stime: data.tms_stime,
+        tms_utime: data.tms_utime,
+        tms_cstime: data.tms_cstime,
+        tms_cutime: data.tms_cutime,
+    }
+}
+
+/// 一个系统调用函数，暂停本进程直到一段时间后结束，要暂停的时间将保存在`req`所指向的[`TimeSpec`]结构处。
+/// 但在`nanosleep`执行过程中，本进程有可能被其他信号唤醒，此时若`rem`不为空，剩余的时间将被写入`rem`所指向的位置。
+/// 函数若正常停止`req`时间则返回0；如果由于因为其他信号而被唤醒，此时函数返回-1(EINTR)。
+///
+/// Reference: [nanosleep](https://man7.org/linux/man-pages/man2/nanosleep.2.html)
+#[syscall_func(101)]
+pub fn nanosleep(req: *mut u8, rem: *mut u8) -> isize {
+    let task = current_task().unwrap().clone();
+    let mut time = TimeSpec::new(0, 0);
+    task.access_inner()
+        .copy_from_user(req as *const TimeSpec, &mut time);
+    warn!("nanosleep: {:?}", time);
+    let end_time = read_timer() + time.to_clock();
+    if sleep_until(Some(end_time)).is_err() {
+        if !rem.is_null() {
+            let remain = TimeSpec::from_freq(end_time.saturating_sub(read_timer()));
+            task.access_inner()
+                .copy_to_user(&remain, rem as *mut TimeSpec);
+        }
+        return LinuxErrno::EINTR as isize;
+    }
+    0
+}
+
+/// 一个系统调用函数，可以根据输入的时钟类型`clock_id`来获取当前的时间，获取的时间将存储在`tp`所指向的[`TimeSpec`]结构处。
+///
+/// 目前支持`Monotonic`、`Realtime`、`ProcessCputimeId`和`ThreadCputimeId`四种时钟类型，
+/// 前两者会返回当前的系统时间，后两者分别返回进程与线程消耗的 CPU 时间。
+/// 执行成功则返回0；当所输入的`clock_id`不在上述时钟类型中时，返回`EINVAL`。
+///
+/// Reference: [clock_get_time](https://www.man7.org/linux/man-pages/man3/clock_gettime.3.html)
+#[syscall_func(113)]
+pub fn clock_get_time(clock_id: usize, tp: *mut u8) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let time = match clock_id {
+        CLOCK_MONOTONIC => TimeSpec::now(),
+        CLOCK_REALTIME => realtime_now(),
+        CLOCK_PROCESS_CPUTIME_ID => {
+            let timers = task.access_inner().posix_timers.clone();
+            let cputime = timers.lock().cputime();
+            TimeSpec::from_freq(cputime)
+        }
+        CLOCK_THREAD_CPUTIME_ID => TimeSpec::from_freq(thread_cputime(task)),
+        _ => {
+            warn!("clock_get_time: clock_id {} not supported", clock_id);
+            return Err(LinuxErrno::EINVAL);
+        }
+    };
+    task.access_inner().copy_to_user(&time, tp as *mut TimeSpec);
+    Ok(0)
+}
+
+/// 当发生时钟中断时，`trap_handler` 会调用该函数检查计时器队列中的 hrtimer，并执行所有已经到期的计时器的回调函数，
+/// 如唤醒睡眠的任务、发送计时器信号等。
+pub fn check_timer_queue() {
+    hrtimer_run();
+}
+
+/// 进程的 `ITIMER_REAL` 计时器
+#[derive(Debug, Copy, Clone)]
+struct RealITimer {
+    /// 下一次到期时使用的 hrtimer
+    timer: HrTimerId,
+    /// 到期后重新装载的间隔，以 cpu 时钟周期数表示
+    interval: usize,
+}
+
+/// 所有进程的 `ITIMER_REAL` 计时器，以 pid 为键
+static REAL_ITIMERS: Mutex<BTreeMap<usize, RealITimer>> = Mutex::new(BTreeMap::new());
+
+/// 为进程 `pid` 启动一个在 `deadline` 时刻到期的 `ITIMER_REAL` 计时器
+fn arm_real_itimer(
+    itimers: &mut BTreeMap<usize, RealITimer>,
+    pid: usize,
+    deadline: usize,
+    interval: usize,
+) {
+    let timer = hrtimer_start(deadline, Box::new(move || real_itimer_expired(pid)));
+    itimers.insert(pid, RealITimer { timer, interval });
+}
+
+/// `ITIMER_REAL` 计时器到期时的回调函数，向进程发送 `SIGALRM` 信号，并在需要时重新装载计时器
+fn real_itimer_expired(pid: usize) {
+    let now = read_timer();
+    let mut itimers = REAL_ITIMERS.lock();
+    let itimer = match itimers.get(&pid) {
+        // 计时器在到期后被重新设置
+        Some(itimer) if itimer.timer.deadline() <= now => *itimer,
+        _ => return,
+    };
+    if itimer.interval == 0 {
+        itimers.remove(&pid);
+    } else {
+        let missed = (now - itimer.timer.deadline()) / itimer.interval;
+        let deadline = itimer.timer.deadline() + (missed + 1) * itimer.interval;
+        arm_real_itimer(&mut itimers, pid, deadline, itimer.interval);
+    }
+    drop(itimers);
+    send_signal(pid, SignalNumber::SIGALRM as usize);
+}
+
+/// 获取进程 `pid` 的 `ITIMER_REAL` 计时器的剩余时间与间隔
+fn get_real_itimer(pid: usize) -> ITimerVal {
+    match REAL_ITIMERS.lock().get(&pid) {
+        Some(itimer) => ITimerVal {
+            it_interval: TimeVal::from_freq(itimer.interval),
+            it_value: TimeVal::from_freq(itimer.timer.deadline().saturating_sub(read_timer())),
+        },
+        None => ITimerVal::default(),
+    }
+}
+
+/// 设置进程 `pid` 的 `ITIMER_REAL` 计时器，`it_value` 为 0 时停止计时器
+fn set_real_itimer(pid: usize, itimer: &ITimerVal) {
+    let mut itimers = REAL_ITIMERS.lock();
+    if let Some(old) = itimers.remove(&pid) {
+        hrtimer_cancel(old.timer);
+    }
+    let value = itimer.it_value.to_clock();
+    if value != 0 {
+        arm_real_itimer(
+            &mut itimers,
+            pid,
+            read_timer() + value,
+            itimer.it_interval.to_clock(),
+        );
+    }
+}
+
+/// 停止进程 `pid` 的 `ITIMER_REAL` 计时器，在进程退出时调用
+pub fn clear_real_itimer(pid: usize) {
+    set_real_itimer(pid, &ITimerVal::default());
+}
+
+/// 一个系统调用函数，用于获取当前进程的计时器，保存在`current_value`指向的[`ITimerVal`]结构处。
+/// `ITIMER_REAL` 计时器由 hrtimer 实现，与其它两种计时器相互独立；而 `ITIMER_VIRTUAL` 与 `ITIMER_PROF` 计时器共用任务控制块中的 `timer` 字段。
+/// 函数执行成功则返回0。
+/// Reference: [getitimer](https://man7.org/linux/man-pages/man2/setitimer.2.html)
+#[syscall_func(102)]
+pub fn getitimer(which: usize, current_value: usize) -> isize {
+    let task = current_task().unwrap();
+    let itimer = if matches!(TimerType::try_from(which), Ok(TimerType::REAL)) {
+        get_real_itimer(task.get_pid() as usize)
+    } else {
+        let timer = &task.access_inner().timer;
+        ITimerVal {
+            it_interval: timer.timer_interval,
+            it_value: TimeVal::from_usize(timer.timer_remained),
+        }
+    };
+    task.access_inner()
+        .copy_to_user(&itimer, current_value as *mut ITimerVal);
+    0
+}
+
+/// 一个系统调用函数，用于将当前进程的定时器设置为`current_value`指向的[`ITimerVal`]结构处，
+/// 同时将旧计时器的信息保存在`old_value`指向的[`ITimerVal`]结构处。
+///
+/// `which`参数需为目前支持的[`TimerType`]类型且不为`NONE`，否则会导致进程被panic。
+/// 如果`current_value`为空，则会导致进程被panic。
+/// 如果`old_value`为空，则不进行保存旧计时器信息操作。
+///
+/// 函数执行正确则返回0。
+/// Reference: [setitimer](https://man7.org/linux/man-pages/man2/setitimer.2.html)
+#[syscall_func(103)]
+pub fn setitimer(which: usize, current_value: usize, old_value: usize) -> isize {
+    let which = TimerType::try_from(which).unwrap();
+    assert_ne!(which, TimerType::NONE);
+    info!(
+        "setitimer: which {:?} ,curret_value {:#x}, old_value {:#x}",
+        which, current_value, old_value
+    );
+    let task = current_task().unwrap();
+    let pid = task.get_pid() as usize;
+    if old_value != 0 {
+        let itimer = if which == TimerType::REAL {
+            get_real_itimer(pid)
+        } else {
+            let timer = task.access_inner().get_timer();
+            ITimerVal {
+                it_interval: timer.timer_interval.into(),
+                it_value: TimeVal::from_usize(timer.timer_remained),
+            }
+        };
+        task.access_inner()
+            .copy_to_user(&itimer, old_value as *mut ITimerVal);
+    }
+    assert_ne!(current_value, 0);
+    let mut itimer = ITimerVal::default();
+    task.access_inner()
+        .copy_from_user(current_value as *const ITimerVal, &mut itimer);
+    info!("setitimer: itimer {:x?}", itimer);
+    if which == TimerType::REAL {
+        set_real_itimer(pid, &itimer);
+    } else {
+        task.access_inner().set_timer(itimer, which);
+    }
+    0
+}
+
+/// 一个系统调用函数，可以根据输入的时钟类型`clock_id`来获取该时钟分辨率(精度)，获取的精度将存储在`res`所指向的[`TimeSpec`]结构处。
+/// 时钟的分辨率取决于实现方式，无法由特定进程配置。`Monotonic`、`Realtime`以及进程/线程的 CPU 时间时钟均由硬件计时器计数得到，
+/// 其分辨率为计时器的一个计数周期 (至少为 1ns)。`res`为空时仅检查`clock_id`是否合法，不支持的时钟类型返回`EINVAL`。
+///
+/// Reference: [clock_getres](https://www.man7.org/linux/man-pages/man3/clock_getres.3.html)
+#[syscall_func(114)]
+pub fn clock_getres(id: usize, res: usize) -> AlienResult<isize> {
+    info!("clock_getres: id {} ,res {:#x}", id, res);
+    match id {
+        CLOCK_MONOTONIC | CLOCK_REALTIME | CLOCK_PROCESS_CPUTIME_ID | CLOCK_THREAD_CPUTIME_ID => {}
+        _ => return Err(LinuxErrno::EINVAL),
+    }
+    if res != 0 {
+        let time_res = TimeSpec::new(0, (1_000_000_000 / CLOCK_FREQ).max(1));
+        current_task()
+            .unwrap()
+            .access_inner()
+            .copy_to_user(&time_res, res as *mut TimeSpec);
+    }
+    Ok(0)
+}
+
+/// 一个系统调用函数，如`nanosleep`一样，暂停本进程直到一段时间后结束，但`clock_nanosleep`可以根据传入的`clock_id`来指定使用的时钟类型。
+///
+/// 要暂停的时间将保存在`req`所指向的[`TimeSpec`]结构处。目前支持`Monotonic`与`Realtime`，
+/// 与 Linux 一致，线程 CPU 时间时钟不能用于睡眠，返回`ENOTSUP`；进程 CPU 时间时钟与其它时钟类型返回`EINVAL`。
+/// 若`flags`中包含`TIMER_ABSTIME`，`req`被视为时钟上的绝对时刻，否则为相对时间。
+/// 如`nanosleep`一样，在`clock_nanosleep`执行过程中，本进程也有可能被其他信号唤醒，
+/// 此时对于相对时间的睡眠，若`remain`不为空，剩余的时间将被写入`remain`所指向的位置。
+///
+/// 函数若正常停止`req`时间则返回0；如果由于因为其他信号而被唤醒，此时函数返回-1(EINTR)。
+///
+/// Reference: [clock_nanosleep](https://man7.org/linux/man-pages/man2/clock_nanosleep.2.html)
+#[syscall_func(115)]
+pub fn clock_nanosleep(
+    clock_id: usize,
+    flags: usize,
+    req: usize,
+    remain: usize,
+) -> AlienResult<isize> {
+    const TIMER_ABSTIME: usize = 1;
+    info!(
+        "clock_nanosleep: id {} ,flags {:#x}, req {:#x}, remain {:#x}",
+        clock_id, flags, req, remain
+    );
+    match clock_id {
+        CLOCK_MONOTONIC | CLOCK_REALTIME => {}
+        CLOCK_THREAD_CPUTIME_ID => return Err(LinuxErrno::EOPNOTSUPP),
+        _ => {
+            warn!("clock_nanosleep: clock_id {} not supported", clock_id);
+            return Err(LinuxErrno::EINVAL);
+        }
+    }
+    let mut target_time = TimeSpec::new(0, 0);
+    let task = current_task().unwrap().clone();
+    task.access_inner()
+        .copy_from_user(req as *const TimeSpec, &mut target_time);
+    let end_time = if flags & TIMER_ABSTIME != 0 {
+        if clock_id == CLOCK_REALTIME {
+            realtime_to_clock(&target_time)
+        } else {
+            target_time.to_clock()
+        }
+    } else {
+        read_timer() + target_time.to_clock()
+    };
+    if sleep_until(Some(end_time)).is_err() {
+        if flags & TIMER_ABSTIME == 0 && remain != 0 {
+            let time = TimeSpec::from_freq(end_time.saturating_sub(read_timer()));
+            task.access_inner()
+                .copy_to_user(&time, remain as *mut TimeSpec);
+        }
+        return Err(LinuxErrno::EINTR);
+    }
+    Ok(0)
+}
+
+#[syscall_func(85)]
+/// See https://man7.org/linux/man-pages/man2/timerfd_create.2.html
+///
+/// See https://blog.csdn.net/lihuan680680/article/details/120850344
+pub fn timerfd_create(clock_id: usize, flags: u32) -> AlienResult<isize> {
+    let id = ClockId::try_from(clock_id).unwrap();
+    let flags = OpenFlags::from_bits_truncate(flags as usize);
+    let task = current_task().unwrap();
+    // println_color!(32, "timerfd_create: Id: {:?} ,flags: {:?}", id, flags);
+    let timer_file = TimerFile::new(flags, ITimeSpec::default(), id);
+    let fd = task
+        .add_file(Arc::new(timer_file))
+        .map_err(|_| LinuxErrno::EMFILE)?;
+    Ok(fd as isize)
+}
+
+#[syscall_func(87)]
+pub fn timerfd_gettime(fd: usize, current_val: usize) -> AlienResult<isize> {
+    // println_color!(
+    //     32,
+    //     "timerfd_gettime: fd {:?} ,current_val {:#x}",
+    //     fd,
+    //     current_val
+    // );
+    let task = current_task().unwrap();
+    let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    let timer_file = file
+        .as_any()
+        .downcast_ref::<TimerFile>()
+        .ok_or(LinuxErrno::EINVAL)?;
+    let itimer = ITimeSpec {
+        it_interval: timer_file.get_interval(),
+        it_value: timer_file.get_it_value(),
+    };
+    task.access_inner()
+        .copy_to_user(&itimer, current_val as *mut ITimeSpec);
+    Ok(0)
+}
+
+#[syscall_func(86)]
+pub fn timerfd_settime(
+    fd: usize,
+    flags: u32,
+    new_value: usize,
+    old_value: usize,
+) -> AlienResult<isize> {
+    let _flags = TimerFdFlags::from_bits_truncate(flags);
+    let task = current_task().unwrap();
+    let mut itimer = ITimeSpec::default();
+    task.access_inner()
+        .copy_from_user(new_value as *const ITimeSpec, &mut itimer);
+    // println_color!(
+    //     32,
+    //     "timerfd_settime: fd {:?} ,flags {:?}, new_value {:#x}, old_value {:#x}",
+    //     fd,
+    //     flags,
+    //     new_value,
+    //     old_value
+    // );
+    // println_color!(32, "timerfd_settime: new_timer: {:#x?}", itimer);
+    let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    let timer_file = file
+        .downcast_arc::<TimerFile>()
+        .map_err(|_| LinuxErrno::EINVAL)?;
+
+    if old_value != 0 {
+        let old_timer = ITimeSpec {
+            it_interval: timer_file.get_interval(),
+            it_value: timer_file.get_it_value(),
+        };
+        task.access_inner()
+            .copy_to_user(&old_timer, old_value as *mut ITimeSpec);
+    }
+    timer_file.set_timer(itimer);
+    Ok(0)
+}
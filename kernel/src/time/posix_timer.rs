@@ -0,0 +1,433 @@
+//! POSIX 间隔计时器，对应 `timer_create` / `timer_settime` / `timer_gettime` / `timer_getoverrun` / `timer_delete` 系统调用。
+//!
+//! 每个进程(线程组)拥有一张 [`PosixTimers`] 表，由同一线程组中的所有线程共享，在任务控制块中记录为 `posix_timers` 字段。
+//! 基于 `CLOCK_REALTIME` / `CLOCK_MONOTONIC` 的计时器由 hrtimer 在到期时刻准时触发；
+//! 基于 `CLOCK_PROCESS_CPUTIME_ID` / `CLOCK_THREAD_CPUTIME_ID` 的计时器只会在进程运行时推进，
+//! 因此在任务进出内核时由 [`check_cpu_timers`] 检查。
+//!
+//! 计时器到期时按照创建时指定的 [`SigEvent`] 进行通知。若检查时已经错过了若干个周期，
+//! 错过的周期数会被记为本次通知的溢出次数，用户可以通过 `timer_getoverrun` 获取。
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+};
+
+use constants::{
+    signal::SignalNumber,
+    time::{ITimeSpec, TimeSpec},
+    AlienResult, LinuxErrno,
+};
+use ksync::Mutex;
+use syscall_table::syscall_func;
+use timer::{
+    hrtimer::{hrtimer_cancel, hrtimer_start, HrTimerId},
+    read_timer, TimeFromFreq, ToClock,
+};
+
+use crate::{
+    ipc::{
+        get_signals_from_tid, send_signal_info, SigEvent, SigInfo, SIGEV_NONE, SIGEV_SIGNAL,
+        SIGEV_THREAD_ID, SI_TIMER,
+    },
+    task::{current_task, Task},
+    time::realtime_to_clock,
+};
+
+/// 系统实时时钟
+pub const CLOCK_REALTIME: usize = 0;
+/// 单调时钟
+pub const CLOCK_MONOTONIC: usize = 1;
+/// 进程(线程组中所有线程)消耗的 CPU 时间
+pub const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+/// 线程消耗的 CPU 时间
+pub const CLOCK_THREAD_CPUTIME_ID: usize = 3;
+
+/// `timer_settime` 的标志位，表示 `it_value` 为绝对时间
+const TIMER_ABSTIME: usize = 1;
+/// 溢出次数的上限
+const DELAYTIMER_MAX: usize = i32::MAX as usize;
+/// 每个进程最多能够创建的计时器个数
+const MAX_POSIX_TIMERS: usize = 1024;
+
+/// 一个 POSIX 计时器
+#[derive(Debug, Clone)]
+pub struct PosixTimer {
+    /// 计时器使用的时钟
+    clock: usize,
+    /// 创建计时器的线程，`CLOCK_THREAD_CPUTIME_ID` 计时器以该线程的 CPU 时间计时
+    owner: usize,
+    /// 通知方式，取值为 `SIGEV_*`
+    notify: i32,
+    /// 到期时发送的信号
+    signo: usize,
+    /// 接收信号的线程
+    target: usize,
+    /// 随信号一起传递的数据
+    value: usize,
+    /// 下一次到期的时刻，以对应时钟的 cpu 时钟周期数表示；为 `None` 时计时器未启动
+    expires: Option<usize>,
+    /// 到期后重新装载的间隔，为 0 时为 one-shot 计时器
+    interval: usize,
+    /// 最近一次通知时的溢出次数
+    overrun: usize,
+    /// 墙上时钟计时器在下一次到期时触发的 hrtimer
+    hrtimer: Option<HrTimerId>,
+}
+
+impl PosixTimer {
+    /// 检查计时器在时刻 `now` 是否到期。到期时更新下一次到期的时刻以及溢出次数，并返回 true
+    fn expire(&mut self, now: usize) -> bool {
+        match self.expires {
+            Some(expires) if now >= expires => {
+                if self.interval == 0 {
+                    self.expires = None;
+                    self.overrun = 0;
+                } else {
+                    let missed = (now - expires) / self.interval;
+                    self.overrun = missed.min(DELAYTIMER_MAX);
+                    self.expires = Some(expires + (missed + 1) * self.interval);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 按照计时器的通知方式发出通知，`id` 为计时器的 id
+    fn notify(&self, id: usize) {
+        if self.notify == SIGEV_SIGNAL || self.notify == SIGEV_THREAD_ID {
+            let info = SigInfo::new(self.signo, SI_TIMER)
+                .with_timer(id, self.overrun)
+                .with_value(self.value);
+            let _ = send_signal_info(self.target, info);
+        }
+    }
+
+    /// 取消计时器的 hrtimer
+    fn cancel(&mut self) {
+        if let Some(hrtimer) = self.hrtimer.take() {
+            hrtimer_cancel(hrtimer);
+        }
+    }
+
+    /// 对于已经启动的墙上时钟计时器，启动一个在下一次到期时触发的 hrtimer
+    fn arm(&mut self, table: &Weak<Mutex<PosixTimers>>, id: usize) {
+        if let (false, Some(expires)) = (is_cpu_clock(self.clock), self.expires) {
+            let table = table.clone();
+            self.hrtimer = Some(hrtimer_start(
+                expires,
+                Box::new(move || wall_timer_expired(table, id)),
+            ));
+        }
+    }
+
+    /// 获取计时器在时刻 `now` 时的剩余时间与间隔
+    fn get(&self, now: usize) -> ITimeSpec {
+        ITimeSpec {
+            it_interval: TimeSpec::from_freq(self.interval),
+            it_value: match self.expires {
+                // 已经到期但尚未被检查的计时器，返回一个最小的非零值表示其仍处于启动状态
+                Some(expires) => TimeSpec::from_freq(expires.saturating_sub(now).max(1)),
+                None => TimeSpec::new(0, 0),
+            },
+        }
+    }
+}
+
+/// 进程的 POSIX 计时器表
+#[derive(Debug, Default)]
+pub struct PosixTimers {
+    /// 计时器 ID 到计时器的映射
+    timers: BTreeMap<usize, PosixTimer>,
+    /// 线程组中所有线程累计消耗的 CPU 时间，作为 `CLOCK_PROCESS_CPUTIME_ID` 的时钟源
+    cputime: usize,
+    /// 创建过线程 CPU 时间计时器的线程最近一次记录的 CPU 时间
+    thread_cputime: BTreeMap<usize, usize>,
+}
+
+impl PosixTimers {
+    /// 创建一张空的计时器表
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 累计线程组消耗的 CPU 时间
+    pub fn account_cputime(&mut self, time: usize) {
+        self.cputime += time;
+    }
+
+    /// 获取线程组消耗的 CPU 时间
+    pub fn cputime(&self) -> usize {
+        self.cputime
+    }
+
+    /// 删除所有计时器，用于 `exec` 时。线程组已消耗的 CPU 时间保持不变
+    pub fn clear(&mut self) {
+        self.timers.values_mut().for_each(PosixTimer::cancel);
+        self.timers.clear();
+        self.thread_cputime.clear();
+    }
+
+    /// 更新线程 `tid` 的 CPU 时间记录
+    fn sample(&mut self, tid: usize, thread_time: usize) {
+        if let Some(time) = self.thread_cputime.get_mut(&tid) {
+            *time = thread_time;
+        }
+    }
+
+    /// 获取时钟 `clock` 的当前时刻
+    fn now(&self, clock: usize, owner: usize) -> usize {
+        clock_now(clock, owner, self.cputime, &self.thread_cputime)
+    }
+
+    /// 检查并通知到期的 CPU 时间计时器
+    fn check_cpu_expired(&mut self) {
+        for (&id, timer) in self.timers.iter_mut() {
+            if !is_cpu_clock(timer.clock) {
+                continue;
+            }
+            let now = clock_now(timer.clock, timer.owner, self.cputime, &self.thread_cputime);
+            if timer.expire(now) {
+                timer.notify(id);
+            }
+        }
+    }
+}
+
+fn is_cpu_clock(clock: usize) -> bool {
+    clock == CLOCK_PROCESS_CPUTIME_ID || clock == CLOCK_THREAD_CPUTIME_ID
+}
+
+fn clock_now(
+    clock: usize,
+    owner: usize,
+    cputime: usize,
+    thread_cputime: &BTreeMap<usize, usize>,
+) -> usize {
+    match clock {
+        CLOCK_PROCESS_CPUTIME_ID => cputime,
+        CLOCK_THREAD_CPUTIME_ID => thread_cputime.get(&owner).copied().unwrap_or(0),
+        _ => read_timer(),
+    }
+}
+
+/// 获取任务 `task` 消耗的 CPU 时间(用户态与内核态运行时间之和)
+pub fn thread_cputime(task: &Arc<Task>) -> usize {
+    let inner = task.access_inner();
+    inner.statistical_data.tms_utime + inner.statistical_data.tms_stime
+}
+
+/// 获取任务 `task` 所在进程的计时器表，并更新 `task` 的 CPU 时间记录
+fn sampled_timers(task: &Arc<Task>) -> Arc<Mutex<PosixTimers>> {
+    let thread_time = thread_cputime(task);
+    let timers = task.access_inner().posix_timers.clone();
+    timers.lock().sample(task.get_tid() as usize, thread_time);
+    timers
+}
+
+/// 墙上时钟计时器的 hrtimer 到期时的回调函数，发出通知并在需要时重新装载计时器。
+///
+/// 计时器所属的进程已经退出，或计时器已经被删除、重新设置时不做任何操作。
+fn wall_timer_expired(table: Weak<Mutex<PosixTimers>>, id: usize) {
+    let Some(timers) = table.upgrade() else {
+        return;
+    };
+    let mut timers = timers.lock();
+    if let Some(timer) = timers.timers.get_mut(&id) {
+        if timer.expire(read_timer()) {
+            timer.notify(id);
+            timer.arm(&table, id);
+        }
+    }
+}
+
+/// 检查当前任务所在进程中基于 CPU 时间的 POSIX 计时器，对到期的计时器发出通知。
+pub fn check_cpu_timers(task: &Arc<Task>) {
+    let thread_time = thread_cputime(task);
+    let timers = task.access_inner().posix_timers.clone();
+    let mut timers = timers.lock();
+    if timers.timers.is_empty() {
+        return;
+    }
+    timers.sample(task.get_tid() as usize, thread_time);
+    timers.check_cpu_expired();
+}
+
+/// 一个系统调用，用于创建一个 POSIX 计时器，新计时器的 ID 将被写入 `timer_id` 指向的位置。
+///
+/// `clock_id` 目前支持 `CLOCK_REALTIME`、`CLOCK_MONOTONIC`、`CLOCK_PROCESS_CPUTIME_ID` 与 `CLOCK_THREAD_CPUTIME_ID`。
+/// `sevp` 指向一个 [`SigEvent`] 结构，指明计时器到期时的通知方式，支持 `SIGEV_SIGNAL`、`SIGEV_THREAD_ID` 与 `SIGEV_NONE`；
+/// `sevp` 为 0 时，计时器到期时向进程发送 `SIGALRM` 信号。
+///
+/// 新创建的计时器处于未启动状态，需要通过 [`timer_settime`] 启动。
+///
+/// Reference: [timer_create](https://man7.org/linux/man-pages/man2/timer_create.2.html)
+#[syscall_func(107)]
+pub fn timer_create(clock_id: usize, sevp: usize, timer_id: usize) -> AlienResult<isize> {
+    if !matches!(
+        clock_id,
+        CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_PROCESS_CPUTIME_ID | CLOCK_THREAD_CPUTIME_ID
+    ) {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let tid = task.get_tid() as usize;
+    let mut event = SigEvent {
+        sigev_signo: SignalNumber::SIGALRM as i32,
+        sigev_notify: SIGEV_SIGNAL,
+        ..Default::default()
+    };
+    if sevp != 0 {
+        task.access_inner()
+            .copy_from_user(sevp as *const SigEvent, &mut event);
+    }
+    let target = match event.sigev_notify {
+        SIGEV_NONE => tid,
+        SIGEV_SIGNAL => task.get_pid() as usize,
+        SIGEV_THREAD_ID => {
+            let target = event.sigev_notify_thread_id as usize;
+            get_signals_from_tid(target).ok_or(LinuxErrno::EINVAL)?;
+            target
+        }
+        _ => return Err(LinuxErrno::EINVAL),
+    };
+    if event.sigev_notify != SIGEV_NONE
+        && u8::try_from(event.sigev_signo)
+            .ok()
+            .and_then(|signo| SignalNumber::try_from(signo).ok())
+            .is_none()
+    {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let thread_time = thread_cputime(task);
+    let timers = task.access_inner().posix_timers.clone();
+    let mut table = timers.lock();
+    let id = (0..MAX_POSIX_TIMERS)
+        .find(|id| !table.timers.contains_key(id))
+        .ok_or(LinuxErrno::EAGAIN)?;
+    let value = if sevp != 0 { event.sigev_value } else { id };
+    table.timers.insert(
+        id,
+        PosixTimer {
+            clock: clock_id,
+            owner: tid,
+            notify: event.sigev_notify,
+            signo: event.sigev_signo as usize,
+            target,
+            value,
+            expires: None,
+            interval: 0,
+            overrun: 0,
+            hrtimer: None,
+        },
+    );
+    if clock_id == CLOCK_THREAD_CPUTIME_ID {
+        table.thread_cputime.insert(tid, thread_time);
+    }
+    drop(table);
+    task.access_inner()
+        .copy_to_user(&(id as i32), timer_id as *mut i32);
+    Ok(0)
+}
+
+/// 一个系统调用，用于启动或停止 ID 为 `timer_id` 的计时器。
+///
+/// `new_value` 指向一个 [`ITimeSpec`] 结构，其中 `it_value` 为计时器首次到期的时间，为 0 时停止计时器；
+/// `it_interval` 为计时器之后每次到期的间隔，为 0 时计时器只会到期一次。
+/// 若 `flags` 中包含 `TIMER_ABSTIME`，`it_value` 被视为计时器所用时钟上的绝对时刻，若该时刻已经过去，计时器将立即到期。
+/// 若 `old_value` 不为 0，计时器原先的设置将被写入 `old_value` 指向的位置。
+///
+/// Reference: [timer_settime](https://man7.org/linux/man-pages/man2/timer_settime.2.html)
+#[syscall_func(110)]
+pub fn timer_settime(
+    timer_id: usize,
+    flags: usize,
+    new_value: usize,
+    old_value: usize,
+) -> AlienResult<isize> {
+    if new_value == 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let mut itimer = ITimeSpec::default();
+    task.access_inner()
+        .copy_from_user(new_value as *const ITimeSpec, &mut itimer);
+    if itimer.it_value.tv_nsec >= 1_000_000_000 || itimer.it_interval.tv_nsec >= 1_000_000_000 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let timers = sampled_timers(task);
+    let mut table = timers.lock();
+    let (clock, owner) = {
+        let timer = table.timers.get(&timer_id).ok_or(LinuxErrno::EINVAL)?;
+        (timer.clock, timer.owner)
+    };
+    let now = table.now(clock, owner);
+    let timer = table.timers.get_mut(&timer_id).unwrap();
+    let old = timer.get(now);
+    timer.cancel();
+    timer.expires = if itimer.it_value.tv_sec == 0 && itimer.it_value.tv_nsec == 0 {
+        None
+    } else if flags & TIMER_ABSTIME != 0 && clock == CLOCK_REALTIME {
+        Some(realtime_to_clock(&itimer.it_value))
+    } else if flags & TIMER_ABSTIME != 0 {
+        Some(itimer.it_value.to_clock())
+    } else {
+        Some(now + itimer.it_value.to_clock())
+    };
+    timer.interval = itimer.it_interval.to_clock();
+    timer.overrun = 0;
+    if timer.expire(now) {
+        timer.notify(timer_id);
+    }
+    timer.arm(&Arc::downgrade(&timers), timer_id);
+    drop(table);
+    if old_value != 0 {
+        task.access_inner()
+            .copy_to_user(&old, old_value as *mut ITimeSpec);
+    }
+    Ok(0)
+}
+
+/// 一个系统调用，用于获取 ID 为 `timer_id` 的计时器距离下一次到期的剩余时间以及到期间隔，
+/// 结果将被写入 `curr_value` 指向的 [`ITimeSpec`] 结构处。计时器未启动时剩余时间为 0。
+///
+/// Reference: [timer_gettime](https://man7.org/linux/man-pages/man2/timer_settime.2.html)
+#[syscall_func(108)]
+pub fn timer_gettime(timer_id: usize, curr_value: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let timers = sampled_timers(task);
+    let table = timers.lock();
+    let timer = table.timers.get(&timer_id).ok_or(LinuxErrno::EINVAL)?;
+    let itimer = timer.get(table.now(timer.clock, timer.owner));
+    drop(table);
+    task.access_inner()
+        .copy_to_user(&itimer, curr_value as *mut ITimeSpec);
+    Ok(0)
+}
+
+/// 一个系统调用，用于获取 ID 为 `timer_id` 的计时器最近一次通知时的溢出次数，
+/// 即最近一次通知时计时器已经错过的到期次数。
+///
+/// Reference: [timer_getoverrun](https://man7.org/linux/man-pages/man2/timer_getoverrun.2.html)
+#[syscall_func(109)]
+pub fn timer_getoverrun(timer_id: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let timers = task.access_inner().posix_timers.clone();
+    let table = timers.lock();
+    let timer = table.timers.get(&timer_id).ok_or(LinuxErrno::EINVAL)?;
+    Ok(timer.overrun as isize)
+}
+
+/// 一个系统调用，用于删除 ID 为 `timer_id` 的计时器。计时器删除后，尚未处理的到期信号仍会保留。
+///
+/// Reference: [timer_delete](https://man7.org/linux/man-pages/man2/timer_delete.2.html)
+#[syscall_func(111)]
+pub fn timer_delete(timer_id: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let timers = task.access_inner().posix_timers.clone();
+    let mut table = timers.lock();
+    let mut timer = table.timers.remove(&timer_id).ok_or(LinuxErrno::EINVAL)?;
+    timer.cancel();
+    Ok(0)
+}
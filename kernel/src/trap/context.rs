@@ -19,7 +19,10 @@ pub struct TrapFrame {
     hart_id: usize,
     /// 给出 Trap 发生之前 CPU 处在哪个特权级等信息
     sstatus: ExtSstatus,
-    fg: [usize; 2],
+    /// 浮点寄存器组 f0-f31
+    f: [usize; 32],
+    /// 浮点控制与状态寄存器
+    fcsr: usize,
 }
 
 impl TrapFrame {
@@ -73,7 +76,8 @@ impl TrapFrame {
             trap_handler,
             hart_id: 0,
             sstatus,
-            fg: [0; 2],
+            f: [0; 32],
+            fcsr: 0,
         };
         res.x[2] = sp;
         res
@@ -99,4 +103,19 @@ impl TrapFrame {
     pub fn regs(&mut self) -> &mut [usize] {
         &mut self.x
     }
+
+    /// 获取浮点寄存器组的可变引用
+    pub fn fregs(&mut self) -> &mut [usize; 32] {
+        &mut self.f
+    }
+
+    /// 返回 Trap 帧中的 fcsr
+    pub fn fcsr(&self) -> usize {
+        self.fcsr
+    }
+
+    /// 设置 Trap 帧中的 fcsr
+    pub fn set_fcsr(&mut self, val: usize) {
+        self.fcsr = val;
+    }
 }
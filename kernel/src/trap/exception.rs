@@ -6,11 +6,14 @@
 use alloc::sync::Arc;
 
 use arch::interrupt_enable;
-use constants::{AlienError, AlienResult};
+use constants::{AlienError, AlienResult, LinuxErrno};
 use riscv::register::scause::{Exception, Trap};
 use vfs::kfile::File;
 
-use crate::task::{current_task, current_trap_frame};
+use crate::{
+    ipc::syscall_interrupted,
+    task::{current_task, current_trap_frame},
+};
 
 /// 系统调用异常处理
 pub fn syscall_exception_handler() {
@@ -56,6 +59,9 @@ pub fn syscall_exception_handler() {
         parameters[5],
         parameters[6]
     );
+    if result == LinuxErrno::EINTR as isize {
+        syscall_interrupted(parameters[0], parameters[1]);
+    }
     let result = Some(result);
     // cx is changed during sys_exec, so we have to call it again
     cx = current_trap_frame();
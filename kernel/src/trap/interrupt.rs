@@ -4,16 +4,16 @@
 use interrupt::record_irq;
 
 use crate::{
-    ipc::solve_futex_wait,
-    task::do_suspend,
+    task::{cgroup::cpu_tick, do_suspend},
     time::{check_timer_queue, set_next_trigger},
 };
 
-/// 时钟中断处理函数
+/// 时钟中断处理函数，当前任务用完了 cgroup 的 `cpu.weight` 决定的时间片或者被 `cpu.max` 限流时让出 CPU
 pub fn timer_interrupt_handler() {
     record_irq(1);
     check_timer_queue();
-    solve_futex_wait();
     set_next_trigger();
-    do_suspend();
+    if cpu_tick() {
+        do_suspend();
+    }
 }
@@ -23,9 +23,12 @@ use riscv::register::{
 };
 
 use crate::{
-    ipc::{send_signal, signal_handler, signal_return, solve_futex_wait},
-    task::{current_task, current_trap_frame, current_user_token, do_exit, do_suspend},
-    time::{check_timer_queue, set_next_trigger_in_kernel},
+    ipc::{
+        force_signal, send_signal, signal_handler, signal_return, SigInfo, ILL_ILLOPC, SEGV_ACCERR,
+        SEGV_MAPERR,
+    },
+    task::{current_task, current_trap_frame, current_user_token, do_suspend},
+    time::{check_timer_queue, posix_timer::check_cpu_timers, set_next_trigger_in_kernel},
 };
 
 mod context;
@@ -115,14 +118,23 @@ impl TrapHandler for Trap {
             }
             Trap::Exception(Exception::StoreFault)
             | Trap::Exception(Exception::LoadFault)
-            | Trap::Exception(Exception::InstructionFault)
-            | Trap::Exception(Exception::IllegalInstruction) => {
+            | Trap::Exception(Exception::InstructionFault) => {
                 error!(
                     "[User] {:?} in application,stval:{:#x?} sepc:{:#x?}",
                     self, stval, sepc
                 );
-                let task = current_task().unwrap();
-                send_signal(task.get_tid() as usize, SignalNumber::SIGSEGV as usize)
+                force_signal(
+                    SigInfo::new(SignalNumber::SIGSEGV as usize, SEGV_ACCERR).with_addr(stval),
+                )
+            }
+            Trap::Exception(Exception::IllegalInstruction) => {
+                error!(
+                    "[User] {:?} in application,stval:{:#x?} sepc:{:#x?}",
+                    self, stval, sepc
+                );
+                force_signal(
+                    SigInfo::new(SignalNumber::SIGILL as usize, ILL_ILLOPC).with_addr(sepc),
+                )
             }
             Trap::Exception(Exception::StorePageFault)
             | Trap::Exception(Exception::LoadPageFault) => {
@@ -138,14 +150,18 @@ impl TrapHandler for Trap {
                         "[User] {:?} in application,stval:{:#x?} sepc:{:#x?}",
                         self, stval, sepc
                     );
-                    let err = res.err().unwrap();
-                    if err == AlienError::EAGAIN {
-                        // println!("thread need wait");
-                        do_suspend();
-                    } else if err == AlienError::EPERM {
-                        do_exit(-1, 0);
-                    } else {
-                        send_signal(tid as usize, SignalNumber::SIGSEGV as usize)
+                    match res.err().unwrap() {
+                        AlienError::EAGAIN => do_suspend(),
+                        AlienError::ENOMEM => oom_kill(),
+                        // 地址已经映射但没有相应的访问权限
+                        AlienError::EACCES => force_signal(
+                            SigInfo::new(SignalNumber::SIGSEGV as usize, SEGV_ACCERR)
+                                .with_addr(stval),
+                        ),
+                        _ => force_signal(
+                            SigInfo::new(SignalNumber::SIGSEGV as usize, SEGV_MAPERR)
+                                .with_addr(stval),
+                        ),
                     }
                 }
             }
@@ -164,13 +180,19 @@ impl TrapHandler for Trap {
                 }
 
                 let res = exception::page_exception_handler(self.clone(), stval);
-                if res.is_err() {
+                if let Err(err) = res {
                     error!(
                         "[User] {:?} in application,stval:{:#x?} sepc:{:#x?}",
                         self, stval, sepc
                     );
-                    let task = current_task().unwrap();
-                    send_signal(task.get_tid() as usize, SignalNumber::SIGSEGV as usize)
+                    if err == AlienError::ENOMEM {
+                        oom_kill();
+                    } else {
+                        force_signal(
+                            SigInfo::new(SignalNumber::SIGSEGV as usize, SEGV_MAPERR)
+                                .with_addr(stval),
+                        )
+                    }
                 }
             }
             Trap::Interrupt(Interrupt::SupervisorTimer) => {
@@ -199,7 +221,6 @@ impl TrapHandler for Trap {
                 trace!("[kernel] timer interrupt");
                 record_irq(1);
                 check_timer_queue();
-                solve_futex_wait();
                 set_next_trigger_in_kernel();
             }
             Trap::Exception(Exception::StorePageFault) => {
@@ -231,6 +252,17 @@ impl TrapHandler for Trap {
     }
 }
 
+/// 缺页异常中无法为用户程序分配物理页 (所在 cgroup 的内存使用量达到 `memory.max`) 时杀死当前进程
+fn oom_kill() {
+    let task = current_task().unwrap();
+    task.cgroup().record_oom_kill();
+    warn!(
+        "out of memory in page fault, kill process {}",
+        task.get_pid()
+    );
+    send_signal(task.get_pid() as usize, SignalNumber::SIGKILL as usize);
+}
+
 /// 用户态陷入处理
 #[no_mangle]
 pub fn user_trap_vector() {
@@ -261,8 +293,11 @@ pub fn user_trap_vector() {
 }
 
 /// 用于检查进程的计时器是否超时。如果超时则会重置计时器，并按照计时器类型向进程发送信号。
+///
+/// 基于 CPU 时间的 POSIX 计时器也在此时检查，具体可见 [`check_cpu_timers`]。
 pub fn check_task_timer_expired() {
     let task = current_task().unwrap();
+    check_cpu_timers(task);
     let timer_expired = task.access_inner().check_timer_expired();
     let tid = task.get_tid() as usize;
     if timer_expired.is_some() {
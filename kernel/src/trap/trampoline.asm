@@ -5,6 +5,12 @@
 .endm
 .macro LOAD_GP n
     ld x\n, \n*8(sp)
+.endm
+.macro SAVE_FP n
+    fsd f\n, (\n+38)*8(sp)
+.endm
+.macro LOAD_FP n
+    fld f\n, (\n+38)*8(sp)
 .endm
     .section .text.trampoline
     .globl user_v
@@ -27,8 +33,14 @@ user_v:
     csrr t1, sepc
     sd t1, 32*8(sp)
     sd t0, 37*8(sp)
-    fsd fs0, 38*8(sp)
-    fsd fs1, 39*8(sp)
+    # save f0~f31 and fcsr
+    .set n, 0
+    .rept 32
+        SAVE_FP %n
+        .set n, n+1
+    .endr
+    csrr t0, fcsr
+    sd t0, 70*8(sp)
     # read user stack from sscratch and save it in TrapContext
     csrr t2, sscratch
     sd t2, 2*8(sp)
@@ -68,6 +80,14 @@ user_r:
 
     csrw sepc, t1
     csrw sstatus, t0
+    # restore f0~f31 and fcsr
+    .set n, 0
+    .rept 32
+        LOAD_FP %n
+        .set n, n+1
+    .endr
+    ld t0, 70*8(sp)
+    csrw fcsr, t0
     # restore general purpose registers except x0/sp
     ld x1, 1*8(sp)
     .set n, 3
@@ -75,8 +95,6 @@ user_r:
         LOAD_GP %n
         .set n, n+1
     .endr
-    fld fs0, 38*8(sp)
-    fld fs1, 39*8(sp)
 
     # back to user stack
     ld sp, 2*8(sp)
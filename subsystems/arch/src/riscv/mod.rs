@@ -63,6 +63,13 @@ pub fn timer_interrupt_enable() {
     }
 }
 
+/// 等待中断，hart 空闲时使用
+pub fn wait_for_interrupt() {
+    unsafe {
+        riscv::asm::wfi();
+    }
+}
+
 /// 读取时钟
 pub fn read_timer() -> usize {
     riscv::register::time::read()
@@ -77,6 +84,13 @@ pub fn activate_paging_mode(root_ppn: usize) {
     }
 }
 
+/// 允许用户态读取 `time` 寄存器，vDSO 依赖于此
+pub fn allow_user_read_time() {
+    unsafe {
+        riscv::register::scounteren::set_tm();
+    }
+}
+
 /// Permit Supervisor User Memory access
 pub fn allow_access_user_memory() {
     unsafe {
@@ -50,6 +50,24 @@ pub const MAX_INPUT_EVENT_NUM: usize = 1024;
 /// 我们将其从 0x4000_0000 开始放置。主要用于动态链接库使用
 pub const ELF_BASE_RELOCATE: usize = 0x400_0000;
 
+/// 动态链接器 (PT_INTERP 指定的解释器) 的加载基址，实际加载的位置会在此基础上加上一个随机的页偏移
+pub const INTERP_BASE: usize = 0x20_0000_0000;
+/// 动态链接器加载位置的随机偏移的最大页数
+pub const INTERP_RANDOM_PAGES: usize = 0x4_0000;
+/// 开启地址空间随机化时，PIE 程序加载位置的随机偏移的最大页数
+pub const ELF_RANDOM_PAGES: usize = 0x1_0000;
+/// 开启地址空间随机化时，用户栈与程序之间的随机间隔的最大页数
+pub const STACK_RANDOM_PAGES: usize = 0x4000;
+/// 开启地址空间随机化时，堆与用户栈之间的随机间隔的最大页数
+pub const HEAP_RANDOM_PAGES: usize = 0x2000;
+/// 开启地址空间随机化时，mmap 区域起始位置的随机偏移的最大页数
+pub const MMAP_RANDOM_PAGES: usize = 0x4_0000;
+
+/// vDSO 数据页的映射位置，vDSO 镜像紧随其后
+pub const VDSO_BASE: usize = 0x30_0000_0000;
+/// 开启地址空间随机化时，vDSO 映射位置的随机偏移的最大页数
+pub const VDSO_RANDOM_PAGES: usize = 0x4_0000;
+
 // QEMU user networking default IP
 pub const QEMU_IP: &str = "10.0.2.15";
 // QEMU user networking gateway
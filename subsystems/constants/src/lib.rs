@@ -39,6 +39,115 @@ impl From<u64> for DeviceId {
 
 pub const AT_FDCWD: isize = -100isize;
 
+/// 块设备的 ioctl 命令
+pub mod block {
+    /// 重新读取磁盘的分区表
+    pub const BLKRRPART: u32 = 0x125f;
+    /// 获取设备大小(以 512 字节扇区为单位)
+    pub const BLKGETSIZE: u32 = 0x1260;
+    /// 将设备缓存写回磁盘
+    pub const BLKFLSBUF: u32 = 0x1261;
+    /// 获取逻辑扇区大小
+    pub const BLKSSZGET: u32 = 0x1268;
+    /// 获取设备大小(字节)
+    pub const BLKGETSIZE64: u32 = 0x80081272;
+
+    /// `cmd` 是否为块设备的 ioctl 命令
+    pub fn is_block_ioctl(cmd: u32) -> bool {
+        matches!(
+            cmd,
+            BLKRRPART | BLKGETSIZE | BLKFLSBUF | BLKSSZGET | BLKGETSIZE64
+        )
+    }
+}
+
+/// loop 设备的 ioctl 命令与数据结构，与 Linux 的 `include/uapi/linux/loop.h` 相同
+pub mod loop_dev {
+    /// 将文件描述符对应的文件绑定到 loop 设备
+    pub const LOOP_SET_FD: u32 = 0x4c00;
+    /// 解除 loop 设备的绑定
+    pub const LOOP_CLR_FD: u32 = 0x4c01;
+    /// 设置偏移、大小限制与标志
+    pub const LOOP_SET_STATUS64: u32 = 0x4c04;
+    /// 获取绑定信息
+    pub const LOOP_GET_STATUS64: u32 = 0x4c05;
+    /// 一次完成绑定与设置，参数为 [`LoopConfig`]
+    pub const LOOP_CONFIGURE: u32 = 0x4c0a;
+    /// `/dev/loop-control`：创建指定编号的 loop 设备
+    pub const LOOP_CTL_ADD: u32 = 0x4c80;
+    /// `/dev/loop-control`：删除指定编号的 loop 设备
+    pub const LOOP_CTL_REMOVE: u32 = 0x4c81;
+    /// `/dev/loop-control`：获取一个空闲的 loop 设备的编号，没有时创建一个
+    pub const LOOP_CTL_GET_FREE: u32 = 0x4c82;
+
+    pub const LO_FLAGS_READ_ONLY: u32 = 1;
+    pub const LO_FLAGS_AUTOCLEAR: u32 = 4;
+    pub const LO_FLAGS_PARTSCAN: u32 = 8;
+    pub const LO_NAME_SIZE: usize = 64;
+
+    #[repr(C)]
+    #[derive(Debug, Copy, Clone)]
+    pub struct LoopInfo64 {
+        pub lo_device: u64,
+        pub lo_inode: u64,
+        pub lo_rdevice: u64,
+        pub lo_offset: u64,
+        pub lo_sizelimit: u64,
+        pub lo_number: u32,
+        pub lo_encrypt_type: u32,
+        pub lo_encrypt_key_size: u32,
+        pub lo_flags: u32,
+        pub lo_file_name: [u8; LO_NAME_SIZE],
+        pub lo_crypt_name: [u8; LO_NAME_SIZE],
+        pub lo_encrypt_key: [u8; 32],
+        pub lo_init: [u64; 2],
+    }
+
+    impl Default for LoopInfo64 {
+        fn default() -> Self {
+            Self {
+                lo_device: 0,
+                lo_inode: 0,
+                lo_rdevice: 0,
+                lo_offset: 0,
+                lo_sizelimit: 0,
+                lo_number: 0,
+                lo_encrypt_type: 0,
+                lo_encrypt_key_size: 0,
+                lo_flags: 0,
+                lo_file_name: [0; LO_NAME_SIZE],
+                lo_crypt_name: [0; LO_NAME_SIZE],
+                lo_encrypt_key: [0; 32],
+                lo_init: [0; 2],
+            }
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Copy, Clone, Default)]
+    pub struct LoopConfig {
+        pub fd: u32,
+        pub block_size: u32,
+        pub info: LoopInfo64,
+        pub reserved: [u64; 8],
+    }
+
+    /// `cmd` 是否为 loop 设备或 `/dev/loop-control` 的 ioctl 命令
+    pub fn is_loop_ioctl(cmd: u32) -> bool {
+        matches!(
+            cmd,
+            LOOP_SET_FD
+                | LOOP_CLR_FD
+                | LOOP_SET_STATUS64
+                | LOOP_GET_STATUS64
+                | LOOP_CONFIGURE
+                | LOOP_CTL_ADD
+                | LOOP_CTL_REMOVE
+                | LOOP_CTL_GET_FREE
+        )
+    }
+}
+
 const USEC_PER_SEC: usize = 1000_000;
 
 pub trait FromUsize {
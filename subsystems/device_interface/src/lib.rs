@@ -18,10 +18,38 @@ pub trait LowBlockDevice {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) -> AlienResult<()>;
     fn write_block(&self, block_id: usize, buf: &[u8]) -> AlienResult<()>;
     fn capacity(&self) -> usize;
-    fn read_block_async(&self, block_id: usize, buf: &mut [u8]) -> AlienResult<()>;
-    fn write_block_async(&self, block_id: usize, buf: &[u8]) -> AlienResult<()>;
+    /// 提交一个从 `block_id` 开始、读满 `buf` 的异步请求，返回请求的令牌。
+    /// 设备不支持异步请求时返回 `None`，调用者应改用同步接口
+    ///
+    /// # Safety
+    ///
+    /// 在通过 [`LowBlockDevice::complete`] 取回该请求之前，`buf` 必须保持有效且不能被访问
+    unsafe fn submit_read(&self, _block_id: usize, _buf: &mut [u8]) -> AlienResult<Option<u16>> {
+        Ok(None)
+    }
+    /// 提交一个将 `buf` 写入 `block_id` 开始的扇区的异步请求，返回请求的令牌。
+    /// 设备不支持异步请求时返回 `None`，调用者应改用同步接口
+    ///
+    /// # Safety
+    ///
+    /// 在通过 [`LowBlockDevice::complete`] 取回该请求之前，`buf` 必须保持有效且不能被修改
+    unsafe fn submit_write(&self, _block_id: usize, _buf: &[u8]) -> AlienResult<Option<u16>> {
+        Ok(None)
+    }
+    /// 取回一个已经完成的异步请求，返回其令牌与结果
+    fn complete(&self) -> Option<(u16, AlienResult<()>)> {
+        None
+    }
+    /// 设备最多同时处理的异步请求数量
+    fn queue_depth(&self) -> usize {
+        1
+    }
     fn handle_irq(&self);
-    fn flush(&self) {}
+    /// 写屏障：返回时此前所有已完成的写操作都已写入持久存储。
+    /// 没有易失性写缓存的设备无需实现
+    fn flush(&self) -> AlienResult<()> {
+        Ok(())
+    }
 }
 
 pub trait GpuDevice: Any + DeviceBase {
@@ -50,3 +78,11 @@ pub trait UartDevice: DeviceBase {
 }
 
 pub trait NetDevice: DeviceBase {}
+
+/// 9P 协议的传输通道，请求与回复一一对应
+pub trait P9Device: DeviceBase {
+    /// 宿主机共享目录的挂载标签
+    fn mount_tag(&self) -> &str;
+    /// 发送一个请求并等待回复，回复写入 `resp`，返回回复的长度
+    fn transact(&self, req: &[u8], resp: &mut [u8]) -> AlienResult<usize>;
+}
@@ -1,8 +1,19 @@
-use alloc::sync::Arc;
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 
-use constants::DeviceId;
+use constants::{
+    block::{BLKFLSBUF, BLKGETSIZE, BLKGETSIZE64, BLKRRPART, BLKSSZGET},
+    AlienResult, DeviceId,
+};
 use device_interface::BlockDevice;
-use drivers::block_device::GenericBlockDevice;
+use drivers::{block_device::GenericBlockDevice, block_queue::IO_SCHEDULERS};
+use ksync::Mutex;
+use log::info;
 use spin::Once;
 use vfscore::{
     error::VfsError,
@@ -11,49 +22,247 @@ use vfscore::{
     utils::{VfsFileStat, VfsNodeType, VfsPollEvents},
     VfsResult,
 };
-pub static BLOCK_DEVICE: Once<Arc<GenericBlockDevice>> = Once::new();
 
-pub fn init_block_device(block_device: Arc<GenericBlockDevice>) {
-    // BLOCK_DEVICE.lock().push(block_device);
-    BLOCK_DEVICE.call_once(|| block_device);
+use crate::partition::scan_partitions;
+
+/// 系统中的所有磁盘，按探测顺序排列，每项为 (设备名, 设备)
+pub static BLOCK_DEVICES: Mutex<Vec<(String, Arc<GenericBlockDevice>)>> = Mutex::new(Vec::new());
+
+/// 所有带缓存的块设备，除了磁盘之外还包括 loop 设备等建立在其它存储上的块设备，按创建顺序排列
+static CACHED_DEVICES: Mutex<Vec<Weak<GenericBlockDevice>>> = Mutex::new(Vec::new());
+
+/// 登记一个磁盘，设备名由调用者根据磁盘类型生成
+pub fn init_block_device(name: String, block_device: Arc<GenericBlockDevice>) {
+    register_cached_device(&block_device);
+    BLOCK_DEVICES.lock().push((name, block_device));
+}
+
+/// 登记一个带缓存的块设备，使其缓存由 [`flush_block_devices`] 写回。设备被释放后自动注销
+pub fn register_cached_device(device: &Arc<GenericBlockDevice>) {
+    let mut devices = CACHED_DEVICES.lock();
+    devices.retain(|device| device.strong_count() > 0);
+    devices.push(Arc::downgrade(device));
+}
+
+/// 设备名以 `prefix` 开头的磁盘数量，用于生成下一个磁盘的设备名
+pub fn disk_count(prefix: &str) -> usize {
+    BLOCK_DEVICES
+        .lock()
+        .iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .count()
+}
+
+/// 系统中所有的磁盘
+pub fn block_devices() -> Vec<(String, Arc<GenericBlockDevice>)> {
+    BLOCK_DEVICES.lock().clone()
+}
+
+fn find_block_device(disk: &str) -> Option<Arc<GenericBlockDevice>> {
+    BLOCK_DEVICES
+        .lock()
+        .iter()
+        .find(|(name, _)| name == disk)
+        .map(|(_, device)| device.clone())
+}
+
+/// 磁盘 `disk` 可选的 I/O 调度器，当前使用的调度器用方括号括起，如 `noop [deadline]`
+pub fn io_scheduler(disk: &str) -> Option<String> {
+    let current = find_block_device(disk)?.queue().scheduler();
+    let list = IO_SCHEDULERS
+        .iter()
+        .map(|&name| {
+            if name == current {
+                format!("[{}]", name)
+            } else {
+                name.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    Some(list.join(" "))
+}
+
+/// 切换磁盘 `disk` 的 I/O 调度器
+pub fn set_io_scheduler(disk: &str, scheduler: &str) -> AlienResult<()> {
+    find_block_device(disk)
+        .ok_or(VfsError::ENODEV)?
+        .queue()
+        .set_scheduler(scheduler)
+}
+
+/// 将所有块设备缓存中的脏页写回。
+///
+/// 后创建的设备可能以先创建的设备上的文件作为存储(如 loop 设备)，写回时会产生新的脏页，
+/// 因此按创建顺序的逆序写回。某个设备写回失败时仍会写回其余设备，最后返回遇到的第一个错误
+pub fn flush_block_devices() -> AlienResult<()> {
+    let devices = CACHED_DEVICES
+        .lock()
+        .iter()
+        .filter_map(|device| device.upgrade())
+        .collect::<Vec<_>>();
+    let mut res = Ok(());
+    for device in devices.iter().rev() {
+        res = res.and(device.flush());
+    }
+    res
+}
+
+/// 管理块设备在 `/dev` 下的设备节点，由 devfs 实现。
+///
+/// 重新扫描分区表时通过它为新分区分配设备号、创建或删除设备节点
+pub trait BlockNodeManager: Send + Sync {
+    /// 为新的块设备分配设备号
+    fn alloc_device_id(&self) -> DeviceId;
+    /// 创建块设备的设备节点并登记设备
+    fn add_node(&self, device: Arc<BLKDevice>);
+    /// 删除块设备的设备节点并注销设备
+    fn remove_node(&self, device: &BLKDevice);
+}
+
+static BLOCK_NODE_MANAGER: Once<Box<dyn BlockNodeManager>> = Once::new();
+
+pub fn register_block_node_manager(manager: Box<dyn BlockNodeManager>) {
+    BLOCK_NODE_MANAGER.call_once(|| manager);
 }
 
+/// `/dev` 下的一个块设备，表示整个磁盘或者磁盘上的一个分区
 pub struct BLKDevice {
     device_id: DeviceId,
     device: Arc<GenericBlockDevice>,
+    name: String,
+    /// 在磁盘上的起始字节偏移，整个磁盘为 0
+    start: usize,
+    /// 设备的字节大小
+    size: usize,
+    /// 分区号，整个磁盘为 `None`
+    partno: Option<usize>,
+    /// 磁盘上的分区，只对整个磁盘有效
+    partitions: Mutex<Vec<Arc<BLKDevice>>>,
 }
 
 impl BLKDevice {
-    pub fn new(device_id: DeviceId, device: Arc<GenericBlockDevice>) -> Self {
-        Self { device_id, device }
+    /// 创建表示整个磁盘的块设备
+    pub fn new(device_id: DeviceId, name: &str, device: Arc<GenericBlockDevice>) -> Self {
+        let size = device.size();
+        Self {
+            device_id,
+            device,
+            name: name.to_string(),
+            start: 0,
+            size,
+            partno: None,
+            partitions: Mutex::new(Vec::new()),
+        }
     }
     pub fn device_id(&self) -> DeviceId {
         self.device_id
     }
+    /// 设备名，如 `vda`、`vda1`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 分区的设备名。磁盘名以数字结尾时用 `p` 分隔分区号，如 `mmcblk0p1`
+    fn partition_name(&self, number: usize) -> String {
+        if self.name.ends_with(|c: char| c.is_ascii_digit()) {
+            format!("{}p{}", self.name, number)
+        } else {
+            format!("{}{}", self.name, number)
+        }
+    }
+
+    /// 重新扫描磁盘的分区表，删除旧的分区设备并为扫描到的分区创建设备。
+    ///
+    /// 只能对整个磁盘调用，否则返回 `EINVAL`；旧分区仍被打开或挂载时返回 `EBUSY`
+    pub fn rescan_partitions(&self) -> AlienResult<()> {
+        if self.partno.is_some() {
+            return Err(VfsError::EINVAL);
+        }
+        let manager = BLOCK_NODE_MANAGER.get().ok_or(VfsError::ENODEV)?;
+        // 扫描分区表需要等待磁盘 I/O，不能在持有分区列表的锁时进行
+        let found = scan_partitions(&self.device)?;
+        let mut partitions = self.partitions.lock();
+        // 每个分区被分区列表和设备表各引用一次，更多的引用来自打开的文件或挂载的文件系统
+        if partitions.iter().any(|part| Arc::strong_count(part) > 2) {
+            return Err(VfsError::EBUSY);
+        }
+        for part in partitions.drain(..) {
+            manager.remove_node(&part);
+        }
+        for part in found {
+            let device = Arc::new(BLKDevice {
+                device_id: manager.alloc_device_id(),
+                device: self.device.clone(),
+                name: self.partition_name(part.number),
+                start: part.start,
+                size: part.size,
+                partno: Some(part.number),
+                partitions: Mutex::new(Vec::new()),
+            });
+            info!(
+                "{}: partition {} start {:#x} size {}MB",
+                self.name,
+                device.name,
+                part.start,
+                part.size / 1024 / 1024
+            );
+            manager.add_node(device.clone());
+            partitions.push(device);
+        }
+        Ok(())
+    }
 }
 
 impl VfsFile for BLKDevice {
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let offset = offset as usize;
+        if offset >= self.size {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.size - offset);
         self.device
-            .read(buf, offset as usize)
+            .read(&mut buf[..len], self.start + offset)
             .map_err(|_| VfsError::IoError)
     }
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let offset = offset as usize;
+        if offset >= self.size && !buf.is_empty() {
+            return Err(VfsError::ENOSPC);
+        }
+        let len = buf.len().min(self.size - offset);
         self.device
-            .write(buf, offset as usize)
+            .write(&buf[..len], self.start + offset)
             .map_err(|_| VfsError::IoError)
     }
-    fn poll(&self, _event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
-        unimplemented!()
+    fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        // 块设备总是可以读写
+        Ok(event & (VfsPollEvents::IN | VfsPollEvents::OUT))
     }
-    fn ioctl(&self, _cmd: u32, _arg: usize) -> VfsResult<usize> {
-        unimplemented!()
+    fn ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
+        match cmd {
+            BLKGETSIZE64 => {
+                let size = self.size as u64;
+                shim::copy_data_to_task(&size, arg as *mut u64);
+            }
+            BLKGETSIZE => {
+                let sectors = self.size / 512;
+                shim::copy_data_to_task(&sectors, arg as *mut usize);
+            }
+            BLKSSZGET => {
+                let sector_size = 512u32;
+                shim::copy_data_to_task(&sector_size, arg as *mut u32);
+            }
+            BLKFLSBUF => self.device.flush().map_err(|_| VfsError::IoError)?,
+            BLKRRPART => self.rescan_partitions()?,
+            _ => return Err(VfsError::ENOTTY),
+        }
+        Ok(0)
     }
     fn flush(&self) -> VfsResult<()> {
-        Ok(())
+        self.device.flush().map_err(|_| VfsError::IoError)
     }
     fn fsync(&self) -> VfsResult<()> {
-        Ok(())
+        self.device.flush().map_err(|_| VfsError::IoError)
     }
 }
 
@@ -64,7 +273,7 @@ impl VfsInode for BLKDevice {
     fn get_attr(&self) -> VfsResult<VfsFileStat> {
         Ok(VfsFileStat {
             st_rdev: self.device_id.id(),
-            st_size: self.device.size() as u64,
+            st_size: self.size as u64,
             st_blksize: 512,
             ..Default::default()
         })
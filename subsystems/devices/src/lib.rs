@@ -4,20 +4,25 @@ mod block;
 mod gpu;
 mod input;
 mod net;
+mod p9;
+pub mod partition;
 mod prob;
 mod rtc;
 mod uart;
 
 extern crate alloc;
 
-use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use alloc::{boxed::Box, format, sync::Arc, vec::Vec};
 use core::ptr::NonNull;
 
-pub use block::{BLKDevice, BLOCK_DEVICE};
+pub use block::{
+    block_devices, flush_block_devices, io_scheduler, register_block_node_manager,
+    register_cached_device, set_io_scheduler, BLKDevice, BlockNodeManager, BLOCK_DEVICES,
+};
 use config::MAX_INPUT_EVENT_NUM;
-use device_interface::{DeviceBase, GpuDevice, LowBlockDevice};
+use device_interface::{DeviceBase, GpuDevice, LowBlockDevice, P9Device};
+pub use drivers::block_device::GenericBlockDevice;
 use drivers::{
-    block_device::GenericBlockDevice,
     rtc::GoldFishRtc,
     uart::{Uart, Uart16550, Uart8250},
 };
@@ -26,6 +31,7 @@ pub use gpu::{GPUDevice, GPU_DEVICE};
 pub use input::{INPUTDevice, KEYBOARD_INPUT_DEVICE, MOUSE_INPUT_DEVICE};
 use interrupt::register_device_to_plic;
 use log::info;
+pub use p9::find_9p_device;
 use platform::println;
 pub use rtc::{RTCDevice, RTC_DEVICE};
 pub use uart::{UARTDevice, UART_DEVICE};
@@ -173,6 +179,7 @@ pub fn init_virtio_mmio(devices: Vec<prob::DeviceInfo>) {
                     DeviceType::Block => init_block_device(device, Some(transport)),
                     DeviceType::GPU => init_gpu(device, Some(transport)),
                     DeviceType::Network => init_net(Some(device)),
+                    DeviceType::_9P => init_9p_device(device, transport),
                     ty => {
                         println!("Don't support virtio device type: {:?}", ty);
                     }
@@ -204,9 +211,13 @@ fn init_block_device(blk: prob::DeviceInfo, mmio_transport: Option<MmioTransport
             let size = block_device.capacity();
             println!("Block device size is {}MB", size * 512 / 1024 / 1024);
             let block_device = Arc::new(GenericBlockDevice::new(Box::new(block_device)));
-            block::init_block_device(block_device);
-            // register_device_to_plic(irq, block_device);
-            println!("Init block device success");
+            // virtio 磁盘依次命名为 vda、vdb ...
+            let name = format!("vd{}", (b'a' + block::disk_count("vd") as u8) as char);
+            println!("Init block device {} success", name);
+            block::init_block_device(name, block_device.clone());
+            register_device_to_plic(irq, block_device.clone());
+            // 中断注册完成后请求才能在等待时睡眠
+            block_device.queue().enable_irq();
         }
         "starfive,jh7110-sdio" => {
             // starfive2
@@ -218,7 +229,8 @@ fn init_block_device(blk: prob::DeviceInfo, mmio_transport: Option<MmioTransport
                 let size = block_device.capacity();
                 println!("Block device size is {}MB", size * 512 / 1024 / 1024);
                 let block_device = Arc::new(GenericBlockDevice::new(Box::new(block_device)));
-                block::init_block_device(block_device);
+                let name = format!("mmcblk{}", block::disk_count("mmcblk"));
+                block::init_block_device(name, block_device);
                 // register_device_to_plic(irq, block_device);
                 println!("Init SDIO block device success");
             }
@@ -247,7 +259,8 @@ fn init_ramdisk() {
         unsafe { core::slice::from_raw_parts_mut(RAMDISK.as_ptr() as *mut u8, RAMDISK.len()) };
     let block_device = GenericBlockDevice::new(Box::new(MemoryFat32Img::new(data)));
     let block_device = Arc::new(block_device);
-    block::init_block_device(block_device);
+    let name = format!("ram{}", block::disk_count("ram"));
+    block::init_block_device(name, block_device);
     println!("Init fake block device success");
 }
 
@@ -272,6 +285,22 @@ fn init_gpu(gpu: prob::DeviceInfo, mmio_transport: Option<MmioTransport>) {
     }
 }
 
+fn init_9p_device(p9: prob::DeviceInfo, mmio_transport: MmioTransport) {
+    use drivers::p9::VirtIO9pDriver;
+    let (base_addr, irq) = (p9.base_addr, p9.irq);
+    println!("Init 9p device, base_addr:{:#x},irq:{}", base_addr, irq);
+    match VirtIO9pDriver::from_mmio(mmio_transport) {
+        Ok(device) => {
+            // 请求以轮询方式等待完成，不需要注册中断
+            println!("Init 9p device success, mount tag: {}", device.mount_tag());
+            p9::init_9p_device(Arc::new(device));
+        }
+        Err(e) => {
+            println!("Init 9p device failed: {:?}", e);
+        }
+    }
+}
+
 fn init_input_device(input: prob::DeviceInfo, name: &str, mmio_transport: Option<MmioTransport>) {
     let (base_addr, irq) = (input.base_addr, input.irq);
     println!(
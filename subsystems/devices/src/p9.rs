@@ -0,0 +1,20 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use device_interface::P9Device;
+use ksync::Mutex;
+
+/// 宿主机共享目录使用的 9P 传输设备
+static P9_DEVICES: Mutex<Vec<Arc<dyn P9Device>>> = Mutex::new(Vec::new());
+
+pub fn init_9p_device(device: Arc<dyn P9Device>) {
+    P9_DEVICES.lock().push(device);
+}
+
+/// 按挂载标签查找 9P 传输设备
+pub fn find_9p_device(tag: &str) -> Option<Arc<dyn P9Device>> {
+    P9_DEVICES
+        .lock()
+        .iter()
+        .find(|device| device.mount_tag() == tag)
+        .cloned()
+}
@@ -0,0 +1,187 @@
+//! 磁盘分区表解析，支持 MBR(含扩展分区中的逻辑分区) 与 GPT
+use alloc::{vec, vec::Vec};
+
+use constants::AlienResult;
+use device_interface::BlockDevice;
+use drivers::block_device::GenericBlockDevice;
+
+const SECTOR_SIZE: usize = 512;
+/// MBR 中分区表的偏移
+const MBR_PARTITION_TABLE: usize = 446;
+/// GPT 保护分区的类型
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+/// 扩展分区的类型
+const MBR_TYPE_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+/// 逻辑分区链的最大长度，防止损坏的分区表形成环
+const MAX_LOGICAL_PARTITIONS: usize = 128;
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+/// GPT 中允许的最大分区项数量
+const GPT_MAX_ENTRIES: usize = 256;
+
+/// 磁盘上的一个分区
+#[derive(Debug, Copy, Clone)]
+pub struct Partition {
+    /// 分区号，从 1 开始。MBR 的逻辑分区从 5 开始编号
+    pub number: usize,
+    /// 分区在磁盘上的起始字节偏移
+    pub start: usize,
+    /// 分区的字节大小
+    pub size: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct MbrEntry {
+    boot: u8,
+    ty: u8,
+    lba: usize,
+    sectors: usize,
+}
+
+impl MbrEntry {
+    fn parse(sector: &[u8], index: usize) -> Self {
+        let entry =
+            &sector[MBR_PARTITION_TABLE + index * 16..MBR_PARTITION_TABLE + (index + 1) * 16];
+        Self {
+            boot: entry[0],
+            ty: entry[4],
+            lba: u32::from_le_bytes(entry[8..12].try_into().unwrap()) as usize,
+            sectors: u32::from_le_bytes(entry[12..16].try_into().unwrap()) as usize,
+        }
+    }
+    fn is_empty(&self) -> bool {
+        self.ty == 0 || self.sectors == 0
+    }
+    fn is_extended(&self) -> bool {
+        MBR_TYPE_EXTENDED.contains(&self.ty)
+    }
+}
+
+fn read_sector(device: &GenericBlockDevice, lba: usize) -> AlienResult<Vec<u8>> {
+    let mut buf = vec![0u8; SECTOR_SIZE];
+    device.read(&mut buf, lba * SECTOR_SIZE)?;
+    Ok(buf)
+}
+
+fn has_mbr_signature(sector: &[u8]) -> bool {
+    sector[510] == 0x55 && sector[511] == 0xaa
+}
+
+/// 没有分区表、直接格式化为 FAT 的磁盘在同一位置也有 0x55aa 签名，需要排除
+fn is_fat_boot_sector(sector: &[u8]) -> bool {
+    (sector[0] == 0xeb || sector[0] == 0xe9)
+        && (&sector[54..57] == b"FAT" || &sector[82..87] == b"FAT32")
+}
+
+/// 扫描磁盘上的分区表，返回所有有效的分区。磁盘没有可识别的分区表时返回空列表
+pub fn scan_partitions(device: &GenericBlockDevice) -> AlienResult<Vec<Partition>> {
+    let capacity = device.size() / SECTOR_SIZE;
+    let mbr = read_sector(device, 0)?;
+    if !has_mbr_signature(&mbr) || is_fat_boot_sector(&mbr) {
+        return Ok(Vec::new());
+    }
+    let entries = (0..4).map(|i| MbrEntry::parse(&mbr, i)).collect::<Vec<_>>();
+    if entries.iter().any(|e| e.boot != 0 && e.boot != 0x80) {
+        return Ok(Vec::new());
+    }
+    if entries.iter().any(|e| e.ty == MBR_TYPE_GPT_PROTECTIVE) {
+        return scan_gpt(device, capacity);
+    }
+    let mut partitions = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.is_empty() {
+            continue;
+        }
+        if entry.is_extended() {
+            scan_logical(device, capacity, entry.lba, &mut partitions)?;
+            continue;
+        }
+        push_partition(&mut partitions, capacity, i + 1, entry.lba, entry.sectors);
+    }
+    Ok(partitions)
+}
+
+/// 沿扩展引导记录(EBR)链扫描逻辑分区。每个 EBR 的第一项描述一个逻辑分区(相对该 EBR)，
+/// 第二项指向下一个 EBR(相对扩展分区起始位置)
+fn scan_logical(
+    device: &GenericBlockDevice,
+    capacity: usize,
+    extended_start: usize,
+    partitions: &mut Vec<Partition>,
+) -> AlienResult<()> {
+    let mut ebr_lba = extended_start;
+    let mut number = 5;
+    for _ in 0..MAX_LOGICAL_PARTITIONS {
+        if ebr_lba >= capacity {
+            break;
+        }
+        let ebr = read_sector(device, ebr_lba)?;
+        if !has_mbr_signature(&ebr) {
+            break;
+        }
+        let logical = MbrEntry::parse(&ebr, 0);
+        if !logical.is_empty() {
+            push_partition(
+                partitions,
+                capacity,
+                number,
+                ebr_lba + logical.lba,
+                logical.sectors,
+            );
+            number += 1;
+        }
+        let next = MbrEntry::parse(&ebr, 1);
+        if next.is_empty() || !next.is_extended() {
+            break;
+        }
+        ebr_lba = extended_start + next.lba;
+    }
+    Ok(())
+}
+
+fn scan_gpt(device: &GenericBlockDevice, capacity: usize) -> AlienResult<Vec<Partition>> {
+    let header = read_sector(device, 1)?;
+    if &header[0..8] != GPT_SIGNATURE {
+        return Ok(Vec::new());
+    }
+    let entry_lba = u64::from_le_bytes(header[72..80].try_into().unwrap()) as usize;
+    let entry_count = u32::from_le_bytes(header[80..84].try_into().unwrap()) as usize;
+    let entry_size = u32::from_le_bytes(header[84..88].try_into().unwrap()) as usize;
+    if !(128..=SECTOR_SIZE).contains(&entry_size) || SECTOR_SIZE % entry_size != 0 {
+        return Ok(Vec::new());
+    }
+    let entry_count = entry_count.min(GPT_MAX_ENTRIES);
+    let mut table = vec![0u8; entry_count * entry_size];
+    device.read(&mut table, entry_lba * SECTOR_SIZE)?;
+    let mut partitions = Vec::new();
+    for (i, entry) in table.chunks(entry_size).enumerate() {
+        // 分区类型 GUID 全为 0 表示未使用的分区项
+        if entry[0..16].iter().all(|&b| b == 0) {
+            continue;
+        }
+        let first = u64::from_le_bytes(entry[32..40].try_into().unwrap()) as usize;
+        let last = u64::from_le_bytes(entry[40..48].try_into().unwrap()) as usize;
+        if last < first {
+            continue;
+        }
+        push_partition(&mut partitions, capacity, i + 1, first, last - first + 1);
+    }
+    Ok(partitions)
+}
+
+/// 记录一个分区，超出磁盘范围的分区会被忽略
+fn push_partition(
+    partitions: &mut Vec<Partition>,
+    capacity: usize,
+    number: usize,
+    lba: usize,
+    sectors: usize,
+) {
+    if lba == 0 || lba >= capacity || sectors > capacity - lba {
+        return;
+    }
+    partitions.push(Partition {
+        number,
+        start: lba * SECTOR_SIZE,
+        size: sectors * SECTOR_SIZE,
+    });
+}
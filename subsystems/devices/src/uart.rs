@@ -2,6 +2,7 @@ use alloc::sync::Arc;
 
 use constants::{
     io::{LocalModes, TeletypeCommand, Termios, WinSize},
+    signal::SignalNumber,
     DeviceId,
 };
 use device_interface::UartDevice;
@@ -22,6 +23,19 @@ pub fn init_uart(uart: Arc<dyn UartDevice>) {
     UART_DEVICE.call_once(|| uart);
 }
 
+/// 在设置了 `ISIG` 时，终端收到后会向前台进程组发送信号的控制字符
+fn signal_of_char(ch: u8) -> Option<SignalNumber> {
+    match ch {
+        // Ctrl-C
+        0x03 => Some(SignalNumber::SIGINT),
+        // Ctrl-\
+        0x1c => Some(SignalNumber::SIGQUIT),
+        // Ctrl-Z
+        0x1a => Some(SignalNumber::SIGTSTP),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct IoData {
     foreground_pgid: u32,
@@ -56,6 +70,22 @@ impl VfsFile for UARTDevice {
             let ch = self.device.get();
             assert!(ch.is_some());
             let ch = ch.unwrap();
+            let (lflag, foreground_pgid) = {
+                let io = self.io.lock();
+                (
+                    LocalModes::from_bits_truncate(io.termios.lflag),
+                    io.foreground_pgid,
+                )
+            };
+            // 控制字符不会被读取，而是向前台进程组发送对应的信号
+            if lflag.contains(LocalModes::ISIG) {
+                if let Some(sig) = signal_of_char(ch) {
+                    if foreground_pgid != 0 {
+                        shim::kill_pgrp(foreground_pgid as usize, sig as usize);
+                    }
+                    continue;
+                }
+            }
             buf[read_count] = ch;
             read_count += 1;
             if ch == b'\r' {
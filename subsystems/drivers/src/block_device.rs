@@ -1,4 +1,8 @@
-use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
 use core::{
     cmp::min,
     fmt::{Debug, Formatter},
@@ -10,26 +14,33 @@ use core::{
 use config::FRAME_SIZE;
 use constants::{AlienResult, LinuxErrno};
 use device_interface::{BlockDevice, DeviceBase, LowBlockDevice};
-use ksync::{Mutex, RwLock};
+use ksync::{Mutex, RwLock, SleepMutex};
 use lru::LruCache;
 use mem::{alloc_frames, free_frames};
 use platform::config::{BLOCK_CACHE_FRAMES, CLOCK_FREQ};
-use shim::KTask;
 use timer::read_timer;
 use virtio_drivers::{
-    device::blk::VirtIOBlk,
+    device::blk::{BlkReq, BlkResp, VirtIOBlk},
     transport::mmio::{MmioTransport, VirtIOHeader},
 };
 pub use visionfive2_sd::Vf2SdDriver;
 use visionfive2_sd::{SDIo, SleepOps};
 
-use crate::hal::HalImpl;
+use crate::{
+    block_queue::{RequestQueue, SECTOR_SIZE},
+    hal::HalImpl,
+};
 const PAGE_CACHE_SIZE: usize = FRAME_SIZE;
 
+/// 块设备的页缓存。
+///
+/// 以 `PAGE_CACHE_SIZE` 为单位缓存设备上的数据，写操作只修改缓存并把页标记为脏页，
+/// 脏页在被 LRU 换出或调用 [`BlockDevice::flush`] 时写回设备。
+/// 对设备的读写都经过请求队列，等待 I/O 时持有的是可以睡眠的缓存锁。
 pub struct GenericBlockDevice {
-    device: Box<dyn LowBlockDevice>,
-    cache: Mutex<LruCache<usize, FrameTracker>>,
-    dirty: Mutex<Vec<usize>>,
+    queue: RequestQueue,
+    cache: SleepMutex<LruCache<usize, FrameTracker>>,
+    dirty: Mutex<BTreeSet<usize>>,
 }
 
 #[derive(Debug)]
@@ -70,18 +81,55 @@ unsafe impl Sync for GenericBlockDevice {}
 impl GenericBlockDevice {
     pub fn new(device: Box<dyn LowBlockDevice>) -> Self {
         Self {
-            device,
-            cache: Mutex::new(LruCache::new(
+            queue: RequestQueue::new(device),
+            cache: SleepMutex::new(LruCache::new(
                 NonZeroUsize::new(BLOCK_CACHE_FRAMES).unwrap(),
             )),
-            dirty: Mutex::new(Vec::new()),
+            dirty: Mutex::new(BTreeSet::new()),
         }
     }
+
+    /// 设备的请求队列
+    pub fn queue(&self) -> &RequestQueue {
+        &self.queue
+    }
+
+    /// 将缓存页 `page_id` 的内容写回设备
+    fn write_page(&self, page_id: usize, cache: &[u8]) -> AlienResult<()> {
+        self.queue
+            .write_batch(&[(page_id * PAGE_CACHE_SIZE / SECTOR_SIZE, cache)])
+    }
+
+    /// 确保页 `page_id` 位于缓存中。缓存未命中时从设备读入，若需要换出一个脏页则先将其写回
+    fn fetch_page(
+        &self,
+        cache_lock: &mut LruCache<usize, FrameTracker>,
+        page_id: usize,
+    ) -> AlienResult<()> {
+        if cache_lock.contains(&page_id) {
+            return Ok(());
+        }
+        let cache = alloc_frames(1);
+        let mut cache = FrameTracker::new(cache as usize);
+        self.queue
+            .read(page_id * PAGE_CACHE_SIZE / SECTOR_SIZE, &mut cache)?;
+        // 缓存已满时先写回将被换出的脏页，写回失败则保留该页
+        if cache_lock.len() == cache_lock.cap().get() {
+            if let Some((&id, old_cache)) = cache_lock.peek_lru() {
+                if self.dirty.lock().contains(&id) {
+                    self.write_page(id, old_cache)?;
+                    self.dirty.lock().remove(&id);
+                }
+            }
+        }
+        cache_lock.push(page_id, cache);
+        Ok(())
+    }
 }
 
 impl DeviceBase for GenericBlockDevice {
     fn handle_irq(&self) {
-        self.device.handle_irq();
+        self.queue.handle_irq();
     }
 }
 
@@ -101,29 +149,7 @@ impl BlockDevice for GenericBlockDevice {
         let mut count = 0;
 
         while count < len {
-            if !cache_lock.contains(&page_id) {
-                let device = &self.device;
-                let cache = alloc_frames(1);
-                let mut cache = FrameTracker::new(cache as usize);
-                let start_block = page_id * PAGE_CACHE_SIZE / 512;
-                let end_block = start_block + PAGE_CACHE_SIZE / 512;
-                for i in start_block..end_block {
-                    let target_buf =
-                        &mut cache[(i - start_block) * 512..(i - start_block + 1) * 512];
-                    device.read_block(i, target_buf).unwrap();
-                }
-                let old_cache = cache_lock.push(page_id, cache);
-                if let Some((id, old_cache)) = old_cache {
-                    let start_block = id * PAGE_CACHE_SIZE / 512;
-                    let end_block = start_block + PAGE_CACHE_SIZE / 512;
-                    for i in start_block..end_block {
-                        let target_buf =
-                            &old_cache[(i - start_block) * 512..(i - start_block + 1) * 512];
-                        device.write_block(i, target_buf).unwrap();
-                        self.dirty.lock().retain(|&x| x != id);
-                    }
-                }
-            }
+            self.fetch_page(&mut cache_lock, page_id)?;
             let cache = cache_lock.get(&page_id).unwrap();
             let copy_len = min(PAGE_CACHE_SIZE - offset, len - count);
             buf[count..count + copy_len].copy_from_slice(&cache[offset..offset + copy_len]);
@@ -140,32 +166,11 @@ impl BlockDevice for GenericBlockDevice {
         let len = buf.len();
         let mut count = 0;
         while count < len {
-            if !cache_lock.contains(&page_id) {
-                let device = &self.device;
-                let cache = alloc_frames(1);
-                let mut cache = FrameTracker::new(cache as usize);
-                let start_block = page_id * PAGE_CACHE_SIZE / 512;
-                let end_block = start_block + PAGE_CACHE_SIZE / 512;
-                for i in start_block..end_block {
-                    let target_buf =
-                        &mut cache[(i - start_block) * 512..(i - start_block + 1) * 512];
-                    device.read_block(i, target_buf).unwrap();
-                }
-                let old_cache = cache_lock.push(page_id, cache);
-                if let Some((id, old_cache)) = old_cache {
-                    let start_block = id * PAGE_CACHE_SIZE / 512;
-                    let end_block = start_block + PAGE_CACHE_SIZE / 512;
-                    for i in start_block..end_block {
-                        let target_buf =
-                            &old_cache[(i - start_block) * 512..(i - start_block + 1) * 512];
-                        device.write_block(i, target_buf).unwrap();
-                        self.dirty.lock().retain(|&x| x != id);
-                    }
-                }
-            }
+            self.fetch_page(&mut cache_lock, page_id)?;
             let cache = cache_lock.get_mut(&page_id).unwrap();
             let copy_len = min(PAGE_CACHE_SIZE - offset, len - count);
             cache[offset..offset + copy_len].copy_from_slice(&buf[count..count + copy_len]);
+            self.dirty.lock().insert(page_id);
             count += copy_len;
             offset = (offset + copy_len) % PAGE_CACHE_SIZE;
             page_id += 1;
@@ -173,31 +178,49 @@ impl BlockDevice for GenericBlockDevice {
         Ok(buf.len())
     }
     fn size(&self) -> usize {
-        self.device.capacity() * 512
+        self.queue.device().capacity() * SECTOR_SIZE
     }
+    /// 一次提交所有脏页的写回请求，由调度器合并相邻的页，然后向设备发出写屏障，保证此前写入的数据都已落盘
     fn flush(&self) -> AlienResult<()> {
-        // let mut device = self.device.lock();
-        // let mut lru = self.cache.lock();
-        // self.dirty.lock().iter().for_each(|id|{
-        //     let start = id * PAGE_CACHE_SIZE;
-        //     let start_block = start / 512;
-        //     let end_block = (start + PAGE_CACHE_SIZE) / 512;
-        //     let cache = lru.get(id).unwrap();
-        //     for i in start_block..end_block {
-        //         let target_buf = &cache[(i - start_block) * 512..(i - start_block + 1) * 512];
-        //         device.write_block(i, target_buf).unwrap();
-        //     }
-        // });
-        // self.dirty.lock().clear();
-        Ok(())
+        let cache_lock = self.cache.lock();
+        let dirty = core::mem::take(&mut *self.dirty.lock());
+        let writes = dirty
+            .iter()
+            .map(|&id| {
+                let cache = cache_lock.peek(&id).unwrap();
+                (id * PAGE_CACHE_SIZE / SECTOR_SIZE, &cache[..])
+            })
+            .collect::<Vec<_>>();
+        if let Err(e) = self.queue.write_batch(&writes) {
+            // 不知道哪些页写回成功，全部保留为脏页
+            self.dirty.lock().extend(dirty);
+            return Err(e);
+        }
+        drop(cache_lock);
+        self.queue.flush()
     }
 }
 
 pub struct VirtIOBlkWrapper {
     device: Mutex<VirtIOBlk<HalImpl, MmioTransport>>,
-    wait_queue: Mutex<BTreeMap<u16, Arc<dyn KTask>>>,
+    /// 已经提交给设备、尚未取回的异步请求
+    in_flight: Mutex<BTreeMap<u16, InFlight>>,
+}
+
+/// 一个异步请求在完成前需要保持有效的数据
+struct InFlight {
+    req: Box<BlkReq>,
+    resp: Box<BlkResp>,
+    buf: *mut u8,
+    len: usize,
+    write: bool,
 }
 
+unsafe impl Send for InFlight {}
+
+/// virtio-blk 的描述符表有 16 项，每个请求占用 3 项
+const VIRTIO_BLK_QUEUE_DEPTH: usize = 4;
+
 impl VirtIOBlkWrapper {
     pub fn new(addr: usize) -> Self {
         let header = NonNull::new(addr as *mut VirtIOHeader).unwrap();
@@ -206,7 +229,7 @@ impl VirtIOBlkWrapper {
             .expect("failed to create blk driver");
         Self {
             device: Mutex::new(blk),
-            wait_queue: Mutex::new(BTreeMap::new()),
+            in_flight: Mutex::new(BTreeMap::new()),
         }
     }
 
@@ -215,7 +238,7 @@ impl VirtIOBlkWrapper {
             .expect("failed to create blk driver");
         Self {
             device: Mutex::new(blk),
-            wait_queue: Mutex::new(BTreeMap::new()),
+            in_flight: Mutex::new(BTreeMap::new()),
         }
     }
 }
@@ -240,93 +263,78 @@ impl LowBlockDevice for VirtIOBlkWrapper {
         self.device.lock().capacity() as usize
     }
 
-    fn read_block_async(&self, block_id: usize, buf: &mut [u8]) -> AlienResult<()> {
-        return self.read_block(block_id, buf);
-        // let task = shim::take_current_task();
-        // if task.is_none() {
-        //     return self.read_block(block_id, buf);
-        // }
-        // let task = task.unwrap();
-        //
-        // use virtio_drivers::device::blk::{BlkReq,BlkResp,RespStatus};
-        //
-        // let mut resp = BlkResp::default();
-        // let mut req = BlkReq::default();
-        // let mut device = self.device.lock();
-        // let token = unsafe { device.read_block_nb(block_id, &mut req, buf, &mut resp) };
-        // match token {
-        //     Ok(token) => {
-        //         task.to_wait();
-        //         self.wait_queue.lock().insert(token, task.clone());
-        //         // platform::println!("insert token:{}, intr: {}", token, arch::is_interrupt_enable());
-        //         drop(device);
-        //         shim::schedule_now(task);
-        //         unsafe {
-        //             self.device
-        //                 .lock()
-        //                 .complete_read_block(token, &req, buf, &mut resp)
-        //                 .unwrap();
-        //         }
-        //         assert_eq!(
-        //             resp.status(),
-        //             RespStatus::OK,
-        //             "Error {:?} reading block.",
-        //             resp.status()
-        //         );
-        //         Ok(())
-        //     }
-        //     Err(virtio_drivers::Error::QueueFull) => self.read_block(block_id, buf),
-        //     Err(_) => Err(LinuxErrno::EIO.into()),
-        // }
-    }
-
-    fn write_block_async(&self, block_id: usize, buf: &[u8]) -> AlienResult<()> {
-        return self.write_block(block_id, buf);
-        // let task = shim::take_current_task();
-        // if task.is_none() {
-        //     return self.write_block(block_id, buf);
-        // }
-        // let task = task.unwrap();
-        // let mut resp = BlkResp::default();
-        // let mut req = BlkReq::default();
-        //
-        // let mut device = self.device.lock();
-        //
-        // let token = unsafe { device.write_block_nb(block_id, &mut req, buf, &mut resp) };
-        // match token {
-        //     Ok(token) => {
-        //         task.to_wait();
-        //         self.wait_queue.lock().insert(token, task.clone());
-        //         drop(device);
-        //         shim::schedule_now(task);
-        //         unsafe {
-        //             self.device
-        //                 .lock()
-        //                 .complete_write_block(token, &req, buf, &mut resp)
-        //                 .unwrap();
-        //         }
-        //         assert_eq!(
-        //             resp.status(),
-        //             RespStatus::OK,
-        //             "Error {:?} writing block.",
-        //             resp.status()
-        //         );
-        //         Ok(())
-        //     }
-        //     Err(virtio_drivers::Error::QueueFull) => self.write_block(block_id, buf),
-        //     Err(_) => Err(LinuxErrno::EIO.into()),
-        // }
+    /// virtio-blk 没有 FUA 标志，协商了 `VIRTIO_BLK_F_FLUSH` 时通过 FLUSH 请求清空设备的写缓存，
+    /// 否则设备保证写操作完成即落盘
+    fn flush(&self) -> AlienResult<()> {
+        self.device
+            .lock()
+            .flush()
+            .map_err(|_| LinuxErrno::EIO.into())
     }
 
-    fn handle_irq(&self) {
+    unsafe fn submit_read(&self, block_id: usize, buf: &mut [u8]) -> AlienResult<Option<u16>> {
+        let mut req = Box::new(BlkReq::default());
+        let mut resp = Box::new(BlkResp::default());
+        // 持有设备锁时中断被关闭，请求在登记之前不会被中断处理程序取走
         let mut device = self.device.lock();
-        device.ack_interrupt();
-        if let Some(token) = device.peek_used() {
-            let mut wait_queue = self.wait_queue.lock();
-            let task = wait_queue.remove(&token).unwrap();
-            task.to_wakeup();
-            shim::put_task(task);
-        }
+        let token = device
+            .read_blocks_nb(block_id, &mut req, buf, &mut resp)
+            .map_err(|_| LinuxErrno::EIO)?;
+        self.in_flight.lock().insert(
+            token,
+            InFlight {
+                req,
+                resp,
+                buf: buf.as_mut_ptr(),
+                len: buf.len(),
+                write: false,
+            },
+        );
+        Ok(Some(token))
+    }
+
+    unsafe fn submit_write(&self, block_id: usize, buf: &[u8]) -> AlienResult<Option<u16>> {
+        let mut req = Box::new(BlkReq::default());
+        let mut resp = Box::new(BlkResp::default());
+        let mut device = self.device.lock();
+        let token = device
+            .write_blocks_nb(block_id, &mut req, buf, &mut resp)
+            .map_err(|_| LinuxErrno::EIO)?;
+        self.in_flight.lock().insert(
+            token,
+            InFlight {
+                req,
+                resp,
+                buf: buf.as_ptr() as *mut u8,
+                len: buf.len(),
+                write: true,
+            },
+        );
+        Ok(Some(token))
+    }
+
+    fn complete(&self) -> Option<(u16, AlienResult<()>)> {
+        let mut device = self.device.lock();
+        let token = device.peek_used()?;
+        let mut request = self.in_flight.lock().remove(&token)?;
+        let res = unsafe {
+            if request.write {
+                let buf = core::slice::from_raw_parts(request.buf, request.len);
+                device.complete_write_blocks(token, &request.req, buf, &mut request.resp)
+            } else {
+                let buf = core::slice::from_raw_parts_mut(request.buf, request.len);
+                device.complete_read_blocks(token, &request.req, buf, &mut request.resp)
+            }
+        };
+        Some((token, res.map_err(|_| LinuxErrno::EIO)))
+    }
+
+    fn queue_depth(&self) -> usize {
+        VIRTIO_BLK_QUEUE_DEPTH
+    }
+
+    fn handle_irq(&self) {
+        self.device.lock().ack_interrupt();
     }
 }
 
@@ -350,13 +358,6 @@ impl LowBlockDevice for MemoryFat32Img {
     fn capacity(&self) -> usize {
         self.data.read().len() / 512
     }
-    fn read_block_async(&self, block_id: usize, buf: &mut [u8]) -> AlienResult<()> {
-        self.read_block(block_id, buf)
-    }
-
-    fn write_block_async(&self, block_id: usize, buf: &[u8]) -> AlienResult<()> {
-        self.write_block(block_id, buf)
-    }
     fn handle_irq(&self) {}
 }
 
@@ -398,13 +399,6 @@ impl LowBlockDevice for VF2SDDriver {
         // 32GB
         32 * 1024 * 1024 * 1024 / 512
     }
-    fn read_block_async(&self, block_id: usize, buf: &mut [u8]) -> AlienResult<()> {
-        self.read_block(block_id, buf)
-    }
-
-    fn write_block_async(&self, block_id: usize, buf: &[u8]) -> AlienResult<()> {
-        self.write_block(block_id, buf)
-    }
     fn handle_irq(&self) {
         unimplemented!()
     }
@@ -0,0 +1,498 @@
+//! 块设备的请求队列。
+//!
+//! 上层以扇区为单位提交 [`Bio`]，由 I/O 调度器([`NoopScheduler`]、[`DeadlineScheduler`])排序并把相邻的
+//! `Bio` 合并为一个多扇区请求后交给设备。支持异步请求的设备在请求完成后通过中断通知，
+//! 中断处理程序唤醒等待的任务；不支持异步请求的设备，或者当前上下文不能睡眠时，退化为同步 I/O 或轮询。
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use constants::{AlienResult, LinuxErrno};
+use device_interface::LowBlockDevice;
+use ksync::Mutex;
+use platform::config::CLOCK_FREQ;
+use shim::KTask;
+use timer::read_timer;
+
+pub const SECTOR_SIZE: usize = 512;
+/// 合并后单个请求的最大扇区数
+const MAX_REQUEST_SECTORS: usize = 256;
+
+/// 可以选择的 I/O 调度器
+pub const IO_SCHEDULERS: [&str; 2] = ["noop", "deadline"];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IoDirection {
+    Read = 0,
+    Write = 1,
+}
+
+/// 一组 I/O 的完成通知
+struct Completion {
+    state: Mutex<CompletionState>,
+}
+
+#[derive(Default)]
+struct CompletionState {
+    result: Option<AlienResult<()>>,
+    waiter: Option<Arc<dyn KTask>>,
+}
+
+impl Completion {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(CompletionState::default()),
+        }
+    }
+    /// 记录结果并唤醒等待的任务
+    fn finish(&self, result: AlienResult<()>) {
+        let mut state = self.state.lock();
+        state.result = Some(result);
+        if let Some(task) = state.waiter.take() {
+            task.to_wakeup();
+            shim::put_task(task);
+        }
+    }
+}
+
+/// 提交者的一段连续 I/O，缓冲区由提交者持有，在完成前保持有效
+pub struct Bio {
+    dir: IoDirection,
+    sector: usize,
+    buf: *mut u8,
+    len: usize,
+    /// 提交的时刻，deadline 调度器据此判断请求是否超时
+    submit_time: usize,
+    completion: Arc<Completion>,
+}
+
+unsafe impl Send for Bio {}
+
+impl Bio {
+    fn sectors(&self) -> usize {
+        self.len / SECTOR_SIZE
+    }
+    fn end(&self) -> usize {
+        self.sector + self.sectors()
+    }
+}
+
+/// 由若干个相邻的 [`Bio`] 合并而成、一次交给设备的请求
+struct Request {
+    dir: IoDirection,
+    sector: usize,
+    bios: Vec<Bio>,
+    /// 合并了多个 `Bio` 时使用的连续缓冲区
+    bounce: Option<Vec<u8>>,
+}
+
+impl Request {
+    fn new(bios: Vec<Bio>) -> Self {
+        let dir = bios[0].dir;
+        let sector = bios[0].sector;
+        let bounce = if bios.len() > 1 {
+            let len = bios.iter().map(|bio| bio.len).sum();
+            let mut bounce = vec![0u8; len];
+            if dir == IoDirection::Write {
+                let mut offset = 0;
+                for bio in bios.iter() {
+                    let data = unsafe { core::slice::from_raw_parts(bio.buf, bio.len) };
+                    bounce[offset..offset + bio.len].copy_from_slice(data);
+                    offset += bio.len;
+                }
+            }
+            Some(bounce)
+        } else {
+            None
+        };
+        Self {
+            dir,
+            sector,
+            bios,
+            bounce,
+        }
+    }
+
+    fn buffer(&mut self) -> &mut [u8] {
+        match self.bounce {
+            Some(ref mut bounce) => bounce.as_mut_slice(),
+            None => unsafe { core::slice::from_raw_parts_mut(self.bios[0].buf, self.bios[0].len) },
+        }
+    }
+
+    /// 请求完成，把读到的数据分发给各个 `Bio` 并通知提交者
+    fn finish(self, result: AlienResult<()>) {
+        if let (Some(bounce), IoDirection::Read, Ok(())) = (&self.bounce, self.dir, result) {
+            let mut offset = 0;
+            for bio in self.bios.iter() {
+                let data = unsafe { core::slice::from_raw_parts_mut(bio.buf, bio.len) };
+                data.copy_from_slice(&bounce[offset..offset + bio.len]);
+                offset += bio.len;
+            }
+        }
+        for bio in self.bios {
+            bio.completion.finish(result);
+        }
+    }
+}
+
+/// I/O 调度器，决定待处理 `Bio` 交给设备的顺序以及如何合并
+pub trait IoScheduler: Send {
+    fn name(&self) -> &'static str;
+    fn add(&mut self, bio: Bio);
+    /// 取出下一批要交给设备的相邻 `Bio`，`now` 为当前时刻
+    fn dispatch(&mut self, now: usize) -> Option<Vec<Bio>>;
+    /// 取出所有尚未派发的 `Bio`，用于切换调度器
+    fn drain(&mut self) -> Vec<Bio>;
+}
+
+/// 按提交顺序派发，只把与队尾相邻的 `Bio` 合并
+#[derive(Default)]
+pub struct NoopScheduler {
+    queue: VecDeque<Vec<Bio>>,
+}
+
+impl IoScheduler for NoopScheduler {
+    fn name(&self) -> &'static str {
+        "noop"
+    }
+    fn add(&mut self, bio: Bio) {
+        if let Some(back) = self.queue.back_mut() {
+            let last = back.last().unwrap();
+            let sectors = back.iter().map(|bio| bio.sectors()).sum::<usize>();
+            if last.dir == bio.dir
+                && last.end() == bio.sector
+                && sectors + bio.sectors() <= MAX_REQUEST_SECTORS
+            {
+                back.push(bio);
+                return;
+            }
+        }
+        self.queue.push_back(vec![bio]);
+    }
+    fn dispatch(&mut self, _now: usize) -> Option<Vec<Bio>> {
+        self.queue.pop_front()
+    }
+    fn drain(&mut self) -> Vec<Bio> {
+        self.queue.drain(..).flatten().collect()
+    }
+}
+
+/// 读请求的超时时间(ms)
+const READ_EXPIRE_MS: usize = 500;
+/// 写请求的超时时间(ms)
+const WRITE_EXPIRE_MS: usize = 5000;
+/// 有写请求等待时最多连续派发的读请求批次
+const WRITES_STARVED: usize = 2;
+
+/// 按扇区顺序单向扫描派发(电梯算法)，读请求优先；某个方向上最早的请求超时后优先派发该请求，
+/// 保证请求不会被饿死
+#[derive(Default)]
+pub struct DeadlineScheduler {
+    /// 每个方向上按扇区排序的 `Bio`，键为 (起始扇区, 提交序号)
+    sorted: [BTreeMap<(usize, usize), Bio>; 2],
+    /// 每个方向上按提交顺序排列的键，已派发的键在检查时再移除
+    fifo: [VecDeque<(usize, usize)>; 2],
+    /// 每个方向上次派发结束的扇区，下一次从这里继续扫描
+    next_sector: [usize; 2],
+    seq: usize,
+    /// 有写请求等待时已经连续派发的读请求批次
+    starved: usize,
+}
+
+impl DeadlineScheduler {
+    /// `dir` 方向上最早提交的、尚未派发的 `Bio` 若已超时则返回其键
+    fn expired(&mut self, dir: IoDirection, now: usize) -> Option<(usize, usize)> {
+        let d = dir as usize;
+        while let Some(key) = self.fifo[d].front() {
+            match self.sorted[d].get(key) {
+                Some(bio) => {
+                    let expire = match dir {
+                        IoDirection::Read => READ_EXPIRE_MS,
+                        IoDirection::Write => WRITE_EXPIRE_MS,
+                    } * CLOCK_FREQ
+                        / 1000;
+                    return (now.saturating_sub(bio.submit_time) >= expire).then_some(*key);
+                }
+                None => {
+                    self.fifo[d].pop_front();
+                }
+            }
+        }
+        None
+    }
+}
+
+impl IoScheduler for DeadlineScheduler {
+    fn name(&self) -> &'static str {
+        "deadline"
+    }
+    fn add(&mut self, bio: Bio) {
+        let d = bio.dir as usize;
+        let key = (bio.sector, self.seq);
+        self.seq += 1;
+        self.fifo[d].push_back(key);
+        self.sorted[d].insert(key, bio);
+    }
+    fn dispatch(&mut self, now: usize) -> Option<Vec<Bio>> {
+        let reads = !self.sorted[IoDirection::Read as usize].is_empty();
+        let writes = !self.sorted[IoDirection::Write as usize].is_empty();
+        let dir = if reads && (!writes || self.starved < WRITES_STARVED) {
+            if writes {
+                self.starved += 1;
+            }
+            IoDirection::Read
+        } else if writes {
+            self.starved = 0;
+            IoDirection::Write
+        } else {
+            return None;
+        };
+        let d = dir as usize;
+        let start = match self.expired(dir, now) {
+            Some(key) => key,
+            None => {
+                *self.sorted[d]
+                    .range((self.next_sector[d], 0)..)
+                    .next()
+                    .or_else(|| self.sorted[d].iter().next())
+                    .unwrap()
+                    .0
+            }
+        };
+        let first = self.sorted[d].remove(&start).unwrap();
+        let mut sectors = first.sectors();
+        let mut end = first.end();
+        let mut batch = vec![first];
+        // 合并之后紧接着的 `Bio`
+        loop {
+            let next = self.sorted[d]
+                .range((end, 0)..)
+                .next()
+                .map(|(key, bio)| (*key, bio.sectors()));
+            match next {
+                Some((key, len)) if key.0 == end && sectors + len <= MAX_REQUEST_SECTORS => {
+                    let bio = self.sorted[d].remove(&key).unwrap();
+                    sectors += len;
+                    end = bio.end();
+                    batch.push(bio);
+                }
+                _ => break,
+            }
+        }
+        self.next_sector[d] = end;
+        Some(batch)
+    }
+    fn drain(&mut self) -> Vec<Bio> {
+        self.fifo = Default::default();
+        self.sorted
+            .iter_mut()
+            .flat_map(|sorted| core::mem::take(sorted).into_values())
+            .collect()
+    }
+}
+
+fn new_scheduler(name: &str) -> Option<Box<dyn IoScheduler>> {
+    match name {
+        "noop" => Some(Box::<NoopScheduler>::default()),
+        "deadline" => Some(Box::<DeadlineScheduler>::default()),
+        _ => None,
+    }
+}
+
+struct QueueInner {
+    scheduler: Box<dyn IoScheduler>,
+    /// 已经交给设备、尚未完成的请求，以设备返回的令牌为键
+    in_flight: BTreeMap<u16, Request>,
+}
+
+/// 一个块设备的请求队列
+pub struct RequestQueue {
+    device: Box<dyn LowBlockDevice>,
+    inner: Mutex<QueueInner>,
+    /// 设备的中断是否已经注册，未注册时只能轮询请求是否完成
+    irq_enabled: AtomicBool,
+}
+
+impl RequestQueue {
+    pub fn new(device: Box<dyn LowBlockDevice>) -> Self {
+        Self {
+            device,
+            inner: Mutex::new(QueueInner {
+                scheduler: Box::<DeadlineScheduler>::default(),
+                in_flight: BTreeMap::new(),
+            }),
+            irq_enabled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn device(&self) -> &dyn LowBlockDevice {
+        self.device.as_ref()
+    }
+
+    /// 设备的中断已经注册，此后等待 I/O 的任务可以睡眠
+    pub fn enable_irq(&self) {
+        self.irq_enabled.store(true, Ordering::Release);
+    }
+
+    pub fn scheduler(&self) -> &'static str {
+        self.inner.lock().scheduler.name()
+    }
+
+    /// 切换 I/O 调度器，尚未派发的请求转交给新的调度器。调度器不存在时返回 `EINVAL`
+    pub fn set_scheduler(&self, name: &str) -> AlienResult<()> {
+        let mut scheduler = new_scheduler(name).ok_or(LinuxErrno::EINVAL)?;
+        let mut inner = self.inner.lock();
+        for bio in inner.scheduler.drain() {
+            scheduler.add(bio);
+        }
+        inner.scheduler = scheduler;
+        Ok(())
+    }
+
+    /// 从扇区 `sector` 开始读满 `buf`
+    pub fn read(&self, sector: usize, buf: &mut [u8]) -> AlienResult<()> {
+        self.execute(IoDirection::Read, &[(sector, buf.as_mut_ptr(), buf.len())])
+    }
+
+    /// 同时提交多段写操作并等待全部完成，调度器会合并其中相邻的部分。返回遇到的第一个错误
+    pub fn write_batch(&self, writes: &[(usize, &[u8])]) -> AlienResult<()> {
+        let writes = writes
+            .iter()
+            .map(|(sector, buf)| (*sector, buf.as_ptr() as *mut u8, buf.len()))
+            .collect::<Vec<_>>();
+        self.execute(IoDirection::Write, &writes)
+    }
+
+    /// 等待所有已提交的请求完成后清空设备的写缓存
+    pub fn flush(&self) -> AlienResult<()> {
+        let mut inner = self.inner.lock();
+        // 持有锁时中断被关闭，只能自己轮询设备
+        while !inner.in_flight.is_empty() {
+            self.reap(&mut inner);
+            core::hint::spin_loop();
+        }
+        self.device.flush()
+    }
+
+    pub fn handle_irq(&self) {
+        self.device.handle_irq();
+        let mut inner = self.inner.lock();
+        self.reap(&mut inner);
+        self.kick(&mut inner);
+    }
+
+    fn execute(&self, dir: IoDirection, segments: &[(usize, *mut u8, usize)]) -> AlienResult<()> {
+        let now = read_timer();
+        let completions = segments
+            .iter()
+            .map(|_| Arc::new(Completion::new()))
+            .collect::<Vec<_>>();
+        {
+            let mut inner = self.inner.lock();
+            for (&(sector, buf, len), completion) in segments.iter().zip(completions.iter()) {
+                assert_eq!(len % SECTOR_SIZE, 0);
+                inner.scheduler.add(Bio {
+                    dir,
+                    sector,
+                    buf,
+                    len,
+                    submit_time: now,
+                    completion: completion.clone(),
+                });
+            }
+            self.kick(&mut inner);
+        }
+        let mut res = Ok(());
+        for completion in completions {
+            res = res.and(self.wait(&completion));
+        }
+        res
+    }
+
+    /// 在设备空闲时从调度器取出请求交给设备
+    fn kick(&self, inner: &mut QueueInner) {
+        while inner.in_flight.len() < self.device.queue_depth() {
+            let bios = match inner.scheduler.dispatch(read_timer()) {
+                Some(bios) => bios,
+                None => break,
+            };
+            let mut req = Request::new(bios);
+            let sector = req.sector;
+            let token = unsafe {
+                match req.dir {
+                    IoDirection::Read => self.device.submit_read(sector, req.buffer()),
+                    IoDirection::Write => self.device.submit_write(sector, req.buffer()),
+                }
+            };
+            match token {
+                Ok(Some(token)) => {
+                    inner.in_flight.insert(token, req);
+                }
+                Ok(None) => {
+                    let res = self.sync_io(&mut req);
+                    req.finish(res);
+                }
+                Err(e) => req.finish(Err(e)),
+            }
+        }
+    }
+
+    /// 处理设备上所有已完成的请求
+    fn reap(&self, inner: &mut QueueInner) {
+        while let Some((token, res)) = self.device.complete() {
+            if let Some(req) = inner.in_flight.remove(&token) {
+                req.finish(res);
+            }
+        }
+    }
+
+    /// 不支持异步请求的设备逐个扇区同步读写
+    fn sync_io(&self, req: &mut Request) -> AlienResult<()> {
+        let sector = req.sector;
+        let dir = req.dir;
+        for (i, block) in req.buffer().chunks_mut(SECTOR_SIZE).enumerate() {
+            match dir {
+                IoDirection::Read => self.device.read_block(sector + i, block)?,
+                IoDirection::Write => self.device.write_block(sector + i, block)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// 当前上下文能否睡眠等待中断
+    fn can_sleep(&self) -> bool {
+        self.irq_enabled.load(Ordering::Acquire)
+            && !ksync::in_atomic()
+            && shim::current_task().is_some()
+    }
+
+    /// 等待一个 `Bio` 完成。能睡眠时让出 CPU 直到中断处理程序唤醒，否则轮询设备
+    fn wait(&self, completion: &Completion) -> AlienResult<()> {
+        loop {
+            let mut state = completion.state.lock();
+            if let Some(res) = state.result.take() {
+                return res;
+            }
+            if self.can_sleep() {
+                let task = shim::take_current_task().unwrap();
+                task.to_wait();
+                state.waiter = Some(task.clone());
+                drop(state);
+                shim::schedule_now(task);
+            } else {
+                drop(state);
+                let mut inner = self.inner.lock();
+                self.reap(&mut inner);
+                self.kick(&mut inner);
+                drop(inner);
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
@@ -2,9 +2,11 @@
 extern crate alloc;
 
 pub mod block_device;
+pub mod block_queue;
 pub mod gpu;
 pub mod hal;
 pub mod input;
 pub mod net;
+pub mod p9;
 pub mod rtc;
 pub mod uart;
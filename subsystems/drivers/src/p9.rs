@@ -0,0 +1,231 @@
+//! virtio-9p 传输设备。
+//!
+//! `virtio-drivers` 没有提供 9P 设备，这里直接通过 [`Transport`] 完成设备初始化并管理一个分离式虚拟队列。
+//! 每个请求占用两个描述符：设备只读的请求与设备只写的回复，同一时刻只有一个请求在队列中。
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    hint::spin_loop,
+    mem::size_of,
+    ptr::{addr_of, NonNull},
+    sync::atomic::{fence, Ordering},
+};
+
+use config::FRAME_SIZE;
+use constants::{AlienResult, LinuxErrno};
+use device_interface::{DeviceBase, P9Device};
+use ksync::SleepMutex;
+use mem::{alloc_frames, free_frames};
+use virtio_drivers::transport::{mmio::MmioTransport, DeviceStatus, Transport};
+
+/// 设备在配置空间中提供挂载标签
+const VIRTIO_9P_MOUNT_TAG: u64 = 1 << 0;
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+/// 挂载标签的最大长度
+const MAX_TAG_LEN: usize = 64;
+
+const QUEUE_INDEX: u16 = 0;
+const QUEUE_SIZE: u16 = 16;
+
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+struct P9Config {
+    tag_len: u16,
+    tag: [u8; MAX_TAG_LEN],
+}
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+#[allow(unused)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// 分离式虚拟队列，描述符表、可用环与已用环位于同一段连续的物理页中，
+/// 已用环按页对齐，同时满足旧版设备的布局要求
+struct SplitQueue {
+    base: usize,
+    pages: usize,
+    desc: usize,
+    avail: usize,
+    used: usize,
+    avail_idx: u16,
+    last_used: u16,
+}
+
+impl SplitQueue {
+    fn new(transport: &mut MmioTransport) -> AlienResult<Self> {
+        if transport.queue_used(QUEUE_INDEX) {
+            return Err(LinuxErrno::EBUSY);
+        }
+        if transport.max_queue_size(QUEUE_INDEX) < QUEUE_SIZE as u32 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let size = QUEUE_SIZE as usize;
+        let desc_size = size_of::<Descriptor>() * size;
+        let avail_size = 2 * (3 + size);
+        let used_offset = (desc_size + avail_size).next_multiple_of(FRAME_SIZE);
+        let used_size = 2 * 3 + size_of::<UsedElem>() * size;
+        let pages = (used_offset + used_size).div_ceil(FRAME_SIZE);
+        let base = alloc_frames(pages) as usize;
+        unsafe { core::ptr::write_bytes(base as *mut u8, 0, pages * FRAME_SIZE) };
+        let queue = Self {
+            base,
+            pages,
+            desc: base,
+            avail: base + desc_size,
+            used: base + used_offset,
+            avail_idx: 0,
+            last_used: 0,
+        };
+        transport.queue_set(
+            QUEUE_INDEX,
+            QUEUE_SIZE as u32,
+            queue.desc,
+            queue.avail,
+            queue.used,
+        );
+        Ok(queue)
+    }
+
+    fn set_desc(&mut self, index: u16, buf: *const u8, len: usize, flags: u16, next: u16) {
+        let desc = (self.desc as *mut Descriptor).wrapping_add(index as usize);
+        unsafe {
+            desc.write_volatile(Descriptor {
+                addr: buf as u64,
+                len: len as u32,
+                flags,
+                next,
+            })
+        };
+    }
+
+    fn used_idx(&self) -> u16 {
+        unsafe { ((self.used + 2) as *const u16).read_volatile() }
+    }
+
+    /// 提交请求并轮询等待设备完成，返回设备写入回复缓冲区的长度
+    fn transact(&mut self, transport: &mut MmioTransport, req: &[u8], resp: &mut [u8]) -> usize {
+        self.set_desc(0, req.as_ptr(), req.len(), VIRTQ_DESC_F_NEXT, 1);
+        self.set_desc(1, resp.as_mut_ptr(), resp.len(), VIRTQ_DESC_F_WRITE, 0);
+        let slot = self.avail_idx % QUEUE_SIZE;
+        unsafe {
+            ((self.avail + 4) as *mut u16)
+                .add(slot as usize)
+                .write_volatile(0);
+        }
+        // 描述符与可用环的内容必须先于索引对设备可见
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        unsafe { ((self.avail + 2) as *mut u16).write_volatile(self.avail_idx) };
+        fence(Ordering::SeqCst);
+        transport.notify(QUEUE_INDEX);
+        while self.used_idx() == self.last_used {
+            spin_loop();
+        }
+        fence(Ordering::SeqCst);
+        let slot = self.last_used % QUEUE_SIZE;
+        let elem = unsafe {
+            ((self.used + 4) as *const UsedElem)
+                .add(slot as usize)
+                .read_volatile()
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+        elem.len as usize
+    }
+}
+
+impl Drop for SplitQueue {
+    fn drop(&mut self) {
+        free_frames(self.base as *mut u8, self.pages);
+    }
+}
+
+struct P9Inner {
+    transport: MmioTransport,
+    queue: SplitQueue,
+}
+
+pub struct VirtIO9pDriver {
+    tag: String,
+    /// 等待设备完成请求期间持有
+    inner: SleepMutex<P9Inner>,
+}
+
+unsafe impl Send for VirtIO9pDriver {}
+
+unsafe impl Sync for VirtIO9pDriver {}
+
+impl VirtIO9pDriver {
+    pub fn from_mmio(mut transport: MmioTransport) -> AlienResult<Self> {
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let features = transport.read_device_features();
+        transport.write_driver_features(features & (VIRTIO_9P_MOUNT_TAG | VIRTIO_F_VERSION_1));
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        transport.set_guest_page_size(FRAME_SIZE as u32);
+        if features & VIRTIO_9P_MOUNT_TAG == 0 {
+            transport.set_status(DeviceStatus::FAILED);
+            return Err(LinuxErrno::EINVAL);
+        }
+        let tag = Self::read_tag(&transport)?;
+        let queue = match SplitQueue::new(&mut transport) {
+            Ok(queue) => queue,
+            Err(e) => {
+                transport.set_status(DeviceStatus::FAILED);
+                return Err(e);
+            }
+        };
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE
+                | DeviceStatus::DRIVER
+                | DeviceStatus::FEATURES_OK
+                | DeviceStatus::DRIVER_OK,
+        );
+        Ok(Self {
+            tag,
+            inner: SleepMutex::new(P9Inner { transport, queue }),
+        })
+    }
+
+    fn read_tag(transport: &MmioTransport) -> AlienResult<String> {
+        let config: NonNull<P9Config> = transport.config_space().map_err(|_| LinuxErrno::EINVAL)?;
+        let config = config.as_ptr();
+        let len = unsafe { addr_of!((*config).tag_len).read_volatile() } as usize;
+        let tag = (0..len.min(MAX_TAG_LEN))
+            .map(|i| unsafe { addr_of!((*config).tag[i]).read_volatile() })
+            .collect::<Vec<_>>();
+        Ok(String::from_utf8_lossy(&tag).to_string())
+    }
+}
+
+impl DeviceBase for VirtIO9pDriver {
+    fn handle_irq(&self) {
+        self.inner.lock().transport.ack_interrupt();
+    }
+}
+
+impl P9Device for VirtIO9pDriver {
+    fn mount_tag(&self) -> &str {
+        &self.tag
+    }
+    fn transact(&self, req: &[u8], resp: &mut [u8]) -> AlienResult<usize> {
+        let mut inner = self.inner.lock();
+        let P9Inner { transport, queue } = &mut *inner;
+        Ok(queue.transact(transport, req, resp))
+    }
+}
@@ -1,10 +1,14 @@
 #![no_std]
 
-use core::cell::{RefCell, RefMut};
+use core::{
+    cell::{RefCell, RefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use arch::{hart_id, interrupt_disable, interrupt_enable, is_interrupt_enable};
 use config::CPU_NUM;
 use kernel_sync::{ticket::TicketMutexGuard, LockAction};
+use lock_api::{GuardSend, RawMutex};
 
 pub type SpinMutex<T> = kernel_sync::spin::SpinMutex<T, KernelLockAction>;
 pub type TicketMutex<T> = kernel_sync::ticket::TicketMutex<T, KernelLockAction>;
@@ -84,3 +88,51 @@ impl LockAction for KernelLockAction {
         pop_off();
     }
 }
+
+/// 当前 CPU 是否持有会关闭中断的锁。此时不能睡眠或让出 CPU
+pub fn in_atomic() -> bool {
+    mycpu().noff > 0
+}
+
+/// 竞争时让出 CPU 的互斥锁。
+///
+/// 持有者可以在临界区内睡眠(例如等待磁盘 I/O 完成)，其它任务竞争该锁时会让出 CPU 而不是一直自旋，
+/// 使得持有者能够被调度回来释放锁。在不能让出 CPU 的上下文中竞争时退化为自旋。
+pub struct RawSleepMutex {
+    locked: AtomicBool,
+}
+
+unsafe impl RawMutex for RawSleepMutex {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const INIT: Self = Self {
+        locked: AtomicBool::new(false),
+    };
+    type GuardMarker = GuardSend;
+
+    fn lock(&self) {
+        while !self.try_lock() {
+            if !in_atomic() && shim::current_task().is_some() {
+                shim::suspend();
+            } else {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    unsafe fn unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+
+    fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+}
+
+pub type SleepMutex<T> = lock_api::Mutex<RawSleepMutex, T>;
+pub type SleepMutexGuard<'a, T> = lock_api::MutexGuard<'a, RawSleepMutex, T>;
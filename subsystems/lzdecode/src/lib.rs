@@ -0,0 +1,61 @@
+//! initramfs 使用的 xz 与 lz4 解码器。
+//!
+//! 这两种格式没有可以在内核中使用的 no_std 解压库，因此解码器实现在这里。单独作为一个
+//! 不依赖内核的 crate，使得可以在主机上用 `cargo test` 对照 `xz`/`lz4` 工具生成的数据测试。
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod lz4;
+pub mod xz;
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, vec::Vec};
+
+    /// 与 `testdata` 中的压缩数据对应的原始内容
+    pub fn text(lines: usize) -> Vec<u8> {
+        (0..lines)
+            .flat_map(|i| {
+                format!(
+                    "{:05} the quick brown fox jumps over the lazy dog {}\n",
+                    i,
+                    i * 7919 % 1000
+                )
+                .into_bytes()
+            })
+            .collect()
+    }
+
+    /// xorshift64 生成的不可压缩数据
+    pub fn random(len: usize, seed: u64) -> Vec<u8> {
+        let mut x = seed;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                (x >> 32) as u8
+            })
+            .collect()
+    }
+
+    pub fn mixed() -> Vec<u8> {
+        let mut data = text(500);
+        data.extend(random(2048, 1));
+        data.extend(text(500));
+        data
+    }
+
+    /// 对每个字节分别做几种修改，解码可以失败或者输出错误的内容，但不能 panic
+    pub fn corrupt_each_byte<T>(data: &[u8], decode: impl Fn(&[u8]) -> T) {
+        let mut corrupted = data.to_vec();
+        for i in 0..data.len() {
+            for mask in [0x01, 0x10, 0x80, 0xff] {
+                corrupted[i] ^= mask;
+                decode(&corrupted);
+                corrupted[i] = data[i];
+            }
+        }
+    }
+}
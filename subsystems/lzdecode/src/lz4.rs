@@ -0,0 +1,273 @@
+//! lz4 格式的解压，支持 `lz4 -l` 生成的旧格式与帧格式。块与内容的校验和不做检查。
+//!
+//! 帧格式见 <https://github.com/lz4/lz4/blob/dev/doc/lz4_Frame_format.md>
+use alloc::{vec, vec::Vec};
+
+/// `lz4 -l` 生成的旧格式，Linux 内核构建时使用这种格式
+pub const LEGACY_MAGIC: u32 = 0x184c_2102;
+pub const FRAME_MAGIC: u32 = 0x184d_2204;
+/// lz4 可跳过帧的魔数为 0x184d2a50..=0x184d2a5f
+const SKIPPABLE_MAGIC: u32 = 0x184d_2a50;
+/// 旧格式中每个块解压后的大小
+const LEGACY_BLOCK_SIZE: usize = 8 << 20;
+
+type Lz4Result<T> = Result<T, &'static str>;
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// 旧格式由魔数与若干个块组成，每个块前有 4 字节的压缩后大小。
+/// 大小为 0 或者超出剩余数据时认为压缩数据结束，遇到魔数时开始新的压缩数据
+pub fn unlz4_legacy(data: &[u8]) -> Lz4Result<(Vec<u8>, usize)> {
+    let mut out = vec![];
+    let mut offset = 4;
+    while let Some(size) = read_u32(data, offset) {
+        if size == LEGACY_MAGIC {
+            offset += 4;
+            continue;
+        }
+        let size = size as usize;
+        if size == 0 || size > data.len() - offset - 4 {
+            break;
+        }
+        offset += 4;
+        let start = out.len();
+        lz4_block(&data[offset..offset + size], &mut out, start)?;
+        if out.len() - start > LEGACY_BLOCK_SIZE {
+            return Err("lz4 block too large");
+        }
+        offset += size;
+    }
+    Ok((out, offset))
+}
+
+/// lz4 帧格式，见 <https://github.com/lz4/lz4/blob/dev/doc/lz4_Frame_format.md>
+pub fn unlz4_frame(data: &[u8]) -> Lz4Result<(Vec<u8>, usize)> {
+    let mut out = vec![];
+    let mut offset = 0;
+    loop {
+        let Some(magic) = read_u32(data, offset) else {
+            break;
+        };
+        if magic & 0xffff_fff0 == SKIPPABLE_MAGIC {
+            let size = read_u32(data, offset + 4).ok_or("truncated lz4 data")? as usize;
+            offset += 8 + size;
+            continue;
+        }
+        if magic != FRAME_MAGIC {
+            break;
+        }
+        offset = lz4_frame(data, offset + 4, &mut out)?;
+    }
+    Ok((out, offset.min(data.len())))
+}
+
+/// 解码一个 lz4 帧，`offset` 指向魔数之后的帧描述符，返回帧结束的位置
+fn lz4_frame(data: &[u8], mut offset: usize, out: &mut Vec<u8>) -> Lz4Result<usize> {
+    let flags = *data.get(offset).ok_or("truncated lz4 data")?;
+    if flags >> 6 != 1 {
+        return Err("unsupported lz4 frame version");
+    }
+    let block_checksum = flags & 0x10 != 0;
+    let content_size = flags & 0x08 != 0;
+    let content_checksum = flags & 0x04 != 0;
+    let dict_id = flags & 0x01 != 0;
+    // 帧描述符: FLG、BD、可选的内容大小与字典 ID、头部校验和
+    offset += 2 + if content_size { 8 } else { 0 } + if dict_id { 4 } else { 0 } + 1;
+    // 块之间可能存在依赖，所有块解压到同一个缓冲区中，前面的数据都可以作为后面的块的字典
+    let frame_start = out.len();
+    loop {
+        let size = read_u32(data, offset).ok_or("truncated lz4 data")?;
+        offset += 4;
+        if size == 0 {
+            break;
+        }
+        let uncompressed = size & 0x8000_0000 != 0;
+        let size = (size & 0x7fff_ffff) as usize;
+        let block = data
+            .get(offset..offset + size)
+            .ok_or("truncated lz4 data")?;
+        if uncompressed {
+            out.extend_from_slice(block);
+        } else {
+            lz4_block(block, out, frame_start)?;
+        }
+        offset += size + if block_checksum { 4 } else { 0 };
+    }
+    if content_checksum {
+        offset += 4;
+    }
+    Ok(offset)
+}
+
+fn lz4_length(block: &[u8], pos: &mut usize, mut len: usize) -> Lz4Result<usize> {
+    if len == 15 {
+        loop {
+            let b = *block.get(*pos).ok_or("corrupted lz4 data")?;
+            *pos += 1;
+            len += b as usize;
+            if b != 255 {
+                break;
+            }
+        }
+    }
+    Ok(len)
+}
+
+/// 解码一个 lz4 块并追加到 `out`，匹配只能引用 `window_start` 之后的数据
+fn lz4_block(block: &[u8], out: &mut Vec<u8>, window_start: usize) -> Lz4Result<()> {
+    let mut pos = 0;
+    while pos < block.len() {
+        let token = block[pos];
+        pos += 1;
+        let literals = lz4_length(block, &mut pos, (token >> 4) as usize)?;
+        let literal = block.get(pos..pos + literals).ok_or("corrupted lz4 data")?;
+        out.extend_from_slice(literal);
+        pos += literals;
+        // 块中的最后一个序列只有字面量
+        if pos == block.len() {
+            break;
+        }
+        let distance = block
+            .get(pos..pos + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or("corrupted lz4 data")?;
+        pos += 2;
+        if distance == 0 || distance > out.len() - window_start {
+            return Err("corrupted lz4 data");
+        }
+        let len = lz4_length(block, &mut pos, (token & 0xf) as usize)? + 4;
+        // 匹配可以与正在输出的数据重叠，需要逐字节复制
+        let from = out.len() - distance;
+        for i in 0..len {
+            out.push(out[from + i]);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{corrupt_each_byte, mixed, random, text};
+
+    type Decode = fn(&[u8]) -> Lz4Result<(Vec<u8>, usize)>;
+
+    fn check(decode: Decode, data: &[u8], expected: &[u8]) {
+        let (out, len) = decode(data).unwrap();
+        assert_eq!(out, expected);
+        assert_eq!(len, data.len());
+    }
+
+    #[test]
+    fn test_reference_archives() {
+        let random = random(4096, 0x9e37_79b9_7f4a_7c15);
+        check(
+            unlz4_legacy,
+            include_bytes!("../testdata/text-legacy.lz4"),
+            &text(2000),
+        );
+        check(
+            unlz4_legacy,
+            include_bytes!("../testdata/random-legacy.lz4"),
+            &random,
+        );
+        check(
+            unlz4_frame,
+            include_bytes!("../testdata/text.lz4"),
+            &text(2000),
+        );
+        check(
+            unlz4_frame,
+            include_bytes!("../testdata/text-linked.lz4"),
+            &text(2000),
+        );
+        check(
+            unlz4_frame,
+            include_bytes!("../testdata/mixed.lz4"),
+            &mixed(),
+        );
+        check(
+            unlz4_frame,
+            include_bytes!("../testdata/random.lz4"),
+            &random,
+        );
+        check(unlz4_frame, include_bytes!("../testdata/empty.lz4"), &[]);
+    }
+
+    #[test]
+    fn test_concatenated_frames() {
+        let mut data = include_bytes!("../testdata/small.lz4").to_vec();
+        // 可跳过帧与多个帧之间可以直接拼接
+        data.extend(0x184d_2a5fu32.to_le_bytes());
+        data.extend(3u32.to_le_bytes());
+        data.extend([1, 2, 3]);
+        data.extend(include_bytes!("../testdata/text.lz4"));
+        let len = data.len();
+        // 之后的其他数据不属于 lz4 帧
+        data.extend([0; 8]);
+        let (out, consumed) = unlz4_frame(&data).unwrap();
+        assert_eq!(out, [text(100), text(2000)].concat());
+        assert_eq!(consumed, len);
+    }
+
+    #[test]
+    fn test_legacy_end() {
+        let mut data = include_bytes!("../testdata/text-legacy.lz4").to_vec();
+        let len = data.len();
+        // 旧格式以大小为 0 或者超出剩余数据的块结束
+        data.extend([0; 4]);
+        let (out, consumed) = unlz4_legacy(&data).unwrap();
+        assert_eq!(out, text(2000));
+        assert_eq!(consumed, len);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let data = include_bytes!("../testdata/small.lz4");
+        // 帧结束标志之前的截断都会失败
+        for len in 4..data.len() - 4 {
+            assert!(unlz4_frame(&data[..len]).is_err(), "truncated at {}", len);
+        }
+        let data = include_bytes!("../testdata/random-legacy.lz4");
+        for len in 0..data.len() {
+            assert!(unlz4_legacy(&data[..len]).unwrap().0.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_corrupted() {
+        corrupt_each_byte(include_bytes!("../testdata/small.lz4"), unlz4_frame);
+        corrupt_each_byte(
+            include_bytes!("../testdata/text-legacy.lz4")[..1024].as_ref(),
+            unlz4_legacy,
+        );
+    }
+
+    #[test]
+    fn test_invalid_block() {
+        let mut out = vec![];
+        // 距离为 0
+        assert!(lz4_block(&[0x10, b'a', 0, 0, 0x00], &mut out, 0).is_err());
+        // 距离超出已经输出的数据
+        out.clear();
+        assert!(lz4_block(&[0x10, b'a', 2, 0, 0x00], &mut out, 0).is_err());
+        // 距离超出当前窗口
+        out = b"abcd".to_vec();
+        assert!(lz4_block(&[0x10, b'a', 2, 0, 0x00], &mut out, 4).is_err());
+        // 字面量长度超出块
+        out.clear();
+        assert!(lz4_block(&[0xf0, 255, 255], &mut out, 0).is_err());
+        assert!(lz4_block(&[0x30, b'a'], &mut out, 0).is_err());
+        // 匹配的长度与距离不完整
+        out.clear();
+        assert!(lz4_block(&[0x1f, b'a', 1, 0, 255], &mut out, 0).is_err());
+        assert!(lz4_block(&[0x10, b'a', 1], &mut out, 0).is_err());
+        // 重叠的匹配
+        out.clear();
+        lz4_block(&[0x12, b'a', 1, 0, 0x10, b'b'], &mut out, 0).unwrap();
+        assert_eq!(out, b"aaaaaaab");
+    }
+}
@@ -0,0 +1,680 @@
+//! xz 格式的解压，只支持只包含 LZMA2 过滤器的块(`xz --check=crc32 --lzma2`)，这也是 Linux
+//! 对 initramfs 的要求。解码器的结构参考 xz-embedded，为了简化实现，解压结果全部保存在内存中，
+//! 直接作为 LZMA 的字典使用。头部、块与索引中的校验和不做检查。
+//!
+//! 格式见 <https://tukaani.org/xz/xz-file-format.txt>
+use alloc::{vec, vec::Vec};
+
+pub const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const FOOTER_MAGIC: [u8; 2] = [b'Y', b'Z'];
+const STREAM_HEADER_SIZE: usize = 12;
+const FILTER_LZMA2: u64 = 0x21;
+
+type XzResult<T> = Result<T, &'static str>;
+
+/// 解压 `data` 开头的一个 xz 流，返回解压后的内容与流占用的字节数。流之后的填充由调用者跳过
+pub fn unxz(data: &[u8]) -> XzResult<(Vec<u8>, usize)> {
+    let header = data.get(..STREAM_HEADER_SIZE).ok_or("truncated xz data")?;
+    if header[6] != 0 || header[7] > 0x0f {
+        return Err("unsupported xz stream flags");
+    }
+    let check_size = match header[7] {
+        0 => 0,
+        id => 4 << ((id - 1) / 3),
+    };
+    let mut input = Input {
+        data,
+        pos: STREAM_HEADER_SIZE,
+    };
+    let mut out = vec![];
+    loop {
+        let block_start = input.pos;
+        let size_byte = input.byte()?;
+        if size_byte == 0 {
+            // 索引标志，块已经全部解压
+            break;
+        }
+        let header_size = (size_byte as usize + 1) * 4;
+        let header = input.bytes(header_size - 1)?;
+        let lzma2 = parse_block_header(header)?;
+        let mut decoder = Lzma2Decoder::new(lzma2);
+        decoder.decode(&mut input, &mut out)?;
+        // 块的压缩数据之后填充到 4 字节对齐，之后是校验和
+        input.align4(block_start)?;
+        input.bytes(check_size)?;
+    }
+    // 索引: 记录数、每个块的两个大小、填充与 CRC32
+    let index_start = input.pos - 1;
+    let records = input.varint()?;
+    for _ in 0..records {
+        input.varint()?;
+        input.varint()?;
+    }
+    input.align4(index_start)?;
+    input.bytes(4)?;
+    let footer = input.bytes(STREAM_HEADER_SIZE)?;
+    if footer[10..] != FOOTER_MAGIC || footer[8..10] != header[6..8] {
+        return Err("corrupted xz stream footer");
+    }
+    Ok((out, input.pos))
+}
+
+/// 解析块头部中的过滤器，返回 LZMA2 的字典大小属性。`header` 不包含表示头部大小的第一个字节
+fn parse_block_header(header: &[u8]) -> XzResult<u8> {
+    let mut input = Input {
+        data: &header[..header.len() - 4],
+        pos: 0,
+    };
+    let flags = input.byte()?;
+    if flags & 0x3c != 0 {
+        return Err("unsupported xz block flags");
+    }
+    if flags & 0x03 != 0 {
+        return Err("unsupported xz filter chain");
+    }
+    if flags & 0x40 != 0 {
+        input.varint()?;
+    }
+    if flags & 0x80 != 0 {
+        input.varint()?;
+    }
+    if input.varint()? != FILTER_LZMA2 || input.varint()? != 1 {
+        return Err("unsupported xz filter");
+    }
+    let dict_size = input.byte()?;
+    if dict_size > 40 {
+        return Err("invalid lzma2 dictionary size");
+    }
+    Ok(dict_size)
+}
+
+struct Input<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn byte(&mut self) -> XzResult<u8> {
+        let b = *self.data.get(self.pos).ok_or("truncated xz data")?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn bytes(&mut self, len: usize) -> XzResult<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or("truncated xz data")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u16_be(&mut self) -> XzResult<usize> {
+        Ok(((self.byte()? as usize) << 8) | self.byte()? as usize)
+    }
+
+    /// xz 的变长整数，每个字节保存 7 位，最多 9 个字节
+    fn varint(&mut self) -> XzResult<u64> {
+        let mut value = 0u64;
+        for i in 0..9 {
+            let b = self.byte()?;
+            value |= ((b & 0x7f) as u64) << (i * 7);
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("invalid xz integer")
+    }
+
+    /// 跳过填充，使从 `start` 开始的数据长度为 4 的倍数。填充必须为 0
+    fn align4(&mut self, start: usize) -> XzResult<()> {
+        while (self.pos - start) % 4 != 0 {
+            if self.byte()? != 0 {
+                return Err("corrupted xz padding");
+            }
+        }
+        Ok(())
+    }
+}
+
+const RC_TOP: u32 = 1 << 24;
+const RC_BIT_MODEL_TOTAL_BITS: u32 = 11;
+const RC_BIT_MODEL_TOTAL: u16 = 1 << RC_BIT_MODEL_TOTAL_BITS;
+const RC_MOVE_BITS: u32 = 5;
+const RC_INIT_BYTES: usize = 5;
+
+/// LZMA 的区间解码器，每个 LZMA2 块使用一个新的区间解码器
+struct RangeDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    fn new(data: &'a [u8]) -> XzResult<Self> {
+        if data.len() < RC_INIT_BYTES || data[0] != 0 {
+            return Err("corrupted lzma2 data");
+        }
+        let code = u32::from_be_bytes(data[1..5].try_into().unwrap());
+        Ok(Self {
+            data,
+            pos: RC_INIT_BYTES,
+            range: u32::MAX,
+            code,
+        })
+    }
+
+    fn normalize(&mut self) -> XzResult<()> {
+        if self.range < RC_TOP {
+            let b = *self.data.get(self.pos).ok_or("corrupted lzma2 data")?;
+            self.pos += 1;
+            self.range <<= 8;
+            self.code = (self.code << 8) | b as u32;
+        }
+        Ok(())
+    }
+
+    fn bit(&mut self, prob: &mut u16) -> XzResult<u32> {
+        self.normalize()?;
+        let bound = (self.range >> RC_BIT_MODEL_TOTAL_BITS) * *prob as u32;
+        if self.code < bound {
+            self.range = bound;
+            *prob += (RC_BIT_MODEL_TOTAL - *prob) >> RC_MOVE_BITS;
+            Ok(0)
+        } else {
+            self.range -= bound;
+            self.code -= bound;
+            *prob -= *prob >> RC_MOVE_BITS;
+            Ok(1)
+        }
+    }
+
+    /// 按从高位到低位的顺序解码 `bits` 位
+    fn bittree(&mut self, probs: &mut [u16], bits: u32) -> XzResult<u32> {
+        let mut symbol = 1;
+        for _ in 0..bits {
+            symbol = (symbol << 1) | self.bit(&mut probs[symbol as usize])?;
+        }
+        Ok(symbol - (1 << bits))
+    }
+
+    /// 按从低位到高位的顺序解码 `bits` 位
+    fn bittree_reverse(&mut self, probs: &mut [u16], bits: u32) -> XzResult<u32> {
+        let mut symbol = 1;
+        let mut value = 0;
+        for i in 0..bits {
+            let bit = self.bit(&mut probs[symbol as usize])?;
+            symbol = (symbol << 1) | bit;
+            value |= bit << i;
+        }
+        Ok(value)
+    }
+
+    /// 解码概率固定为 1/2 的 `bits` 位
+    fn direct(&mut self, bits: u32) -> XzResult<u32> {
+        let mut value = 0u32;
+        for _ in 0..bits {
+            self.normalize()?;
+            self.range >>= 1;
+            self.code = self.code.wrapping_sub(self.range);
+            let mask = 0u32.wrapping_sub(self.code >> 31);
+            self.code = self.code.wrapping_add(self.range & mask);
+            value = (value << 1).wrapping_add(mask.wrapping_add(1));
+        }
+        Ok(value)
+    }
+
+    /// 最后一个符号解码后还需要读入可能剩余的一个字节
+    fn is_finished(&mut self) -> XzResult<bool> {
+        self.normalize()?;
+        Ok(self.pos == self.data.len() && self.code == 0)
+    }
+}
+
+const STATES: usize = 12;
+const LIT_STATES: usize = 7;
+const POS_STATES_MAX: usize = 1 << 4;
+const MATCH_LEN_MIN: usize = 2;
+const LEN_LOW_BITS: u32 = 3;
+const LEN_MID_BITS: u32 = 3;
+const LEN_HIGH_BITS: u32 = 8;
+const DIST_STATES: usize = 4;
+const DIST_SLOT_BITS: u32 = 6;
+const DIST_MODEL_START: u32 = 4;
+const DIST_MODEL_END: u32 = 14;
+const FULL_DISTANCES: usize = 1 << (DIST_MODEL_END / 2);
+const ALIGN_BITS: u32 = 4;
+const LITERAL_CODER_SIZE: usize = 0x300;
+const PROB_INIT: u16 = RC_BIT_MODEL_TOTAL / 2;
+
+struct LengthDecoder {
+    choice: u16,
+    choice2: u16,
+    low: [[u16; 1 << LEN_LOW_BITS]; POS_STATES_MAX],
+    mid: [[u16; 1 << LEN_MID_BITS]; POS_STATES_MAX],
+    high: [u16; 1 << LEN_HIGH_BITS],
+}
+
+impl LengthDecoder {
+    fn new() -> Self {
+        Self {
+            choice: PROB_INIT,
+            choice2: PROB_INIT,
+            low: [[PROB_INIT; 1 << LEN_LOW_BITS]; POS_STATES_MAX],
+            mid: [[PROB_INIT; 1 << LEN_MID_BITS]; POS_STATES_MAX],
+            high: [PROB_INIT; 1 << LEN_HIGH_BITS],
+        }
+    }
+
+    fn decode(&mut self, rc: &mut RangeDecoder, pos_state: usize) -> XzResult<usize> {
+        let len = if rc.bit(&mut self.choice)? == 0 {
+            rc.bittree(&mut self.low[pos_state], LEN_LOW_BITS)? as usize
+        } else if rc.bit(&mut self.choice2)? == 0 {
+            (1 << LEN_LOW_BITS) + rc.bittree(&mut self.mid[pos_state], LEN_MID_BITS)? as usize
+        } else {
+            (1 << LEN_LOW_BITS)
+                + (1 << LEN_MID_BITS)
+                + rc.bittree(&mut self.high, LEN_HIGH_BITS)? as usize
+        };
+        Ok(len + MATCH_LEN_MIN)
+    }
+}
+
+/// LZMA 解码器的状态，在 LZMA2 块之间保留，直到块要求重置状态
+struct LzmaState {
+    lc: u32,
+    lp: u32,
+    pb: u32,
+    state: usize,
+    reps: [usize; 4],
+    is_match: [[u16; POS_STATES_MAX]; STATES],
+    is_rep: [u16; STATES],
+    is_rep0: [u16; STATES],
+    is_rep1: [u16; STATES],
+    is_rep2: [u16; STATES],
+    is_rep0_long: [[u16; POS_STATES_MAX]; STATES],
+    dist_slot: [[u16; 1 << DIST_SLOT_BITS]; DIST_STATES],
+    /// 第一个元素不使用，使 `dist - slot` 可以直接作为树的起始下标
+    dist_special: [u16; FULL_DISTANCES - DIST_MODEL_END as usize + 1],
+    dist_align: [u16; 1 << ALIGN_BITS],
+    match_len: LengthDecoder,
+    rep_len: LengthDecoder,
+    literal: Vec<u16>,
+}
+
+impl LzmaState {
+    fn new() -> Self {
+        Self {
+            lc: 0,
+            lp: 0,
+            pb: 0,
+            state: 0,
+            reps: [0; 4],
+            is_match: [[PROB_INIT; POS_STATES_MAX]; STATES],
+            is_rep: [PROB_INIT; STATES],
+            is_rep0: [PROB_INIT; STATES],
+            is_rep1: [PROB_INIT; STATES],
+            is_rep2: [PROB_INIT; STATES],
+            is_rep0_long: [[PROB_INIT; POS_STATES_MAX]; STATES],
+            dist_slot: [[PROB_INIT; 1 << DIST_SLOT_BITS]; DIST_STATES],
+            dist_special: [PROB_INIT; FULL_DISTANCES - DIST_MODEL_END as usize + 1],
+            dist_align: [PROB_INIT; 1 << ALIGN_BITS],
+            match_len: LengthDecoder::new(),
+            rep_len: LengthDecoder::new(),
+            literal: Vec::new(),
+        }
+    }
+
+    /// 按 `lc`/`lp`/`pb` 属性重置所有概率与状态
+    fn reset(&mut self, props: u8) -> XzResult<()> {
+        let props = props as u32;
+        if props >= 9 * 5 * 5 {
+            return Err("invalid lzma2 properties");
+        }
+        let (lc, lp, pb) = (props % 9, props / 9 % 5, props / 45);
+        if lc + lp > 4 {
+            return Err("invalid lzma2 properties");
+        }
+        *self = Self {
+            lc,
+            lp,
+            pb,
+            literal: vec![PROB_INIT; LITERAL_CODER_SIZE << (lc + lp)],
+            ..Self::new()
+        };
+        Ok(())
+    }
+
+    /// 只重置概率与状态，保留 `lc`/`lp`/`pb` 属性
+    fn reset_state(&mut self) {
+        let props = (self.pb * 5 + self.lp) * 9 + self.lc;
+        self.reset(props as u8).unwrap();
+    }
+
+    /// 解码一个字面量，位置从字典重置时的输出位置 `dict_start` 开始计算
+    fn literal(
+        &mut self,
+        rc: &mut RangeDecoder,
+        out: &mut Vec<u8>,
+        dict_start: usize,
+    ) -> XzResult<()> {
+        let pos = out.len() - dict_start;
+        let prev = if pos == 0 {
+            0
+        } else {
+            out[out.len() - 1] as usize
+        };
+        let lp_mask = (1 << self.lp) - 1;
+        let index = (((pos & lp_mask) << self.lc) + (prev >> (8 - self.lc))) * LITERAL_CODER_SIZE;
+        let probs = &mut self.literal[index..index + LITERAL_CODER_SIZE];
+        let mut symbol = 1u32;
+        if self.state < LIT_STATES {
+            while symbol < 0x100 {
+                symbol = (symbol << 1) | rc.bit(&mut probs[symbol as usize])?;
+            }
+        } else {
+            // 上一个操作是匹配，使用匹配位置之后的字节辅助解码
+            let mut match_byte = out[out.len() - self.reps[0] - 1] as u32;
+            let mut offset = 0x100;
+            while symbol < 0x100 {
+                match_byte <<= 1;
+                let match_bit = match_byte & offset;
+                let i = offset + match_bit + symbol;
+                if rc.bit(&mut probs[i as usize])? == 1 {
+                    symbol = (symbol << 1) | 1;
+                    offset = match_bit;
+                } else {
+                    symbol <<= 1;
+                    offset ^= match_bit;
+                }
+            }
+        }
+        out.push(symbol as u8);
+        self.state = match self.state {
+            0..=3 => 0,
+            4..=9 => self.state - 3,
+            _ => self.state - 6,
+        };
+        Ok(())
+    }
+
+    /// 使用最近的四个距离之一的匹配，返回匹配长度
+    fn rep_match(&mut self, rc: &mut RangeDecoder, pos_state: usize) -> XzResult<usize> {
+        let state = self.state;
+        if rc.bit(&mut self.is_rep0[state])? == 0 {
+            if rc.bit(&mut self.is_rep0_long[state][pos_state])? == 0 {
+                // 重复上一个距离的单个字节
+                self.state = if state < LIT_STATES { 9 } else { 11 };
+                return Ok(1);
+            }
+        } else {
+            let index = if rc.bit(&mut self.is_rep1[state])? == 0 {
+                1
+            } else if rc.bit(&mut self.is_rep2[state])? == 0 {
+                2
+            } else {
+                3
+            };
+            // 选中的距离移到最前面
+            let dist = self.reps[index];
+            self.reps.copy_within(0..index, 1);
+            self.reps[0] = dist;
+        }
+        self.state = if state < LIT_STATES { 8 } else { 11 };
+        self.rep_len.decode(rc, pos_state)
+    }
+
+    fn distance(&mut self, rc: &mut RangeDecoder, len: usize) -> XzResult<usize> {
+        let dist_state = (len - MATCH_LEN_MIN).min(DIST_STATES - 1);
+        let slot = rc.bittree(&mut self.dist_slot[dist_state], DIST_SLOT_BITS)?;
+        if slot < DIST_MODEL_START {
+            return Ok(slot as usize);
+        }
+        let limit = (slot >> 1) - 1;
+        let mut dist = (2 | (slot & 1)) << limit;
+        if slot < DIST_MODEL_END {
+            let base = (dist - slot) as usize;
+            dist += rc.bittree_reverse(&mut self.dist_special[base..], limit)?;
+        } else {
+            dist = dist.wrapping_add(rc.direct(limit - ALIGN_BITS)? << ALIGN_BITS);
+            dist = dist.wrapping_add(rc.bittree_reverse(&mut self.dist_align, ALIGN_BITS)?);
+        }
+        Ok(dist as usize)
+    }
+}
+
+struct Lzma2Decoder {
+    dict_size: usize,
+    lzma: LzmaState,
+    /// 字典重置时的输出位置，匹配不能引用它之前的数据
+    dict_start: usize,
+    need_dict_reset: bool,
+    need_props: bool,
+}
+
+impl Lzma2Decoder {
+    fn new(dict_size_prop: u8) -> Self {
+        let dict_size = if dict_size_prop == 40 {
+            u32::MAX as usize
+        } else {
+            (2 | (dict_size_prop as usize & 1)) << (dict_size_prop / 2 + 11)
+        };
+        Self {
+            dict_size,
+            lzma: LzmaState::new(),
+            dict_start: 0,
+            need_dict_reset: true,
+            need_props: true,
+        }
+    }
+
+    /// 解码 LZMA2 数据直到结束标志，输出追加到 `out`
+    fn decode(&mut self, input: &mut Input, out: &mut Vec<u8>) -> XzResult<()> {
+        loop {
+            let control = input.byte()?;
+            if control == 0x00 {
+                return Ok(());
+            }
+            // 第一个块必须重置字典
+            let dict_reset = control == 0x01 || control >= 0xe0;
+            if dict_reset {
+                // 字典重置之后的第一个 LZMA 块必须重新设置属性
+                self.dict_start = out.len();
+                self.need_dict_reset = false;
+                self.need_props = true;
+            } else if self.need_dict_reset {
+                return Err("corrupted lzma2 data");
+            }
+            match control {
+                0x01 | 0x02 => {
+                    // 不压缩的块
+                    let size = input.u16_be()? + 1;
+                    out.extend_from_slice(input.bytes(size)?);
+                }
+                0x80..=0xff => {
+                    let unpacked = (((control as usize) & 0x1f) << 16) + input.u16_be()? + 1;
+                    let packed = input.u16_be()? + 1;
+                    if control >= 0xc0 {
+                        self.lzma.reset(input.byte()?)?;
+                        self.need_props = false;
+                    } else if self.need_props {
+                        return Err("corrupted lzma2 data");
+                    } else if control >= 0xa0 {
+                        self.lzma.reset_state();
+                    }
+                    let mut rc = RangeDecoder::new(input.bytes(packed)?)?;
+                    self.decode_chunk(&mut rc, out, out.len() + unpacked)?;
+                    if !rc.is_finished()? {
+                        return Err("corrupted lzma2 data");
+                    }
+                }
+                _ => return Err("corrupted lzma2 data"),
+            }
+        }
+    }
+
+    fn decode_chunk(
+        &mut self,
+        rc: &mut RangeDecoder,
+        out: &mut Vec<u8>,
+        end: usize,
+    ) -> XzResult<()> {
+        let lzma = &mut self.lzma;
+        let pb_mask = (1 << lzma.pb) - 1;
+        while out.len() < end {
+            let pos_state = (out.len() - self.dict_start) & pb_mask;
+            let state = lzma.state;
+            if rc.bit(&mut lzma.is_match[state][pos_state])? == 0 {
+                lzma.literal(rc, out, self.dict_start)?;
+                continue;
+            }
+            let len = if rc.bit(&mut lzma.is_rep[state])? == 0 {
+                // 新的匹配
+                lzma.state = if state < LIT_STATES { 7 } else { 10 };
+                let len = lzma.match_len.decode(rc, pos_state)?;
+                let dist = lzma.distance(rc, len)?;
+                if dist == u32::MAX as usize {
+                    return Err("unexpected lzma end marker");
+                }
+                lzma.reps = [dist, lzma.reps[0], lzma.reps[1], lzma.reps[2]];
+                len
+            } else {
+                lzma.rep_match(rc, pos_state)?
+            };
+            if lzma.reps[0] >= out.len() - self.dict_start || lzma.reps[0] >= self.dict_size {
+                return Err("corrupted lzma2 data");
+            }
+            // 匹配不能跨越 LZMA 块
+            if len > end - out.len() {
+                return Err("corrupted lzma2 data");
+            }
+            copy_match(out, lzma.reps[0], len);
+        }
+        Ok(())
+    }
+}
+
+/// 复制距离为 `dist + 1` 的 `len` 个字节，源与目标可以重叠
+fn copy_match(out: &mut Vec<u8>, dist: usize, len: usize) {
+    let from = out.len() - dist - 1;
+    for i in 0..len {
+        out.push(out[from + i]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::*;
+    use crate::tests::{corrupt_each_byte, mixed, random, text};
+
+    fn check(data: &[u8], expected: &[u8]) {
+        let (out, len) = unxz(data).unwrap();
+        assert_eq!(out, expected);
+        assert_eq!(len, data.len());
+    }
+
+    /// 用给定的 LZMA2 数据构造只有一个块的 xz 流，校验和与索引中的大小不会被检查，直接填 0
+    fn stream(lzma2: &[u8]) -> Vec<u8> {
+        let mut data = XZ_MAGIC.to_vec();
+        data.extend([0, 1, 0, 0, 0, 0]);
+        data.extend([2, 0, FILTER_LZMA2 as u8, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend(lzma2);
+        data.resize(data.len().next_multiple_of(4) + 4, 0);
+        data.extend([0, 1, 0, 0, 0, 0, 0, 0]);
+        data.extend([0, 0, 0, 0, 0, 0, 0, 0, 0, 1, b'Y', b'Z']);
+        data
+    }
+
+    #[test]
+    fn test_reference_archives() {
+        check(include_bytes!("../testdata/text.xz"), &text(2000));
+        check(include_bytes!("../testdata/text-blocks.xz"), &text(2000));
+        check(include_bytes!("../testdata/small-sha256.xz"), &text(100));
+        check(include_bytes!("../testdata/small-none.xz"), &text(100));
+        check(include_bytes!("../testdata/small-lp2.xz"), &text(100));
+        check(include_bytes!("../testdata/small-lc4.xz"), &text(100));
+        check(
+            include_bytes!("../testdata/random.xz"),
+            &random(4096, 0x9e37_79b9_7f4a_7c15),
+        );
+        check(include_bytes!("../testdata/mixed.xz"), &mixed());
+        check(
+            include_bytes!("../testdata/repeat.xz"),
+            &text(100).repeat(400),
+        );
+        check(include_bytes!("../testdata/empty.xz"), &[]);
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut data = include_bytes!("../testdata/text.xz").to_vec();
+        let len = data.len();
+        data.extend([0; 8]);
+        data.extend(include_bytes!("../testdata/empty.xz"));
+        let (out, consumed) = unxz(&data).unwrap();
+        assert_eq!(out, text(2000));
+        assert_eq!(consumed, len);
+    }
+
+    #[test]
+    fn test_unsupported_filter() {
+        assert!(unxz(include_bytes!("../testdata/small-x86.xz")).is_err());
+    }
+
+    #[test]
+    fn test_truncated() {
+        for data in [
+            &include_bytes!("../testdata/small-none.xz")[..],
+            &include_bytes!("../testdata/random.xz")[..],
+        ] {
+            for len in 0..data.len() {
+                assert!(unxz(&data[..len]).is_err(), "truncated at {}", len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_corrupted() {
+        corrupt_each_byte(include_bytes!("../testdata/small-none.xz"), unxz);
+        corrupt_each_byte(include_bytes!("../testdata/small-lp2.xz"), unxz);
+        corrupt_each_byte(include_bytes!("../testdata/empty.xz"), unxz);
+    }
+
+    #[test]
+    fn test_lzma2_chunks() {
+        // 两个不压缩的块，第二个块不重置字典
+        let data = stream(&[1, 0, 2, b'a', b'b', b'c', 2, 0, 1, b'd', b'e', 0]);
+        check(&data, b"abcde");
+        // 第一个块必须重置字典
+        assert!(unxz(&stream(&[2, 0, 0, b'a', 0])).is_err());
+        // 字典重置之后的 LZMA 块必须设置属性，即使之前的块已经设置过
+        let lzma = [0x80, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0];
+        assert!(unxz(&stream(&[&[1, 0, 0, b'a'][..], &lzma, &[0]].concat())).is_err());
+        let data = include_bytes!("../testdata/small-none.xz");
+        let start = STREAM_HEADER_SIZE + (data[STREAM_HEADER_SIZE] as usize + 1) * 4;
+        let packed = ((data[start + 3] as usize) << 8 | data[start + 4] as usize) + 1;
+        let chunk = &data[start..start + 6 + packed];
+        check(
+            &stream(&[chunk, &lzma, &[0]].concat()),
+            &[text(100), vec![0]].concat(),
+        );
+        assert!(unxz(&stream(&[chunk, &[1, 0, 0, b'a'], &lzma, &[0]].concat())).is_err());
+        // 未定义的控制字节
+        assert!(unxz(&stream(&[1, 0, 0, b'a', 3, 0])).is_err());
+        // 缺少结束标志
+        assert!(unxz(&stream(&[1, 0, 0, b'a'])).is_err());
+    }
+
+    #[test]
+    fn test_missing_dict_reset() {
+        let mut data = include_bytes!("../testdata/text.xz").to_vec();
+        // 块头部之后的第一个字节是 LZMA2 的控制字节，改为不重置字典的 LZMA 块
+        let control = STREAM_HEADER_SIZE + (data[STREAM_HEADER_SIZE] as usize + 1) * 4;
+        assert!(data[control] >= 0xe0);
+        data[control] &= !0x60;
+        assert!(unxz(&data).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use alloc::format;
+use alloc::{boxed::Box, format, sync::Arc};
 use core::{
     mem::forget,
     ops::{Deref, DerefMut},
@@ -13,6 +13,7 @@ use page_table::{
 };
 use pager::{PageAllocator, PageAllocatorExt};
 use platform::println;
+use spin::Once;
 
 use crate::manager::FRAME_REF_MANAGER;
 
@@ -104,24 +105,72 @@ impl DerefMut for FrameTracker {
     }
 }
 
-pub fn alloc_frame_trackers(count: usize) -> FrameTracker {
+/// 物理页被记账到的对象
+pub trait FrameOwner: Send + Sync {
+    /// 属于该对象的 `count` 个物理页被释放
+    fn uncharge(&self, count: usize);
+}
+
+/// 记账超出了限制，物理页不会被分配
+#[derive(Debug)]
+pub struct OverLimit;
+
+/// 物理页的记账接口，由内核注册，用于 cgroup 的内存控制器
+pub trait FrameCharger: Send + Sync {
+    /// 当前任务将要分配 `count` 个物理页，返回被记账的对象，为 `None` 时表示不记账。
+    ///
+    /// 超出限制时不进行记账并返回 [`OverLimit`]，`force` 为真时总是进行记账
+    fn charge(&self, count: usize, force: bool) -> Result<Option<Arc<dyn FrameOwner>>, OverLimit>;
+}
+
+static FRAME_CHARGER: Once<Box<dyn FrameCharger>> = Once::new();
+
+/// 注册物理页的记账接口
+pub fn register_frame_charger(charger: Box<dyn FrameCharger>) {
+    FRAME_CHARGER.call_once(|| charger);
+}
+
+fn charge_frames(count: usize, force: bool) -> Result<Option<Arc<dyn FrameOwner>>, OverLimit> {
+    FRAME_CHARGER
+        .get()
+        .map_or(Ok(None), |charger| charger.charge(count, force))
+}
+
+fn alloc_charged_frames(count: usize, owner: Option<Arc<dyn FrameOwner>>) -> FrameTracker {
     let frame = FRAME_ALLOCATOR
         .lock()
         .alloc_pages(count, FRAME_SIZE)
         .expect(format!("alloc {} frame failed", count).as_str());
     trace!("alloc frame [{}] start page: {:#x}", count, frame);
+    let mut manager = FRAME_REF_MANAGER.lock();
     for i in 0..count {
-        let refs = FRAME_REF_MANAGER.lock().add_ref(frame + i);
-        assert_eq!(refs, 1)
+        let refs = manager.add_ref(frame + i);
+        assert_eq!(refs, 1);
+        if let Some(owner) = &owner {
+            manager.set_owner(frame + i, owner.clone());
+        }
     }
     FrameTracker::new(frame, count)
 }
 
+/// 分配 `count` 个连续的物理页，超出记账的限制时仍然会记账，用于不能失败的分配
+pub fn alloc_frame_trackers(count: usize) -> FrameTracker {
+    let owner = charge_frames(count, true).ok().flatten();
+    alloc_charged_frames(count, owner)
+}
+
+/// 分配 `count` 个连续的物理页，记账超出限制时不分配物理页并返回 `None`
+pub fn try_alloc_frame_trackers(count: usize) -> Option<FrameTracker> {
+    // 记账可能需要获取当前任务的信息，因此在获取 FRAME_REF_MANAGER 的锁之前进行
+    let owner = charge_frames(count, false).ok()?;
+    Some(alloc_charged_frames(count, owner))
+}
+
 pub struct VmmPageAllocator;
 
 impl PagingIf for VmmPageAllocator {
     fn alloc_frame() -> Option<PhysAddr> {
-        let frame = alloc_frame_trackers(1);
+        let frame = try_alloc_frame_trackers(1)?;
         let start_addr = frame.start();
         forget(frame);
         Some(PhysAddr::from(start_addr))
@@ -136,7 +185,7 @@ impl PagingIf for VmmPageAllocator {
     }
 
     fn alloc_contiguous_frames(size: usize) -> Option<PhysAddr> {
-        let frames = alloc_frame_trackers(size);
+        let frames = try_alloc_frame_trackers(size)?;
         let start_addr = frames.start();
         forget(frames);
         Some(PhysAddr::from(start_addr))
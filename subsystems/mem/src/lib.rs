@@ -15,7 +15,10 @@ mod manager;
 mod talc_wrapper;
 mod vmm;
 
-pub use frame::{alloc_frame_trackers, alloc_frames, free_frames, FrameTracker, VmmPageAllocator};
+pub use frame::{
+    alloc_frame_trackers, alloc_frames, free_frames, register_frame_charger,
+    try_alloc_frame_trackers, FrameCharger, FrameOwner, FrameTracker, OverLimit, VmmPageAllocator,
+};
 pub use manager::FRAME_REF_MANAGER;
 use platform::config::HEAP_SIZE;
 pub use vmm::{kernel_pgd, kernel_satp, kernel_space, map_region_to_kernel, query_kernel_space};
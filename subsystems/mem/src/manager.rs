@@ -1,26 +1,32 @@
-use alloc::collections::BTreeMap;
+use alloc::{collections::BTreeMap, sync::Arc};
 
 use ksync::Mutex;
 use log::trace;
 use pager::PageAllocator;
 use spin::Lazy;
 
-use crate::frame::FRAME_ALLOCATOR;
+use crate::frame::{FrameOwner, FRAME_ALLOCATOR};
 
 pub static FRAME_REF_MANAGER: Lazy<Mutex<FrameRefManager>> =
     Lazy::new(|| Mutex::new(FrameRefManager::new()));
 
-#[derive(Debug)]
 pub struct FrameRefManager {
     record: BTreeMap<usize, usize>,
+    /// 被记账的物理页所属的记账对象，每个物理页持有对象的一个引用，物理页被释放时解除记账
+    owner: BTreeMap<usize, Arc<dyn FrameOwner>>,
 }
 
 impl FrameRefManager {
     pub fn new() -> Self {
         Self {
             record: BTreeMap::new(),
+            owner: BTreeMap::new(),
         }
     }
+    /// 记录物理页 `id` 被记账到对象 `owner` 上
+    pub fn set_owner(&mut self, id: usize, owner: Arc<dyn FrameOwner>) {
+        self.owner.insert(id, owner);
+    }
     pub fn add_ref(&mut self, id: usize) -> usize {
         if let Some(count) = self.record.get_mut(&id) {
             *count += 1;
@@ -38,6 +44,9 @@ impl FrameRefManager {
                 self.record.remove(&id);
                 trace!("free frame:{:#x}", id);
                 FRAME_ALLOCATOR.lock().free(id, 0).unwrap();
+                if let Some(owner) = self.owner.remove(&id) {
+                    owner.uncharge(1);
+                }
             }
             return Some(now_count);
         } else {
@@ -0,0 +1,80 @@
+//! 内核命令行。
+//!
+//! 命令行来自设备树 `/chosen` 节点的 `bootargs` 属性，由空格分隔的 `key=value` 或 `key` 组成，
+//! 无法识别的参数会被忽略。
+use alloc::string::{String, ToString};
+
+use log::LevelFilter;
+use spin::Once;
+
+/// 已解析的内核命令行参数
+#[derive(Debug, Default, Clone)]
+pub struct Cmdline {
+    /// `root=`: 根文件系统所在的块设备，如 `/dev/vda2`
+    pub root: Option<String>,
+    /// `rootfstype=`: 根文件系统的类型，未指定时根据超级块识别
+    pub rootfstype: Option<String>,
+    /// `rootflags=`: 挂载根文件系统时传入的选项字符串
+    pub rootflags: Option<String>,
+    /// `ro`/`rw`: 是否以只读方式挂载根文件系统，默认可写
+    pub readonly: bool,
+    /// `init=`: 第一个用户程序的路径
+    pub init: Option<String>,
+    /// `console=`: 第一个用户程序的标准输入输出使用的设备名，如 `tty`。忽略 `,` 之后的波特率等选项
+    pub console: Option<String>,
+    /// `loglevel=`: 内核日志的输出等级
+    pub loglevel: Option<LevelFilter>,
+}
+
+static CMDLINE: Once<Cmdline> = Once::new();
+
+/// 解析命令行字符串
+pub fn parse_cmdline(bootargs: &str) -> Cmdline {
+    let mut cmdline = Cmdline::default();
+    for arg in bootargs.split_ascii_whitespace() {
+        let (key, value) = match arg.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (arg, None),
+        };
+        match (key, value) {
+            ("root", Some(value)) => cmdline.root = Some(value.to_string()),
+            ("rootfstype", Some(value)) => cmdline.rootfstype = Some(value.to_string()),
+            ("rootflags", Some(value)) => cmdline.rootflags = Some(value.to_string()),
+            ("ro", None) => cmdline.readonly = true,
+            ("rw", None) => cmdline.readonly = false,
+            ("init", Some(value)) => cmdline.init = Some(value.to_string()),
+            ("console", Some(value)) => {
+                let name = value.split(',').next().unwrap();
+                cmdline.console = Some(name.trim_start_matches("/dev/").to_string());
+            }
+            ("loglevel", Some(value)) => {
+                cmdline.loglevel = value.parse::<usize>().ok().map(loglevel_filter)
+            }
+            _ => {}
+        }
+    }
+    cmdline
+}
+
+/// 将 Linux 的日志等级转换为输出过滤等级。Linux 只输出等级数值小于 `loglevel` 的消息，
+/// 错误(3)、警告(4)、信息(6)、调试(7) 分别对应这里的 `Error`、`Warn`、`Info`、`Debug`，
+/// 9 及以上输出所有日志
+fn loglevel_filter(level: usize) -> LevelFilter {
+    match level {
+        0 => LevelFilter::Off,
+        1..=4 => LevelFilter::Error,
+        5..=6 => LevelFilter::Warn,
+        7 => LevelFilter::Info,
+        8 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+pub(crate) fn init_cmdline(bootargs: &str) {
+    CMDLINE.call_once(|| parse_cmdline(bootargs));
+}
+
+/// 内核命令行参数。设备树中没有 `bootargs` 时所有参数都取默认值
+pub fn cmdline() -> &'static Cmdline {
+    CMDLINE.call_once(Cmdline::default)
+}
@@ -5,6 +5,7 @@ extern crate alloc;
 
 #[macro_use]
 pub mod console;
+pub mod cmdline;
 mod common_riscv;
 #[cfg(feature = "hifive")]
 mod hifive_riscv;
@@ -44,6 +45,12 @@ pub fn platform_init(hart_id: usize, _dtb: usize) {
     #[cfg(feature = "qemu_riscv")]
     qemu_riscv::init_dtb(Some(_dtb));
     let machine_info = basic_machine_info();
+    let bootargs = machine_info
+        .bootargs
+        .as_ref()
+        .map(|args| core::str::from_utf8(&args[..machine_info.bootargs_len]).unwrap_or(""))
+        .unwrap_or("");
+    cmdline::init_cmdline(bootargs);
     MACHINE_INFO.call_once(|| machine_info);
     logging::init_logger();
     preprint::init_print(&PrePrint);
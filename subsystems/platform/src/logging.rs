@@ -30,12 +30,14 @@ impl Log for SimpleLogger {
 pub fn init_logger() {
     println!("Init logger {:?}", option_env!("LOG"));
     log::set_logger(&SimpleLogger).unwrap();
-    log::set_max_level(match option_env!("LOG") {
+    let level = match option_env!("LOG") {
         Some("ERROR") => LevelFilter::Error,
         Some("WARN") => LevelFilter::Warn,
         Some("INFO") => LevelFilter::Info,
         Some("DEBUG") => LevelFilter::Debug,
         Some("TRACE") => LevelFilter::Trace,
         _ => LevelFilter::Off,
-    });
+    };
+    // 命令行中的 `loglevel=` 覆盖编译时指定的日志等级
+    log::set_max_level(crate::cmdline::cmdline().loglevel.unwrap_or(level));
 }
@@ -22,6 +22,8 @@ pub trait KTaskShim: Send + Sync {
     fn schedule_now(&self, task: Arc<dyn KTask>);
     fn transfer_ptr_raw(&self, ptr: usize) -> usize;
     fn transfer_buf_raw(&self, src: usize, size: usize) -> Vec<&mut [u8]>;
+    /// Send a signal generated by the kernel to every process in the process group `pgid`.
+    fn kill_pgrp(&self, pgid: usize, signum: usize);
 }
 
 impl dyn KTaskShim {
@@ -87,11 +89,9 @@ pub fn take_current_task() -> Option<Arc<dyn KTask>> {
         .take_current_task()
 }
 #[cfg(feature = "lib")]
+/// Get the current task. Return `None` before the shim is registered.
 pub fn current_task() -> Option<Arc<dyn KTask>> {
-    KTASK_SHIM
-        .get()
-        .expect("ktask_shim not initialized")
-        .current_task()
+    KTASK_SHIM.get().and_then(|shim| shim.current_task())
 }
 #[cfg(feature = "lib")]
 pub fn suspend() {
@@ -124,6 +124,14 @@ pub fn copy_data_to_task<T: 'static + Copy>(src: *const T, dst: *mut T) {
         .copy_data_to_task(src, dst);
 }
 #[cfg(feature = "lib")]
+/// Send a signal to every process in the process group `pgid`.
+pub fn kill_pgrp(pgid: usize, signum: usize) {
+    KTASK_SHIM
+        .get()
+        .expect("ktask_shim not initialized")
+        .kill_pgrp(pgid, signum);
+}
+#[cfg(feature = "lib")]
 pub fn copy_data_from_task<T: 'static + Copy>(src: *const T, dst: *mut T) {
     KTASK_SHIM
         .get()
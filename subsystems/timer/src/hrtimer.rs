@@ -0,0 +1,127 @@
+//! 高精度计时器 (hrtimer)。
+//!
+//! 所有计时器按照 (到期时刻, 序号) 的顺序保存在一个全局的有序队列中，队首即为最早到期的计时器。
+//! 每个 hart 会将 SBI 计时器设置为 "本 hart 的下一个时钟节拍" 与 "最早到期的计时器" 中较早的一个，
+//! 因此计时器能够在到期时刻准时触发，而不必等到下一个时钟节拍。
+//!
+//! 空闲的 hart 可以通过 [`program_next_event`] 关闭时钟节拍 (tickless idle)，此时只有计时器到期或外部中断才会将其唤醒。
+//!
+//! 计时器的回调函数在时钟中断中由 [`hrtimer_run`] 调用，调用时不持有计时器队列的锁，
+//! 因此回调函数中可以再次启动或取消计时器。
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use arch::hart_id;
+use config::CPU_NUM;
+use ksync::Mutex;
+use platform::set_timer;
+
+use crate::read_timer;
+
+/// 计时器到期时执行的回调函数
+pub type HrTimerCallback = Box<dyn FnOnce() + Send>;
+
+/// 计时器的句柄，用于取消计时器
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HrTimerId {
+    /// 到期时刻，以 cpu 时钟周期数表示
+    deadline: usize,
+    /// 序号，用于区分到期时刻相同的计时器
+    seq: usize,
+}
+
+impl HrTimerId {
+    /// 获取计时器的到期时刻
+    pub fn deadline(&self) -> usize {
+        self.deadline
+    }
+}
+
+/// 全局的计时器队列
+struct HrTimerQueue {
+    timers: BTreeMap<HrTimerId, HrTimerCallback>,
+    next_seq: usize,
+}
+
+static HRTIMER_QUEUE: Mutex<HrTimerQueue> = Mutex::new(HrTimerQueue {
+    timers: BTreeMap::new(),
+    next_seq: 0,
+});
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_EVENT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// 每个 hart 下一个时钟节拍的时刻，为 `usize::MAX` 时表示该 hart 关闭了时钟节拍
+static TICK_DEADLINE: [AtomicUsize; CPU_NUM] = [NO_EVENT; CPU_NUM];
+/// 每个 hart 当前设置给 SBI 计时器的时刻
+static NEXT_EVENT: [AtomicUsize; CPU_NUM] = [NO_EVENT; CPU_NUM];
+
+/// 启动一个在 `deadline` 时刻到期的计时器，到期时在时钟中断中执行 `callback`。
+///
+/// 如果该计时器比当前 hart 设置的下一次时钟中断更早到期，会重新设置 SBI 计时器。
+pub fn hrtimer_start(deadline: usize, callback: HrTimerCallback) -> HrTimerId {
+    let mut queue = HRTIMER_QUEUE.lock();
+    let id = HrTimerId {
+        deadline,
+        seq: queue.next_seq,
+    };
+    queue.next_seq += 1;
+    queue.timers.insert(id, callback);
+    drop(queue);
+    let hart = hart_id();
+    if deadline < NEXT_EVENT[hart].load(Ordering::Relaxed) {
+        NEXT_EVENT[hart].store(deadline, Ordering::Relaxed);
+        set_timer(deadline);
+    }
+    id
+}
+
+/// 取消一个计时器。如果计时器已经到期或已经被取消，返回 false
+pub fn hrtimer_cancel(id: HrTimerId) -> bool {
+    HRTIMER_QUEUE.lock().timers.remove(&id).is_some()
+}
+
+/// 最早到期的计时器的到期时刻
+pub fn hrtimer_next_deadline() -> Option<usize> {
+    HRTIMER_QUEUE
+        .lock()
+        .timers
+        .first_key_value()
+        .map(|(id, _)| id.deadline)
+}
+
+/// 执行所有已经到期的计时器的回调函数，在时钟中断中调用
+pub fn hrtimer_run() {
+    let now = read_timer();
+    let expired = {
+        let mut queue = HRTIMER_QUEUE.lock();
+        let mut expired = Vec::new();
+        while let Some(entry) = queue.timers.first_entry() {
+            if entry.key().deadline > now {
+                break;
+            }
+            expired.push(entry.remove());
+        }
+        expired
+    };
+    for callback in expired {
+        callback();
+    }
+}
+
+/// 设置当前 hart 的下一个时钟节拍，并将 SBI 计时器设置为该时钟节拍与最早到期的计时器中较早的一个。
+///
+/// `tick` 为 `None` 时关闭当前 hart 的时钟节拍，用于 hart 空闲时。
+pub fn program_next_event(tick: Option<usize>) {
+    let hart = hart_id();
+    let tick = tick.unwrap_or(usize::MAX);
+    TICK_DEADLINE[hart].store(tick, Ordering::Relaxed);
+    let next = hrtimer_next_deadline().map_or(tick, |deadline| deadline.min(tick));
+    NEXT_EVENT[hart].store(next, Ordering::Relaxed);
+    set_timer(next);
+}
+
+/// 当前 hart 的时钟节拍是否处于开启状态
+pub fn tick_enabled() -> bool {
+    TICK_DEADLINE[hart_id()].load(Ordering::Relaxed) != usize::MAX
+}
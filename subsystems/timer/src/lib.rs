@@ -1,8 +1,13 @@
 #![no_std]
 
+extern crate alloc;
+
 use constants::time::{TimeSpec, TimeVal};
 use platform::config::CLOCK_FREQ;
 use vfscore::utils::VfsTimeSpec;
+
+pub mod hrtimer;
+
 /// 每秒包含的毫秒数
 const MSEC_PER_SEC: usize = 1000;
 /// 程序运行时间
@@ -0,0 +1,21 @@
+use alloc::sync::Arc;
+
+use constants::io::MountFlags;
+use dynfs::DynFsDirInode;
+use spin::Once;
+use vfscore::{dentry::VfsDentry, fstype::VfsFsType};
+
+use crate::CommonFsProviderImpl;
+
+pub type CgroupFsDirInodeImpl = DynFsDirInode<CommonFsProviderImpl, spin::Mutex<()>>;
+/// cgroup v2 文件系统的根目录，对应根 cgroup，所有 cgroup 组成的层级结构在这里以目录的形式出现
+pub static CGROUP_FS_ROOT: Once<Arc<dyn VfsDentry>> = Once::new();
+
+pub fn init_cgroupfs(fs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
+    let root = fs
+        .i_mount(MountFlags::empty().bits(), "/sys/fs/cgroup", None, &[])
+        .unwrap();
+    CGROUP_FS_ROOT.call_once(|| root.clone());
+    println!("cgroupfs init success");
+    root
+}
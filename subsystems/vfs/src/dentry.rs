@@ -0,0 +1,120 @@
+//! 通用的目录项实现。
+//!
+//! 自身不保存 inode 的层次结构、每次查找都会构造新 inode 的文件系统(overlay、fuse)使用它缓存查找结果，
+//! 保证同一路径总是对应同一个 inode。
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+};
+
+use ksync::Mutex;
+use vfscore::{
+    dentry::{VfsDentry, VfsMountPoint},
+    error::VfsError,
+    inode::VfsInode,
+    utils::VfsNodeType,
+    VfsResult,
+};
+
+/// 只在内存中存在的目录项，子目录项在第一次查找时插入
+pub struct GenericDentry {
+    inner: Mutex<GenericDentryInner>,
+}
+
+struct GenericDentryInner {
+    name: String,
+    inode: Arc<dyn VfsInode>,
+    parent: Option<Weak<dyn VfsDentry>>,
+    mnt: Option<VfsMountPoint>,
+    children: BTreeMap<String, Arc<GenericDentry>>,
+}
+
+impl GenericDentry {
+    pub fn root(inode: Arc<dyn VfsInode>) -> Arc<Self> {
+        Arc::new(Self::new("/", inode))
+    }
+
+    fn new(name: &str, inode: Arc<dyn VfsInode>) -> Self {
+        Self {
+            inner: Mutex::new(GenericDentryInner {
+                name: name.to_string(),
+                inode,
+                parent: None,
+                mnt: None,
+                children: BTreeMap::new(),
+            }),
+        }
+    }
+}
+
+impl VfsDentry for GenericDentry {
+    fn name(&self) -> String {
+        self.inner.lock().name.clone()
+    }
+    fn to_mount_point(
+        self: Arc<Self>,
+        sub_fs_root: Arc<dyn VfsDentry>,
+        mount_flag: u32,
+    ) -> VfsResult<()> {
+        if self.inode()?.inode_type() != VfsNodeType::Dir {
+            return Err(VfsError::ENOTDIR);
+        }
+        let point = self.clone() as Arc<dyn VfsDentry>;
+        self.inner.lock().mnt = Some(VfsMountPoint {
+            root: sub_fs_root,
+            mount_point: Arc::downgrade(&point),
+            mnt_flags: mount_flag,
+        });
+        Ok(())
+    }
+    fn inode(&self) -> VfsResult<Arc<dyn VfsInode>> {
+        Ok(self.inner.lock().inode.clone())
+    }
+    fn mount_point(&self) -> Option<VfsMountPoint> {
+        self.inner.lock().mnt.clone()
+    }
+    fn clear_mount_point(&self) {
+        self.inner.lock().mnt = None;
+    }
+    fn find(&self, path: &str) -> Option<Arc<dyn VfsDentry>> {
+        self.inner
+            .lock()
+            .children
+            .get(path)
+            .map(|child| child.clone() as Arc<dyn VfsDentry>)
+    }
+    fn insert(
+        self: Arc<Self>,
+        name: &str,
+        child: Arc<dyn VfsInode>,
+    ) -> VfsResult<Arc<dyn VfsDentry>> {
+        if self.inode()?.inode_type() != VfsNodeType::Dir {
+            return Err(VfsError::ENOTDIR);
+        }
+        let child = Arc::new(GenericDentry::new(name, child));
+        child.set_parent(&(self.clone() as Arc<dyn VfsDentry>));
+        self.inner
+            .lock()
+            .children
+            .insert(name.to_string(), child.clone());
+        Ok(child)
+    }
+    fn remove(&self, name: &str) -> Option<Arc<dyn VfsDentry>> {
+        self.inner
+            .lock()
+            .children
+            .remove(name)
+            .map(|child| child as Arc<dyn VfsDentry>)
+    }
+    fn parent(&self) -> Option<Arc<dyn VfsDentry>> {
+        self.inner
+            .lock()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+    }
+    fn set_parent(&self, parent: &Arc<dyn VfsDentry>) {
+        self.inner.lock().parent = Some(Arc::downgrade(parent));
+    }
+}
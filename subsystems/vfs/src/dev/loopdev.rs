@@ -0,0 +1,463 @@
+//! loop 设备。
+//!
+//! `/dev/loopN` 把普通文件当作块设备使用，绑定后对设备的读写经过块设备缓存转换为对文件的读写，
+//! 存放在磁盘上的文件系统镜像因此可以直接挂载。`/dev/loop-control` 用于分配、创建与删除 loop 设备。
+//!
+//! 绑定需要调用者的文件描述符，`LOOP_SET_FD` 与 `LOOP_CONFIGURE` 由系统调用层解析后调用
+//! [`LoopDevice::attach`]，其余命令由设备自己处理。`LO_FLAGS_AUTOCLEAR` 只被记录，
+//! 设备不会在最后一次关闭时自动解除绑定，设备上挂载着文件系统时 `LOOP_CLR_FD` 返回 `EBUSY`；
+//! `LO_FLAGS_PARTSCAN` 同样不会扫描镜像中的分区表。
+use alloc::{boxed::Box, collections::BTreeMap, format, string::String, sync::Arc, vec::Vec};
+
+use constants::{
+    block::{BLKFLSBUF, BLKGETSIZE, BLKGETSIZE64, BLKSSZGET},
+    loop_dev::*,
+    AlienResult, DeviceId, LinuxErrno,
+};
+use device_interface::{BlockDevice, LowBlockDevice};
+use devices::GenericBlockDevice;
+use ksync::{Mutex, SleepMutex};
+use spin::Once;
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType, VfsPollEvents},
+    VfsResult,
+};
+
+use super::{alloc_device_id, register_device, unregister_device};
+use crate::{kfile::File, mount::device_mounted};
+
+const SECTOR_SIZE: usize = 512;
+/// 启动时创建的 loop 设备数量
+const DEFAULT_LOOP_DEVICES: usize = 8;
+/// `LOOP_SET_STATUS64` 可以修改的标志
+const LO_FLAGS_SETTABLE: u32 = LO_FLAGS_AUTOCLEAR | LO_FLAGS_PARTSCAN;
+
+/// 所有 loop 设备，按编号排列
+static LOOP_DEVICES: Mutex<BTreeMap<usize, Arc<LoopDevice>>> = Mutex::new(BTreeMap::new());
+/// `/dev` 的根目录，用于创建与删除设备节点
+static DEV_ROOT: Once<Arc<dyn VfsInode>> = Once::new();
+
+/// 以文件中从 `offset` 开始的 `size` 字节作为存储的块设备
+struct LoopBackend {
+    file: Arc<dyn File>,
+    offset: u64,
+    size: u64,
+    readonly: bool,
+}
+
+impl LowBlockDevice for LoopBackend {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> AlienResult<()> {
+        let pos = (block_id * SECTOR_SIZE) as u64;
+        // 缓存页可能超出设备末尾，超出设备或者文件末尾的部分读为 0
+        let len = (buf.len() as u64).min(self.size.saturating_sub(pos)) as usize;
+        let mut count = 0;
+        while count < len {
+            let read = self
+                .file
+                .read_at(self.offset + pos + count as u64, &mut buf[count..len])?;
+            if read == 0 {
+                break;
+            }
+            count += read;
+        }
+        buf[count..].fill(0);
+        Ok(())
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> AlienResult<()> {
+        if self.readonly {
+            return Err(LinuxErrno::EROFS);
+        }
+        let pos = (block_id * SECTOR_SIZE) as u64;
+        // 超出设备末尾的部分不写入文件，避免文件被扩大
+        let len = (buf.len() as u64).min(self.size.saturating_sub(pos)) as usize;
+        let mut count = 0;
+        while count < len {
+            let written = self
+                .file
+                .write_at(self.offset + pos + count as u64, &buf[count..len])?;
+            if written == 0 {
+                return Err(LinuxErrno::EIO);
+            }
+            count += written;
+        }
+        Ok(())
+    }
+    fn capacity(&self) -> usize {
+        self.size as usize / SECTOR_SIZE
+    }
+    fn handle_irq(&self) {}
+    fn flush(&self) -> AlienResult<()> {
+        self.file.fsync()
+    }
+}
+
+struct LoopBinding {
+    file: Arc<dyn File>,
+    device: Arc<GenericBlockDevice>,
+    /// 偏移、大小限制、标志与文件名，其余字段在获取状态时填写
+    info: LoopInfo64,
+}
+
+impl LoopBinding {
+    fn readonly(&self) -> bool {
+        self.info.lo_flags & LO_FLAGS_READ_ONLY != 0
+    }
+}
+
+/// `/dev/loopN`
+pub struct LoopDevice {
+    device_id: DeviceId,
+    number: usize,
+    /// 绑定的文件，修改绑定时需要等待缓存写回
+    binding: SleepMutex<Option<LoopBinding>>,
+}
+
+impl LoopDevice {
+    fn new(device_id: DeviceId, number: usize) -> Self {
+        Self {
+            device_id,
+            number,
+            binding: SleepMutex::new(None),
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("loop{}", self.number)
+    }
+
+    /// 按 `info` 中的偏移与大小限制创建以 `file` 为存储的块设备
+    fn backend(
+        file: &Arc<dyn File>,
+        info: &LoopInfo64,
+        readonly: bool,
+    ) -> AlienResult<Arc<GenericBlockDevice>> {
+        let file_size = file.get_attr()?.st_size;
+        if info.lo_offset > file_size {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let mut size = file_size - info.lo_offset;
+        if info.lo_sizelimit != 0 {
+            size = size.min(info.lo_sizelimit);
+        }
+        // 设备的大小按扇区向下取整
+        size -= size % SECTOR_SIZE as u64;
+        let backend = LoopBackend {
+            file: file.clone(),
+            offset: info.lo_offset,
+            size,
+            readonly,
+        };
+        let device = Arc::new(GenericBlockDevice::new(Box::new(backend)));
+        // 与磁盘一样由 sync 与定期写回的内核线程写回缓存，解除绑定后设备被释放，自动注销
+        devices::register_cached_device(&device);
+        Ok(device)
+    }
+
+    /// 绑定文件，`info` 为 `LOOP_CONFIGURE` 同时给出的设置。
+    /// 文件不可写或者 `info` 中设置了 `LO_FLAGS_READ_ONLY` 时设备只读
+    pub fn attach(&self, file: Arc<dyn File>, info: Option<LoopInfo64>) -> AlienResult<()> {
+        if !matches!(
+            file.inode().inode_type(),
+            VfsNodeType::File | VfsNodeType::BlockDevice
+        ) {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let mut binding = self.binding.lock();
+        if binding.is_some() {
+            return Err(LinuxErrno::EBUSY);
+        }
+        let mut info = info.unwrap_or_default();
+        let readonly = !file.is_writable() || info.lo_flags & LO_FLAGS_READ_ONLY != 0;
+        info.lo_flags &= LO_FLAGS_SETTABLE;
+        if readonly {
+            info.lo_flags |= LO_FLAGS_READ_ONLY;
+        }
+        let device = Self::backend(&file, &info, readonly)?;
+        *binding = Some(LoopBinding { file, device, info });
+        Ok(())
+    }
+
+    /// 解除绑定，缓存中的数据先写回文件。设备上挂载着文件系统时返回 `EBUSY`
+    pub fn detach(&self) -> AlienResult<()> {
+        if device_mounted(self.device_id.id()) {
+            return Err(LinuxErrno::EBUSY);
+        }
+        let mut binding = self.binding.lock();
+        // 先写回缓存，写回失败时保留绑定，脏页不会丢失
+        binding.as_ref().ok_or(LinuxErrno::ENXIO)?.device.flush()?;
+        binding.take();
+        Ok(())
+    }
+
+    /// `LOOP_SET_STATUS64`。修改偏移或大小限制时重新建立块设备，只读标志不能修改
+    fn set_status(&self, info: &LoopInfo64) -> AlienResult<()> {
+        let mut binding = self.binding.lock();
+        let binding = binding.as_mut().ok_or(LinuxErrno::ENXIO)?;
+        if info.lo_offset != binding.info.lo_offset
+            || info.lo_sizelimit != binding.info.lo_sizelimit
+        {
+            binding.device.flush()?;
+            binding.device = Self::backend(&binding.file, info, binding.readonly())?;
+            binding.info.lo_offset = info.lo_offset;
+            binding.info.lo_sizelimit = info.lo_sizelimit;
+        }
+        binding.info.lo_flags =
+            (binding.info.lo_flags & !LO_FLAGS_SETTABLE) | (info.lo_flags & LO_FLAGS_SETTABLE);
+        binding.info.lo_file_name = info.lo_file_name;
+        binding.info.lo_file_name[LO_NAME_SIZE - 1] = 0;
+        Ok(())
+    }
+
+    /// `LOOP_GET_STATUS64`
+    fn status(&self) -> AlienResult<LoopInfo64> {
+        let binding = self.binding.lock();
+        let binding = binding.as_ref().ok_or(LinuxErrno::ENXIO)?;
+        let stat = binding.file.get_attr()?;
+        Ok(LoopInfo64 {
+            lo_device: stat.st_dev,
+            lo_inode: stat.st_ino,
+            lo_rdevice: stat.st_rdev,
+            lo_number: self.number as u32,
+            ..binding.info
+        })
+    }
+
+    fn is_bound(&self) -> bool {
+        self.binding.lock().is_some()
+    }
+
+    /// 绑定的块设备与设备是否只读，没有绑定时返回 `ENXIO`
+    fn device(&self) -> VfsResult<(Arc<GenericBlockDevice>, bool)> {
+        let binding = self.binding.lock();
+        let binding = binding.as_ref().ok_or(VfsError::ENXIO)?;
+        Ok((binding.device.clone(), binding.readonly()))
+    }
+
+    fn size(&self) -> usize {
+        self.device().map(|(device, _)| device.size()).unwrap_or(0)
+    }
+}
+
+impl VfsFile for LoopDevice {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let (device, _) = self.device()?;
+        let (offset, size) = (offset as usize, device.size());
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min(size - offset);
+        device.read(&mut buf[..len], offset)
+    }
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let (device, readonly) = self.device()?;
+        if readonly {
+            return Err(VfsError::EROFS);
+        }
+        let (offset, size) = (offset as usize, device.size());
+        if offset >= size && !buf.is_empty() {
+            return Err(VfsError::ENOSPC);
+        }
+        let len = buf.len().min(size - offset);
+        device.write(&buf[..len], offset)
+    }
+    fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        Ok(event & (VfsPollEvents::IN | VfsPollEvents::OUT))
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
+        match cmd {
+            LOOP_CLR_FD => self.detach()?,
+            LOOP_SET_STATUS64 => {
+                let mut info = LoopInfo64::default();
+                shim::copy_data_from_task(arg as *const LoopInfo64, &mut info);
+                self.set_status(&info)?;
+            }
+            LOOP_GET_STATUS64 => {
+                let info = self.status()?;
+                shim::copy_data_to_task(&info, arg as *mut LoopInfo64);
+            }
+            BLKGETSIZE64 => {
+                let size = self.size() as u64;
+                shim::copy_data_to_task(&size, arg as *mut u64);
+            }
+            BLKGETSIZE => {
+                let sectors = self.size() / SECTOR_SIZE;
+                shim::copy_data_to_task(&sectors, arg as *mut usize);
+            }
+            BLKSSZGET => {
+                let sector_size = SECTOR_SIZE as u32;
+                shim::copy_data_to_task(&sector_size, arg as *mut u32);
+            }
+            BLKFLSBUF => self.flush()?,
+            // 绑定需要调用者的文件描述符，由系统调用层处理
+            _ => return Err(VfsError::ENOTTY),
+        }
+        Ok(0)
+    }
+    fn flush(&self) -> VfsResult<()> {
+        match self.device() {
+            Ok((device, _)) => device.flush(),
+            Err(_) => Ok(()),
+        }
+    }
+    fn fsync(&self) -> VfsResult<()> {
+        self.flush()
+    }
+}
+
+impl VfsInode for LoopDevice {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_rdev: self.device_id.id(),
+            st_size: self.size() as u64,
+            st_blksize: SECTOR_SIZE as u32,
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::BlockDevice
+    }
+}
+
+/// 创建编号为 `number` 的 loop 设备及其设备节点
+fn add_loop_device(number: usize) -> AlienResult<Arc<LoopDevice>> {
+    let root = DEV_ROOT.get().ok_or(LinuxErrno::ENODEV)?;
+    let mut devices = LOOP_DEVICES.lock();
+    if devices.contains_key(&number) {
+        return Err(LinuxErrno::EEXIST);
+    }
+    let device = Arc::new(LoopDevice::new(
+        alloc_device_id(VfsNodeType::BlockDevice),
+        number,
+    ));
+    root.create(
+        &device.name(),
+        VfsNodeType::BlockDevice,
+        "rw-rw----".into(),
+        Some(device.device_id.id()),
+    )?;
+    register_device(device.clone());
+    devices.insert(number, device.clone());
+    Ok(device)
+}
+
+/// 删除没有绑定文件的 loop 设备
+fn remove_loop_device(number: usize) -> AlienResult<()> {
+    let root = DEV_ROOT.get().ok_or(LinuxErrno::ENODEV)?;
+    let device = LOOP_DEVICES
+        .lock()
+        .get(&number)
+        .cloned()
+        .ok_or(LinuxErrno::ENODEV)?;
+    // 检查绑定需要获取睡眠锁，不能在持有设备表的锁时进行
+    if device.is_bound() {
+        return Err(LinuxErrno::EBUSY);
+    }
+    LOOP_DEVICES.lock().remove(&number);
+    let _ = root.unlink(&device.name());
+    unregister_device(device.device_id);
+    Ok(())
+}
+
+/// 找到一个没有绑定文件的 loop 设备，都已绑定时创建一个新的
+fn free_loop_device() -> AlienResult<usize> {
+    let devices = LOOP_DEVICES.lock().values().cloned().collect::<Vec<_>>();
+    match devices.iter().find(|device| !device.is_bound()) {
+        Some(device) => Ok(device.number),
+        None => {
+            let next = devices.last().map_or(0, |device| device.number + 1);
+            add_loop_device(next).map(|device| device.number)
+        }
+    }
+}
+
+/// 查找设备节点 `inode` 对应的 loop 设备，用于处理需要文件描述符的 `LOOP_SET_FD` 与 `LOOP_CONFIGURE`
+pub fn find_loop_device(inode: &Arc<dyn VfsInode>) -> Option<Arc<LoopDevice>> {
+    if inode.inode_type() != VfsNodeType::BlockDevice {
+        return None;
+    }
+    let rdev = inode.get_attr().ok()?.st_rdev;
+    LOOP_DEVICES
+        .lock()
+        .values()
+        .find(|device| device.device_id.id() == rdev)
+        .cloned()
+}
+
+/// `/dev/loop-control`
+pub struct LoopControl {
+    device_id: DeviceId,
+}
+
+impl LoopControl {
+    pub fn new(device_id: DeviceId) -> Self {
+        Self { device_id }
+    }
+    pub fn device_id(&self) -> DeviceId {
+        self.device_id
+    }
+}
+
+impl VfsFile for LoopControl {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
+        match cmd {
+            LOOP_CTL_GET_FREE => free_loop_device(),
+            LOOP_CTL_ADD => add_loop_device(arg).map(|_| arg),
+            LOOP_CTL_REMOVE => remove_loop_device(arg).map(|_| arg),
+            _ => Err(VfsError::ENOTTY),
+        }
+    }
+}
+
+impl VfsInode for LoopControl {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        VfsNodePerm::empty()
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_rdev: self.device_id.id(),
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::CharDevice
+    }
+}
+
+/// 在 `/dev` 下创建 `loop-control` 与 `loop0` ~ `loop7`
+pub fn init_loop_devices(root: &Arc<dyn VfsInode>) {
+    DEV_ROOT.call_once(|| root.clone());
+    let control = Arc::new(LoopControl::new(alloc_device_id(VfsNodeType::CharDevice)));
+    root.create(
+        "loop-control",
+        'c'.into(),
+        "rw-rw----".into(),
+        Some(control.device_id().id()),
+    )
+    .unwrap();
+    register_device(control);
+    for number in 0..DEFAULT_LOOP_DEVICES {
+        add_loop_device(number).unwrap();
+    }
+}
@@ -1,10 +1,11 @@
-use alloc::{collections::BTreeMap, sync::Arc};
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
 
 use constants::DeviceId;
 use devfs::DevKernelProvider;
 use devices::{
-    BLKDevice, GPUDevice, INPUTDevice, RTCDevice, UARTDevice, BLOCK_DEVICE, GPU_DEVICE,
-    KEYBOARD_INPUT_DEVICE, MOUSE_INPUT_DEVICE, RTC_DEVICE, UART_DEVICE,
+    block_devices, register_block_node_manager, BLKDevice, BlockNodeManager, GPUDevice,
+    INPUTDevice, RTCDevice, UARTDevice, GPU_DEVICE, KEYBOARD_INPUT_DEVICE, MOUSE_INPUT_DEVICE,
+    RTC_DEVICE, UART_DEVICE,
 };
 use ksync::Mutex;
 use log::info;
@@ -18,8 +19,11 @@ use vfscore::{
     utils::{VfsNodeType, VfsTimeSpec},
 };
 
+use crate::fuse::FuseDevice;
+
+pub mod loopdev;
 mod null;
-mod random;
+pub mod random;
 
 pub static DEVICES: Lazy<Mutex<BTreeMap<DeviceId, Arc<dyn VfsInode>>>> =
     Lazy::new(|| Mutex::new(BTreeMap::new()));
@@ -94,8 +98,13 @@ impl DevKernelProvider for DevFsProviderImpl {
 /// |-- zero
 /// |-- random
 /// |-- urandom
+/// |-- fuse
+/// |-- loop-control
+/// |-- loop0 ... loop7 (loop devices backed by regular files)
 /// |-- tty
+/// |-- vda, vda1 ... (block devices and their partitions)
 /// |-- shm (a ramfs will be mounted here)
+/// |-- mqueue (the mqueue fs will be mounted here)
 /// |-- misc
 ///    |-- rtc
 /// ```
@@ -107,6 +116,7 @@ pub fn init_devfs(devfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
     let zero_device = Arc::new(NullDevice::new(alloc_device_id(VfsNodeType::CharDevice)));
     let random_device = Arc::new(RandomDevice::new(alloc_device_id(VfsNodeType::CharDevice)));
     let urandom_device = Arc::new(RandomDevice::new(alloc_device_id(VfsNodeType::CharDevice)));
+    let fuse_device = Arc::new(FuseDevice::new(alloc_device_id(VfsNodeType::CharDevice)));
 
     root_inode
         .create(
@@ -140,41 +150,88 @@ pub fn init_devfs(devfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
             Some(urandom_device.device_id().id()),
         )
         .unwrap();
+    root_inode
+        .create(
+            "fuse",
+            'c'.into(),
+            "rw-rw-rw-".into(),
+            Some(fuse_device.device_id().id()),
+        )
+        .unwrap();
 
     register_device(null_device);
     register_device(zero_device);
     register_device(random_device);
     register_device(urandom_device);
+    register_device(fuse_device);
 
     root_inode
         .create("shm", VfsNodeType::Dir, "rwxrwxrwx".into(), None)
         .unwrap();
+    root_inode
+        .create("mqueue", VfsNodeType::Dir, "rwxrwxrwx".into(), None)
+        .unwrap();
     root_inode
         .create("misc", VfsNodeType::Dir, "rwxrwxrwx".into(), None)
         .unwrap();
 
+    loopdev::init_loop_devices(&root_inode);
     scan_system_devices(root_inode);
     // todo!(tty,shm,misc)
     println!("devfs init success");
     root
 }
 
-fn scan_system_devices(root: Arc<dyn VfsInode>) {
-    BLOCK_DEVICE.get().map(|blk| {
-        let block_device = Arc::new(BLKDevice::new(
-            alloc_device_id(VfsNodeType::BlockDevice),
-            blk.clone(),
-        ));
+/// 在 `/dev` 下为块设备和分区创建设备节点
+struct DevBlockNodeManager {
+    root: Arc<dyn VfsInode>,
+}
+
+impl DevBlockNodeManager {
+    fn create_node(root: &Arc<dyn VfsInode>, device: &BLKDevice) {
         root.create(
-            "sda",
+            device.name(),
             VfsNodeType::BlockDevice,
             "rw-rw----".into(),
-            Some(block_device.device_id().id()),
+            Some(device.device_id().id()),
         )
         .unwrap();
-        info!("block device id: {}", block_device.device_id().id());
-        register_device(block_device);
-    });
+    }
+}
+
+impl BlockNodeManager for DevBlockNodeManager {
+    fn alloc_device_id(&self) -> DeviceId {
+        alloc_device_id(VfsNodeType::BlockDevice)
+    }
+    fn add_node(&self, device: Arc<BLKDevice>) {
+        Self::create_node(&self.root, &device);
+        register_device(device);
+    }
+    fn remove_node(&self, device: &BLKDevice) {
+        let _ = self.root.unlink(device.name());
+        unregister_device(device.device_id());
+    }
+}
+
+fn scan_system_devices(root: Arc<dyn VfsInode>) {
+    register_block_node_manager(Box::new(DevBlockNodeManager { root: root.clone() }));
+    for (name, blk) in block_devices() {
+        let block_device = Arc::new(BLKDevice::new(
+            alloc_device_id(VfsNodeType::BlockDevice),
+            &name,
+            blk,
+        ));
+        DevBlockNodeManager::create_node(&root, &block_device);
+        info!(
+            "block device {} id: {}",
+            name,
+            block_device.device_id().id()
+        );
+        register_device(block_device.clone());
+        if let Err(e) = block_device.rescan_partitions() {
+            println!("{}: failed to read partition table: {:?}", name, e);
+        }
+    }
     GPU_DEVICE.get().map(|gpu| {
         let gpu_device = Arc::new(GPUDevice::new(
             alloc_device_id(VfsNodeType::CharDevice),
@@ -1,4 +1,9 @@
+//! 随机数设备 `/dev/random` 与 `/dev/urandom`，以及内核中使用的随机数源。
+//!
+//! 随机数由 splitmix64 生成，每次生成时都会混入当前的时钟周期数，使得输出与调用的时机相关。
+//! `getrandom` 系统调用、ELF 加载器中的地址空间随机化以及 `AT_RANDOM` 都使用这里的随机数源，不能用于密码学用途。
 use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use arch::read_timer;
 use vfscore::{
@@ -11,6 +16,28 @@ use vfscore::{
 };
 
 use crate::dev::DeviceId;
+
+/// 随机数生成器的状态
+static RANDOM_STATE: AtomicUsize = AtomicUsize::new(0);
+
+/// 生成一个随机数
+pub fn random_usize() -> usize {
+    let mut z = RANDOM_STATE
+        .fetch_add(0x9e37_79b9_7f4a_7c15 ^ read_timer(), Ordering::Relaxed)
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// 使用随机数填充 `buf`
+pub fn random_fill(buf: &mut [u8]) {
+    buf.chunks_mut(core::mem::size_of::<usize>())
+        .for_each(|chunk| {
+            let bytes = random_usize().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        });
+}
 pub struct RandomDevice {
     device_id: DeviceId,
 }
@@ -25,11 +52,7 @@ impl RandomDevice {
 
 impl VfsFile for RandomDevice {
     fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
-        let mut current_time = read_timer();
-        buf.iter_mut().for_each(|x| {
-            *x = current_time as u8;
-            current_time = current_time.wrapping_sub(1);
-        });
+        random_fill(buf);
         Ok(buf.len())
     }
     fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
@@ -0,0 +1,393 @@
+//! FUSE 协议中的消息格式，与 Linux 的 `include/uapi/linux/fuse.h` 相同。
+//!
+//! 所有结构体都按自然对齐排列，不含填充字节，可以直接按字节读写。
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use vfscore::utils::VfsNodeType;
+
+pub const FUSE_KERNEL_VERSION: u32 = 7;
+/// 内核支持的协议次版本号，守护进程回复的次版本号可以更低
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 31;
+/// 支持的守护进程的最低次版本号
+pub const FUSE_MIN_MINOR_VERSION: u32 = 8;
+pub const FUSE_ROOT_ID: u64 = 1;
+
+/// 普通请求的 unique 都是偶数，中断请求使用被中断的请求的 unique 加上这一位
+pub const FUSE_INT_REQ_BIT: u64 = 1;
+
+pub const FUSE_LOOKUP: u32 = 1;
+pub const FUSE_FORGET: u32 = 2;
+pub const FUSE_GETATTR: u32 = 3;
+pub const FUSE_SETATTR: u32 = 4;
+pub const FUSE_READLINK: u32 = 5;
+pub const FUSE_SYMLINK: u32 = 6;
+pub const FUSE_MKNOD: u32 = 8;
+pub const FUSE_MKDIR: u32 = 9;
+pub const FUSE_UNLINK: u32 = 10;
+pub const FUSE_RMDIR: u32 = 11;
+pub const FUSE_RENAME: u32 = 12;
+pub const FUSE_LINK: u32 = 13;
+pub const FUSE_OPEN: u32 = 14;
+pub const FUSE_READ: u32 = 15;
+pub const FUSE_WRITE: u32 = 16;
+pub const FUSE_RELEASE: u32 = 18;
+pub const FUSE_FSYNC: u32 = 20;
+pub const FUSE_FLUSH: u32 = 25;
+pub const FUSE_INIT: u32 = 26;
+pub const FUSE_OPENDIR: u32 = 27;
+pub const FUSE_READDIR: u32 = 28;
+pub const FUSE_RELEASEDIR: u32 = 29;
+pub const FUSE_CREATE: u32 = 35;
+pub const FUSE_INTERRUPT: u32 = 36;
+pub const FUSE_RENAME2: u32 = 45;
+
+pub const FATTR_SIZE: u32 = 1 << 3;
+pub const FATTR_ATIME: u32 = 1 << 4;
+pub const FATTR_MTIME: u32 = 1 << 5;
+pub const FATTR_FH: u32 = 1 << 6;
+
+/// 7.9 之前的 `fuse_attr` 没有 `blksize` 与 `padding`，包含它的结构体也较短
+pub const FUSE_COMPAT_ENTRY_OUT_SIZE: usize = 120;
+pub const FUSE_COMPAT_ATTR_OUT_SIZE: usize = 96;
+/// 7.9 之前的 `fuse_write_in` 只有 `fh`、`offset`、`size` 与 `write_flags`
+pub const FUSE_COMPAT_WRITE_IN_SIZE: usize = 24;
+/// 7.12 之前的 `fuse_mknod_in` 没有 `umask`
+pub const FUSE_COMPAT_MKNOD_IN_SIZE: usize = 8;
+/// 7.12 之前的 `fuse_create_in` 只有 `flags` 与 `mode`
+pub const FUSE_COMPAT_CREATE_IN_SIZE: usize = 8;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseInHeader {
+    pub len: u32,
+    pub opcode: u32,
+    pub unique: u64,
+    pub nodeid: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseOutHeader {
+    pub len: u32,
+    pub error: i32,
+    pub unique: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseAttr {
+    pub ino: u64,
+    pub size: u64,
+    pub blocks: u64,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+    pub atimensec: u32,
+    pub mtimensec: u32,
+    pub ctimensec: u32,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u32,
+    pub blksize: u32,
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseEntryOut {
+    pub nodeid: u64,
+    pub generation: u64,
+    pub entry_valid: u64,
+    pub attr_valid: u64,
+    pub entry_valid_nsec: u32,
+    pub attr_valid_nsec: u32,
+    pub attr: FuseAttr,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseAttrOut {
+    pub attr_valid: u64,
+    pub attr_valid_nsec: u32,
+    pub dummy: u32,
+    pub attr: FuseAttr,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseInitIn {
+    pub major: u32,
+    pub minor: u32,
+    pub max_readahead: u32,
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseInitOut {
+    pub major: u32,
+    pub minor: u32,
+    pub max_readahead: u32,
+    pub flags: u32,
+    pub max_background: u16,
+    pub congestion_threshold: u16,
+    pub max_write: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseForgetIn {
+    pub nlookup: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseGetattrIn {
+    pub getattr_flags: u32,
+    pub dummy: u32,
+    pub fh: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseSetattrIn {
+    pub valid: u32,
+    pub padding: u32,
+    pub fh: u64,
+    pub size: u64,
+    pub lock_owner: u64,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+    pub atimensec: u32,
+    pub mtimensec: u32,
+    pub ctimensec: u32,
+    pub mode: u32,
+    pub unused4: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub unused5: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseMknodIn {
+    pub mode: u32,
+    pub rdev: u32,
+    pub umask: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseMkdirIn {
+    pub mode: u32,
+    pub umask: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseRenameIn {
+    pub newdir: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseRename2In {
+    pub newdir: u64,
+    pub flags: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseLinkIn {
+    pub oldnodeid: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseOpenIn {
+    pub flags: u32,
+    pub unused: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseCreateIn {
+    pub flags: u32,
+    pub mode: u32,
+    pub umask: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseOpenOut {
+    pub fh: u64,
+    pub open_flags: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseReleaseIn {
+    pub fh: u64,
+    pub flags: u32,
+    pub release_flags: u32,
+    pub lock_owner: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseFlushIn {
+    pub fh: u64,
+    pub unused: u32,
+    pub padding: u32,
+    pub lock_owner: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseReadIn {
+    pub fh: u64,
+    pub offset: u64,
+    pub size: u32,
+    pub read_flags: u32,
+    pub lock_owner: u64,
+    pub flags: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseWriteIn {
+    pub fh: u64,
+    pub offset: u64,
+    pub size: u32,
+    pub write_flags: u32,
+    pub lock_owner: u64,
+    pub flags: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseWriteOut {
+    pub size: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseFsyncIn {
+    pub fh: u64,
+    pub fsync_flags: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseInterruptIn {
+    pub unique: u64,
+}
+
+/// `fuse_dirent` 的固定部分，之后是不以 0 结尾的名字，整个目录项按 8 字节对齐
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FuseDirent {
+    pub ino: u64,
+    pub off: u64,
+    pub namelen: u32,
+    pub ty: u32,
+}
+
+/// 可以直接按字节读写的协议结构体
+pub trait FuseAbi: Copy + Default {
+    fn as_bytes(&self) -> &[u8] {
+        // 结构体中没有填充字节，所有字节都已初始化
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+
+    /// 从 `bytes` 的开头读取结构体，旧版本协议的结构体较短，缺少的字段为 0
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut value = Self::default();
+        let len = bytes.len().min(size_of::<Self>());
+        unsafe {
+            core::ptr::copy_nonoverlapping(bytes.as_ptr(), &mut value as *mut Self as *mut u8, len);
+        }
+        value
+    }
+}
+
+impl FuseAbi for FuseInHeader {}
+impl FuseAbi for FuseOutHeader {}
+impl FuseAbi for FuseEntryOut {}
+impl FuseAbi for FuseAttrOut {}
+impl FuseAbi for FuseInitIn {}
+impl FuseAbi for FuseInitOut {}
+impl FuseAbi for FuseForgetIn {}
+impl FuseAbi for FuseGetattrIn {}
+impl FuseAbi for FuseSetattrIn {}
+impl FuseAbi for FuseMknodIn {}
+impl FuseAbi for FuseMkdirIn {}
+impl FuseAbi for FuseRenameIn {}
+impl FuseAbi for FuseRename2In {}
+impl FuseAbi for FuseLinkIn {}
+impl FuseAbi for FuseOpenIn {}
+impl FuseAbi for FuseCreateIn {}
+impl FuseAbi for FuseOpenOut {}
+impl FuseAbi for FuseReleaseIn {}
+impl FuseAbi for FuseFlushIn {}
+impl FuseAbi for FuseReadIn {}
+impl FuseAbi for FuseWriteIn {}
+impl FuseAbi for FuseWriteOut {}
+impl FuseAbi for FuseFsyncIn {}
+impl FuseAbi for FuseInterruptIn {}
+impl FuseAbi for FuseDirent {}
+
+/// 以 0 结尾的名字，请求中的名字都使用这种格式
+pub fn c_name(name: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(name.len() + 1);
+    bytes.extend_from_slice(name.as_bytes());
+    bytes.push(0);
+    bytes
+}
+
+/// 目录项中的类型与 `st_mode` 中的类型位相同，只是没有左移 12 位
+pub fn node_type(dt: u32) -> VfsNodeType {
+    match dt {
+        1 => VfsNodeType::Fifo,
+        2 => VfsNodeType::CharDevice,
+        4 => VfsNodeType::Dir,
+        6 => VfsNodeType::BlockDevice,
+        8 => VfsNodeType::File,
+        10 => VfsNodeType::SymLink,
+        12 => VfsNodeType::Socket,
+        _ => VfsNodeType::Unknown,
+    }
+}
+
+pub fn mode_to_type(mode: u32) -> VfsNodeType {
+    node_type((mode >> 12) & 0xf)
+}
+
+pub fn type_to_mode(ty: VfsNodeType) -> u32 {
+    (ty as u32) << 12
+}
+
+/// Linux 用户态使用的设备号编码
+pub fn encode_dev(major: u32, minor: u32) -> u32 {
+    (minor & 0xff) | (major << 8) | ((minor & !0xff) << 12)
+}
+
+pub fn decode_dev(dev: u32) -> (u32, u32) {
+    ((dev >> 8) & 0xfff, (dev & 0xff) | ((dev >> 12) & 0xfff00))
+}
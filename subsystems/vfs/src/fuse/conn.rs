@@ -0,0 +1,380 @@
+//! 内核与守护进程之间的连接。
+//!
+//! 文件系统的操作被编码为请求放入发送队列，守护进程读取 `/dev/fuse` 取出请求，处理完成后写入回复，
+//! 内核根据回复中的 `unique` 找到等待的请求。等待回复期间收到信号时，还没有被读取的请求直接撤回，
+//! 已经被读取的请求会向守护进程发送 `FUSE_INTERRUPT`，由守护进程决定是以 `EINTR` 结束还是正常完成。
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
+use core::mem::size_of;
+
+use constants::LinuxErrno;
+use ksync::Mutex;
+use log::warn;
+use vfscore::{error::VfsError, VfsResult};
+
+use super::abi::*;
+
+/// 守护进程没有在初始化回复中给出 `max_write` 时使用的大小
+const DEFAULT_MAX_WRITE: usize = 4096;
+
+/// 请求的回复由谁处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReplyTo {
+    /// 发出请求的任务等待回复
+    Task,
+    /// 初始化请求在挂载时异步发出，回复由连接自己处理
+    Init,
+    /// 释放文件句柄等不需要等待的请求，回复被丢弃
+    Discard,
+}
+
+struct Request {
+    reply_to: ReplyTo,
+    /// 守护进程已经读取了这个请求
+    sent: bool,
+    reply: Option<VfsResult<Vec<u8>>>,
+}
+
+struct ConnState {
+    connected: bool,
+    initialized: bool,
+    mounted: bool,
+    minor: u32,
+    max_write: usize,
+    next_unique: u64,
+    /// 等待守护进程读取的消息与其 `unique`
+    pending: VecDeque<(u64, Vec<u8>)>,
+    /// 已经放入发送队列、等待回复的请求
+    requests: BTreeMap<u64, Request>,
+}
+
+pub struct FuseConn {
+    state: Mutex<ConnState>,
+}
+
+/// 当前任务是否有待处理的信号
+fn signal_pending() -> bool {
+    shim::current_task().is_some_and(|task| task.have_signal())
+}
+
+fn message(opcode: u32, unique: u64, nodeid: u64, args: &[&[u8]]) -> Vec<u8> {
+    let len = size_of::<FuseInHeader>() + args.iter().map(|arg| arg.len()).sum::<usize>();
+    let header = FuseInHeader {
+        len: len as u32,
+        opcode,
+        unique,
+        nodeid,
+        // 所有请求都以 root 的身份发出
+        uid: 0,
+        gid: 0,
+        pid: 0,
+        padding: 0,
+    };
+    let mut msg = Vec::with_capacity(len);
+    msg.extend_from_slice(header.as_bytes());
+    for arg in args {
+        msg.extend_from_slice(arg);
+    }
+    msg
+}
+
+impl FuseConn {
+    pub(super) fn new() -> Self {
+        Self {
+            state: Mutex::new(ConnState {
+                connected: true,
+                initialized: false,
+                mounted: false,
+                minor: 0,
+                max_write: DEFAULT_MAX_WRITE,
+                next_unique: 2,
+                pending: VecDeque::new(),
+                requests: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// 守护进程使用的协议次版本号，旧版本的部分结构体较短
+    pub fn minor(&self) -> u32 {
+        self.state.lock().minor
+    }
+
+    pub fn max_write(&self) -> usize {
+        self.state.lock().max_write
+    }
+
+    /// 一个连接只能挂载一次，挂载时发出初始化请求
+    pub fn mount(&self) -> VfsResult<()> {
+        let init = FuseInitIn {
+            major: FUSE_KERNEL_VERSION,
+            minor: FUSE_KERNEL_MINOR_VERSION,
+            max_readahead: 0,
+            flags: 0,
+        };
+        let mut state = self.state.lock();
+        if state.mounted || !state.connected {
+            return Err(VfsError::Invalid);
+        }
+        state.mounted = true;
+        // 守护进程通常在挂载完成之后才开始读取请求，不能在这里等待回复
+        Self::queue(&mut state, FUSE_INIT, 0, &[init.as_bytes()], ReplyTo::Init);
+        Ok(())
+    }
+
+    /// 断开连接，所有等待中的请求以 `ENOTCONN` 结束，守护进程之后的读取返回 `ENODEV`
+    pub fn abort(&self) {
+        let mut state = self.state.lock();
+        state.connected = false;
+        state.pending.clear();
+        state
+            .requests
+            .retain(|_, req| req.reply_to == ReplyTo::Task);
+    }
+
+    fn queue(
+        state: &mut ConnState,
+        opcode: u32,
+        nodeid: u64,
+        args: &[&[u8]],
+        reply_to: ReplyTo,
+    ) -> u64 {
+        let unique = state.next_unique;
+        state.next_unique += 2;
+        state
+            .pending
+            .push_back((unique, message(opcode, unique, nodeid, args)));
+        state.requests.insert(
+            unique,
+            Request {
+                reply_to,
+                sent: false,
+                reply: None,
+            },
+        );
+        unique
+    }
+
+    /// 发出请求并等待回复，返回回复中消息头之后的内容
+    pub fn request(&self, opcode: u32, nodeid: u64, args: &[&[u8]]) -> VfsResult<Vec<u8>> {
+        self.wait_initialized()?;
+        let unique = {
+            let mut state = self.state.lock();
+            if !state.connected {
+                return Err(VfsError::ENOTCONN);
+            }
+            Self::queue(&mut state, opcode, nodeid, args, ReplyTo::Task)
+        };
+        self.wait_reply(unique)
+    }
+
+    /// 发出不需要等待回复的请求，连接已经断开时直接丢弃
+    pub fn request_background(&self, opcode: u32, nodeid: u64, args: &[&[u8]]) {
+        let mut state = self.state.lock();
+        if state.connected {
+            Self::queue(&mut state, opcode, nodeid, args, ReplyTo::Discard);
+        }
+    }
+
+    /// `FUSE_FORGET` 没有回复
+    pub fn forget(&self, nodeid: u64, nlookup: u64) {
+        let mut state = self.state.lock();
+        if state.connected {
+            let unique = state.next_unique;
+            state.next_unique += 2;
+            let arg = FuseForgetIn { nlookup };
+            let msg = message(FUSE_FORGET, unique, nodeid, &[arg.as_bytes()]);
+            state.pending.push_back((unique, msg));
+        }
+    }
+
+    fn wait_initialized(&self) -> VfsResult<()> {
+        loop {
+            {
+                let state = self.state.lock();
+                if !state.connected {
+                    return Err(VfsError::ENOTCONN);
+                }
+                if state.initialized {
+                    return Ok(());
+                }
+            }
+            if signal_pending() {
+                return Err(VfsError::EINTR);
+            }
+            shim::suspend();
+        }
+    }
+
+    fn wait_reply(&self, unique: u64) -> VfsResult<Vec<u8>> {
+        let mut interrupted = false;
+        loop {
+            {
+                let mut state = self.state.lock();
+                if let Some(reply) = state
+                    .requests
+                    .get_mut(&unique)
+                    .and_then(|req| req.reply.take())
+                {
+                    state.requests.remove(&unique);
+                    return reply;
+                }
+                if !state.connected {
+                    state.requests.remove(&unique);
+                    return Err(VfsError::ENOTCONN);
+                }
+                if !interrupted && signal_pending() {
+                    interrupted = true;
+                    if let Some(index) = state.pending.iter().position(|(u, _)| *u == unique) {
+                        // 守护进程还没有读到这个请求，直接撤回
+                        state.pending.remove(index);
+                        state.requests.remove(&unique);
+                        return Err(VfsError::EINTR);
+                    }
+                    Self::queue_interrupt(&mut state, unique);
+                }
+            }
+            shim::suspend();
+        }
+    }
+
+    /// 中断请求优先于其它请求被读取
+    fn queue_interrupt(state: &mut ConnState, unique: u64) {
+        let arg = FuseInterruptIn { unique };
+        let int_unique = unique | FUSE_INT_REQ_BIT;
+        let msg = message(FUSE_INTERRUPT, int_unique, 0, &[arg.as_bytes()]);
+        state.pending.push_front((int_unique, msg));
+    }
+
+    /// 守护进程读取下一个请求。没有请求时阻塞，`nonblock` 为真时返回 `EAGAIN`
+    pub fn read_request(&self, buf: &mut [u8], nonblock: bool) -> VfsResult<usize> {
+        loop {
+            {
+                let mut state = self.state.lock();
+                if !state.connected {
+                    return Err(VfsError::ENODEV);
+                }
+                if let Some((unique, msg)) = state.pending.pop_front() {
+                    if buf.len() < msg.len() {
+                        // 缓冲区放不下这个请求，请求以 EIO 结束。初始化请求失败时断开连接，
+                        // 否则挂载后的所有操作都会一直等待初始化完成
+                        match state.requests.get(&unique).map(|req| req.reply_to) {
+                            Some(ReplyTo::Task) => {
+                                state.requests.get_mut(&unique).unwrap().reply =
+                                    Some(Err(VfsError::IoError));
+                            }
+                            Some(ReplyTo::Init) => {
+                                state.requests.remove(&unique);
+                                Self::finish_init(&mut state, Err(VfsError::IoError));
+                            }
+                            Some(ReplyTo::Discard) => {
+                                state.requests.remove(&unique);
+                            }
+                            None => {}
+                        }
+                        return Err(VfsError::Invalid);
+                    }
+                    buf[..msg.len()].copy_from_slice(&msg);
+                    if let Some(req) = state.requests.get_mut(&unique) {
+                        req.sent = true;
+                    }
+                    return Ok(msg.len());
+                }
+            }
+            if nonblock {
+                return Err(VfsError::EAGAIN);
+            }
+            if signal_pending() {
+                return Err(VfsError::EINTR);
+            }
+            shim::suspend();
+        }
+    }
+
+    /// 守护进程写入回复
+    pub fn write_reply(&self, buf: &[u8]) -> VfsResult<usize> {
+        if buf.len() < size_of::<FuseOutHeader>() {
+            return Err(VfsError::Invalid);
+        }
+        let header = FuseOutHeader::from_bytes(buf);
+        if header.len as usize != buf.len() || header.error > 0 || header.error <= -512 {
+            return Err(VfsError::Invalid);
+        }
+        let body = &buf[size_of::<FuseOutHeader>()..];
+        let reply = if header.error == 0 {
+            Ok(body.to_vec())
+        } else {
+            Err(LinuxErrno::try_from(header.error as isize).unwrap_or(VfsError::IoError))
+        };
+        let mut state = self.state.lock();
+        if !state.connected {
+            return Err(VfsError::ENODEV);
+        }
+        if header.unique == 0 {
+            // 守护进程主动发出的通知，目前不处理
+            return Ok(buf.len());
+        }
+        if header.unique & FUSE_INT_REQ_BIT != 0 {
+            // 守护进程暂时无法处理中断时回复 EAGAIN，请求还没有完成时重新发送中断
+            let unique = header.unique & !FUSE_INT_REQ_BIT;
+            let waiting = state
+                .requests
+                .get(&unique)
+                .is_some_and(|req| req.reply.is_none());
+            if waiting && matches!(reply, Err(VfsError::EAGAIN)) {
+                Self::queue_interrupt(&mut state, unique);
+            }
+            return Ok(buf.len());
+        }
+        let reply_to = match state.requests.get_mut(&header.unique) {
+            Some(req) if req.sent && req.reply.is_none() => {
+                if req.reply_to == ReplyTo::Task {
+                    req.reply = Some(reply);
+                    return Ok(buf.len());
+                }
+                req.reply_to
+            }
+            _ => return Err(VfsError::ENOENT),
+        };
+        state.requests.remove(&header.unique);
+        if reply_to == ReplyTo::Init {
+            Self::finish_init(&mut state, reply);
+        }
+        Ok(buf.len())
+    }
+
+    /// 初始化失败或者协议版本不兼容时断开连接
+    fn finish_init(state: &mut ConnState, reply: VfsResult<Vec<u8>>) {
+        let init = match reply {
+            // 只读取守护进程给出的字段，旧版本的回复较短
+            Ok(body) if body.len() >= 8 => FuseInitOut::from_bytes(&body),
+            _ => {
+                warn!("fuse: init failed, abort the connection");
+                state.connected = false;
+                state.pending.clear();
+                return;
+            }
+        };
+        if init.major != FUSE_KERNEL_VERSION || init.minor < FUSE_MIN_MINOR_VERSION {
+            warn!(
+                "fuse: unsupported protocol version {}.{}",
+                init.major, init.minor
+            );
+            state.connected = false;
+            state.pending.clear();
+            return;
+        }
+        state.minor = init.minor.min(FUSE_KERNEL_MINOR_VERSION);
+        if init.max_write != 0 {
+            state.max_write = (init.max_write as usize).max(DEFAULT_MAX_WRITE);
+        }
+        state.initialized = true;
+    }
+
+    /// 有请求等待读取或者连接已经断开时可读
+    pub fn readable(&self) -> bool {
+        let state = self.state.lock();
+        !state.connected || !state.pending.is_empty()
+    }
+}
@@ -0,0 +1,166 @@
+//! `/dev/fuse` 字符设备。
+//!
+//! 每次打开设备都会建立一个新的连接，守护进程把得到的文件描述符通过挂载选项 `fd=` 交给内核。
+//! 最后一个引用该连接的文件描述符关闭时连接断开。
+use alloc::sync::Arc;
+use core::fmt::{Debug, Formatter};
+
+use constants::{
+    io::{OpenFlags, PollEvents, SeekFrom},
+    AlienResult, DeviceId, LinuxErrno,
+};
+use ksync::Mutex;
+use spin::Once;
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType},
+    VfsResult,
+};
+
+use super::conn::FuseConn;
+use crate::kfile::File;
+
+static FUSE_DEVICE_ID: Once<DeviceId> = Once::new();
+
+/// 设备节点本身不能读写，打开后的读写由 [`FuseDevFile`] 处理
+pub struct FuseDevice {
+    device_id: DeviceId,
+}
+
+impl FuseDevice {
+    pub fn new(device_id: DeviceId) -> Self {
+        FUSE_DEVICE_ID.call_once(|| device_id);
+        Self { device_id }
+    }
+    pub fn device_id(&self) -> DeviceId {
+        self.device_id
+    }
+}
+
+impl VfsFile for FuseDevice {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+}
+
+impl VfsInode for FuseDevice {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        VfsNodePerm::empty()
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_rdev: self.device_id.id(),
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::CharDevice
+    }
+}
+
+/// `dentry` 是否是 `/dev/fuse`，打开它时需要使用 [`FuseDevFile`]
+pub fn is_fuse_device(dentry: &Arc<dyn VfsDentry>) -> bool {
+    let Some(id) = FUSE_DEVICE_ID.get() else {
+        return false;
+    };
+    let Ok(inode) = dentry.inode() else {
+        return false;
+    };
+    inode.inode_type() == VfsNodeType::CharDevice
+        && inode.get_attr().is_ok_and(|attr| attr.st_rdev == id.id())
+}
+
+/// 打开 `/dev/fuse` 得到的文件，读取请求、写入回复
+pub struct FuseDevFile {
+    open_flag: Mutex<OpenFlags>,
+    dentry: Arc<dyn VfsDentry>,
+    conn: Arc<FuseConn>,
+}
+
+impl Debug for FuseDevFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FuseDevFile")
+            .field("open_flag", &self.open_flag)
+            .finish()
+    }
+}
+
+impl FuseDevFile {
+    pub fn new(dentry: Arc<dyn VfsDentry>, open_flag: OpenFlags) -> Self {
+        Self {
+            open_flag: Mutex::new(open_flag),
+            dentry,
+            conn: Arc::new(FuseConn::new()),
+        }
+    }
+
+    pub(super) fn conn(&self) -> Arc<FuseConn> {
+        self.conn.clone()
+    }
+}
+
+impl File for FuseDevFile {
+    fn read(&self, buf: &mut [u8]) -> AlienResult<usize> {
+        let nonblock = self.open_flag.lock().contains(OpenFlags::O_NONBLOCK);
+        self.conn.read_request(buf, nonblock)
+    }
+    fn write(&self, buf: &[u8]) -> AlienResult<usize> {
+        self.conn.write_reply(buf)
+    }
+    fn seek(&self, _pos: SeekFrom) -> AlienResult<u64> {
+        Err(LinuxErrno::ESPIPE)
+    }
+    fn get_attr(&self) -> AlienResult<VfsFileStat> {
+        self.dentry.inode()?.get_attr()
+    }
+    fn set_open_flag(&self, flag: OpenFlags) {
+        *self.open_flag.lock() = flag;
+    }
+    fn get_open_flag(&self) -> OpenFlags {
+        *self.open_flag.lock()
+    }
+    fn dentry(&self) -> Arc<dyn VfsDentry> {
+        self.dentry.clone()
+    }
+    fn inode(&self) -> Arc<dyn VfsInode> {
+        self.dentry.inode().unwrap()
+    }
+    fn is_readable(&self) -> bool {
+        true
+    }
+    fn is_writable(&self) -> bool {
+        true
+    }
+    fn is_append(&self) -> bool {
+        false
+    }
+    fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        let mut res = PollEvents::empty();
+        if event.contains(PollEvents::EPOLLIN) && self.conn.readable() {
+            res |= PollEvents::EPOLLIN;
+        }
+        if event.contains(PollEvents::EPOLLOUT) {
+            res |= PollEvents::EPOLLOUT;
+        }
+        Ok(res)
+    }
+}
+
+impl Drop for FuseDevFile {
+    fn drop(&mut self) {
+        self.conn.abort();
+    }
+}
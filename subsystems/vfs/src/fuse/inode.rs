@@ -0,0 +1,512 @@
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::mem::size_of;
+
+use ksync::{Mutex, SleepMutex};
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{
+        VfsDirEntry, VfsFileStat, VfsNodePerm, VfsNodeType, VfsPollEvents, VfsRenameFlag, VfsTime,
+        VfsTimeSpec,
+    },
+    VfsResult,
+};
+
+use super::{abi::*, FuseSuperBlock};
+
+const O_RDONLY: u32 = 0;
+const O_WRONLY: u32 = 1;
+const O_CREAT: u32 = 0o100;
+const O_EXCL: u32 = 0o200;
+/// 读取目录时每个请求的缓冲区大小
+const READDIR_SIZE: u32 = 4096;
+
+/// 守护进程中的一个节点。
+///
+/// 每个由 `LOOKUP` 等请求得到的 inode 对应守护进程中的一次查找计数，inode 释放时通过 `FUSE_FORGET` 归还。
+/// 文件在第一次读写时打开，读与写分别使用只读与只写的文件句柄，inode 释放时关闭。
+pub struct FuseInode {
+    sb: Arc<FuseSuperBlock>,
+    nodeid: u64,
+    ty: VfsNodeType,
+    /// 以只读与只写方式打开得到的文件句柄，打开期间需要等待守护进程的回复
+    handles: SleepMutex<[Option<u64>; 2]>,
+    /// 目录项列表，在从头读取目录时重新获取
+    dir_entries: Mutex<Vec<VfsDirEntry>>,
+}
+
+impl FuseInode {
+    pub(super) fn new(sb: Arc<FuseSuperBlock>, nodeid: u64, ty: VfsNodeType) -> Arc<Self> {
+        Arc::new(Self {
+            sb,
+            nodeid,
+            ty,
+            handles: SleepMutex::new([None, None]),
+            dir_entries: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn request(&self, opcode: u32, args: &[&[u8]]) -> VfsResult<Vec<u8>> {
+        self.sb.conn.request(opcode, self.nodeid, args)
+    }
+
+    /// 7.9 之前的守护进程使用较短的 `fuse_attr`
+    fn compat(&self) -> bool {
+        self.sb.conn.minor() < 9
+    }
+
+    fn entry_size(&self) -> usize {
+        if self.compat() {
+            FUSE_COMPAT_ENTRY_OUT_SIZE
+        } else {
+            size_of::<FuseEntryOut>()
+        }
+    }
+
+    /// 根据 `LOOKUP`、`MKDIR` 等请求的回复创建子节点
+    fn new_child(&self, reply: &[u8]) -> VfsResult<Arc<FuseInode>> {
+        let size = self.entry_size();
+        if reply.len() < size {
+            return Err(VfsError::IoError);
+        }
+        let entry = FuseEntryOut::from_bytes(&reply[..size]);
+        // nodeid 为 0 表示不存在，并且守护进程希望缓存这个结果
+        if entry.nodeid == 0 {
+            return Err(VfsError::ENOENT);
+        }
+        let ty = mode_to_type(entry.attr.mode);
+        Ok(FuseInode::new(self.sb.clone(), entry.nodeid, ty))
+    }
+
+    fn attr(&self) -> VfsResult<FuseAttr> {
+        let reply = if self.compat() {
+            self.request(FUSE_GETATTR, &[])?
+        } else {
+            self.request(FUSE_GETATTR, &[FuseGetattrIn::default().as_bytes()])?
+        };
+        let size = if self.compat() {
+            FUSE_COMPAT_ATTR_OUT_SIZE
+        } else {
+            size_of::<FuseAttrOut>()
+        };
+        if reply.len() < size {
+            return Err(VfsError::IoError);
+        }
+        Ok(FuseAttrOut::from_bytes(&reply[..size]).attr)
+    }
+
+    fn set_attr_with(&self, mut arg: FuseSetattrIn) -> VfsResult<()> {
+        if let Some(fh) = self.handles.lock()[1] {
+            arg.valid |= FATTR_FH;
+            arg.fh = fh;
+        }
+        self.request(FUSE_SETATTR, &[arg.as_bytes()])?;
+        Ok(())
+    }
+
+    /// 获取读(`write` 为假)或写使用的文件句柄，第一次使用时打开文件
+    fn handle(&self, write: bool) -> VfsResult<u64> {
+        match self.ty {
+            VfsNodeType::File => {}
+            VfsNodeType::Dir => return Err(VfsError::EISDIR),
+            _ => return Err(VfsError::Invalid),
+        }
+        let mut handles = self.handles.lock();
+        if let Some(fh) = handles[write as usize] {
+            return Ok(fh);
+        }
+        let arg = FuseOpenIn {
+            flags: if write { O_WRONLY } else { O_RDONLY },
+            unused: 0,
+        };
+        let reply = self.request(FUSE_OPEN, &[arg.as_bytes()])?;
+        let fh = FuseOpenOut::from_bytes(&reply).fh;
+        handles[write as usize] = Some(fh);
+        Ok(fh)
+    }
+
+    /// 打开目录并读取所有目录项
+    fn read_dir(&self) -> VfsResult<Vec<VfsDirEntry>> {
+        let arg = FuseOpenIn::default();
+        let reply = self.request(FUSE_OPENDIR, &[arg.as_bytes()])?;
+        let fh = FuseOpenOut::from_bytes(&reply).fh;
+        let entries = self.read_dir_entries(fh);
+        let release = FuseReleaseIn {
+            fh,
+            ..Default::default()
+        };
+        self.sb
+            .conn
+            .request_background(FUSE_RELEASEDIR, self.nodeid, &[release.as_bytes()]);
+        entries
+    }
+
+    fn read_dir_entries(&self, fh: u64) -> VfsResult<Vec<VfsDirEntry>> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        loop {
+            let arg = FuseReadIn {
+                fh,
+                offset,
+                size: READDIR_SIZE,
+                ..Default::default()
+            };
+            let reply = self.request(FUSE_READDIR, &[arg.as_bytes()])?;
+            if reply.is_empty() {
+                break;
+            }
+            let mut pos = 0;
+            while pos + size_of::<FuseDirent>() <= reply.len() {
+                let dirent = FuseDirent::from_bytes(&reply[pos..]);
+                let name_start = pos + size_of::<FuseDirent>();
+                let name = reply
+                    .get(name_start..name_start + dirent.namelen as usize)
+                    .ok_or(VfsError::IoError)?;
+                entries.push(VfsDirEntry {
+                    ino: dirent.ino,
+                    ty: node_type(dirent.ty),
+                    name: String::from_utf8_lossy(name).to_string(),
+                });
+                offset = dirent.off;
+                pos = (name_start + dirent.namelen as usize + 7) & !7;
+            }
+        }
+        Ok(entries)
+    }
+
+    fn mknod(
+        &self,
+        name: &str,
+        ty: VfsNodeType,
+        perm: VfsNodePerm,
+        rdev: u64,
+    ) -> VfsResult<Vec<u8>> {
+        let (major, minor) = ((rdev >> 32) as u32, rdev as u32);
+        let arg = FuseMknodIn {
+            mode: type_to_mode(ty) | perm.bits() as u32,
+            rdev: encode_dev(major, minor),
+            umask: 0,
+            padding: 0,
+        };
+        let size = if self.sb.conn.minor() < 12 {
+            FUSE_COMPAT_MKNOD_IN_SIZE
+        } else {
+            size_of::<FuseMknodIn>()
+        };
+        self.request(FUSE_MKNOD, &[&arg.as_bytes()[..size], &c_name(name)])
+    }
+
+    /// 创建并打开普通文件，打开得到的句柄作为写句柄保存在新的 inode 中。
+    /// 守护进程不支持 `FUSE_CREATE` 时使用 `FUSE_MKNOD`
+    fn create_file(&self, name: &str, perm: VfsNodePerm) -> VfsResult<Arc<FuseInode>> {
+        let arg = FuseCreateIn {
+            flags: O_CREAT | O_EXCL | O_WRONLY,
+            mode: type_to_mode(VfsNodeType::File) | perm.bits() as u32,
+            umask: 0,
+            padding: 0,
+        };
+        let size = if self.sb.conn.minor() < 12 {
+            FUSE_COMPAT_CREATE_IN_SIZE
+        } else {
+            size_of::<FuseCreateIn>()
+        };
+        let reply = match self.request(FUSE_CREATE, &[&arg.as_bytes()[..size], &c_name(name)]) {
+            Err(VfsError::NoSys) => {
+                let reply = self.mknod(name, VfsNodeType::File, perm, 0)?;
+                return self.new_child(&reply);
+            }
+            reply => reply?,
+        };
+        let child = self.new_child(&reply)?;
+        let open = &reply[self.entry_size()..];
+        if open.len() >= size_of::<FuseOpenOut>() {
+            child.handles.lock()[1] = Some(FuseOpenOut::from_bytes(open).fh);
+        }
+        Ok(child)
+    }
+}
+
+impl VfsFile for FuseInode {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let fh = self.handle(false)?;
+        let chunk = self.sb.conn.max_write();
+        let mut count = 0;
+        while count < buf.len() {
+            let size = (buf.len() - count).min(chunk);
+            let arg = FuseReadIn {
+                fh,
+                offset: offset + count as u64,
+                size: size as u32,
+                flags: O_RDONLY,
+                ..Default::default()
+            };
+            let reply = self.request(FUSE_READ, &[arg.as_bytes()])?;
+            let len = reply.len().min(size);
+            buf[count..count + len].copy_from_slice(&reply[..len]);
+            count += len;
+            // 读到的数据少于请求的大小说明已经到达文件末尾
+            if len < size {
+                break;
+            }
+        }
+        Ok(count)
+    }
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let fh = self.handle(true)?;
+        let chunk = self.sb.conn.max_write();
+        let in_size = if self.compat() {
+            FUSE_COMPAT_WRITE_IN_SIZE
+        } else {
+            size_of::<FuseWriteIn>()
+        };
+        let mut count = 0;
+        while count < buf.len() {
+            let data = &buf[count..(count + chunk).min(buf.len())];
+            let arg = FuseWriteIn {
+                fh,
+                offset: offset + count as u64,
+                size: data.len() as u32,
+                flags: O_WRONLY,
+                ..Default::default()
+            };
+            let reply = self.request(FUSE_WRITE, &[&arg.as_bytes()[..in_size], data])?;
+            let written = (FuseWriteOut::from_bytes(&reply).size as usize).min(data.len());
+            count += written;
+            if written < data.len() {
+                break;
+            }
+        }
+        Ok(count)
+    }
+    fn readdir(&self, start_index: usize) -> VfsResult<Option<VfsDirEntry>> {
+        if self.ty != VfsNodeType::Dir {
+            return Err(VfsError::ENOTDIR);
+        }
+        if start_index == 0 {
+            *self.dir_entries.lock() = self.read_dir()?;
+        }
+        Ok(self.dir_entries.lock().get(start_index).cloned())
+    }
+    fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        let mut res = VfsPollEvents::empty();
+        if event.contains(VfsPollEvents::IN) {
+            res |= VfsPollEvents::IN;
+        }
+        if event.contains(VfsPollEvents::OUT) {
+            res |= VfsPollEvents::OUT;
+        }
+        Ok(res)
+    }
+    fn flush(&self) -> VfsResult<()> {
+        let Some(fh) = self.handles.lock()[1] else {
+            return Ok(());
+        };
+        let arg = FuseFlushIn {
+            fh,
+            ..Default::default()
+        };
+        match self.request(FUSE_FLUSH, &[arg.as_bytes()]) {
+            Ok(_) | Err(VfsError::NoSys) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+    fn fsync(&self) -> VfsResult<()> {
+        let Some(fh) = self.handles.lock()[1] else {
+            return Ok(());
+        };
+        let arg = FuseFsyncIn {
+            fh,
+            ..Default::default()
+        };
+        match self.request(FUSE_FSYNC, &[arg.as_bytes()]) {
+            Ok(_) | Err(VfsError::NoSys) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl VfsInode for FuseInode {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Ok(self.sb.clone())
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        self.attr()
+            .map(|attr| VfsNodePerm::from_bits_truncate((attr.mode & 0o777) as u16))
+            .unwrap_or(VfsNodePerm::empty())
+    }
+    fn create(
+        &self,
+        name: &str,
+        ty: VfsNodeType,
+        perm: VfsNodePerm,
+        rdev: Option<u64>,
+    ) -> VfsResult<Arc<dyn VfsInode>> {
+        let child = match ty {
+            VfsNodeType::File => self.create_file(name, perm)?,
+            VfsNodeType::Dir => {
+                let arg = FuseMkdirIn {
+                    mode: type_to_mode(ty) | perm.bits() as u32,
+                    umask: 0,
+                };
+                let reply = self.request(FUSE_MKDIR, &[arg.as_bytes(), &c_name(name)])?;
+                self.new_child(&reply)?
+            }
+            VfsNodeType::SymLink => return Err(VfsError::Invalid),
+            _ => {
+                let reply = self.mknod(name, ty, perm, rdev.unwrap_or(0))?;
+                self.new_child(&reply)?
+            }
+        };
+        Ok(child)
+    }
+    fn link(&self, name: &str, src: Arc<dyn VfsInode>) -> VfsResult<Arc<dyn VfsInode>> {
+        let src = src
+            .downcast_arc::<FuseInode>()
+            .map_err(|_| VfsError::EXDEV)?;
+        if !Arc::ptr_eq(&src.sb, &self.sb) {
+            return Err(VfsError::EXDEV);
+        }
+        let arg = FuseLinkIn {
+            oldnodeid: src.nodeid,
+        };
+        let reply = self.request(FUSE_LINK, &[arg.as_bytes(), &c_name(name)])?;
+        Ok(self.new_child(&reply)?)
+    }
+    fn unlink(&self, name: &str) -> VfsResult<()> {
+        self.request(FUSE_UNLINK, &[&c_name(name)])?;
+        Ok(())
+    }
+    fn symlink(&self, name: &str, sy_name: &str) -> VfsResult<Arc<dyn VfsInode>> {
+        let reply = self.request(FUSE_SYMLINK, &[&c_name(name), &c_name(sy_name)])?;
+        Ok(self.new_child(&reply)?)
+    }
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn VfsInode>> {
+        if self.ty != VfsNodeType::Dir {
+            return Err(VfsError::ENOTDIR);
+        }
+        let reply = self.request(FUSE_LOOKUP, &[&c_name(name)])?;
+        Ok(self.new_child(&reply)?)
+    }
+    fn rmdir(&self, name: &str) -> VfsResult<()> {
+        self.request(FUSE_RMDIR, &[&c_name(name)])?;
+        Ok(())
+    }
+    fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let reply = self.request(FUSE_READLINK, &[])?;
+        let len = reply.len().min(buf.len());
+        buf[..len].copy_from_slice(&reply[..len]);
+        Ok(len)
+    }
+    /// 权限与属主的修改暂不转发给守护进程
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        let attr = self.attr()?;
+        let (major, minor) = decode_dev(attr.rdev);
+        Ok(VfsFileStat {
+            st_ino: attr.ino,
+            st_mode: attr.mode,
+            st_nlink: attr.nlink,
+            st_uid: attr.uid,
+            st_gid: attr.gid,
+            st_rdev: ((major as u64) << 32) | minor as u64,
+            st_size: attr.size,
+            st_blksize: if attr.blksize == 0 {
+                4096
+            } else {
+                attr.blksize
+            },
+            st_blocks: attr.blocks,
+            st_atime: VfsTimeSpec::new(attr.atime, attr.atimensec as u64),
+            st_mtime: VfsTimeSpec::new(attr.mtime, attr.mtimensec as u64),
+            st_ctime: VfsTimeSpec::new(attr.ctime, attr.ctimensec as u64),
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        self.ty
+    }
+    fn truncate(&self, len: u64) -> VfsResult<()> {
+        self.set_attr_with(FuseSetattrIn {
+            valid: FATTR_SIZE,
+            size: len,
+            ..Default::default()
+        })
+    }
+    fn rename_to(
+        &self,
+        old_name: &str,
+        new_parent: Arc<dyn VfsInode>,
+        new_name: &str,
+        flag: VfsRenameFlag,
+    ) -> VfsResult<()> {
+        let new_parent = new_parent
+            .downcast_arc::<FuseInode>()
+            .map_err(|_| VfsError::EXDEV)?;
+        if !Arc::ptr_eq(&new_parent.sb, &self.sb) {
+            return Err(VfsError::EXDEV);
+        }
+        let names = [c_name(old_name), c_name(new_name)];
+        if flag.is_empty() {
+            let arg = FuseRenameIn {
+                newdir: new_parent.nodeid,
+            };
+            self.request(FUSE_RENAME, &[arg.as_bytes(), &names[0], &names[1]])?;
+        } else {
+            let arg = FuseRename2In {
+                newdir: new_parent.nodeid,
+                flags: flag.bits(),
+                padding: 0,
+            };
+            self.request(FUSE_RENAME2, &[arg.as_bytes(), &names[0], &names[1]])?;
+        }
+        Ok(())
+    }
+    fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
+        let mut arg = FuseSetattrIn::default();
+        match time {
+            VfsTime::AccessTime(t) => {
+                arg.valid = FATTR_ATIME;
+                arg.atime = t.sec;
+                arg.atimensec = t.nsec as u32;
+            }
+            VfsTime::ModifiedTime(t) => {
+                arg.valid = FATTR_MTIME;
+                arg.mtime = t.sec;
+                arg.mtimensec = t.nsec as u32;
+            }
+        }
+        arg.ctime = now.sec;
+        arg.ctimensec = now.nsec as u32;
+        self.set_attr_with(arg)
+    }
+}
+
+impl Drop for FuseInode {
+    fn drop(&mut self) {
+        let handles = *self.handles.get_mut();
+        for (fh, flags) in handles.into_iter().zip([O_RDONLY, O_WRONLY]) {
+            if let Some(fh) = fh {
+                let arg = FuseReleaseIn {
+                    fh,
+                    flags,
+                    ..Default::default()
+                };
+                self.sb
+                    .conn
+                    .request_background(FUSE_RELEASE, self.nodeid, &[arg.as_bytes()]);
+            }
+        }
+        // 根目录的查找计数由守护进程自己维护
+        if self.nodeid != FUSE_ROOT_ID {
+            self.sb.conn.forget(self.nodeid, 1);
+        }
+    }
+}
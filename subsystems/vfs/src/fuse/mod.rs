@@ -0,0 +1,150 @@
+//! 用户态文件系统(FUSE)。
+//!
+//! 文件系统的操作以 FUSE 协议的消息转发给用户态的守护进程，协议与 Linux 相同，
+//! 可以直接运行基于 libfuse 或 `fuser` 编写的文件系统。守护进程打开 `/dev/fuse` 后挂载：
+//!
+//! `mount("fuse", "/mnt", "fuse.myfs", 0, "fd=3,rootmode=40000,user_id=0,group_id=0")`
+//!
+//! 挂载不会等待守护进程完成初始化，之后的请求在初始化完成前阻塞。
+//! 卸载后连接在所有 inode 释放时断开，守护进程的读取返回 `ENODEV`。
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+};
+
+use constants::{io::MountFlags, AlienResult, LinuxErrno};
+use spin::Once;
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    fstype::{FileSystemFlags, VfsFsType},
+    inode::VfsInode,
+    path::VfsPath,
+    superblock::{SuperType, VfsSuperBlock},
+    utils::{VfsFsStat, VfsNodeType},
+    VfsResult,
+};
+
+pub use self::dev::{is_fuse_device, FuseDevFile, FuseDevice};
+use self::{
+    abi::{mode_to_type, FUSE_ROOT_ID},
+    conn::FuseConn,
+    inode::FuseInode,
+};
+use crate::{dentry::GenericDentry, kfile::File, mount::record_mount, system_support_fs};
+
+mod abi;
+mod conn;
+mod dev;
+mod inode;
+
+pub struct FuseFs;
+
+impl VfsFsType for FuseFs {
+    /// 挂载需要调用者打开的 `/dev/fuse`，只能通过 [`do_mount`] 完成
+    fn mount(
+        self: Arc<Self>,
+        _flags: u32,
+        _ab_mnt: &str,
+        _dev: Option<Arc<dyn VfsInode>>,
+        _data: &[u8],
+    ) -> VfsResult<Arc<dyn VfsDentry>> {
+        Err(VfsError::Invalid)
+    }
+    fn kill_sb(&self, _sb: Arc<dyn VfsSuperBlock>) -> VfsResult<()> {
+        Ok(())
+    }
+    fn fs_flag(&self) -> FileSystemFlags {
+        FileSystemFlags::empty()
+    }
+    fn fs_name(&self) -> String {
+        "fuse".to_string()
+    }
+}
+
+pub struct FuseSuperBlock {
+    fs_type: Arc<dyn VfsFsType>,
+    conn: Arc<FuseConn>,
+    /// 根目录的 inode 持有超级块，这里只保存弱引用
+    root: Once<Weak<FuseInode>>,
+}
+
+impl VfsSuperBlock for FuseSuperBlock {
+    fn sync_fs(&self, _wait: bool) -> VfsResult<()> {
+        Ok(())
+    }
+    fn stat_fs(&self) -> VfsResult<VfsFsStat> {
+        Err(VfsError::NoSys)
+    }
+    fn super_type(&self) -> SuperType {
+        SuperType::Independent
+    }
+    fn fs_type(&self) -> Arc<dyn VfsFsType> {
+        self.fs_type.clone()
+    }
+    fn root_inode(&self) -> VfsResult<Arc<dyn VfsInode>> {
+        let root = self.root.get().and_then(|root| root.upgrade());
+        root.map(|root| root as Arc<dyn VfsInode>)
+            .ok_or(VfsError::Invalid)
+    }
+}
+
+impl Drop for FuseSuperBlock {
+    fn drop(&mut self) {
+        self.conn.abort();
+    }
+}
+
+/// `fuse` 与 `fuse.<子类型>` 都是用户态文件系统
+pub fn is_fuse_type(fstype: &str) -> bool {
+    fstype == "fuse" || fstype.starts_with("fuse.")
+}
+
+fn option<'a>(data: &'a str, key: &str) -> Option<&'a str> {
+    data.split(',')
+        .find_map(|option| option.split_once('=').filter(|(k, _)| *k == key))
+        .map(|(_, value)| value)
+}
+
+/// 挂载选项 `fd=` 给出的 `/dev/fuse` 文件描述符
+pub fn device_fd(data: &str) -> AlienResult<usize> {
+    option(data, "fd")
+        .and_then(|fd| fd.parse().ok())
+        .ok_or(LinuxErrno::EINVAL)
+}
+
+/// 使用 `dev` 对应的连接在 `target` 上挂载用户态文件系统，`rootmode=` 给出根目录的类型
+pub fn do_mount(
+    path: &VfsPath,
+    source: &str,
+    target: &str,
+    fstype: &str,
+    flags: MountFlags,
+    data: &str,
+    dev: Arc<dyn File>,
+) -> AlienResult<()> {
+    let dev = dev
+        .downcast_arc::<FuseDevFile>()
+        .map_err(|_| LinuxErrno::EINVAL)?;
+    let rootmode = option(data, "rootmode")
+        .and_then(|mode| u32::from_str_radix(mode, 8).ok())
+        .ok_or(LinuxErrno::EINVAL)?;
+    if mode_to_type(rootmode) != VfsNodeType::Dir {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let fs_type = system_support_fs("fuse").ok_or(LinuxErrno::ENODEV)?;
+    let conn = dev.conn();
+    conn.mount()?;
+    let sb = Arc::new(FuseSuperBlock {
+        fs_type,
+        conn,
+        root: Once::new(),
+    });
+    let root = FuseInode::new(sb.clone(), FUSE_ROOT_ID, VfsNodeType::Dir);
+    sb.root.call_once(|| Arc::downgrade(&root));
+    let fs_root: Arc<dyn VfsDentry> = GenericDentry::root(root);
+    // 挂载失败时根目录随之释放，连接断开
+    path.join(target)?.mount(fs_root.clone(), flags.bits())?;
+    record_mount(source, target, fstype, flags, data, fs_root, None);
+    Ok(())
+}
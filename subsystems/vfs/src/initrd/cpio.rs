@@ -0,0 +1,93 @@
+//! newc 格式(`070701`/`070702`)的 cpio 归档。
+//!
+//! 每个成员由 110 字节的头部、文件名与文件内容组成，文件名与内容分别对齐到 4 字节。
+//! 头部中的数值均为 8 位十六进制数。名为 `TRAILER!!!` 的成员表示归档结束。
+
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFSOCK: u32 = 0o140000;
+pub const S_IFLNK: u32 = 0o120000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFBLK: u32 = 0o060000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFIFO: u32 = 0o010000;
+
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+/// 数据是否以 newc 格式的 cpio 头部开始
+pub fn is_cpio(data: &[u8]) -> bool {
+    data.starts_with(b"070701") || data.starts_with(b"070702")
+}
+
+/// 归档中的一个成员
+pub struct Entry<'a> {
+    pub ino: u32,
+    pub mode: u32,
+    pub nlink: u32,
+    pub mtime: u32,
+    pub dev_major: u32,
+    pub dev_minor: u32,
+    pub rdev_major: u32,
+    pub rdev_minor: u32,
+    pub name: &'a str,
+    pub data: &'a [u8],
+}
+
+impl Entry<'_> {
+    pub fn file_type(&self) -> u32 {
+        self.mode & S_IFMT
+    }
+    pub fn is_trailer(&self) -> bool {
+        self.name == TRAILER
+    }
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+fn hex_field(header: &[u8], index: usize) -> Result<u32, &'static str> {
+    let start = 6 + index * 8;
+    let field = core::str::from_utf8(&header[start..start + 8]).map_err(|_| "broken header")?;
+    u32::from_str_radix(field, 16).map_err(|_| "broken header")
+}
+
+/// 解析 `data` 开头的一个成员，返回成员与它占用的字节数
+pub fn parse_entry(data: &[u8]) -> Result<(Entry, usize), &'static str> {
+    if data.len() < HEADER_SIZE {
+        return Err("truncated cpio header");
+    }
+    if !is_cpio(data) {
+        return Err("no cpio magic");
+    }
+    let header = &data[..HEADER_SIZE];
+    let field = |index| hex_field(header, index);
+    let file_size = field(6)? as usize;
+    let name_size = field(11)? as usize;
+    if name_size == 0 {
+        return Err("empty file name");
+    }
+    let name_end = HEADER_SIZE + name_size;
+    let data_start = align4(name_end);
+    let data_end = data_start + file_size;
+    if data.len() < data_end {
+        return Err("truncated cpio archive");
+    }
+    // 文件名以 '\0' 结尾
+    let name = core::str::from_utf8(&data[HEADER_SIZE..name_end - 1])
+        .map_err(|_| "file name is not valid utf-8")?;
+    let entry = Entry {
+        ino: field(0)?,
+        mode: field(1)?,
+        nlink: field(4)?,
+        mtime: field(5)?,
+        dev_major: field(7)?,
+        dev_minor: field(8)?,
+        rdev_major: field(9)?,
+        rdev_minor: field(10)?,
+        name,
+        data: &data[data_start..data_end],
+    };
+    Ok((entry, align4(data_end).min(data.len())))
+}
@@ -0,0 +1,62 @@
+//! 根据魔数识别 initramfs 的压缩格式并解压。
+//!
+//! gzip 与 zstd 使用外部的解压库，lz4 与 xz 的解码器在 `lzdecode` 中。
+use alloc::{vec, vec::Vec};
+
+use core2::io::Read;
+use lzdecode::{lz4, xz};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// 旧版本 gzip 使用的魔数
+const GZIP_OLD_MAGIC: [u8; 2] = [0x1f, 0x9e];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// 解压 `data` 开头的压缩数据，返回解压后的内容与压缩数据占用的字节数
+pub fn decompress(data: &[u8]) -> Result<(Vec<u8>, usize), &'static str> {
+    if data.starts_with(&GZIP_MAGIC) || data.starts_with(&GZIP_OLD_MAGIC) {
+        gunzip(data)
+    } else if data.starts_with(&ZSTD_MAGIC) {
+        unzstd(data)
+    } else if data.starts_with(&xz::XZ_MAGIC) {
+        xz::unxz(data)
+    } else if data.starts_with(&lz4::LEGACY_MAGIC.to_le_bytes()) {
+        lz4::unlz4_legacy(data)
+    } else if data.starts_with(&lz4::FRAME_MAGIC.to_le_bytes()) {
+        lz4::unlz4_frame(data)
+    } else {
+        Err("invalid magic at start of compressed archive")
+    }
+}
+
+/// gzip 成员的末尾没有长度信息，解压器会读取所有拼接在一起的成员，因此 gzip 压缩的归档只能位于最后
+fn gunzip(data: &[u8]) -> Result<(Vec<u8>, usize), &'static str> {
+    let mut decoder = libflate::gzip::MultiDecoder::new(data).map_err(|_| "invalid gzip header")?;
+    let mut out = vec![];
+    decoder
+        .read_to_end(&mut out)
+        .map_err(|_| "corrupted gzip data")?;
+    Ok((out, data.len()))
+}
+
+fn unzstd(data: &[u8]) -> Result<(Vec<u8>, usize), &'static str> {
+    use ruzstd::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    let mut source = data;
+    let mut decoder = FrameDecoder::new();
+    let mut out = vec![];
+    // 多个 zstd 帧可以直接拼接
+    while source.starts_with(&ZSTD_MAGIC) {
+        decoder
+            .reset(&mut source)
+            .map_err(|_| "invalid zstd frame header")?;
+        decoder
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .map_err(|_| "corrupted zstd data")?;
+        if !decoder.is_finished() {
+            return Err("truncated zstd data");
+        }
+        if let Some(content) = decoder.collect() {
+            out.extend_from_slice(&content);
+        }
+    }
+    Ok((out, data.len() - source.len()))
+}
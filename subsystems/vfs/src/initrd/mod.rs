@@ -0,0 +1,188 @@
+//! initramfs 的解包。
+//!
+//! initramfs 由一个或多个 newc 格式的 cpio 归档拼接而成，每个归档可以不压缩，也可以使用
+//! gzip/zstd/lz4/xz 压缩，归档之间允许出现用于对齐的 0。解包的行为与 Linux 一致：
+//! 归档格式错误时停止解包并报告错误，单个成员创建失败时只输出警告并继续解包后面的成员。
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+use constants::{AlienResult, DeviceId, LinuxErrno};
+use vfscore::{
+    dentry::VfsDentry,
+    path::VfsPath,
+    utils::{VfsInodeMode, VfsNodePerm, VfsNodeType, VfsTime, VfsTimeSpec},
+};
+
+use self::cpio::Entry;
+
+mod cpio;
+mod decompress;
+
+/// 解包 bootloader 加载的 initramfs 到 `root`，完成后释放 initramfs 占用的内存
+pub fn populate_initrd(root: Arc<dyn VfsDentry>) -> AlienResult<()> {
+    // 解包期间不持有锁，initramfs 在函数返回时被释放
+    let Some(initrd) = mem::data::INITRD_DATA.lock().take() else {
+        return Ok(());
+    };
+    let data = unsafe { core::slice::from_raw_parts(initrd.data_ptr as *const u8, initrd.size) };
+    let mut unpacker = Unpacker::new(root);
+    let result = unpacker.unpack(data, true);
+    unpacker.finish();
+    match result {
+        Ok(()) => println!("Initrd populate success"),
+        Err(msg) => println!("Initramfs unpacking failed: {}", msg),
+    }
+    Ok(())
+}
+
+struct Unpacker {
+    path: VfsPath,
+    /// 当前归档中已经创建的硬链接文件，键为 (dev_major, dev_minor, ino)
+    links: BTreeMap<(u32, u32, u32), String>,
+    /// 目录的修改时间在目录中的成员创建完成后才能设置
+    dirs: Vec<(Arc<dyn VfsDentry>, u32)>,
+}
+
+impl Unpacker {
+    fn new(root: Arc<dyn VfsDentry>) -> Self {
+        Self {
+            path: VfsPath::new(root.clone(), root),
+            links: BTreeMap::new(),
+            dirs: Vec::new(),
+        }
+    }
+
+    /// 依次解包拼接在一起的归档。压缩数据解压后的内容只能是 cpio 归档
+    fn unpack(&mut self, mut data: &[u8], allow_compressed: bool) -> Result<(), &'static str> {
+        loop {
+            let padding = data.iter().position(|&b| b != 0).unwrap_or(data.len());
+            data = &data[padding..];
+            if data.is_empty() {
+                return Ok(());
+            }
+            if cpio::is_cpio(data) {
+                let used = self.extract_archive(data)?;
+                data = &data[used..];
+            } else if allow_compressed {
+                let (content, used) = decompress::decompress(data)?;
+                self.unpack(&content, false)?;
+                data = &data[used..];
+            } else {
+                return Err("junk within compressed archive");
+            }
+        }
+    }
+
+    /// 解包一个 cpio 归档，返回归档占用的字节数
+    fn extract_archive(&mut self, data: &[u8]) -> Result<usize, &'static str> {
+        let mut offset = 0;
+        loop {
+            let (entry, used) = cpio::parse_entry(&data[offset..])?;
+            offset += used;
+            if entry.is_trailer() {
+                // 硬链接只在同一个归档内有效
+                self.links.clear();
+                return Ok(offset);
+            }
+            if let Err(e) = self.extract_entry(&entry) {
+                println!("initramfs: failed to extract {}: {:?}", entry.name, e);
+            }
+        }
+    }
+
+    fn extract_entry(&mut self, entry: &Entry) -> AlienResult<()> {
+        let name = entry.name.trim_start_matches("./").trim_start_matches('/');
+        if name.is_empty() || name == "." {
+            return Ok(());
+        }
+        let path = self.path.join(name)?;
+        let perm = VfsNodePerm::from_bits_truncate((entry.mode & 0o7777) as u16);
+        match entry.file_type() {
+            cpio::S_IFDIR => {
+                let dentry = match path.open(None) {
+                    Ok(dentry) if dentry.inode()?.inode_type() == VfsNodeType::Dir => dentry,
+                    _ => {
+                        remove_existing(&path);
+                        path.open(Some(VfsInodeMode::from_bits_truncate(entry.mode)))?
+                    }
+                };
+                self.dirs.push((dentry, entry.mtime));
+            }
+            cpio::S_IFREG => {
+                let key = (entry.dev_major, entry.dev_minor, entry.ino);
+                let linked = match self.links.get(&key) {
+                    Some(first) if entry.nlink > 1 => {
+                        let old = self.path.join(first)?.open(None)?;
+                        remove_existing(&path);
+                        path.link(old)?;
+                        true
+                    }
+                    _ => false,
+                };
+                if !linked {
+                    remove_existing(&path);
+                    if entry.nlink > 1 {
+                        self.links.insert(key, String::from(name));
+                    }
+                }
+                let dentry = path.open(Some(VfsInodeMode::from_bits_truncate(entry.mode)))?;
+                let inode = dentry.inode()?;
+                if !entry.data.is_empty() {
+                    inode.write_at(0, entry.data)?;
+                }
+                set_mtime(&dentry, entry.mtime)?;
+            }
+            cpio::S_IFLNK => {
+                let target = core::str::from_utf8(entry.data).map_err(|_| LinuxErrno::EINVAL)?;
+                remove_existing(&path);
+                path.symlink(target)?;
+            }
+            ty @ (cpio::S_IFCHR | cpio::S_IFBLK | cpio::S_IFIFO | cpio::S_IFSOCK) => {
+                let node_type = match ty {
+                    cpio::S_IFCHR => VfsNodeType::CharDevice,
+                    cpio::S_IFBLK => VfsNodeType::BlockDevice,
+                    cpio::S_IFIFO => VfsNodeType::Fifo,
+                    _ => VfsNodeType::Socket,
+                };
+                let rdev = DeviceId::new(entry.rdev_major, entry.rdev_minor).id();
+                let (parent, base) = match name.rsplit_once('/') {
+                    Some((parent, base)) => (self.path.join(parent)?.open(None)?, base),
+                    None => (self.path.open(None)?, name),
+                };
+                remove_existing(&path);
+                parent.inode()?.create(base, node_type, perm, Some(rdev))?;
+            }
+            _ => println!(
+                "initramfs: skip {} with unknown file type {:#o}",
+                name, entry.mode
+            ),
+        }
+        Ok(())
+    }
+
+    /// 所有归档解包完成后设置目录的修改时间
+    fn finish(&mut self) {
+        for (dentry, mtime) in self.dirs.drain(..) {
+            let _ = set_mtime(&dentry, mtime);
+        }
+    }
+}
+
+/// 删除已经存在的非目录文件，使归档中后出现的同名成员覆盖先出现的成员
+fn remove_existing(path: &VfsPath) {
+    if let Ok(dentry) = path.open(None) {
+        if dentry
+            .inode()
+            .is_ok_and(|inode| inode.inode_type() != VfsNodeType::Dir)
+        {
+            let _ = path.unlink();
+        }
+    }
+}
+
+fn set_mtime(dentry: &Arc<dyn VfsDentry>, mtime: u32) -> AlienResult<()> {
+    let time = VfsTimeSpec::new(mtime as u64, 0);
+    dentry
+        .inode()?
+        .update_time(VfsTime::ModifiedTime(time), time)?;
+    Ok(())
+}
@@ -6,7 +6,7 @@ use constants::{
     AlienResult, LinuxErrno,
 };
 use downcast_rs::{impl_downcast, DowncastSync};
-use ksync::Mutex;
+use ksync::{Mutex, SleepMutex};
 use vfscore::{
     dentry::VfsDentry,
     error::VfsError,
@@ -18,7 +18,8 @@ use vfscore::{
 use crate::system_root_fs;
 
 pub struct KernelFile {
-    pos: Mutex<u64>,
+    /// 文件偏移。读写时需要在等待磁盘 I/O 期间持有，因此使用可睡眠的锁
+    pos: SleepMutex<u64>,
     open_flag: Mutex<OpenFlags>,
     dentry: Arc<dyn VfsDentry>,
 }
@@ -41,7 +42,7 @@ impl KernelFile {
             0
         };
         Self {
-            pos: Mutex::new(pos),
+            pos: SleepMutex::new(pos),
             open_flag: Mutex::new(open_flag),
             dentry,
         }
@@ -6,27 +6,42 @@ extern crate alloc;
 extern crate platform;
 use alloc::{
     collections::BTreeMap,
+    format,
     string::{String, ToString},
-    sync::Arc,
+    sync::{Arc, Weak},
+    vec::Vec,
 };
 use core::ops::Index;
 
-use constants::AlienResult;
+use constants::{io::MountFlags, AlienResult, LinuxErrno};
 use dynfs::DynFsKernelProvider;
 use ksync::Mutex;
+use platform::cmdline::Cmdline;
 use spin::{Lazy, Once};
-#[cfg(feature = "ext")]
-use vfscore::inode::VfsInode;
-use vfscore::{dentry::VfsDentry, fstype::VfsFsType, path::VfsPath, utils::VfsTimeSpec};
+use vfscore::{
+    dentry::VfsDentry,
+    fstype::VfsFsType,
+    inode::VfsInode,
+    path::VfsPath,
+    superblock::VfsSuperBlock,
+    utils::{VfsInodeMode, VfsNodeType, VfsTimeSpec},
+};
 
 use crate::dev::DevFsProviderImpl;
+pub mod cgroup;
+mod dentry;
 pub mod dev;
 pub mod epoll;
 pub mod eventfd;
 #[cfg(feature = "ext")]
 mod extffi;
+pub mod fuse;
 mod initrd;
 pub mod kfile;
+pub mod mount;
+pub mod mqueue;
+pub mod overlay;
+pub mod p9;
 pub mod pipefs;
 pub mod proc;
 pub mod ram;
@@ -38,18 +53,23 @@ pub static FS: Lazy<Mutex<BTreeMap<String, Arc<dyn VfsFsType>>>> =
 
 static SYSTEM_ROOT_FS: Once<Arc<dyn VfsDentry>> = Once::new();
 
+/// 建立在块设备上的文件系统，`sync` 时需要将它们的元数据写回块设备缓存
+static DISK_SUPER_BLOCKS: Mutex<Vec<Weak<dyn VfsSuperBlock>>> = Mutex::new(Vec::new());
+
 type SysFs = dynfs::DynFs<CommonFsProviderImpl, spin::Mutex<()>>;
 type ProcFs = dynfs::DynFs<CommonFsProviderImpl, spin::Mutex<()>>;
 type RamFs = ramfs::RamFs<CommonFsProviderImpl, spin::Mutex<()>>;
 type DevFs = devfs::DevFs<DevFsProviderImpl, spin::Mutex<()>>;
 type TmpFs = ramfs::RamFs<CommonFsProviderImpl, spin::Mutex<()>>;
 type PipeFs = dynfs::DynFs<CommonFsProviderImpl, spin::Mutex<()>>;
+type MqueueFs = dynfs::DynFs<CommonFsProviderImpl, spin::Mutex<()>>;
+type CgroupFs = dynfs::DynFs<CommonFsProviderImpl, spin::Mutex<()>>;
 
 #[cfg(feature = "fat")]
-type DiskFs = fat_vfs::FatFs<CommonFsProviderImpl, spin::Mutex<()>>;
+type FatFs = fat_vfs::FatFs<CommonFsProviderImpl, ksync::RawSleepMutex>;
 
 #[cfg(feature = "ext")]
-type DiskFs = lwext4_vfs::ExtFs<CommonFsProviderImpl, spin::Mutex<()>>;
+type ExtFs = lwext4_vfs::ExtFs<CommonFsProviderImpl, ksync::RawSleepMutex>;
 
 #[derive(Clone)]
 pub struct CommonFsProviderImpl;
@@ -90,6 +110,11 @@ fn register_all_fs() {
     let devfs = Arc::new(DevFs::new(DevFsProviderImpl));
     let tmpfs = Arc::new(TmpFs::new(CommonFsProviderImpl));
     let pipefs = Arc::new(PipeFs::new(CommonFsProviderImpl, "pipefs"));
+    let mqueuefs = Arc::new(MqueueFs::new(CommonFsProviderImpl, "mqueue"));
+    let cgroupfs = Arc::new(CgroupFs::new(CommonFsProviderImpl, "cgroup2"));
+    let overlayfs = Arc::new(overlay::OverlayFs);
+    let fusefs = Arc::new(fuse::FuseFs);
+    let p9fs = Arc::new(p9::P9Fs);
 
     FS.lock().insert("procfs".to_string(), procfs);
     FS.lock().insert("sysfs".to_string(), sysfs);
@@ -97,21 +122,34 @@ fn register_all_fs() {
     FS.lock().insert("devfs".to_string(), devfs);
     FS.lock().insert("tmpfs".to_string(), tmpfs);
     FS.lock().insert("pipefs".to_string(), pipefs);
+    FS.lock().insert("mqueue".to_string(), mqueuefs);
+    FS.lock().insert("cgroup2".to_string(), cgroupfs);
+    FS.lock().insert("overlay".to_string(), overlayfs);
+    FS.lock().insert("fuse".to_string(), fusefs);
+    FS.lock().insert("9p".to_string(), p9fs);
 
+    // 磁盘文件系统可以同时启用，挂载时按名字选择或者由 `mount::probe_fs` 识别
     #[cfg(feature = "fat")]
-    let diskfs = Arc::new(DiskFs::new(CommonFsProviderImpl));
+    FS.lock().insert(
+        "vfat".to_string(),
+        Arc::new(FatFs::new(CommonFsProviderImpl)),
+    );
     #[cfg(feature = "ext")]
-    let diskfs = Arc::new(DiskFs::new(
-        lwext4_vfs::ExtFsType::Ext4,
-        CommonFsProviderImpl,
-    ));
-
-    FS.lock().insert("diskfs".to_string(), diskfs);
+    FS.lock().insert(
+        "ext4".to_string(),
+        Arc::new(ExtFs::new(
+            lwext4_vfs::ExtFsType::Ext4,
+            CommonFsProviderImpl,
+        )),
+    );
 
     println!("register fs success");
 }
 
 /// Init the filesystem
+///
+/// 命令行指定了 `root=` 时挂载该块设备作为根文件系统，挂载失败或者没有指定时以 initramfs 作为根文件系统，
+/// 并尽量把第一个磁盘挂载到 `/tests`，挂载失败时只打印错误
 pub fn init_filesystem() -> AlienResult<()> {
     register_all_fs();
     let ramfs_root = ram::init_ramfs(FS.lock().index("ramfs").clone());
@@ -126,41 +164,151 @@ pub fn init_filesystem() -> AlienResult<()> {
         .i_mount(0, "/tmp", None, &[])?;
 
     pipefs::init_pipefs(FS.lock().index("pipefs").clone());
-
-    let path = VfsPath::new(ramfs_root.clone(), ramfs_root.clone());
-    path.join("proc")?.mount(procfs_root, 0)?;
-    path.join("sys")?.mount(sysfs_root, 0)?;
-    path.join("dev")?.mount(devfs_root, 0)?;
-    path.join("tmp")?.mount(tmpfs_root.clone(), 0)?;
-
+    let mqueue_root = mqueue::init_mqueuefs(FS.lock().index("mqueue").clone());
+    let cgroup_root = cgroup::init_cgroupfs(FS.lock().index("cgroup2").clone());
     let shm_ramfs = FS
         .lock()
         .index("ramfs")
         .clone()
         .i_mount(0, "/dev/shm", None, &[])?;
-    path.join("dev/shm")?.mount(shm_ramfs, 0)?;
 
-    let diskfs = FS.lock().index("diskfs").clone();
-    let blk_inode = path
-        .join("/dev/sda")?
-        .open(None)
-        .expect("open /dev/sda failed")
-        .inode()?;
+    let cmdline = platform::cmdline::cmdline();
+    let disk_root =
+        cmdline
+            .root
+            .as_ref()
+            .and_then(|root| match mount_root(&devfs_root, root, cmdline) {
+                Ok(disk_root) => Some(disk_root),
+                Err(e) => {
+                    println!(
+                        "mount root {} failed: {:?}, fall back to initramfs",
+                        root, e
+                    );
+                    None
+                }
+            });
+    let root = disk_root.clone().unwrap_or_else(|| ramfs_root.clone());
 
-    let diskfs_root = diskfs.i_mount(0, "/tests", Some(blk_inode), &[])?;
-    path.join("tests")?.mount(diskfs_root, 0)?;
-    println!("mount fs success");
+    let no_flags = MountFlags::empty();
+    let special = [
+        ("proc", "/proc", "procfs", procfs_root),
+        ("sysfs", "/sys", "sysfs", sysfs_root),
+        ("cgroup2", "/sys/fs/cgroup", "cgroup2", cgroup_root),
+        ("devfs", "/dev", "devfs", devfs_root),
+        ("tmpfs", "/tmp", "tmpfs", tmpfs_root),
+        ("shm", "/dev/shm", "ramfs", shm_ramfs),
+        ("mqueue", "/dev/mqueue", "mqueue", mqueue_root),
+    ];
+    let path = VfsPath::new(root.clone(), root.clone());
+    for (source, target, fstype, fs_root) in special {
+        let dir = path.join(target)?;
+        if dir.open(None).is_err() {
+            // 磁盘上的根文件系统可能没有这些目录
+            if let Err(e) = dir.open(Some(VfsInodeMode::from_bits_truncate(0o40755))) {
+                println!("skip mounting {}: {:?}", target, e);
+                continue;
+            }
+        }
+        dir.mount(fs_root.clone(), 0)?;
+        mount::record_mount(source, target, fstype, no_flags, "", fs_root, None);
+    }
 
-    vfscore::path::print_fs_tree(&mut VfsOutPut, ramfs_root.clone(), "".to_string(), false)
-        .unwrap();
+    if disk_root.is_none() {
+        mount::record_mount(
+            "rootfs",
+            "/",
+            "ramfs",
+            no_flags,
+            "",
+            ramfs_root.clone(),
+            None,
+        );
+        // 没有磁盘或者磁盘上没有可识别的文件系统时不挂载 `/tests`
+        if let Err(e) = mount_tests(&path) {
+            println!("skip mounting /tests: {:?}", e);
+        }
+        initrd::populate_initrd(ramfs_root.clone())?;
+    } else {
+        // 不使用 initramfs 时直接释放它占用的内存
+        mem::data::INITRD_DATA.lock().take();
+    }
+    println!("mount fs success");
 
-    initrd::populate_initrd(ramfs_root.clone())?;
+    vfscore::path::print_fs_tree(&mut VfsOutPut, root.clone(), "".to_string(), false).unwrap();
 
-    SYSTEM_ROOT_FS.call_once(|| ramfs_root);
+    SYSTEM_ROOT_FS.call_once(|| root);
     println!("Init filesystem success");
     Ok(())
 }
 
+/// 挂载命令行中 `root=` 指定的块设备，返回其根目录。
+///
+/// 设备可以写作 `/dev/vda2` 或 `vda2`，文件系统类型由 `rootfstype=` 指定或根据超级块识别
+fn mount_root(
+    devfs_root: &Arc<dyn VfsDentry>,
+    root: &str,
+    cmdline: &Cmdline,
+) -> AlienResult<Arc<dyn VfsDentry>> {
+    let name = root.trim_start_matches("/dev/");
+    let dev = VfsPath::new(devfs_root.clone(), devfs_root.clone())
+        .join(name)?
+        .open(None)?
+        .inode()?;
+    if dev.inode_type() != VfsNodeType::BlockDevice {
+        return Err(LinuxErrno::ENOTBLK);
+    }
+    let fstype = match cmdline.rootfstype.as_deref() {
+        Some(fstype) => fstype,
+        None => mount::probe_fs(&dev).ok_or(LinuxErrno::EINVAL)?,
+    };
+    let fs = system_support_fs(fstype).ok_or(LinuxErrno::ENODEV)?;
+    let mut flags = MountFlags::empty();
+    flags.set(MountFlags::MS_RDONLY, cmdline.readonly);
+    let data = cmdline.rootflags.as_deref().unwrap_or("");
+    let disk_root = fs.i_mount(flags.bits(), "/", Some(dev.clone()), data.as_bytes())?;
+    register_disk_fs(&disk_root.inode()?.get_super_block()?);
+    let source = format!("/dev/{}", name);
+    mount::record_mount(
+        &source,
+        "/",
+        fstype,
+        flags,
+        data,
+        disk_root.clone(),
+        Some(&dev),
+    );
+    println!("mount {} ({}) as root", source, fstype);
+    Ok(disk_root)
+}
+
+/// 第一个探测到的磁盘存放测试程序，整个磁盘就是一个文件系统，挂载到 initramfs 的 `/tests`。
+///
+/// 没有块设备时返回 `ENODEV`，无法识别磁盘上的文件系统时返回 `EINVAL`
+fn mount_tests(path: &VfsPath) -> AlienResult<()> {
+    let (disk, _) = devices::block_devices()
+        .into_iter()
+        .next()
+        .ok_or(LinuxErrno::ENODEV)?;
+    let disk = format!("/dev/{}", disk);
+    let blk_inode = path.join(&disk)?.open(None)?.inode()?;
+    let fstype = mount::probe_fs(&blk_inode).ok_or(LinuxErrno::EINVAL)?;
+    let diskfs = FS.lock().index(fstype).clone();
+
+    let diskfs_root = diskfs.i_mount(0, "/tests", Some(blk_inode.clone()), &[])?;
+    register_disk_fs(&diskfs_root.inode()?.get_super_block()?);
+    path.join("tests")?.mount(diskfs_root.clone(), 0)?;
+    mount::record_mount(
+        &disk,
+        "/tests",
+        fstype,
+        MountFlags::empty(),
+        "",
+        diskfs_root,
+        Some(&blk_inode),
+    );
+    Ok(())
+}
+
 struct VfsOutPut;
 impl core::fmt::Write for VfsOutPut {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
@@ -186,3 +334,28 @@ pub fn system_support_fs(fs_name: &str) -> Option<Arc<dyn VfsFsType>> {
         }
     })
 }
+
+/// 登记一个建立在块设备上的文件系统，使其参与 [`sync_filesystems`]
+pub fn register_disk_fs(sb: &Arc<dyn VfsSuperBlock>) {
+    let mut sbs = DISK_SUPER_BLOCKS.lock();
+    sbs.retain(|sb| sb.strong_count() > 0);
+    sbs.push(Arc::downgrade(sb));
+}
+
+/// 同步所有已登记的磁盘文件系统，并将块设备缓存中的脏页写回磁盘。
+///
+/// 某个文件系统同步失败时仍会继续同步其余文件系统，最后返回遇到的第一个错误
+pub fn sync_filesystems() -> AlienResult<()> {
+    let sbs = DISK_SUPER_BLOCKS
+        .lock()
+        .iter()
+        .filter_map(|sb| sb.upgrade())
+        .collect::<Vec<_>>();
+    let mut res: AlienResult<()> = Ok(());
+    for sb in sbs {
+        if let Err(e) = sb.sync_fs(true) {
+            res = res.and(Err(e.into()));
+        }
+    }
+    res.and(devices::flush_block_devices())
+}
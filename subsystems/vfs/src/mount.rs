@@ -0,0 +1,368 @@
+//! 挂载表。
+//!
+//! 记录每次挂载的来源、挂载点、文件系统类型与挂载选项，用于重新挂载、只读检查以及生成 `/proc/mounts`。
+//!
+//! 只读状态分为两级：挂载项自身的 `MS_RDONLY` 只影响通过该挂载点的访问，由 `MS_REMOUNT | MS_BIND` 修改；
+//! 文件系统(超级块)的只读状态影响它的所有挂载，由不带 `MS_BIND` 的 `MS_REMOUNT` 修改。
+//! 绑定挂载与源目录共享同一组目录项，因此覆盖某个目录项的挂载需要结合访问它时使用的路径来确定。
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+
+use constants::{io::MountFlags, AlienResult, LinuxErrno};
+use ksync::Mutex;
+use vfscore::{
+    dentry::VfsDentry, fstype::FileSystemFlags, inode::VfsInode, path::VfsPath,
+    superblock::VfsSuperBlock, utils::VfsNodeType,
+};
+
+use crate::{
+    cgroup::CGROUP_FS_ROOT, overlay::OverlayFs, p9::P9Fs, register_disk_fs, system_support_fs,
+};
+
+/// 挂载表中的一项
+pub struct MountEntry {
+    /// 挂载的来源，块设备的路径或者文件系统的名字
+    pub source: String,
+    /// 挂载点的路径
+    pub target: String,
+    pub fstype: String,
+    /// 挂载项自身的选项
+    pub flags: MountFlags,
+    /// 挂载的文件系统是否只读，同一文件系统的所有挂载项保持一致
+    sb_readonly: bool,
+    /// 挂载时传入的选项字符串
+    pub data: String,
+    root: Arc<dyn VfsDentry>,
+    /// 文件系统所在块设备的设备号
+    rdev: Option<u64>,
+}
+
+static MOUNTS: Mutex<Vec<MountEntry>> = Mutex::new(Vec::new());
+
+/// 根据超级块中的魔数识别块设备上的文件系统，按顺序尝试
+const FS_PROBES: [(&str, fn(&dyn VfsInode) -> bool); 2] =
+    [("ext4", probe_ext), ("vfat", probe_fat)];
+
+fn probe_ext(dev: &dyn VfsInode) -> bool {
+    // ext2/3/4 的超级块位于偏移 1024 处，魔数位于超级块的偏移 56 处
+    let mut magic = [0u8; 2];
+    matches!(dev.read_at(1024 + 56, &mut magic), Ok(2)) && u16::from_le_bytes(magic) == 0xef53
+}
+
+fn probe_fat(dev: &dyn VfsInode) -> bool {
+    let mut sector = vec![0u8; 512];
+    if !matches!(dev.read_at(0, &mut sector), Ok(512)) {
+        return false;
+    }
+    sector[510] == 0x55
+        && sector[511] == 0xaa
+        && (sector[0] == 0xeb || sector[0] == 0xe9)
+        && (&sector[54..57] == b"FAT" || &sector[82..87] == b"FAT32")
+}
+
+/// 识别块设备上的文件系统，返回已注册的文件系统的名字
+pub fn probe_fs(dev: &Arc<dyn VfsInode>) -> Option<&'static str> {
+    FS_PROBES
+        .iter()
+        .find(|(name, probe)| system_support_fs(name).is_some() && probe(dev.as_ref()))
+        .map(|(name, _)| *name)
+}
+
+fn same_super_block(a: &Arc<dyn VfsSuperBlock>, b: &Arc<dyn VfsSuperBlock>) -> bool {
+    Arc::as_ptr(a) as *const u8 == Arc::as_ptr(b) as *const u8
+}
+
+fn super_block_of(dentry: &Arc<dyn VfsDentry>) -> Option<Arc<dyn VfsSuperBlock>> {
+    dentry.inode().ok()?.get_super_block().ok()
+}
+
+/// 将相对于根目录的路径规范化为以 `/` 开头、不含 `.`、`..` 与多余的 `/` 的路径
+fn normalize(path: &str) -> String {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// 挂载点 `target` 是否覆盖路径 `path`，两者都已经规范化
+fn covers(target: &str, path: &str) -> bool {
+    target == "/"
+        || path
+            .strip_prefix(target)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// 找到通过路径 `path` 访问目录项 `dentry` 时经过的挂载项。
+///
+/// 候选项是与 `dentry` 属于同一文件系统的挂载项，其中挂载点覆盖 `path` 且最长的一项即为所求，
+/// 后挂载的优先。路径经过符号链接等原因使得没有挂载点覆盖 `path` 时，返回该文件系统最早的挂载项
+fn covering_mount(mounts: &[MountEntry], dentry: &Arc<dyn VfsDentry>, path: &str) -> Option<usize> {
+    let sb = super_block_of(dentry)?;
+    let path = normalize(path);
+    let candidates = mounts
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| {
+            super_block_of(&entry.root).is_some_and(|root| same_super_block(&root, &sb))
+        })
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    candidates
+        .iter()
+        .filter(|&&index| covers(&mounts[index].target, &path))
+        .max_by_key(|&&index| (mounts[index].target.len(), index))
+        .or(candidates.first())
+        .copied()
+}
+
+/// 登记一次挂载，供重新挂载与 `/proc/mounts` 使用。
+///
+/// `target` 相对于根目录解析，不是绑定挂载时 `MS_RDONLY` 同时将挂载的文件系统设置为只读
+pub fn record_mount(
+    source: &str,
+    target: &str,
+    fstype: &str,
+    flags: MountFlags,
+    data: &str,
+    root: Arc<dyn VfsDentry>,
+    dev: Option<&Arc<dyn VfsInode>>,
+) {
+    let rdev = dev
+        .and_then(|dev| dev.get_attr().ok())
+        .map(|attr| attr.st_rdev);
+    let mut mounts = MOUNTS.lock();
+    let sb_readonly = (flags.contains(MountFlags::MS_RDONLY)
+        && !flags.contains(MountFlags::MS_BIND))
+        || sb_readonly(&mounts, &root);
+    mounts.push(MountEntry {
+        source: source.to_string(),
+        target: normalize(target),
+        fstype: fstype.to_string(),
+        flags,
+        sb_readonly,
+        data: data.to_string(),
+        root,
+        rdev,
+    });
+}
+
+/// `dentry` 所在的文件系统是否已经被设置为只读
+fn sb_readonly(mounts: &[MountEntry], dentry: &Arc<dyn VfsDentry>) -> bool {
+    let Some(sb) = super_block_of(dentry) else {
+        return false;
+    };
+    mounts.iter().any(|entry| {
+        entry.sb_readonly
+            && super_block_of(&entry.root).is_some_and(|root| same_super_block(&root, &sb))
+    })
+}
+
+/// 将 `source` 挂载到 `target`，路径都相对于 `path` 解析。
+///
+/// + `MS_REMOUNT`: 修改 `target` 上已有挂载的 `MS_RDONLY` 与选项字符串。同时指定 `MS_BIND` 时只修改该挂载项自身的只读状态，
+///   否则同时修改文件系统的只读状态，影响它的所有挂载，切换为只读前先同步文件系统
+/// + `MS_BIND`: 将 `source` 目录挂载到 `target`，与原目录共享同一个文件系统，文件系统级别的只读状态也是共享的
+/// + 其它情况下按 `fstype` 创建新的文件系统，`fstype` 为 `auto` 时根据块设备上的超级块识别文件系统，
+///   `9p` 的 `source` 为 virtio-9p 设备的挂载标签
+pub fn do_mount(
+    path: &VfsPath,
+    source: &str,
+    target: &str,
+    fstype: &str,
+    flags: MountFlags,
+    data: &str,
+) -> AlienResult<()> {
+    if flags.contains(MountFlags::MS_REMOUNT) {
+        return remount(path, target, flags, data);
+    }
+    if flags.contains(MountFlags::MS_BIND) {
+        let src = path.join(source)?.open(None)?;
+        let fstype = fstype_of(&src).unwrap_or_else(|| "none".to_string());
+        path.join(target)?.mount(src.clone(), flags.bits())?;
+        record_mount(source, target, &fstype, flags, data, src, None);
+        return Ok(());
+    }
+    let mut dev = None;
+    let fstype = if fstype.is_empty() || fstype == "auto" {
+        let inode = block_device_at(path, source)?;
+        let name = probe_fs(&inode).ok_or(LinuxErrno::EINVAL)?;
+        dev = Some(inode);
+        name.to_string()
+    } else {
+        fstype.to_string()
+    };
+    let fs = system_support_fs(&fstype).ok_or(LinuxErrno::ENODEV)?;
+    let fs_root = if fstype == "cgroup2" {
+        // cgroup v2 只有一个层级结构，每次挂载得到的都是同一个根目录
+        CGROUP_FS_ROOT.get().unwrap().clone()
+    } else if fstype == "overlay" {
+        // 各层目录的路径需要按调用者的根目录与工作目录解析
+        let fs = fs
+            .downcast_arc::<OverlayFs>()
+            .map_err(|_| LinuxErrno::EINVAL)?;
+        fs.mount_at(path, data)?
+    } else if fstype == "9p" {
+        // 9p 的挂载来源是宿主机共享目录的挂载标签
+        let fs = fs.downcast_arc::<P9Fs>().map_err(|_| LinuxErrno::EINVAL)?;
+        fs.mount_tag(source, data)?
+    } else {
+        if dev.is_none() && fs.fs_flag().contains(FileSystemFlags::REQUIRES_DEV) {
+            dev = Some(block_device_at(path, source)?);
+        }
+        fs.i_mount(flags.bits(), target, dev.clone(), data.as_bytes())?
+    };
+    path.join(target)?.mount(fs_root.clone(), flags.bits())?;
+    if dev.is_some() {
+        register_disk_fs(&fs_root.inode()?.get_super_block()?);
+    }
+    record_mount(source, target, &fstype, flags, data, fs_root, dev.as_ref());
+    Ok(())
+}
+
+fn block_device_at(path: &VfsPath, source: &str) -> AlienResult<Arc<dyn VfsInode>> {
+    let inode = path.join(source)?.open(None)?.inode()?;
+    if inode.inode_type() != VfsNodeType::BlockDevice {
+        return Err(LinuxErrno::ENOTBLK);
+    }
+    Ok(inode)
+}
+
+fn remount(path: &VfsPath, target: &str, flags: MountFlags, data: &str) -> AlienResult<()> {
+    let dentry = path.join(target)?.open(None)?;
+    // `target` 必须是某个挂载的根目录
+    let find = |mounts: &[MountEntry]| {
+        covering_mount(mounts, &dentry, target)
+            .filter(|&index| Arc::ptr_eq(&mounts[index].root, &dentry))
+            .ok_or(LinuxErrno::EINVAL)
+    };
+    let readonly = flags.contains(MountFlags::MS_RDONLY);
+    let bind = flags.contains(MountFlags::MS_BIND);
+    let was_readonly = {
+        let mounts = MOUNTS.lock();
+        find(&mounts)?;
+        sb_readonly(&mounts, &dentry)
+    };
+    // 同步需要等待磁盘 I/O，不能持有挂载表的锁
+    if !bind && readonly && !was_readonly {
+        if let Some(sb) = super_block_of(&dentry) {
+            sb.sync_fs(true)?;
+        }
+    }
+    let mut mounts = MOUNTS.lock();
+    let index = find(&mounts)?;
+    if let Some(sb) = super_block_of(&dentry).filter(|_| !bind) {
+        mounts
+            .iter_mut()
+            .filter(|entry| {
+                super_block_of(&entry.root).is_some_and(|root| same_super_block(&root, &sb))
+            })
+            .for_each(|entry| entry.sb_readonly = readonly);
+    }
+    let entry = &mut mounts[index];
+    entry.flags.set(MountFlags::MS_RDONLY, readonly);
+    if !data.is_empty() {
+        entry.data = data.to_string();
+    }
+    Ok(())
+}
+
+/// 卸载 `target` 上的文件系统并从挂载表中移除。
+///
+/// 建立在块设备上的文件系统在卸载前同步，卸载后将块设备缓存写回，
+/// 使 loop 设备上的文件系统的修改到达其背后的文件
+pub fn do_umount(path: &VfsPath, target: &str) -> AlienResult<()> {
+    let dentry = path.join(target)?.open(None)?;
+    let find = |mounts: &[MountEntry]| {
+        covering_mount(mounts, &dentry, target)
+            .filter(|&index| Arc::ptr_eq(&mounts[index].root, &dentry))
+    };
+    let on_device = {
+        let mounts = MOUNTS.lock();
+        find(&mounts).is_some_and(|index| mounts[index].rdev.is_some())
+    };
+    // 同步需要等待磁盘 I/O，不能持有挂载表的锁
+    if on_device {
+        if let Some(sb) = super_block_of(&dentry) {
+            sb.sync_fs(true)?;
+        }
+    }
+    path.join(target)?.umount()?;
+    {
+        let mut mounts = MOUNTS.lock();
+        if let Some(index) = find(&mounts) {
+            mounts.remove(index);
+        }
+    }
+    if on_device {
+        devices::flush_block_devices()?;
+    }
+    Ok(())
+}
+
+/// 设备号为 `rdev` 的块设备上是否挂载着文件系统
+pub fn device_mounted(rdev: u64) -> bool {
+    MOUNTS.lock().iter().any(|entry| entry.rdev == Some(rdev))
+}
+
+/// `dentry` 所在文件系统的类型
+fn fstype_of(dentry: &Arc<dyn VfsDentry>) -> Option<String> {
+    let sb = super_block_of(dentry)?;
+    MOUNTS
+        .lock()
+        .iter()
+        .rev()
+        .find(|entry| super_block_of(&entry.root).is_some_and(|root| same_super_block(&root, &sb)))
+        .map(|entry| entry.fstype.clone())
+}
+
+/// 通过路径 `path` (相对于根目录) 访问的目录项 `dentry` 不可写时返回 `EROFS`。
+///
+/// 访问时经过的挂载项自身是只读的，或者 `dentry` 所在的文件系统被设置为只读时不可写，
+/// 同一目录的其它绑定挂载各自的只读状态不受影响
+pub fn check_writable(dentry: &Arc<dyn VfsDentry>, path: &str) -> AlienResult<()> {
+    let mounts = MOUNTS.lock();
+    let Some(index) = covering_mount(&mounts, dentry, path) else {
+        return Ok(());
+    };
+    let entry = &mounts[index];
+    if entry.sb_readonly || entry.flags.contains(MountFlags::MS_RDONLY) {
+        Err(LinuxErrno::EROFS)
+    } else {
+        Ok(())
+    }
+}
+
+/// 按 `/proc/mounts` 的格式输出挂载表
+pub fn mounts_info() -> String {
+    let mut info = String::new();
+    for entry in MOUNTS.lock().iter() {
+        let mut options = String::from(
+            if entry.sb_readonly || entry.flags.contains(MountFlags::MS_RDONLY) {
+                "ro"
+            } else {
+                "rw"
+            },
+        );
+        if !entry.data.is_empty() {
+            options.push(',');
+            options.push_str(&entry.data);
+        }
+        info.push_str(&format!(
+            "{} {} {} {} 0 0\n",
+            entry.source, entry.target, entry.fstype, options
+        ));
+    }
+    info
+}
@@ -0,0 +1,21 @@
+use alloc::sync::Arc;
+
+use constants::io::MountFlags;
+use dynfs::DynFsDirInode;
+use spin::Once;
+use vfscore::{dentry::VfsDentry, fstype::VfsFsType};
+
+use crate::CommonFsProviderImpl;
+
+pub type MqueueFsDirInodeImpl = DynFsDirInode<CommonFsProviderImpl, spin::Mutex<()>>;
+/// POSIX 消息队列文件系统的根目录，所有通过 `mq_open` 创建的消息队列都会在这里出现
+pub static MQUEUE_FS_ROOT: Once<Arc<dyn VfsDentry>> = Once::new();
+
+pub fn init_mqueuefs(fs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
+    let root = fs
+        .i_mount(MountFlags::empty().bits(), "/dev/mqueue", None, &[])
+        .unwrap();
+    MQUEUE_FS_ROOT.call_once(|| root.clone());
+    println!("mqueuefs init success");
+    root
+}
@@ -0,0 +1,452 @@
+use alloc::{
+    collections::BTreeSet,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+
+use ksync::{Mutex, SleepMutex};
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{
+        VfsDirEntry, VfsFileStat, VfsNodePerm, VfsNodeType, VfsPollEvents, VfsRenameFlag, VfsTime,
+        VfsTimeSpec,
+    },
+    VfsResult,
+};
+
+use super::{OverlaySuperBlock, OPAQUE_MARKER};
+
+/// 合并后的 inode。
+///
+/// 同一路径在上层与各个下层中的 inode 合并为一个：非目录只使用最上面的一个，目录则合并所有层中的目录项。
+/// 只存在于下层的文件在第一次修改时被复制到上层(copy-up)，之后所有操作都在上层的副本上进行。
+pub struct OverlayInode {
+    this: Weak<OverlayInode>,
+    sb: Arc<OverlaySuperBlock>,
+    /// 复制到上层时需要先复制父目录
+    parent: Option<Arc<OverlayInode>>,
+    name: String,
+    ty: VfsNodeType,
+    /// 上层中的 inode，copy-up 期间持有锁，避免同一文件被复制两次
+    upper: SleepMutex<Option<Arc<dyn VfsInode>>>,
+    /// 下层中的 inode，从上到下排列。只有目录会对应多个下层
+    lowers: Vec<Arc<dyn VfsInode>>,
+    /// 从头开始读目录时合并得到的目录项
+    dir_entries: Mutex<Vec<VfsDirEntry>>,
+}
+
+/// 上层中的字符设备 0/0 表示下层中的同名文件已被删除
+fn is_whiteout(inode: &Arc<dyn VfsInode>) -> bool {
+    inode.inode_type() == VfsNodeType::CharDevice
+        && inode.get_attr().is_ok_and(|attr| attr.st_rdev == 0)
+}
+
+/// 不透明的目录会隐藏下层中的同名目录，用目录中的 [`OPAQUE_MARKER`] 文件标记
+fn is_opaque(dir: &Arc<dyn VfsInode>) -> bool {
+    dir.lookup(OPAQUE_MARKER).is_ok()
+}
+
+/// 删除上层目录中的 whiteout，返回是否存在 whiteout
+fn remove_whiteout(dir: &Arc<dyn VfsInode>, name: &str) -> VfsResult<bool> {
+    match dir.lookup(name) {
+        Ok(inode) if is_whiteout(&inode) => {
+            dir.unlink(name)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn create_whiteout(dir: &Arc<dyn VfsInode>, name: &str) -> VfsResult<()> {
+    dir.create(name, VfsNodeType::CharDevice, VfsNodePerm::empty(), Some(0))?;
+    Ok(())
+}
+
+fn check_name(name: &str) -> VfsResult<()> {
+    if name == OPAQUE_MARKER {
+        return Err(VfsError::EINVAL);
+    }
+    Ok(())
+}
+
+impl OverlayInode {
+    pub(super) fn new(
+        sb: Arc<OverlaySuperBlock>,
+        parent: Option<Arc<OverlayInode>>,
+        name: &str,
+        ty: VfsNodeType,
+        upper: Option<Arc<dyn VfsInode>>,
+        lowers: Vec<Arc<dyn VfsInode>>,
+    ) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            sb,
+            parent,
+            name: name.to_string(),
+            ty,
+            upper: SleepMutex::new(upper),
+            lowers,
+            dir_entries: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn upper(&self) -> Option<Arc<dyn VfsInode>> {
+        self.upper.lock().clone()
+    }
+
+    /// 读操作使用的 inode：上层中存在时使用上层，否则使用最上面的下层
+    fn real(&self) -> Arc<dyn VfsInode> {
+        self.upper().unwrap_or_else(|| self.lowers[0].clone())
+    }
+
+    /// 参与合并的目录，不透明目录之下的层被忽略
+    fn layers(&self) -> (Option<Arc<dyn VfsInode>>, Vec<Arc<dyn VfsInode>>) {
+        let upper = self.upper();
+        if upper.as_ref().is_some_and(is_opaque) {
+            return (upper, Vec::new());
+        }
+        let mut lowers = Vec::new();
+        for lower in self.lowers.iter() {
+            lowers.push(lower.clone());
+            if is_opaque(lower) {
+                break;
+            }
+        }
+        (upper, lowers)
+    }
+
+    fn new_child(
+        &self,
+        name: &str,
+        ty: VfsNodeType,
+        upper: Option<Arc<dyn VfsInode>>,
+        lowers: Vec<Arc<dyn VfsInode>>,
+    ) -> Arc<OverlayInode> {
+        OverlayInode::new(
+            self.sb.clone(),
+            self.this.upgrade(),
+            name,
+            ty,
+            upper,
+            lowers,
+        )
+    }
+
+    /// 在各层中查找 `name`。上层或较高的下层中的 whiteout、非目录文件与不透明目录会隐藏更低层中的同名文件
+    fn lookup_child(&self, name: &str) -> VfsResult<Arc<OverlayInode>> {
+        if self.ty != VfsNodeType::Dir {
+            return Err(VfsError::ENOTDIR);
+        }
+        check_name(name).map_err(|_| VfsError::ENOENT)?;
+        let (upper_dir, lower_dirs) = self.layers();
+        let mut ty = None;
+        let mut upper = None;
+        if let Some(dir) = upper_dir {
+            if let Ok(inode) = dir.lookup(name) {
+                if is_whiteout(&inode) {
+                    return Err(VfsError::ENOENT);
+                }
+                ty = Some(inode.inode_type());
+                if ty == Some(VfsNodeType::Dir) && is_opaque(&inode) {
+                    return Ok(self.new_child(name, VfsNodeType::Dir, Some(inode), vec![]));
+                }
+                upper = Some(inode);
+            }
+        }
+        let mut lowers = Vec::new();
+        if ty.is_none() || ty == Some(VfsNodeType::Dir) {
+            for dir in lower_dirs {
+                let Ok(inode) = dir.lookup(name) else {
+                    continue;
+                };
+                if is_whiteout(&inode) {
+                    break;
+                }
+                let lower_ty = inode.inode_type();
+                match ty {
+                    None => ty = Some(lower_ty),
+                    Some(VfsNodeType::Dir) if lower_ty == VfsNodeType::Dir => {}
+                    // 目录会隐藏下层中的同名非目录文件
+                    Some(_) => break,
+                }
+                let stop = lower_ty != VfsNodeType::Dir || is_opaque(&inode);
+                lowers.push(inode);
+                if stop {
+                    break;
+                }
+            }
+        }
+        let ty = ty.ok_or(VfsError::ENOENT)?;
+        Ok(self.new_child(name, ty, upper, lowers))
+    }
+
+    /// 合并所有层中的目录项，上层的同名文件与 whiteout 会隐藏下层的文件
+    fn merged_entries(&self) -> VfsResult<Vec<VfsDirEntry>> {
+        let (upper, lowers) = self.layers();
+        let mut seen = BTreeSet::new();
+        let mut entries = Vec::new();
+        for (layer, dir) in upper.into_iter().chain(lowers).enumerate() {
+            let mut index = 0;
+            while let Some(entry) = dir.readdir(index)? {
+                index += 1;
+                if entry.name == "." || entry.name == ".." {
+                    if layer == 0 {
+                        entries.push(entry);
+                    }
+                    continue;
+                }
+                if entry.name == OPAQUE_MARKER || !seen.insert(entry.name.clone()) {
+                    continue;
+                }
+                if entry.ty == VfsNodeType::CharDevice
+                    && dir
+                        .lookup(&entry.name)
+                        .is_ok_and(|inode| is_whiteout(&inode))
+                {
+                    continue;
+                }
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// 将文件复制到上层，返回上层中的 inode。父目录会先被复制到上层，没有上层时返回 `EROFS`
+    fn copy_up(&self) -> VfsResult<Arc<dyn VfsInode>> {
+        let mut upper = self.upper.lock();
+        if let Some(upper) = upper.as_ref() {
+            return Ok(upper.clone());
+        }
+        let parent = self.parent.as_ref().ok_or(VfsError::EROFS)?;
+        let parent_upper = parent.copy_up()?;
+        let lower = self.lowers[0].clone();
+        let attr = lower.get_attr()?;
+        let perm = lower.node_perm();
+        let inode = match self.ty {
+            VfsNodeType::Dir => parent_upper.create(&self.name, VfsNodeType::Dir, perm, None)?,
+            VfsNodeType::File => self
+                .sb
+                .copy_up_file(&lower, perm, &parent_upper, &self.name)?,
+            VfsNodeType::SymLink => {
+                let mut buf = vec![0u8; 4096];
+                let len = lower.readlink(&mut buf)?;
+                let target = core::str::from_utf8(&buf[..len]).map_err(|_| VfsError::Invalid)?;
+                parent_upper.symlink(&self.name, target)?
+            }
+            ty => parent_upper.create(&self.name, ty, perm, Some(attr.st_rdev))?,
+        };
+        let _ = inode.update_time(VfsTime::ModifiedTime(attr.st_mtime), attr.st_mtime);
+        *upper = Some(inode.clone());
+        Ok(inode)
+    }
+
+    /// 在上层目录中准备创建 `name`：检查名字未被使用并删除已有的 whiteout
+    fn prepare_create(&self, name: &str) -> VfsResult<(Arc<dyn VfsInode>, bool)> {
+        check_name(name)?;
+        if self.lookup_child(name).is_ok() {
+            return Err(VfsError::EEXIST);
+        }
+        let dir = self.copy_up()?;
+        let replaced = remove_whiteout(&dir, name)?;
+        Ok((dir, replaced))
+    }
+}
+
+impl VfsFile for OverlayInode {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if self.ty == VfsNodeType::Dir {
+            return Err(VfsError::EISDIR);
+        }
+        self.real().read_at(offset, buf)
+    }
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if self.ty == VfsNodeType::Dir {
+            return Err(VfsError::EISDIR);
+        }
+        self.copy_up()?.write_at(offset, buf)
+    }
+    fn readdir(&self, start_index: usize) -> VfsResult<Option<VfsDirEntry>> {
+        if self.ty != VfsNodeType::Dir {
+            return Err(VfsError::ENOTDIR);
+        }
+        // 每次从头读目录时重新合并，之后的读取使用同一份结果
+        if start_index == 0 {
+            let entries = self.merged_entries()?;
+            *self.dir_entries.lock() = entries;
+        }
+        Ok(self
+            .dir_entries
+            .lock()
+            .get(start_index)
+            .map(|entry| VfsDirEntry {
+                ino: entry.ino,
+                ty: entry.ty,
+                name: entry.name.clone(),
+            }))
+    }
+    fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        self.real().poll(event)
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
+        self.real().ioctl(cmd, arg)
+    }
+    fn flush(&self) -> VfsResult<()> {
+        match self.upper() {
+            Some(upper) => upper.flush(),
+            None => Ok(()),
+        }
+    }
+    fn fsync(&self) -> VfsResult<()> {
+        match self.upper() {
+            Some(upper) => upper.fsync(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl VfsInode for OverlayInode {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Ok(self.sb.clone())
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        self.real().node_perm()
+    }
+    fn create(
+        &self,
+        name: &str,
+        ty: VfsNodeType,
+        perm: VfsNodePerm,
+        rdev: Option<u64>,
+    ) -> VfsResult<Arc<dyn VfsInode>> {
+        let (dir, replaced) = self.prepare_create(name)?;
+        let inode = dir.create(name, ty, perm, rdev)?;
+        // 新目录替换了下层中被删除的目录，下层目录中的文件不应再出现
+        if ty == VfsNodeType::Dir && replaced {
+            inode.create(OPAQUE_MARKER, VfsNodeType::File, VfsNodePerm::empty(), None)?;
+        }
+        Ok(self.new_child(name, ty, Some(inode), vec![]))
+    }
+    fn link(&self, name: &str, src: Arc<dyn VfsInode>) -> VfsResult<Arc<dyn VfsInode>> {
+        let src = src
+            .downcast_arc::<OverlayInode>()
+            .map_err(|_| VfsError::EXDEV)?;
+        let src_upper = src.copy_up()?;
+        let (dir, _) = self.prepare_create(name)?;
+        let inode = dir.link(name, src_upper)?;
+        Ok(self.new_child(name, src.ty, Some(inode), vec![]))
+    }
+    fn unlink(&self, name: &str) -> VfsResult<()> {
+        let child = self.lookup_child(name)?;
+        if child.ty == VfsNodeType::Dir {
+            return Err(VfsError::EISDIR);
+        }
+        let dir = self.copy_up()?;
+        if child.upper().is_some() {
+            dir.unlink(name)?;
+        }
+        if !child.lowers.is_empty() {
+            create_whiteout(&dir, name)?;
+        }
+        Ok(())
+    }
+    fn symlink(&self, name: &str, sy_name: &str) -> VfsResult<Arc<dyn VfsInode>> {
+        let (dir, _) = self.prepare_create(name)?;
+        let inode = dir.symlink(name, sy_name)?;
+        Ok(self.new_child(name, VfsNodeType::SymLink, Some(inode), vec![]))
+    }
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn VfsInode>> {
+        Ok(self.lookup_child(name)?)
+    }
+    fn rmdir(&self, name: &str) -> VfsResult<()> {
+        let child = self.lookup_child(name)?;
+        if child.ty != VfsNodeType::Dir {
+            return Err(VfsError::ENOTDIR);
+        }
+        if child
+            .merged_entries()?
+            .iter()
+            .any(|entry| entry.name != "." && entry.name != "..")
+        {
+            return Err(VfsError::ENOTEMPTY);
+        }
+        let dir = self.copy_up()?;
+        if let Some(upper) = child.upper() {
+            // 合并后为空的目录在上层中只可能剩下 whiteout 与不透明标记
+            let mut names = Vec::new();
+            let mut index = 0;
+            while let Some(entry) = upper.readdir(index)? {
+                index += 1;
+                if entry.name != "." && entry.name != ".." {
+                    names.push(entry.name);
+                }
+            }
+            for name in names {
+                upper.unlink(&name)?;
+            }
+            dir.rmdir(name)?;
+        }
+        if !child.lowers.is_empty() {
+            create_whiteout(&dir, name)?;
+        }
+        Ok(())
+    }
+    fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        self.real().readlink(buf)
+    }
+    fn set_attr(&self, attr: InodeAttr) -> VfsResult<()> {
+        self.copy_up()?.set_attr(attr)
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        self.real().get_attr()
+    }
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        self.real().list_xattr()
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        self.ty
+    }
+    fn truncate(&self, len: u64) -> VfsResult<()> {
+        self.copy_up()?.truncate(len)
+    }
+    /// 只有不包含下层内容的目录可以重命名，否则返回 `EXDEV`，由用户程序退化为复制后删除
+    fn rename_to(
+        &self,
+        old_name: &str,
+        new_parent: Arc<dyn VfsInode>,
+        new_name: &str,
+        flag: VfsRenameFlag,
+    ) -> VfsResult<()> {
+        let new_parent = new_parent
+            .downcast_arc::<OverlayInode>()
+            .map_err(|_| VfsError::EXDEV)?;
+        check_name(new_name)?;
+        let src = self.lookup_child(old_name)?;
+        let target = new_parent.lookup_child(new_name).ok();
+        let merged_dir =
+            |inode: &OverlayInode| inode.ty == VfsNodeType::Dir && !inode.lowers.is_empty();
+        if merged_dir(src.as_ref()) || target.as_deref().is_some_and(merged_dir) {
+            return Err(VfsError::EXDEV);
+        }
+        let exchange = flag.contains(VfsRenameFlag::RENAME_EXCHANGE);
+        src.copy_up()?;
+        if exchange {
+            target.as_ref().ok_or(VfsError::ENOENT)?.copy_up()?;
+        }
+        let old_dir = self.copy_up()?;
+        let new_dir = new_parent.copy_up()?;
+        remove_whiteout(&new_dir, new_name)?;
+        old_dir.rename_to(old_name, new_dir, new_name, flag)?;
+        if !src.lowers.is_empty() && !exchange {
+            create_whiteout(&old_dir, old_name)?;
+        }
+        Ok(())
+    }
+    fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
+        self.copy_up()?.update_time(time, now)
+    }
+}
@@ -0,0 +1,236 @@
+//! overlay 文件系统。
+//!
+//! 将一个可写的上层目录(`upperdir`)叠加在一个或多个只读的下层目录(`lowerdir`)之上，各层可以位于任意文件系统中。
+//! 对下层文件的修改会先把文件复制到上层，删除下层中的文件时在上层创建 whiteout，下层目录不会被修改。
+//! 挂载选项与 Linux 相同：
+//!
+//! `mount -t overlay overlay -o lowerdir=/lower1:/lower2,upperdir=/upper,workdir=/work /merged`
+//!
+//! 多个下层目录之间用 `:` 分隔，靠前的位于上方。不指定 `upperdir` 与 `workdir` 时整个文件系统只读。
+//! 由于底层文件系统不支持扩展属性，不透明目录通过目录中名为 [`OPAQUE_MARKER`] 的文件标记。
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use constants::{AlienResult, LinuxErrno};
+use spin::Once;
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    fstype::{FileSystemFlags, VfsFsType},
+    inode::VfsInode,
+    path::VfsPath,
+    superblock::{SuperType, VfsSuperBlock},
+    utils::{VfsFsStat, VfsNodePerm, VfsNodeType, VfsRenameFlag},
+    VfsResult,
+};
+
+use self::inode::OverlayInode;
+use crate::{dentry::GenericDentry, SYSTEM_ROOT_FS};
+
+mod inode;
+
+/// 不透明目录中的标记文件，对用户不可见
+pub const OPAQUE_MARKER: &str = ".wh..wh..opq";
+/// copy-up 时在 `workdir` 下使用的目录，文件在这里写完后再移动到上层
+const WORK_DIR: &str = "work";
+const COPY_CHUNK: usize = 4096;
+
+pub struct OverlayFs;
+
+impl OverlayFs {
+    /// 按挂载选项 `data` 创建 overlay 文件系统，选项中的路径相对于 `path` 解析
+    pub fn mount_at(
+        self: &Arc<Self>,
+        path: &VfsPath,
+        data: &str,
+    ) -> AlienResult<Arc<dyn VfsDentry>> {
+        let mut lowerdir = None;
+        let mut upperdir = None;
+        let mut workdir = None;
+        for option in data.split(',') {
+            match option.split_once('=') {
+                Some(("lowerdir", value)) => lowerdir = Some(value),
+                Some(("upperdir", value)) => upperdir = Some(value),
+                Some(("workdir", value)) => workdir = Some(value),
+                _ => {}
+            }
+        }
+        let lowers = lowerdir
+            .ok_or(LinuxErrno::EINVAL)?
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| layer_dir(path, dir))
+            .collect::<AlienResult<Vec<_>>>()?;
+        if lowers.is_empty() {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let (upper, work) = match (upperdir, workdir) {
+            (Some(upper), Some(work)) => {
+                let upper = layer_dir(path, upper)?;
+                let work = layer_dir(path, work)?;
+                // copy-up 通过重命名把文件从 workdir 移动到上层，两者必须位于同一个文件系统
+                let same_fs = Arc::as_ptr(&upper.get_super_block()?) as *const u8
+                    == Arc::as_ptr(&work.get_super_block()?) as *const u8;
+                if !same_fs {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                (Some(upper), Some(prepare_work_dir(&work)?))
+            }
+            (None, None) => (None, None),
+            _ => return Err(LinuxErrno::EINVAL),
+        };
+        let sb = OverlaySuperBlock::new(self.clone(), upper.clone(), work, lowers.clone());
+        let root = OverlayInode::new(sb.clone(), None, "", VfsNodeType::Dir, upper, lowers);
+        sb.root.call_once(|| Arc::downgrade(&root));
+        Ok(GenericDentry::root(root))
+    }
+}
+
+fn layer_dir(path: &VfsPath, dir: &str) -> AlienResult<Arc<dyn VfsInode>> {
+    let inode = path.join(dir)?.open(None)?.inode()?;
+    if inode.inode_type() != VfsNodeType::Dir {
+        return Err(LinuxErrno::ENOTDIR);
+    }
+    Ok(inode)
+}
+
+/// 创建 `workdir/work`，并删除上次 copy-up 中断时留下的临时文件
+fn prepare_work_dir(work: &Arc<dyn VfsInode>) -> AlienResult<Arc<dyn VfsInode>> {
+    let dir = match work.lookup(WORK_DIR) {
+        Ok(dir) if dir.inode_type() == VfsNodeType::Dir => dir,
+        Ok(_) => return Err(LinuxErrno::EINVAL),
+        Err(_) => work.create(WORK_DIR, VfsNodeType::Dir, "rwx------".into(), None)?,
+    };
+    let mut stale = Vec::new();
+    let mut index = 0;
+    while let Some(entry) = dir.readdir(index)? {
+        index += 1;
+        if entry.ty == VfsNodeType::File {
+            stale.push(entry.name);
+        }
+    }
+    for name in stale {
+        dir.unlink(&name)?;
+    }
+    Ok(dir)
+}
+
+impl VfsFsType for OverlayFs {
+    /// 挂载选项中的路径按系统的根目录解析，系统调用通过 [`OverlayFs::mount_at`] 在调用者的上下文中解析
+    fn mount(
+        self: Arc<Self>,
+        _flags: u32,
+        _ab_mnt: &str,
+        _dev: Option<Arc<dyn VfsInode>>,
+        data: &[u8],
+    ) -> VfsResult<Arc<dyn VfsDentry>> {
+        let root = SYSTEM_ROOT_FS.get().ok_or(VfsError::Invalid)?;
+        let path = VfsPath::new(root.clone(), root.clone());
+        let data = core::str::from_utf8(data).map_err(|_| VfsError::Invalid)?;
+        self.mount_at(&path, data)
+    }
+    fn kill_sb(&self, _sb: Arc<dyn VfsSuperBlock>) -> VfsResult<()> {
+        Ok(())
+    }
+    fn fs_flag(&self) -> FileSystemFlags {
+        FileSystemFlags::empty()
+    }
+    fn fs_name(&self) -> String {
+        "overlay".to_string()
+    }
+}
+
+pub struct OverlaySuperBlock {
+    fs_type: Arc<OverlayFs>,
+    upper: Option<Arc<dyn VfsInode>>,
+    /// `workdir/work`
+    work: Option<Arc<dyn VfsInode>>,
+    lowers: Vec<Arc<dyn VfsInode>>,
+    /// 根目录的 inode 持有超级块，这里只保存弱引用
+    root: Once<Weak<OverlayInode>>,
+    /// 用于生成 copy-up 时的临时文件名
+    next_temp: AtomicUsize,
+}
+
+impl OverlaySuperBlock {
+    fn new(
+        fs_type: Arc<OverlayFs>,
+        upper: Option<Arc<dyn VfsInode>>,
+        work: Option<Arc<dyn VfsInode>>,
+        lowers: Vec<Arc<dyn VfsInode>>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            fs_type,
+            upper,
+            work,
+            lowers,
+            root: Once::new(),
+            next_temp: AtomicUsize::new(0),
+        })
+    }
+
+    /// 在 `workdir/work` 中写好文件内容后再重命名到上层目录，避免上层中出现只复制了一部分的文件
+    fn copy_up_file(
+        &self,
+        lower: &Arc<dyn VfsInode>,
+        perm: VfsNodePerm,
+        parent: &Arc<dyn VfsInode>,
+        name: &str,
+    ) -> VfsResult<Arc<dyn VfsInode>> {
+        let work = self.work.as_ref().ok_or(VfsError::EROFS)?;
+        let temp = format!("#{:x}", self.next_temp.fetch_add(1, Ordering::Relaxed));
+        let file = work.create(&temp, VfsNodeType::File, perm, None)?;
+        let copy = || -> VfsResult<()> {
+            let mut buf = vec![0u8; COPY_CHUNK];
+            let mut offset = 0;
+            loop {
+                let len = lower.read_at(offset, &mut buf)?;
+                if len == 0 {
+                    break;
+                }
+                file.write_at(offset, &buf[..len])?;
+                offset += len as u64;
+            }
+            work.rename_to(&temp, parent.clone(), name, VfsRenameFlag::empty())
+        };
+        if let Err(e) = copy() {
+            let _ = work.unlink(&temp);
+            return Err(e);
+        }
+        parent.lookup(name)
+    }
+
+    /// 上层不存在时使用最上面的下层
+    fn top(&self) -> &Arc<dyn VfsInode> {
+        self.upper.as_ref().unwrap_or(&self.lowers[0])
+    }
+}
+
+impl VfsSuperBlock for OverlaySuperBlock {
+    fn sync_fs(&self, wait: bool) -> VfsResult<()> {
+        match self.upper.as_ref() {
+            Some(upper) => upper.get_super_block()?.sync_fs(wait),
+            None => Ok(()),
+        }
+    }
+    fn stat_fs(&self) -> VfsResult<VfsFsStat> {
+        self.top().get_super_block()?.stat_fs()
+    }
+    fn super_type(&self) -> SuperType {
+        SuperType::Independent
+    }
+    fn fs_type(&self) -> Arc<dyn VfsFsType> {
+        self.fs_type.clone()
+    }
+    fn root_inode(&self) -> VfsResult<Arc<dyn VfsInode>> {
+        let root = self.root.get().and_then(|root| root.upgrade());
+        root.map(|root| root as Arc<dyn VfsInode>)
+            .ok_or(VfsError::Invalid)
+    }
+}
@@ -0,0 +1,297 @@
+//! 9P2000.L 客户端。
+//!
+//! 传输设备一次只处理一个请求，所有请求都使用同一个 tag。fid 由客户端分配，
+//! 释放的 fid 先放入待关闭列表，在下一次发出请求前通过 `Tclunk` 归还后才能复用，
+//! 这样 inode 在持有锁的上下文中被释放时也不需要等待设备。
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+
+use constants::LinuxErrno;
+use device_interface::P9Device;
+use ksync::Mutex;
+use vfscore::{error::VfsError, VfsResult};
+
+use super::proto::*;
+
+const TAG: u16 = 1;
+/// 客户端支持的最小消息长度
+const MIN_MSIZE: u32 = 4096;
+
+#[derive(Default)]
+struct FidPool {
+    next: u32,
+    free: Vec<u32>,
+    /// 已经释放、等待 `Tclunk` 的 fid
+    to_clunk: Vec<u32>,
+}
+
+pub struct P9Client {
+    dev: Arc<dyn P9Device>,
+    msize: u32,
+    fids: Mutex<FidPool>,
+}
+
+impl P9Client {
+    /// 与服务器协商协议版本与消息的最大长度
+    pub fn connect(dev: Arc<dyn P9Device>, msize: u32) -> VfsResult<Self> {
+        let mut client = Self {
+            dev,
+            msize: msize.max(MIN_MSIZE),
+            fids: Mutex::new(FidPool::default()),
+        };
+        let mut msg = Encoder::new(TVERSION, NOTAG);
+        msg.u32(client.msize).str(P9_VERSION);
+        let reply = client.transact(TVERSION, msg.finish())?;
+        let mut reply = Decoder::new(&reply);
+        let msize = reply.u32()?;
+        if reply.str()? != P9_VERSION {
+            return Err(VfsError::EPROTONOSUPPORT);
+        }
+        if msize < MIN_MSIZE {
+            return Err(VfsError::IoError);
+        }
+        client.msize = client.msize.min(msize);
+        Ok(client)
+    }
+
+    /// 一次读写最多传输的数据量
+    pub fn iounit(&self) -> usize {
+        self.msize as usize - IOHDRSZ
+    }
+
+    fn alloc_fid(&self) -> u32 {
+        let mut fids = self.fids.lock();
+        fids.free.pop().unwrap_or_else(|| {
+            fids.next += 1;
+            fids.next
+        })
+    }
+
+    /// 释放 fid，实际的 `Tclunk` 在下一次请求前发出
+    pub fn release(&self, fid: u32) {
+        self.fids.lock().to_clunk.push(fid);
+    }
+
+    /// 归还所有已经释放的 fid
+    pub fn clunk_released(&self) {
+        let fids = core::mem::take(&mut self.fids.lock().to_clunk);
+        for fid in fids {
+            let mut msg = Encoder::new(TCLUNK, TAG);
+            msg.u32(fid);
+            // 即使服务器返回错误，fid 也已经失效
+            let _ = self.transact(TCLUNK, msg.finish());
+            self.fids.lock().free.push(fid);
+        }
+    }
+
+    fn transact(&self, ty: u8, req: Vec<u8>) -> VfsResult<Vec<u8>> {
+        if req.len() > self.msize as usize {
+            return Err(VfsError::Invalid);
+        }
+        let mut resp = vec![0u8; self.msize as usize];
+        let len = self.dev.transact(&req, &mut resp)?;
+        let mut header = Decoder::new(&resp[..len.min(resp.len())]);
+        let size = header.u32()? as usize;
+        let rty = header.u8()?;
+        let _tag = header.u16()?;
+        if size < HEADER_SIZE || size > len {
+            return Err(VfsError::IoError);
+        }
+        let body = &resp[HEADER_SIZE..size];
+        if rty == RLERROR {
+            let ecode = Decoder::new(body).u32()?;
+            return Err(LinuxErrno::try_from(-(ecode as isize)).unwrap_or(VfsError::IoError));
+        }
+        if rty != ty + 1 {
+            return Err(VfsError::IoError);
+        }
+        Ok(body.to_vec())
+    }
+
+    fn rpc(&self, ty: u8, build: impl FnOnce(&mut Encoder)) -> VfsResult<Vec<u8>> {
+        self.clunk_released();
+        let mut msg = Encoder::new(ty, TAG);
+        build(&mut msg);
+        self.transact(ty, msg.finish())
+    }
+
+    /// 以 `uname` 的身份连接服务器导出的目录树 `aname`，返回根目录的 fid
+    pub fn attach(&self, uname: &str, aname: &str) -> VfsResult<(u32, Qid)> {
+        let fid = self.alloc_fid();
+        let reply = self.rpc(TATTACH, |msg| {
+            msg.u32(fid).u32(NOFID).str(uname).str(aname).u32(0);
+        });
+        match reply.and_then(|reply| Decoder::new(&reply).qid()) {
+            Ok(qid) => Ok((fid, qid)),
+            Err(e) => {
+                self.fids.lock().free.push(fid);
+                Err(e)
+            }
+        }
+    }
+
+    /// 从 `fid` 出发依次走过 `names`，返回新的 fid。`names` 为空时复制 `fid`
+    pub fn walk(&self, fid: u32, names: &[&str]) -> VfsResult<u32> {
+        let newfid = self.alloc_fid();
+        let reply = self.rpc(TWALK, |msg| {
+            msg.u32(fid).u32(newfid).u16(names.len() as u16);
+            for name in names {
+                msg.str(name);
+            }
+        });
+        let res = reply.and_then(|reply| {
+            // 只走过了部分路径时服务器不会创建新的 fid
+            let nwqid = Decoder::new(&reply).u16()? as usize;
+            if nwqid < names.len() {
+                return Err(VfsError::ENOENT);
+            }
+            Ok(newfid)
+        });
+        if res.is_err() {
+            self.fids.lock().free.push(newfid);
+        }
+        res
+    }
+
+    pub fn getattr(&self, fid: u32) -> VfsResult<P9Stat> {
+        let reply = self.rpc(TGETATTR, |msg| {
+            msg.u32(fid).u64(GETATTR_BASIC);
+        })?;
+        Decoder::new(&reply).stat()
+    }
+
+    pub fn setattr(&self, fid: u32, attr: P9SetAttr) -> VfsResult<()> {
+        self.rpc(TSETATTR, |msg| {
+            msg.u32(fid)
+                .u32(attr.valid)
+                .u32(attr.mode)
+                .u32(attr.uid)
+                .u32(attr.gid)
+                .u64(attr.size)
+                .u64(attr.atime.0)
+                .u64(attr.atime.1)
+                .u64(attr.mtime.0)
+                .u64(attr.mtime.1);
+        })?;
+        Ok(())
+    }
+
+    /// 打开 `fid`，`flags` 为 Linux 的打开标志
+    pub fn lopen(&self, fid: u32, flags: u32) -> VfsResult<()> {
+        self.rpc(TLOPEN, |msg| {
+            msg.u32(fid).u32(flags);
+        })?;
+        Ok(())
+    }
+
+    /// 在目录 `fid` 中创建并打开文件，之后 `fid` 指向新文件
+    pub fn lcreate(&self, fid: u32, name: &str, flags: u32, mode: u32) -> VfsResult<Qid> {
+        let reply = self.rpc(TLCREATE, |msg| {
+            msg.u32(fid).str(name).u32(flags).u32(mode).u32(0);
+        })?;
+        Decoder::new(&reply).qid()
+    }
+
+    pub fn mkdir(&self, dfid: u32, name: &str, mode: u32) -> VfsResult<Qid> {
+        let reply = self.rpc(TMKDIR, |msg| {
+            msg.u32(dfid).str(name).u32(mode).u32(0);
+        })?;
+        Decoder::new(&reply).qid()
+    }
+
+    pub fn symlink(&self, dfid: u32, name: &str, target: &str) -> VfsResult<Qid> {
+        let reply = self.rpc(TSYMLINK, |msg| {
+            msg.u32(dfid).str(name).str(target).u32(0);
+        })?;
+        Decoder::new(&reply).qid()
+    }
+
+    pub fn mknod(
+        &self,
+        dfid: u32,
+        name: &str,
+        mode: u32,
+        major: u32,
+        minor: u32,
+    ) -> VfsResult<Qid> {
+        let reply = self.rpc(TMKNOD, |msg| {
+            msg.u32(dfid)
+                .str(name)
+                .u32(mode)
+                .u32(major)
+                .u32(minor)
+                .u32(0);
+        })?;
+        Decoder::new(&reply).qid()
+    }
+
+    pub fn readlink(&self, fid: u32) -> VfsResult<String> {
+        let reply = self.rpc(TREADLINK, |msg| {
+            msg.u32(fid);
+        })?;
+        Decoder::new(&reply).str()
+    }
+
+    /// 读取目录项，`offset` 为上一个目录项中服务器给出的偏移
+    pub fn readdir(&self, fid: u32, offset: u64) -> VfsResult<Vec<u8>> {
+        let count = self.iounit() as u32;
+        let reply = self.rpc(TREADDIR, |msg| {
+            msg.u32(fid).u64(offset).u32(count);
+        })?;
+        let mut reply = Decoder::new(&reply);
+        let len = reply.u32()? as usize;
+        Ok(reply.bytes(len)?.to_vec())
+    }
+
+    pub fn read(&self, fid: u32, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let count = buf.len().min(self.iounit()) as u32;
+        let reply = self.rpc(TREAD, |msg| {
+            msg.u32(fid).u64(offset).u32(count);
+        })?;
+        let mut reply = Decoder::new(&reply);
+        let len = (reply.u32()? as usize).min(count as usize);
+        buf[..len].copy_from_slice(reply.bytes(len)?);
+        Ok(len)
+    }
+
+    pub fn write(&self, fid: u32, offset: u64, data: &[u8]) -> VfsResult<usize> {
+        let data = &data[..data.len().min(self.iounit())];
+        let reply = self.rpc(TWRITE, |msg| {
+            msg.u32(fid).u64(offset).u32(data.len() as u32).bytes(data);
+        })?;
+        Ok((Decoder::new(&reply).u32()? as usize).min(data.len()))
+    }
+
+    pub fn fsync(&self, fid: u32) -> VfsResult<()> {
+        self.rpc(TFSYNC, |msg| {
+            msg.u32(fid).u32(0);
+        })?;
+        Ok(())
+    }
+
+    pub fn link(&self, dfid: u32, fid: u32, name: &str) -> VfsResult<()> {
+        self.rpc(TLINK, |msg| {
+            msg.u32(dfid).u32(fid).str(name);
+        })?;
+        Ok(())
+    }
+
+    pub fn renameat(
+        &self,
+        olddir: u32,
+        oldname: &str,
+        newdir: u32,
+        newname: &str,
+    ) -> VfsResult<()> {
+        self.rpc(TRENAMEAT, |msg| {
+            msg.u32(olddir).str(oldname).u32(newdir).str(newname);
+        })?;
+        Ok(())
+    }
+
+    pub fn unlinkat(&self, dfid: u32, name: &str, flags: u32) -> VfsResult<()> {
+        self.rpc(TUNLINKAT, |msg| {
+            msg.u32(dfid).str(name).u32(flags);
+        })?;
+        Ok(())
+    }
+}
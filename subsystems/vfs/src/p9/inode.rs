@@ -0,0 +1,351 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use ksync::{Mutex, SleepMutex};
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{
+        VfsDirEntry, VfsFileStat, VfsNodePerm, VfsNodeType, VfsPollEvents, VfsRenameFlag, VfsTime,
+        VfsTimeSpec,
+    },
+    VfsResult,
+};
+
+use super::{client::P9Client, proto::*, P9SuperBlock};
+
+const O_RDONLY: u32 = 0;
+const O_WRONLY: u32 = 1;
+const O_CREAT: u32 = 0o100;
+const O_EXCL: u32 = 0o200;
+const O_DIRECTORY: u32 = 0o200000;
+
+/// 服务器上的一个文件。
+///
+/// 每个 inode 持有一个只用于路径操作、不打开的 fid。文件在第一次读写时复制这个 fid 并打开，
+/// 读与写分别使用只读与只写打开的 fid，inode 释放时一起归还。
+pub struct P9Inode {
+    sb: Arc<P9SuperBlock>,
+    fid: u32,
+    ty: VfsNodeType,
+    /// 以只读与只写方式打开的 fid，打开期间需要等待服务器的回复
+    handles: SleepMutex<[Option<u32>; 2]>,
+    /// 目录项列表，在从头读取目录时重新获取
+    dir_entries: Mutex<Vec<VfsDirEntry>>,
+}
+
+impl P9Inode {
+    pub(super) fn new(sb: Arc<P9SuperBlock>, fid: u32, ty: VfsNodeType) -> Arc<Self> {
+        Arc::new(Self {
+            sb,
+            fid,
+            ty,
+            handles: SleepMutex::new([None, None]),
+            dir_entries: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn client(&self) -> &P9Client {
+        &self.sb.client
+    }
+
+    fn stat(&self) -> VfsResult<P9Stat> {
+        self.client().getattr(self.fid)
+    }
+
+    fn child(&self, name: &str) -> VfsResult<Arc<P9Inode>> {
+        let fid = self.client().walk(self.fid, &[name])?;
+        match self.client().getattr(fid) {
+            Ok(stat) => Ok(P9Inode::new(self.sb.clone(), fid, mode_to_type(stat.mode))),
+            Err(e) => {
+                self.client().release(fid);
+                Err(e)
+            }
+        }
+    }
+
+    /// 复制 fid 并以 `flags` 打开
+    fn open(&self, flags: u32) -> VfsResult<u32> {
+        let fid = self.client().walk(self.fid, &[])?;
+        if let Err(e) = self.client().lopen(fid, flags) {
+            self.client().release(fid);
+            return Err(e);
+        }
+        Ok(fid)
+    }
+
+    /// 获取读(`write` 为假)或写使用的 fid，第一次使用时打开文件
+    fn handle(&self, write: bool) -> VfsResult<u32> {
+        match self.ty {
+            VfsNodeType::File => {}
+            VfsNodeType::Dir => return Err(VfsError::EISDIR),
+            _ => return Err(VfsError::Invalid),
+        }
+        let mut handles = self.handles.lock();
+        if let Some(fid) = handles[write as usize] {
+            return Ok(fid);
+        }
+        let fid = self.open(if write { O_WRONLY } else { O_RDONLY })?;
+        handles[write as usize] = Some(fid);
+        Ok(fid)
+    }
+
+    /// 打开目录并读取所有目录项
+    fn read_dir(&self) -> VfsResult<Vec<VfsDirEntry>> {
+        let fid = self.open(O_RDONLY | O_DIRECTORY)?;
+        let entries = self.read_dir_entries(fid);
+        self.client().release(fid);
+        entries
+    }
+
+    fn read_dir_entries(&self, fid: u32) -> VfsResult<Vec<VfsDirEntry>> {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        loop {
+            let data = self.client().readdir(fid, offset)?;
+            if data.is_empty() {
+                break;
+            }
+            let mut data = Decoder::new(&data);
+            while !data.is_empty() {
+                let qid = data.qid()?;
+                offset = data.u64()?;
+                let ty = node_type(data.u8()?);
+                entries.push(VfsDirEntry {
+                    ino: qid.path,
+                    ty,
+                    name: data.str()?,
+                });
+            }
+        }
+        Ok(entries)
+    }
+}
+
+impl VfsFile for P9Inode {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let fid = self.handle(false)?;
+        let mut count = 0;
+        while count < buf.len() {
+            let len = self
+                .client()
+                .read(fid, offset + count as u64, &mut buf[count..])?;
+            count += len;
+            // 读到 0 字节说明已经到达文件末尾
+            if len == 0 {
+                break;
+            }
+        }
+        Ok(count)
+    }
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let fid = self.handle(true)?;
+        let mut count = 0;
+        while count < buf.len() {
+            let len = self
+                .client()
+                .write(fid, offset + count as u64, &buf[count..])?;
+            count += len;
+            if len == 0 {
+                break;
+            }
+        }
+        Ok(count)
+    }
+    fn readdir(&self, start_index: usize) -> VfsResult<Option<VfsDirEntry>> {
+        if self.ty != VfsNodeType::Dir {
+            return Err(VfsError::ENOTDIR);
+        }
+        if start_index == 0 {
+            *self.dir_entries.lock() = self.read_dir()?;
+        }
+        Ok(self.dir_entries.lock().get(start_index).cloned())
+    }
+    fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        let mut res = VfsPollEvents::empty();
+        if event.contains(VfsPollEvents::IN) {
+            res |= VfsPollEvents::IN;
+        }
+        if event.contains(VfsPollEvents::OUT) {
+            res |= VfsPollEvents::OUT;
+        }
+        Ok(res)
+    }
+    fn flush(&self) -> VfsResult<()> {
+        Ok(())
+    }
+    fn fsync(&self) -> VfsResult<()> {
+        match self.handles.lock()[1] {
+            Some(fid) => self.client().fsync(fid),
+            None => Ok(()),
+        }
+    }
+}
+
+impl VfsInode for P9Inode {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Ok(self.sb.clone())
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        self.stat()
+            .map(|stat| VfsNodePerm::from_bits_truncate((stat.mode & 0o777) as u16))
+            .unwrap_or(VfsNodePerm::empty())
+    }
+    fn create(
+        &self,
+        name: &str,
+        ty: VfsNodeType,
+        perm: VfsNodePerm,
+        rdev: Option<u64>,
+    ) -> VfsResult<Arc<dyn VfsInode>> {
+        let mode = perm.bits() as u32;
+        let child = match ty {
+            VfsNodeType::File => {
+                // 创建后 fid 指向已经以只写方式打开的新文件，作为新 inode 的写句柄
+                let fid = self.client().walk(self.fid, &[])?;
+                let res = self
+                    .client()
+                    .lcreate(fid, name, O_CREAT | O_EXCL | O_WRONLY, mode)
+                    .and_then(|_| self.child(name));
+                match res {
+                    Ok(child) => {
+                        child.handles.lock()[1] = Some(fid);
+                        child
+                    }
+                    Err(e) => {
+                        self.client().release(fid);
+                        return Err(e);
+                    }
+                }
+            }
+            VfsNodeType::Dir => {
+                self.client().mkdir(self.fid, name, mode)?;
+                self.child(name)?
+            }
+            VfsNodeType::SymLink => return Err(VfsError::Invalid),
+            _ => {
+                let rdev = rdev.unwrap_or(0);
+                let (major, minor) = ((rdev >> 32) as u32, rdev as u32);
+                self.client()
+                    .mknod(self.fid, name, type_to_mode(ty) | mode, major, minor)?;
+                self.child(name)?
+            }
+        };
+        Ok(child)
+    }
+    fn link(&self, name: &str, src: Arc<dyn VfsInode>) -> VfsResult<Arc<dyn VfsInode>> {
+        let src = src.downcast_arc::<P9Inode>().map_err(|_| VfsError::EXDEV)?;
+        if !Arc::ptr_eq(&src.sb, &self.sb) {
+            return Err(VfsError::EXDEV);
+        }
+        self.client().link(self.fid, src.fid, name)?;
+        Ok(self.child(name)?)
+    }
+    fn unlink(&self, name: &str) -> VfsResult<()> {
+        self.client().unlinkat(self.fid, name, 0)
+    }
+    fn symlink(&self, name: &str, sy_name: &str) -> VfsResult<Arc<dyn VfsInode>> {
+        self.client().symlink(self.fid, name, sy_name)?;
+        Ok(self.child(name)?)
+    }
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn VfsInode>> {
+        if self.ty != VfsNodeType::Dir {
+            return Err(VfsError::ENOTDIR);
+        }
+        Ok(self.child(name)?)
+    }
+    fn rmdir(&self, name: &str) -> VfsResult<()> {
+        self.client().unlinkat(self.fid, name, AT_REMOVEDIR)
+    }
+    fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let target = self.client().readlink(self.fid)?;
+        let len = target.len().min(buf.len());
+        buf[..len].copy_from_slice(&target.as_bytes()[..len]);
+        Ok(len)
+    }
+    /// 权限与属主的修改暂不转发给服务器
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        let stat = self.stat()?;
+        let (major, minor) = decode_dev(stat.rdev);
+        Ok(VfsFileStat {
+            st_ino: stat.qid.path,
+            st_mode: stat.mode,
+            st_nlink: stat.nlink as u32,
+            st_uid: stat.uid,
+            st_gid: stat.gid,
+            st_rdev: ((major as u64) << 32) | minor as u64,
+            st_size: stat.size,
+            st_blksize: if stat.blksize == 0 {
+                4096
+            } else {
+                stat.blksize as u32
+            },
+            st_blocks: stat.blocks,
+            st_atime: VfsTimeSpec::new(stat.atime.0, stat.atime.1),
+            st_mtime: VfsTimeSpec::new(stat.mtime.0, stat.mtime.1),
+            st_ctime: VfsTimeSpec::new(stat.ctime.0, stat.ctime.1),
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        self.ty
+    }
+    fn truncate(&self, len: u64) -> VfsResult<()> {
+        let attr = P9SetAttr {
+            valid: SETATTR_SIZE,
+            size: len,
+            ..Default::default()
+        };
+        self.client().setattr(self.fid, attr)
+    }
+    /// 9P2000.L 的 `Trenameat` 不支持重命名标志
+    fn rename_to(
+        &self,
+        old_name: &str,
+        new_parent: Arc<dyn VfsInode>,
+        new_name: &str,
+        flag: VfsRenameFlag,
+    ) -> VfsResult<()> {
+        if !flag.is_empty() {
+            return Err(VfsError::Invalid);
+        }
+        let new_parent = new_parent
+            .downcast_arc::<P9Inode>()
+            .map_err(|_| VfsError::EXDEV)?;
+        if !Arc::ptr_eq(&new_parent.sb, &self.sb) {
+            return Err(VfsError::EXDEV);
+        }
+        self.client()
+            .renameat(self.fid, old_name, new_parent.fid, new_name)
+    }
+    fn update_time(&self, time: VfsTime, _now: VfsTimeSpec) -> VfsResult<()> {
+        let mut attr = P9SetAttr::default();
+        match time {
+            VfsTime::AccessTime(t) => {
+                attr.valid = SETATTR_ATIME | SETATTR_ATIME_SET;
+                attr.atime = (t.sec, t.nsec);
+            }
+            VfsTime::ModifiedTime(t) => {
+                attr.valid = SETATTR_MTIME | SETATTR_MTIME_SET;
+                attr.mtime = (t.sec, t.nsec);
+            }
+        }
+        // ctime 由服务器设置为当前时间
+        attr.valid |= SETATTR_CTIME;
+        self.client().setattr(self.fid, attr)
+    }
+}
+
+impl Drop for P9Inode {
+    fn drop(&mut self) {
+        let handles = *self.handles.get_mut();
+        for fid in handles.into_iter().flatten() {
+            self.sb.client.release(fid);
+        }
+        self.sb.client.release(self.fid);
+    }
+}
@@ -0,0 +1,134 @@
+//! 9P2000.L 客户端文件系统。
+//!
+//! 通过 virtio-9p 设备访问宿主机共享的目录，挂载来源为 QEMU 中设置的挂载标签：
+//!
+//! `mount -t 9p -o trans=virtio,version=9p2000.L,msize=65536 hostshare /mnt`
+//!
+//! 支持的挂载选项：`trans`(只支持 `virtio`)、`version`(只支持 `9p2000.L`)、`msize`、`uname` 与 `aname`。
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+};
+
+use constants::{AlienResult, LinuxErrno};
+use spin::Once;
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    fstype::{FileSystemFlags, VfsFsType},
+    inode::VfsInode,
+    superblock::{SuperType, VfsSuperBlock},
+    utils::{VfsFsStat, VfsNodeType},
+    VfsResult,
+};
+
+use self::{client::P9Client, inode::P9Inode, proto::mode_to_type};
+use crate::dentry::GenericDentry;
+
+mod client;
+mod inode;
+mod proto;
+
+/// 没有指定 `msize` 时协商的消息最大长度
+const DEFAULT_MSIZE: u32 = 65536;
+
+pub struct P9Fs;
+
+impl P9Fs {
+    /// 挂载标签为 `tag` 的设备共享的目录，`data` 为挂载选项
+    pub fn mount_tag(self: Arc<Self>, tag: &str, data: &str) -> AlienResult<Arc<dyn VfsDentry>> {
+        let mut msize = DEFAULT_MSIZE;
+        let (mut uname, mut aname) = ("root", "");
+        for option in data.split(',').filter(|option| !option.is_empty()) {
+            let (key, value) = option.split_once('=').unwrap_or((option, ""));
+            match key {
+                "trans" if value != "virtio" => return Err(LinuxErrno::EINVAL),
+                "version" if !value.eq_ignore_ascii_case("9p2000.L") => {
+                    return Err(LinuxErrno::EINVAL)
+                }
+                "msize" => msize = value.parse().map_err(|_| LinuxErrno::EINVAL)?,
+                "uname" => uname = value,
+                "aname" => aname = value,
+                // 其它选项(如 `access`、`cache`)不影响客户端的行为
+                _ => {}
+            }
+        }
+        let dev = devices::find_9p_device(tag).ok_or(LinuxErrno::ENOENT)?;
+        let client = P9Client::connect(dev, msize)?;
+        let (fid, _) = client.attach(uname, aname)?;
+        let stat = match client.getattr(fid) {
+            Ok(stat) => stat,
+            Err(e) => {
+                client.release(fid);
+                return Err(e);
+            }
+        };
+        let sb = Arc::new(P9SuperBlock {
+            fs_type: self,
+            client,
+            root: Once::new(),
+        });
+        let root = P9Inode::new(sb.clone(), fid, mode_to_type(stat.mode));
+        if root.inode_type() != VfsNodeType::Dir {
+            return Err(LinuxErrno::ENOTDIR);
+        }
+        sb.root.call_once(|| Arc::downgrade(&root));
+        Ok(GenericDentry::root(root))
+    }
+}
+
+impl VfsFsType for P9Fs {
+    /// 挂载需要设备的挂载标签，只能通过 [`P9Fs::mount_tag`] 完成
+    fn mount(
+        self: Arc<Self>,
+        _flags: u32,
+        _ab_mnt: &str,
+        _dev: Option<Arc<dyn VfsInode>>,
+        _data: &[u8],
+    ) -> VfsResult<Arc<dyn VfsDentry>> {
+        Err(VfsError::Invalid)
+    }
+    fn kill_sb(&self, _sb: Arc<dyn VfsSuperBlock>) -> VfsResult<()> {
+        Ok(())
+    }
+    fn fs_flag(&self) -> FileSystemFlags {
+        FileSystemFlags::empty()
+    }
+    fn fs_name(&self) -> String {
+        "9p".to_string()
+    }
+}
+
+pub struct P9SuperBlock {
+    fs_type: Arc<P9Fs>,
+    client: P9Client,
+    /// 根目录的 inode 持有超级块，这里只保存弱引用
+    root: Once<Weak<P9Inode>>,
+}
+
+impl VfsSuperBlock for P9SuperBlock {
+    fn sync_fs(&self, _wait: bool) -> VfsResult<()> {
+        Ok(())
+    }
+    fn stat_fs(&self) -> VfsResult<VfsFsStat> {
+        Err(VfsError::NoSys)
+    }
+    fn super_type(&self) -> SuperType {
+        SuperType::Independent
+    }
+    fn fs_type(&self) -> Arc<dyn VfsFsType> {
+        self.fs_type.clone()
+    }
+    fn root_inode(&self) -> VfsResult<Arc<dyn VfsInode>> {
+        let root = self.root.get().and_then(|root| root.upgrade());
+        root.map(|root| root as Arc<dyn VfsInode>)
+            .ok_or(VfsError::Invalid)
+    }
+}
+
+impl Drop for P9SuperBlock {
+    /// 所有 inode 都已释放，归还它们留下的 fid
+    fn drop(&mut self) {
+        self.client.clunk_released();
+    }
+}
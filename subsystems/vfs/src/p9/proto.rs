@@ -0,0 +1,222 @@
+//! 9P2000.L 协议的消息编码。
+//!
+//! 每个消息以 `size[4] type[1] tag[2]` 开头，整数使用小端序，字符串为 `len[2]` 加上 UTF-8 字节。
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use vfscore::{error::VfsError, utils::VfsNodeType, VfsResult};
+
+pub const P9_VERSION: &str = "9P2000.L";
+/// 消息头 `size[4] type[1] tag[2]` 的长度
+pub const HEADER_SIZE: usize = 7;
+/// `Rread` 与 `Twrite` 中数据之前的部分的最大长度
+pub const IOHDRSZ: usize = 24;
+
+pub const NOTAG: u16 = !0;
+pub const NOFID: u32 = !0;
+
+pub const RLERROR: u8 = 7;
+pub const TLOPEN: u8 = 12;
+pub const TLCREATE: u8 = 14;
+pub const TSYMLINK: u8 = 16;
+pub const TMKNOD: u8 = 18;
+pub const TREADLINK: u8 = 22;
+pub const TGETATTR: u8 = 24;
+pub const TSETATTR: u8 = 26;
+pub const TREADDIR: u8 = 40;
+pub const TFSYNC: u8 = 50;
+pub const TLINK: u8 = 70;
+pub const TMKDIR: u8 = 72;
+pub const TRENAMEAT: u8 = 74;
+pub const TUNLINKAT: u8 = 76;
+pub const TVERSION: u8 = 100;
+pub const TATTACH: u8 = 104;
+pub const TWALK: u8 = 110;
+pub const TREAD: u8 = 116;
+pub const TWRITE: u8 = 118;
+pub const TCLUNK: u8 = 120;
+
+/// `Tgetattr` 请求 stat 中的所有基本字段
+pub const GETATTR_BASIC: u64 = 0x7ff;
+
+pub const SETATTR_SIZE: u32 = 1 << 3;
+pub const SETATTR_ATIME: u32 = 1 << 4;
+pub const SETATTR_MTIME: u32 = 1 << 5;
+pub const SETATTR_CTIME: u32 = 1 << 6;
+pub const SETATTR_ATIME_SET: u32 = 1 << 7;
+pub const SETATTR_MTIME_SET: u32 = 1 << 8;
+
+/// `Tunlinkat` 删除目录
+pub const AT_REMOVEDIR: u32 = 0x200;
+
+/// 服务器上文件的唯一标识，只使用其中的 `path` 作为 inode 号
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Qid {
+    pub path: u64,
+}
+
+/// `Rgetattr` 中的文件属性
+#[derive(Debug, Default, Clone, Copy)]
+pub struct P9Stat {
+    pub qid: Qid,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u64,
+    pub rdev: u64,
+    pub size: u64,
+    pub blksize: u64,
+    pub blocks: u64,
+    pub atime: (u64, u64),
+    pub mtime: (u64, u64),
+    pub ctime: (u64, u64),
+}
+
+/// `Tsetattr` 的参数，`valid` 指明哪些字段有效
+#[derive(Debug, Default, Clone, Copy)]
+pub struct P9SetAttr {
+    pub valid: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub atime: (u64, u64),
+    pub mtime: (u64, u64),
+}
+
+/// 按顺序写入消息的各个字段，消息头中的长度在 [`Encoder::finish`] 时填写
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new(ty: u8, tag: u16) -> Self {
+        let mut encoder = Self { buf: Vec::new() };
+        encoder.u32(0).u8(ty).u16(tag);
+        encoder
+    }
+    pub fn u8(&mut self, v: u8) -> &mut Self {
+        self.buf.push(v);
+        self
+    }
+    pub fn u16(&mut self, v: u16) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+    pub fn u32(&mut self, v: u32) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+    pub fn u64(&mut self, v: u64) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+    pub fn str(&mut self, s: &str) -> &mut Self {
+        self.u16(s.len() as u16);
+        self.buf.extend_from_slice(s.as_bytes());
+        self
+    }
+    pub fn bytes(&mut self, data: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(data);
+        self
+    }
+    pub fn finish(mut self) -> Vec<u8> {
+        let len = self.buf.len() as u32;
+        self.buf[..4].copy_from_slice(&len.to_le_bytes());
+        self.buf
+    }
+}
+
+/// 按顺序读取回复中的字段，回复被截断时返回 `EIO`
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+    pub fn bytes(&mut self, len: usize) -> VfsResult<&'a [u8]> {
+        let data = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or(VfsError::IoError)?;
+        self.pos += len;
+        Ok(data)
+    }
+    pub fn u8(&mut self) -> VfsResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+    pub fn u16(&mut self) -> VfsResult<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+    pub fn u32(&mut self) -> VfsResult<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    pub fn u64(&mut self) -> VfsResult<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+    pub fn str(&mut self) -> VfsResult<String> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).to_string())
+    }
+    /// `qid` 由 `type[1] version[4] path[8]` 组成
+    pub fn qid(&mut self) -> VfsResult<Qid> {
+        self.bytes(5)?;
+        Ok(Qid { path: self.u64()? })
+    }
+    pub fn stat(&mut self) -> VfsResult<P9Stat> {
+        let _valid = self.u64()?;
+        let mut stat = P9Stat {
+            qid: self.qid()?,
+            mode: self.u32()?,
+            uid: self.u32()?,
+            gid: self.u32()?,
+            nlink: self.u64()?,
+            rdev: self.u64()?,
+            size: self.u64()?,
+            blksize: self.u64()?,
+            blocks: self.u64()?,
+            ..Default::default()
+        };
+        stat.atime = (self.u64()?, self.u64()?);
+        stat.mtime = (self.u64()?, self.u64()?);
+        stat.ctime = (self.u64()?, self.u64()?);
+        Ok(stat)
+    }
+}
+
+/// `Rreaddir` 与 stat 的 `st_mode` 中的文件类型与 `DT_*` 的取值相同
+pub fn node_type(dt: u8) -> VfsNodeType {
+    match dt {
+        1 => VfsNodeType::Fifo,
+        2 => VfsNodeType::CharDevice,
+        4 => VfsNodeType::Dir,
+        6 => VfsNodeType::BlockDevice,
+        8 => VfsNodeType::File,
+        10 => VfsNodeType::SymLink,
+        12 => VfsNodeType::Socket,
+        _ => VfsNodeType::Unknown,
+    }
+}
+
+pub fn mode_to_type(mode: u32) -> VfsNodeType {
+    node_type(((mode >> 12) & 0xf) as u8)
+}
+
+pub fn type_to_mode(ty: VfsNodeType) -> u32 {
+    (ty as u32) << 12
+}
+
+/// 服务器返回的是宿主机 glibc 编码的设备号
+pub fn decode_dev(dev: u64) -> (u32, u32) {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    (major as u32, minor as u32)
+}
@@ -0,0 +1,424 @@
+//! binfmt_misc: 根据文件开头的魔数或文件的扩展名，将不能直接执行的文件交给指定的解释器执行。
+//!
+//! 规则通过向 `/proc/sys/fs/binfmt_misc/register` 写入 `:name:type:offset:magic:mask:interpreter:flags`
+//! 的方式注册，其中 `type` 为 `M` (按魔数匹配) 或 `E` (按扩展名匹配)，分隔符可以是任意不出现在各字段中的字符。
+//! 注册后会在同一目录下出现以规则名命名的文件，读取该文件可以获得规则的内容，写入 `0`/`1`/`-1`
+//! 分别表示禁用、启用和删除该规则。写入 `status` 文件则对所有规则生效。
+//!
+//! 标志中目前只有 `P` 起作用，表示保留原本的 `argv[0]`，其余标志 (`O`、`C`、`F`) 会被接受但忽略。
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    cmp::min,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use ksync::Mutex;
+use spin::Once;
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType},
+    VfsResult,
+};
+
+use crate::proc::ProcFsDirInodeImpl;
+
+/// 魔数的最大长度
+const MAX_MAGIC_LEN: usize = 128;
+/// 魔数在文件中的偏移的上限
+const MAX_MAGIC_OFFSET: usize = 128;
+
+/// `/proc/sys/fs/binfmt_misc` 目录，规则对应的文件在注册时被加入该目录
+static BINFMT_MISC_ROOT: Once<Arc<dyn VfsDentry>> = Once::new();
+/// 所有已经注册的规则，以规则名为键
+static BINFMT_RULES: Mutex<BTreeMap<String, BinfmtRule>> = Mutex::new(BTreeMap::new());
+/// 是否启用 binfmt_misc
+static BINFMT_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// 规则的匹配方式
+enum BinfmtMatch {
+    /// 文件偏移 `offset` 处的内容与 `magic` 按 `mask` 相与后相同
+    Magic {
+        offset: usize,
+        magic: Vec<u8>,
+        mask: Option<Vec<u8>>,
+    },
+    /// 文件名以 `.扩展名` 结尾
+    Extension(String),
+}
+
+/// 一条 binfmt_misc 规则
+struct BinfmtRule {
+    enabled: bool,
+    matcher: BinfmtMatch,
+    interpreter: String,
+    flags: String,
+}
+
+/// 查找文件对应的解释器的结果
+#[derive(Debug, Clone)]
+pub struct BinfmtHandler {
+    /// 解释器的路径
+    pub interpreter: String,
+    /// 是否保留原本的 `argv[0]`，否则 `argv[0]` 会被替换为文件的路径
+    pub preserve_argv0: bool,
+}
+
+impl BinfmtRule {
+    fn matches(&self, path: &str, header: &[u8]) -> bool {
+        match &self.matcher {
+            BinfmtMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                let Some(data) = header.get(*offset..*offset + magic.len()) else {
+                    return false;
+                };
+                data.iter().enumerate().all(|(i, byte)| {
+                    let mask = mask.as_ref().map_or(0xff, |mask| mask[i]);
+                    byte & mask == magic[i] & mask
+                })
+            }
+            BinfmtMatch::Extension(ext) => {
+                let name = path.rsplit('/').next().unwrap_or(path);
+                name.rsplit_once('.')
+                    .map_or(false, |(stem, e)| !stem.is_empty() && e == ext)
+            }
+        }
+    }
+
+    fn serialize(&self) -> String {
+        let mut res = String::new();
+        res.push_str(if self.enabled {
+            "enabled\n"
+        } else {
+            "disabled\n"
+        });
+        res.push_str(&format!("interpreter {}\n", self.interpreter));
+        res.push_str(&format!("flags: {}\n", self.flags));
+        match &self.matcher {
+            BinfmtMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                res.push_str(&format!("offset {}\n", offset));
+                res.push_str(&format!("magic {}\n", hex(magic)));
+                if let Some(mask) = mask {
+                    res.push_str(&format!("mask {}\n", hex(mask)));
+                }
+            }
+            BinfmtMatch::Extension(ext) => res.push_str(&format!("extension .{}\n", ext)),
+        }
+        res
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 解析魔数与掩码中的转义序列，支持 `\xHH` 与 `\\`
+fn unescape(s: &str) -> Option<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            match bytes.get(i + 1) {
+                Some(b'x') => {
+                    let hex = s.get(i + 2..i + 4)?;
+                    res.push(u8::from_str_radix(hex, 16).ok()?);
+                    i += 4;
+                }
+                Some(b'\\') => {
+                    res.push(b'\\');
+                    i += 2;
+                }
+                _ => return None,
+            }
+        } else {
+            res.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(res)
+}
+
+/// 解析写入 `register` 文件的规则
+fn parse_rule(line: &str) -> Option<(String, BinfmtRule)> {
+    let line = line.trim_end_matches(|c| c == '\n' || c == '\0');
+    let delimiter = line.chars().next()?;
+    let fields = line[delimiter.len_utf8()..]
+        .split(delimiter)
+        .collect::<Vec<&str>>();
+    if fields.len() < 6 || fields.len() > 7 {
+        return None;
+    }
+    let name = fields[0];
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+        return None;
+    }
+    if name == "register" || name == "status" {
+        return None;
+    }
+    let matcher = match fields[1] {
+        "M" => {
+            let offset = if fields[2].is_empty() {
+                0
+            } else {
+                fields[2].parse::<usize>().ok()?
+            };
+            let magic = unescape(fields[3])?;
+            if magic.is_empty() || magic.len() > MAX_MAGIC_LEN || offset > MAX_MAGIC_OFFSET {
+                return None;
+            }
+            let mask = if fields[4].is_empty() {
+                None
+            } else {
+                let mask = unescape(fields[4])?;
+                if mask.len() != magic.len() {
+                    return None;
+                }
+                Some(mask)
+            };
+            BinfmtMatch::Magic {
+                offset,
+                magic,
+                mask,
+            }
+        }
+        "E" => {
+            // the offset and mask fields are ignored for extension rules
+            let ext = fields[3];
+            if ext.is_empty() || ext.contains('/') {
+                return None;
+            }
+            BinfmtMatch::Extension(ext.to_string())
+        }
+        _ => return None,
+    };
+    let interpreter = fields[5];
+    if interpreter.is_empty() {
+        return None;
+    }
+    let flags = fields.get(6).copied().unwrap_or("");
+    if flags.chars().any(|c| !"POCF".contains(c)) {
+        return None;
+    }
+    Some((
+        name.to_string(),
+        BinfmtRule {
+            enabled: true,
+            matcher,
+            interpreter: interpreter.to_string(),
+            flags: flags.to_string(),
+        },
+    ))
+}
+
+/// 查找能够执行文件 `path` 的解释器，`header` 为文件开头的内容
+pub fn binfmt_match(path: &str, header: &[u8]) -> Option<BinfmtHandler> {
+    if !BINFMT_ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    BINFMT_RULES
+        .lock()
+        .values()
+        .find(|rule| rule.enabled && rule.matches(path, header))
+        .map(|rule| BinfmtHandler {
+            interpreter: rule.interpreter.clone(),
+            preserve_argv0: rule.flags.contains('P'),
+        })
+}
+
+/// 读取写入控制文件的命令，`0` 表示禁用，`1` 表示启用，`-1` 表示删除
+fn parse_command(buf: &[u8]) -> VfsResult<i8> {
+    let cmd = core::str::from_utf8(buf).map_err(|_| VfsError::Invalid)?;
+    match cmd.trim_end_matches(|c| c == '\n' || c == '\0') {
+        "0" => Ok(0),
+        "1" => Ok(1),
+        "-1" => Ok(-1),
+        _ => Err(VfsError::Invalid),
+    }
+}
+
+fn read_str(info: &str, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+    let info = info.as_bytes();
+    if offset as usize >= info.len() {
+        return Ok(0);
+    }
+    let min_len = min(buf.len(), info.len() - offset as usize);
+    buf[..min_len].copy_from_slice(&info[offset as usize..offset as usize + min_len]);
+    Ok(min_len)
+}
+
+fn root_inode() -> VfsResult<(Arc<dyn VfsDentry>, Arc<ProcFsDirInodeImpl>)> {
+    let root = BINFMT_MISC_ROOT.get().ok_or(VfsError::NoSys)?;
+    let root_inode = root
+        .inode()?
+        .downcast_arc::<ProcFsDirInodeImpl>()
+        .map_err(|_| VfsError::Invalid)?;
+    Ok((root.clone(), root_inode))
+}
+
+/// 删除规则以及其在 binfmt_misc 目录中对应的文件
+fn remove_rule(name: &str) -> VfsResult<()> {
+    if BINFMT_RULES.lock().remove(name).is_none() {
+        return Ok(());
+    }
+    let (root, root_inode) = root_inode()?;
+    root.remove(name)?;
+    root_inode.remove_manually(name)?;
+    Ok(())
+}
+
+macro_rules! impl_binfmt_inode {
+    ($name:ident, $perm:expr) => {
+        impl VfsInode for $name {
+            fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+                Err(VfsError::NoSys)
+            }
+            fn node_perm(&self) -> VfsNodePerm {
+                $perm.into()
+            }
+            fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+                Ok(())
+            }
+            fn get_attr(&self) -> VfsResult<VfsFileStat> {
+                Ok(VfsFileStat {
+                    st_size: self.serialize().len() as u64,
+                    ..Default::default()
+                })
+            }
+            fn inode_type(&self) -> VfsNodeType {
+                VfsNodeType::File
+            }
+        }
+    };
+}
+
+/// `register` 文件，写入规则以注册
+pub struct BinfmtRegister;
+
+impl BinfmtRegister {
+    fn serialize(&self) -> String {
+        String::new()
+    }
+}
+
+impl VfsFile for BinfmtRegister {
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let line = core::str::from_utf8(buf).map_err(|_| VfsError::Invalid)?;
+        let (name, rule) = parse_rule(line).ok_or(VfsError::Invalid)?;
+        let (root, root_inode) = root_inode()?;
+        let mut rules = BINFMT_RULES.lock();
+        if rules.contains_key(&name) {
+            return Err(VfsError::Invalid);
+        }
+        let inode = root_inode.add_file_manually(
+            &name,
+            Arc::new(BinfmtEntry { name: name.clone() }),
+            "rw-r--r--".into(),
+        )?;
+        root.i_insert(&name, inode)?;
+        rules.insert(name, rule);
+        Ok(buf.len())
+    }
+}
+
+impl_binfmt_inode!(BinfmtRegister, "-w-------");
+
+/// `status` 文件，读取可以获得 binfmt_misc 是否启用，写入命令则对所有规则生效
+pub struct BinfmtStatus;
+
+impl BinfmtStatus {
+    fn serialize(&self) -> String {
+        if BINFMT_ENABLED.load(Ordering::Relaxed) {
+            "enabled\n".to_string()
+        } else {
+            "disabled\n".to_string()
+        }
+    }
+}
+
+impl VfsFile for BinfmtStatus {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        read_str(&self.serialize(), offset, buf)
+    }
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        match parse_command(buf)? {
+            -1 => {
+                let names = BINFMT_RULES.lock().keys().cloned().collect::<Vec<String>>();
+                for name in names {
+                    remove_rule(&name)?;
+                }
+            }
+            cmd => BINFMT_ENABLED.store(cmd == 1, Ordering::Relaxed),
+        }
+        Ok(buf.len())
+    }
+}
+
+impl_binfmt_inode!(BinfmtStatus, "rw-r--r--");
+
+/// 每条规则对应的文件
+pub struct BinfmtEntry {
+    name: String,
+}
+
+impl BinfmtEntry {
+    fn serialize(&self) -> String {
+        BINFMT_RULES
+            .lock()
+            .get(&self.name)
+            .map(|rule| rule.serialize())
+            .unwrap_or_default()
+    }
+}
+
+impl VfsFile for BinfmtEntry {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        read_str(&self.serialize(), offset, buf)
+    }
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        match parse_command(buf)? {
+            -1 => remove_rule(&self.name)?,
+            cmd => {
+                if let Some(rule) = BINFMT_RULES.lock().get_mut(&self.name) {
+                    rule.enabled = cmd == 1;
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+}
+
+impl_binfmt_inode!(BinfmtEntry, "rw-r--r--");
+
+/// 在 `dir` 目录下创建 `register` 与 `status` 文件
+pub fn init_binfmt_misc(dir: Arc<dyn VfsDentry>) {
+    let dir_inode = dir
+        .inode()
+        .unwrap()
+        .downcast_arc::<ProcFsDirInodeImpl>()
+        .map_err(|_| VfsError::Invalid)
+        .unwrap();
+    dir_inode
+        .add_file_manually("register", Arc::new(BinfmtRegister), "-w-------".into())
+        .unwrap();
+    dir_inode
+        .add_file_manually("status", Arc::new(BinfmtStatus), "rw-r--r--".into())
+        .unwrap();
+    BINFMT_MISC_ROOT.call_once(|| dir);
+}
@@ -22,14 +22,14 @@ impl SystemSupportFS {
     pub fn serialize(&self) -> String {
         let mut res = String::new();
         let fs = FS.lock();
-        for (_, fs) in fs.iter() {
+        for (name, fs) in fs.iter() {
             let flag = fs.fs_flag();
             if !flag.contains(FileSystemFlags::REQUIRES_DEV) {
                 res.push_str("nodev ")
             } else {
                 res.push_str("      ");
             }
-            res.push_str(&fs.fs_name());
+            res.push_str(name);
             res.push_str("\n");
         }
         res
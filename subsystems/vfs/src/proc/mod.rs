@@ -1,7 +1,9 @@
+pub mod binfmt;
 mod filesystem;
 mod interrupt;
 mod mem;
 mod mounts;
+pub mod sysctl;
 
 use alloc::sync::Arc;
 use core::ops::Index;
@@ -11,6 +13,7 @@ use filesystem::SystemSupportFS;
 use interrupt::InterruptRecord;
 use mem::MemInfo;
 use mounts::MountInfo;
+use sysctl::{SysctlFile, SysctlStringFile, CORE_PATTERN, RANDOMIZE_VA_SPACE};
 use vfscore::{dentry::VfsDentry, error::VfsError, fstype::VfsFsType, path::VfsPath};
 
 use crate::{CommonFsProviderImpl, FS};
@@ -23,6 +26,17 @@ pub type ProcFsDirInodeImpl = DynFsDirInode<CommonFsProviderImpl, spin::Mutex<()
 /// |-- interrupts
 /// |-- mounts
 /// |-- filesystems
+/// |-- self
+///     |-- exe
+///     |-- root (a static symlink to "/", not a per-task link)
+/// |-- sys
+///     |-- fs
+///         |-- binfmt_misc
+///             |-- register
+///             |-- status
+///     |-- kernel
+///         |-- randomize_va_space
+///         |-- core_pattern
 /// ```
 // todo!(use ramfs instead of dynfs)
 pub fn init_procfs(procfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
@@ -49,8 +63,35 @@ pub fn init_procfs(procfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
     root_inode
         .add_dir_manually("self", "r-xr-xr-x".into())
         .unwrap();
+    root_inode
+        .add_dir_manually("sys", "r-xr-xr-x".into())
+        .unwrap();
 
     let path = VfsPath::new(root_dt.clone(), root_dt.clone());
+    let sys = path.join("sys").unwrap().open(None).unwrap();
+    let sys_fs = add_dir(&sys, "fs");
+    let binfmt_misc = add_dir(&sys_fs, "binfmt_misc");
+    binfmt::init_binfmt_misc(binfmt_misc);
+    let sys_kernel = add_dir(&sys, "kernel")
+        .inode()
+        .unwrap()
+        .downcast_arc::<ProcFsDirInodeImpl>()
+        .map_err(|_| VfsError::Invalid)
+        .unwrap();
+    sys_kernel
+        .add_file_manually(
+            "randomize_va_space",
+            Arc::new(SysctlFile::new(&RANDOMIZE_VA_SPACE, 2)),
+            "rw-r--r--".into(),
+        )
+        .unwrap();
+    sys_kernel
+        .add_file_manually(
+            "core_pattern",
+            Arc::new(SysctlStringFile::new(&CORE_PATTERN, 127)),
+            "rw-r--r--".into(),
+        )
+        .unwrap();
     let ramfs = FS.lock().index("ramfs").clone();
     let fake_ramfs = ramfs.i_mount(0, "/proc/self", None, &[]).unwrap();
     path.join("self").unwrap().mount(fake_ramfs, 0).unwrap();
@@ -59,8 +100,29 @@ pub fn init_procfs(procfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
         .unwrap()
         .symlink("/bin/busybox")
         .unwrap();
+    // 这是所有任务共享的、指向 "/" 的静态符号链接，而不是每个任务各自的链接：readlink 总是得到 "/"，
+    // 只是由于符号链接基于访问者的根目录解析，通过它访问到的是访问者自身的根目录
+    path.join("self/root").unwrap().symlink("/").unwrap();
 
     println!("procfs init success");
 
     root_dt
 }
+
+/// 在 procfs 的目录 `parent` 下创建一个子目录
+fn add_dir(parent: &Arc<dyn VfsDentry>, name: &str) -> Arc<dyn VfsDentry> {
+    let parent_inode = parent
+        .inode()
+        .unwrap()
+        .downcast_arc::<ProcFsDirInodeImpl>()
+        .map_err(|_| VfsError::Invalid)
+        .unwrap();
+    parent_inode
+        .add_dir_manually(name, "r-xr-xr-x".into())
+        .unwrap();
+    VfsPath::new(parent.clone(), parent.clone())
+        .join(name)
+        .unwrap()
+        .open(None)
+        .unwrap()
+}
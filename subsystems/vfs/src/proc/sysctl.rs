@@ -0,0 +1,160 @@
+//! `/proc/sys` 下的内核参数。
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+};
+use core::{
+    cmp::min,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use ksync::Mutex;
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType},
+    VfsResult,
+};
+
+/// 地址空间随机化的等级，对应 `/proc/sys/kernel/randomize_va_space`。
+///
+/// + 0: 关闭地址空间随机化
+/// + 1: 随机化栈、mmap 区域、动态链接器以及 PIE 程序的加载位置
+/// + 2: 在 1 的基础上随机化堆的起始位置
+pub static RANDOMIZE_VA_SPACE: AtomicUsize = AtomicUsize::new(2);
+
+/// core 文件的命名模板，对应 `/proc/sys/kernel/core_pattern`。
+///
+/// 模板中可以使用 `%p`、`%e`、`%s`、`%t` 等占位符，由内核在生成 core 文件时展开。
+pub static CORE_PATTERN: Mutex<String> = Mutex::new(String::new());
+
+/// 获取当前的 core 文件命名模板，未设置时为 `core`
+pub fn core_pattern() -> String {
+    let pattern = CORE_PATTERN.lock();
+    if pattern.is_empty() {
+        "core".to_string()
+    } else {
+        pattern.clone()
+    }
+}
+
+/// 一个取值范围为 `0..=max` 的整数内核参数
+pub struct SysctlFile {
+    value: &'static AtomicUsize,
+    max: usize,
+}
+
+impl SysctlFile {
+    pub fn new(value: &'static AtomicUsize, max: usize) -> Self {
+        Self { value, max }
+    }
+}
+
+impl VfsFile for SysctlFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let info = format!("{}\n", self.value.load(Ordering::Relaxed));
+        let info = info.as_bytes();
+        if offset as usize >= info.len() {
+            return Ok(0);
+        }
+        let min_len = min(buf.len(), info.len() - offset as usize);
+        buf[..min_len].copy_from_slice(&info[offset as usize..offset as usize + min_len]);
+        Ok(min_len)
+    }
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let value = core::str::from_utf8(buf)
+            .map_err(|_| VfsError::Invalid)?
+            .trim_end_matches(|c| c == '\n' || c == '\0')
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| VfsError::Invalid)?;
+        if value > self.max {
+            return Err(VfsError::Invalid);
+        }
+        self.value.store(value, Ordering::Relaxed);
+        Ok(buf.len())
+    }
+}
+
+impl VfsInode for SysctlFile {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        "rw-r--r--".into()
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_size: format!("{}\n", self.value.load(Ordering::Relaxed)).len() as u64,
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::File
+    }
+}
+
+/// 一个字符串类型的内核参数，写入时去掉结尾的换行符
+pub struct SysctlStringFile {
+    value: &'static Mutex<String>,
+    max_len: usize,
+}
+
+impl SysctlStringFile {
+    pub fn new(value: &'static Mutex<String>, max_len: usize) -> Self {
+        Self { value, max_len }
+    }
+    fn info(&self) -> String {
+        format!("{}\n", self.value.lock())
+    }
+}
+
+impl VfsFile for SysctlStringFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let info = self.info();
+        let info = info.as_bytes();
+        if offset as usize >= info.len() {
+            return Ok(0);
+        }
+        let min_len = min(buf.len(), info.len() - offset as usize);
+        buf[..min_len].copy_from_slice(&info[offset as usize..offset as usize + min_len]);
+        Ok(min_len)
+    }
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let value = core::str::from_utf8(buf)
+            .map_err(|_| VfsError::Invalid)?
+            .trim_end_matches(|c| c == '\n' || c == '\0');
+        if value.len() > self.max_len {
+            return Err(VfsError::Invalid);
+        }
+        *self.value.lock() = value.to_string();
+        Ok(buf.len())
+    }
+}
+
+impl VfsInode for SysctlStringFile {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        "rw-r--r--".into()
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_size: self.info().len() as u64,
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::File
+    }
+}
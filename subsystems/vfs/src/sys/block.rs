@@ -0,0 +1,78 @@
+//! `/sys/block` 下磁盘的属性。
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+};
+use core::cmp::min;
+
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType},
+    VfsResult,
+};
+
+/// `/sys/block/<disk>/queue/scheduler`，读取时列出可选的 I/O 调度器并用方括号标出当前使用的调度器，
+/// 写入调度器的名字以切换调度器
+pub struct SchedulerFile {
+    disk: String,
+}
+
+impl SchedulerFile {
+    pub fn new(disk: &str) -> Self {
+        Self {
+            disk: disk.to_string(),
+        }
+    }
+    fn info(&self) -> String {
+        format!(
+            "{}\n",
+            devices::io_scheduler(&self.disk).unwrap_or_default()
+        )
+    }
+}
+
+impl VfsFile for SchedulerFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let info = self.info();
+        let info = info.as_bytes();
+        if offset as usize >= info.len() {
+            return Ok(0);
+        }
+        let min_len = min(buf.len(), info.len() - offset as usize);
+        buf[..min_len].copy_from_slice(&info[offset as usize..offset as usize + min_len]);
+        Ok(min_len)
+    }
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let name = core::str::from_utf8(buf)
+            .map_err(|_| VfsError::Invalid)?
+            .trim_end_matches(|c| c == '\n' || c == '\0')
+            .trim();
+        devices::set_io_scheduler(&self.disk, name)?;
+        Ok(buf.len())
+    }
+}
+
+impl VfsInode for SchedulerFile {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+    fn node_perm(&self) -> VfsNodePerm {
+        "rw-r--r--".into()
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_size: self.info().len() as u64,
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::File
+    }
+}
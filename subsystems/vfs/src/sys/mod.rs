@@ -1,18 +1,73 @@
-use alloc::sync::Arc;
+mod block;
+
+use alloc::{format, sync::Arc};
 
 use dynfs::DynFsDirInode;
-use vfscore::{dentry::VfsDentry, fstype::VfsFsType};
+use vfscore::{dentry::VfsDentry, error::VfsError, fstype::VfsFsType, path::VfsPath};
 
-use crate::CommonFsProviderImpl;
+use crate::{sys::block::SchedulerFile, CommonFsProviderImpl};
 
 pub type SysFsDirInodeImpl = DynFsDirInode<CommonFsProviderImpl, spin::Mutex<()>>;
 
+///
+/// ```bash
+/// |
+/// |-- fs
+/// |   |-- cgroup (cgroup v2 文件系统的挂载点)
+/// |-- block
+///     |-- vda
+///         |-- queue
+///             |-- scheduler (磁盘的 I/O 调度器)
+/// ```
 pub fn init_sysfs(sysfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
     let root_dt = sysfs.i_mount(0, "/sys", None, &[]).unwrap();
-    // let root_inode = root_dt.inode().unwrap();
-    // let root_inode = root_inode
-    //     .downcast_arc::<SysFsDirInodeImpl>()
-    //     .map_err(|_| VfsError::Invalid).unwrap();
+    let root_inode = root_dt
+        .inode()
+        .unwrap()
+        .downcast_arc::<SysFsDirInodeImpl>()
+        .map_err(|_| VfsError::Invalid)
+        .unwrap();
+    root_inode
+        .add_dir_manually("fs", "r-xr-xr-x".into())
+        .unwrap();
+    let fs_inode = sys_dir(&root_dt, "fs");
+    fs_inode
+        .add_dir_manually("cgroup", "r-xr-xr-x".into())
+        .unwrap();
+    root_inode
+        .add_dir_manually("block", "r-xr-xr-x".into())
+        .unwrap();
+    let block_inode = sys_dir(&root_dt, "block");
+    for (name, _) in devices::block_devices() {
+        block_inode
+            .add_dir_manually(&name, "r-xr-xr-x".into())
+            .unwrap();
+        let disk_inode = sys_dir(&root_dt, &format!("block/{}", name));
+        disk_inode
+            .add_dir_manually("queue", "r-xr-xr-x".into())
+            .unwrap();
+        let queue_inode = sys_dir(&root_dt, &format!("block/{}/queue", name));
+        queue_inode
+            .add_file_manually(
+                "scheduler",
+                Arc::new(SchedulerFile::new(&name)),
+                "rw-r--r--".into(),
+            )
+            .unwrap();
+    }
     println!("sysfs init success");
     root_dt
 }
+
+fn sys_dir(root_dt: &Arc<dyn VfsDentry>, path: &str) -> Arc<SysFsDirInodeImpl> {
+    VfsPath::new(root_dt.clone(), root_dt.clone())
+        .join(path)
+        .unwrap()
+        .open(None)
+        .unwrap()
+        .inode()
+        .unwrap()
+        .downcast_arc::<SysFsDirInodeImpl>()
+        .map_err(|_| VfsError::Invalid)
+        .unwrap()
+}
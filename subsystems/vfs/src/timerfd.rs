@@ -1,4 +1,4 @@
-use alloc::sync::Arc;
+use alloc::{boxed::Box, sync::Arc};
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use constants::{
@@ -7,7 +7,10 @@ use constants::{
     AlienError, AlienResult,
 };
 use ksync::Mutex;
-use timer::{TimeNow, ToClock};
+use timer::{
+    hrtimer::{hrtimer_cancel, hrtimer_start, HrTimerId},
+    TimeNow, ToClock,
+};
 use vfscore::{dentry::VfsDentry, inode::VfsInode, utils::VfsFileStat};
 
 use crate::kfile::File;
@@ -21,6 +24,8 @@ pub struct TimerFile {
     /// Record the number of ticks that have been triggered
     ticks: AtomicUsize,
     disable: AtomicBool,
+    /// The hrtimer armed for the next expiration
+    hrtimer: Mutex<Option<HrTimerId>>,
     #[allow(unused)]
     id: ClockId,
 }
@@ -34,6 +39,7 @@ impl TimerFile {
             timer_interval_clock: AtomicUsize::new(0),
             timer_next_clock: AtomicUsize::new(0),
             disable: AtomicBool::new(true),
+            hrtimer: Mutex::new(None),
             id,
         }
     }
@@ -49,7 +55,14 @@ impl TimerFile {
     }
 
     /// Reset the timer
-    pub fn set_timer(&self, timer: ITimeSpec) {
+    ///
+    /// The expirations are driven by an hrtimer, so they are counted at the exact deadline
+    /// instead of the next time the file is read.
+    pub fn set_timer(self: &Arc<Self>, timer: ITimeSpec) {
+        let mut hrtimer = self.hrtimer.lock();
+        if let Some(old) = hrtimer.take() {
+            hrtimer_cancel(old);
+        }
         if timer.it_value == TimeSpec::default() {
             self.disable.store(true, Ordering::Relaxed);
         } else {
@@ -61,6 +74,33 @@ impl TimerFile {
         self.timer_next_clock.store(next_clock, Ordering::Relaxed);
         self.timer_interval_clock
             .store(interval_clock, Ordering::Relaxed);
+        if !self.disable.load(Ordering::Relaxed) {
+            *hrtimer = Some(self.arm(next_clock));
+        }
+    }
+
+    /// Start an hrtimer that counts the expiration at `deadline`
+    fn arm(self: &Arc<Self>, deadline: usize) -> HrTimerId {
+        let file = Arc::downgrade(self);
+        hrtimer_start(
+            deadline,
+            Box::new(move || {
+                if let Some(file) = file.upgrade() {
+                    file.expire();
+                }
+            }),
+        )
+    }
+
+    /// Called by the hrtimer when the timer expires
+    fn expire(self: &Arc<Self>) {
+        let mut hrtimer = self.hrtimer.lock();
+        self.calculate_ticks();
+        *hrtimer = if self.disable.load(Ordering::Relaxed) {
+            None
+        } else {
+            Some(self.arm(self.timer_next_clock.load(Ordering::Relaxed)))
+        };
     }
 
     pub fn calculate_ticks(&self) {
@@ -71,21 +111,32 @@ impl TimerFile {
         let mut t_ticks = 0;
         let next_clock = self.timer_next_clock.load(Ordering::Relaxed);
         let interval_clock = self.timer_interval_clock.load(Ordering::Relaxed);
-        if now > next_clock {
+        if now >= next_clock {
             t_ticks += 1;
             if interval_clock != 0 {
                 let diff = now - next_clock;
                 let nums = diff / interval_clock;
                 t_ticks += nums;
+                // update next_clock
+                let next_clock = next_clock + (nums + 1) * interval_clock;
+                self.timer_next_clock.store(next_clock, Ordering::Relaxed);
+            } else {
+                // one-shot timer
+                self.disable.store(true, Ordering::Relaxed);
             }
-            // update next_clock
-            let next_clock = now + interval_clock;
-            self.timer_next_clock.store(next_clock, Ordering::Relaxed);
             self.ticks.fetch_add(t_ticks, Ordering::Relaxed);
         }
     }
 }
 
+impl Drop for TimerFile {
+    fn drop(&mut self) {
+        if let Some(hrtimer) = self.hrtimer.lock().take() {
+            hrtimer_cancel(hrtimer);
+        }
+    }
+}
+
 impl File for TimerFile {
     fn read(&self, buf: &mut [u8]) -> AlienResult<usize> {
         if buf.len() != 8 {